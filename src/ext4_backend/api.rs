@@ -4,28 +4,329 @@ use alloc::vec::Vec;
 use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::dir::*;
 use crate::ext4_backend::disknode::*;
+use crate::ext4_backend::entries::*;
 use crate::ext4_backend::ext4::*;
 use crate::ext4_backend::file::*;
+use crate::ext4_backend::inline_data::*;
 use crate::ext4_backend::loopfile::*;
 use crate::ext4_backend::error::*;
 use crate::ext4_backend::*;
 use crate::BLOCK_SIZE;
+/// 打开文件的标志
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OpenFlags {
+    /// 允许读
+    pub read: bool,
+    /// 允许写
+    pub write: bool,
+    /// 追加模式：每次写入都从文件末尾开始
+    pub append: bool,
+    /// 打开时把文件截断为 0
+    pub truncate: bool,
+    /// 文件不存在时创建
+    pub create: bool,
+}
+
+impl OpenFlags {
+    /// 只读
+    pub const RDONLY: Self = Self {
+        read: true,
+        write: false,
+        append: false,
+        truncate: false,
+        create: false,
+    };
+    /// 读写
+    pub const RDWR: Self = Self {
+        read: true,
+        write: true,
+        append: false,
+        truncate: false,
+        create: false,
+    };
+
+    pub const fn with_create(mut self) -> Self {
+        self.create = true;
+        self
+    }
+
+    pub const fn with_append(mut self) -> Self {
+        self.append = true;
+        self
+    }
+
+    pub const fn with_truncate(mut self) -> Self {
+        self.truncate = true;
+        self
+    }
+
+    fn writable(&self) -> bool {
+        self.write || self.append
+    }
+}
+
 /// 文件句柄
+/// 句柄持有 inode 号，文件被 unlink 后仍可读写，直到 close
 pub struct OpenFile {
     pub path: String,
+    pub ino: u32,
     pub inode: Ext4Inode,
     pub offset: u64,
+    pub flags: OpenFlags,
+    /// 上一次 read_at 读到的最后一个逻辑块，用于识别顺序读
+    pub last_read_lbn: Option<u64>,
+}
+
+/// 目录句柄，由 open_dir 返回，供 readdir 使用
+pub struct OpenDir {
+    pub path: String,
+    pub ino: u32,
+    pub inode: Ext4Inode,
+}
+
+/// 文件元数据，由 stat 返回
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    /// inode 号
+    pub ino: u32,
+    /// 文件类型（Ext4DirEntry2::EXT4_FT_*）
+    pub file_type: u8,
+    /// 完整 i_mode（类型位 + 权限位）
+    pub mode: u16,
+    /// 文件大小（字节）
+    pub size: u64,
+    /// 占用的 512 字节扇区数
+    pub blocks: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub links_count: u16,
+    pub atime: Ext4Timestamp,
+    pub mtime: Ext4Timestamp,
+    pub ctime: Ext4Timestamp,
+    pub crtime: Ext4Timestamp,
+}
+
+impl FileStat {
+    /// 由 inode 构造元数据
+    pub fn from_inode(ino: u32, inode: &Ext4Inode) -> Self {
+        let file_type = match inode.i_mode & Ext4Inode::S_IFMT {
+            Ext4Inode::S_IFREG => Ext4DirEntry2::EXT4_FT_REG_FILE,
+            Ext4Inode::S_IFDIR => Ext4DirEntry2::EXT4_FT_DIR,
+            Ext4Inode::S_IFLNK => Ext4DirEntry2::EXT4_FT_SYMLINK,
+            Ext4Inode::S_IFCHR => Ext4DirEntry2::EXT4_FT_CHRDEV,
+            Ext4Inode::S_IFBLK => Ext4DirEntry2::EXT4_FT_BLKDEV,
+            Ext4Inode::S_IFIFO => Ext4DirEntry2::EXT4_FT_FIFO,
+            Ext4Inode::S_IFSOCK => Ext4DirEntry2::EXT4_FT_SOCK,
+            _ => Ext4DirEntry2::EXT4_FT_UNKNOWN,
+        };
+        Self {
+            ino,
+            file_type,
+            mode: inode.i_mode,
+            size: inode.size(),
+            blocks: inode.blocks_count(),
+            uid: inode.uid(),
+            gid: inode.gid(),
+            links_count: inode.i_links_count,
+            atime: inode.atime(),
+            mtime: inode.mtime(),
+            ctime: inode.ctime(),
+            crtime: inode.crtime(),
+        }
+    }
 }
 
 ///挂载Ext4文件系统
-pub fn fs_mount<B: BlockDevice>(dev: &mut Jbd2Dev<B>) -> BlockDevResult<Ext4FileSystem> {
+pub fn fs_mount<B: BlockDevice>(dev: &mut Jbd2Dev<B>) -> Ext4Result<Ext4FileSystem> {
     ext4::mount(dev)
 }
 
 ///卸载Ext4文件系统
-pub fn fs_umount<B: BlockDevice>(fs: Ext4FileSystem, dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+pub fn fs_umount<B: BlockDevice>(fs: Ext4FileSystem, dev: &mut Jbd2Dev<B>) -> Ext4Result<()> {
     ext4::umount(fs, dev)
 }
+
+/// 挂载句柄：在卸载前独占借用设备，文件操作不必再传设备，也无法混用其它设备
+/// 方法都是对同名自由函数的薄包装；其余操作可经 `parts` 取得设备和文件系统后调用自由函数
+pub struct MountedFs<'a, B: BlockDevice> {
+    dev: &'a mut Jbd2Dev<B>,
+    fs: Ext4FileSystem,
+}
+
+impl<'a, B: BlockDevice> MountedFs<'a, B> {
+    ///挂载设备上的文件系统
+    pub fn mount(dev: &'a mut Jbd2Dev<B>) -> Ext4Result<Self> {
+        let fs = ext4::mount(dev)?;
+        Ok(Self { dev, fs })
+    }
+
+    ///包装已在该设备上挂载的文件系统
+    pub fn from_parts(dev: &'a mut Jbd2Dev<B>, fs: Ext4FileSystem) -> Self {
+        Self { dev, fs }
+    }
+
+    ///文件系统状态
+    pub fn fs(&self) -> &Ext4FileSystem {
+        &self.fs
+    }
+
+    ///同时借出设备和文件系统状态
+    pub fn parts(&mut self) -> (&mut Jbd2Dev<B>, &mut Ext4FileSystem) {
+        (&mut *self.dev, &mut self.fs)
+    }
+
+    ///查找路径对应的 inode
+    pub fn find_file(&mut self, path: &str) -> Ext4Result<Ext4Inode> {
+        ext4::find_file(&mut self.fs, self.dev, path)
+    }
+
+    ///读取整个文件内容
+    pub fn read_file(&mut self, path: &str) -> Ext4Result<Vec<u8>> {
+        read_file(self.dev, &mut self.fs, path)
+    }
+
+    ///创建单级目录，父目录必须已存在
+    pub fn mkdir(&mut self, path: &str) -> Ext4Result<Ext4Inode> {
+        mkdir(self.dev, &mut self.fs, path)
+    }
+
+    ///创建目录（含缺失的中间目录）
+    pub fn mkdir_p(&mut self, path: &str) -> Ext4Result<Ext4Inode> {
+        mkdir_p(self.dev, &mut self.fs, path)
+    }
+
+    ///以读写方式打开文件，不存在时创建
+    pub fn create(&mut self, path: &str) -> Ext4Result<OpenFile> {
+        open(self.dev, &mut self.fs, path, OpenFlags::RDWR.with_create())
+    }
+
+    ///按 flags 打开文件
+    pub fn open(&mut self, path: &str, flags: OpenFlags) -> Ext4Result<OpenFile> {
+        open(self.dev, &mut self.fs, path, flags)
+    }
+
+    ///从句柄当前 offset 读取
+    pub fn read_at(&mut self, file: &mut OpenFile, len: usize) -> Ext4Result<Vec<u8>> {
+        read_at(self.dev, &mut self.fs, file, len)
+    }
+
+    ///从句柄当前 offset 写入
+    pub fn write_at(&mut self, file: &mut OpenFile, data: &[u8]) -> Ext4Result<()> {
+        write_at(self.dev, &mut self.fs, file, data)
+    }
+
+    ///提交包含该文件在内的整个事务，见 [`fsync_transaction`]
+    pub fn fsync_transaction(&mut self, file: &mut OpenFile) -> Ext4Result<()> {
+        fsync_transaction(self.dev, &mut self.fs, file)
+    }
+
+    ///关闭文件句柄
+    pub fn close(&mut self, file: OpenFile) -> Ext4Result<()> {
+        close(self.dev, &mut self.fs, file)
+    }
+
+    ///查询文件元数据
+    pub fn stat(&mut self, path: &str) -> Ext4Result<FileStat> {
+        stat(self.dev, &mut self.fs, path)
+    }
+
+    ///删除文件
+    pub fn unlink(&mut self, path: &str) -> Ext4Result<()> {
+        unlink(self.dev, &mut self.fs, path)
+    }
+
+    ///按 flags 打开文件并包装为带游标的 Ext4File
+    pub fn open_file(&mut self, path: &str, flags: OpenFlags) -> Ext4Result<Ext4File<'_, B>> {
+        let file = open(self.dev, &mut self.fs, path, flags)?;
+        Ok(Ext4File::new(self.dev, &mut self.fs, file))
+    }
+
+    ///卸载文件系统并归还设备借用
+    pub fn umount(self) -> Ext4Result<()> {
+        ext4::umount(self.fs, self.dev)
+    }
+}
+
+/// 游标定位方式，语义同 std::io::SeekFrom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+/// 带游标的文件：仿照 std::io 的 seek/read/write，内部调用按 offset 读写的自由函数
+/// 游标即 OpenFile 的 offset；读到 EOF 截止，写过 EOF 时扩展文件（跳过的部分为空洞）
+pub struct Ext4File<'a, B: BlockDevice> {
+    dev: &'a mut Jbd2Dev<B>,
+    fs: &'a mut Ext4FileSystem,
+    file: OpenFile,
+}
+
+impl<'a, B: BlockDevice> Ext4File<'a, B> {
+    ///包装已打开的文件，游标从 file.offset 开始
+    pub fn new(dev: &'a mut Jbd2Dev<B>, fs: &'a mut Ext4FileSystem, file: OpenFile) -> Self {
+        Self { dev, fs, file }
+    }
+
+    ///当前文件大小
+    pub fn len(&self) -> u64 {
+        self.file.inode.size()
+    }
+
+    ///文件是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///当前游标位置
+    pub fn stream_position(&self) -> u64 {
+        self.file.offset
+    }
+
+    ///移动游标并返回新位置；允许越过 EOF，定位到负位置返回 InvalidInput
+    pub fn seek(&mut self, pos: SeekFrom) -> Ext4Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(off) => (off, 0),
+            SeekFrom::End(delta) => {
+                refresh_open_file_inode(self.dev, self.fs, &mut self.file)?;
+                (self.file.inode.size(), delta)
+            }
+            SeekFrom::Current(delta) => (self.file.offset, delta),
+        };
+        let new_pos = base.checked_add_signed(delta).ok_or(Ext4Error::InvalidInput)?;
+        self.file.offset = new_pos;
+        Ok(new_pos)
+    }
+
+    ///从游标处读入 buf，返回读到的字节数（EOF 处为 0）
+    pub fn read(&mut self, buf: &mut [u8]) -> Ext4Result<usize> {
+        let data = read_at(self.dev, self.fs, &mut self.file, buf.len())?;
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    ///从游标处写入 buf（追加模式下写到末尾），返回写入的字节数
+    pub fn write(&mut self, buf: &[u8]) -> Ext4Result<usize> {
+        write_at(self.dev, self.fs, &mut self.file, buf)?;
+        Ok(buf.len())
+    }
+
+    ///提交包含该文件在内的整个事务，见 [`fsync_transaction`]
+    pub fn sync(&mut self) -> Ext4Result<()> {
+        fsync_transaction(self.dev, self.fs, &mut self.file)
+    }
+
+    ///取回内部的 OpenFile，不关闭
+    pub fn into_inner(self) -> OpenFile {
+        self.file
+    }
+
+    ///关闭文件
+    pub fn close(self) -> Ext4Result<()> {
+        close(self.dev, self.fs, self.file)
+    }
+}
 pub fn lseek(
     file:&mut OpenFile,
     location: u64
@@ -39,77 +340,285 @@ fn refresh_open_file_inode<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     file: &mut OpenFile,
 ) -> BlockDevResult<()> {
-    let Some((_ino, inode)) = get_file_inode(fs, dev, &file.path)? else {
-        return Err(BlockDevError::InvalidInput);
-    };
-    file.inode = inode;
+    file.inode = fs.get_inode_by_num(dev, file.ino)?;
     Ok(())
 }
 
-///打开文件：可选自动创建
+///打开文件：按 flags 决定是否创建/截断
+/// 目标是目录时返回 IsADirectory，目录请使用 open_dir；不存在且未指定 create 时返回 NotFound
 pub fn open<B: BlockDevice>(
     dev: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     path: &str,
-    create: bool,
-) -> BlockDevResult<OpenFile> {
+    flags: OpenFlags,
+) -> Ext4Result<OpenFile> {
     let norm_path = split_paren_child_and_tranlatevalid(path);
 
-    if let Ok(Some(inode)) = get_file_inode(fs, dev, &norm_path) {
-        let real_inode = inode.1;
-        return Ok(OpenFile {
+    if let Some((ino, inode)) = get_file_inode(fs, dev, &norm_path)? {
+        if inode.is_dir() {
+            return Err(Ext4Error::IsADirectory);
+        }
+        let mut file = OpenFile {
             path: norm_path,
-            inode: real_inode,
+            ino,
+            inode,
             offset: 0,
-        });
+            flags,
+            last_read_lbn: None,
+        };
+        if flags.truncate && flags.writable() && file.inode.size() != 0 {
+            truncate(dev, fs, &file.path, 0)?;
+            refresh_open_file_inode(dev, fs, &mut file)?;
+        }
+        fs.inode_opened(ino);
+        return Ok(file);
     }
 
-    if !create {
-        return Err(BlockDevError::WriteError);
+    if !flags.create {
+        return Err(path_lookup_error(fs, dev, &norm_path));
     }
 
-    let inode = match mkfile(dev, fs, &norm_path, None,None) {
-        Some(ino) => ino,
-        None => return Err(BlockDevError::WriteError),
-    };
+    let (ino, inode) = try_mkfile_with_ino(dev, fs, &norm_path, None, None)?;
+    fs.inode_opened(ino);
 
     Ok(OpenFile {
         path: norm_path,
+        ino,
         inode,
         offset: 0,
+        flags,
+        last_read_lbn: None,
+    })
+}
+
+///打开目录：路径分量不存在返回 NotFound，不是目录返回 NotADirectory
+pub fn open_dir<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<OpenDir> {
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    let (ino, inode) = lookup_dir(fs, dev, &norm_path)?;
+    Ok(OpenDir {
+        path: norm_path,
+        ino,
+        inode,
     })
 }
 
+///读取目录句柄下的目录项
+pub fn readdir<'a, B: BlockDevice>(
+    dev: &'a mut Jbd2Dev<B>,
+    fs: &'a mut Ext4FileSystem,
+    dir: &OpenDir,
+) -> Ext4Result<DirIter<'a, B>> {
+    read_dir(dev, fs, &dir.path)
+}
+
+///关闭文件：已被 unlink 的文件在最后一个句柄关闭时回收
+pub fn close<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    file: OpenFile,
+) -> Ext4Result<()> {
+    Ok(release_open_inode(dev, fs, file.ino)?)
+}
+
+/// 以整个事务为单位落盘：不只写回该文件，而是写回所有脏 inode 及其数据块
+/// （数据先于元数据，目录块作为元数据），再连同父目录块、位图和块组描述符提交日志并刷新设备写缓存。
+/// 代价与全部脏元数据成正比，而不是只与该文件成正比；
+/// 之所以不能只写这一个文件，是因为共享的目录块和位图里还有其它新建文件的目录项和分配位，
+/// 单独提交会在崩溃后留下悬空目录项或无属主的块。
+/// inode 不脏的文件留在缓存里的脏块不受影响。只读挂载时什么也不做
+pub fn fsync_transaction<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    file: &mut OpenFile,
+) -> Ext4Result<()> {
+    if fs.read_only {
+        return Ok(());
+    }
+    refresh_open_file_inode(dev, fs, file)?;
+    let blocks: Vec<u64> = resolve_inode_block_allextend(fs, dev, file.ino, &mut file.inode)?
+        .into_values()
+        .collect();
+    fs.datablock_cache.flush_blocks(dev, &blocks)?;
+    for ino in fs.inodetable_cahce.dirty_inodes() {
+        let ino = ino as u32;
+        if ino == file.ino {
+            continue;
+        }
+        let mut inode = fs.get_inode_by_num(dev, ino)?;
+        let blocks: Vec<u64> = resolve_inode_block_allextend(fs, dev, ino, &mut inode)?
+            .into_values()
+            .collect();
+        if inode.is_dir() {
+            for blk in blocks {
+                fs.datablock_cache.flush_as_metadata(dev, blk)?;
+            }
+        } else {
+            fs.datablock_cache.flush_blocks(dev, &blocks)?;
+        }
+    }
+    if let Some((parent_ino, mut parent)) = get_file_inode(fs, dev, &parent_of(&file.path))? {
+        for blk in resolve_inode_block_allextend(fs, dev, parent_ino, &mut parent)?.into_values() {
+            fs.datablock_cache.flush_as_metadata(dev, blk)?;
+        }
+    }
+    fs.inodetable_cahce.flush_all(dev)?;
+    fs.bitmap_cache.flush_all(dev)?;
+    fs.write_group_descriptors(dev)?;
+    dev.commit_journal()?;
+    Ok(dev.cantflush()?)
+}
+
 ///写入文件:基于当前offset追加写入
 pub fn write_at<B: BlockDevice>(
     dev: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     file: &mut OpenFile,
     data: &[u8],
-) -> BlockDevResult<()> {
+) -> Ext4Result<()> {
 
     if data.len() > usize::MAX {
         // 超出平台支持的大小
-        return Err(BlockDevError::Unsupported);
+        return Err(BlockDevError::Unsupported.into());
     }
 
     if data.is_empty() {
         return Ok(());
     }
+    if !file.flags.writable() {
+        return Err(Ext4Error::PermissionDenied);
+    }
+    if file.flags.append {
+        refresh_open_file_inode(dev, fs, file)?;
+        file.offset = file.inode.size();
+    }
 
     let off = file.offset;
-    write_file(dev, fs, &file.path, off, data)?;
+    write_file_with_ino(dev, fs, file.ino, off, data)?;
     file.offset = file.offset.saturating_add(data.len() as u64);
     refresh_open_file_inode(dev, fs, file)?;
     Ok(())
 }
 
-///读取整个文件内容
+///按指定 offset 写入（不移动文件句柄 offset），返回写入字节数
+/// 超出当前大小时扩展文件，跳过的块保持为空洞；
+/// 句柄不可写返回 PermissionDenied，offset + len 溢出返回 InvalidInput
+pub fn write_to_file<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    file: &mut OpenFile,
+    offset: u64,
+    data: &[u8],
+) -> Ext4Result<usize> {
+    if data.is_empty() {
+        return Ok(0);
+    }
+    if !file.flags.writable() {
+        return Err(Ext4Error::PermissionDenied);
+    }
+    offset
+        .checked_add(data.len() as u64)
+        .ok_or(Ext4Error::InvalidInput)?;
+
+    write_file_with_ino(dev, fs, file.ino, offset, data)?;
+    refresh_open_file_inode(dev, fs, file)?;
+    Ok(data.len())
+}
+
+/// 追加写入：从当前文件大小处写入，并把句柄 offset 移到新的末尾
+/// 句柄未以 append 打开时返回 PermissionDenied
+pub fn append_to_file<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    file: &mut OpenFile,
+    data: &[u8],
+) -> Ext4Result<usize> {
+    if !file.flags.append {
+        return Err(Ext4Error::PermissionDenied);
+    }
+    if data.is_empty() {
+        return Ok(0);
+    }
+
+    refresh_open_file_inode(dev, fs, file)?;
+    let end = file.inode.size();
+    // 末尾 extent 与新块物理连续时由 insert_extent 合并，否则新建 extent
+    write_file_with_ino(dev, fs, file.ino, end, data)?;
+    refresh_open_file_inode(dev, fs, file)?;
+    file.offset = file.inode.size();
+    Ok(data.len())
+}
+
+///查询文件元数据，不跟随路径最后一级的符号链接（与 lstat 相同）
+/// 路径不存在时返回 NotFound
+pub fn stat<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<FileStat> {
+    let (ino, inode) = lookup_path(fs, dev, path)?;
+    Ok(FileStat::from_inode(ino, &inode))
+}
+
+///读取整个文件内容并返回其元数据，inode 只加载一次
+/// 数据长度与 stat 的 size 来自同一个 inode；错误语义同 read_file（路径不存在时返回 NotFound）
+pub fn read_file_with_stat<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<(Vec<u8>, FileStat)> {
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    let (ino, inode, data) = read_file_with_inode(dev, fs, &norm_path)?;
+    Ok((data, FileStat::from_inode(ino, &inode)))
+}
+
+///修改权限位（含 setuid/setgid/sticky），保留 i_mode 的文件类型位，并更新 ctime
+/// 路径不存在时返回 NotFound
+pub fn chmod<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    mode: u16,
+) -> Ext4Result<()> {
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    let (ino, _) = lookup_path(fs, dev, &norm_path)?;
+    let now = fs.current_time();
+    Ok(fs.modify_inode(dev, ino, |inode| {
+        inode.i_mode = (inode.i_mode & Ext4Inode::S_IFMT) | (mode & !Ext4Inode::S_IFMT);
+        inode.set_ctime_ts(now);
+    })?)
+}
+
+///修改所有者，uid/gid 超过 16 位的部分写入 uid_high/gid_high，并更新 ctime
+/// 路径不存在时返回 NotFound
+pub fn chown<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    uid: u32,
+    gid: u32,
+) -> Ext4Result<()> {
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    let (ino, _) = lookup_path(fs, dev, &norm_path)?;
+    let now = fs.current_time();
+    Ok(fs.modify_inode(dev, ino, |inode| {
+        inode.i_uid = uid as u16;
+        inode.l_i_uid_high = (uid >> 16) as u16;
+        inode.i_gid = gid as u16;
+        inode.l_i_gid_high = (gid >> 16) as u16;
+        inode.set_ctime_ts(now);
+    })?)
+}
+
+///读取整个文件内容，路径不存在时返回 NotFound
 pub fn read<B: BlockDevice>(
     dev: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     path: &str,
-) -> BlockDevResult<Option<Vec<u8>>> {
+) -> Ext4Result<Vec<u8>> {
     read_file(dev, fs, path)
 }
 
@@ -119,10 +628,13 @@ pub fn read_at<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     file: &mut OpenFile,
     len: usize,
-) -> BlockDevResult<Vec<u8>> {
+) -> Ext4Result<Vec<u8>> {
     if len == 0 {
         return Ok(Vec::new());
     }
+    if !file.flags.read {
+        return Err(Ext4Error::PermissionDenied);
+    }
 
     refresh_open_file_inode(dev, fs, file)?;
 
@@ -137,8 +649,18 @@ pub fn read_at<B: BlockDevice>(
         return Ok(Vec::new());
     }
 
+    if file.inode.has_inline_data() {
+        let (_, xattr_area) = fs.get_inode_with_xattr_area(dev, file.ino)?;
+        let data = read_inline(&file.inode, &xattr_area)?;
+        let start = file.offset as usize;
+        let out = data[start..start + to_read as usize].to_vec();
+        file.offset = file.offset.saturating_add(to_read);
+        fs.touch_atime(dev, file.ino)?;
+        return Ok(out);
+    }
+
     if !file.inode.have_extend_header_and_use_extend() {
-        return Err(BlockDevError::Unsupported);
+        return Err(BlockDevError::Unsupported.into());
     }
 
     let block_bytes = BLOCK_SIZE as u64;
@@ -148,7 +670,9 @@ pub fn read_at<B: BlockDevice>(
     let start_lbn = start_off / block_bytes;
     let end_lbn = (end_off - 1) / block_bytes;
 
-    let extent_map = resolve_inode_block_allextend(fs, dev, &mut file.inode)?;
+    // 只展开本次读取覆盖的块，空洞和未初始化 extent 读出为 0
+    let extents = collect_inode_extents(dev, fs, file.ino, &mut file.inode)?;
+    let extent_map = map_extent_range(&extents, start_lbn, end_lbn + 1);
 
     let mut out = Vec::with_capacity(to_read as usize);
     for lbn in start_lbn..=end_lbn {
@@ -178,5 +702,591 @@ pub fn read_at<B: BlockDevice>(
 
     out.truncate(to_read as usize);
     file.offset = file.offset.saturating_add(out.len() as u64);
+    // 从文件开头读、或紧接上一次读取的位置继续读时视为顺序读，才做预读
+    let sequential = match file.last_read_lbn {
+        None => start_lbn == 0,
+        Some(last) => start_lbn == last || start_lbn == last + 1,
+    };
+    file.last_read_lbn = Some(end_lbn);
+    // 本次读取结束在空洞里时，空洞就是预读边界，不越过它去读后面的映射
+    if sequential && extent_map.contains_key(&(end_lbn as u32)) {
+        let file_blocks = file.inode.size().div_ceil(block_bytes);
+        prefetch_extent_window(dev, fs, &extents, end_lbn + 1, file_blocks)?;
+    }
+    fs.touch_atime(dev, file.ino)?;
     Ok(out)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::test_support::*;
+    use alloc::vec;
+
+    #[test]
+    fn write_to_file_straddles_block_boundary() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let mut file = open(&mut dev, &mut fs, "/w", OpenFlags::RDWR.with_create()).unwrap();
+        let base = vec![b'a'; BLOCK_SIZE + 100];
+        write_to_file(&mut dev, &mut fs, &mut file, 0, &base).unwrap();
+
+        let patch = [b'Z'; 20];
+        let n = write_to_file(&mut dev, &mut fs, &mut file, BLOCK_SIZE as u64 - 10, &patch).unwrap();
+        assert_eq!(n, 20);
+        assert_eq!(file.offset, 0);
+        assert_eq!(file.inode.size(), (BLOCK_SIZE + 100) as u64);
+
+        let data = read_at(&mut dev, &mut fs, &mut file, BLOCK_SIZE + 100).unwrap();
+        assert!(data[..BLOCK_SIZE - 10].iter().all(|&b| b == b'a'));
+        assert!(data[BLOCK_SIZE - 10..BLOCK_SIZE + 10].iter().all(|&b| b == b'Z'));
+        assert!(data[BLOCK_SIZE + 10..].iter().all(|&b| b == b'a'));
+    }
+
+    #[test]
+    fn write_to_file_reports_length_and_typed_errors() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let mut file = open(&mut dev, &mut fs, "/w", OpenFlags::RDWR.with_create()).unwrap();
+        assert_eq!(write_to_file(&mut dev, &mut fs, &mut file, 0, b"hello"), Ok(5));
+        assert_eq!(write_to_file(&mut dev, &mut fs, &mut file, 0, b""), Ok(0));
+        assert_eq!(
+            write_to_file(&mut dev, &mut fs, &mut file, u64::MAX - 1, b"xyz"),
+            Err(Ext4Error::InvalidInput)
+        );
+
+        let mut ro = open(&mut dev, &mut fs, "/w", OpenFlags::RDONLY).unwrap();
+        assert_eq!(
+            write_to_file(&mut dev, &mut fs, &mut ro, 0, b"x"),
+            Err(Ext4Error::PermissionDenied)
+        );
+        assert_eq!(read_file(&mut dev, &mut fs, "/w").unwrap(), b"hello");
+    }
+
+    #[test]
+    fn write_to_file_past_eof_leaves_hole() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let mut file = open(&mut dev, &mut fs, "/sparse", OpenFlags::RDWR.with_create()).unwrap();
+        write_to_file(&mut dev, &mut fs, &mut file, 0, b"head").unwrap();
+
+        // 跳过 lbn 1..4，在 lbn 5 写入并跨到 lbn 6
+        let off = 5 * BLOCK_SIZE as u64 + BLOCK_SIZE as u64 - 2;
+        write_to_file(&mut dev, &mut fs, &mut file, off, b"tail").unwrap();
+        assert_eq!(file.inode.size(), off + 4);
+
+        let map = resolve_inode_block_allextend(&mut fs, &mut dev, file.ino, &mut file.inode).unwrap();
+        let lbns: Vec<u32> = map.keys().copied().collect();
+        assert_eq!(lbns, vec![0, 5, 6]);
+
+        let data = read_at(&mut dev, &mut fs, &mut file, (off + 4) as usize).unwrap();
+        assert_eq!(&data[..4], b"head");
+        assert!(data[4..off as usize].iter().all(|&b| b == 0));
+        assert_eq!(&data[off as usize..], b"tail");
+    }
+
+    #[test]
+    fn append_to_file_in_chunks_stays_contiguous() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let flags = OpenFlags::RDWR.with_create().with_append();
+        let mut file = open(&mut dev, &mut fs, "/log", flags).unwrap();
+
+        let mut expect = Vec::new();
+        for i in 0..10u8 {
+            let chunk = vec![b'0' + i; 1024];
+            assert_eq!(append_to_file(&mut dev, &mut fs, &mut file, &chunk).unwrap(), 1024);
+            expect.extend_from_slice(&chunk);
+        }
+        assert_eq!(file.offset, 10 * 1024);
+        assert_eq!(file.inode.size(), 10 * 1024);
+
+        let data = read(&mut dev, &mut fs, "/log").unwrap();
+        assert_eq!(&data[..10 * 1024], &expect[..]);
+        let frag = fragmentation(&mut dev, &mut fs, "/log").unwrap();
+        assert_eq!(frag.extent_count, 1);
+    }
+
+    #[test]
+    fn append_requires_append_flag() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let mut file = open(&mut dev, &mut fs, "/plain", OpenFlags::RDWR.with_create()).unwrap();
+        write_at(&mut dev, &mut fs, &mut file, b"abc").unwrap();
+        assert!(matches!(
+            append_to_file(&mut dev, &mut fs, &mut file, b"def"),
+            Err(Ext4Error::PermissionDenied)
+        ));
+
+        let mut ro = open(&mut dev, &mut fs, "/plain", OpenFlags::RDONLY).unwrap();
+        assert!(matches!(
+            write_at(&mut dev, &mut fs, &mut ro, b"x"),
+            Err(Ext4Error::PermissionDenied)
+        ));
+        assert_eq!(read_at(&mut dev, &mut fs, &mut ro, 3).unwrap(), b"abc");
+
+        let tr = open(&mut dev, &mut fs, "/plain", OpenFlags::RDWR.with_truncate()).unwrap();
+        assert_eq!(tr.inode.size(), 0);
+    }
+
+    #[test]
+    fn unlinked_file_stays_alive_until_close() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let mut file = open(&mut dev, &mut fs, "/tmpf", OpenFlags::RDWR.with_create()).unwrap();
+        write_at(&mut dev, &mut fs, &mut file, b"still here").unwrap();
+        let free_blocks = fs.superblock.free_blocks_count();
+        let free_inodes = fs.superblock.s_free_inodes_count;
+
+        unlink(&mut dev, &mut fs, "/tmpf").unwrap();
+        assert!(get_file_inode(&mut fs, &mut dev, "/tmpf").unwrap().is_none());
+        // 句柄仍然可用，inode 与数据块尚未回收
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+        lseek(&mut file, 0);
+        assert_eq!(read_at(&mut dev, &mut fs, &mut file, 10).unwrap(), b"still here");
+
+        close(&mut dev, &mut fs, file).unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks + 1);
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes + 1);
+        assert!(fs.orphan_inodes.is_empty());
+    }
+
+    #[test]
+    fn stat_reports_type_size_and_timestamps() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/f", Some(&[7u8; 5000]), None).unwrap();
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+        symlink(&mut dev, &mut fs, "/f", "/l").unwrap();
+
+        let st = stat(&mut dev, &mut fs, "/f").unwrap();
+        assert_eq!(st.file_type, Ext4DirEntry2::EXT4_FT_REG_FILE);
+        assert_eq!(st.mode & Ext4Inode::S_IFMT, Ext4Inode::S_IFREG);
+        assert_eq!(st.size, 5000);
+        assert_eq!(st.blocks, 2 * (BLOCK_SIZE as u64 / 512));
+        assert_eq!(st.links_count, 1);
+        assert_eq!(
+            stat(&mut dev, &mut fs, "/d").unwrap().file_type,
+            Ext4DirEntry2::EXT4_FT_DIR
+        );
+        assert_eq!(
+            stat(&mut dev, &mut fs, "/l").unwrap().file_type,
+            Ext4DirEntry2::EXT4_FT_SYMLINK
+        );
+        assert_eq!(
+            stat(&mut dev, &mut fs, "/missing").err(),
+            Some(Ext4Error::NotFound { index: 0, component: "missing".into() })
+        );
+
+        // 大 inode 的 extra 字段：纳秒在高 30 位，低 2 位扩展 epoch
+        fs.modify_inode(&mut dev, st.ino, |inode| {
+            inode.i_extra_isize = 32;
+            inode.i_mtime = 100;
+            inode.i_mtime_extra = (123_456_789 << 2) | 1;
+            inode.i_crtime = 50;
+            inode.i_crtime_extra = 7 << 2;
+        })
+        .unwrap();
+        let st = stat(&mut dev, &mut fs, "/f").unwrap();
+        assert_eq!(
+            st.mtime,
+            Ext4Timestamp {
+                sec: 100 + (1 << 32),
+                nsec: 123_456_789
+            }
+        );
+        assert_eq!(st.crtime, Ext4Timestamp { sec: 50, nsec: 7 });
+    }
+
+    #[test]
+    fn open_rejects_directories_and_open_dir_lists_them() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/test_dir/").unwrap();
+        mkfile(&mut dev, &mut fs, "/test_dir/a", None, None).unwrap();
+
+        assert!(matches!(
+            open(&mut dev, &mut fs, "/test_dir/", OpenFlags::RDONLY),
+            Err(Ext4Error::IsADirectory)
+        ));
+        assert!(matches!(
+            open_dir(&mut dev, &mut fs, "/test_dir/a"),
+            Err(Ext4Error::NotADirectory { index: 1, .. })
+        ));
+        assert_eq!(
+            open_dir(&mut dev, &mut fs, "/test_dir/nope").err(),
+            Some(Ext4Error::NotFound { index: 1, component: "nope".into() })
+        );
+
+        let dir = open_dir(&mut dev, &mut fs, "/test_dir/").unwrap();
+        let names: Vec<String> = readdir(&mut dev, &mut fs, &dir)
+            .unwrap()
+            .map(|e| e.unwrap().name)
+            .collect();
+        assert_eq!(names, [".", "..", "a"]);
+    }
+
+    #[test]
+    fn chmod_and_chown_keep_type_bits_and_touch_ctime() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        fs.now = || 1_700_000_000;
+        mkfile(&mut dev, &mut fs, "/f", None, None).unwrap();
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+
+        chmod(&mut dev, &mut fs, "/f", 0o644).unwrap();
+        let st = stat(&mut dev, &mut fs, "/f").unwrap();
+        assert_eq!(st.mode, Ext4Inode::S_IFREG | 0o644);
+        assert_eq!(st.ctime.sec, 1_700_000_000);
+
+        // 传入的类型位被忽略
+        chmod(&mut dev, &mut fs, "/d", Ext4Inode::S_IFREG | 0o1755).unwrap();
+        let st = stat(&mut dev, &mut fs, "/d").unwrap();
+        assert_eq!(st.mode, Ext4Inode::S_IFDIR | 0o1755);
+        assert_eq!(st.file_type, Ext4DirEntry2::EXT4_FT_DIR);
+
+        chown(&mut dev, &mut fs, "/f", 70_000, 1000).unwrap();
+        let st = stat(&mut dev, &mut fs, "/f").unwrap();
+        assert_eq!((st.uid, st.gid), (70_000, 1000));
+        let (_, inode) = get_file_inode(&mut fs, &mut dev, "/f").unwrap().unwrap();
+        assert_eq!((inode.i_uid, inode.l_i_uid_high), (70_000u32 as u16, 1));
+
+        assert_eq!(
+            chmod(&mut dev, &mut fs, "/missing", 0o600),
+            Err(Ext4Error::NotFound { index: 0, component: "missing".into() })
+        );
+        assert_eq!(
+            chown(&mut dev, &mut fs, "/missing", 1, 1),
+            Err(Ext4Error::NotFound { index: 0, component: "missing".into() })
+        );
+    }
+
+    #[test]
+    fn readahead_stops_at_hole_boundary() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let mut file = open(&mut dev, &mut fs, "/sparse", OpenFlags::RDWR.with_create()).unwrap();
+        write_to_file(&mut dev, &mut fs, &mut file, 0, &vec![1u8; 3 * BLOCK_SIZE]).unwrap();
+        write_to_file(&mut dev, &mut fs, &mut file, 6 * BLOCK_SIZE as u64, &vec![2u8; BLOCK_SIZE])
+            .unwrap();
+        refresh_open_file_inode(&mut dev, &mut fs, &mut file).unwrap();
+        let map = resolve_inode_block_allextend(&mut fs, &mut dev, file.ino, &mut file.inode).unwrap();
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), [0, 1, 2, 6]);
+
+        fs.datablock_cache.flush_all(&mut dev).unwrap();
+        fs.datablock_cache.clear();
+        fs.readahead_blocks = 8;
+        let base = fs.datablock_cache.cache_stats();
+
+        // 读第 0 块：预读 1、2 块后在空洞前停止，不读第 6 块
+        lseek(&mut file, 0);
+        assert_eq!(read_at(&mut dev, &mut fs, &mut file, BLOCK_SIZE).unwrap(), vec![1u8; BLOCK_SIZE]);
+        for lbn in [0, 1, 2] {
+            assert!(fs.datablock_cache.get(map[&lbn]).is_some());
+        }
+        assert!(fs.datablock_cache.get(map[&6]).is_none());
+        assert_eq!(fs.datablock_cache.stats().total_entries, 3);
+        assert_eq!(fs.datablock_cache.cache_stats().misses, base.misses + 1);
+
+        // 后续顺序读命中预读的块；空洞读出 0 且不产生设备读
+        // 读到第 5 块为止：读取结束在空洞里，不越过空洞预读第 6 块
+        let rest = read_at(&mut dev, &mut fs, &mut file, 5 * BLOCK_SIZE).unwrap();
+        assert!(rest[..2 * BLOCK_SIZE].iter().all(|&b| b == 1));
+        assert!(rest[2 * BLOCK_SIZE..].iter().all(|&b| b == 0));
+        let counters = fs.datablock_cache.cache_stats();
+        assert_eq!(counters.hits, base.hits + 2);
+        assert_eq!(counters.misses, base.misses + 1);
+        assert_eq!(fs.datablock_cache.stats().total_entries, 3);
+        close(&mut dev, &mut fs, file).unwrap();
+    }
+
+    #[test]
+    fn readahead_crosses_adjacent_extents_but_stops_at_uninit_extent() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let mut file = open(&mut dev, &mut fs, "/frag", OpenFlags::RDWR.with_create()).unwrap();
+        write_to_file(&mut dev, &mut fs, &mut file, 0, &vec![1u8; 2 * BLOCK_SIZE]).unwrap();
+        // 占掉紧随其后的物理块，让 /frag 的第 2、3 块与前两块物理上不连续
+        refresh_open_file_inode(&mut dev, &mut fs, &mut file).unwrap();
+        let last = resolve_inode_block(&mut dev, &fs, file.ino, &mut file.inode, 1).unwrap().unwrap();
+        fs.release_reservation(file.ino);
+        assert_eq!(fs.alloc_blocks_near(&mut dev, Some(last + 1), 1).unwrap(), (last + 1, 1));
+        write_to_file(&mut dev, &mut fs, &mut file, 2 * BLOCK_SIZE as u64, &vec![2u8; 2 * BLOCK_SIZE])
+            .unwrap();
+        fallocate(&mut dev, &mut fs, "/frag", 4 * BLOCK_SIZE as u64, 2 * BLOCK_SIZE as u64).unwrap();
+        write_to_file(&mut dev, &mut fs, &mut file, 6 * BLOCK_SIZE as u64, &vec![3u8; BLOCK_SIZE])
+            .unwrap();
+        refresh_open_file_inode(&mut dev, &mut fs, &mut file).unwrap();
+        let extents = collect_inode_extents(&mut dev, &fs, file.ino, &mut file.inode).unwrap();
+        assert!(extents.len() >= 3);
+        let map = resolve_inode_block_allextend(&mut fs, &mut dev, file.ino, &mut file.inode).unwrap();
+        assert_ne!(map[&1] + 1, map[&2]);
+
+        fs.datablock_cache.flush_all(&mut dev).unwrap();
+        fs.datablock_cache.clear();
+        fs.readahead_blocks = 8;
+        lseek(&mut file, 0);
+        read_at(&mut dev, &mut fs, &mut file, BLOCK_SIZE).unwrap();
+        for lbn in [1, 2, 3] {
+            assert!(fs.datablock_cache.get(map[&lbn]).is_some());
+        }
+        for lbn in [4, 5, 6] {
+            assert!(fs.datablock_cache.get(map[&lbn]).is_none());
+        }
+        assert_eq!(fs.datablock_cache.stats().total_entries, 4);
+        close(&mut dev, &mut fs, file).unwrap();
+    }
+
+    #[test]
+    fn sequential_reads_prefetch_windows_random_reads_do_not() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(16 * 1024), false, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let data: Vec<u8> = (0..20 * BLOCK_SIZE).map(|i| (i / BLOCK_SIZE) as u8).collect();
+        mkfile(&mut dev, &mut fs, "/big", Some(&data), None).unwrap();
+        fs.datablock_cache.flush_all(&mut dev).unwrap();
+
+        let mut whole_file_reads = |fs: &mut Ext4FileSystem, window: u32| {
+            fs.readahead_blocks = window;
+            fs.datablock_cache.clear();
+            let before = dev.with_raw_device(|d| Ok(d.reads)).unwrap();
+            assert_eq!(read_file(&mut dev, fs, "/big").unwrap(), data);
+            dev.with_raw_device(|d| Ok(d.reads)).unwrap() - before
+        };
+        // read_file：20 块按 8 块一个窗口读入，最后一个窗口只有 4 块
+        let single = whole_file_reads(&mut fs, 0);
+        let windowed = whole_file_reads(&mut fs, 8);
+        assert!(single >= 20);
+        assert!(windowed + (20 - 3) <= single);
+
+        // read_at：顺序读触发预读，lseek 之后的随机读不预读
+        fs.datablock_cache.clear();
+        let mut file = open(&mut dev, &mut fs, "/big", OpenFlags::RDONLY).unwrap();
+        read_at(&mut dev, &mut fs, &mut file, BLOCK_SIZE).unwrap();
+        assert_eq!(fs.datablock_cache.stats().total_entries, 9);
+        lseek(&mut file, 15 * BLOCK_SIZE as u64);
+        assert_eq!(read_at(&mut dev, &mut fs, &mut file, 10).unwrap(), [15u8; 10]);
+        assert_eq!(fs.datablock_cache.stats().total_entries, 10);
+        // 接着上一次的位置继续读，恢复预读，但不越过文件末尾
+        read_at(&mut dev, &mut fs, &mut file, BLOCK_SIZE).unwrap();
+        assert_eq!(fs.datablock_cache.stats().total_entries, 14);
+        close(&mut dev, &mut fs, file).unwrap();
+    }
+
+    #[test]
+    fn sparse_file_at_one_gib_reads_zeros_in_gap() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let mut file = open(&mut dev, &mut fs, "/sparse1g", OpenFlags::RDWR.with_create()).unwrap();
+        let gib = 1u64 << 30;
+        write_to_file(&mut dev, &mut fs, &mut file, 0, b"start").unwrap();
+        write_to_file(&mut dev, &mut fs, &mut file, gib, b"end").unwrap();
+
+        // 只分配了首尾两个块，中间的 1GiB 没有占用磁盘
+        let st = stat(&mut dev, &mut fs, "/sparse1g").unwrap();
+        assert_eq!(st.size, gib + 3);
+        assert_eq!(st.blocks, 2 * (BLOCK_SIZE / 512) as u64);
+        let extents = collect_inode_extents(&mut dev, &fs, file.ino, &mut file.inode).unwrap();
+        assert_eq!(extents.len(), 2);
+
+        lseek(&mut file, 0);
+        assert_eq!(read_at(&mut dev, &mut fs, &mut file, 5).unwrap(), b"start");
+        // 跨越首块末尾进入空洞
+        lseek(&mut file, 2);
+        let head = read_at(&mut dev, &mut fs, &mut file, BLOCK_SIZE + 10).unwrap();
+        assert_eq!(&head[..3], b"art");
+        assert!(head[3..].iter().all(|&b| b == 0));
+        lseek(&mut file, gib / 2);
+        let middle = read_at(&mut dev, &mut fs, &mut file, 3 * BLOCK_SIZE).unwrap();
+        assert_eq!(middle.len(), 3 * BLOCK_SIZE);
+        assert!(middle.iter().all(|&b| b == 0));
+        // 空洞末尾接上最后一个块
+        lseek(&mut file, gib - 4);
+        assert_eq!(read_at(&mut dev, &mut fs, &mut file, 100).unwrap(), b"\0\0\0\0end");
+        close(&mut dev, &mut fs, file).unwrap();
+    }
+
+    #[test]
+    fn read_file_with_stat_loads_inode_once() {
+        use crate::ext4_backend::ext4::*;
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(16 * 1024), false, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let body = vec![b'q'; 3 * BLOCK_SIZE + 17];
+        let mut file = open(&mut dev, &mut fs, "/s", OpenFlags::RDWR.with_create()).unwrap();
+        write_to_file(&mut dev, &mut fs, &mut file, 0, &body).unwrap();
+
+        // 清空所有缓存并清零读日志，之后的每次读都落到设备上
+        let go_cold = |dev: &mut Jbd2Dev<CountingDev>, fs: &mut Ext4FileSystem| {
+            fs.inodetable_cahce.flush_all(dev).unwrap();
+            fs.inodetable_cahce.clear();
+            fs.datablock_cache.flush_all(dev).unwrap();
+            fs.datablock_cache.clear();
+            fs.dentry_cache.clear();
+            dev.with_raw_device(|d| {
+                d.read_log.clear();
+                Ok(())
+            })
+            .unwrap();
+        };
+        let read_log = |dev: &mut Jbd2Dev<CountingDev>| dev.with_raw_device(|d| Ok(d.read_log.clone())).unwrap();
+
+        go_cold(&mut dev, &mut fs);
+        let data = read_file(&mut dev, &mut fs, "/s").unwrap();
+        let plain = read_log(&mut dev);
+
+        go_cold(&mut dev, &mut fs);
+        let (data2, st) = read_file_with_stat(&mut dev, &mut fs, "/s").unwrap();
+        let with_stat = read_log(&mut dev);
+
+        assert_eq!(data2, data);
+        assert_eq!(st.size, data2.len() as u64);
+        // 根 inode 与文件 inode 同在 inode 表第一块，只读一次；根目录块读一次，
+        // 4 个数据块合并为一次读；返回元数据不额外读取 inode 表
+        let (_, mut root) = lookup_path(&mut fs, &mut dev, "/").unwrap();
+        let root_block = resolve_inode_block(&mut dev, &fs, 2, &mut root, 0).unwrap().unwrap() as u32;
+        let (ino, mut inode) = lookup_path(&mut fs, &mut dev, "/s").unwrap();
+        let data_block = resolve_inode_block(&mut dev, &fs, ino, &mut inode, 0).unwrap().unwrap() as u32;
+        let itable = fs.group_descs[0].inode_table() as u32;
+        let expected = [(itable, 1), (root_block, 1), (data_block, 4)];
+        assert_eq!(plain, expected);
+        assert_eq!(with_stat, expected);
+        assert!(matches!(
+            read_file_with_stat(&mut dev, &mut fs, "/missing"),
+            Err(Ext4Error::NotFound { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn read_file_with_stat_matches_read_file_semantics() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+        mkfile(&mut dev, &mut fs, "/d/f", Some(b"data"), None).unwrap();
+        symlink(&mut dev, &mut fs, "/d/f", "/l").unwrap();
+
+        for path in ["/d/missing", "/nope/f", "/d/f/x", "/d"] {
+            assert_eq!(
+                read_file_with_stat(&mut dev, &mut fs, path).err(),
+                read_file(&mut dev, &mut fs, path).err(),
+                "{path}"
+            );
+        }
+        assert_eq!(
+            read_file_with_stat(&mut dev, &mut fs, "/d/missing").err(),
+            Some(Ext4Error::NotFound { index: 1, component: "missing".into() })
+        );
+        assert_eq!(read_file_with_stat(&mut dev, &mut fs, "/d").err(), Some(Ext4Error::IsADirectory));
+
+        // 符号链接跟随到目标，stat 描述目标 inode
+        let (data, st) = read_file_with_stat(&mut dev, &mut fs, "/l").unwrap();
+        assert_eq!(data, b"data");
+        assert_eq!(st.ino, lookup_path(&mut fs, &mut dev, "/d/f").unwrap().0);
+        assert_eq!(st.size, 4);
+    }
+
+    #[test]
+    fn fsync_transaction_survives_crash_while_other_files_stay_cached() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+        dev.set_journal_use(true);
+        let fs = mount(&mut dev).unwrap();
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+
+        // /old 在改写前已整体落盘，/keep 和 /sub/other 是之后新建的
+        let mut old = open(&mut dev, &mut fs, "/old", OpenFlags::RDWR.with_create()).unwrap();
+        write_to_file(&mut dev, &mut fs, &mut old, 0, &[0xAA; BLOCK_SIZE]).unwrap();
+        fs.flush_all_caches(&mut dev).unwrap();
+        fs.write_group_descriptors(&mut dev).unwrap();
+        dev.commit_journal().unwrap();
+        // 原地改写不分配；随后只提交 inode（mtime），数据块留在缓存里
+        write_to_file(&mut dev, &mut fs, &mut old, 0, &[0xBB; BLOCK_SIZE]).unwrap();
+        fs.inodetable_cahce.flush_all(&mut dev).unwrap();
+        dev.commit_journal().unwrap();
+
+        mkdir(&mut dev, &mut fs, "/sub").unwrap();
+        let mut other = open(&mut dev, &mut fs, "/sub/other", OpenFlags::RDWR.with_create()).unwrap();
+        let mut keep = open(&mut dev, &mut fs, "/keep", OpenFlags::RDWR.with_create()).unwrap();
+        // 逻辑上隔一块写一块，extent 超过 inode 内 4 个槽位，树需要独立的叶子块
+        for i in 0..8u64 {
+            let blk = vec![i as u8 + 1; BLOCK_SIZE];
+            write_to_file(&mut dev, &mut fs, &mut keep, 2 * i * BLOCK_SIZE as u64, &blk).unwrap();
+            write_to_file(&mut dev, &mut fs, &mut other, 2 * i * BLOCK_SIZE as u64, &blk).unwrap();
+        }
+        // i_block[1] 高 16 位是 extent 头的 eh_depth
+        assert!(keep.inode.i_block[1] >> 16 > 0);
+        let expected = read_file(&mut dev, &mut fs, "/keep").unwrap();
+        assert_eq!(expected.len(), 15 * BLOCK_SIZE);
+
+        fsync_transaction(&mut dev, &mut fs, &mut keep).unwrap();
+        assert!(fs.datablock_cache.stats().dirty_entries > 0);
+
+        // 崩溃：缓存里的内容全部丢失，只剩设备上的数据和日志
+        let crashed = dev.with_raw_device(|d| Ok(d.clone())).unwrap();
+        drop(fs);
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, crashed, true, false);
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/keep").unwrap(), expected);
+        // 与 /keep 交错分配的 /sub/other 随同一事务提交，位图里的块都有属主
+        assert_eq!(read_file(&mut dev, &mut fs, "/sub/other").unwrap(), expected);
+        assert_eq!(fsck_bitmap_owners(&mut dev, &mut fs).unwrap(), (vec![], vec![]));
+        assert!(verify_free_counts(&mut dev, &mut fs).unwrap().is_empty());
+        // inode 不脏的原地改写未 fsync，随缓存丢失
+        assert_eq!(read_file(&mut dev, &mut fs, "/old").unwrap(), vec![0xAA; BLOCK_SIZE]);
+    }
+
+    #[test]
+    fn mounted_fs_handle_borrows_device_until_umount() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        ext4::umount(fs, &mut dev).unwrap();
+
+        let mut mounted = MountedFs::mount(&mut dev).unwrap();
+        mounted.mkdir_p("/docs/sub").unwrap();
+        let mut file = mounted.create("/docs/sub/note").unwrap();
+        mounted.write_at(&mut file, b"hello").unwrap();
+        mounted.close(file).unwrap();
+        assert_eq!(mounted.read_file("/docs/sub/note").unwrap(), b"hello");
+        assert!(mounted.find_file("/docs").unwrap().is_dir());
+        assert_eq!(mounted.stat("/docs/sub/note").unwrap().size, 5);
+        assert_eq!(
+            mounted.read_file("/docs/nope"),
+            Err(Ext4Error::NotFound { index: 1, component: "nope".into() })
+        );
+        mounted.umount().unwrap();
+
+        // 卸载后设备借用结束，自由函数照常可用
+        let mut fs = ext4::mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/docs/sub/note").unwrap(), b"hello");
+        let mut mounted = MountedFs::from_parts(&mut dev, fs);
+        mounted.unlink("/docs/sub/note").unwrap();
+        assert!(mounted.read_file("/docs/sub/note").is_err());
+        mounted.umount().unwrap();
+    }
+
+    #[test]
+    fn ext4_file_cursor_seeks_reads_and_extends() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let data: Vec<u8> = (0..9000u32).map(|i| (i % 251) as u8).collect();
+        mkfile(&mut dev, &mut fs, "/data", Some(&data), None).unwrap();
+
+        let file = open(&mut dev, &mut fs, "/data", OpenFlags::RDWR).unwrap();
+        let mut f = Ext4File::new(&mut dev, &mut fs, file);
+        assert_eq!(f.len(), 9000);
+
+        // 从中间跨块边界读
+        let mid = BLOCK_SIZE as u64 - 10;
+        assert_eq!(f.seek(SeekFrom::Start(mid)).unwrap(), mid);
+        let mut buf = [0u8; 20];
+        assert_eq!(f.read(&mut buf).unwrap(), 20);
+        assert_eq!(&buf[..], &data[mid as usize..mid as usize + 20]);
+        assert_eq!(f.stream_position(), mid + 20);
+
+        // 读到 EOF 截止
+        assert_eq!(f.seek(SeekFrom::End(-5)).unwrap(), 8995);
+        assert_eq!(f.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], &data[8995..]);
+        assert_eq!(f.read(&mut buf).unwrap(), 0);
+        assert_eq!(f.seek(SeekFrom::Current(-9001)), Err(Ext4Error::InvalidInput));
+
+        // 越过 EOF 写入扩展文件，中间为空洞
+        assert_eq!(f.seek(SeekFrom::Current(100)).unwrap(), 9100);
+        assert_eq!(f.write(b"tail").unwrap(), 4);
+        assert_eq!(f.len(), 9104);
+        f.seek(SeekFrom::Start(8990)).unwrap();
+        let mut rest = [0xffu8; 200];
+        assert_eq!(f.read(&mut rest).unwrap(), 114);
+        assert_eq!(&rest[..10], &data[8990..]);
+        assert!(rest[10..110].iter().all(|&b| b == 0));
+        assert_eq!(&rest[110..114], b"tail");
+        f.close().unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+//! 块设备吞吐量自测模块
+//!
+//! 在已挂载的文件系统上创建临时文件，测量顺序/随机读写吞吐量，
+//! 以结构化数字返回，便于程序化比较不同缓存大小、分配策略等配置。
+//! 计时依赖 `std::time`，仅在 `std` 特性（或测试）下编译
+
+use alloc::vec::Vec;
+use std::time::Instant;
+
+use crate::ext4_backend::api::*;
+use crate::ext4_backend::block_cache::*;
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::file::*;
+
+/// 自测参数
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// 测试文件路径，测试结束后删除
+    pub path: &'static str,
+    /// 测试文件总大小（字节）
+    pub file_size: usize,
+    /// 单次 I/O 大小（字节）
+    pub io_size: usize,
+    /// 随机读写的次数
+    pub random_ops: usize,
+    /// 随机偏移的种子
+    pub seed: u64,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            path: "/.bench_io",
+            file_size: 64 * 1024 * 1024,
+            io_size: 1024 * 1024,
+            random_ops: 256,
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+
+/// 单项测量结果
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BenchSample {
+    /// 传输字节数
+    pub bytes: u64,
+    /// 耗时（秒）
+    pub secs: f64,
+}
+
+impl BenchSample {
+    /// 吞吐量（MiB/s），耗时为 0 时返回 0
+    pub fn mib_per_sec(&self) -> f64 {
+        if self.secs > 0.0 {
+            self.bytes as f64 / (1024.0 * 1024.0) / self.secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// 自测结果
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BenchResult {
+    pub seq_write: BenchSample,
+    pub seq_read: BenchSample,
+    pub rand_write: BenchSample,
+    pub rand_read: BenchSample,
+    /// 整个自测期间的缓存计数，用于对照吞吐量调整缓存大小
+    pub cache: Ext4CacheStats,
+}
+
+/// xorshift64 伪随机数，只用于生成可复现的随机偏移
+fn next_rand(state: &mut u64) -> u64 {
+    let mut x = *state;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    *state = x;
+    x
+}
+
+/// 把缓存中的数据、inode 表和位图刷到设备，使写入计时包含落盘
+fn flush_caches<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+) -> BlockDevResult<()> {
+    fs.datablock_cache.flush_all(block_dev)?;
+    fs.inodetable_cahce.flush_all(block_dev)?;
+    fs.bitmap_cache.flush_all(block_dev)?;
+    Ok(())
+}
+
+/// 测量顺序/随机读写吞吐量
+/// io_size 为 0 或大于 file_size 时返回 InvalidInput；测试文件已存在时会被覆盖
+pub fn bench_io<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    opts: &BenchOptions,
+) -> Ext4Result<BenchResult> {
+    if opts.io_size == 0 || opts.io_size > opts.file_size {
+        return Err(Ext4Error::InvalidInput);
+    }
+    let chunks = (opts.file_size / opts.io_size) as u64;
+    let io_size = opts.io_size as u64;
+    let payload: Vec<u8> = (0..opts.io_size).map(|i| (i % 251) as u8).collect();
+    let mut result = BenchResult::default();
+    fs.reset_cache_stats();
+    let mut file = open(
+        block_dev,
+        fs,
+        opts.path,
+        OpenFlags::RDWR.with_create().with_truncate(),
+    )?;
+
+    // 顺序写
+    let start = Instant::now();
+    for i in 0..chunks {
+        write_to_file(block_dev, fs, &mut file, i * io_size, &payload)?;
+    }
+    flush_caches(block_dev, fs)?;
+    result.seq_write = BenchSample {
+        bytes: chunks * io_size,
+        secs: start.elapsed().as_secs_f64(),
+    };
+
+    // 顺序读
+    let start = Instant::now();
+    let mut read_bytes = 0u64;
+    lseek(&mut file, 0);
+    for _ in 0..chunks {
+        let data = read_at(block_dev, fs, &mut file, opts.io_size)?;
+        read_bytes += data.len() as u64;
+    }
+    result.seq_read = BenchSample {
+        bytes: read_bytes,
+        secs: start.elapsed().as_secs_f64(),
+    };
+
+    // 随机写：覆盖已分配的块，不改变文件大小
+    let mut state = opts.seed | 1;
+    let offsets: Vec<u64> = (0..opts.random_ops)
+        .map(|_| (next_rand(&mut state) % chunks) * io_size)
+        .collect();
+    let start = Instant::now();
+    for &off in &offsets {
+        write_to_file(block_dev, fs, &mut file, off, &payload)?;
+    }
+    flush_caches(block_dev, fs)?;
+    result.rand_write = BenchSample {
+        bytes: offsets.len() as u64 * io_size,
+        secs: start.elapsed().as_secs_f64(),
+    };
+
+    // 随机读
+    let start = Instant::now();
+    let mut read_bytes = 0u64;
+    for &off in offsets.iter().rev() {
+        lseek(&mut file, off);
+        let data = read_at(block_dev, fs, &mut file, opts.io_size)?;
+        read_bytes += data.len() as u64;
+    }
+    result.rand_read = BenchSample {
+        bytes: read_bytes,
+        secs: start.elapsed().as_secs_f64(),
+    };
+
+    result.cache = fs.cache_stats();
+
+    close(block_dev, fs, file)?;
+    unlink(block_dev, fs, opts.path)?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::loopfile::get_file_inode;
+    use crate::ext4_backend::test_support::*;
+
+    #[test]
+    fn bench_io_reports_nonzero_throughput() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let opts = BenchOptions {
+            file_size: 1024 * 1024,
+            io_size: 64 * 1024,
+            random_ops: 16,
+            ..BenchOptions::default()
+        };
+        let result = bench_io(&mut dev, &mut fs, &opts).unwrap();
+        for sample in [
+            result.seq_write,
+            result.seq_read,
+            result.rand_write,
+            result.rand_read,
+        ] {
+            assert!(sample.bytes > 0);
+            assert!(sample.mib_per_sec() > 0.0);
+        }
+        assert_eq!(result.seq_read.bytes, 1024 * 1024);
+        assert!(result.cache.data.counters.hits + result.cache.data.counters.misses > 0);
+        assert!(result.cache.data.counters.writebacks > 0);
+        assert!(get_file_inode(&mut fs, &mut dev, opts.path).unwrap().is_none());
+
+        let bad = BenchOptions {
+            io_size: 0,
+            ..BenchOptions::default()
+        };
+        assert!(matches!(
+            bench_io(&mut dev, &mut fs, &bad),
+            Err(Ext4Error::InvalidInput)
+        ));
+    }
+}
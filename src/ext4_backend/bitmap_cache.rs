@@ -1,9 +1,12 @@
 //! 位图缓存模块
 
+use crate::ext4_backend::block_cache::{CacheCategoryStats, CacheCounters};
 use crate::ext4_backend::blockdev::*;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+use crate::ext4_backend::crc32c::crc32c;
 use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::ChecksumPolicy;
 use crate::BITMAP_CACHE_MAX;
 use log::debug;
 
@@ -68,6 +71,77 @@ impl CachedBitmap {
     }
 }
 
+/// metadata_csum 下的位图校验和状态
+/// 校验和为 crc32c(种子, 位图前 N 字节)，块位图 N=每组块数/8，inode 位图 N=每组 inode 数/8
+#[derive(Debug, Clone)]
+pub struct BitmapCsum {
+    /// 元数据校验和种子
+    pub seed: u32,
+    /// 块位图参与校验的字节数
+    pub block_bytes: usize,
+    /// inode 位图参与校验的字节数
+    pub inode_bytes: usize,
+    /// 块组描述符是否存放高 16 位（64 字节描述符）
+    pub has_hi: bool,
+    /// 校验失败时的处理策略
+    pub policy: ChecksumPolicy,
+    /// 每个位图当前应有的校验和；未初始化的位图不在其中，加载时不校验
+    expected: BTreeMap<CacheKey, u32>,
+    /// 写回后尚未填入块组描述符的新校验和
+    updates: BTreeMap<CacheKey, u32>,
+}
+
+impl BitmapCsum {
+    pub fn new(
+        seed: u32,
+        block_bytes: usize,
+        inode_bytes: usize,
+        has_hi: bool,
+        policy: ChecksumPolicy,
+    ) -> Self {
+        Self {
+            seed,
+            block_bytes,
+            inode_bytes,
+            has_hi,
+            policy,
+            expected: BTreeMap::new(),
+            updates: BTreeMap::new(),
+        }
+    }
+
+    /// 记录块组描述符中保存的校验和，之后加载该位图时与之比对
+    pub fn set_expected(&mut self, key: CacheKey, csum: u32) {
+        self.expected.insert(key, csum);
+    }
+
+    /// 计算位图校验和
+    pub fn compute(&self, key: &CacheKey, data: &[u8]) -> u32 {
+        let len = match key.bitmap_type {
+            BitmapType::Block => self.block_bytes,
+            BitmapType::Inode => self.inode_bytes,
+        };
+        crc32c(self.seed, &data[..len.min(data.len())])
+    }
+
+    /// 校验刚读入的位图；描述符只有低 16 位时只比较低 16 位
+    fn verify(&self, key: &CacheKey, data: &[u8]) -> BlockDevResult<()> {
+        let Some(&expected) = self.expected.get(key) else {
+            return Ok(());
+        };
+        let mask = if self.has_hi { u32::MAX } else { 0xFFFF };
+        let ok = self.compute(key, data) & mask == expected & mask;
+        self.policy.check(ok, "bitmap")
+    }
+
+    /// 位图写回时重算校验和，等待填入块组描述符
+    fn record(&mut self, key: CacheKey, data: &[u8]) {
+        let csum = self.compute(&key, data);
+        self.expected.insert(key, csum);
+        self.updates.insert(key, csum);
+    }
+}
+
 /// 位图缓存管理器
 pub struct BitmapCache {
     /// 缓存的位图
@@ -76,6 +150,10 @@ pub struct BitmapCache {
     max_entries: usize,
     /// 访问计数器（用于LRU）
     access_counter: u64,
+    /// 命中、未命中、写回和淘汰计数
+    counters: CacheCounters,
+    /// 启用 metadata_csum 时的位图校验和状态
+    pub csum: Option<BitmapCsum>,
 }
 
 impl BitmapCache {
@@ -85,6 +163,8 @@ impl BitmapCache {
             cache: BTreeMap::new(),
             max_entries,
             access_counter: 0,
+            counters: CacheCounters::default(),
+            csum: None,
         }
     }
 
@@ -103,17 +183,11 @@ impl BitmapCache {
         key: CacheKey,
         block_num: u64,
     ) -> BlockDevResult<&CachedBitmap> {
-        if !self.cache.contains_key(&key) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
-            }
-
-            block_dev.read_block(block_num as u32)?;
-            let buffer = block_dev.buffer();
-            let data = buffer.to_vec();
-
-            let bitmap = CachedBitmap::new(data, block_num);
-            self.cache.insert(key, bitmap);
+        if self.cache.contains_key(&key) {
+            self.counters.hits += 1;
+        } else {
+            self.counters.misses += 1;
+            self.load(block_dev, key, block_num)?;
         }
 
         self.access_counter += 1;
@@ -131,17 +205,11 @@ impl BitmapCache {
         key: CacheKey,
         block_num: u64,
     ) -> BlockDevResult<&mut CachedBitmap> {
-        if !self.cache.contains_key(&key) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
-            }
-
-            block_dev.read_block(block_num as u32)?;
-            let buffer = block_dev.buffer();
-            let data = buffer.to_vec();
-
-            let bitmap = CachedBitmap::new(data, block_num);
-            self.cache.insert(key, bitmap);
+        if self.cache.contains_key(&key) {
+            self.counters.hits += 1;
+        } else {
+            self.counters.misses += 1;
+            self.load(block_dev, key, block_num)?;
         }
 
         self.access_counter += 1;
@@ -153,6 +221,42 @@ impl BitmapCache {
         }
     }
 
+    /// 从磁盘读入位图，启用校验和时先校验再放入缓存
+    fn load<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        key: CacheKey,
+        block_num: u64,
+    ) -> BlockDevResult<()> {
+        if self.cache.len() >= self.max_entries {
+            self.evict_lru(block_dev)?;
+        }
+
+        block_dev.read_block(block_num)?;
+        let data = block_dev.buffer().to_vec();
+        if let Some(csum) = &self.csum {
+            csum.verify(&key, &data)?;
+        }
+
+        self.cache.insert(key, CachedBitmap::new(data, block_num));
+        Ok(())
+    }
+
+    /// 取出写回后重算的位图校验和，由调用方填入块组描述符
+    pub fn take_csum_updates(&mut self) -> BTreeMap<CacheKey, u32> {
+        match &mut self.csum {
+            Some(csum) => core::mem::take(&mut csum.updates),
+            None => BTreeMap::new(),
+        }
+    }
+
+    /// 位图即将写回：启用校验和时记录新的校验和
+    fn record_csum(&mut self, key: CacheKey, data: &[u8]) {
+        if let Some(csum) = &mut self.csum {
+            csum.record(key, data);
+        }
+    }
+
     /// 获取已缓存的位图（不加载）
     pub fn get(&self, key: &CacheKey) -> Option<&CachedBitmap> {
         self.cache.get(key)
@@ -208,6 +312,7 @@ impl BitmapCache {
 
         if let Some(key) = lru_key {
             self.evict(block_dev, &key)?;
+            self.counters.evictions += 1;
         }
 
         Ok(())
@@ -221,7 +326,9 @@ impl BitmapCache {
     ) -> BlockDevResult<()> {
         if let Some(bitmap) = self.cache.remove(key)
             && bitmap.dirty {
+                self.record_csum(*key, &bitmap.data);
                 Self::write_bitmap_static(block_dev, bitmap.block_num, &bitmap.data)?;
+                self.counters.writebacks += 1;
             }
         Ok(())
     }
@@ -253,7 +360,9 @@ impl BitmapCache {
                 key.group_id, key.bitmap_type, block_num
             );
 
+            self.record_csum(key, &data);
             Self::write_bitmap_static(block_dev, block_num, &data)?;
+            self.counters.writebacks += 1;
         }
 
         for bitmap in self.cache.values_mut() {
@@ -275,7 +384,9 @@ impl BitmapCache {
                 let data = bitmap.data.clone();
 
                 // 写回磁盘
+                self.record_csum(*key, &data);
                 Self::write_bitmap_static(block_dev, block_num, &data)?;
+                self.counters.writebacks += 1;
 
                 // 清除脏标记
                 if let Some(bitmap) = self.cache.get_mut(key) {
@@ -291,10 +402,10 @@ impl BitmapCache {
         block_num: u64,
         data: &[u8],
     ) -> BlockDevResult<()> {
-        block_dev.read_block(block_num as u32)?;
+        block_dev.read_block(block_num)?;
         let buffer = block_dev.buffer_mut();
         buffer[..data.len()].copy_from_slice(data);
-        block_dev.write_block(block_num as u32, true)?;
+        block_dev.write_block(block_num, true)?;
         Ok(())
     }
 
@@ -303,6 +414,33 @@ impl BitmapCache {
         self.cache.clear();
     }
 
+    /// 调整容量上限，超出部分按 LRU 淘汰（脏位图先写回）
+    pub fn set_capacity<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        max_entries: usize,
+    ) -> BlockDevResult<()> {
+        self.max_entries = max_entries.max(1);
+        while self.cache.len() > self.max_entries {
+            self.evict_lru(block_dev)?;
+        }
+        Ok(())
+    }
+
+    /// 块缓存统计中的位图部分
+    pub fn category_stats(&self) -> CacheCategoryStats {
+        CacheCategoryStats {
+            cached_blocks: self.cache.len(),
+            capacity: self.max_entries,
+            counters: self.counters,
+        }
+    }
+
+    /// 访问计数清零
+    pub fn reset_counters(&mut self) {
+        self.counters = CacheCounters::default();
+    }
+
     /// 获取缓存统计
     pub fn stats(&self) -> CacheStats {
         let dirty_count = self.cache.values().filter(|b| b.dirty).count();
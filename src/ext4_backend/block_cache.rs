@@ -0,0 +1,107 @@
+//! 数据块、inode、位图三类块缓存的容量与统计
+//!
+//! 三类缓存各自保存条目、各自按 LRU 淘汰，彼此不共享空间：每类缓存的容量单独设置，
+//! 大文件顺序读写只会淘汰数据块缓存中的条目，不会把热的 inode 表块和位图淘汰出去。
+//! inode 类的容量同时覆盖 inode 表块和已解析的 inode。
+//! 写回经 `Ext4FileSystem::flush_all_caches` 一次完成
+
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::error::*;
+
+/// 元数据（inode 表、位图）缓存的最小容量（块）
+pub const CACHE_META_MIN_BLOCKS: usize = 4;
+/// 数据块缓存的最小容量（块）
+pub const CACHE_DATA_MIN_BLOCKS: usize = 8;
+
+/// 三类缓存各自的容量（单位：块），互不挤占
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheCapacities {
+    pub data: usize,
+    pub inode_table: usize,
+    pub bitmap: usize,
+}
+
+impl CacheCapacities {
+    /// 任一类低于最小容量时返回 InvalidInput
+    pub fn new(data: usize, inode_table: usize, bitmap: usize) -> Ext4Result<Self> {
+        if data < CACHE_DATA_MIN_BLOCKS || inode_table < CACHE_META_MIN_BLOCKS || bitmap < CACHE_META_MIN_BLOCKS {
+            return Err(Ext4Error::InvalidInput);
+        }
+        Ok(Self { data, inode_table, bitmap })
+    }
+
+    /// 三类缓存容量之和，即块缓存最多占用的块数
+    pub fn total_blocks(&self) -> usize {
+        self.data + self.inode_table + self.bitmap
+    }
+}
+
+impl Default for CacheCapacities {
+    /// 各缓存的默认容量
+    fn default() -> Self {
+        Self {
+            data: DATABLOCK_CACHE_MAX,
+            inode_table: INODE_TABLE_BLOCK_CACHE_MAX,
+            bitmap: BITMAP_CACHE_MAX,
+        }
+    }
+}
+
+/// 单个缓存的访问计数，均为普通计数器，可随时清零
+/// 命中指请求的块无需读设备（inode 缓存中已解析的 inode 或其所在表块已缓存都算命中）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheCounters {
+    pub hits: u64,
+    pub misses: u64,
+    /// 写回设备的块数（inode 缓存按 inode 表块的写次数计）
+    pub writebacks: u64,
+    /// LRU 淘汰的条目数
+    pub evictions: u64,
+}
+
+impl CacheCounters {
+    /// 命中率（百分比），尚无访问时为 0
+    pub fn hit_rate_percent(&self) -> u64 {
+        let total = self.hits + self.misses;
+        if total == 0 { 0 } else { self.hits * 100 / total }
+    }
+
+    /// 两组计数相加
+    pub fn merged(&self, other: &CacheCounters) -> CacheCounters {
+        CacheCounters {
+            hits: self.hits + other.hits,
+            misses: self.misses + other.misses,
+            writebacks: self.writebacks + other.writebacks,
+            evictions: self.evictions + other.evictions,
+        }
+    }
+}
+
+/// 单类缓存的占用、容量和访问计数
+/// inode 类按块折算：inode 表块和已解析的 inode（按所占块数）都计入
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheCategoryStats {
+    pub cached_blocks: usize,
+    pub capacity: usize,
+    pub counters: CacheCounters,
+}
+
+/// 三类缓存各自的统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ext4CacheStats {
+    pub data: CacheCategoryStats,
+    pub inode: CacheCategoryStats,
+    pub bitmap: CacheCategoryStats,
+}
+
+impl Ext4CacheStats {
+    /// 三类缓存的计数之和
+    pub fn total(&self) -> CacheCounters {
+        self.data.counters.merged(&self.inode.counters).merged(&self.bitmap.counters)
+    }
+
+    /// 三类缓存当前占用的块数之和，不超过 CacheCapacities::total_blocks
+    pub fn cached_blocks(&self) -> usize {
+        self.data.cached_blocks + self.inode.cached_blocks + self.bitmap.cached_blocks
+    }
+}
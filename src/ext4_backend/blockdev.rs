@@ -1,4 +1,7 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
 use log::{error, trace, warn};
 
 use crate::ext4_backend::config::*;
@@ -11,17 +14,18 @@ use crate::ext4_backend::config::JBD2_BUFFER_MAX;
 pub trait INeedBlockdevToWrite {}
 
 /// 外部需要实现的块设备trait
+/// read/write 的 count 为 0 时约定为成功的空操作：不访问设备，也不检查 block_id 和缓冲区
 pub trait BlockDevice {
     /// 写入数据到块设备
     /// * `buffer` - 要写入的数据
     /// * `block_id` - 起始块号
-    /// * `count` - 块数量
+    /// * `count` - 块数量，为 0 时直接返回 Ok
     fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()>;
 
     /// 从块设备读取数据
     /// * `buffer` - 读取数据的目标缓冲区
     /// * `block_id` - 起始块号
-    /// * `count` - 块数量
+    /// * `count` - 块数量，为 0 时直接返回 Ok
     fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()>;
 
     /// 打开块设备
@@ -52,6 +56,184 @@ pub trait BlockDevice {
     fn is_readonly(&self) -> bool {
         false // 默认为可读写
     }
+
+    /// 以 64 位块号写入，启用 64bit 特性、块号可能超过 u32 时经由这里访问
+    /// 默认在块号能用 u32 表示时转给 write，否则返回 BlockOutOfRange；
+    /// 支持超过 2^32 个块的设备需要覆盖 read64/write64
+    fn write64(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        match u32::try_from(block_id) {
+            Ok(id) => self.write(buffer, id, count),
+            Err(_) => Err(BlockDevError::BlockOutOfRange {
+                block_id,
+                max_blocks: self.total_blocks(),
+            }),
+        }
+    }
+
+    /// 以 64 位块号读取，默认行为同 write64
+    fn read64(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        match u32::try_from(block_id) {
+            Ok(id) => self.read(buffer, id, count),
+            Err(_) => Err(BlockDevError::BlockOutOfRange {
+                block_id,
+                max_blocks: self.total_blocks(),
+            }),
+        }
+    }
+
+    /// 通知设备这些块已不再使用（TRIM/discard），精简配置的后端可以回收空间
+    /// 默认不支持，返回 Unsupported
+    fn discard(&mut self, block_id: u64, count: u32) -> BlockDevResult<()> {
+        let _ = (block_id, count);
+        Err(BlockDevError::Unsupported)
+    }
+
+    /// 报告设备能力，文件系统据此决定是否下发 discard、提交日志时是否需要 flush 屏障
+    /// 默认：不支持 discard，flush 视为有效（保守地保留屏障），非旋转介质
+    fn capabilities(&self) -> DevCaps {
+        DevCaps {
+            discard: false,
+            flush: true,
+            rotational: false,
+            sector_size: self.block_size(),
+            readonly: self.is_readonly(),
+        }
+    }
+}
+
+/// 块设备能力
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DevCaps {
+    /// 支持 discard/TRIM
+    pub discard: bool,
+    /// 设备带易失写缓存，flush 才能保证数据落盘；为 false 时写入即持久
+    pub flush: bool,
+    /// 旋转介质（机械盘）
+    pub rotational: bool,
+    /// 扇区大小（字节）
+    pub sector_size: u32,
+    /// 只读设备
+    pub readonly: bool,
+}
+
+/// 分区视图：把大磁盘镜像中从 offset 开始、长 size 个块的区间当作独立的块设备
+/// 块号按分区内偏移计算，越过分区末尾的访问返回 BlockOutOfRange
+pub struct PartitionDev<B: BlockDevice> {
+    inner: B,
+    offset: u64,
+    size: u64,
+}
+
+impl<B: BlockDevice> PartitionDev<B> {
+    /// offset、size 以块为单位，分区必须完整落在底层设备内
+    pub fn new(inner: B, offset: u64, size: u64) -> BlockDevResult<Self> {
+        let max_blocks = inner.total_blocks();
+        if offset.checked_add(size).is_none_or(|end| end > max_blocks) {
+            return Err(BlockDevError::BlockOutOfRange {
+                block_id: offset.saturating_add(size),
+                max_blocks,
+            });
+        }
+        Ok(Self {
+            inner,
+            offset,
+            size,
+        })
+    }
+
+    /// 分区起始块（底层设备块号）
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// 底层设备
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// 底层设备（可变）
+    pub fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// 取回底层设备
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// 分区内块号转换为底层设备块号，区间越过分区末尾时报错
+    fn translate(&self, block_id: u64, count: u32) -> BlockDevResult<u64> {
+        match block_id.checked_add(count as u64) {
+            Some(end) if end <= self.size => Ok(self.offset + block_id),
+            _ => Err(BlockDevError::BlockOutOfRange {
+                block_id,
+                max_blocks: self.size,
+            }),
+        }
+    }
+}
+
+impl<B: BlockDevice> BlockDevice for PartitionDev<B> {
+    fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.write64(buffer, block_id as u64, count)
+    }
+
+    fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.read64(buffer, block_id as u64, count)
+    }
+
+    fn write64(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        let phys = self.translate(block_id, count)?;
+        self.inner.write64(buffer, phys, count)
+    }
+
+    fn read64(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        let phys = self.translate(block_id, count)?;
+        self.inner.read64(buffer, phys, count)
+    }
+
+    fn open(&mut self) -> BlockDevResult<()> {
+        self.inner.open()
+    }
+
+    fn close(&mut self) -> BlockDevResult<()> {
+        self.inner.close()
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.size
+    }
+
+    fn block_size(&self) -> u32 {
+        self.inner.block_size()
+    }
+
+    fn flush(&mut self) -> BlockDevResult<()> {
+        self.inner.flush()
+    }
+
+    fn is_open(&self) -> bool {
+        self.inner.is_open()
+    }
+
+    fn is_readonly(&self) -> bool {
+        self.inner.is_readonly()
+    }
+
+    fn discard(&mut self, block_id: u64, count: u32) -> BlockDevResult<()> {
+        let phys = self.translate(block_id, count)?;
+        self.inner.discard(phys, count)
+    }
+
+    fn capabilities(&self) -> DevCaps {
+        self.inner.capabilities()
+    }
 }
 
 /// 块设备缓存
@@ -100,7 +282,7 @@ struct BlockDev<B: BlockDevice> {
     dev: B,
     buffer: BlockBuffer,
     is_dirty: bool,            // 缓冲区是否已修改
-    cached_block: Option<u32>, // 当前缓存的块号
+    cached_block: Option<u64>, // 当前缓存的块号
 }
 pub enum Jbd2RunState {
     Commit,
@@ -110,8 +292,23 @@ pub struct Jbd2Dev<B: BlockDevice> {
     _mode: u8, //日志级别，默认ordered 0
     inner: BlockDev<B>,
     journal_use: bool, //是否启用日志系统
+    journal_checksum: bool, //新写入的事务是否带 CSUM_V3 校验和
     _state: Jbd2RunState,
     systeam: Option<JBD2DEVSYSTEM>,
+    commit_threshold: usize, //事务缓存达到该块数时自动提交
+    txn_depth: u32, //begin_transaction 嵌套层数，>0 时只在描述块装满时提前提交
+    direct_write: bool, //元数据绕过日志直接落盘
+    replay_progress: Box<dyn FnMut(u32, u32) + Send>, //日志重放进度回调(已处理事务数, 总事务数)
+}
+
+/// 日志 tag 只保存 32 位主盘块号，范围内的块才能经由日志写入
+/// 读写挂载时已拒绝日志无法寻址的文件系统，这里兜底防止块号被截断
+fn journal_addressable(block_id: u64, count: u32) -> BlockDevResult<()> {
+    if block_id + count as u64 > u32::MAX as u64 + 1 {
+        error!("metadata block {block_id} (+{count}) beyond the 32-bit journal tag range");
+        return Err(BlockDevError::Unsupported);
+    }
+    Ok(())
 }
 
 ///jbd2代理blockdev
@@ -119,14 +316,76 @@ pub struct Jbd2Dev<B: BlockDevice> {
 /// 采用Jouranl超级快注入的思想，必须需要使用mount来给块设备注入超级块，之后才能使用日志。
 impl<B: BlockDevice> Jbd2Dev<B> {
     ///你拿到我之后应该先把超级块给我传进来吧
-    pub fn initial_jbd2dev(_mode: u8, block_dev:B, use_journal: bool) -> Self {
+    /// journal_checksum 为 true 时在日志干净的前提下启用 CSUM_V3，重放时丢弃校验失败的事务及其之后的事务
+    pub fn initial_jbd2dev(_mode: u8, block_dev:B, use_journal: bool, journal_checksum: bool) -> Self {
         let block_dev = BlockDev::new(block_dev);
         Self {
             _mode,
             inner: block_dev,
             journal_use: use_journal,
+            journal_checksum,
             _state: Jbd2RunState::Commit,
             systeam: None,
+            commit_threshold: JBD2_BUFFER_MAX,
+            txn_depth: 0,
+            direct_write: false,
+            replay_progress: Box::new(|_, _| {}),
+        }
+    }
+
+    /// 设置事务自动提交阈值（元数据块数）
+    /// 阈值越小提交越频繁、延迟越低；越大合并写越多
+    /// 取值会被限制在 [1, 单个描述块可容纳的 tag 数]
+    pub fn set_commit_threshold(&mut self, blocks: usize) {
+        self.commit_threshold = blocks.clamp(1, JBD2_DESCRIPTOR_TAG_MAX);
+    }
+
+    pub fn commit_threshold(&self) -> usize {
+        self.commit_threshold
+    }
+
+    /// 当前生效的自动提交阈值：运行中事务内只受单个描述块容量限制
+    fn effective_threshold(&self) -> usize {
+        if self.txn_depth > 0 {
+            JBD2_DESCRIPTOR_TAG_MAX
+        } else {
+            self.commit_threshold
+        }
+    }
+
+    /// 开始一个运行中事务：之后的元数据写只进事务缓存，直到配对的 commit_transaction
+    /// 可以嵌套，最外层结束时才真正写出 descriptor + commit；
+    /// 缓存装满一个描述块时仍会提前提交
+    /// 最外层开始时若日志剩余空间低于 JBD2_CHECKPOINT_FREE_MIN（小日志取总块数一半）会先做 checkpoint
+    pub fn begin_transaction(&mut self) {
+        if self.txn_depth == 0 {
+            let usage = self.journal_usage();
+            if usage.total_blocks != 0
+                && usage.free_blocks() < JBD2_CHECKPOINT_FREE_MIN.min(usage.total_blocks / 2)
+                && let Err(e) = self.checkpoint()
+            {
+                warn!("journal checkpoint failed: {e:?}");
+            }
+        }
+        self.txn_depth += 1;
+    }
+
+    /// 结束 begin_transaction 开启的事务，最外层时提交缓存的元数据
+    pub fn commit_transaction(&mut self) -> BlockDevResult<()> {
+        self.txn_depth = self.txn_depth.saturating_sub(1);
+        if self.txn_depth > 0 {
+            return Ok(());
+        }
+        self.commit_journal()
+    }
+
+    /// 开始事务并返回句柄，句柄 drop 时自动 commit_transaction
+    /// 句柄可解引用为 Jbd2Dev，期间照常读写
+    pub fn transaction(&mut self) -> Jbd2Handle<'_, B> {
+        self.begin_transaction();
+        Jbd2Handle {
+            dev: self,
+            finished: false,
         }
     }
 
@@ -134,20 +393,68 @@ impl<B: BlockDevice> Jbd2Dev<B> {
         self.journal_use
     }
 
+    /// 当前是否处于直写模式（元数据不进日志）
+    pub fn is_direct_write(&self) -> bool {
+        self.direct_write
+    }
+
+    /// 在直写模式下执行一次操作：期间的元数据写不进 JBD2 事务，
+    /// 直接写到底层设备，成功结束后立即 flush。
+    /// 用于 journal 尚不存在的 mkfs 引导等场景，结束后恢复原模式
+    pub fn with_direct_write<R>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> BlockDevResult<R>,
+    ) -> BlockDevResult<R> {
+        let old = self.direct_write;
+        self.direct_write = true;
+        let result = f(self);
+        self.direct_write = old;
+        let value = result?;
+        self.inner.flush()?;
+        Ok(value)
+    }
+
+    /// 设置日志重放进度回调，参数为 (已处理事务数, 总事务数)
+    /// 挂载时的崩溃恢复会调用它，可用于显示启动进度；默认什么也不做
+    pub fn set_replay_progress(&mut self, progress: impl FnMut(u32, u32) + Send + 'static) {
+        self.replay_progress = Box::new(progress);
+    }
+
     ///外部重放journal日志入口 注意性能影响
-    pub fn journal_replay(&mut self) {
+    /// 返回重放的事务数；重放直接写底层设备，结束后丢弃块缓冲避免读到旧内容
+    pub fn journal_replay(&mut self) -> u32 {
         if self.journal_use {
+            let _ = self.inner.flush();
             let dev = &mut self.inner.dev;
             let jbd_sys = &mut self
                 .systeam
                 .as_mut()
                 .expect("jbd2dev are not initial,please initial the jbd2dev first!");
-            jbd_sys.replay(&mut *dev);
+            let applied = jbd_sys.replay_with_progress(&mut *dev, &mut *self.replay_progress);
+            // 重放后日志已清空，可以安全切换到带校验和的格式
+            if self.journal_checksum {
+                jbd_sys.enable_csum_v3();
+            }
+            let _ = self.inner.invalidate();
+            applied
         } else {
             warn!("Jouranl function not turn ,please turn on this function and retry!");
+            0
         }
     }
 
+    /// 绕过日志和块缓冲直接访问底层块设备
+    /// 调用前先把块缓冲落盘，结束后丢弃缓冲，避免读到旧内容
+    pub fn with_raw_device<R>(
+        &mut self,
+        f: impl FnOnce(&mut B) -> BlockDevResult<R>,
+    ) -> BlockDevResult<R> {
+        self.inner.flush()?;
+        let result = f(&mut self.inner.dev);
+        self.inner.invalidate()?;
+        result
+    }
+
     /// 运行时打开/关闭日志功能（例如 mkfs 阶段强制关闭，真正挂载再打开）
     pub fn set_journal_use(&mut self, use_journal: bool) {
         self.journal_use = use_journal;
@@ -158,16 +465,22 @@ impl<B: BlockDevice> Jbd2Dev<B> {
     pub fn set_journal_superblock(
         &mut self,
         super_block: JournalSuperBllockS,
-        jouranl_start_block: u32,
+        jouranl_start_block: u64,
     ) {
-        let system = JBD2DEVSYSTEM {
+        let mut system = JBD2DEVSYSTEM {
             start_block: jouranl_start_block,
             max_len: super_block.s_maxlen,
             head: 0,
             sequence: super_block.s_sequence,
             jbd2_super_block: super_block,
             commit_queue: Vec::new(),
+            revoke_table: BTreeMap::new(),
+            logged: BTreeSet::new(),
+            checkpoint_list: BTreeMap::new(),
         };
+        if self.journal_checksum && super_block.s_start == 0 {
+            system.enable_csum_v3();
+        }
         self.systeam = Some(system);
     }
 
@@ -183,14 +496,87 @@ impl<B: BlockDevice> Jbd2Dev<B> {
         }
     }
 
-    pub fn write_block(&mut self, block_id: u32, is_metadata: bool) -> BlockDevResult<()> {
+    /// 块被释放时撤销它在日志中的旧副本，避免崩溃恢复时旧元数据覆盖复用后的新内容
+    /// 日志未开启或尚未初始化时什么也不做
+    pub fn revoke_block(&mut self, block_id: u64) {
+        // 超出 32 位的块从不进日志，无需撤销
+        if !self.journal_use || journal_addressable(block_id, 1).is_err() {
+            return;
+        }
+        if let Some(systeam) = self.systeam.as_mut() {
+            systeam.revoke(block_id as u32);
+        }
+    }
+
+    /// 底层设备的能力报告
+    pub fn capabilities(&self) -> DevCaps {
+        self.inner.dev.capabilities()
+    }
+
+    /// 对底层设备下发 discard，count 为 0 时直接返回 Ok
+    pub fn discard(&mut self, block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        self.with_raw_device(|dev| dev.discard(block_id, count))
+    }
+
+    /// 日志空间占用，日志未开启或尚未初始化时全为 0
+    pub fn journal_usage(&self) -> JournalUsage {
+        match self.systeam.as_ref() {
+            Some(systeam) if self.journal_use => systeam.usage(),
+            _ => JournalUsage::default(),
+        }
+    }
+
+    /// 把已提交但尚未写回主盘的元数据写回，并推进日志尾释放空间
+    /// 日志未开启或尚未初始化时什么也不做
+    pub fn checkpoint(&mut self) -> BlockDevResult<()> {
+        if !self.journal_use {
+            return Ok(());
+        }
+        let Some(systeam) = self.systeam.as_mut() else {
+            return Ok(());
+        };
+        // 先落盘块缓冲里的写，checkpoint 直接写底层设备，结束后丢弃缓冲
+        self.inner.flush()?;
+        systeam.checkpoint(&mut self.inner.dev)?;
+        self.inner.invalidate()
+    }
+
+    /// 已进入事务缓存但尚未提交的元数据块数
+    pub fn pending_journal_blocks(&self) -> usize {
+        self.systeam.as_ref().map_or(0, |s| s.commit_queue.len())
+    }
+
+    /// 立即提交已缓存的事务，日志未开启或尚未初始化时什么也不做
+    /// 供需要原子性的多块元数据操作在结束时划定事务边界
+    pub fn commit_journal(&mut self) -> BlockDevResult<()> {
+        if !self.journal_use {
+            return Ok(());
+        }
+        let Some(systeam) = self.systeam.as_mut() else {
+            return Ok(());
+        };
+        if systeam.commit_queue.is_empty() && systeam.revoke_table.is_empty() {
+            return Ok(());
+        }
+        systeam
+            .commit_transaction(&mut self.inner.dev)
+            .map_err(|_| BlockDevError::WriteError)?;
+        Ok(())
+    }
+
+    pub fn write_block(&mut self, block_id: u64, is_metadata: bool) -> BlockDevResult<()> {
         //error!("write block :{} ,use journal?:{} ismetadata:{}",block_id,self.journal_use,is_metadata);
 
-        // 1) 非元数据 或 未开启日志：直接写回到底层块设备
-        if !self.journal_use || !is_metadata {
+        // 1) 非元数据 或 未开启日志 或 直写模式：直接写回到底层块设备
+        if !self.journal_use || !is_metadata || self.direct_write {
             // BlockDev 内部的 buffer 已经被上层写好，直接把当前 buffer 写到 block_id
             return self.inner.write_block(block_id);//把缓存直接写入盘
         }
+        // 日志 tag 只记录 32 位主盘块号，超出的元数据块既不能进日志也不能绕过日志
+        journal_addressable(block_id, 1)?;
 
         // 2) 元数据且启用日志：走 JBD2 事务
         //    此时之前的普通数据块已经完成写入
@@ -198,7 +584,7 @@ impl<B: BlockDevice> Jbd2Dev<B> {
 
         let meta_vec = self.inner.buffer();
         let updates = Jbd2Update( //把缓存变成事务
-            block_id as u64,
+            block_id,
             meta_vec
                 .try_into()
                 .expect("Data can;t into [u8;BLOCK_SIZE] panic!,os should process"),
@@ -212,21 +598,20 @@ impl<B: BlockDevice> Jbd2Dev<B> {
             return self.inner.write_block(block_id);
         }
 
+        let threshold = self.effective_threshold();
         let systeam = self.systeam.as_mut().unwrap();
 
         // 使用原始底层块设备提交事务
         let raw_dev = self.inner.device_mut();
 
         //先写入缓存
-        if systeam.commit_queue.len() > JBD2_BUFFER_MAX {
-            //缓存已满 直接提交，然后再塞入缓存
-            let _ = systeam.commit_transaction(raw_dev);
-            //赛入缓存
-            systeam.commit_queue.push(updates);
+        systeam.queue_update(updates);
+        if systeam.commit_queue.len() >= threshold {
+            //缓存已满 直接提交
+            systeam
+                .commit_transaction(raw_dev)
+                .map_err(|_| BlockDevError::WriteError)?;
             trace!("[JBD2 BUFFER] BUFFER IS FULL ,FLUSHED!")
-        } else {
-            //赛入缓存
-            systeam.commit_queue.push(updates);
         }
 
         if self._mode == 0 {//ordered模式
@@ -238,7 +623,7 @@ impl<B: BlockDevice> Jbd2Dev<B> {
 
         Ok(())
     }
-    pub fn read_block(&mut self, block_id: u32) -> BlockDevResult<()> {
+    pub fn read_block(&mut self, block_id: u64) -> BlockDevResult<()> {
         self.inner.read_block(block_id)
     }
     pub fn buffer(&self) -> &[u8] {
@@ -247,23 +632,28 @@ impl<B: BlockDevice> Jbd2Dev<B> {
     pub fn buffer_mut(&mut self) -> &mut [u8] {
         self.inner.buffer_mut()
     }
-    pub fn read_blocks(&mut self, buf: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+    pub fn read_blocks(&mut self, buf: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
         self.inner.read_blocks(buf, block_id, count)
     }
+    /// count 为 0 时不进入事务也不访问设备
     pub fn write_blocks(
         &mut self,
         buf: &[u8],
-        block_id: u32,
+        block_id: u64,
         count: u32,
         is_metadata: bool,
     ) -> BlockDevResult<()> {
         //error!("write block :{} ,use journal?:{} ismetadata:{}",block_id,self.journal_use,is_metadata);
+        if count == 0 {
+            return Ok(());
+        }
 
-        // 1) 非元数据 或 未开启日志：直接写回到底层块设备
-        if !self.journal_use || !is_metadata {
+        // 1) 非元数据 或 未开启日志 或 直写模式：直接写回到底层块设备
+        if !self.journal_use || !is_metadata || self.direct_write {
             // BlockDev 内部的 buffer 已经被上层写好，直接把当前 buffer 写到 block_id
             return self.inner.write_blocks(buf, block_id, count);
         }
+        journal_addressable(block_id, count)?;
 
         // 2) 元数据且启用日志：走 JBD2 事务
         //    此时之前的普通数据块已经完成写入
@@ -279,6 +669,7 @@ impl<B: BlockDevice> Jbd2Dev<B> {
             return self.inner.write_blocks(buf, block_id, count);
         }
 
+        let threshold = self.effective_threshold();
         let systeam = self.systeam.as_mut().unwrap();
 
         // 使用原始底层块设备提交事务
@@ -290,19 +681,17 @@ impl<B: BlockDevice> Jbd2Dev<B> {
             let block_bytes: [u8; BLOCK_SIZE] = buf[off..off + (BLOCK_SIZE as usize)]
                 .try_into()
                 .expect("slice len must be BLOCK_SIZE");
-            let updates = Jbd2Update((block_id + i) as u64, block_bytes);
+            let updates = Jbd2Update(block_id + i as u64, block_bytes);
             
 
             //先写入缓存
-            if systeam.commit_queue.len() > JBD2_BUFFER_MAX {
-                //缓存已满 直接提交，然后再塞入缓存
-                let _ = systeam.commit_transaction(raw_dev);
-                //赛入缓存
-                systeam.commit_queue.push(updates);
+            systeam.queue_update(updates);
+            if systeam.commit_queue.len() >= threshold {
+                //缓存已满 直接提交
+                systeam
+                    .commit_transaction(raw_dev)
+                    .map_err(|_| BlockDevError::WriteError)?;
                 trace!("[JBD2 BUFFER] BUFFER IS FULL ,FLUSHED!")
-            } else {
-                //赛入缓存
-                systeam.commit_queue.push(updates);
             }
         }
 
@@ -367,7 +756,7 @@ impl<B: BlockDevice> BlockDev<B> {
     }
 
     /// 读取指定块到内部缓冲区
-    pub fn read_block(&mut self, block_id: u32) -> BlockDevResult<()> {
+    pub fn read_block(&mut self, block_id: u64) -> BlockDevResult<()> {
         // 检查是否需要刷新脏数据
         if self.is_dirty && self.cached_block != Some(block_id) {
             self.flush()?;
@@ -379,7 +768,7 @@ impl<B: BlockDevice> BlockDev<B> {
         }
 
         // 读取块
-        self.dev.read(self.buffer.as_mut_slice(), block_id, 1)?;
+        self.dev.read64(self.buffer.as_mut_slice(), block_id, 1)?;
         self.cached_block = Some(block_id);
         self.is_dirty = false;
 
@@ -388,20 +777,23 @@ impl<B: BlockDevice> BlockDev<B> {
 
     /// 写入内部缓冲区到指定块
     ///
-    pub fn write_block(&mut self, block_id: u32) -> BlockDevResult<()> {
+    pub fn write_block(&mut self, block_id: u64) -> BlockDevResult<()> {
         if self.dev.is_readonly() {
             return Err(BlockDevError::ReadOnly);
         }
 
-        self.dev.write(self.buffer.as_slice(), block_id, 1)?;
+        self.dev.write64(self.buffer.as_slice(), block_id, 1)?;
         self.cached_block = Some(block_id);
         self.is_dirty = false;
 
         Ok(())
     }
 
-    /// 直接读取多个块
-    pub fn read_blocks(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+    /// 直接读取多个块，count 为 0 时不访问设备
+    pub fn read_blocks(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
         let block_size = self.dev.block_size() as usize;
         let required_size = block_size * count as usize;
 
@@ -412,11 +804,14 @@ impl<B: BlockDevice> BlockDev<B> {
             });
         }
 
-        self.dev.read(buffer, block_id, count)
+        self.dev.read64(buffer, block_id, count)
     }
 
-    /// 直接写入多个块
-    pub fn write_blocks(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+    /// 直接写入多个块，count 为 0 时不访问设备（只读设备上也返回 Ok）
+    pub fn write_blocks(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
         if self.dev.is_readonly() {
             return Err(BlockDevError::ReadOnly);
         }
@@ -431,7 +826,7 @@ impl<B: BlockDevice> BlockDev<B> {
             });
         }
 
-        self.dev.write(buffer, block_id, count)
+        self.dev.write64(buffer, block_id, count)
     }
 
     /// 获取缓冲区引用
@@ -445,6 +840,13 @@ impl<B: BlockDevice> BlockDev<B> {
         self.buffer.as_mut_slice()
     }
 
+    /// 刷新脏缓冲区并丢弃缓存的块，底层设备被绕过缓冲写入后调用
+    pub fn invalidate(&mut self) -> BlockDevResult<()> {
+        self.flush()?;
+        self.cached_block = None;
+        Ok(())
+    }
+
     /// 刷新脏缓冲区到磁盘
     pub fn flush(&mut self) -> BlockDevResult<()> {
         if self.is_dirty
@@ -465,13 +867,13 @@ impl<B: BlockDevice> BlockDev<B> {
     }
 
     /// 检查块号是否有效
-    pub fn _is_valid_block(&self, block_id: u32) -> bool {
-        (block_id as u64) < self.total_blocks()
+    pub fn _is_valid_block(&self, block_id: u64) -> bool {
+        block_id < self.total_blocks()
     }
 
     /// 验证块范围
-    pub fn _validate_block_range(&self, block_id: u32, count: u32) -> BlockDevResult<()> {
-        let end_block = block_id as u64 + count as u64;
+    pub fn _validate_block_range(&self, block_id: u64, count: u32) -> BlockDevResult<()> {
+        let end_block = block_id + count as u64;
         if end_block > self.total_blocks() {
             return Err(BlockDevError::BlockOutOfRange {
                 block_id,
@@ -491,3 +893,463 @@ impl<B: BlockDevice> BlockDev<B> {
         &mut self.dev
     }
 }
+
+/// 运行中事务的句柄，drop 时自动提交
+/// 需要拿到提交错误时调用 commit
+pub struct Jbd2Handle<'a, B: BlockDevice> {
+    dev: &'a mut Jbd2Dev<B>,
+    finished: bool,
+}
+
+impl<B: BlockDevice> Jbd2Handle<'_, B> {
+    /// 立即结束事务并返回提交结果
+    pub fn commit(mut self) -> BlockDevResult<()> {
+        self.finished = true;
+        self.dev.commit_transaction()
+    }
+}
+
+impl<B: BlockDevice> Deref for Jbd2Handle<'_, B> {
+    type Target = Jbd2Dev<B>;
+
+    fn deref(&self) -> &Jbd2Dev<B> {
+        self.dev
+    }
+}
+
+impl<B: BlockDevice> DerefMut for Jbd2Handle<'_, B> {
+    fn deref_mut(&mut self) -> &mut Jbd2Dev<B> {
+        self.dev
+    }
+}
+
+impl<B: BlockDevice> Drop for Jbd2Handle<'_, B> {
+    fn drop(&mut self) {
+        if !self.finished
+            && let Err(e) = self.dev.commit_transaction()
+        {
+            error!("journal handle commit failed: {e:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::endian::DiskFormat;
+    use crate::ext4_backend::test_support::*;
+
+    #[test]
+    fn commit_threshold_forces_commit_after_n_metadata_blocks() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(2048), true, false);
+        dev.set_journal_superblock(JournalSuperBllockS::default(), 1024);
+        dev.set_commit_threshold(3);
+        assert_eq!(dev.commit_threshold(), 3);
+
+        let seq0 = dev.systeam.as_ref().unwrap().sequence;
+        for blk in 10..12u32 {
+            dev.write_block(blk as u64, true).unwrap();
+        }
+        // 未达到阈值，不提交
+        let sys = dev.systeam.as_ref().unwrap();
+        assert_eq!(sys.sequence, seq0);
+        assert_eq!(sys.commit_queue.len(), 2);
+
+        // 第 3 个元数据块触发提交
+        dev.write_block(12, true).unwrap();
+        let sys = dev.systeam.as_ref().unwrap();
+        assert_eq!(sys.sequence, seq0 + 1);
+        assert!(sys.commit_queue.is_empty());
+
+        // 数据块不计入事务
+        dev.write_block(13, false).unwrap();
+        assert!(dev.systeam.as_ref().unwrap().commit_queue.is_empty());
+    }
+
+    #[test]
+    fn failed_threshold_commit_is_reported_by_the_write() {
+        // 日志区紧贴设备末尾，提交时描述块落在设备之外
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(1025), true, false);
+        dev.set_journal_superblock(JournalSuperBllockS::default(), 1024);
+        dev.set_commit_threshold(2);
+
+        dev.write_block(10, true).unwrap();
+        assert!(dev.write_block(11, true).is_err());
+        let buf = [0u8; BLOCK_SIZE * 2];
+        assert!(dev.write_blocks(&buf, 12, 2, true).is_err());
+    }
+
+    #[test]
+    fn commit_threshold_is_clamped() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(16), false, false);
+        assert_eq!(dev.commit_threshold(), JBD2_BUFFER_MAX);
+        dev.set_commit_threshold(0);
+        assert_eq!(dev.commit_threshold(), 1);
+        dev.set_commit_threshold(usize::MAX);
+        assert_eq!(dev.commit_threshold(), JBD2_DESCRIPTOR_TAG_MAX);
+    }
+
+    #[test]
+    fn direct_write_bypasses_journal_transaction() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(2048), true, false);
+        dev.set_journal_superblock(JournalSuperBllockS::default(), 1024);
+        let seq0 = dev.systeam.as_ref().unwrap().sequence;
+
+        dev.with_direct_write(|d| {
+            assert!(d.is_direct_write());
+            d.buffer_mut().fill(0xA5);
+            d.write_block(20, true)
+        })
+        .unwrap();
+        assert!(!dev.is_direct_write());
+
+        // 没有事务产生，数据已经在底层设备上
+        let sys = dev.systeam.as_ref().unwrap();
+        assert_eq!(sys.sequence, seq0);
+        assert!(sys.commit_queue.is_empty());
+        let mut raw = [0u8; BLOCK_SIZE];
+        dev.inner.dev.read(&mut raw, 20, 1).unwrap();
+        assert!(raw.iter().all(|&b| b == 0xA5));
+
+        // 退出直写模式后元数据重新进入日志
+        dev.write_block(21, true).unwrap();
+        assert_eq!(dev.systeam.as_ref().unwrap().commit_queue.len(), 1);
+    }
+
+    #[test]
+    fn replay_reports_monotonic_progress() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(2048), true, false);
+        dev.set_journal_superblock(JournalSuperBllockS::default(), 1024);
+        // 提交 3 个事务但不清理日志，再抹掉原位置的数据，模拟检查点前崩溃
+        for blk in 10..13u32 {
+            dev.buffer_mut().fill(blk as u8);
+            dev.write_block(blk as u64, true).unwrap();
+            dev.commit_journal().unwrap();
+        }
+        let mut inner = dev.inner.dev;
+        let mut raw = [0u8; BLOCK_SIZE];
+        for blk in 10..13u32 {
+            inner.write(&raw, blk, 1).unwrap();
+        }
+        inner.read(&mut raw, 1024, 1).unwrap();
+        let jsb = JournalSuperBllockS::from_disk_bytes(&raw);
+
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, inner, true, false);
+        dev.set_journal_superblock(jsb, 1024);
+        let sink = seen.clone();
+        dev.set_replay_progress(move |done, total| sink.lock().unwrap().push((done, total)));
+        dev.journal_replay();
+
+        assert_eq!(*seen.lock().unwrap(), [(1, 3), (2, 3), (3, 3)]);
+        for blk in 10..13u32 {
+            dev.inner.dev.read(&mut raw, blk, 1).unwrap();
+            assert!(raw.iter().all(|&b| b == blk as u8));
+        }
+    }
+
+    #[test]
+    fn mount_recovers_journaled_metadata_after_crash() {
+        use crate::ext4_backend::ext4::*;
+        use crate::ext4_backend::file::*;
+        use crate::ext4_backend::jbd2::jbd2::jbd2_recover;
+        use crate::ext4_backend::loopfile::*;
+        use alloc::collections::BTreeSet;
+
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+        dev.set_journal_use(true);
+        // 第一次日志挂载创建 journal，再挂一次得到干净的日志
+        let fs = mount(&mut dev).unwrap();
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let mut before = dev.inner.dev.clone();
+
+        mkfile(&mut dev, &mut fs, "/survivor", Some(b"still here"), None).unwrap();
+        fs.bitmap_cache.flush_all(&mut dev).unwrap();
+        fs.inodetable_cahce.flush_all(&mut dev).unwrap();
+        fs.datablock_cache.flush_all(&mut dev).unwrap();
+        dev.commit_journal().unwrap();
+
+        // 崩溃镜像：已进入日志的元数据块在原位置回退到操作前的内容
+        let mut j_inode = fs.get_inode_by_num(&mut dev, 8).unwrap();
+        let journal_blocks: BTreeSet<u64> = resolve_inode_block_allextend(&mut fs, &mut dev, 8, &mut j_inode)
+            .unwrap()
+            .into_values()
+            .collect();
+        let mut after = dev.inner.dev.clone();
+        let mut raw = [0u8; BLOCK_SIZE];
+        let mut journaled: BTreeSet<[u8; BLOCK_SIZE]> = BTreeSet::new();
+        for &blk in &journal_blocks {
+            after.read(&mut raw, blk as u32, 1).unwrap();
+            journaled.insert(raw);
+        }
+        let mut crashed = after.clone();
+        let mut old = [0u8; BLOCK_SIZE];
+        let mut rolled_back = 0;
+        for blk in 0..after.total_blocks as u32 {
+            if journal_blocks.contains(&(blk as u64)) {
+                continue;
+            }
+            after.read(&mut raw, blk, 1).unwrap();
+            before.read(&mut old, blk, 1).unwrap();
+            if raw != old && journaled.contains(&raw) {
+                crashed.write(&old, blk, 1).unwrap();
+                rolled_back += 1;
+            }
+        }
+        assert!(rolled_back > 0);
+        drop(fs);
+
+        // 不回放日志时新文件的 inode 还没落到原位置
+        let mut ro_dev = Jbd2Dev::initial_jbd2dev(0, crashed.clone(), true, false);
+        let mut ro_fs = mount_ro(&mut ro_dev).unwrap();
+        let lost = get_file_inode(&mut ro_fs, &mut ro_dev, "/survivor").unwrap();
+        assert!(lost.is_none_or(|(_, inode)| inode.i_mode == 0));
+
+        // remount_rw 回放后按回放结果重新加载，不能用只读挂载时的旧超级块覆盖
+        remount_rw(&mut ro_dev, &mut ro_fs).unwrap();
+        assert_eq!(read_file(&mut ro_dev, &mut ro_fs, "/survivor").unwrap(), b"still here");
+        umount(ro_fs, &mut ro_dev).unwrap();
+        let mut re_fs = mount(&mut ro_dev).unwrap();
+        assert_eq!(read_file(&mut ro_dev, &mut re_fs, "/survivor").unwrap(), b"still here");
+
+        // 单独恢复：重放后日志是干净的
+        let mut rec_dev = Jbd2Dev::initial_jbd2dev(0, crashed.clone(), true, false);
+        assert!(jbd2_recover(&mut rec_dev).unwrap() >= 1);
+        assert_eq!(jbd2_recover(&mut rec_dev).unwrap(), 0);
+
+        // 挂载时自动恢复
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, crashed, true, false);
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(
+            read_file(&mut dev, &mut fs, "/survivor").unwrap(),
+            b"still here"
+        );
+    }
+
+    #[test]
+    fn revoked_block_is_not_replayed_over_reused_data() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(2048), true, false);
+        dev.set_journal_superblock(JournalSuperBllockS::default(), 1024);
+
+        // 事务 1：块 10 作为元数据进日志
+        dev.buffer_mut().fill(0xAA);
+        dev.write_block(10, true).unwrap();
+        dev.commit_journal().unwrap();
+
+        // 事务 2：块 10 被释放后当作数据块复用，同时有别的元数据更新
+        dev.revoke_block(10);
+        dev.buffer_mut().fill(0x55);
+        dev.write_block(10, false).unwrap();
+        dev.buffer_mut().fill(0x11);
+        dev.write_block(11, true).unwrap();
+        dev.commit_journal().unwrap();
+        assert!(dev.systeam.as_ref().unwrap().revoke_table.is_empty());
+
+        // 只撤销不写元数据也能单独成事务
+        dev.revoke_block(11);
+        dev.commit_journal().unwrap();
+
+        // 模拟未做检查点就崩溃：两个事务都留在日志里，重新挂载时重放
+        let mut inner = dev.inner.dev;
+        let mut raw = [0u8; BLOCK_SIZE];
+        inner.read(&mut raw, 1024, 1).unwrap();
+        let jsb = JournalSuperBllockS::from_disk_bytes(&raw);
+        inner.write(&[0u8; BLOCK_SIZE], 11, 1).unwrap();
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, inner, true, false);
+        dev.set_journal_superblock(jsb, 1024);
+        assert_eq!(dev.journal_replay(), 3);
+
+        dev.inner.dev.read(&mut raw, 10, 1).unwrap();
+        assert!(raw.iter().all(|&b| b == 0x55));
+        // 块 11 在事务 3 中被撤销，事务 2 的副本也不再写回
+        dev.inner.dev.read(&mut raw, 11, 1).unwrap();
+        assert!(raw.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn running_transaction_batches_until_commit() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(2048), true, false);
+        dev.set_journal_superblock(JournalSuperBllockS::default(), 1024);
+        dev.set_commit_threshold(1);
+        let seq0 = dev.systeam.as_ref().unwrap().sequence;
+
+        // 阈值为 1，但运行中事务内不自动提交；重复写同一块只占一个槽位
+        dev.begin_transaction();
+        for blk in [20u32, 21, 22, 20] {
+            dev.write_block(blk as u64, true).unwrap();
+        }
+        dev.begin_transaction();
+        dev.write_block(23, true).unwrap();
+        dev.commit_transaction().unwrap();
+        assert_eq!(dev.pending_journal_blocks(), 4);
+        assert_eq!(dev.systeam.as_ref().unwrap().sequence, seq0);
+        dev.commit_transaction().unwrap();
+        assert_eq!(dev.pending_journal_blocks(), 0);
+        assert_eq!(dev.systeam.as_ref().unwrap().sequence, seq0 + 1);
+
+        // 句柄 drop 时自动提交
+        {
+            let mut handle = dev.transaction();
+            handle.write_block(24, true).unwrap();
+            handle.write_block(25, true).unwrap();
+            assert_eq!(handle.pending_journal_blocks(), 2);
+        }
+        assert_eq!(dev.pending_journal_blocks(), 0);
+        assert_eq!(dev.systeam.as_ref().unwrap().sequence, seq0 + 2);
+
+        // 事务外恢复按阈值提交
+        dev.write_block(26, true).unwrap();
+        assert_eq!(dev.systeam.as_ref().unwrap().sequence, seq0 + 3);
+    }
+
+    #[test]
+    fn journal_checksum_discards_torn_transaction_and_rest() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(2048), true, true);
+        dev.set_journal_superblock(JournalSuperBllockS::default(), 1024);
+        for blk in 10..13u32 {
+            dev.buffer_mut().fill(blk as u8);
+            dev.write_block(blk as u64, true).unwrap();
+            dev.commit_journal().unwrap();
+        }
+        // 一个事务写满多个 descriptor
+        let per_desc = dev.systeam.as_ref().unwrap().tags_per_descriptor() as u32;
+        dev.begin_transaction();
+        for blk in 100..100 + per_desc + 5 {
+            dev.buffer_mut().fill(blk as u8);
+            dev.write_block(blk as u64, true).unwrap();
+        }
+        dev.commit_transaction().unwrap();
+        let mut inner = dev.inner.dev;
+        let zero = [0u8; BLOCK_SIZE];
+        for blk in (10..13u32).chain(100..100 + per_desc + 5) {
+            inner.write(&zero, blk, 1).unwrap();
+        }
+        let mut raw = [0u8; BLOCK_SIZE];
+        inner.read(&mut raw, 1024, 1).unwrap();
+        let jsb = JournalSuperBllockS::from_disk_bytes(&raw);
+        assert_ne!(jsb.s_feature_incompat & JBD2_FEATURE_INCOMPAT_CSUM_V3, 0);
+
+        let mut intact = Jbd2Dev::initial_jbd2dev(0, inner.clone(), true, true);
+        intact.set_journal_superblock(jsb, 1024);
+        assert_eq!(intact.journal_replay(), 4);
+        intact.inner.dev.read(&mut raw, 100 + per_desc + 4, 1).unwrap();
+        assert!(raw.iter().all(|&b| b == (100 + per_desc + 4) as u8));
+
+        // 翻转事务 2 日志块中的一个字节：事务 2 及之后的事务都被丢弃
+        let log_blk = (1025..1040u32)
+            .find(|&b| {
+                inner.read(&mut raw, b, 1).unwrap();
+                raw.iter().all(|&x| x == 11)
+            })
+            .unwrap();
+        raw[7] ^= 0xFF;
+        inner.write(&raw, log_blk, 1).unwrap();
+        let mut torn = Jbd2Dev::initial_jbd2dev(0, inner, true, true);
+        torn.set_journal_superblock(jsb, 1024);
+        assert_eq!(torn.journal_replay(), 1);
+        for (blk, want) in [(10u32, 10u8), (11, 0), (12, 0), (100, 0)] {
+            torn.inner.dev.read(&mut raw, blk, 1).unwrap();
+            assert!(raw.iter().all(|&b| b == want), "block {blk}");
+        }
+    }
+
+    #[test]
+    fn checkpoint_keeps_small_journal_from_wrapping() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(2048), true, false);
+        let jsb = JournalSuperBllockS {
+            s_maxlen: 40,
+            ..JournalSuperBllockS::default()
+        };
+        dev.set_journal_superblock(jsb, 1024);
+        let mut latest = [0u8; 7];
+        let mut checkpoints = 0;
+        let mut last_used = 0;
+        for i in 1..=300u32 {
+            let mut handle = dev.transaction();
+            for blk in [10 + i % 7, 10 + (i + 3) % 7] {
+                handle.buffer_mut().fill(i as u8);
+                handle.write_block(blk as u64, true).unwrap();
+                latest[(blk - 10) as usize] = i as u8;
+            }
+            handle.commit().unwrap();
+            let usage = dev.journal_usage();
+            assert_eq!(usage.total_blocks, 40);
+            assert!(usage.used_blocks <= usage.total_blocks);
+            if usage.used_blocks < last_used {
+                checkpoints += 1;
+            }
+            last_used = usage.used_blocks;
+        }
+        assert!(checkpoints > 10);
+
+        // 模拟崩溃：只靠盘上内容重放，日志里只剩最近的事务，不会用回绕前的旧副本覆盖
+        let crashed = dev.inner.dev.clone();
+        let mut raw = [0u8; BLOCK_SIZE];
+        let mut inner = crashed;
+        inner.read(&mut raw, 1024, 1).unwrap();
+        let jsb = JournalSuperBllockS::from_disk_bytes(&raw);
+        let mut rec = Jbd2Dev::initial_jbd2dev(0, inner, true, false);
+        rec.set_journal_superblock(jsb, 1024);
+        let applied = rec.journal_replay();
+        assert!(applied > 0 && applied < 10, "applied {applied}");
+        for (idx, &want) in latest.iter().enumerate() {
+            rec.inner.dev.read(&mut raw, 10 + idx as u32, 1).unwrap();
+            assert!(raw.iter().all(|&b| b == want), "block {}", 10 + idx);
+        }
+    }
+
+    #[test]
+    fn zero_count_read_write_is_noop() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(64), true, false);
+        dev.set_journal_superblock(JournalSuperBllockS::default(), 32);
+        let before = (dev.inner.dev.reads, dev.inner.dev.writes);
+
+        // 越界块号和空缓冲区在 count 为 0 时都不报错
+        dev.read_blocks(&mut [], u32::MAX as u64, 0).unwrap();
+        dev.write_blocks(&[], u32::MAX as u64, 0, false).unwrap();
+        dev.write_blocks(&[], u32::MAX as u64, 0, true).unwrap();
+        assert_eq!((dev.inner.dev.reads, dev.inner.dev.writes), before);
+        assert!(dev.systeam.as_ref().unwrap().commit_queue.is_empty());
+
+        let mut mem = MemBlockDev::new(4);
+        mem.read(&mut [], 100, 0).unwrap();
+        mem.write(&[], 100, 0).unwrap();
+    }
+
+    #[test]
+    fn block_numbers_beyond_u32_round_trip() {
+        let high = (1u64 << 32) + 3;
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(high + 8), true, false);
+        dev.set_journal_superblock(JournalSuperBllockS::default(), 1024);
+
+        // 元数据块超出日志 tag 的 32 位范围时拒绝写入，既不截断到低 32 位也不绕过日志
+        dev.read_block(high).unwrap();
+        dev.buffer_mut()[..4].copy_from_slice(b"high");
+        assert_eq!(dev.write_block(high, true), Err(BlockDevError::Unsupported));
+        assert_eq!(
+            dev.write_blocks(&[0x5Au8; BLOCK_SIZE * 2], u32::MAX as u64, 2, true),
+            Err(BlockDevError::Unsupported)
+        );
+        assert!(dev.systeam.as_ref().unwrap().commit_queue.is_empty());
+
+        // 数据块不进日志，照常直接落盘
+        dev.write_block(high, false).unwrap();
+        dev.write_blocks(&[0x5Au8; BLOCK_SIZE * 2], high + 1, 2, false).unwrap();
+        dev.read_block(3).unwrap();
+        assert!(dev.buffer().iter().all(|&b| b == 0));
+
+        let mut buf = [0u8; BLOCK_SIZE * 3];
+        dev.read_blocks(&mut buf, high, 3).unwrap();
+        assert_eq!(&buf[..4], b"high");
+        assert!(buf[BLOCK_SIZE..].iter().all(|&b| b == 0x5A));
+
+        // 只实现 u32 接口的设备经默认 read64 访问高块号时报越界
+        let mut small = CountingDev::new(16);
+        assert!(matches!(
+            small.read64(&mut buf[..BLOCK_SIZE], high, 1),
+            Err(BlockDevError::BlockOutOfRange { block_id, .. }) if block_id == high
+        ));
+    }
+}
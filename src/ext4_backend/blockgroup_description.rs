@@ -1,3 +1,4 @@
+use crate::ext4_backend::crc32c::crc32c;
 use crate::ext4_backend::endian::*;
 
 /// Ext4 块组描述符结构
@@ -70,6 +71,18 @@ impl Ext4GroupDesc {
         (self.bg_free_inodes_count_hi as u32) << 16 | self.bg_free_inodes_count_lo as u32
     }
 
+    /// 设置空闲块数（32位）
+    pub fn set_free_blocks_count(&mut self, count: u32) {
+        self.bg_free_blocks_count_lo = (count & 0xFFFF) as u16;
+        self.bg_free_blocks_count_hi = (count >> 16) as u16;
+    }
+
+    /// 设置空闲inode数（32位）
+    pub fn set_free_inodes_count(&mut self, count: u32) {
+        self.bg_free_inodes_count_lo = (count & 0xFFFF) as u16;
+        self.bg_free_inodes_count_hi = (count >> 16) as u16;
+    }
+
     /// 获取目录数（32位）
     pub fn used_dirs_count(&self) -> u32 {
         (self.bg_used_dirs_count_hi as u32) << 16 | self.bg_used_dirs_count_lo as u32
@@ -95,6 +108,27 @@ impl Ext4GroupDesc {
         (self.bg_inode_bitmap_csum_hi as u32) << 16 | self.bg_inode_bitmap_csum_lo as u32
     }
 
+    /// 设置块位图校验和（32位）
+    pub fn set_block_bitmap_csum(&mut self, csum: u32) {
+        self.bg_block_bitmap_csum_lo = (csum & 0xFFFF) as u16;
+        self.bg_block_bitmap_csum_hi = (csum >> 16) as u16;
+    }
+
+    /// 设置inode位图校验和（32位）
+    pub fn set_inode_bitmap_csum(&mut self, csum: u32) {
+        self.bg_inode_bitmap_csum_lo = (csum & 0xFFFF) as u16;
+        self.bg_inode_bitmap_csum_hi = (csum >> 16) as u16;
+    }
+
+    /// metadata_csum 下的描述符校验和：依次混入组号和 bg_checksum 置零后的前 desc_size 字节，取低 16 位
+    pub fn metadata_csum(&self, seed: u32, group: u32, desc_size: usize) -> u16 {
+        let mut raw = [0u8; 64];
+        self.to_disk_bytes(&mut raw);
+        raw[30..32].fill(0);
+        let sum = crc32c(seed, &group.to_le_bytes());
+        (crc32c(sum, &raw[..desc_size.min(raw.len())]) & 0xFFFF) as u16
+    }
+
     /// 检查块组是否未初始化（inode表和位图未初始化）
     pub fn is_uninit_bg(&self) -> bool {
         self.bg_flags & Self::EXT4_BG_INODE_UNINIT != 0
@@ -148,19 +182,15 @@ impl<'a> BlockGroupDescTable<'a> {
     }
 
     /// 获取指定块组的描述符
-    pub fn get_desc(&self, group_idx: u32) -> Option<&Ext4GroupDesc> {
+    /// 按小端序逐字段解析，不直接把字节重解释为结构体（与主机字节序、对齐无关）
+    pub fn get_desc(&self, group_idx: u32) -> Option<Ext4GroupDesc> {
         if group_idx >= self.group_count {
             return None;
         }
 
         let offset = (group_idx as usize) * self.desc_size;
-        if offset + core::mem::size_of::<Ext4GroupDesc>() > self.data.len() {
-            return None;
-        }
-
-        // 安全地将字节切片转换为结构体引用
-        let desc_ptr = self.data[offset..].as_ptr() as *const Ext4GroupDesc;
-        unsafe { Some(&*desc_ptr) }
+        let bytes = self.data.get(offset..offset + self.desc_size)?;
+        Some(Ext4GroupDesc::from_disk_bytes(bytes))
     }
 
     /// 获取块组数量
@@ -236,7 +266,7 @@ pub struct BlockGroupDescIter<'a> {
 }
 
 impl<'a> Iterator for BlockGroupDescIter<'a> {
-    type Item = &'a Ext4GroupDesc;
+    type Item = Ext4GroupDesc;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.current >= self.table.group_count {
@@ -267,97 +297,70 @@ impl<'a> BlockGroupDescTableMut<'a> {
         }
     }
 
-    /// 获取指定块组的可变描述符引用
-    pub fn get_desc_mut(&mut self, group_idx: u32) -> Option<&mut Ext4GroupDesc> {
+    /// 解析指定块组的描述符，交给 f 修改后按小端序写回原位置
+    /// 32 字节描述符只写回前 32 字节
+    pub fn modify_desc(&mut self, group_idx: u32, f: impl FnOnce(&mut Ext4GroupDesc)) -> bool {
         if group_idx >= self.group_count {
-            return None;
+            return false;
         }
 
         let offset = (group_idx as usize) * self.desc_size;
-        if offset + core::mem::size_of::<Ext4GroupDesc>() > self.data.len() {
-            return None;
-        }
-
-        // 安全地将字节切片转换为可变结构体引用
-        let desc_ptr = self.data[offset..].as_mut_ptr() as *mut Ext4GroupDesc;
-        unsafe { Some(&mut *desc_ptr) }
+        let Some(bytes) = self.data.get_mut(offset..offset + self.desc_size) else {
+            return false;
+        };
+        let mut desc = Ext4GroupDesc::from_disk_bytes(bytes);
+        f(&mut desc);
+        let mut raw = [0u8; Ext4GroupDesc::EXT4_DESC_SIZE_64BIT];
+        desc.to_disk_bytes(&mut raw);
+        let len = bytes.len().min(raw.len());
+        bytes[..len].copy_from_slice(&raw[..len]);
+        true
     }
 
     /// 更新块组的空闲块数
     pub fn update_free_blocks(&mut self, group_idx: u32, count: u32) -> bool {
-        if let Some(desc) = self.get_desc_mut(group_idx) {
-            desc.bg_free_blocks_count_lo = (count & 0xFFFF) as u16;
-            desc.bg_free_blocks_count_hi = ((count >> 16) & 0xFFFF) as u16;
-            true
-        } else {
-            false
-        }
+        self.modify_desc(group_idx, |desc| desc.set_free_blocks_count(count))
     }
 
     /// 更新块组的空闲inode数
     pub fn update_free_inodes(&mut self, group_idx: u32, count: u32) -> bool {
-        if let Some(desc) = self.get_desc_mut(group_idx) {
-            desc.bg_free_inodes_count_lo = (count & 0xFFFF) as u16;
-            desc.bg_free_inodes_count_hi = ((count >> 16) & 0xFFFF) as u16;
-            true
-        } else {
-            false
-        }
+        self.modify_desc(group_idx, |desc| desc.set_free_inodes_count(count))
     }
 
     /// 更新块组的目录数
     pub fn update_used_dirs(&mut self, group_idx: u32, count: u32) -> bool {
-        if let Some(desc) = self.get_desc_mut(group_idx) {
+        self.modify_desc(group_idx, |desc| {
             desc.bg_used_dirs_count_lo = (count & 0xFFFF) as u16;
             desc.bg_used_dirs_count_hi = ((count >> 16) & 0xFFFF) as u16;
-            true
-        } else {
-            false
-        }
+        })
     }
 
     /// 递增块组的目录数
     pub fn increment_used_dirs(&mut self, group_idx: u32) -> bool {
-        if let Some(desc) = self.get_desc_mut(group_idx) {
+        self.modify_desc(group_idx, |desc| {
             let count = desc.used_dirs_count() + 1;
             desc.bg_used_dirs_count_lo = (count & 0xFFFF) as u16;
             desc.bg_used_dirs_count_hi = ((count >> 16) & 0xFFFF) as u16;
-            true
-        } else {
-            false
-        }
+        })
     }
 
     /// 递减块组的目录数
     pub fn decrement_used_dirs(&mut self, group_idx: u32) -> bool {
-        if let Some(desc) = self.get_desc_mut(group_idx) {
+        self.modify_desc(group_idx, |desc| {
             let count = desc.used_dirs_count().saturating_sub(1);
             desc.bg_used_dirs_count_lo = (count & 0xFFFF) as u16;
             desc.bg_used_dirs_count_hi = ((count >> 16) & 0xFFFF) as u16;
-            true
-        } else {
-            false
-        }
+        })
     }
 
     /// 设置块组标志
     pub fn set_flags(&mut self, group_idx: u32, flags: u16) -> bool {
-        if let Some(desc) = self.get_desc_mut(group_idx) {
-            desc.bg_flags |= flags;
-            true
-        } else {
-            false
-        }
+        self.modify_desc(group_idx, |desc| desc.bg_flags |= flags)
     }
 
     /// 清除块组标志
     pub fn clear_flags(&mut self, group_idx: u32, flags: u16) -> bool {
-        if let Some(desc) = self.get_desc_mut(group_idx) {
-            desc.bg_flags &= !flags;
-            true
-        } else {
-            false
-        }
+        self.modify_desc(group_idx, |desc| desc.bg_flags &= !flags)
     }
 }
 
@@ -462,6 +465,37 @@ mod tests {
         assert!(desc.is_inode_bitmap_uninit());
         assert!(!desc.is_block_bitmap_uninit());
     }
+
+    #[test]
+    fn desc_table_accesses_fields_in_little_endian() {
+        for desc_size in [Ext4GroupDesc::GOOD_OLD_DESC_SIZE, Ext4GroupDesc::EXT4_DESC_SIZE_64BIT] {
+            // 奇数偏移起始，确认不依赖结构体对齐
+            let mut raw = [0u8; 1 + 2 * 64];
+            let data = &mut raw[1..1 + 2 * desc_size];
+            let mut table = BlockGroupDescTableMut::new(data, desc_size, 2);
+            assert!(table.update_free_blocks(1, 0x0001_2345));
+            assert!(table.set_flags(1, Ext4GroupDesc::EXT4_BG_INODE_ZEROED));
+            assert!(table.increment_used_dirs(1));
+            assert!(!table.update_free_inodes(2, 1));
+
+            // 磁盘字节为小端序：与模拟大端主机上 to_le 之后的内存字节一致
+            let off = desc_size;
+            assert_eq!(&data[off + 12..off + 14], &le16_on(true, 0x2345).to_be_bytes());
+            assert_eq!(&data[off + 18..off + 20], &[Ext4GroupDesc::EXT4_BG_INODE_ZEROED as u8, 0]);
+            assert_eq!(&data[off + 16..off + 18], &[1, 0]);
+            // 32 字节描述符没有高位字段，写回不越界到下一个描述符
+            assert!(data[..off].iter().all(|&b| b == 0));
+
+            let table = BlockGroupDescTable::new(data, desc_size, 2);
+            let desc = table.get_desc(1).unwrap();
+            let expect_free = if desc_size == 64 { 0x0001_2345 } else { 0x2345 };
+            assert_eq!(desc.free_blocks_count(), expect_free);
+            assert_eq!(desc.used_dirs_count(), 1);
+            assert!(desc.is_inode_table_zeroed());
+            assert_eq!(table.iter().count(), 2);
+            assert!(table.get_desc(2).is_none());
+        }
+    }
 }
 
 /// 实现 DiskFormat trait 用于字节序转换
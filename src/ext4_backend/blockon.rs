@@ -0,0 +1,194 @@
+//! 把读写返回 future 的块设备接到同步文件系统上的阻塞桥接
+//!
+//! 文件系统是同步实现，不提供异步 API，mount、read_file、缓存写回等都不会让出。
+//! `AsyncBlockDevice` 描述 embassy 一类驱动提供的设备（读写返回 `core::future::Future`），
+//! `BlockOn` 把它包装成同步的 `BlockDevice`：每次 I/O 在调用处用 noop waker 忙等 poll 到完成
+//! （不依赖执行器和分配器），之后照常对 `Jbd2Dev<BlockOn<D>>` 调用 mount、read_file 等。
+//! 这是阻塞调用，等待期间当前任务一直占用 CPU；
+//! 只适合设备 future 靠轮询硬件自行完成、不依赖中断唤醒的场合。
+//! 仅在 `block-on` 特性（或测试）下编译
+
+use core::future::Future;
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::error::*;
+
+/// 外部需要实现的异步块设备 trait，语义与 `BlockDevice` 相同
+/// read/write 的 count 为 0 时约定为成功的空操作
+pub trait AsyncBlockDevice {
+    /// 写入 count 个块
+    fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> impl Future<Output = BlockDevResult<()>>;
+
+    /// 读取 count 个块
+    fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> impl Future<Output = BlockDevResult<()>>;
+
+    /// 刷新设备写缓存
+    fn flush(&mut self) -> impl Future<Output = BlockDevResult<()>> {
+        core::future::ready(Ok(()))
+    }
+
+    /// 获取块设备的总块数
+    fn total_blocks(&self) -> u64;
+
+    /// 获取块大小（字节）
+    fn block_size(&self) -> u32 {
+        BLOCK_SIZE as u32
+    }
+
+    /// 检查设备是否只读
+    fn is_readonly(&self) -> bool {
+        false
+    }
+}
+
+/// 在当前上下文中把 future 跑到完成：noop waker + 忙等轮询，返回前不会让出
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return out;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// 把 `AsyncBlockDevice` 桥接为同步的 `BlockDevice`，供 `Jbd2Dev` 使用
+/// 每次读写都经 block_on 阻塞到设备完成
+pub struct BlockOn<D: AsyncBlockDevice> {
+    dev: D,
+}
+
+impl<D: AsyncBlockDevice> BlockOn<D> {
+    pub fn new(dev: D) -> Self {
+        Self { dev }
+    }
+
+    /// 底层异步设备
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.dev
+    }
+
+    /// 取回底层异步设备
+    pub fn into_inner(self) -> D {
+        self.dev
+    }
+}
+
+impl<D: AsyncBlockDevice> BlockDevice for BlockOn<D> {
+    fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.write64(buffer, block_id as u64, count)
+    }
+
+    fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.read64(buffer, block_id as u64, count)
+    }
+
+    fn write64(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        block_on(self.dev.write(buffer, block_id, count))
+    }
+
+    fn read64(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        block_on(self.dev.read(buffer, block_id, count))
+    }
+
+    fn open(&mut self) -> BlockDevResult<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> BlockDevResult<()> {
+        Ok(())
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.dev.total_blocks()
+    }
+
+    fn block_size(&self) -> u32 {
+        self.dev.block_size()
+    }
+
+    fn flush(&mut self) -> BlockDevResult<()> {
+        block_on(self.dev.flush())
+    }
+
+    fn is_readonly(&self) -> bool {
+        self.dev.is_readonly()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::ext4::*;
+    use crate::ext4_backend::file::*;
+    use crate::ext4_backend::test_support::*;
+
+    /// 每次 I/O 先返回一次 Pending 的内存异步设备
+    struct YieldingDev {
+        mem: MemBlockDev,
+        polls: usize,
+    }
+
+    struct YieldOnce<'a> {
+        yielded: bool,
+        polls: &'a mut usize,
+    }
+
+    impl Future for YieldOnce<'_> {
+        type Output = ();
+        fn poll(mut self: core::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            *self.polls += 1;
+            if self.yielded {
+                return Poll::Ready(());
+            }
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    impl AsyncBlockDevice for YieldingDev {
+        async fn write(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+            YieldOnce { yielded: false, polls: &mut self.polls }.await;
+            self.mem.write64(buffer, block_id, count)
+        }
+
+        async fn read(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+            YieldOnce { yielded: false, polls: &mut self.polls }.await;
+            self.mem.read64(buffer, block_id, count)
+        }
+
+        fn total_blocks(&self) -> u64 {
+            self.mem.total_blocks
+        }
+    }
+
+    #[test]
+    fn block_on_bridge_mounts_and_round_trips_a_file() {
+        let dev = YieldingDev {
+            mem: MemBlockDev::new(16 * 1024),
+            polls: 0,
+        };
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, BlockOn::new(dev), false, false);
+        mkfs(&mut dev).unwrap();
+
+        // 每次 I/O 的 future 先返回一次 Pending，由 BlockOn 在调用处轮询到完成
+        let mut fs = mount(&mut dev).unwrap();
+        mkfile(&mut dev, &mut fs, "/a", Some(b"async"), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/a").unwrap(), b"async");
+        assert!(dev.with_raw_device(|d| Ok(d.inner_mut().polls)).unwrap() > 0);
+    }
+}
@@ -33,6 +33,16 @@ impl core::fmt::Display for AllocError {
     }
 }
 
+/// inode 分配策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InodeAllocPolicy {
+    /// 普通文件优先放在父目录所在块组，子目录按 Orlov 策略选组（默认）
+    #[default]
+    Locality,
+    /// 总是从第一个有空闲 inode 的块组开始分配
+    FirstFit,
+}
+
 /// 块分配结果
 /// 包含分配的块号和所在的块组
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -45,6 +55,22 @@ pub struct BlockAlloc {
     pub global_block: u64,
 }
 
+/// 一次位图扫描得到的候选位置 (起始簇, 簇数)
+/// honored 不碰避开区间和预留窗口；fallback 忽略这些建议限制，
+/// 调用方只在所有块组都没有 honored 时才使用它
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunCandidates {
+    pub honored: Option<(u32, u32)>,
+    pub fallback: Option<(u32, u32)>,
+}
+
+impl RunCandidates {
+    /// honor_avoid 为 true 时取 honored，否则取 fallback
+    pub fn pick(self, honor_avoid: bool) -> Option<(u32, u32)> {
+        if honor_avoid { self.honored } else { self.fallback }
+    }
+}
+
 ///Bitmap Buffer
 pub struct BitmapBuffer<'a> {
     _data: BTreeMap<u32, (&'a mut [u8], &'a mut [u8])>, //group_id bitmap_data
@@ -64,18 +90,178 @@ pub struct InodeAlloc {
 
 /// 块分配器
 /// 负责管理块的分配和释放
+/// 启用 bigalloc 时块位图的每一位对应一个簇，分配按簇取整
 pub struct BlockAllocator {
     blocks_per_group: u32,
     first_data_block: u32,
+    /// 每簇块数（未启用 bigalloc 时为 1）
+    cluster_ratio: u32,
+    /// 每组簇数（即块位图的有效位数）
+    clusters_per_group: u32,
+    /// 块组数（0 表示按整组处理）
+    groups: u32,
+    /// 最后一个块组实际包含的块数，可能不满一组
+    last_group_blocks: u32,
+    /// 尽量避开的全局块区间 [start, end)，仅在别处无空间时才使用
+    avoid: Option<(u64, u64)>,
+    /// 各 inode 的预留窗口 ino -> 全局块区间 [start, end)
+    /// 窗口内的块在位图中仍是空闲的，其它分配先绕开它们，空间不足时才占用
+    windows: BTreeMap<u32, (u64, u64)>,
+    /// 每个块组内的元数据区间（组内块号 [start, end)，有序且不重叠），挂载时算好
+    /// 位图把这些块错标为空闲时也不会分配出去
+    metadata: Vec<Vec<(u32, u32)>>,
 }
 
 impl BlockAllocator {
     /// 创建块分配器
     pub fn new(sb: &Ext4Superblock) -> Self {
+        let cluster_ratio = sb.cluster_ratio();
+        let clusters_per_group = if cluster_ratio > 1 && sb.s_clusters_per_group != 0 {
+            sb.s_clusters_per_group
+        } else {
+            sb.s_blocks_per_group / cluster_ratio
+        };
         Self {
             blocks_per_group: sb.s_blocks_per_group,
             first_data_block: sb.s_first_data_block,
+            cluster_ratio,
+            clusters_per_group,
+            groups: sb.block_groups_count(),
+            last_group_blocks: sb.blocks_in_group_cnt(sb.block_groups_count().saturating_sub(1)),
+            avoid: None,
+            windows: BTreeMap::new(),
+            metadata: Vec::new(),
+        }
+    }
+
+    /// 设置各块组的元数据区间，ranges[g] 为块组 g 内的 [start, end) 组内块号，无需预先排序
+    pub fn set_metadata_ranges(&mut self, mut ranges: Vec<Vec<(u32, u32)>>) {
+        for group in ranges.iter_mut() {
+            group.sort_unstable();
+            let mut merged: Vec<(u32, u32)> = Vec::with_capacity(group.len());
+            for &(start, end) in group.iter() {
+                match merged.last_mut() {
+                    Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            *group = merged;
+        }
+        self.metadata = ranges;
+    }
+
+    /// 全局块号是否是元数据块（块 0、超级块及其备份、GDT、位图、inode 表）
+    pub fn is_metadata_block(&self, block: u64) -> bool {
+        if block <= self.first_data_block as u64 {
+            return true;
+        }
+        let (group_idx, block_in_group) = self.global_to_group(block);
+        self.range_has_metadata(group_idx, block_in_group, block_in_group + 1)
+    }
+
+    /// 组内块区间 [start, end) 是否与元数据区间重叠
+    fn range_has_metadata(&self, group_idx: u32, start: u32, end: u32) -> bool {
+        let Some(ranges) = self.metadata.get(group_idx as usize) else {
+            return false;
+        };
+        // 第一个结束位置在 start 之后的区间
+        let i = ranges.partition_point(|&(_, e)| e <= start);
+        ranges.get(i).is_some_and(|&(s, _)| s < end)
+    }
+
+    /// 设置建议避开的块区间（如坏道或慢速区域），len 为 0 时清除
+    /// 只是建议：其它位置还有空间时不会分配到该区间
+    pub fn set_avoid_range(&mut self, start: u64, len: u64) {
+        self.avoid = if len == 0 {
+            None
+        } else {
+            Some((start, start.saturating_add(len)))
+        };
+    }
+
+    /// 清除避开区间
+    pub fn clear_avoid_range(&mut self) {
+        self.avoid = None;
+    }
+
+    /// 当前避开区间 (start, len)
+    pub fn avoid_range(&self) -> Option<(u64, u64)> {
+        self.avoid.map(|(start, end)| (start, end - start))
+    }
+
+    /// 为 ino 预留从 start 开始的 len 个块，替换它原有的窗口；len 为 0 时释放
+    pub fn set_reservation(&mut self, ino: u32, start: u64, len: u64) {
+        if len == 0 {
+            self.windows.remove(&ino);
+        } else {
+            self.windows.insert(ino, (start, start.saturating_add(len)));
+        }
+    }
+
+    /// ino 当前的预留窗口 [start, end)
+    pub fn reservation(&self, ino: u32) -> Option<(u64, u64)> {
+        self.windows.get(&ino).copied()
+    }
+
+    /// 释放 ino 的预留窗口（文件关闭、截断或不再增长时调用）
+    pub fn release_reservation(&mut self, ino: u32) {
+        self.windows.remove(&ino);
+    }
+
+    /// 当前预留窗口的数量
+    pub fn reservation_count(&self) -> usize {
+        self.windows.len()
+    }
+
+    /// 组内簇是否与避开区间或任何预留窗口重叠
+    fn cluster_avoided(&self, group_idx: u32, cluster: u32) -> bool {
+        let first = self.block_to_global(group_idx, cluster * self.cluster_ratio);
+        let last = first + self.cluster_ratio as u64;
+        self.avoid
+            .iter()
+            .chain(self.windows.values())
+            .any(|&(start, end)| first < end && last > start)
+    }
+
+    /// 簇是否包含元数据块（含块 0 和主超级块所在块），这样的簇永远不能分配
+    pub fn cluster_is_metadata(&self, group_idx: u32, cluster: u32) -> bool {
+        let first = cluster * self.cluster_ratio;
+        self.block_to_global(group_idx, first) <= self.first_data_block as u64
+            || self.range_has_metadata(group_idx, first, first + self.cluster_ratio)
+    }
+
+    /// 每簇块数
+    pub fn cluster_ratio(&self) -> u32 {
+        self.cluster_ratio
+    }
+
+    /// 每组簇数（块位图的有效位数）
+    pub fn clusters_per_group(&self) -> u32 {
+        self.clusters_per_group
+    }
+
+    /// 块组中实际存在的簇数：最后一个块组可能不完整，
+    /// 超出部分在位图中只是填充位，既不能分配也不计入统计
+    pub fn clusters_in_group(&self, group_idx: u32) -> u32 {
+        if self.groups == 0 || group_idx + 1 < self.groups {
+            return self.clusters_per_group;
+        }
+        if group_idx + 1 > self.groups {
+            return 0;
         }
+        self.last_group_blocks
+            .div_ceil(self.cluster_ratio)
+            .min(self.clusters_per_group)
+    }
+
+    /// 容纳 blocks 个块需要的簇数
+    pub fn clusters_for(&self, blocks: u32) -> u32 {
+        blocks.div_ceil(self.cluster_ratio)
+    }
+
+    /// 组内块号是否是所在簇的首块
+    pub fn is_cluster_head(&self, block_in_group: u32) -> bool {
+        block_in_group % self.cluster_ratio == 0
     }
 
     /// 在指定块组中分配一个块
@@ -93,13 +279,19 @@ impl BlockAllocator {
             return Err(AllocError::NoSpace);
         }
 
-        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.blocks_per_group);
+        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.clusters_in_group(group_idx));
 
-        // 查找第一个空闲块
-        let block_in_group = self.find_free_block(&bitmap)?.ok_or(AllocError::NoSpace)?;
+        // 查找第一个空闲簇，组内只剩避开区间时才落在区间内
+        let cluster = match self.find_free_block(&bitmap, group_idx, true)? {
+            Some(c) => c,
+            None => self
+                .find_free_block(&bitmap, group_idx, false)?
+                .ok_or(AllocError::NoSpace)?,
+        };
 
-        // 分配块
-        bitmap.allocate(block_in_group)?;
+        // 分配簇
+        bitmap.allocate(cluster)?;
+        let block_in_group = cluster * self.cluster_ratio;
 
         // 计算全局块号
         let global_block = self.block_to_global(group_idx, block_in_group);
@@ -111,7 +303,8 @@ impl BlockAllocator {
         })
     }
 
-    /// 在指定块组中分配连续的多个块
+    /// 在指定块组中分配连续的多个块（bigalloc 下向上取整到整簇）
+    /// 不会使用避开区间内的块
     /// * `bitmap_data` - 块位图数据
     /// * `group_idx` - 块组索引
     /// * `count` - 需要的连续块数
@@ -120,31 +313,192 @@ impl BlockAllocator {
         bitmap_data: &mut [u8],
         group_idx: u32,
         count: u32,
+    ) -> Result<BlockAlloc, AllocError> {
+        self.alloc_contiguous_blocks_with(bitmap_data, group_idx, count, true)
+    }
+
+    /// 同 alloc_contiguous_blocks，`honor_avoid` 为 false 时忽略避开区间
+    /// （调用方在所有块组都找不到其它空间后再用它兜底）
+    pub fn alloc_contiguous_blocks_with(
+        &self,
+        bitmap_data: &mut [u8],
+        group_idx: u32,
+        count: u32,
+        honor_avoid: bool,
     ) -> Result<BlockAlloc, AllocError> {
         if count == 0 {
             return Err(AllocError::InvalidParameter);
         }
+        let found = self.find_contiguous(bitmap_data, group_idx, count);
+        let (cluster, clusters) = found.pick(honor_avoid).ok_or(AllocError::NoSpace)?;
+        self.allocate_clusters(bitmap_data, group_idx, cluster, clusters)
+    }
 
-        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.blocks_per_group);
+    /// 从组内 goal 块开始向后（到组尾后回绕）找第一个可用簇，
+    /// 再尽量向后延伸，最多分配能容纳 max 个块的簇数
+    /// 返回分配结果和实际得到的块数（可能少于 max）
+    pub fn alloc_run_near(
+        &self,
+        bitmap_data: &mut [u8],
+        group_idx: u32,
+        goal_in_group: u32,
+        max: u32,
+        honor_avoid: bool,
+    ) -> Result<(BlockAlloc, u32), AllocError> {
+        if max == 0 {
+            return Err(AllocError::InvalidParameter);
+        }
+        let found = self.find_run_near(bitmap_data, group_idx, goal_in_group, max);
+        let (first, run) = found.pick(honor_avoid).ok_or(AllocError::NoSpace)?;
+        let alloc = self.allocate_clusters(bitmap_data, group_idx, first, run)?;
+        Ok((alloc, (run * self.cluster_ratio).min(max)))
+    }
 
-        // 查找连续的空闲块
-        let block_in_group = self
-            .find_contiguous_free_blocks(&bitmap, count)?
-            .ok_or(AllocError::NoSpace)?;
+    /// 只读扫描一遍位图，找 count 个块所需的连续空闲簇，
+    /// 同时给出避开建议限制的位置和忽略它们的兜底位置
+    pub fn find_contiguous(&self, bitmap_data: &[u8], group_idx: u32, count: u32) -> RunCandidates {
+        let bitmap = BlockBitmap::new(bitmap_data, self.clusters_in_group(group_idx));
+        let clusters = self.clusters_for(count);
+        let mut found = RunCandidates::default();
+        if clusters == 0 {
+            return found;
+        }
+        // 两条正在延伸的空闲段 (起始簇, 长度)：一条忽略建议限制，一条遵守
+        let (mut any, mut honored) = ((0u32, 0u32), (0u32, 0u32));
+        for c in 0..self.clusters_in_group(group_idx) {
+            if !self.cluster_free(&bitmap, group_idx, c) {
+                any.1 = 0;
+                honored.1 = 0;
+                continue;
+            }
+            if any.1 == 0 {
+                any.0 = c;
+            }
+            any.1 += 1;
+            if any.1 == clusters && found.fallback.is_none() {
+                found.fallback = Some((any.0, clusters));
+            }
+            if self.cluster_avoided(group_idx, c) {
+                honored.1 = 0;
+                continue;
+            }
+            if honored.1 == 0 {
+                honored.0 = c;
+            }
+            honored.1 += 1;
+            if honored.1 == clusters {
+                found.honored = Some((honored.0, clusters));
+                break;
+            }
+        }
+        found
+    }
 
-        // 批量分配
-        bitmap.allocate_range(block_in_group, count)?;
+    /// 只读扫描一遍位图，从 goal 块开始（到组尾后回绕）找第一个空闲簇并尽量延伸到 max 个块，
+    /// 同时给出避开建议限制的位置和忽略它们的兜底位置
+    pub fn find_run_near(
+        &self,
+        bitmap_data: &[u8],
+        group_idx: u32,
+        goal_in_group: u32,
+        max: u32,
+    ) -> RunCandidates {
+        let clusters_in_group = self.clusters_in_group(group_idx);
+        let bitmap = BlockBitmap::new(bitmap_data, clusters_in_group);
+        let wanted = self.clusters_for(max);
+        let goal = (goal_in_group / self.cluster_ratio).min(clusters_in_group);
+        let extend = |first: u32, honor: bool| {
+            let mut run = 1u32;
+            while run < wanted
+                && first + run < clusters_in_group
+                && self.cluster_free(&bitmap, group_idx, first + run)
+                && !(honor && self.cluster_avoided(group_idx, first + run))
+            {
+                run += 1;
+            }
+            (first, run)
+        };
 
-        let global_block = self.block_to_global(group_idx, block_in_group);
+        let mut found = RunCandidates::default();
+        for c in (goal..clusters_in_group).chain(0..goal) {
+            if !self.cluster_free(&bitmap, group_idx, c) {
+                continue;
+            }
+            if found.fallback.is_none() {
+                found.fallback = Some(extend(c, false));
+            }
+            if !self.cluster_avoided(group_idx, c) {
+                found.honored = Some(extend(c, true));
+                break;
+            }
+        }
+        found
+    }
 
+    /// 在位图中占用组内从 first 开始的 clusters 个簇
+    pub fn allocate_clusters(
+        &self,
+        bitmap_data: &mut [u8],
+        group_idx: u32,
+        first: u32,
+        clusters: u32,
+    ) -> Result<BlockAlloc, AllocError> {
+        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.clusters_in_group(group_idx));
+        bitmap.allocate_range(first, clusters)?;
+        let block_in_group = first * self.cluster_ratio;
         Ok(BlockAlloc {
             group_idx,
             block_in_group,
-            global_block,
+            global_block: self.block_to_global(group_idx, block_in_group),
         })
     }
 
-    /// 释放一个块
+    /// 从组内 block_in_group 开始，原地占用最多 max 个连续空闲块，
+    /// 遇到已占用的块即停止，返回实际占用的块数（用于从预留窗口取块，仅支持非 bigalloc）
+    pub fn alloc_exact(
+        &self,
+        bitmap_data: &mut [u8],
+        group_idx: u32,
+        block_in_group: u32,
+        max: u32,
+    ) -> Result<u32, AllocError> {
+        if self.cluster_ratio != 1 {
+            return Err(AllocError::InvalidParameter);
+        }
+        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.clusters_in_group(group_idx));
+        let mut got = 0u32;
+        while got < max
+            && bitmap.is_allocated(block_in_group + got) == Some(false)
+            && !self.cluster_is_metadata(group_idx, block_in_group + got)
+        {
+            got += 1;
+        }
+        if got > 0 {
+            bitmap.allocate_range(block_in_group, got)?;
+        }
+        Ok(got)
+    }
+
+    /// 从组内 block_in_group 开始有多少个连续的空闲且未被预留的块（最多 max），不修改位图
+    pub fn free_run_at(
+        &self,
+        bitmap_data: &[u8],
+        group_idx: u32,
+        block_in_group: u32,
+        max: u32,
+    ) -> u32 {
+        let bitmap = BlockBitmap::new(bitmap_data, self.clusters_in_group(group_idx));
+        let mut run = 0u32;
+        while run < max
+            && bitmap.is_free(block_in_group + run) == Some(true)
+            && !self.cluster_avoided(group_idx, block_in_group + run)
+        {
+            run += 1;
+        }
+        run
+    }
+
+    /// 释放一个块（bigalloc 下释放其所在的整簇）
     /// * `bitmap_data` - 块位图数据
     /// * `block_in_group` - 块组内的块索引
     pub fn free_block(
@@ -152,59 +506,63 @@ impl BlockAllocator {
         bitmap_data: &mut [u8],
         block_in_group: u32,
     ) -> Result<(), AllocError> {
-        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.blocks_per_group);
-        bitmap.free(block_in_group)?;
+        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.clusters_per_group);
+        bitmap.free(block_in_group / self.cluster_ratio)?;
         Ok(())
     }
 
-    /// 释放连续的多个块
+    /// 释放连续的多个块（按覆盖到的簇释放）
     pub fn free_blocks(
         &self,
         bitmap_data: &mut [u8],
         start_block: u32,
         count: u32,
     ) -> Result<(), AllocError> {
-        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.blocks_per_group);
-        bitmap.free_range(start_block, count)?;
+        let mut bitmap = BlockBitmapMut::new(bitmap_data, self.clusters_per_group);
+        let first = start_block / self.cluster_ratio;
+        let last = (start_block + count).div_ceil(self.cluster_ratio);
+        bitmap.free_range(first, last - first)?;
         Ok(())
     }
 
-    /// 查找第一个空闲块
-    fn find_free_block(&self, bitmap: &BlockBitmapMut) -> Result<Option<u32>, AllocError> {
-        for block_idx in 0..self.blocks_per_group {
-            if bitmap.is_allocated(block_idx) == Some(false) {
-                return Ok(Some(block_idx));
-            }
-        }
-        Ok(None)
+    /// 簇是否可用：空闲，且（需要时）不在避开区间或预留窗口内
+    fn cluster_usable(
+        &self,
+        bitmap: &BlockBitmapMut,
+        group_idx: u32,
+        cluster: u32,
+        honor_avoid: bool,
+    ) -> bool {
+        bitmap.is_allocated(cluster) == Some(false)
+            && !self.cluster_is_metadata(group_idx, cluster)
+            && !(honor_avoid && self.cluster_avoided(group_idx, cluster))
+    }
+
+    /// 簇是否空闲且不属于组内元数据（不考虑建议限制）
+    fn cluster_free(&self, bitmap: &BlockBitmap, group_idx: u32, cluster: u32) -> bool {
+        bitmap.is_free(cluster) == Some(true) && !self.cluster_is_metadata(group_idx, cluster)
     }
 
-    /// 查找连续的空闲块
-    fn find_contiguous_free_blocks(
+    /// 查找第一个空闲簇
+    fn find_free_block(
         &self,
         bitmap: &BlockBitmapMut,
-        count: u32,
+        group_idx: u32,
+        honor_avoid: bool,
     ) -> Result<Option<u32>, AllocError> {
-        let mut consecutive = 0u32;
-        let mut start_idx = 0u32;
-
-        for block_idx in 0..self.blocks_per_group {
-            if bitmap.is_allocated(block_idx) == Some(false) {
-                if consecutive == 0 {
-                    start_idx = block_idx;
-                }
-                consecutive += 1;
-                if consecutive == count {
-                    return Ok(Some(start_idx));
-                }
-            } else {
-                consecutive = 0;
+        for block_idx in 0..self.clusters_in_group(group_idx) {
+            if self.cluster_usable(bitmap, group_idx, block_idx, honor_avoid) {
+                return Ok(Some(block_idx));
             }
         }
-
         Ok(None)
     }
 
+    /// 块组第一个块的全局块号
+    pub fn group_first_block(&self, group_idx: u32) -> u64 {
+        self.block_to_global(group_idx, 0)
+    }
+
     /// 将块组内块号转换为全局块号
     fn block_to_global(&self, group_idx: u32, block_in_group: u32) -> u64 {
         (group_idx as u64 * self.blocks_per_group as u64)
@@ -256,7 +614,9 @@ impl InodeAllocator {
         let mut bitmap = InodeBitmapMut::new(bitmap_data, self.inodes_per_group);
 
         // 查找第一个空闲inode
-        let inode_in_group = self.find_free_inode(&bitmap)?.ok_or(AllocError::NoSpace)?;
+        let inode_in_group = self
+            .find_free_inode(&bitmap, group_idx)?
+            .ok_or(AllocError::NoSpace)?;
 
         // 分配inode
         bitmap.allocate(inode_in_group)?;
@@ -298,8 +658,9 @@ impl InodeAllocator {
     }
 
     /// 查找第一个空闲inode
-    fn find_free_inode(&self, bitmap: &InodeBitmapMut) -> Result<Option<u32>, AllocError> {
-        let start_idx = if self.first_inode > 0 {
+    /// 保留 inode（1..first_ino）只存在于块组 0，其它块组从组内第一个 inode 开始找
+    fn find_free_inode(&self, bitmap: &InodeBitmapMut, group_idx: u32) -> Result<Option<u32>, AllocError> {
+        let start_idx = if group_idx == 0 && self.first_inode > 0 {
             self.first_inode - 1 // 比如 first_ino=11 → 从 index 10 开始
         } else {
             0
@@ -329,6 +690,7 @@ impl InodeAllocator {
 }
 
 use alloc::collections::btree_map::BTreeMap;
+use alloc::vec::Vec;
 use lazy_static::lazy_static;
 use log::error;
 //crete global inode_alloctor and block alloctor;
@@ -354,10 +716,11 @@ mod tests {
         let result = allocator.alloc_block_in_group(&mut bitmap_data, 0, &gd);
         assert!(result.is_ok());
 
+        // 块 0 永远不会分配出去
         let alloc = result.unwrap();
         assert_eq!(alloc.group_idx, 0);
-        assert_eq!(alloc.block_in_group, 0);
-        assert_eq!(alloc.global_block, 0);
+        assert_eq!(alloc.block_in_group, 1);
+        assert_eq!(alloc.global_block, 1);
     }
 
     #[test]
@@ -374,7 +737,7 @@ mod tests {
         assert!(result.is_ok());
 
         let alloc = result.unwrap();
-        assert_eq!(alloc.block_in_group, 0);
+        assert_eq!(alloc.block_in_group, 1);
     }
 
     #[test]
@@ -413,4 +776,161 @@ mod tests {
         let global = allocator.inode_to_global(group, inode_in_group);
         assert_eq!(global, 257);
     }
+
+    #[test]
+    fn test_block_allocator_bigalloc_rounds_to_cluster() {
+        let mut sb = Ext4Superblock::default();
+        sb.s_blocks_per_group = 1024;
+        sb.s_first_data_block = 0;
+        sb.s_log_block_size = 2;
+        sb.s_log_cluster_size = 4; // 簇 16KB = 4 块
+        sb.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_BIGALLOC;
+        sb.s_clusters_per_group = 256;
+
+        let allocator = BlockAllocator::new(&sb);
+        assert_eq!(allocator.cluster_ratio(), 4);
+        assert_eq!(allocator.clusters_for(5), 2);
+
+        let mut bitmap_data = vec![0u8; 32]; // 256 簇
+        // 簇 0 含块 0，不参与分配
+        let a = allocator.alloc_contiguous_blocks(&mut bitmap_data, 0, 1).unwrap();
+        assert_eq!(a.block_in_group, 4);
+        let b = allocator.alloc_contiguous_blocks(&mut bitmap_data, 0, 5).unwrap();
+        // 第一次分配占满簇 1，第二次从簇 2 开始并占两个簇
+        assert_eq!(b.block_in_group, 8);
+        assert_eq!(bitmap_data[0], 0b0000_1110);
+
+        assert!(!allocator.is_cluster_head(9));
+        allocator.free_block(&mut bitmap_data, 8).unwrap();
+        assert_eq!(bitmap_data[0], 0b0000_1010);
+    }
+
+    #[test]
+    fn test_block_allocator_avoid_range() {
+        let mut sb = Ext4Superblock::default();
+        sb.s_blocks_per_group = 1024;
+        sb.s_first_data_block = 0;
+
+        let mut allocator = BlockAllocator::new(&sb);
+        allocator.set_avoid_range(0, 100);
+        assert_eq!(allocator.avoid_range(), Some((0, 100)));
+
+        let mut bitmap_data = vec![0u8; 128];
+        let a = allocator.alloc_contiguous_blocks(&mut bitmap_data, 0, 4).unwrap();
+        assert_eq!(a.block_in_group, 100);
+
+        // 组内只剩避开区间时，单块分配退回到区间内，连续分配则交由调用方兜底
+        allocator.set_avoid_range(0, 1024);
+        let mut gd = Ext4GroupDesc::default();
+        gd.bg_free_blocks_count_lo = 1020;
+        assert!(allocator.alloc_contiguous_blocks(&mut bitmap_data, 0, 1).is_err());
+        let b = allocator.alloc_block_in_group(&mut bitmap_data, 0, &gd).unwrap();
+        assert_eq!(b.block_in_group, 1);
+        let c = allocator
+            .alloc_contiguous_blocks_with(&mut bitmap_data, 0, 1, false)
+            .unwrap();
+        assert_eq!(c.block_in_group, 2);
+
+        allocator.clear_avoid_range();
+        assert_eq!(allocator.avoid_range(), None);
+    }
+
+    #[test]
+    fn test_block_allocator_run_near_goal() {
+        let mut sb = Ext4Superblock::default();
+        sb.s_blocks_per_group = 1024;
+        sb.s_first_data_block = 0;
+
+        let allocator = BlockAllocator::new(&sb);
+        let mut bitmap_data = vec![0u8; 128];
+        // 块 0..8 和 20 已占用
+        bitmap_data[0] = 0xFF;
+        bitmap_data[2] = 0b0001_0000;
+
+        // 从 goal 向后取满 8 块
+        let (a, n) = allocator.alloc_run_near(&mut bitmap_data, 0, 10, 8, true).unwrap();
+        assert_eq!((a.block_in_group, n), (10, 8));
+        // 中途遇到已占用块时返回较短的一段
+        let (b, n) = allocator.alloc_run_near(&mut bitmap_data, 0, 18, 8, true).unwrap();
+        assert_eq!((b.block_in_group, n), (18, 2));
+        // goal 之后没有空间时回绕到组首
+        bitmap_data[8..].fill(0xFF);
+        let (c, n) = allocator.alloc_run_near(&mut bitmap_data, 0, 100, 4, true).unwrap();
+        assert_eq!((c.block_in_group, n), (8, 2));
+    }
+
+    #[test]
+    fn one_scan_reports_honored_and_fallback_runs() {
+        let mut sb = Ext4Superblock::default();
+        sb.s_blocks_per_group = 1024;
+        sb.s_first_data_block = 0;
+
+        let mut allocator = BlockAllocator::new(&sb);
+        let mut bitmap_data = vec![0u8; 128];
+        // 块 16..24 是另一个文件的预留窗口，24 之后到 40 已占用
+        allocator.set_reservation(7, 16, 8);
+        bitmap_data[3..5].fill(0xFF);
+
+        let found = allocator.find_run_near(&bitmap_data, 0, 18, 4);
+        assert_eq!(found.fallback, Some((18, 4)));
+        assert_eq!(found.honored, Some((40, 4)));
+        // 块 0 是超级块所在的元数据块
+        let found = allocator.find_contiguous(&bitmap_data, 0, 20);
+        assert_eq!(found.fallback, Some((1, 20)));
+        assert_eq!(found.honored, Some((40, 20)));
+
+        // 窗口之外没有空间时只剩兜底位置，按它分配会占用窗口
+        bitmap_data[..2].fill(0xFF);
+        bitmap_data[5..].fill(0xFF);
+        let found = allocator.find_run_near(&bitmap_data, 0, 0, 4);
+        assert_eq!(found, RunCandidates { honored: None, fallback: Some((16, 4)) });
+        assert!(allocator.alloc_run_near(&mut bitmap_data, 0, 0, 4, true).is_err());
+        let (a, n) = allocator.alloc_run_near(&mut bitmap_data, 0, 0, 4, false).unwrap();
+        assert_eq!((a.block_in_group, n), (16, 4));
+        assert_eq!(bitmap_data[2], 0x0F);
+    }
+
+    #[test]
+    fn block_allocator_addresses_groups_beyond_u32() {
+        let mut sb = Ext4Superblock::default();
+        sb.s_blocks_per_group = 32768;
+        sb.s_first_data_block = 0;
+        sb.s_blocks_count_lo = 0;
+        sb.s_blocks_count_hi = 2;
+        let allocator = BlockAllocator::new(&sb);
+
+        // 第 131072 组起始于 2^32
+        let group = 131_073;
+        let mut bitmap_data = vec![0u8; 4096];
+        let mut gd = Ext4GroupDesc::default();
+        gd.bg_free_blocks_count_lo = 32768;
+        let alloc = allocator.alloc_block_in_group(&mut bitmap_data, group, &gd).unwrap();
+        assert_eq!(alloc.global_block, (1u64 << 32) + 32768 + alloc.block_in_group as u64);
+        assert_eq!(
+            allocator.global_to_group(alloc.global_block),
+            (group, alloc.block_in_group)
+        );
+    }
+
+    #[test]
+    fn metadata_ranges_are_never_allocated_even_if_bitmap_says_free() {
+        let mut sb = Ext4Superblock::default();
+        sb.s_blocks_per_group = 1024;
+        let mut allocator = BlockAllocator::new(&sb);
+        // 重叠、乱序的区间合并为 [1, 5) 和 [8, 12)
+        allocator.set_metadata_ranges(vec![vec![(8, 12), (1, 3), (2, 5), (9, 10)]]);
+        assert!(allocator.is_metadata_block(0));
+        assert!((1..5).chain(8..12).all(|b| allocator.is_metadata_block(b)));
+        assert!(!allocator.is_metadata_block(5) && !allocator.is_metadata_block(12));
+
+        let mut bitmap_data = vec![0u8; 128];
+        let a = allocator.alloc_contiguous_blocks(&mut bitmap_data, 0, 4).unwrap();
+        assert_eq!(a.block_in_group, 12);
+        let (b, n) = allocator.alloc_run_near(&mut bitmap_data, 0, 3, 8, true).unwrap();
+        assert_eq!((b.block_in_group, n), (5, 3));
+        assert_eq!(allocator.alloc_exact(&mut bitmap_data, 0, 10, 4).unwrap(), 0);
+        // 元数据位在位图里仍是空闲的：分配器只是跳过它们，没有改动位图
+        assert_eq!(bitmap_data[0] & 0b0001_1110, 0);
+        assert_eq!(bitmap_data[1] & 0b0000_1111, 0);
+    }
 }
@@ -5,6 +5,13 @@ use crate::ext4_backend::superblock::*;
 // ============================================================================
 /// JBD2 日志缓冲区最大数量
 pub const JBD2_BUFFER_MAX: usize = 10; //最多10条缓存
+/// 单个 descriptor 块可容纳的 tag 数（12 字节头 + 8 字节 tag）
+pub const JBD2_DESCRIPTOR_TAG_MAX: usize = (BLOCK_SIZE - 12) / 8;
+/// 单个 revoke 块可容纳的块号数（16 字节头 + be32 块号）
+pub const JBD2_REVOKE_RECORDS_MAX: usize = (BLOCK_SIZE - 16) / 4;
+/// 开始事务时日志剩余块数低于该值则先做 checkpoint
+/// 至少要容纳一个装满的描述块事务（descriptor + 日志块 + revoke + commit）
+pub const JBD2_CHECKPOINT_FREE_MIN: u32 = JBD2_DESCRIPTOR_TAG_MAX as u32 + 8;
 
 // ============================================================================
 // 块相关配置
@@ -41,10 +48,28 @@ pub const DEFAULT_INODE_SIZE: u16 = 256;
 // ============================================================================
 ///Inodecahe数量
 pub const INODE_CACHE_MAX: usize = 128;
+///Inode表块缓存数量（按整块缓存，一块可服务多个 inode）
+pub const INODE_TABLE_BLOCK_CACHE_MAX: usize = 16;
 ///Datablock cahce数量
 pub const DATABLOCK_CACHE_MAX: usize = 128;
+///数据块缓存合并写回时单次设备写的最大块数
+pub const WRITEBACK_RUN_MAX: usize = 256;
 ///BITMAP cache数量
 pub const BITMAP_CACHE_MAX: usize = 128;
+///目录项名字缓存数量
+pub const DENTRY_CACHE_MAX: usize = 256;
+///路径解析时最多跟随的符号链接次数
+pub const SYMLINK_MAX_HOPS: usize = 8;
+///顺序读文件时默认预读的块数（0 表示关闭预读）
+pub const DEFAULT_READAHEAD_BLOCKS: u32 = 8;
+///文件增长时在新分配区之后为它预留的块数（0 表示不预留）
+pub const RESERVATION_WINDOW_BLOCKS: u32 = 8;
+///挂载后默认是否关闭读文件时的 atime 更新（类似 Linux noatime）
+pub const DEFAULT_NOATIME: bool = false;
+///挂载后默认是否在释放数据块时对设备下发 discard（类似 Linux discard 挂载选项）
+pub const DEFAULT_ONLINE_DISCARD: bool = false;
+///fstrim 默认只对不短于该块数的空闲区间下发 discard
+pub const DEFAULT_FSTRIM_MIN_BLOCKS: u32 = 1;
 
 //============================================================================
 //目录项DirEntry配置
@@ -67,6 +92,9 @@ pub const SUPERBLOCK_SIZE: usize = 1024;
 /// 预留的 GDT 块数（用于未来扩展块组描述符）
 pub const RESERVED_GDT_BLOCKS: u32 = 0;
 
+/// mkfs 在块组 0 元数据之外至少需要的数据块数（根目录、lost+found 等）
+pub const MKFS_MIN_DATA_BLOCKS: u64 = 16;
+
 // ============================================================================
 // 特性标志
 // ============================================================================
@@ -82,6 +110,25 @@ pub const DEFAULT_FEATURE_INCOMPAT: u32 = Ext4Superblock::EXT4_FEATURE_INCOMPAT_
 /// 默认的只读兼容特性标志
 pub const DEFAULT_FEATURE_RO_COMPAT: u32 = Ext4Superblock::EXT4_FEATURE_RO_COMPAT_EXTRA_ISIZE
     | Ext4Superblock::EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER;
+/// 本实现能正确处理的 incompat 特性，超级块中出现其它 incompat 位时拒绝挂载
+/// 挂载前可用 `sb.s_feature_incompat & !EXT4_SUPPORTED_INCOMPAT` 判断兼容性
+pub const EXT4_SUPPORTED_INCOMPAT: u32 = Ext4Superblock::EXT4_FEATURE_INCOMPAT_FILETYPE
+    | Ext4Superblock::EXT4_FEATURE_INCOMPAT_RECOVER
+    | Ext4Superblock::EXT4_FEATURE_INCOMPAT_EXTENTS
+    | Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT
+    | Ext4Superblock::EXT4_FEATURE_INCOMPAT_FLEX_BG
+    | Ext4Superblock::EXT4_FEATURE_INCOMPAT_INLINE_DATA
+    | Ext4Superblock::EXT4_FEATURE_INCOMPAT_CSUM_SEED;
+
+/// 读写挂载时可以安全处理的 ro_compat 特性，其余特性只能只读挂载
+pub const EXT4_RW_SUPPORTED_RO_COMPAT: u32 = Ext4Superblock::EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER
+    | Ext4Superblock::EXT4_FEATURE_RO_COMPAT_LARGE_FILE
+    | Ext4Superblock::EXT4_FEATURE_RO_COMPAT_HUGE_FILE
+    | Ext4Superblock::EXT4_FEATURE_RO_COMPAT_GDT_CSUM
+    | Ext4Superblock::EXT4_FEATURE_RO_COMPAT_DIR_NLINK
+    | Ext4Superblock::EXT4_FEATURE_RO_COMPAT_EXTRA_ISIZE
+    | Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM
+    | Ext4Superblock::EXT4_FEATURE_RO_COMPAT_BIGALLOC;
 
 // ============================================================================
 // 魔数和版本
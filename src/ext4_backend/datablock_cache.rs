@@ -2,11 +2,25 @@
 //!
 //! 提供文件和目录数据块的缓存管理，支持延迟写回和LRU淘汰
 
+use crate::ext4_backend::block_cache::*;
 use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::error::*;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+/// 文件数据块的写回策略
+///
+/// WriteBack：修改只标记为脏，由淘汰、flush 或 umount 批量写回，吞吐量高，
+/// 但崩溃时会丢失尚未写回的数据。
+/// WriteThrough：每次修改文件数据块后立即写到 Jbd2Dev，崩溃时最多丢失正在进行的那次写，
+/// 代价是每个块一次设备写，连续写无法合并。目录块等元数据仍随事务提交，不受影响
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CachePolicy {
+    #[default]
+    WriteBack,
+    WriteThrough,
+}
+
 /// 数据块缓存键（全局块号）
 pub type BlockCacheKey = u64;
 
@@ -49,6 +63,10 @@ pub struct DataBlockCache {
     access_counter: u64,
     /// 块大小
     block_size: usize,
+    /// 命中、未命中、写回和淘汰计数
+    counters: CacheCounters,
+    /// 文件数据块的写回策略
+    policy: CachePolicy,
 }
 
 impl DataBlockCache {
@@ -63,12 +81,14 @@ impl DataBlockCache {
             max_entries,
             access_counter: 0,
             block_size,
+            counters: CacheCounters::default(),
+            policy: CachePolicy::WriteBack,
         }
     }
 
-    /// 创建默认配置的缓存（最多64个块，4KB大小）
+    /// 创建默认配置的缓存（容量取 DATABLOCK_CACHE_MAX）
     pub fn default() -> Self {
-        Self::new(64, BLOCK_SIZE)
+        Self::new(DATABLOCK_CACHE_MAX, BLOCK_SIZE)
     }
 
     /// 从磁盘加载数据块
@@ -77,7 +97,7 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
     ) -> BlockDevResult<Vec<u8>> {
-        block_dev.read_block(block_num as u32)?;
+        block_dev.read_block(block_num)?;
         let buffer = block_dev.buffer();
         Ok(buffer.to_vec())
     }
@@ -93,10 +113,11 @@ impl DataBlockCache {
         block_num: u64,
     ) -> BlockDevResult<&CachedBlock> {
         // 如果缓存中不存在，则加载
-        if !self.cache.contains_key(&block_num) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
-            }
+        if self.cache.contains_key(&block_num) {
+            self.counters.hits += 1;
+        } else {
+            self.counters.misses += 1;
+            self.make_room(block_dev)?;
 
             let data = self.load_block(block_dev, block_num)?;
             let cached = CachedBlock::new(data, block_num);
@@ -118,10 +139,11 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
     ) -> BlockDevResult<&mut CachedBlock> {
-        if !self.cache.contains_key(&block_num) {
-            if self.cache.len() >= self.max_entries {
-                self.evict_lru(block_dev)?;
-            }
+        if self.cache.contains_key(&block_num) {
+            self.counters.hits += 1;
+        } else {
+            self.counters.misses += 1;
+            self.make_room(block_dev)?;
 
             let data = self.load_block(block_dev, block_num)?;
             let cached = CachedBlock::new(data, block_num);
@@ -137,6 +159,79 @@ impl DataBlockCache {
         }
     }
 
+    /// 用一次 read_blocks 预读从 block_num 开始的 count 个物理连续块，返回新载入的块数
+    /// 开头已在缓存中的块跳过，缓存里已有的块（可能是脏的）保持不变；
+    /// 预读量不超过容量的一半，避免挤掉正在使用的块。预读不计入命中/未命中
+    pub fn prefetch<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        block_num: u64,
+        count: u32,
+    ) -> BlockDevResult<u32> {
+        let count = count.min((self.max_entries / 2) as u32) as u64;
+        let Some(skip) = (0..count).find(|i| !self.cache.contains_key(&(block_num + i))) else {
+            return Ok(0);
+        };
+        let start = block_num + skip;
+        let count = (count - skip) as usize;
+
+        let mut buf = alloc::vec![0u8; count * self.block_size];
+        block_dev.read_blocks(&mut buf, start, count as u32)?;
+        let mut loaded = 0;
+        for (i, chunk) in buf.chunks_exact(self.block_size).enumerate() {
+            let blk = start + i as u64;
+            if self.cache.contains_key(&blk) {
+                continue;
+            }
+            self.make_room(block_dev)?;
+            self.access_counter += 1;
+            let mut cached = CachedBlock::new(chunk.to_vec(), blk);
+            cached.last_access = self.access_counter;
+            self.cache.insert(blk, cached);
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    /// 当前写回策略
+    pub fn policy(&self) -> CachePolicy {
+        self.policy
+    }
+
+    /// 设置写回策略；切换到 WriteThrough 不会写回已有的脏块
+    pub fn set_policy(&mut self, policy: CachePolicy) {
+        self.policy = policy;
+    }
+
+    /// 文件数据块修改完成后调用：WriteThrough 下立即写回并清除脏标记，WriteBack 下什么也不做
+    pub fn write_through<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        block_num: u64,
+    ) -> BlockDevResult<()> {
+        if self.policy == CachePolicy::WriteThrough {
+            self.flush(block_dev, block_num)?;
+        }
+        Ok(())
+    }
+
+    /// 同 write_through，但把一批块中物理连续的脏块合并成一次设备写
+    pub fn write_through_blocks<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        blocks: &[u64],
+    ) -> BlockDevResult<()> {
+        if self.policy != CachePolicy::WriteThrough {
+            return Ok(());
+        }
+        for &b in blocks {
+            if self.cache.get(&b).is_some_and(|c| c.dirty) {
+                self.write_back_run(block_dev, b)?;
+            }
+        }
+        Ok(())
+    }
+
     /// 获取已缓存的数据块（不加载）
     pub fn get(&self, block_num: u64) -> Option<&CachedBlock> {
         self.cache.get(&block_num)
@@ -204,6 +299,31 @@ impl DataBlockCache {
         cached.mark_dirty();
     }
 
+    /// 淘汰到低于容量上限，为新块腾出位置
+    /// create_new 没有设备句柄，可能让缓存暂时超出上限，在这里一并收回
+    fn make_room<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        while !self.cache.is_empty() && self.cache.len() >= self.max_entries {
+            self.evict_lru(block_dev)?;
+        }
+        Ok(())
+    }
+
+    /// 淘汰到不超过容量上限，供批量 create_new/modify_new 之后调用
+    /// 按访问时间一次排序后淘汰，避免大批量写入后逐个扫描
+    pub fn shrink_to_capacity<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        let excess = self.cache.len().saturating_sub(self.max_entries);
+        if excess == 0 {
+            return Ok(());
+        }
+        let mut order: Vec<(u64, u64)> = self.cache.iter().map(|(k, c)| (c.last_access, *k)).collect();
+        order.sort_unstable();
+        for &(_, key) in &order[..excess] {
+            self.evict(block_dev, key)?;
+            self.counters.evictions += 1;
+        }
+        Ok(())
+    }
+
     /// LRU淘汰：找到最久未访问的并写回（如果脏）
     fn evict_lru<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
         // 找到最小的last_access
@@ -215,6 +335,7 @@ impl DataBlockCache {
 
         if let Some(key) = lru_key {
             self.evict(block_dev, key)?;
+            self.counters.evictions += 1;
         }
 
         Ok(())
@@ -226,11 +347,45 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
     ) -> BlockDevResult<()> {
-        if let Some(cached) = self.cache.remove(&block_num)
-            && cached.dirty {
-                // 写回磁盘
-                Self::write_block_static(block_dev, cached.block_num, &cached.data)?;
+        if self.cache.get(&block_num).is_some_and(|c| c.dirty) {
+            self.write_back_run(block_dev, block_num)?;
+        }
+        self.cache.remove(&block_num);
+        Ok(())
+    }
+
+    /// 把 block_num 所在的连续脏块一次性写回并清除脏标记（最多 WRITEBACK_RUN_MAX 块）
+    /// 顺序写大文件时淘汰一个块就顺带写回整段，设备写调用次数按段而不是按块计
+    fn write_back_run<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        block_num: u64,
+    ) -> BlockDevResult<()> {
+        let dirty = |cache: &BTreeMap<u64, CachedBlock>, b: u64| cache.get(&b).is_some_and(|c| c.dirty);
+        let mut start = block_num;
+        while block_num - start + 1 < WRITEBACK_RUN_MAX as u64
+            && start > 0
+            && dirty(&self.cache, start - 1)
+        {
+            start -= 1;
+        }
+        let mut end = block_num + 1;
+        while end - start < WRITEBACK_RUN_MAX as u64 && dirty(&self.cache, end) {
+            end += 1;
+        }
+
+        let count = (end - start) as usize;
+        let mut buf: Vec<u8> = Vec::with_capacity(self.block_size * count);
+        for b in start..end {
+            buf.extend_from_slice(&self.cache[&b].data);
+        }
+        block_dev.write_blocks(&buf, start, count as u32, false)?;
+        self.counters.writebacks += count as u64;
+        for b in start..end {
+            if let Some(cached) = self.cache.get_mut(&b) {
+                cached.dirty = false;
             }
+        }
         Ok(())
     }
 
@@ -251,7 +406,6 @@ impl DataBlockCache {
         dirty_blocks.sort_by_key(|(block_num, _)| *block_num);
 
         // 将连续块聚合后，使用 write_blocks 一次性写回
-        let max_part_size = BLOCK_SIZE * 100; //最大聚合块数;
         let block_size = self.block_size;
         let mut idx = 0usize;
         while idx < dirty_blocks.len() {
@@ -259,7 +413,7 @@ impl DataBlockCache {
             let mut run_len = 1usize;
 
             // 统计从 start_block 开始的连续块数量
-            while idx + run_len < dirty_blocks.len() && run_len <= max_part_size {
+            while idx + run_len < dirty_blocks.len() && run_len < WRITEBACK_RUN_MAX {
                 let expected = start_block + run_len as u64;
                 if dirty_blocks[idx + run_len].0 == expected {
                     run_len += 1;
@@ -275,7 +429,8 @@ impl DataBlockCache {
             }
 
             // 通过底层的 write_blocks 一次性写入连续块
-            block_dev.write_blocks(&buf, start_block as u32, run_len as u32, false)?;
+            block_dev.write_blocks(&buf, start_block, run_len as u32, false)?;
+            self.counters.writebacks += run_len as u64;
 
             idx += run_len;
         }
@@ -288,16 +443,76 @@ impl DataBlockCache {
         Ok(())
     }
 
+    /// 只写回给定块中的脏块，物理连续的合并成一次设备写
+    /// 与淘汰时不同，不会顺带写回范围之外的相邻脏块
+    pub fn flush_blocks<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        blocks: &[u64],
+    ) -> BlockDevResult<()> {
+        let mut dirty: Vec<u64> = blocks
+            .iter()
+            .copied()
+            .filter(|b| self.cache.get(b).is_some_and(|c| c.dirty))
+            .collect();
+        dirty.sort_unstable();
+        dirty.dedup();
+
+        let mut idx = 0usize;
+        while idx < dirty.len() {
+            let start = dirty[idx];
+            let mut run_len = 1usize;
+            while idx + run_len < dirty.len()
+                && run_len < WRITEBACK_RUN_MAX
+                && dirty[idx + run_len] == start + run_len as u64
+            {
+                run_len += 1;
+            }
+            let mut buf: Vec<u8> = Vec::with_capacity(self.block_size * run_len);
+            for &b in &dirty[idx..idx + run_len] {
+                buf.extend_from_slice(&self.cache[&b].data);
+            }
+            block_dev.write_blocks(&buf, start, run_len as u32, false)?;
+            self.counters.writebacks += run_len as u64;
+            for &b in &dirty[idx..idx + run_len] {
+                if let Some(cached) = self.cache.get_mut(&b) {
+                    cached.dirty = false;
+                }
+            }
+            idx += run_len;
+        }
+        Ok(())
+    }
+
     /// 刷新指定数据块到磁盘
     pub fn flush<B: BlockDevice>(
         &mut self,
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
+    ) -> BlockDevResult<()> {
+        self.flush_with(block_dev, block_num, false)
+    }
+
+    /// 把指定块（如目录块）作为元数据刷新，开启日志时进入 JBD2 事务
+    pub fn flush_as_metadata<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        block_num: u64,
+    ) -> BlockDevResult<()> {
+        self.flush_with(block_dev, block_num, true)
+    }
+
+    fn flush_with<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        block_num: u64,
+        is_metadata: bool,
     ) -> BlockDevResult<()> {
         if let Some(cached) = self.cache.get(&block_num)
             && cached.dirty {
                 let data = cached.data.clone();
-                Self::write_block_static(block_dev, block_num, &data)?;
+                Self::write_block_static(block_dev, block_num, &data, is_metadata)?;
+                self.counters.writebacks += 1;
 
                 if let Some(cached) = self.cache.get_mut(&block_num) {
                     cached.dirty = false;
@@ -311,11 +526,12 @@ impl DataBlockCache {
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
         data: &[u8],
+        is_metadata: bool,
     ) -> BlockDevResult<()> {
-        block_dev.read_block(block_num as u32)?;
+        block_dev.read_block(block_num)?;
         let buffer = block_dev.buffer_mut();
         buffer[..data.len()].copy_from_slice(data);
-        block_dev.write_block(block_num as u32, false)?;
+        block_dev.write_block(block_num, is_metadata)?;
         Ok(())
     }
 
@@ -331,6 +547,25 @@ impl DataBlockCache {
         self.cache.clear();
     }
 
+    /// 调整容量上限，超出部分按 LRU 淘汰（脏块先写回）
+    pub fn set_capacity<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        max_entries: usize,
+    ) -> BlockDevResult<()> {
+        self.max_entries = max_entries.max(1);
+        self.shrink_to_capacity(block_dev)
+    }
+
+    /// 块缓存统计中的数据块部分
+    pub fn category_stats(&self) -> CacheCategoryStats {
+        CacheCategoryStats {
+            cached_blocks: self.cache.len(),
+            capacity: self.max_entries,
+            counters: self.counters,
+        }
+    }
+
     /// 获取缓存统计
     pub fn stats(&self) -> DataBlockCacheStats {
         let dirty_count = self.cache.values().filter(|c| c.dirty).count();
@@ -344,6 +579,28 @@ impl DataBlockCache {
             total_size_bytes: total_size,
         }
     }
+
+    /// 命中、未命中和 LRU 淘汰次数
+    pub fn cache_stats(&self) -> DataBlockCacheCounters {
+        DataBlockCacheCounters {
+            hits: self.counters.hits,
+            misses: self.counters.misses,
+            evictions: self.counters.evictions,
+        }
+    }
+
+    /// 访问计数清零
+    pub fn reset_counters(&mut self) {
+        self.counters = CacheCounters::default();
+    }
+}
+
+/// 数据块缓存的访问计数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DataBlockCacheCounters {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
 }
 
 /// 数据块缓存统计信息
@@ -393,4 +650,55 @@ mod tests {
         cache.invalidate(100);
         assert_eq!(cache.cache.len(), 0);
     }
+
+    #[test]
+    fn lru_evicts_least_recent_and_writes_back_dirty() {
+        use crate::ext4_backend::test_support::*;
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(256), false, false);
+        let mut cache = DataBlockCache::new(4, BLOCK_SIZE);
+        let mut evicted = Vec::new();
+        let mut touch = |cache: &mut DataBlockCache, dev: &mut Jbd2Dev<MemBlockDev>, blk: u64| {
+            let before: Vec<u64> = cache.cache.keys().copied().collect();
+            if blk % 2 == 0 {
+                cache.modify(dev, blk, |d| d.fill(blk as u8)).unwrap();
+            } else {
+                cache.get_or_load(dev, blk).unwrap();
+            }
+            evicted.extend(before.into_iter().filter(|k| !cache.cache.contains_key(k)));
+        };
+
+        for blk in 100..104 {
+            touch(&mut cache, &mut dev, blk);
+        }
+        // 重新访问 100，使它变为最近使用
+        touch(&mut cache, &mut dev, 100);
+        for blk in 104..110 {
+            touch(&mut cache, &mut dev, blk);
+        }
+
+        assert_eq!(evicted, [101, 102, 103, 100, 104, 105]);
+        assert_eq!(cache.stats().total_entries, 4);
+        assert_eq!(
+            cache.cache_stats(),
+            DataBlockCacheCounters {
+                hits: 1,
+                misses: 10,
+                evictions: 6
+            }
+        );
+        assert_eq!(cache.category_stats().counters.writebacks, 3);
+
+        // 被淘汰的脏块已经写回设备
+        for blk in [100u64, 102, 104] {
+            dev.read_block(blk).unwrap();
+            assert!(dev.buffer().iter().all(|&b| b == blk as u8));
+        }
+        // 仍在缓存里的脏块由 flush_all 写回
+        assert_eq!(cache.stats().dirty_entries, 2);
+        cache.flush_all(&mut dev).unwrap();
+        assert_eq!(cache.stats().dirty_entries, 0);
+        dev.read_block(106).unwrap();
+        assert!(dev.buffer().iter().all(|&b| b == 106));
+    }
 }
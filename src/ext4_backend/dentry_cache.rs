@@ -0,0 +1,164 @@
+//! 目录项名字缓存模块
+//!
+//! 缓存 (父目录 inode 号, 文件名) → 子 inode 号 的映射，
+//! 避免重复解析同一路径时反复扫描目录块，容量满时按 LRU 淘汰
+
+use crate::ext4_backend::config::*;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// 名字缓存键（父目录 inode 号, 文件名字节）
+pub type DentryCacheKey = (u32, Vec<u8>);
+
+/// 缓存的目录项
+#[derive(Debug, Clone, Copy)]
+struct CachedDentry {
+    /// 子 inode 号
+    ino: u32,
+    /// 最后访问时间戳
+    last_access: u64,
+}
+
+/// 名字缓存管理器
+pub struct DentryCache {
+    /// 缓存的目录项
+    cache: BTreeMap<DentryCacheKey, CachedDentry>,
+    /// 最大缓存条目数
+    max_entries: usize,
+    /// 访问计数器
+    access_counter: u64,
+    /// 命中次数
+    hits: u64,
+    /// 未命中次数
+    misses: u64,
+}
+
+impl DentryCache {
+    /// 创建名字缓存
+    /// * `max_entries` - 最大缓存条目数
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            cache: BTreeMap::new(),
+            max_entries,
+            access_counter: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// 查找 parent 目录下名为 name 的子 inode 号
+    pub fn lookup(&mut self, parent: u32, name: &[u8]) -> Option<u32> {
+        self.access_counter += 1;
+        let key = (parent, name.to_vec());
+        match self.cache.get_mut(&key) {
+            Some(cached) => {
+                cached.last_access = self.access_counter;
+                self.hits += 1;
+                Some(cached.ino)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// 记录一次成功的目录查找结果
+    pub fn insert(&mut self, parent: u32, name: &[u8], ino: u32) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let key = (parent, name.to_vec());
+        if !self.cache.contains_key(&key) && self.cache.len() >= self.max_entries {
+            self.evict_lru();
+        }
+        self.access_counter += 1;
+        self.cache.insert(
+            key,
+            CachedDentry {
+                ino,
+                last_access: self.access_counter,
+            },
+        );
+    }
+
+    /// 目录项被删除或改名时使对应映射失效
+    pub fn invalidate(&mut self, parent: u32, name: &[u8]) {
+        self.cache.remove(&(parent, name.to_vec()));
+    }
+
+    /// 目录被删除时使其下所有映射失效（inode 号之后可能被复用）
+    pub fn invalidate_dir(&mut self, dir: u32) {
+        self.cache.retain(|(parent, _), _| *parent != dir);
+    }
+
+    /// 清空缓存
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// LRU淘汰
+    fn evict_lru(&mut self) {
+        let lru_key = self
+            .cache
+            .iter()
+            .min_by_key(|(_, cached)| cached.last_access)
+            .map(|(key, _)| key.clone());
+
+        if let Some(key) = lru_key {
+            self.cache.remove(&key);
+        }
+    }
+
+    /// 获取缓存统计
+    pub fn stats(&self) -> DentryCacheStats {
+        DentryCacheStats {
+            total_entries: self.cache.len(),
+            max_entries: self.max_entries,
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+}
+
+impl Default for DentryCache {
+    /// 创建默认配置的缓存
+    fn default() -> Self {
+        Self::new(DENTRY_CACHE_MAX)
+    }
+}
+
+/// 名字缓存统计信息
+#[derive(Debug, Clone, Copy)]
+pub struct DentryCacheStats {
+    pub total_entries: usize,
+    pub max_entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dentry_cache_lru_and_invalidate() {
+        let mut cache = DentryCache::new(2);
+        cache.insert(2, b"a", 11);
+        cache.insert(2, b"b", 12);
+        assert_eq!(cache.lookup(2, b"a"), Some(11));
+
+        // "b" 最久未访问，插入第三项时被淘汰
+        cache.insert(11, b"c", 13);
+        assert_eq!(cache.lookup(2, b"b"), None);
+        assert_eq!(cache.lookup(11, b"c"), Some(13));
+
+        cache.invalidate(2, b"a");
+        assert_eq!(cache.lookup(2, b"a"), None);
+
+        cache.invalidate_dir(11);
+        assert_eq!(cache.stats().total_entries, 0);
+        assert_eq!(cache.stats().hits, 2);
+        assert_eq!(cache.stats().misses, 2);
+    }
+}
@@ -10,8 +10,10 @@ use crate::ext4_backend::entries::*;
 use crate::ext4_backend::ext4::*;
 use crate::ext4_backend::extents_tree::*;
 use crate::ext4_backend::file::*;
+use crate::ext4_backend::hashtree::*;
 use crate::ext4_backend::loopfile::*;
 use crate::ext4_backend::error::*;
+use crate::ext4_backend::superblock::*;
 use alloc::string::String;
 use alloc::vec::Vec;
 use log::error;
@@ -45,6 +47,25 @@ pub fn split_paren_child_and_tranlatevalid(pat: &str) -> String {
     result_s
 }
 
+/// 解析 path 中最后一个分量之前的部分，确认父目录存在且是目录，返回父目录的 (inode 号, inode)
+/// 不要求最后一个分量存在，是 mkfile/symlink 等创建操作共用的前置检查
+/// path 是根目录时返回 InvalidInput，父路径上的分量不存在返回 NotFound，不是目录返回 NotADirectory
+pub fn validate_parent<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<(u32, Ext4Inode)> {
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    if norm_path.is_empty() || norm_path == "/" {
+        return Err(Ext4Error::InvalidInput);
+    }
+    let parent = match norm_path.rfind('/') {
+        Some(0) | None => "/",
+        Some(pos) => &norm_path[..pos],
+    };
+    lookup_dir(fs, device, parent)
+}
+
 /// 路径解析，返回 (inode_num, inode)
 pub fn get_inode_with_num<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
@@ -94,7 +115,7 @@ pub fn get_inode_with_num<B: BlockDevice>(
         let mut found_inode_num: Option<u64> = None;
 
         for lbn in 0..total_blocks {
-            let phys = match resolve_inode_block( device, &mut current_inode, lbn as u32)? {
+            let phys = match resolve_inode_block(device, fs, current_ino, &mut current_inode, lbn as u32)? {
                 Some(b) => b,
                 None => continue,
             };
@@ -102,7 +123,7 @@ pub fn get_inode_with_num<B: BlockDevice>(
             let cached_block = fs.datablock_cache.get_or_load(device, phys as u64)?;
             let block_data = &cached_block.data[..block_bytes];
 
-            if let Some(entry) = classic_dir::find_entry(block_data, target) {
+            if let Some(entry) = classic_dir::find_entry(block_data, target)? {
                 found_inode_num = Some(entry.inode as u64);
                 break;
             }
@@ -113,19 +134,7 @@ pub fn get_inode_with_num<B: BlockDevice>(
             None => return Ok(None),
         };
 
-        let (inode_group_idx, _idx_in_group) = fs.inode_allocator.global_to_group(inode_num as u32);
-        let inode_table_start = fs
-            .group_descs
-            .get(inode_group_idx as usize)
-            .ok_or(BlockDevError::Corrupted)?
-            .inode_table();
-
-        let (block_num, offset, _group_idx) = fs.inodetable_cahce.calc_inode_location(
-            inode_num as u32,
-            fs.superblock.s_inodes_per_group,
-            inode_table_start,
-            BLOCK_SIZE,
-        );
+        let (block_num, offset) = fs.locate_inode(device, inode_num as u32)?;
 
         let cached_inode = fs
             .inodetable_cahce
@@ -137,8 +146,39 @@ pub fn get_inode_with_num<B: BlockDevice>(
     Ok(Some((current_ino, current_inode)))
 }
 
+/// 目录块校验和的种子（目录 inode 的校验和种子），未开启 metadata_csum 时为 None
+pub fn dir_csum_seed(fs: &Ext4FileSystem, dir_ino: u32, dir_inode: &Ext4Inode) -> Option<u32> {
+    fs.superblock.has_metadata_csum().then(|| {
+        ExtentTree::inode_csum_seed(
+            fs.superblock.metadata_csum_seed(),
+            dir_ino,
+            dir_inode.i_generation,
+        )
+    })
+}
+
+/// 新建目录块末尾为校验和预留的字节数
+pub fn dir_block_tail(fs: &Ext4FileSystem) -> usize {
+    if fs.superblock.has_metadata_csum() {
+        dir_csum::DIR_TAIL_SIZE
+    } else {
+        0
+    }
+}
+
+/// 按 checksum_policy 校验读到的目录块，种子为 None 时不校验
+pub fn check_dir_block(fs: &Ext4FileSystem, seed: Option<u32>, block: &[u8]) -> BlockDevResult<()> {
+    match seed {
+        Some(seed) => fs
+            .checksum_policy
+            .check(dir_csum::verify(seed, block), "directory block"),
+        None => Ok(()),
+    }
+}
+
 /// 在父目录的所有逻辑块中查找空闲空间并插入一个目录项；
 /// 若所有现有块都无法容纳，则自动为目录分配一个新数据块并扩展 inode 映射和大小。
+/// 已建立 htree 索引的目录按哈希插入对应叶子；单块线性目录写满时转换为 htree 目录。
 pub fn insert_dir_entry<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     device: &mut Jbd2Dev<B>,
@@ -150,7 +190,6 @@ pub fn insert_dir_entry<B: BlockDevice>(
 ) -> BlockDevResult<()> {
     let name_bytes = child_name.as_bytes();
     let name_len = core::cmp::min(name_bytes.len(), Ext4DirEntry2::MAX_NAME_LEN as usize);
-    let new_rec_len = Ext4DirEntry2::entry_len(name_len as u8) as usize;
     let new_entry = Ext4DirEntry2::new(
         child_ino,
         Ext4DirEntry2::entry_len(name_len as u8),
@@ -158,6 +197,14 @@ pub fn insert_dir_entry<B: BlockDevice>(
         &name_bytes[..name_len],
     );
 
+    // 内联目录只支持读取
+    if parent_inode.has_inline_data() {
+        return Err(BlockDevError::Unsupported);
+    }
+    if parent_inode.is_htree_indexed() {
+        return dx_insert_entry(fs, device, parent_ino_num, parent_inode, &new_entry);
+    }
+
     let total_size = parent_inode.size() as usize;
     let block_bytes = BLOCK_SIZE;
     let total_blocks = if total_size == 0 {
@@ -167,8 +214,9 @@ pub fn insert_dir_entry<B: BlockDevice>(
     };
 
     let mut inserted = false;
+    let seed = dir_csum_seed(fs, parent_ino_num, parent_inode);
 
-    let blocks = resolve_inode_block_allextend(fs, device, parent_inode)?;
+    let blocks = resolve_inode_block_allextend(fs, device, parent_ino_num, parent_inode)?;
 
     for lbn in 0..total_blocks {
         if inserted {
@@ -186,83 +234,55 @@ pub fn insert_dir_entry<B: BlockDevice>(
             }
         };
 
-        let _ = fs.datablock_cache.modify(device, phys as u64, |data| {
-            if inserted {
-                return;
-            }
-
-            let block_bytes = BLOCK_SIZE;
-
-            let mut offset = 0usize;
-            while offset + 8 <= block_bytes {
-                let inode = u32::from_le_bytes([
-                    data[offset],
-                    data[offset + 1],
-                    data[offset + 2],
-                    data[offset + 3],
-                ]);
-                let rec_len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]) as usize;
-                if rec_len < 8 {
-                    return;
-                }
-                let entry_end = offset + rec_len;
-                if entry_end > block_bytes {
-                    return;
-                }
-
-                // Free entry: directly use it if it can hold the new entry.
-                if inode == 0 {
-                    if rec_len >= new_rec_len {
-                        let mut full_entry = new_entry;
-                        full_entry.rec_len = rec_len as u16;
-                        full_entry.to_disk_bytes(&mut data[offset..offset + 8]);
-                        let nlen = full_entry.name_len as usize;
-                        data[offset + 8..offset + 8 + nlen]
-                            .copy_from_slice(&full_entry.name[..nlen]);
-                        inserted = true;
-                    }
-                    return;
-                }
-
-                // Occupied entry: try to split tail space.
-                let cur_name_len = data[offset + 6] as usize;
-                let mut ideal = 8 + cur_name_len;
-                ideal = (ideal + 3) & !3;
-                if ideal <= rec_len {
-                    let tail = rec_len - ideal;
-                    if tail >= new_rec_len {
-                        let ideal_bytes = (ideal as u16).to_le_bytes();
-                        data[offset + 4] = ideal_bytes[0];
-                        data[offset + 5] = ideal_bytes[1];
-
-                        let new_off = offset + ideal;
-                        let mut full_entry = new_entry;
-                        full_entry.rec_len = tail as u16;
-                        full_entry.to_disk_bytes(&mut data[new_off..new_off + 8]);
-                        let nlen = full_entry.name_len as usize;
-                        data[new_off + 8..new_off + 8 + nlen]
-                            .copy_from_slice(&full_entry.name[..nlen]);
-                        inserted = true;
-                        return;
-                    }
-                }
-
-                if entry_end == block_bytes {
-                    return;
-                }
-                offset = entry_end;
+        fs.datablock_cache.modify(device, phys, |data| {
+            inserted = classic_dir::insert_entry(data, &new_entry);
+            if let (true, Some(seed)) = (inserted, seed) {
+                dir_csum::refresh(seed, data);
             }
-        });
+        })?;
     }
 
     if inserted {
         return Ok(());
     }
 
-    // 所有现有逻辑块都无法容纳新目录项：为目录分配一个新数据块，并扩展 inode 映射
+    // 唯一的块已写满：开启了 dir_index 时改为 htree 目录
+    if total_blocks == 1
+        && fs
+            .superblock
+            .has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_DIR_INDEX)
+        && parent_inode.have_extend_header_and_use_extend()
+    {
+        return make_indexed_dir(fs, device, parent_ino_num, parent_inode, &new_entry);
+    }
+
+    // 所有现有逻辑块都无法容纳新目录项：为目录分配一个新数据块
+    let (_, new_block) = append_dir_block(fs, device, parent_ino_num, parent_inode)?;
+
+    // 在新分配的数据块中写入唯一的目录项，占满整个块（校验和块尾之前）
+    fs.datablock_cache.modify(device, new_block, |data| {
+        classic_dir::insert_entry(data, &new_entry);
+        if let Some(seed) = seed {
+            dir_csum::refresh(seed, data);
+        }
+    })?;
+
+    Ok(())
+}
+
+/// 为目录追加一个数据块：扩展 inode 映射、i_size 和 i_blocks 并写回 inode 表
+/// 新块内容为一个占满整块的空闲目录项（开启 metadata_csum 时另有块尾），
+/// 返回 (逻辑块号, 物理块号)
+pub fn append_dir_block<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+) -> BlockDevResult<(u32, u64)> {
     let new_block = fs.alloc_block(device)?;
 
-    // 更新 parent_inode 的块映射（extent 或直接块）和大小统计
+    // 更新 dir_inode 的块映射（extent 或直接块）和大小统计
+    let total_size = dir_inode.size() as usize;
     let block_bytes = BLOCK_SIZE;
     let old_blocks = if total_size == 0 {
         0
@@ -271,81 +291,97 @@ pub fn insert_dir_entry<B: BlockDevice>(
     };
     let new_lbn = old_blocks as u32; // 新块对应的逻辑块号
 
-    if fs.superblock.has_extents() && parent_inode.have_extend_header_and_use_extend() {
+    if fs.superblock.has_extents() && dir_inode.have_extend_header_and_use_extend() {
         // extent 目录：通过 ExtentTree 追加一个长度为 1 的 extent
         let new_ext = Ext4Extent::new(new_lbn, new_block, 1);
-        let mut tree = ExtentTree::new(parent_inode);
+        let mut tree = ExtentTree::new(dir_inode).with_checksum(fs, dir_ino);
         tree.insert_extent(fs, new_ext, device)?;
     } else {
         // 传统直接块模式：仅支持追加到前 12 个直接块
         if old_blocks >= 12 {
             return Err(BlockDevError::Unsupported);
         }
-        parent_inode.i_block[old_blocks] = new_block as u32;
+        dir_inode.i_block[old_blocks] = new_block as u32;
     }
 
-    // 更新 parent_inode 的 i_size / i_blocks，并写回 inode 表
+    // 更新 dir_inode 的 i_size / i_blocks，并写回 inode 表
     let new_size = total_size + block_bytes;
-    parent_inode.i_size_lo = new_size as u32;
-    parent_inode.i_size_high = ((new_size as u64) >> 32) as u32;
+    dir_inode.i_size_lo = new_size as u32;
+    dir_inode.i_size_high = ((new_size as u64) >> 32) as u32;
     //fix:extend元数据也会占block，不能仅仅靠现有blocks_count计算，需要考虑extent树的开销
-    let cur = parent_inode.blocks_count();
+    let cur = dir_inode.blocks_count();
     let add_sectors = (BLOCK_SIZE as u64 / 512) as u64;
     let newv = cur.saturating_add(add_sectors);
-    parent_inode.i_blocks_lo = (newv & 0xffff_ffff) as u32;
-    parent_inode.l_i_blocks_high = ((newv >> 32) & 0xffff) as u16;
+    dir_inode.i_blocks_lo = (newv & 0xffff_ffff) as u32;
+    dir_inode.l_i_blocks_high = ((newv >> 32) & 0xffff) as u16;
 
-    let (p_group, _pidx) = fs.inode_allocator.global_to_group(parent_ino_num);
-    let inode_table_start = match fs.group_descs.get(p_group as usize) {
-        Some(desc) => desc.inode_table(),
-        None => return Err(BlockDevError::Corrupted),
-    };
-    let (p_block_num, p_offset, _pg) = fs.inodetable_cahce.calc_inode_location(
-        parent_ino_num,
-        fs.superblock.s_inodes_per_group,
-        inode_table_start,
-        BLOCK_SIZE,
-    );
+    let (p_block_num, p_offset) = fs.locate_inode(device, dir_ino)?;
 
     fs.inodetable_cahce.modify(
         device,
-        parent_ino_num as u64,
+        dir_ino as u64,
         p_block_num,
         p_offset,
         |inode| {
-            inode.i_size_lo = parent_inode.i_size_lo;
-            inode.i_size_high = parent_inode.i_size_high;
-            inode.i_blocks_lo = parent_inode.i_blocks_lo;
-            inode.l_i_blocks_high = parent_inode.l_i_blocks_high;
-            inode.i_flags = parent_inode.i_flags;
-            inode.i_block = parent_inode.i_block;
+            inode.i_size_lo = dir_inode.i_size_lo;
+            inode.i_size_high = dir_inode.i_size_high;
+            inode.i_blocks_lo = dir_inode.i_blocks_lo;
+            inode.l_i_blocks_high = dir_inode.l_i_blocks_high;
+            inode.i_flags = dir_inode.i_flags;
+            inode.i_block = dir_inode.i_block;
         },
     )?;
 
-    // 在新分配的数据块中写入唯一的目录项，占满整个块
-    fs.datablock_cache
-        .modify(device, new_block, |data| {
-            for b in data.iter_mut() {
-                *b = 0;
-            }
-            let mut full_entry = new_entry;
-            full_entry.rec_len = BLOCK_SIZE as u16;
-            full_entry.to_disk_bytes(&mut data[0..8]);
-            let nlen = full_entry.name_len as usize;
-            data[8..8 + nlen].copy_from_slice(&full_entry.name[..nlen]);
-        })?;
+    let tail = dir_block_tail(fs);
+    let seed = dir_csum_seed(fs, dir_ino, dir_inode);
+    fs.datablock_cache.modify(device, new_block, |data| {
+        data.fill(0);
+        write_u16_le((BLOCK_SIZE - tail) as u16, &mut data[4..6]);
+        if let Some(seed) = seed {
+            dir_csum::init_tail(data);
+            dir_csum::refresh(seed, data);
+        }
+    })?;
 
-    Ok(())
+    Ok((new_lbn, new_block))
 }
 
 /// 默认开启hashtree查找
-/// 通用文件创建：支持多级路径、递归创建父目录
+/// 创建单级目录，父目录必须已存在（逐级创建请用 mkdir_p）；目标已是目录时直接返回
+/// 父目录缺失时返回 NotFound，路径分量或目标本身已存在但不是目录时返回 NotADirectory
 pub fn mkdir<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     path: &str,
-) -> Option<Ext4Inode> {
-    mkdir_with_ino(device, fs, path).map(|(_, inode)| inode)
+) -> Ext4Result<Ext4Inode> {
+    try_mkdir_with_ino(device, fs, path).map(|(_, inode)| inode)
+}
+
+/// 逐级创建路径上缺失的目录（mkdir -p），整条路径已是目录时直接返回
+/// 已有分量不是目录时返回 NotADirectory
+pub fn mkdir_p<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<Ext4Inode> {
+    let mut cur_path = String::new();
+    let mut inode = fs.get_root(device)?;
+    for (index, component) in path.split('/').filter(|s| !s.is_empty()).enumerate() {
+        cur_path.push('/');
+        cur_path.push_str(component);
+        inode = match get_file_inode(fs, device, &cur_path)? {
+            Some((_, found)) if found.is_dir() => found,
+            Some(_) => {
+                return Err(Ext4Error::NotADirectory {
+                    index,
+                    component: component.to_string(),
+                });
+            }
+            // 父目录此时必然存在，只创建这一级
+            None => try_mkdir_with_ino(device, fs, &cur_path)?.1,
+        };
+    }
+    Ok(inode)
 }
 
 pub fn mkdir_with_ino<B: BlockDevice>(
@@ -353,26 +389,54 @@ pub fn mkdir_with_ino<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     path: &str,
 ) -> Option<(u32, Ext4Inode)> {
-    // 先对传入路径做规范化（去掉重复的 '/' 等）
-    let norm_path = split_paren_child_and_tranlatevalid(path);
+    try_mkdir_with_ino(device, fs, path).ok()
+}
 
-    // 若目标已存在，直接返回
-    if let Ok(Some(inode)) = get_file_inode(fs, device, &norm_path) {
-        return Some(inode);
+/// 创建单级目录并返回 (inode 号, inode)，目标已是目录时直接返回已有的
+/// 父路径上的分量不存在返回 NotFound，父路径或目标已存在但不是目录时返回 NotADirectory
+pub fn try_mkdir_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<(u32, Ext4Inode)> {
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    // 根目录可能尚未初始化，直接交给创建流程
+    if !norm_path.is_empty() && norm_path != "/" {
+        match lookup_dir(fs, device, &norm_path) {
+            Ok(found) => return Ok(found),
+            Err(Ext4Error::NotFound { .. }) => {}
+            Err(e) => return Err(e),
+        }
+        validate_parent(device, fs, &norm_path)?;
     }
+    Ok(create_dir_with_ino(device, fs, path)?)
+}
+
+/// 在已确认存在的父目录下创建目录（路径检查由 try_mkdir_with_ino 完成）
+fn create_dir_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> BlockDevResult<(u32, Ext4Inode)> {
+    // 整个创建过程的元数据写归入同一个日志事务，返回时提交
+    let mut handle = device.transaction();
+    let device = &mut *handle;
+
+    // 先对传入路径做规范化（去掉重复的 '/' 等）
+    let norm_path = split_paren_child_and_tranlatevalid(path);
 
     // 根目录和空路径的特殊情况
     if norm_path.is_empty() || norm_path == "/" {
         debug!("Creating root directory");
         if let Err(e) = create_root_directory_entry(fs, device) {
             error!("mkdir create_root_directory_entry failed path={} err={:?} ({})", path, e, e);
-            return None;
+            return Err(e);
         }
         return match fs.get_root(device) {
-            Ok(inode) => Some((fs.root_inode, inode)),
+            Ok(inode) => Ok((fs.root_inode, inode)),
             Err(e) => {
                 error!("mkdir get_root failed path={} err={:?} ({})", path, e, e);
-                None
+                Err(e)
             }
         };
     }
@@ -382,34 +446,14 @@ pub fn mkdir_with_ino<B: BlockDevice>(
 
     if parts.is_empty() {
         return match fs.get_root(device) {
-            Ok(inode) => Some((fs.root_inode, inode)),
+            Ok(inode) => Ok((fs.root_inode, inode)),
             Err(e) => {
                 error!("mkdir get_root failed(empty parts) path={} err={:?} ({})", path, e, e);
-                None
+                Err(e)
             }
         };
     }
 
-    // 从头逐一判断父路径是否存在，不存在则递归创建
-    // 只针对中间父目录，最后一个组件留给当前 mkd 创建
-    let mut cur_path = String::from("");
-    for i in 0..(parts.len().saturating_sub(1)) {
-        if cur_path.is_empty() {
-            cur_path.push('/');
-            cur_path.push_str(parts[i]);
-        } else {
-            cur_path.push('/');
-            cur_path.push_str(parts[i]);
-        }
-
-        if let Ok(None) = get_file_inode(fs, device, &cur_path) {
-            if mkdir(device, fs, &cur_path).is_none() {
-                error!("mkdir recursive parent create failed path={} parent={}", path, cur_path);
-                return None;
-            }
-        }
-    }
-
     // 计算 parent 与 child
     let child = parts.last().unwrap().to_string();
     let parent = if parts.len() == 1 {
@@ -425,11 +469,11 @@ pub fn mkdir_with_ino<B: BlockDevice>(
 
     // 再次获取父目录 inode 及其 inode 号
     let (parent_ino_num, mut parent_inode) =
-        match get_inode_with_num(fs, device, &parent).ok().flatten() {
+        match get_inode_with_num(fs, device, &parent)? {
             Some((n, ino)) => (n, ino),
             None => {
                 error!("mkdir get parent inode failed path={} parent={} child={}", path, parent, child);
-                return None;
+                return Err(BlockDevError::InvalidInput);
             }
         };
 
@@ -438,13 +482,13 @@ pub fn mkdir_with_ino<B: BlockDevice>(
         debug!("Creating root directory");
         if let Err(e) = create_root_directory_entry(fs, device) {
             error!("mkdir create_root_directory_entry failed path={} err={:?} ({})", path, e, e);
-            return None;
+            return Err(e);
         }
         return match fs.get_root(device) {
-            Ok(inode) => Some((fs.root_inode, inode)),
+            Ok(inode) => Ok((fs.root_inode, inode)),
             Err(e) => {
                 error!("mkdir get_root failed path={} err={:?} ({})", path, e, e);
-                None
+                Err(e)
             }
         };
     }
@@ -454,36 +498,38 @@ pub fn mkdir_with_ino<B: BlockDevice>(
         debug!("Creating /lost+found directory");
         if let Err(e) = create_lost_found_directory(fs, device) {
             error!("mkdir create_lost_found_directory failed path={} err={:?} ({})", path, e, e);
-            return None;
+            return Err(e);
         }
-        return match get_inode_with_num(fs, device, "/lost+found").ok().flatten() {
-            Some((ino, inode)) => Some((ino, inode)),
+        return match get_inode_with_num(fs, device, "/lost+found")? {
+            Some((ino, inode)) => Ok((ino, inode)),
             None => {
                 error!("mkdir post-create lost+found lookup failed path={}", path);
-                None
+                Err(BlockDevError::Corrupted)
             }
         };
     }
 
     // 为新目录分配 inode（内部自动选择块组）
-    let new_dir_ino = match fs.alloc_inode(device) {
+    let new_dir_ino = match fs.alloc_inode_near(device, parent_ino_num, true) {
         Ok(ino) => ino,
         Err(e) => {
             error!("mkdir alloc_inode failed path={} parent={} child={} err={:?} ({})", path, parent, child, e, e);
-            return None;
+            return Err(e);
         }
     };
 
-    // 为新目录分配数据块（内部自动选择块组）
-    let data_block = match fs.alloc_block(device) {
-        Ok(b) => b,
+    // 为新目录分配数据块，尽量与目录 inode 位于同一块组
+    let goal = fs.data_goal_for_inode(new_dir_ino);
+    let data_block = match fs.alloc_blocks_near(device, goal, 1) {
+        Ok((b, _)) => b,
         Err(e) => {
             error!("mkdir alloc_block failed path={} ino={} err={:?} ({})", path, new_dir_ino, e, e);
-            return None;
+            return Err(e);
         }
     };
 
-    // 初始化新目录的数据块：写 '.' 和 '..'
+    // 初始化新目录的数据块：写 '.' 和 '..'（开启 metadata_csum 时预留块尾）
+    let tail = dir_block_tail(fs);
     {
         let cached = fs.datablock_cache.create_new(data_block);
         let data = &mut cached.data;
@@ -498,7 +544,7 @@ pub fn mkdir_with_ino<B: BlockDevice>(
         );
 
         let dotdot_name = b"..";
-        let dotdot_rec_len = (BLOCK_SIZE as u16).saturating_sub(dot_rec_len);
+        let dotdot_rec_len = ((BLOCK_SIZE - tail) as u16).saturating_sub(dot_rec_len);
         let dotdot = Ext4DirEntry2::new(
             parent_ino_num,
             dotdot_rec_len,
@@ -518,17 +564,26 @@ pub fn mkdir_with_ino<B: BlockDevice>(
             let name_len = dotdot.name_len as usize;
             data[offset + 8..offset + 8 + name_len].copy_from_slice(&dotdot.name[..name_len]);
         }
+        if tail != 0 {
+            dir_csum::init_tail(data);
+        }
     }
 
     // 写新目录 inode（单块目录，按特性选择 extent 或直接块）
     let (group_idx, _idx) = fs.inode_allocator.global_to_group(new_dir_ino);
     //仅仅的视图，修改过后的
 
-    let mut inode_pre = fs
-        .get_inode_by_num(device, new_dir_ino)
-        .expect("Can't getinode");
-    build_file_block_mapping(fs, &mut inode_pre, &[data_block], device);
-    if fs
+    let mut inode_pre = fs.get_inode_by_num(device, new_dir_ino)?;
+    build_file_block_mapping(fs, &mut inode_pre, new_dir_ino, &[data_block], device);
+    // 新目录必须有且仅有一个存放 "." 和 ".." 的数据块，零块目录会让 read_dir/查找失败
+    if !matches!(
+        resolve_inode_block(device, fs, new_dir_ino, &mut inode_pre, 0),
+        Ok(Some(b)) if b == data_block
+    ) {
+        error!("mkdir block mapping missing path={path} ino={new_dir_ino} block={data_block}");
+        return Err(BlockDevError::Corrupted);
+    }
+    if let Err(e) = fs
         .modify_inode(device, new_dir_ino, |inode| {
             inode.i_block = inode_pre.i_block;
             inode.i_mode = Ext4Inode::S_IFDIR | 0o755;
@@ -542,28 +597,28 @@ pub fn mkdir_with_ino<B: BlockDevice>(
 
             //由于借用冲突，暂时先把mapping移步到外面
         })
-        .is_err()
     {
         error!("mkdir modify_inode failed path={} ino={}", path, new_dir_ino);
-        return None;
+        return Err(e);
+    }
+    if let Some(seed) = dir_csum_seed(fs, new_dir_ino, &inode_pre)
+        && let Err(e) = fs
+            .datablock_cache
+            .modify(device, data_block, |data| dir_csum::refresh(seed, data))
+    {
+        error!("mkdir checksum update failed path={path} ino={new_dir_ino}");
+        return Err(e);
     }
 
     //更新父目录的i_links_count+1
     {
-        let (p_group, _pidx) = fs.inode_allocator.global_to_group(parent_ino_num);
-        let p_inode_table_start = match fs.group_descs.get(p_group as usize) {
-            Some(desc) => desc.inode_table(),
-            None => {
-                error!("mkdir parent group desc missing path={} parent_ino={} group={}", path, parent_ino_num, p_group);
-                return None;
+        let (p_block_num, p_offset) = match fs.locate_inode(device, parent_ino_num) {
+            Ok(loc) => loc,
+            Err(e) => {
+                error!("mkdir parent inode location invalid path={} parent_ino={} err={:?}", path, parent_ino_num, e);
+                return Err(e);
             }
         };
-        let (p_block_num, p_offset, _pg) = fs.inodetable_cahce.calc_inode_location(
-            parent_ino_num,
-            fs.superblock.s_inodes_per_group,
-            p_inode_table_start,
-            BLOCK_SIZE,
-        );
 
         let _ = fs.inodetable_cahce.modify(
             device,
@@ -584,7 +639,7 @@ pub fn mkdir_with_ino<B: BlockDevice>(
     }
 
     // 在父目录的数据块中插入新目录项（线性目录，多块遍历，必要时自动扩展目录块）
-    if insert_dir_entry(
+    if let Err(e) = insert_dir_entry(
         fs,
         device,
         parent_ino_num,
@@ -592,9 +647,7 @@ pub fn mkdir_with_ino<B: BlockDevice>(
         new_dir_ino,
         &child,
         Ext4DirEntry2::EXT4_FT_DIR,
-    )
-    .is_err()
-    {
+    ) {
         error!(
             "mkdir insert_dir_entry failed path={} parent_ino={} child={} ino={}",
             path,
@@ -602,11 +655,11 @@ pub fn mkdir_with_ino<B: BlockDevice>(
             child,
             new_dir_ino
         );
-        return None;
+        return Err(e);
     }
 
     match fs.get_inode_by_num(device, new_dir_ino) {
-        Ok(inode) => Some((new_dir_ino, inode)),
+        Ok(inode) => Ok((new_dir_ino, inode)),
         Err(e) => {
             error!(
                 "mkdir get_inode_by_num failed path={} ino={} err={:?} ({})",
@@ -615,7 +668,7 @@ pub fn mkdir_with_ino<B: BlockDevice>(
                 e,
                 e
             );
-            None
+            Err(e)
         }
     }
 }
@@ -676,7 +729,7 @@ pub fn create_root_directory_entry<B: BlockDevice>(
     let mut inode_pre = fs
         .get_inode_by_num(block_dev, root_inode_num)
         .expect("Can't getinode");
-    build_file_block_mapping(fs, &mut inode_pre, &[data_block], block_dev);
+    build_file_block_mapping(fs, &mut inode_pre, root_inode_num, &[data_block], block_dev);
 
     fs.modify_inode(block_dev, fs.root_inode, |inode| {
         inode.i_flags = inode_pre.i_flags;
@@ -762,7 +815,7 @@ pub fn create_lost_found_directory<B: BlockDevice>(
     let mut inode_pre = fs
         .get_inode_by_num(block_dev, lost_ino)
         .expect("Can't getinode");
-    build_file_block_mapping(fs, &mut inode_pre, &[data_block], block_dev);
+    build_file_block_mapping(fs, &mut inode_pre, lost_ino, &[data_block], block_dev);
     debug!(
         "When create lost+found inode iblock,:{:?} ,data_block:{:?}",
         inode_pre.i_block, data_block
@@ -788,7 +841,7 @@ pub fn create_lost_found_directory<B: BlockDevice>(
 
     //这里也需要根据extend来解析
     let mut root_inode = fs.get_root(block_dev)?;
-    let root_block = resolve_inode_block( block_dev, &mut root_inode, 0)?
+    let root_block = resolve_inode_block(block_dev, fs, fs.root_inode, &mut root_inode, 0)?
         .expect("lost+found logical_block can't map to physical blcok!");
 
     if root_block == 0 {
@@ -844,16 +897,7 @@ pub fn create_lost_found_directory<B: BlockDevice>(
         })?;
 
     //  更新根 inode 的链接计数（多了一个子目录）
-    let inode_table_start = match fs.group_descs.first() {
-        Some(desc) => desc.inode_table(),
-        None => return Err(BlockDevError::Corrupted),
-    };
-    let (block_num, offset, _group_idx) = fs.inodetable_cahce.calc_inode_location(
-        fs.root_inode,
-        fs.superblock.s_inodes_per_group,
-        inode_table_start,
-        BLOCK_SIZE,
-    );
+    let (block_num, offset) = fs.locate_inode(block_dev, fs.root_inode)?;
 
     fs.inodetable_cahce.modify(
         block_dev,
@@ -874,3 +918,487 @@ pub fn create_lost_found_directory<B: BlockDevice>(
 
     Ok(())
 }
+
+/// read_dir 产出的目录项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub inode: u32,
+    pub file_type: u8,
+}
+
+/// 目录惰性迭代器
+/// 按 extent 顺序逐块读取目录数据，每次只持有当前一个块，
+/// 跳过 inode 为 0 的空闲槽位（包括 htree 索引块中的伪条目）。
+/// 内联目录在创建时就转换成一个线性目录块，不再读盘
+pub struct DirIter<'a, B: BlockDevice> {
+    block_dev: &'a mut Jbd2Dev<B>,
+    fs: &'a mut Ext4FileSystem,
+    extents: Vec<Ext4Extent>,
+    ext_idx: usize,   // 当前 extent 下标
+    blk_in_ext: u32,  // 当前 extent 内的下一个块偏移
+    block: Vec<u8>,   // 当前目录块内容
+    offset: usize,    // 当前块内偏移
+    done: bool,
+}
+
+impl<'a, B: BlockDevice> DirIter<'a, B> {
+    /// 加载下一个目录块，没有更多块时返回 false
+    fn load_next_block(&mut self) -> BlockDevResult<bool> {
+        while let Some(ext) = self.extents.get(self.ext_idx) {
+            let mut len = ext.ee_len as u32;
+            // 最高位表示 uninitialized 标志，长度使用低 15 位
+            if (len & 0x8000) != 0 {
+                len &= 0x7FFF;
+            }
+            if self.blk_in_ext >= len {
+                self.ext_idx += 1;
+                self.blk_in_ext = 0;
+                continue;
+            }
+            let base = ((ext.ee_start_hi as u64) << 32) | ext.ee_start_lo as u64;
+            let phys = base + self.blk_in_ext as u64;
+            self.blk_in_ext += 1;
+            let cached = self.fs.datablock_cache.get_or_load(self.block_dev, phys)?;
+            self.block.clear();
+            self.block.extend_from_slice(&cached.data[..BLOCK_SIZE]);
+            self.offset = 0;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+impl<'a, B: BlockDevice> Iterator for DirIter<'a, B> {
+    type Item = Ext4Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if self.offset + 8 > self.block.len() {
+                match self.load_next_block() {
+                    Ok(true) => continue,
+                    Ok(false) => {
+                        self.done = true;
+                        return None;
+                    }
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e.into()));
+                    }
+                }
+            }
+
+            // rec_len 决定下一条目的位置，最后一个条目延伸到块尾
+            let rest = &self.block[self.offset..];
+            let rec_len = read_u16_le(&rest[4..6]) as usize;
+            if !rec_len_valid(rec_len, rest.len()) {
+                error!("Corrupted dir entry rec_len {} at offset {}", rec_len, self.offset);
+                self.done = true;
+                return Some(Err(Ext4Error::Corrupt));
+            }
+            let parsed = Ext4DirEntryInfo::parse_from_bytes(&rest[..rec_len]).map(|e| DirEntry {
+                name: String::from_utf8_lossy(e.name).into_owned(),
+                inode: e.inode,
+                file_type: e.file_type,
+            });
+            self.offset += rec_len;
+            if let Some(entry) = parsed {
+                return Some(Ok(entry));
+            }
+        }
+        None
+    }
+}
+
+/// 查找 parent 目录中名为 name 的目录项在磁盘上的位置：(物理块号, 块内字节偏移)
+/// 索引目录经哈希树定位到叶子块，其余目录逐块线性扫描；
+/// 名字不存在，或目录项内联在 inode 中（没有所在的数据块）时返回 None；
+/// parent 不存在返回 NotFound，不是目录返回 NotADirectory
+pub fn find_entry_location<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    parent: &str,
+    name: &str,
+) -> Ext4Result<Option<(u64, usize)>> {
+    let norm_path = split_paren_child_and_tranlatevalid(parent);
+    let (dir_ino, inode) = lookup_dir(fs, block_dev, &norm_path)?;
+    if inode.has_inline_data() {
+        return Ok(None);
+    }
+    match lookup_directory_entry(fs, block_dev, dir_ino, &inode, name.as_bytes()) {
+        Ok(found) => Ok(Some((found.block_num, found.offset))),
+        Err(HashTreeError::EntryNotFound) => Ok(None),
+        Err(HashTreeError::ChecksumError) => Err(Ext4Error::ChecksumError),
+        Err(e) => {
+            error!("find_entry_location {parent}/{name} failed: {e}");
+            Err(Ext4Error::Corrupt)
+        }
+    }
+}
+
+///列出目录内容，返回惰性迭代器（包含 "." 和 ".."）
+/// 路径分量不存在时返回 NotFound，不是目录时返回 NotADirectory
+pub fn read_dir<'a, B: BlockDevice>(
+    block_dev: &'a mut Jbd2Dev<B>,
+    fs: &'a mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<DirIter<'a, B>> {
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    let (ino, mut inode) = lookup_dir(fs, block_dev, &norm_path)?;
+    if inode.has_inline_data() {
+        let raw = inline_dir::raw_bytes(&inode.i_block);
+        return Ok(DirIter {
+            block_dev,
+            fs,
+            extents: Vec::new(),
+            ext_idx: 0,
+            blk_in_ext: 0,
+            block: inline_dir::to_classic_block(ino, &raw),
+            offset: 0,
+            done: false,
+        });
+    }
+    if !inode.have_extend_header_and_use_extend() {
+        error!("Only Support Extend mode!");
+        return Err(BlockDevError::Unsupported.into());
+    }
+    let extents = collect_inode_extents(block_dev, fs, ino, &mut inode)?;
+    // 目录至少包含 "." 和 ".."，没有任何数据块说明 inode 已损坏
+    if extents.is_empty() {
+        error!("Directory inode {ino} has no data blocks");
+        return Err(Ext4Error::Corrupt);
+    }
+    Ok(DirIter {
+        block_dev,
+        fs,
+        extents,
+        ext_idx: 0,
+        blk_in_ext: 0,
+        block: Vec::new(),
+        offset: 0,
+        done: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::test_support::*;
+    use alloc::collections::BTreeSet;
+    use alloc::format;
+
+    #[test]
+    fn read_dir_lists_every_entry_once() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+        // 长文件名让目录跨越多个数据块
+        let names: Vec<String> = (0..50)
+            .map(|i| format!("entry_{i:02}_{}", "x".repeat(90)))
+            .collect();
+        for name in &names {
+            mkfile(&mut dev, &mut fs, &format!("/d/{name}"), None, None).unwrap();
+        }
+        let (ino, mut d_inode) = get_file_inode(&mut fs, &mut dev, "/d").unwrap().unwrap();
+        assert!(d_inode.size() as usize > BLOCK_SIZE);
+        assert!(resolve_inode_block_allextend(&mut fs, &mut dev, ino, &mut d_inode).unwrap().len() > 1);
+        unlink(&mut dev, &mut fs, &format!("/d/{}", names[0])).unwrap();
+
+        let mut seen = BTreeSet::new();
+        let mut dots = 0;
+        for entry in read_dir(&mut dev, &mut fs, "/d").unwrap() {
+            let entry = entry.unwrap();
+            assert_ne!(entry.inode, 0);
+            if entry.name == "." || entry.name == ".." {
+                dots += 1;
+                continue;
+            }
+            assert!(seen.insert(entry.name), "duplicate entry");
+        }
+        assert_eq!(dots, 2);
+        let expected: BTreeSet<String> = names[1..].iter().cloned().collect();
+        assert_eq!(seen, expected);
+
+        assert!(matches!(
+            read_dir(&mut dev, &mut fs, &format!("/d/{}", names[1])),
+            Err(Ext4Error::NotADirectory { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn inline_dir_entries_are_listed_and_resolved() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/small").unwrap();
+        mkfile(&mut dev, &mut fs, "/f1", None, None).unwrap();
+        mkfile(&mut dev, &mut fs, "/f2", None, None).unwrap();
+        let (small_ino, _) = get_file_inode(&mut fs, &mut dev, "/small").unwrap().unwrap();
+        let (f1_ino, _) = get_file_inode(&mut fs, &mut dev, "/f1").unwrap().unwrap();
+        let (f2_ino, _) = get_file_inode(&mut fs, &mut dev, "/f2").unwrap().unwrap();
+
+        // 手工构造内联目录：4 字节父目录号 + "a"(12 字节) + "bb"(占满剩余 44 字节)
+        let mut raw = [0u8; 60];
+        raw[0..4].copy_from_slice(&2u32.to_le_bytes());
+        raw[4..8].copy_from_slice(&f1_ino.to_le_bytes());
+        raw[8..10].copy_from_slice(&12u16.to_le_bytes());
+        raw[10] = 1;
+        raw[11] = Ext4DirEntry2::EXT4_FT_REG_FILE;
+        raw[12] = b'a';
+        raw[16..20].copy_from_slice(&f2_ino.to_le_bytes());
+        raw[20..22].copy_from_slice(&44u16.to_le_bytes());
+        raw[22] = 2;
+        raw[23] = Ext4DirEntry2::EXT4_FT_REG_FILE;
+        raw[24..26].copy_from_slice(b"bb");
+        fs.modify_inode(&mut dev, small_ino, |inode| {
+            inode.i_flags = (inode.i_flags | Ext4Inode::EXT4_INLINE_DATA_FL)
+                & !Ext4Inode::EXT4_EXTENTS_FL;
+            for (i, word) in raw.chunks_exact(4).enumerate() {
+                inode.i_block[i] = u32::from_le_bytes(word.try_into().unwrap());
+            }
+            inode.i_size_lo = 60;
+        })
+        .unwrap();
+        fs.dentry_cache.clear();
+
+        let listed: Vec<(String, u32)> = read_dir(&mut dev, &mut fs, "/small")
+            .unwrap()
+            .map(|e| e.map(|e| (e.name, e.inode)).unwrap())
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                (String::from("."), small_ino),
+                (String::from(".."), 2),
+                (String::from("a"), f1_ino),
+                (String::from("bb"), f2_ino),
+            ]
+        );
+
+        let (ino, _) = get_file_inode(&mut fs, &mut dev, "/small/bb").unwrap().unwrap();
+        assert_eq!(ino, f2_ino);
+        assert!(get_file_inode(&mut fs, &mut dev, "/small/c").unwrap().is_none());
+    }
+
+    #[test]
+    fn fresh_dir_lists_dot_entries_from_one_block() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/a").unwrap();
+        let (ino, mut inode) = mkdir_with_ino(&mut dev, &mut fs, "/a/fresh").unwrap();
+        let (parent_ino, _) = get_file_inode(&mut fs, &mut dev, "/a").unwrap().unwrap();
+
+        let listed: Vec<(String, u32)> = read_dir(&mut dev, &mut fs, "/a/fresh")
+            .unwrap()
+            .map(|e| e.map(|e| (e.name, e.inode)).unwrap())
+            .collect();
+        assert_eq!(
+            listed,
+            [(String::from("."), ino), (String::from(".."), parent_ino)]
+        );
+        assert!(!inode.has_inline_data());
+        assert_eq!(inode.size() as usize, BLOCK_SIZE);
+        assert_eq!(
+            resolve_inode_block_allextend(&mut fs, &mut dev, ino, &mut inode).unwrap().len(),
+            1
+        );
+
+        // 数据块映射被清空的目录视为损坏，而不是当作空目录
+        fs.modify_inode(&mut dev, ino, |inode| {
+            // 保留 extent 头魔数，把 eh_entries 清零
+            inode.i_block[0] &= 0xFFFF;
+            inode.i_block[3..].fill(0);
+        })
+        .unwrap();
+        assert!(matches!(
+            read_dir(&mut dev, &mut fs, "/a/fresh"),
+            Err(Ext4Error::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn bad_rec_len_is_reported_as_corrupted_not_missing() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+        mkfile(&mut dev, &mut fs, "/d/a", None, None).unwrap();
+        mkfile(&mut dev, &mut fs, "/d/b", None, None).unwrap();
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/d").unwrap().unwrap();
+        let phys = resolve_inode_block(&mut dev, &fs, ino, &mut inode, 0).unwrap().unwrap();
+        // ".." 的 rec_len 改成未对齐的 13，其后的 "a"、"b" 无法再按 rec_len 找到
+        fs.datablock_cache
+            .modify(&mut dev, phys as u64, |data| data[16..18].copy_from_slice(&13u16.to_le_bytes()))
+            .unwrap();
+        fs.dentry_cache.clear();
+
+        let listed: Vec<Ext4Result<DirEntry>> = read_dir(&mut dev, &mut fs, "/d").unwrap().collect();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].as_ref().unwrap().name, ".");
+        assert_eq!(listed[1], Err(Ext4Error::Corrupt));
+
+        assert_eq!(get_file_inode(&mut fs, &mut dev, "/d/b").err(), Some(BlockDevError::Corrupted));
+        assert_eq!(mv(&mut fs, &mut dev, "/d/b", "/c").err(), Some(Ext4Error::Corrupt));
+        assert_eq!(rmdir(&mut dev, &mut fs, "/d").err(), Some(Ext4Error::Corrupt));
+        assert_eq!(remove_all(&mut dev, &mut fs, "/d").err(), Some(Ext4Error::Corrupt));
+        assert!(get_file_inode(&mut fs, &mut dev, "/d").unwrap().is_some());
+    }
+
+    #[test]
+    fn entry_location_decodes_to_the_same_entry() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+        // 长文件名让目标条目落在第二个目录块的中间
+        let names: Vec<String> = (0..60)
+            .map(|i| format!("n{i:02}_{}", "y".repeat(100)))
+            .collect();
+        for name in &names {
+            mkfile(&mut dev, &mut fs, &format!("/d/{name}"), None, None).unwrap();
+        }
+        let target = &names[45];
+        let (ino, _) = get_file_inode(&mut fs, &mut dev, &format!("/d/{target}"))
+            .unwrap()
+            .unwrap();
+        let (block, offset) = find_entry_location(&mut dev, &mut fs, "/d", target)
+            .unwrap()
+            .unwrap();
+        assert_ne!(offset, 0);
+
+        let data = fs.datablock_cache.get_or_load(&mut dev, block).unwrap().data.clone();
+        let entry = Ext4DirEntryInfo::parse_from_bytes(&data[offset..]).unwrap();
+        assert_eq!(entry.name, target.as_bytes());
+        assert_eq!(entry.inode, ino);
+
+        assert!(find_entry_location(&mut dev, &mut fs, "/d", "missing").unwrap().is_none());
+        assert!(matches!(
+            find_entry_location(&mut dev, &mut fs, &format!("/d/{target}"), "x"),
+            Err(Ext4Error::NotADirectory { index: 1, .. })
+        ));
+        assert!(matches!(
+            find_entry_location(&mut dev, &mut fs, "/nope", "x"),
+            Err(Ext4Error::NotFound { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn validate_parent_resolves_all_but_last_component() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let (dir_ino, _) = mkdir_with_ino(&mut dev, &mut fs, "/d").unwrap();
+        mkfile(&mut dev, &mut fs, "/d/f", Some(b"x"), None).unwrap();
+
+        let (ino, inode) = validate_parent(&mut dev, &mut fs, "/d/not_yet").unwrap();
+        assert_eq!(ino, dir_ino);
+        assert!(inode.is_dir());
+        assert_eq!(validate_parent(&mut dev, &mut fs, "/top").unwrap().0, fs.root_inode);
+        assert_eq!(
+            validate_parent(&mut dev, &mut fs, "/d/f/child").err(),
+            Some(Ext4Error::NotADirectory { index: 1, component: "f".to_string() })
+        );
+        assert_eq!(
+            validate_parent(&mut dev, &mut fs, "/missing/child").err(),
+            Some(Ext4Error::NotFound { index: 0, component: "missing".to_string() })
+        );
+        assert_eq!(
+            symlink(&mut dev, &mut fs, "/d", "/d/f/link"),
+            Err(Ext4Error::NotADirectory { index: 1, component: "f".to_string() })
+        );
+    }
+
+    #[test]
+    fn dir_block_checksum_catches_flipped_byte() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        fs.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+        mkdir(&mut dev, &mut fs, "/big").unwrap();
+        for name in ["a", "b", "c"] {
+            mkfile(&mut dev, &mut fs, &format!("/d/{name}"), None, None).unwrap();
+        }
+        for i in 0..400 {
+            mkfile(&mut dev, &mut fs, &format!("/big/entry_{i:04}"), None, None).unwrap();
+        }
+
+        // htree 的 dx_root 和各叶子块都带有正确的校验和
+        let (big_ino, mut big) = get_file_inode(&mut fs, &mut dev, "/big").unwrap().unwrap();
+        assert!(big.is_htree_indexed());
+        let seed = dir_csum_seed(&fs, big_ino, &big).unwrap();
+        for phys in resolve_inode_block_allextend(&mut fs, &mut dev, big_ino, &mut big).unwrap().into_values() {
+            let block = &fs.datablock_cache.get_or_load(&mut dev, phys).unwrap().data;
+            assert!(dir_csum::verify(seed, block));
+        }
+        assert!(find_entry_location(&mut dev, &mut fs, "/big", "entry_0123").unwrap().is_some());
+
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/d").unwrap().unwrap();
+        let seed = dir_csum_seed(&fs, ino, &inode).unwrap();
+        let phys = resolve_inode_block(&mut dev, &fs, ino, &mut inode, 0).unwrap().unwrap();
+        let block = fs.datablock_cache.get_or_load(&mut dev, phys).unwrap().data.clone();
+        assert!(dir_csum::has_tail(&block));
+        assert!(dir_csum::verify(seed, &block));
+
+        // 翻转 "b" 的 inode 号中的一个字节
+        let (_, off) = find_entry_location(&mut dev, &mut fs, "/d", "b").unwrap().unwrap();
+        fs.datablock_cache.modify(&mut dev, phys, |data| data[off] ^= 0x01).unwrap();
+        fs.dentry_cache.clear();
+        assert!(matches!(
+            find_entry_location(&mut dev, &mut fs, "/d", "b"),
+            Err(Ext4Error::ChecksumError)
+        ));
+        assert!(matches!(
+            get_file_inode(&mut fs, &mut dev, "/d/c"),
+            Err(BlockDevError::ChecksumError)
+        ));
+
+        fs.checksum_policy = ChecksumPolicy::WarnAndContinue;
+        assert!(get_file_inode(&mut fs, &mut dev, "/d/c").unwrap().is_some());
+    }
+
+    #[test]
+    fn mkdir_p_creates_missing_parents_idempotently() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/a").unwrap();
+        let inode = mkdir_p(&mut dev, &mut fs, "/a/b/c/d").unwrap();
+        assert!(inode.is_dir());
+        for path in ["/a/b", "/a/b/c", "/a/b/c/d"] {
+            assert!(fs.find_file(&mut dev, path).unwrap().is_dir());
+        }
+
+        let free_inodes = fs.superblock.s_free_inodes_count;
+        let free_blocks = fs.superblock.free_blocks_count();
+        assert!(mkdir_p(&mut dev, &mut fs, "/a/b/c/d").unwrap().is_dir());
+        assert!(mkdir_p(&mut dev, &mut fs, "//a/b/").unwrap().is_dir());
+        assert!(mkdir_p(&mut dev, &mut fs, "/").unwrap().is_dir());
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes);
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+
+        mkfile(&mut dev, &mut fs, "/a/b/f", Some(b"x"), None).unwrap();
+        let expected = Ext4Error::NotADirectory { index: 2, component: "f".to_string() };
+        assert_eq!(mkdir_p(&mut dev, &mut fs, "/a/b/f/g").err(), Some(expected.clone()));
+        assert_eq!(mkdir_p(&mut dev, &mut fs, "/a/b/f").err(), Some(expected));
+        assert!(matches!(fs.find_file(&mut dev, "/a/b/f/g"), Err(Ext4Error::NotADirectory { .. })));
+    }
+
+    #[test]
+    fn mkdir_creates_only_the_last_component() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/f", Some(b"x"), None).unwrap();
+        let free_inodes = fs.superblock.s_free_inodes_count;
+        let free_blocks = fs.superblock.free_blocks_count();
+
+        // 父目录缺失、父路径或目标本身是普通文件时都失败，且不分配任何资源
+        assert_eq!(
+            mkdir(&mut dev, &mut fs, "/x/y").err(),
+            Some(Ext4Error::NotFound { index: 0, component: "x".to_string() })
+        );
+        assert_eq!(
+            mkdir(&mut dev, &mut fs, "/f/y").err(),
+            Some(Ext4Error::NotADirectory { index: 0, component: "f".to_string() })
+        );
+        assert_eq!(
+            mkdir(&mut dev, &mut fs, "/f").err(),
+            Some(Ext4Error::NotADirectory { index: 0, component: "f".to_string() })
+        );
+        assert!(fs.find_file(&mut dev, "/f").unwrap().is_file());
+        assert!(matches!(fs.find_file(&mut dev, "/x"), Err(Ext4Error::NotFound { .. })));
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes);
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+
+        // 已存在的目录直接返回
+        let inode = mkdir(&mut dev, &mut fs, "/x").unwrap();
+        assert!(inode.is_dir());
+        assert_eq!(mkdir(&mut dev, &mut fs, "/x/").unwrap().i_block, inode.i_block);
+        assert!(mkdir(&mut dev, &mut fs, "/x/y").unwrap().is_dir());
+    }
+}
@@ -67,12 +67,7 @@ impl Ext4Inode {
         //转换写回
         let mut new_slice: [u32; 15] = [0; 15];
         for idx in 0..15 {
-            new_slice[idx] = u32::from_le_bytes([
-                extent_buffer[idx * 4],
-                extent_buffer[idx * 4 + 1],
-                extent_buffer[idx * 4 + 2],
-                extent_buffer[idx * 4 + 3],
-            ])
+            new_slice[idx] = read_u32_le(&extent_buffer[idx * 4..idx * 4 + 4])
         }
         self.i_block.copy_from_slice(&new_slice);
     }
@@ -83,6 +78,9 @@ impl Ext4Inode {
     /// 大inode默认大小（256字节）
     pub const LARGE_INODE_SIZE: u16 = 256;
 
+    /// 本结构体在磁盘上覆盖的字节数，大 inode 中其后是扩展属性区
+    pub const CORE_FIELDS_SIZE: usize = 160;
+
     /// 获取完整的文件大小（64位）
     pub fn size(&self) -> u64 {
         (self.i_size_high as u64) << 32 | self.i_size_lo as u64
@@ -108,6 +106,12 @@ impl Ext4Inode {
         (self.l_i_file_acl_high as u64) << 32 | self.i_file_acl_lo as u64
     }
 
+    /// 设置扩展属性块号
+    pub fn set_file_acl(&mut self, block: u64) {
+        self.i_file_acl_lo = block as u32;
+        self.l_i_file_acl_high = (block >> 32) as u16;
+    }
+
     /// 检查是否是目录
     pub fn is_dir(&self) -> bool {
         self.i_mode & Self::S_IFMT == Self::S_IFDIR
@@ -123,6 +127,11 @@ impl Ext4Inode {
         self.i_mode & Self::S_IFMT == Self::S_IFLNK
     }
 
+    /// 检查内容是否内联存放在 inode 中（inline_data）
+    pub fn has_inline_data(&self) -> bool {
+        self.i_flags & Self::EXT4_INLINE_DATA_FL != 0
+    }
+
     /// 检查是否使用extent树
     fn is_extent(&self) -> bool {
         self.i_flags & Self::EXT4_EXTENTS_FL != 0
@@ -135,8 +144,9 @@ impl Ext4Inode {
             return false;
         }
 
-        let word0_le = self.i_block[0].to_le_bytes();
-        let magic = u16::from_le_bytes([word0_le[0], word0_le[1]]);
+        let mut word0 = [0u8; 4];
+        write_u32_le(self.i_block[0], &mut word0);
+        let magic = read_u16_le(&word0);
         if magic == Ext4ExtentHeader::EXT4_EXT_MAGIC {
             true
         } else {
@@ -147,6 +157,73 @@ impl Ext4Inode {
     }
 
 
+    /// 大 inode 的扩展区是否覆盖到 inode 内偏移 end 处（由 i_extra_isize 决定）
+    fn has_extra_field(&self, end: usize) -> bool {
+        Self::GOOD_OLD_INODE_SIZE as usize + self.i_extra_isize as usize >= end
+    }
+
+    /// 访问时间
+    pub fn atime(&self) -> Ext4Timestamp {
+        Ext4Timestamp::decode(self.i_atime, self.has_extra_field(0x90).then_some(self.i_atime_extra))
+    }
+
+    /// 修改时间
+    pub fn mtime(&self) -> Ext4Timestamp {
+        Ext4Timestamp::decode(self.i_mtime, self.has_extra_field(0x8C).then_some(self.i_mtime_extra))
+    }
+
+    /// 状态改变时间
+    pub fn ctime(&self) -> Ext4Timestamp {
+        Ext4Timestamp::decode(self.i_ctime, self.has_extra_field(0x88).then_some(self.i_ctime_extra))
+    }
+
+    /// 创建时间，只存在于大 inode 中，没有时为 0
+    pub fn crtime(&self) -> Ext4Timestamp {
+        if !self.has_extra_field(0x94) {
+            return Ext4Timestamp::default();
+        }
+        let extra = self.has_extra_field(0x98).then_some(self.i_crtime_extra);
+        Ext4Timestamp::decode(self.i_crtime, extra)
+    }
+
+    /// 设置访问时间，extra 字段存在时同时写入纳秒和 epoch 扩展位
+    pub fn set_atime_ts(&mut self, ts: Ext4Timestamp) {
+        let (sec, extra) = ts.encode();
+        self.i_atime = sec;
+        if self.has_extra_field(0x90) {
+            self.i_atime_extra = extra;
+        }
+    }
+
+    /// 设置修改时间
+    pub fn set_mtime_ts(&mut self, ts: Ext4Timestamp) {
+        let (sec, extra) = ts.encode();
+        self.i_mtime = sec;
+        if self.has_extra_field(0x8C) {
+            self.i_mtime_extra = extra;
+        }
+    }
+
+    /// 设置状态改变时间
+    pub fn set_ctime_ts(&mut self, ts: Ext4Timestamp) {
+        let (sec, extra) = ts.encode();
+        self.i_ctime = sec;
+        if self.has_extra_field(0x88) {
+            self.i_ctime_extra = extra;
+        }
+    }
+
+    /// 设置创建时间，小 inode 没有该字段时忽略
+    pub fn set_crtime_ts(&mut self, ts: Ext4Timestamp) {
+        let (sec, extra) = ts.encode();
+        if self.has_extra_field(0x94) {
+            self.i_crtime = sec;
+        }
+        if self.has_extra_field(0x98) {
+            self.i_crtime_extra = extra;
+        }
+    }
+
     //some metadata change support 
     pub fn set_mtime(&mut self, mtime: u32) {
         self.i_mtime = mtime;
@@ -160,6 +237,45 @@ impl Ext4Inode {
 
 }
 
+/// inode 时间戳（秒 + 纳秒）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ext4Timestamp {
+    /// 自 1970 年起的秒数
+    pub sec: i64,
+    /// 纳秒部分
+    pub nsec: u32,
+}
+
+impl Ext4Timestamp {
+    /// 由 Unix 秒构造，纳秒为 0
+    pub fn from_secs(sec: u64) -> Self {
+        Self {
+            sec: sec as i64,
+            nsec: 0,
+        }
+    }
+
+    /// 编码为 (基础秒字段, *_extra 字段)，是 decode 的逆过程
+    pub fn encode(&self) -> (u32, u32) {
+        let base = self.sec as u32;
+        let epoch = ((self.sec - base as i32 as i64) >> 32) as u32 & 0x3;
+        (base, epoch | (self.nsec << 2))
+    }
+
+    /// 由基础秒字段和 *_extra 字段解码
+    /// extra 低 2 位扩展 epoch（秒的第 32、33 位），高 30 位为纳秒；没有 extra 时按有符号 32 位秒处理
+    pub fn decode(secs: u32, extra: Option<u32>) -> Self {
+        let base = secs as i32 as i64;
+        match extra {
+            Some(extra) => Self {
+                sec: base + (((extra & 0x3) as i64) << 32),
+                nsec: extra >> 2,
+            },
+            None => Self { sec: base, nsec: 0 },
+        }
+    }
+}
+
 // 文件模式常量 - 文件类型
 impl Ext4Inode {
     pub const S_IFMT: u16 = 0xF000; // 文件类型位掩码
@@ -294,6 +410,10 @@ impl Ext4Extent {
     /// extent最大长度（未初始化）
     pub const EXT_UNINIT_MAX_LEN: u16 = 32768;
 
+    /// 合并或一次分配时单个 extent 的长度上限
+    /// ee_len 最高位在读写路径上当作未初始化标志，长度只用低 15 位
+    pub const EXT_MAX_MERGE_LEN: u16 = 0x7FFF;
+
     ///默认配置
     pub fn new(logic_start: u32, start_phy_block: u64, len: u16) -> Self {
         let high = (start_phy_block >> 32) as u16;
@@ -306,6 +426,11 @@ impl Ext4Extent {
         }
     }
 
+    /// 未初始化（已分配未写入）extent：长度加上 EXT_INIT_MAX_LEN 作为标志，读出为 0
+    pub fn new_uninit(logic_start: u32, start_phy_block: u64, len: u16) -> Self {
+        Self::new(logic_start, start_phy_block, Self::EXT_INIT_MAX_LEN + len)
+    }
+
     /// 获取完整的起始物理块号（48位）
     pub fn start_block(&self) -> u64 {
         (self.ee_start_hi as u64) << 32 | self.ee_start_lo as u64
@@ -4,46 +4,106 @@
 /// 本模块提供在内存表示和磁盘表示之间转换的辅助函数
 use core::mem::size_of;
 
+/// 主机是否为大端序
+#[cfg(target_endian = "big")]
+pub const HOST_IS_BIG_ENDIAN: bool = true;
+/// 主机是否为大端序
+#[cfg(target_endian = "little")]
+pub const HOST_IS_BIG_ENDIAN: bool = false;
+
+/// 按给定的主机字节序在主机值和小端值之间转换
+/// 大端主机交换字节，小端主机原样返回；转换是对合的，同一函数既编码也解码
+#[inline]
+pub const fn le16_on(host_big_endian: bool, value: u16) -> u16 {
+    if host_big_endian { value.swap_bytes() } else { value }
+}
+
+/// 同 le16_on，32 位
+#[inline]
+pub const fn le32_on(host_big_endian: bool, value: u32) -> u32 {
+    if host_big_endian { value.swap_bytes() } else { value }
+}
+
+/// 同 le16_on，64 位
+#[inline]
+pub const fn le64_on(host_big_endian: bool, value: u64) -> u64 {
+    if host_big_endian { value.swap_bytes() } else { value }
+}
+
+/// 主机值转换为小端表示（内存中的字节即磁盘字节）
+#[inline]
+pub const fn to_le16(value: u16) -> u16 {
+    le16_on(HOST_IS_BIG_ENDIAN, value)
+}
+
+/// 主机值转换为小端表示
+#[inline]
+pub const fn to_le32(value: u32) -> u32 {
+    le32_on(HOST_IS_BIG_ENDIAN, value)
+}
+
+/// 主机值转换为小端表示
+#[inline]
+pub const fn to_le64(value: u64) -> u64 {
+    le64_on(HOST_IS_BIG_ENDIAN, value)
+}
+
+/// 小端表示转换回主机值
+#[inline]
+pub const fn from_le16(value: u16) -> u16 {
+    le16_on(HOST_IS_BIG_ENDIAN, value)
+}
+
+/// 小端表示转换回主机值
+#[inline]
+pub const fn from_le32(value: u32) -> u32 {
+    le32_on(HOST_IS_BIG_ENDIAN, value)
+}
+
+/// 小端表示转换回主机值
+#[inline]
+pub const fn from_le64(value: u64) -> u64 {
+    le64_on(HOST_IS_BIG_ENDIAN, value)
+}
+
 /// 从小端字节序读取 u16
+/// 磁盘字节按主机字节序原样装入，再由 from_le16 转成主机值
 #[inline]
 pub fn read_u16_le(bytes: &[u8]) -> u16 {
-    u16::from_le_bytes([bytes[0], bytes[1]])
+    from_le16(u16::from_ne_bytes([bytes[0], bytes[1]]))
 }
 
 /// 从小端字节序读取 u32
 #[inline]
 pub fn read_u32_le(bytes: &[u8]) -> u32 {
-    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    from_le32(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
 }
 
 /// 从小端字节序读取 u64
 #[inline]
 pub fn read_u64_le(bytes: &[u8]) -> u64 {
-    u64::from_le_bytes([
+    from_le64(u64::from_ne_bytes([
         bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-    ])
+    ]))
 }
 
 /// 写入 u16 为小端字节序
+/// 先由 to_le16 转成小端表示，其内存字节即磁盘字节
 #[inline]
 pub fn write_u16_le(value: u16, bytes: &mut [u8]) {
-    let le_bytes = value.to_le_bytes();
-    bytes[0] = le_bytes[0];
-    bytes[1] = le_bytes[1];
+    bytes[0..2].copy_from_slice(&to_le16(value).to_ne_bytes());
 }
 
 /// 写入 u32 为小端字节序
 #[inline]
 pub fn write_u32_le(value: u32, bytes: &mut [u8]) {
-    let le_bytes = value.to_le_bytes();
-    bytes[0..4].copy_from_slice(&le_bytes);
+    bytes[0..4].copy_from_slice(&to_le32(value).to_ne_bytes());
 }
 
 /// 写入 u64 为小端字节序
 #[inline]
 pub fn write_u64_le(value: u64, bytes: &mut [u8]) {
-    let le_bytes = value.to_le_bytes();
-    bytes[0..8].copy_from_slice(&le_bytes);
+    bytes[0..8].copy_from_slice(&to_le64(value).to_ne_bytes());
 }
 
 /// 可以从字节序列化/反序列化的 trait
@@ -99,4 +159,35 @@ mod tests {
         let read_value = read_u64_le(&bytes);
         assert_eq!(read_value, value);
     }
+
+    #[test]
+    fn le_conversions_round_trip_on_both_hosts() {
+        assert_eq!(HOST_IS_BIG_ENDIAN, cfg!(target_endian = "big"));
+        assert_eq!(from_le16(to_le16(0x1234)), 0x1234);
+        assert_eq!(from_le32(to_le32(0x1234_5678)), 0x1234_5678);
+        assert_eq!(from_le64(to_le64(0x0123_4567_89AB_CDEF)), 0x0123_4567_89AB_CDEF);
+
+        // 小端值在内存中的字节就是磁盘字节：真实主机按本机字节序取出
+        assert_eq!(to_le32(0x1234_5678).to_ne_bytes(), [0x78, 0x56, 0x34, 0x12]);
+
+        // 模拟大端主机：内存按大端排布，转换后的字节同样是小端序
+        for host_be in [false, true] {
+            let mem16 = |v: u16| if host_be { v.to_be_bytes() } else { v.to_le_bytes() };
+            let mem32 = |v: u32| if host_be { v.to_be_bytes() } else { v.to_le_bytes() };
+            let mem64 = |v: u64| if host_be { v.to_be_bytes() } else { v.to_le_bytes() };
+            assert_eq!(mem16(le16_on(host_be, 0x1234)), [0x34, 0x12]);
+            assert_eq!(mem32(le32_on(host_be, 0x1234_5678)), [0x78, 0x56, 0x34, 0x12]);
+            assert_eq!(
+                mem64(le64_on(host_be, 0x1234_5678_9ABC_DEF0)),
+                [0xF0, 0xDE, 0xBC, 0x9A, 0x78, 0x56, 0x34, 0x12]
+            );
+            assert_eq!(le32_on(host_be, le32_on(host_be, 0xDEAD_BEEF)), 0xDEAD_BEEF);
+
+            // read/write 辅助函数与主机字节序无关，和模拟主机上的结果一致
+            let mut bytes = [0u8; 8];
+            write_u64_le(0x1234_5678_9ABC_DEF0, &mut bytes);
+            assert_eq!(bytes, mem64(le64_on(host_be, 0x1234_5678_9ABC_DEF0)));
+            assert_eq!(read_u32_le(&mem32(le32_on(host_be, 7))), 7);
+        }
+    }
 }
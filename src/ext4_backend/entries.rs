@@ -1,5 +1,6 @@
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::endian::*;
+use crate::ext4_backend::error::*;
 use alloc::vec::Vec;
 /// Ext4 目录条目结构（传统格式）
 /// 用于ext3/ext4的线性目录条目格式
@@ -175,12 +176,12 @@ impl<'a> Ext4DirEntryInfo<'a> {
             return None;
         }
 
-        let inode = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        let inode = read_u32_le(&data[0..4]);
         if inode == 0 {
             return None; // 无效条目
         }
 
-        let rec_len = u16::from_le_bytes([data[4], data[5]]);
+        let rec_len = read_u16_le(&data[4..6]);
         let name_len = data[6] as usize;
         let file_type = data[7];
 
@@ -218,15 +219,31 @@ impl<'a> Ext4DirEntryInfo<'a> {
 pub struct DirEntryIterator<'a> {
     data: &'a [u8],
     offset: usize,
+    error: Option<BlockDevError>,
 }
 
 impl<'a> DirEntryIterator<'a> {
     /// 创建新的目录条目迭代器
     pub fn new(data: &'a [u8]) -> Self {
-        Self { data, offset: 0 }
+        Self {
+            data,
+            offset: 0,
+            error: None,
+        }
+    }
+
+    /// 遍历是否因目录块损坏而提前结束（此时为 Some(Corrupted)）
+    pub fn error(&self) -> Option<BlockDevError> {
+        self.error
     }
 }
 
+/// rec_len 是否合法：至少 8 字节、4 字节对齐、不越过剩余空间
+/// 为 0 的 rec_len 会让按 rec_len 前进的解析原地打转
+pub(crate) fn rec_len_valid(rec_len: usize, room: usize) -> bool {
+    rec_len >= 8 && rec_len % 4 == 0 && rec_len <= room
+}
+
 impl<'a> Iterator for DirEntryIterator<'a> {
     type Item = (Ext4DirEntryInfo<'a>, u16); // (条目信息, rec_len)
 
@@ -237,8 +254,10 @@ impl<'a> Iterator for DirEntryIterator<'a> {
                 return None;
             }
 
-            let rec_len = u16::from_le_bytes([remaining[4], remaining[5]]);
-            if rec_len < 8 || rec_len as usize > remaining.len() {
+            let rec_len = read_u16_le(&remaining[4..6]);
+            if !rec_len_valid(rec_len as usize, remaining.len()) {
+                self.error = Some(BlockDevError::Corrupted);
+                self.offset = self.data.len();
                 return None;
             }
 
@@ -260,23 +279,334 @@ pub mod classic_dir {
     use super::*;
 
     /// 在线性目录块中查找文件名
+    /// 找到目标之前遇到损坏的 rec_len 时返回 Corrupted，而不是当作不存在
     pub fn find_entry<'a>(
         block_data: &'a [u8],
         target_name: &[u8],
-    ) -> Option<Ext4DirEntryInfo<'a>> {
-        let iter = DirEntryIterator::new(block_data);
-        for (entry, _) in iter {
-            if entry.name == target_name {
-                return Some(entry);
+    ) -> BlockDevResult<Option<Ext4DirEntryInfo<'a>>> {
+        let mut iter = DirEntryIterator::new(block_data);
+        let found = iter.by_ref().map(|(entry, _)| entry).find(|entry| entry.name == target_name);
+        match iter.error() {
+            Some(e) => Err(e),
+            None => Ok(found),
+        }
+    }
+
+    /// 在线性目录块中查找文件名，返回条目及其在块内的字节偏移
+    /// rec_len 损坏时返回 Corrupted
+    pub fn find_entry_with_offset<'a>(
+        block_data: &'a [u8],
+        target_name: &[u8],
+    ) -> BlockDevResult<Option<(Ext4DirEntryInfo<'a>, usize)>> {
+        let mut offset = 0usize;
+        while offset + 8 <= block_data.len() {
+            let rest = &block_data[offset..];
+            let rec_len = read_u16_le(&rest[4..6]) as usize;
+            if !rec_len_valid(rec_len, rest.len()) {
+                return Err(BlockDevError::Corrupted);
+            }
+            if let Some(entry) = Ext4DirEntryInfo::parse_from_bytes(&rest[..rec_len])
+                && entry.name == target_name
+            {
+                return Ok(Some((entry, offset)));
+            }
+            offset += rec_len;
+        }
+        Ok(None)
+    }
+
+    /// 列出目录中的所有条目，rec_len 损坏时返回 Corrupted
+    pub fn list_entries<'a>(block_data: &'a [u8]) -> BlockDevResult<Vec<Ext4DirEntryInfo<'a>>> {
+        let mut iter = DirEntryIterator::new(block_data);
+        let entries = iter.by_ref().map(|(entry, _)| entry).collect();
+        match iter.error() {
+            Some(e) => Err(e),
+            None => Ok(entries),
+        }
+    }
+
+    /// 在线性目录块中为 entry 找位置插入：复用足够大的空闲项，
+    /// 或拆分某个条目 rec_len 尾部的富余空间；放不下时返回 false，块内容不变
+    pub fn insert_entry(block_data: &mut [u8], entry: &Ext4DirEntry2) -> bool {
+        let block_bytes = block_data.len();
+        let new_rec_len = Ext4DirEntry2::entry_len(entry.name_len) as usize;
+        let nlen = entry.name_len as usize;
+        let mut offset = 0usize;
+        while offset + 8 <= block_bytes {
+            let inode = read_u32_le(&block_data[offset..offset + 4]);
+            let rec_len = read_u16_le(&block_data[offset + 4..offset + 6]) as usize;
+            if rec_len < 8 {
+                return false;
+            }
+            let entry_end = offset + rec_len;
+            if entry_end > block_bytes {
+                return false;
+            }
+
+            // 空闲项：够大就直接占用（块尾的校验和伪目录项除外）
+            if inode == 0 {
+                if entry_end == block_bytes && dir_csum::has_tail(block_data) {
+                    return false;
+                }
+                if rec_len >= new_rec_len {
+                    let mut full_entry = *entry;
+                    full_entry.rec_len = rec_len as u16;
+                    full_entry.to_disk_bytes(&mut block_data[offset..offset + 8]);
+                    block_data[offset + 8..offset + 8 + nlen].copy_from_slice(&entry.name[..nlen]);
+                    return true;
+                }
+                return false;
+            }
+
+            // 已占用：尝试拆分尾部富余空间
+            let cur_name_len = block_data[offset + 6] as usize;
+            let ideal = (8 + cur_name_len + 3) & !3;
+            if ideal <= rec_len {
+                let tail = rec_len - ideal;
+                if tail >= new_rec_len {
+                    write_u16_le(ideal as u16, &mut block_data[offset + 4..offset + 6]);
+
+                    let new_off = offset + ideal;
+                    let mut full_entry = *entry;
+                    full_entry.rec_len = tail as u16;
+                    full_entry.to_disk_bytes(&mut block_data[new_off..new_off + 8]);
+                    block_data[new_off + 8..new_off + 8 + nlen].copy_from_slice(&entry.name[..nlen]);
+                    return true;
+                }
             }
+
+            if entry_end == block_bytes {
+                return false;
+            }
+            offset = entry_end;
+        }
+        false
+    }
+
+    /// 从线性目录块中删除名为 target_name 的条目，返回其 inode 号
+    /// 被删条目的空间并入前一个条目的 rec_len；位于块首时只把 inode 清零
+    pub fn remove_entry(block_data: &mut [u8], target_name: &[u8]) -> Option<u32> {
+        let block_bytes = block_data.len();
+        let mut offset: usize = 0;
+        let mut prev_off: Option<usize> = None;
+        while offset + 8 <= block_bytes {
+            let inode = read_u32_le(&block_data[offset..offset + 4]);
+            let rec_len =
+                read_u16_le(&block_data[offset + 4..offset + 6]) as usize;
+            if rec_len < 8 {
+                break;
+            }
+            let name_len = block_data[offset + 6] as usize;
+            let entry_end = offset + rec_len;
+            if entry_end > block_bytes {
+                break;
+            }
+
+            // 只在当前条目的 rec_len 范围内比较名字
+            if inode != 0
+                && name_len > 0
+                && offset + 8 + name_len <= entry_end
+                && &block_data[offset + 8..offset + 8 + name_len] == target_name
+            {
+                if let Some(poff) = prev_off {
+                    let prev_rec_len =
+                        read_u16_le(&block_data[poff + 4..poff + 6]) as usize;
+                    let merged = (prev_rec_len + rec_len) as u16;
+                    write_u16_le(merged, &mut block_data[poff + 4..poff + 6]);
+                }
+                block_data[offset..offset + 4].fill(0);
+                return Some(inode);
+            }
+            if entry_end >= block_bytes {
+                break;
+            }
+            prev_off = Some(offset);
+            offset = entry_end;
         }
         None
     }
+}
+
+/// 目录块校验和（metadata_csum）
+/// 线性目录块/htree 叶子块末尾是 12 字节的 ext4_dir_entry_tail，伪装成 inode 为 0 的目录项；
+/// dx_root/dx_node 在 limit 个索引条目之后放 8 字节的 dx_tail。
+/// 种子为目录 inode 的校验和种子（文件系统种子依次混入 inode 号和 i_generation）
+pub mod dir_csum {
+    use super::*;
+    use crate::ext4_backend::crc32c::crc32c;
+    use crate::ext4_backend::hashtree::{DX_ROOT_ENTRIES_OFF, DX_ROOT_INFO_OFF};
+
+    /// ext4_dir_entry_tail 的大小
+    pub const DIR_TAIL_SIZE: usize = Ext4DirEntryTail::TAIL_LEN as usize;
+    /// dx_root 中 dx_root_info 的 info_length 字节偏移（reserved_zero 4 字节 + hash_version 1 字节之后）
+    const DX_ROOT_INFO_LENGTH_OFF: usize = DX_ROOT_INFO_OFF + 5;
+
+    /// 在块末尾写入目录块尾，校验和由 refresh 填写
+    pub fn init_tail(block: &mut [u8]) {
+        let off = block.len() - DIR_TAIL_SIZE;
+        block[off..].fill(0);
+        write_u16_le(DIR_TAIL_SIZE as u16, &mut block[off + 4..off + 6]);
+        block[off + 7] = Ext4DirEntryTail::RESERVED_FT;
+    }
+
+    /// 块末尾是否有目录块尾
+    pub fn has_tail(block: &[u8]) -> bool {
+        let off = block.len() - DIR_TAIL_SIZE;
+        read_u32_le(&block[off..off + 4]) == 0
+            && read_u16_le(&block[off + 4..off + 6]) as usize == DIR_TAIL_SIZE
+            && block[off + 6] == 0
+            && block[off + 7] == Ext4DirEntryTail::RESERVED_FT
+    }
+
+    /// 给未开启校验和时写出的目录块补上块尾，校验和随后由 refresh 填写
+    /// 叶子块（countlimit_off 为 None）从最后一个目录项的空闲空间中划出目录块尾；
+    /// 索引块把 limit 减一，腾出的 8 字节作为 dx_tail。已有块尾时不变，放不下时返回 false
+    pub fn add_tail(block: &mut [u8], countlimit_off: Option<usize>) -> bool {
+        let bs = block.len();
+        if let Some(off) = countlimit_off {
+            let limit = dx_limit(off, bs);
+            if read_u16_le(&block[off..off + 2]) == limit {
+                return true;
+            }
+            if read_u16_le(&block[off + 2..off + 4]) > limit {
+                return false;
+            }
+            write_u16_le(limit, &mut block[off..off + 2]);
+            let tail = off + limit as usize * 8;
+            block[tail..tail + 8].fill(0);
+            return true;
+        }
+        if has_tail(block) {
+            return true;
+        }
+        // 找到延伸到块末尾的最后一个目录项
+        let mut off = 0;
+        let rec_len = loop {
+            let rec_len = read_u16_le(&block[off + 4..off + 6]) as usize;
+            if !rec_len_valid(rec_len, bs - off) {
+                return false;
+            }
+            if off + rec_len == bs {
+                break rec_len;
+            }
+            off += rec_len;
+        };
+        let used = if read_u32_le(&block[off..off + 4]) == 0 {
+            8
+        } else {
+            (8 + block[off + 6] as usize + 3) & !3
+        };
+        if rec_len < used + DIR_TAIL_SIZE {
+            return false;
+        }
+        write_u16_le((rec_len - DIR_TAIL_SIZE) as u16, &mut block[off + 4..off + 6]);
+        init_tail(block);
+        true
+    }
+
+    /// 带 dx_tail 的索引块的 limit：比不带校验和时少一个条目
+    pub fn dx_limit(countlimit_off: usize, block_size: usize) -> u16 {
+        ((block_size - countlimit_off) / 8 - 1) as u16
+    }
+
+    /// 带 dx_tail 的索引块返回 (countlimit 偏移, dx_tail 偏移)
+    /// dx_node 以 inode 为 0、占满整块的伪目录项开头；dx_root 的 ".." 延伸到块尾且 info_length 为 8
+    fn dx_layout(block: &[u8]) -> Option<(usize, usize)> {
+        let bs = block.len();
+        let rec_len = read_u16_le(&block[4..6]) as usize;
+        let off = if read_u32_le(&block[0..4]) == 0 && rec_len == bs && block[6] == 0 {
+            8
+        } else if rec_len == 12
+            && read_u16_le(&block[16..18]) as usize == bs - 12
+            && block[DX_ROOT_INFO_LENGTH_OFF] == 8
+        {
+            DX_ROOT_ENTRIES_OFF
+        } else {
+            return None;
+        };
+        let limit = read_u16_le(&block[off..off + 2]);
+        let count = read_u16_le(&block[off + 2..off + 4]);
+        (limit == dx_limit(off, bs) && count <= limit).then_some((off, off + limit as usize * 8))
+    }
+
+    fn dx_checksum(seed: u32, block: &[u8], off: usize, tail: usize) -> u32 {
+        let count = read_u16_le(&block[off + 2..off + 4]) as usize;
+        let csum = crc32c(seed, &block[..off + count * 8]);
+        let csum = crc32c(csum, &block[tail..tail + 4]);
+        crc32c(csum, &[0u8; 4])
+    }
+
+    /// 重新计算目录块的校验和：有目录块尾的按叶子块计算，带 dx_tail 的按索引块计算，
+    /// 两者都没有的块（未开启校验和时创建）保持不变
+    pub fn refresh(seed: u32, block: &mut [u8]) {
+        let len = block.len();
+        if has_tail(block) {
+            let sum = crc32c(seed, &block[..len - DIR_TAIL_SIZE]);
+            write_u32_le(sum, &mut block[len - 4..]);
+        } else if let Some((off, tail)) = dx_layout(block) {
+            let sum = dx_checksum(seed, block, off, tail);
+            write_u32_le(sum, &mut block[tail + 4..tail + 8]);
+        }
+    }
 
-    /// 列出目录中的所有条目
-    pub fn list_entries<'a>(block_data: &'a [u8]) -> Vec<Ext4DirEntryInfo<'a>> {
-        let iter = DirEntryIterator::new(block_data);
-        iter.map(|(entry, _)| entry).collect()
+    /// 校验目录块；没有块尾的块视为未启用校验和，总是通过
+    pub fn verify(seed: u32, block: &[u8]) -> bool {
+        let len = block.len();
+        if has_tail(block) {
+            read_u32_le(&block[len - 4..]) == crc32c(seed, &block[..len - DIR_TAIL_SIZE])
+        } else if let Some((off, tail)) = dx_layout(block) {
+            read_u32_le(&block[tail + 4..tail + 8]) == dx_checksum(seed, block, off, tail)
+        } else {
+            true
+        }
+    }
+}
+
+/// 内联目录（inline_data）辅助函数
+/// 目录内容存放在 inode 的 i_block（60 字节）中：前 4 字节是父目录 inode 号，
+/// 其后是与线性目录相同格式的目录项，不包含 "." 和 ".."
+pub mod inline_dir {
+    use super::*;
+
+    /// i_block 区域字节数
+    pub const INLINE_DIR_BYTES: usize = 60;
+    /// 开头父目录 inode 号占用的字节数
+    pub const PARENT_INO_SIZE: usize = 4;
+
+    /// 取出 i_block 的原始字节
+    pub fn raw_bytes(i_block: &[u32; 15]) -> [u8; INLINE_DIR_BYTES] {
+        let mut raw = [0u8; INLINE_DIR_BYTES];
+        for (i, word) in i_block.iter().enumerate() {
+            write_u32_le(*word, &mut raw[i * 4..i * 4 + 4]);
+        }
+        raw
+    }
+
+    /// 父目录 inode 号
+    pub fn parent_ino(raw: &[u8]) -> u32 {
+        read_u32_le(&raw[..PARENT_INO_SIZE])
+    }
+
+    /// 目录项区域
+    pub fn entries(raw: &[u8]) -> &[u8] {
+        &raw[PARENT_INO_SIZE..]
+    }
+
+    /// 补上 "." 和 ".." 转换为线性目录块格式，供统一的目录遍历使用
+    pub fn to_classic_block(self_ino: u32, raw: &[u8]) -> Vec<u8> {
+        let dot_len = Ext4DirEntry2::entry_len(1);
+        let dotdot_len = Ext4DirEntry2::entry_len(2);
+        let mut block = alloc::vec![0u8; (dot_len + dotdot_len) as usize];
+        let dot = Ext4DirEntry2::new(self_ino, dot_len, Ext4DirEntry2::EXT4_FT_DIR, b".");
+        dot.to_disk_bytes(&mut block[..8]);
+        block[8] = b'.';
+        let off = dot_len as usize;
+        let dotdot =
+            Ext4DirEntry2::new(parent_ino(raw), dotdot_len, Ext4DirEntry2::EXT4_FT_DIR, b"..");
+        dotdot.to_disk_bytes(&mut block[off..off + 8]);
+        block[off + 8..off + 10].copy_from_slice(b"..");
+        block.extend_from_slice(entries(raw));
+        block
     }
 }
 
@@ -285,15 +615,25 @@ pub mod htree_dir {
     use super::*;
 
     /// 计算文件名的哈希值
+    /// 与内核一致，最低位清零（留给索引中的冲突续接标志），0xFFFFFFFE 保留给 EOF
     pub fn calculate_hash(name: &[u8], hash_version: u8, hash_seed: &[u32; 4]) -> u32 {
-        match hash_version {
+        let hash = match hash_version {
             Ext4DxRootInfo::DX_HASH_LEGACY => legacy_hash(name),
-            Ext4DxRootInfo::DX_HASH_HALF_MD4 => half_md4_hash(name, hash_seed),
+            Ext4DxRootInfo::DX_HASH_HALF_MD4 => half_md4_hash(name, hash_seed, true),
+            Ext4DxRootInfo::DX_HASH_HALF_MD4_UNSIGNED => half_md4_hash(name, hash_seed, false),
             Ext4DxRootInfo::DX_HASH_TEA => tea_hash(name, hash_seed),
-            _ => 0,
+            _ => return 0,
+        } & !1;
+        if hash == HTREE_EOF_HASH {
+            HTREE_EOF_HASH - 2
+        } else {
+            hash
         }
     }
 
+    /// 32 位 htree 的 EOF 哈希值，不能作为文件名哈希
+    const HTREE_EOF_HASH: u32 = 0x7FFF_FFFF << 1;
+
     /// 传统哈希算法（简化实现）
     fn legacy_hash(name: &[u8]) -> u32 {
         let mut hash = 0u32;
@@ -303,14 +643,99 @@ pub mod htree_dir {
         hash
     }
 
-    /// Half MD4哈希算法（简化实现）
-    fn half_md4_hash(name: &[u8], seed: &[u32; 4]) -> u32 {
-        // 这是一个简化版本，实际实现需要完整的MD4算法
-        let mut hash = seed[0];
-        for &byte in name {
-            hash = hash.wrapping_mul(1103515245).wrapping_add(byte as u32);
+    /// MD4 的默认初始值，种子全为 0 时使用
+    const DX_DEFAULT_SEED: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+    /// 把文件名打包成 num 个 u32（与内核 str2hashbuf 相同），不足部分用长度填充
+    /// signed 决定字节按有符号还是无符号字符参与运算
+    fn str2hashbuf(msg: &[u8], num: usize, signed: bool, out: &mut [u32]) {
+        let len = msg.len() as u32;
+        let mut pad = len | (len << 8);
+        pad |= pad << 16;
+        let mut val = pad;
+        let mut filled = 0;
+        for (i, &b) in msg.iter().take(num * 4).enumerate() {
+            let c = if signed { b as i8 as i32 as u32 } else { b as u32 };
+            val = c.wrapping_add(val << 8);
+            if i % 4 == 3 {
+                out[filled] = val;
+                filled += 1;
+                val = pad;
+            }
         }
-        hash
+        if filled < num {
+            out[filled] = val;
+            filled += 1;
+        }
+        for slot in &mut out[filled..num] {
+            *slot = pad;
+        }
+    }
+
+    /// 内核 half_md4_transform：MD4 的三轮压缩，只处理 8 个输入字
+    fn half_md4_transform(buf: &mut [u32; 4], input: &[u32; 8]) {
+        const K1: u32 = 0;
+        const K2: u32 = 0x5A82_7999;
+        const K3: u32 = 0x6ED9_EBA1;
+        let f = |x: u32, y: u32, z: u32| z ^ (x & (y ^ z));
+        let g = |x: u32, y: u32, z: u32| (x & y).wrapping_add((x ^ y) & z);
+        let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+        let [mut a, mut b, mut c, mut d] = *buf;
+        macro_rules! round {
+            ($f:expr, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+                $a = $a.wrapping_add($f($b, $c, $d)).wrapping_add($x);
+                $a = $a.rotate_left($s);
+            };
+        }
+        round!(f, a, b, c, d, input[0].wrapping_add(K1), 3);
+        round!(f, d, a, b, c, input[1].wrapping_add(K1), 7);
+        round!(f, c, d, a, b, input[2].wrapping_add(K1), 11);
+        round!(f, b, c, d, a, input[3].wrapping_add(K1), 19);
+        round!(f, a, b, c, d, input[4].wrapping_add(K1), 3);
+        round!(f, d, a, b, c, input[5].wrapping_add(K1), 7);
+        round!(f, c, d, a, b, input[6].wrapping_add(K1), 11);
+        round!(f, b, c, d, a, input[7].wrapping_add(K1), 19);
+
+        round!(g, a, b, c, d, input[1].wrapping_add(K2), 3);
+        round!(g, d, a, b, c, input[3].wrapping_add(K2), 5);
+        round!(g, c, d, a, b, input[5].wrapping_add(K2), 9);
+        round!(g, b, c, d, a, input[7].wrapping_add(K2), 13);
+        round!(g, a, b, c, d, input[0].wrapping_add(K2), 3);
+        round!(g, d, a, b, c, input[2].wrapping_add(K2), 5);
+        round!(g, c, d, a, b, input[4].wrapping_add(K2), 9);
+        round!(g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+        round!(h, a, b, c, d, input[3].wrapping_add(K3), 3);
+        round!(h, d, a, b, c, input[7].wrapping_add(K3), 9);
+        round!(h, c, d, a, b, input[2].wrapping_add(K3), 11);
+        round!(h, b, c, d, a, input[6].wrapping_add(K3), 15);
+        round!(h, a, b, c, d, input[1].wrapping_add(K3), 3);
+        round!(h, d, a, b, c, input[5].wrapping_add(K3), 9);
+        round!(h, c, d, a, b, input[0].wrapping_add(K3), 11);
+        round!(h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+        buf[0] = buf[0].wrapping_add(a);
+        buf[1] = buf[1].wrapping_add(b);
+        buf[2] = buf[2].wrapping_add(c);
+        buf[3] = buf[3].wrapping_add(d);
+    }
+
+    /// Half MD4 哈希（ext4 默认的目录哈希），每 32 字节文件名做一次变换，取 buf[1]
+    /// 种子全为 0 时使用 MD4 默认初始值
+    fn half_md4_hash(name: &[u8], seed: &[u32; 4], signed: bool) -> u32 {
+        let mut buf = if seed.iter().any(|&s| s != 0) {
+            *seed
+        } else {
+            DX_DEFAULT_SEED
+        };
+        let mut input = [0u32; 8];
+        let mut rest = name;
+        while !rest.is_empty() {
+            str2hashbuf(rest, 8, signed, &mut input);
+            half_md4_transform(&mut buf, &input);
+            rest = &rest[rest.len().min(32)..];
+        }
+        buf[1]
     }
 
     /// TEA哈希算法（Tiny Encryption Algorithm）
@@ -363,3 +788,130 @@ impl DiskFormat for Ext4DirEntry2 {
         8 // 固定头部大小，不包括变长文件名
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_entry(block: &mut [u8], off: usize, ino: u32, rec_len: u16, name: &[u8]) {
+        let entry = Ext4DirEntry2::new(ino, rec_len, 1, name);
+        entry.to_disk_bytes(&mut block[off..off + 8]);
+        block[off + 8..off + 8 + name.len()].copy_from_slice(name);
+    }
+
+    #[test]
+    fn remove_entry_coalesces_into_previous() {
+        let mut block = [0u8; 64];
+        put_entry(&mut block, 0, 11, 12, b"a");
+        put_entry(&mut block, 12, 12, 12, b"b");
+        put_entry(&mut block, 24, 13, 40, b"c");
+
+        assert_eq!(classic_dir::remove_entry(&mut block, b"b"), Some(12));
+        // "a" 的 rec_len 吞并了 "b" 的空间
+        assert_eq!(u16::from_le_bytes([block[4], block[5]]), 24);
+        let names: Vec<&[u8]> = classic_dir::list_entries(&block)
+            .unwrap()
+            .iter()
+            .filter(|e| e.inode != 0)
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, [b"a".as_slice(), b"c".as_slice()]);
+
+        // 块首条目没有前驱，只清零 inode
+        assert_eq!(classic_dir::remove_entry(&mut block, b"a"), Some(11));
+        assert_eq!(u32::from_le_bytes([block[0], block[1], block[2], block[3]]), 0);
+        assert_eq!(classic_dir::remove_entry(&mut block, b"missing"), None);
+    }
+
+    #[test]
+    fn entries_parse_from_little_endian_bytes_on_any_host() {
+        // 手工按小端排布字节：在大端主机上按本机字节序读取会得到不同的值
+        let mut block = [0u8; 64];
+        block[0..12].copy_from_slice(&[0x04, 0x03, 0x02, 0x01, 12, 0, 1, 2, b'.', 0, 0, 0]);
+        block[12..24].copy_from_slice(&[0x0b, 0x0a, 0, 0, 20, 0, 5, 1, b'h', b'e', b'l', b'l']);
+        block[24] = b'o';
+        block[32..40].copy_from_slice(&[0x78, 0x56, 0x34, 0x12, 32, 0, 3, 2]);
+        block[40..43].copy_from_slice(b"sub");
+        assert_ne!(u32::from_be_bytes([0x04, 0x03, 0x02, 0x01]), 0x0102_0304);
+
+        let parsed: Vec<(u32, u16, u8, &[u8])> = DirEntryIterator::new(&block)
+            .map(|(e, rec_len)| (e.inode, rec_len, e.file_type, e.name))
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                (0x0102_0304, 12, 2, &b"."[..]),
+                (0x0a0b, 20, 1, &b"hello"[..]),
+                (0x1234_5678, 32, 2, &b"sub"[..]),
+            ]
+        );
+        let (entry, off) = classic_dir::find_entry_with_offset(&block, b"sub").unwrap().unwrap();
+        assert_eq!((entry.inode, off), (0x1234_5678, 32));
+        let disk = Ext4DirEntry2::from_disk_bytes(&block[12..]);
+        assert_eq!((disk.inode, disk.rec_len, disk.name_len), (0x0a0b, 20, 5));
+
+        // 删除后合并的 rec_len 也按小端写回
+        assert_eq!(classic_dir::remove_entry(&mut block, b"sub"), Some(0x1234_5678));
+        assert_eq!(&block[16..18], &[52, 0]);
+    }
+
+    #[test]
+    fn half_md4_matches_debugfs_dx_hash() {
+        // 期望值取自 debugfs "dx_hash -h half_md4 [-s uuid] name"
+        let zero = [0u32; 4];
+        assert_eq!(
+            htree_dir::calculate_hash(b"hello", Ext4DxRootInfo::DX_HASH_HALF_MD4, &zero),
+            0x1746_da32
+        );
+        let uuid: [u8; 16] = [
+            0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc,
+            0xde, 0xf0,
+        ];
+        let seed: [u32; 4] =
+            core::array::from_fn(|i| u32::from_le_bytes(uuid[i * 4..i * 4 + 4].try_into().unwrap()));
+        assert_eq!(
+            htree_dir::calculate_hash(b"file_0001", Ext4DxRootInfo::DX_HASH_HALF_MD4, &seed),
+            0x2cd1_5e48
+        );
+        // 超过 32 字节的名字需要多轮变换
+        assert_eq!(
+            htree_dir::calculate_hash(
+                b"a_rather_long_file_name_exceeding_32_bytes_total",
+                Ext4DxRootInfo::DX_HASH_HALF_MD4,
+                &seed
+            ),
+            0xe2e0_b458
+        );
+    }
+
+    #[test]
+    fn zero_rec_len_stops_iteration_with_corrupted() {
+        let mut block = [0u8; 64];
+        put_entry(&mut block, 0, 11, 12, b"a");
+        // 第二个条目 rec_len 为 0：解析必须终止而不是原地循环
+        put_entry(&mut block, 12, 12, 0, b"b");
+        let mut iter = DirEntryIterator::new(&block);
+        assert_eq!(iter.next().map(|(e, _)| e.inode), Some(11));
+        assert!(iter.next().is_none());
+        assert_eq!(iter.error(), Some(BlockDevError::Corrupted));
+        assert!(iter.next().is_none());
+        assert_eq!(
+            classic_dir::find_entry_with_offset(&block, b"b").err(),
+            Some(BlockDevError::Corrupted)
+        );
+        assert_eq!(classic_dir::find_entry(&block, b"b").err(), Some(BlockDevError::Corrupted));
+        assert_eq!(classic_dir::find_entry(&block, b"a").unwrap().map(|e| e.inode), Some(11));
+        assert_eq!(classic_dir::list_entries(&block).err(), Some(BlockDevError::Corrupted));
+
+        // 未对齐的 rec_len 同样视为损坏
+        put_entry(&mut block, 12, 12, 14, b"b");
+        let mut iter = DirEntryIterator::new(&block);
+        assert_eq!(iter.by_ref().count(), 1);
+        assert_eq!(iter.error(), Some(BlockDevError::Corrupted));
+
+        put_entry(&mut block, 12, 12, 52, b"b");
+        let mut iter = DirEntryIterator::new(&block);
+        assert_eq!(iter.by_ref().count(), 2);
+        assert_eq!(iter.error(), None);
+    }
+}
@@ -1,5 +1,8 @@
 //!错误处理模块
 //! 
+use crate::ext4_backend::superblock::Ext4Superblock;
+use alloc::string::String;
+
 /// 块设备错误类型
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlockDevError {
@@ -14,7 +17,7 @@ pub enum BlockDevError {
     WriteError,
 
     /// 块号超出范围
-    BlockOutOfRange { block_id: u32, max_blocks: u64 },
+    BlockOutOfRange { block_id: u64, max_blocks: u64 },
 
     /// 无效的块大小
     InvalidBlockSize { size: usize, expected: usize },
@@ -123,6 +126,8 @@ pub enum RSEXT4Error {
     FilesystemHasErrors,
     /// 不支持的特性
     UnsupportedFeature,
+    /// 超级块含有不支持的 incompat 特性位（值为这些位的掩码）
+    UnsupportedIncompat(u32),
     /// 已经挂载
     AlreadyMounted,
 }
@@ -135,7 +140,113 @@ impl core::fmt::Display for RSEXT4Error {
             RSEXT4Error::InvalidSuperblock => write!(f, "超级块无效"),
             RSEXT4Error::FilesystemHasErrors => write!(f, "文件系统有错误"),
             RSEXT4Error::UnsupportedFeature => write!(f, "不支持的特性"),
+            RSEXT4Error::UnsupportedIncompat(bits) => {
+                write!(f, "不支持的 incompat 特性 {bits:#x}:")?;
+                for i in 0..32 {
+                    let bit = 1u32 << i;
+                    if bits & bit != 0 {
+                        match Ext4Superblock::incompat_feature_name(bit) {
+                            Some(name) => write!(f, " {name}")?,
+                            None => write!(f, " {bit:#x}")?,
+                        }
+                    }
+                }
+                Ok(())
+            }
             RSEXT4Error::AlreadyMounted => write!(f, "文件系统已挂载"),
         }
     }
-}
\ No newline at end of file
+}
+/// 公开文件系统 API 的统一错误：包装块设备错误和挂载错误，并补充文件系统层面的分类
+/// 路径相关错误携带出错分量的下标（从 0 起，按非空分量计，含 "." 和 ".."）和名字；
+/// 由块层错误转换而来、无法定位分量时 component 为空
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ext4Error {
+    /// 块设备错误
+    Device(BlockDevError),
+    /// 挂载失败（魔数、超级块、特性检查等）
+    Mount(RSEXT4Error),
+    /// 路径分量不存在
+    NotFound { index: usize, component: String },
+    /// 路径分量不是目录却被当作目录使用
+    NotADirectory { index: usize, component: String },
+    /// 目标是目录
+    IsADirectory,
+    /// 目录非空
+    DirectoryNotEmpty,
+    /// 符号链接层数过多（可能成环）
+    TooManyLinks,
+    /// 跨设备链接
+    CrossDevice,
+    /// 块或 inode 不足
+    NoSpace,
+    /// 元数据损坏
+    Corrupt,
+    /// extent 树节点损坏（magic、深度、条目数或顺序不合法）
+    CorruptExtentTree,
+    /// 元数据校验和不匹配
+    ChecksumError,
+    /// 镜像太小，放不下最基本的元数据（和请求的 journal）
+    ImageTooSmall { required_blocks: u64, total_blocks: u64 },
+    /// 文件系统只读
+    ReadOnlyFs,
+    /// 权限不足
+    PermissionDenied,
+    /// 非法参数
+    InvalidInput,
+}
+
+/// 公开文件系统 API 的结果类型
+pub type Ext4Result<T> = Result<T, Ext4Error>;
+
+impl From<BlockDevError> for Ext4Error {
+    fn from(e: BlockDevError) -> Self {
+        match e {
+            BlockDevError::InvalidInput => Ext4Error::InvalidInput,
+            BlockDevError::NoSpace => Ext4Error::NoSpace,
+            BlockDevError::Corrupted => Ext4Error::Corrupt,
+            BlockDevError::ChecksumError => Ext4Error::ChecksumError,
+            BlockDevError::ReadOnly => Ext4Error::ReadOnlyFs,
+            BlockDevError::PermissionDenied => Ext4Error::PermissionDenied,
+            other => Ext4Error::Device(other),
+        }
+    }
+}
+
+impl From<RSEXT4Error> for Ext4Error {
+    fn from(e: RSEXT4Error) -> Self {
+        Ext4Error::Mount(e)
+    }
+}
+
+impl core::fmt::Display for Ext4Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Ext4Error::Device(e) => write!(f, "{e}"),
+            Ext4Error::Mount(e) => write!(f, "mount failed: {e}"),
+            Ext4Error::NotFound { index, component } => {
+                write!(f, "no such file or directory: component {index} \"{component}\"")
+            }
+            Ext4Error::NotADirectory { component, .. } if component.is_empty() => {
+                write!(f, "not a directory")
+            }
+            Ext4Error::NotADirectory { index, component } => {
+                write!(f, "not a directory: component {index} \"{component}\"")
+            }
+            Ext4Error::IsADirectory => write!(f, "is a directory"),
+            Ext4Error::DirectoryNotEmpty => write!(f, "directory not empty"),
+            Ext4Error::TooManyLinks => write!(f, "too many levels of symbolic links"),
+            Ext4Error::CrossDevice => write!(f, "invalid cross-device link"),
+            Ext4Error::NoSpace => write!(f, "no space left on filesystem"),
+            Ext4Error::Corrupt => write!(f, "filesystem metadata is corrupted"),
+            Ext4Error::CorruptExtentTree => write!(f, "extent tree is corrupted"),
+            Ext4Error::ChecksumError => write!(f, "metadata checksum mismatch"),
+            Ext4Error::ImageTooSmall { required_blocks, total_blocks } => {
+                write!(f, "image too small: {total_blocks} blocks, at least {required_blocks} required")
+            }
+            Ext4Error::ReadOnlyFs => write!(f, "read-only filesystem"),
+            Ext4Error::PermissionDenied => write!(f, "permission denied"),
+            Ext4Error::InvalidInput => write!(f, "invalid argument"),
+        }
+    }
+}
@@ -8,10 +8,13 @@ use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::blockgroup_description::*;
 use crate::ext4_backend::bmalloc::*;
 use crate::ext4_backend::config::*;
+use crate::ext4_backend::block_cache::*;
 use crate::ext4_backend::datablock_cache::*;
+use crate::ext4_backend::dentry_cache::*;
 use crate::ext4_backend::dir::*;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::endian::*;
+use crate::ext4_backend::file::{reclaim_inode, resolve_path_follow};
 use crate::ext4_backend::inodetable_cache::*;
 use crate::ext4_backend::jbd2::jbd2::*;
 use crate::ext4_backend::jbd2::jbdstruct::*;
@@ -19,13 +22,76 @@ use crate::ext4_backend::loopfile::*;
 use crate::ext4_backend::superblock::*;
 use crate::ext4_backend::tool::*;
 use crate::ext4_backend::error::*;
+use crate::ext4_backend::entries::dir_csum;
+use crate::ext4_backend::extents_tree::ExtentTree;
+use crate::ext4_backend::hashtree::dx_index_blocks;
+use crate::ext4_backend::xattr::{refresh_block_csum as refresh_xattr_block_csum, release_xattr_block};
 use log::trace;
 
 use alloc::collections::vec_deque::VecDeque;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::vec::Vec;
 use log::{debug, error, info, warn};
 
 
+/// 元数据校验和不匹配时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumPolicy {
+    /// 返回 ChecksumError，中止当前操作（默认）
+    #[default]
+    Strict,
+    /// 记录警告后继续使用（可能已损坏的）数据，供数据恢复、取证工具使用
+    WarnAndContinue,
+}
+
+impl ChecksumPolicy {
+    /// 按策略处理一次校验结果：Strict 下校验失败返回 ChecksumError，否则只打警告
+    pub fn check(self, ok: bool, what: &str) -> BlockDevResult<()> {
+        if ok {
+            return Ok(());
+        }
+        match self {
+            ChecksumPolicy::Strict => {
+                error!("{what} checksum mismatch");
+                Err(BlockDevError::ChecksumError)
+            }
+            ChecksumPolicy::WarnAndContinue => {
+                warn!("{what} checksum mismatch, continuing with possibly corrupt data");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// 块组描述符备份的更新策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GdtBackupPolicy {
+    /// 运行期间只写主 GDT，备份在卸载（sync_backups）时统一同步（默认）
+    #[default]
+    Lazy,
+    /// 每次写回描述符都同时更新所有备份
+    Eager,
+}
+
+/// 卸载选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UmountOptions {
+    /// 提交日志后是否 checkpoint：关闭时已提交的事务留在日志中，
+    /// 下次挂载时重放，卸载更快
+    pub checkpoint: bool,
+    /// 是否同步各块组的超级块/GDT 备份
+    pub sync_backups: bool,
+}
+
+impl Default for UmountOptions {
+    fn default() -> Self {
+        Self {
+            checkpoint: true,
+            sync_backups: true,
+        }
+    }
+}
+
 /// Ext4文件系统实例
 /// 管理挂载后的文件系统状态
 pub struct Ext4FileSystem {
@@ -43,6 +109,10 @@ pub struct Ext4FileSystem {
     pub inodetable_cahce: InodeCache,
     /// DataBlock缓存
     pub datablock_cache: DataBlockCache,
+    /// 三类块缓存各自的容量
+    pub cache_capacities: CacheCapacities,
+    /// 目录项名字缓存
+    pub dentry_cache: DentryCache,
     /// 根目录inode号
     pub root_inode: u32,
     /// 块组数量
@@ -50,7 +120,40 @@ pub struct Ext4FileSystem {
     /// 是否已挂载
     pub mounted: bool,
     /// Journal 超级块 开始块号
-    pub journal_sb_block_start: Option<u32>,
+    pub journal_sb_block_start: Option<u64>,
+    /// inode 分配策略
+    pub inode_alloc_policy: InodeAllocPolicy,
+    /// 是否只读挂载
+    pub read_only: bool,
+    /// 被打开的 inode 及其句柄数
+    pub open_inodes: BTreeMap<u32, u32>,
+    /// 链接数已降为 0 但仍被打开的 inode，最后一个句柄关闭时回收
+    pub orphan_inodes: BTreeSet<u32>,
+    /// 当前时间（Unix 秒）来源，用于更新 inode 时间戳，挂载时设置
+    pub now: fn() -> u64,
+    /// 读文件时不更新 atime（类似 Linux noatime）
+    pub noatime: bool,
+    /// 释放数据块时对设备下发 discard
+    pub online_discard: bool,
+    /// 已释放、尚未下发 discard 的块（online_discard 开启时记录）
+    pub pending_discards: Vec<u64>,
+    /// read_at 之后沿当前 extent 预读的块数，0 表示关闭
+    pub readahead_blocks: u32,
+    /// 为批量导入预留、尚未被分配掉的 inode 数，其他分配不能动用这部分
+    pub reserved_inodes: u32,
+    /// 当前分配是否由预留的持有者发起（见 with_reserved_inodes）
+    pub(crate) drawing_reserved_inodes: bool,
+    /// 元数据校验和不匹配时的处理策略
+    pub checksum_policy: ChecksumPolicy,
+    /// 块组描述符备份的更新策略
+    pub gdt_backup_policy: GdtBackupPolicy,
+    /// 主 GDT 或超级块特性位已改、备份尚未同步；卸载时只在置位时重写备份
+    pub gdt_backups_stale: bool,
+}
+
+/// 默认时间来源：no_std 下没有时钟，时间戳冻结为 0
+pub fn frozen_clock() -> u64 {
+    0
 }
 
 impl Ext4FileSystem {
@@ -123,20 +226,40 @@ impl Ext4FileSystem {
     }
 
     ///遍历目录
+    /// 空路径视为非法输入；"/"、"//"、"/./" 等均解析为根目录
+    /// 失败时 NotFound / NotADirectory 携带出错的路径分量
     pub fn find_file<B: BlockDevice>(
         &mut self,
         device: &mut Jbd2Dev<B>,
         path: &str,
-    ) -> Option<Ext4Inode> {
-        let inode = get_file_inode(self, device, path).expect("no dir");
-        match &inode {
-            Some(inode) => {
+    ) -> Ext4Result<Ext4Inode> {
+        find_file(self, device, path)
+    }
+
+    /// 同 find_file，`follow_symlinks` 为 true 时跟随路径中的符号链接
+    /// （包括最后一个分量），超过 SYMLINK_MAX_HOPS 次返回 TooManyLinks；
+    /// 不存在时返回带分量的 NotFound
+    pub fn find_file_with<B: BlockDevice>(
+        &mut self,
+        device: &mut Jbd2Dev<B>,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> Ext4Result<Ext4Inode> {
+        if path.is_empty() {
+            warn!("find_file: empty path");
+            return Err(Ext4Error::InvalidInput);
+        }
+        if !follow_symlinks {
+            return lookup_path(self, device, path).map(|(_, inode)| inode);
+        }
+        match resolve_path_follow(device, self, path, true)? {
+            Some((_, inode)) => {
                 debug!("Found it: {path} !");
-                Some(inode.1)
+                Ok(inode)
             }
             None => {
                 warn!("Not found: {path} !");
-                None
+                Err(path_lookup_error(self, device, path))
             }
         }
     }
@@ -149,16 +272,7 @@ impl Ext4FileSystem {
     ) -> BlockDevResult<Ext4Inode> {
         let root_inode_num = self.root_inode as u64;
         // 根目录位于块组0的 inode 表中，起始块号从块组描述符读取
-        let inode_table_start = match self.group_descs.first() {
-            Some(desc) => desc.inode_table(),
-            None => return Err(BlockDevError::Corrupted),
-        };
-        let (block_num, offset, _group_idx) = self.inodetable_cahce.calc_inode_location(
-            self.root_inode,
-            self.superblock.s_inodes_per_group,
-            inode_table_start,
-            BLOCK_SIZE,
-        );
+        let (block_num, offset) = self.locate_inode(block_dev, self.root_inode)?;
         let result =
             self.inodetable_cahce
                 .get_or_load(block_dev, root_inode_num, block_num, offset)?;
@@ -180,21 +294,119 @@ impl Ext4FileSystem {
 
     /// 打开Ext4文件系统
     pub fn mount<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> Result<Self, RSEXT4Error> {
-        debug!("Start mounting Ext4 filesystem...");
+        Self::mount_inner(block_dev, false, ChecksumPolicy::default())
+    }
 
-        //在mount时应该重放一遍日志
-        //block_dev.set_journal_superblock(super_block, jouranl_start_block);
+    /// 挂载并指定校验和策略：WarnAndContinue 时超级块等元数据校验失败只告警，
+    /// 用于从损坏的镜像中尽量读出数据；策略保存在 checksum_policy 中供后续读取使用
+    pub fn mount_with_checksum_policy<B: BlockDevice>(
+        block_dev: &mut Jbd2Dev<B>,
+        policy: ChecksumPolicy,
+    ) -> Result<Self, RSEXT4Error> {
+        Self::mount_inner(block_dev, false, policy)
+    }
 
-        // 1. 读取超级块（按 ext4 标准偏移 1024 字节，大小 1024 字节）
-        let superblock = read_superblock(block_dev).map_err(|_| RSEXT4Error::IoError)?;
+    /// 挂载并指定时间来源（Unix 秒），之后的读写会用它更新 inode 时间戳
+    pub fn mount_with_clock<B: BlockDevice>(
+        block_dev: &mut Jbd2Dev<B>,
+        now: fn() -> u64,
+    ) -> Result<Self, RSEXT4Error> {
+        let mut fs = Self::mount_inner(block_dev, false, ChecksumPolicy::default())?;
+        fs.now = now;
+        Ok(fs)
+    }
 
-        // 2. 验证魔数
-        if superblock.s_magic != EXT4_SUPER_MAGIC {
+    /// 只读挂载：不回放日志、不创建缺失的目录，所有写路径返回 ReadOnly
+    /// 之后可通过 remount_rw 升级为读写
+    pub fn mount_ro<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> Result<Self, RSEXT4Error> {
+        Self::mount_inner(block_dev, true, ChecksumPolicy::default())
+    }
+
+    fn mount_inner<B: BlockDevice>(
+        block_dev: &mut Jbd2Dev<B>,
+        read_only: bool,
+        policy: ChecksumPolicy,
+    ) -> Result<Self, RSEXT4Error> {
+        debug!("Start mounting Ext4 filesystem... read_only={read_only}");
+
+        // 读写挂载前先做日志崩溃恢复，之后读到的超级块和块组描述符已是重放后的内容
+        // 只读挂载推迟到 remount_rw 再回放；注定被拒绝的读写挂载不回放
+        if !read_only {
+            let superblock = Self::probe_with_policy(block_dev, policy)?;
+            Self::check_rw_ro_compat(&superblock)?;
+            Self::check_journal_addressable(&superblock, block_dev)?;
+            jbd2_recover(block_dev).map_err(|e| {
+                error!("Journal recovery failed: {e:?}");
+                RSEXT4Error::IoError
+            })?;
+        }
+
+        let superblock = Self::probe_with_policy(block_dev, policy)?;
+        if superblock.s_feature_incompat & Ext4Superblock::EXT4_FEATURE_INCOMPAT_RECOVER != 0 {
+            warn!("Journal needs recovery, read-only mount may see stale metadata until remount_rw");
+        }
+        let mut fs = Self::mount_with_superblock(superblock, block_dev, read_only)?;
+        fs.checksum_policy = policy;
+        if let Some(csum) = &mut fs.bitmap_cache.csum {
+            csum.policy = policy;
+        }
+        Ok(fs)
+    }
+
+    /// 读写挂载要求认识全部 ro_compat 特性，否则返回 UnsupportedFeature
+    fn check_rw_ro_compat(superblock: &Ext4Superblock) -> Result<(), RSEXT4Error> {
+        let unsupported = superblock.s_feature_ro_compat & !EXT4_RW_SUPPORTED_RO_COMPAT;
+        if unsupported != 0 {
+            error!("Unsupported ro_compat features {unsupported:#x}, refuse read-write mount");
+            return Err(RSEXT4Error::UnsupportedFeature);
+        }
+        Ok(())
+    }
+
+    /// 日志 tag 只记录 32 位主盘块号（未实现 JBD2 64BIT 特性），
+    /// 启用日志时块数超过 2^32 的文件系统无法经由日志提交高位块的元数据，拒绝读写挂载
+    fn check_journal_addressable<B: BlockDevice>(
+        superblock: &Ext4Superblock,
+        block_dev: &Jbd2Dev<B>,
+    ) -> Result<(), RSEXT4Error> {
+        if block_dev.is_use_journal()
+            && superblock.has_journal()
+            && superblock.blocks_count() > u32::MAX as u64 + 1
+        {
             error!(
-                "Invalid magic: {:#x}, expected: {:#x}",
-                superblock.s_magic, EXT4_SUPER_MAGIC
+                "Journal can't address {} blocks with 32-bit tags, refuse read-write mount",
+                superblock.blocks_count()
             );
-            return Err(RSEXT4Error::InvalidMagic);
+            return Err(RSEXT4Error::UnsupportedFeature);
+        }
+        Ok(())
+    }
+
+    /// 读取并校验超级块，不建立任何内存状态
+    pub fn probe<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> Result<Ext4Superblock, RSEXT4Error> {
+        Self::probe_with_policy(block_dev, ChecksumPolicy::Strict)
+    }
+
+    fn probe_with_policy<B: BlockDevice>(
+        block_dev: &mut Jbd2Dev<B>,
+        policy: ChecksumPolicy,
+    ) -> Result<Ext4Superblock, RSEXT4Error> {
+        // 1. 读取超级块（按 ext4 标准偏移 1024 字节，大小 1024 字节）并校验
+        let mut superblock = Ext4Superblock::default();
+        match block_dev.with_raw_device(|dev| ext4_sb_read(dev, &mut superblock)) {
+            Ok(()) => {}
+            // 2. 校验和不匹配时按 policy 处理
+            Err(BlockDevError::ChecksumError) => policy
+                .check(false, "Superblock")
+                .map_err(superblock_read_error)?,
+            Err(BlockDevError::Corrupted) if !superblock.is_valid() => {
+                error!(
+                    "Invalid magic: {:#x}, expected: {:#x}",
+                    superblock.s_magic, EXT4_SUPER_MAGIC
+                );
+                return Err(RSEXT4Error::InvalidMagic);
+            }
+            Err(e) => return Err(superblock_read_error(e)),
         }
         debug!("Superblock magic verified");
 
@@ -204,17 +416,61 @@ impl Ext4FileSystem {
           //  return Err(RSEXT4Error::FilesystemHasErrors);
         }
 
+        Ok(superblock)
+    }
+
+    /// 用已经读出并校验过的超级块构建文件系统实例（读写挂载）
+    /// 不会重复读取超级块，但仍会加载块组描述符并初始化缓存
+    pub fn from_superblock<B: BlockDevice>(
+        superblock: Ext4Superblock,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> Result<Self, RSEXT4Error> {
+        Self::mount_with_superblock(superblock, block_dev, false)
+    }
+
+    fn mount_with_superblock<B: BlockDevice>(
+        superblock: Ext4Superblock,
+        block_dev: &mut Jbd2Dev<B>,
+        read_only: bool,
+    ) -> Result<Self, RSEXT4Error> {
+        if superblock.s_magic != EXT4_SUPER_MAGIC {
+            return Err(RSEXT4Error::InvalidMagic);
+        }
+
+        // 簇大小必须 >= 块大小，否则簇/块换算没有意义
+        if !superblock.cluster_size_valid() {
+            error!(
+                "Invalid cluster size: log_cluster_size={} log_block_size={}",
+                superblock.s_log_cluster_size, superblock.s_log_block_size
+            );
+            return Err(RSEXT4Error::InvalidSuperblock);
+        }
+
+        // 不认识的 incompat 特性改变了磁盘格式，继续读写只会损坏数据
+        let unsupported = superblock.s_feature_incompat & !EXT4_SUPPORTED_INCOMPAT;
+        if unsupported != 0 {
+            error!("Unsupported incompat features {unsupported:#x}, refuse to mount");
+            return Err(RSEXT4Error::UnsupportedIncompat(unsupported));
+        }
+
+        // 不支持的 ro_compat 特性不影响读取，只允许只读挂载
+        if !read_only {
+            Self::check_rw_ro_compat(&superblock)?;
+            Self::check_journal_addressable(&superblock, block_dev)?;
+        }
+
         // 4. 计算块组数量
         let group_count = superblock.block_groups_count();
         debug!("Block group count: {group_count}");
 
         // 5. 读取所有块组描述符
         let group_descs =
-            Self::load_group_descriptors(block_dev, group_count)?;
+            Self::load_group_descriptors(block_dev, &superblock, group_count)?;
         debug!("Loaded {} group descriptors", group_descs.len());
 
         // 6. 初始化分配器
-        let block_allocator = BlockAllocator::new(&superblock);
+        let mut block_allocator = BlockAllocator::new(&superblock);
+        block_allocator.set_metadata_ranges(metadata_ranges(&superblock, &group_descs, &block_allocator));
         let inode_allocator = InodeAllocator::new(&superblock);
         debug!("Allocators initialized");
 
@@ -247,10 +503,32 @@ impl Ext4FileSystem {
             root_inode: 2, // Ext4根目录固定为inode 2
             inodetable_cahce: inode_cache,
             datablock_cache,
+            dentry_cache: DentryCache::default(),
             group_count,
             mounted: true,
             journal_sb_block_start: None,
+            inode_alloc_policy: InodeAllocPolicy::default(),
+            read_only,
+            open_inodes: BTreeMap::new(),
+            orphan_inodes: BTreeSet::new(),
+            now: frozen_clock,
+            noatime: DEFAULT_NOATIME,
+            online_discard: DEFAULT_ONLINE_DISCARD,
+            pending_discards: Vec::new(),
+            readahead_blocks: DEFAULT_READAHEAD_BLOCKS,
+            reserved_inodes: 0,
+            drawing_reserved_inodes: false,
+            checksum_policy: ChecksumPolicy::default(),
+            gdt_backup_policy: GdtBackupPolicy::default(),
+            gdt_backups_stale: false,
+            cache_capacities: CacheCapacities::default(),
         };
+        fs.init_bitmap_csums();
+        fs.inodetable_cahce.csum_seed =
+            fs.superblock.has_metadata_csum().then(|| fs.superblock.metadata_csum_seed());
+        // 三类缓存的容量从一开始就按 cache_capacities 设置
+        fs.set_cache_capacities(block_dev, fs.cache_capacities)
+            .map_err(|_| RSEXT4Error::IoError)?;
         //详细debug输出
         debug_super_and_desc(&fs.superblock, &fs);
 
@@ -258,6 +536,10 @@ impl Ext4FileSystem {
         debug!("Checking root directory...");
         {
             let root_inode = fs.get_root(block_dev).map_err(|_| RSEXT4Error::IoError)?;
+            if read_only && (root_inode.i_mode == 0 || !root_inode.is_dir()) {
+                error!("Root inode is invalid, can't repair on read-only mount");
+                return Err(RSEXT4Error::InvalidSuperblock);
+            }
             if root_inode.i_mode == 0 || !root_inode.is_dir() {
                 warn!(
                     "Root inode is uninitialized or not a directory, creating root and lost+found... i_mode: {}, is_dir: {}",
@@ -282,66 +564,22 @@ impl Ext4FileSystem {
 
             // 2. 通过路径做一次校验（不会在失败时创建新目录）
             match find_file(&mut fs, block_dev, "/lost+found") {
-                Some(_inode) => {
+                Ok(_inode) => {
                     info!("/lost+found exists (path resolution)");
                 }
-                None => {
+                _ if read_only => {
+                    warn!("/lost+found not found, skip creating on read-only mount");
+                }
+                _ => {
                     info!("/lost+found not found by path scan;will create!");
                     create_lost_found_directory(&mut fs, block_dev).ok();
                 }
             }
         }
 
-        // journal check
-        {
-            if fs.superblock.has_journal() {
-                let mut jouranl_exist: bool = true;
-                fs.modify_inode(block_dev, JOURNAL_FILE_INODE as u32, |ji| {
-                    jouranl_exist = ji.i_mode != 0;
-                })
-                .expect("file system error panic!");
-
-                if fs
-                    .superblock
-                    .has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_HAS_JOURNAL)
-                    && !jouranl_exist
-                {
-                    // 不存在但 superblock 声明有 journal，则创建一个新的 journal 文件
-                    create_journal_entry(&mut fs, block_dev).expect("create journal entry failed");
-                    //dump_journal_inode(&mut fs, block_dev);
-                }
-            }
-            //实际启用Journal
-            if block_dev.is_use_journal() {
-                // 到这里为止：journal inode 一定存在
-                // 初始化 jbd2：读入 journal 超级块并塞进 Jbd2Dev
-                let mut j_inode = fs
-                    .get_inode_by_num(block_dev, JOURNAL_FILE_INODE as u32)
-                    .expect("load journal inode failed");
-
-                // 解析 journal inode 第 0 号逻辑块 -> 物理块
-                let journal_first_block = resolve_inode_block( block_dev, &mut j_inode, 0)
-                    .and_then(|opt| opt.ok_or(BlockDevError::Corrupted))
-                    .expect("resolve journal first block failed");
-
-                //写入fs
-                fs.journal_sb_block_start = Some(journal_first_block);
-                // 通过数据块缓存读出 journal superblock 内容
-                let journal_data = fs
-                    .datablock_cache
-                    .get_or_load(block_dev, journal_first_block as u64)
-                    .expect("load journal superblock block failed")
-                    .data
-                    .clone();
-
-                let j_sb = JournalSuperBllockS::from_disk_bytes(&journal_data);
-
-                // 把 journal superblock 交给 Jbd2Dev，由它内部 lazy-init JBD2DEVSYSTEM
-                block_dev.set_journal_superblock(j_sb, fs.journal_sb_block_start.unwrap());
-
-                // Mount-time journal replay for crash recovery.
-                block_dev.journal_replay(); //这里是在读取超级块之后再进行回放的，目前为了快速开启日志时数据不一致问题已经在写入超级块，块组描述符时直接落盘
-            }
+        // journal check（只读挂载推迟到 remount_rw）
+        if !read_only {
+            fs.setup_journal(block_dev);
         }
 
         //详细的Inode/DataBlock占用情况
@@ -355,15 +593,19 @@ impl Ext4FileSystem {
             let inode_cache_key = CacheKey::new_inode(0);
             let data_cache_key = CacheKey::new_block(0);
 
+            let bitmap_err = |e: BlockDevError| {
+                error!("Group 0 bitmap read failed: {e:?}");
+                RSEXT4Error::FilesystemHasErrors
+            };
             let inode_bitmap_data = fs
                 .bitmap_cache
-                .get_or_load(block_dev, inode_cache_key, inode_bitmap_blk as u64)
-                .expect("Blcok Read Failed!")
+                .get_or_load(block_dev, inode_cache_key, inode_bitmap_blk)
+                .map_err(bitmap_err)?
                 .clone();
             let blockbitmap_data = fs
                 .bitmap_cache
-                .get_or_load(block_dev, data_cache_key, data_bitmap_blk as u64)
-                .expect("Blcok Read Failed!");
+                .get_or_load(block_dev, data_cache_key, data_bitmap_blk)
+                .map_err(bitmap_err)?;
 
             let mut indoe_count: u64 = 0;
             let mut datablock_count: u64 = 0;
@@ -410,20 +652,153 @@ impl Ext4FileSystem {
         info!("  - total inodes: {}", fs.superblock.s_inodes_count);
         info!("  - free inodes: {}", fs.superblock.s_free_inodes_count);
         //缓存刷新回磁盘
-        fs.datablock_cache
-            .flush_all(block_dev)
-            .expect("flush failed!");
-        fs.bitmap_cache.flush_all(block_dev).expect("flush failed!");
-        fs.inodetable_cahce
-            .flush_all(block_dev)
-            .expect("flush failed!");
+        if !read_only {
+            fs.flush_all_caches(block_dev).expect("flush failed!");
+        }
 
         Ok(fs)
     }
 
+    /// 检查 journal inode（缺失则创建），并初始化 jbd2 后回放日志
+    fn setup_journal<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) {
+        if self.superblock.has_journal() {
+            let mut jouranl_exist: bool = true;
+            self.modify_inode(block_dev, JOURNAL_FILE_INODE as u32, |ji| {
+                jouranl_exist = ji.i_mode != 0;
+            })
+            .expect("file system error panic!");
+
+            if self
+                .superblock
+                .has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_HAS_JOURNAL)
+                && !jouranl_exist
+            {
+                // 不存在但 superblock 声明有 journal，则创建一个新的 journal 文件
+                create_journal_entry(self, block_dev).expect("create journal entry failed");
+                //dump_journal_inode(self, block_dev);
+            }
+        }
+        //实际启用Journal
+        if block_dev.is_use_journal() {
+            // 到这里为止：journal inode 一定存在
+            // 初始化 jbd2：读入 journal 超级块并塞进 Jbd2Dev
+            let mut j_inode = self
+                .get_inode_by_num(block_dev, JOURNAL_FILE_INODE as u32)
+                .expect("load journal inode failed");
+
+            // 解析 journal inode 第 0 号逻辑块 -> 物理块
+            let journal_first_block = resolve_inode_block(block_dev, self, JOURNAL_FILE_INODE as u32, &mut j_inode, 0)
+                .and_then(|opt| opt.ok_or(BlockDevError::Corrupted))
+                .expect("resolve journal first block failed");
+
+            //写入fs
+            self.journal_sb_block_start = Some(journal_first_block);
+            // 通过数据块缓存读出 journal superblock 内容
+            let journal_data = self
+                .datablock_cache
+                .get_or_load(block_dev, journal_first_block as u64)
+                .expect("load journal superblock block failed")
+                .data
+                .clone();
+
+            let j_sb = JournalSuperBllockS::from_disk_bytes(&journal_data);
+
+            // 把 journal superblock 交给 Jbd2Dev，由它内部 lazy-init JBD2DEVSYSTEM
+            block_dev.set_journal_superblock(j_sb, journal_first_block);
+
+            // Mount-time journal replay for crash recovery.
+            block_dev.journal_replay(); //这里是在读取超级块之后再进行回放的，目前为了快速开启日志时数据不一致问题已经在写入超级块，块组描述符时直接落盘
+        }
+    }
+
+    /// 将只读挂载升级为读写：
+    /// 检查 ro_compat 特性、执行推迟的日志回放、标记文件系统为未干净卸载
+    /// 回放可能改写超级块、GDT 和任意元数据块，因此回放后按磁盘内容重建全部挂载状态，
+    /// 只保留运行期设置和打开的句柄
+    pub fn remount_rw<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> Ext4Result<()> {
+        if !self.read_only {
+            return Ok(());
+        }
+
+        let unsupported = self.superblock.s_feature_ro_compat & !EXT4_RW_SUPPORTED_RO_COMPAT;
+        if unsupported != 0 {
+            error!("remount_rw: unsupported ro_compat features {unsupported:#x}");
+            return Err(BlockDevError::Unsupported.into());
+        }
+
+        // 读写挂载路径先回放日志，再读超级块、GDT 并初始化缓存
+        let mut fresh = Self::mount_inner(block_dev, false, self.checksum_policy).map_err(|e| {
+            error!("remount_rw: reload after journal replay failed: {e}");
+            Ext4Error::Mount(e)
+        })?;
+        fresh.now = self.now;
+        fresh.noatime = self.noatime;
+        fresh.online_discard = self.online_discard;
+        fresh.readahead_blocks = self.readahead_blocks;
+        fresh.inode_alloc_policy = self.inode_alloc_policy;
+        fresh.gdt_backup_policy = self.gdt_backup_policy;
+        fresh.open_inodes = core::mem::take(&mut self.open_inodes);
+        fresh.set_cache_capacities(block_dev, self.cache_capacities)?;
+        *self = fresh;
+
+        // 挂载期间清除 VALID 标志，umount 时再置回
+        self.superblock.s_state &= !Ext4Superblock::EXT4_VALID_FS;
+        self.sync_superblock(block_dev)?;
+        info!("Filesystem remounted read-write");
+        Ok(())
+    }
+
+    /// 当前时间
+    pub fn current_time(&self) -> Ext4Timestamp {
+        Ext4Timestamp::from_secs((self.now)())
+    }
+
+    /// 新建 inode 应保留的额外字节数：大 inode 至少覆盖全部时间戳 extra 字段
+    pub fn new_inode_extra_isize(&self) -> u16 {
+        if self.superblock.s_inode_size < Ext4Inode::LARGE_INODE_SIZE {
+            return 0;
+        }
+        self.superblock.s_want_extra_isize.max(32)
+    }
+
+    /// 读取文件后更新 atime；noatime 或只读挂载时什么也不做
+    pub fn touch_atime<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        inode_num: u32,
+    ) -> BlockDevResult<()> {
+        if self.noatime || self.read_only {
+            return Ok(());
+        }
+        let now = self.current_time();
+        self.modify_inode(block_dev, inode_num, |inode| inode.set_atime_ts(now))
+    }
+
+    /// 文件内容被修改后同时更新 mtime 和 ctime
+    pub fn touch_mtime_ctime<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        inode_num: u32,
+    ) -> BlockDevResult<()> {
+        let now = self.current_time();
+        self.modify_inode(block_dev, inode_num, |inode| {
+            inode.set_mtime_ts(now);
+            inode.set_ctime_ts(now);
+        })
+    }
+
+    /// 只读挂载时拒绝写操作
+    pub fn ensure_writable(&self) -> BlockDevResult<()> {
+        if self.read_only {
+            return Err(BlockDevError::ReadOnly);
+        }
+        Ok(())
+    }
+
     /// 加载所有块组描述符 顺序性
     fn load_group_descriptors<B: BlockDevice>(
         block_dev: &mut Jbd2Dev<B>,
+        superblock: &Ext4Superblock,
         group_count: u32,
     ) -> Result<Vec<Ext4GroupDesc>, RSEXT4Error> {
         let mut group_descs = Vec::new();
@@ -432,7 +807,6 @@ impl Ext4FileSystem {
         // 为了减少重复读块，这里缓存当前块号
         let mut current_block: Option<u64> = None;
 
-        let superblock = read_superblock(block_dev).map_err(|_| RSEXT4Error::IoError)?;
         let desc_size = superblock.get_desc_size() as usize;
 
         debug!(
@@ -447,7 +821,7 @@ impl Ext4FileSystem {
             // 只在块号变化时重新读取块
             if current_block != Some(block_num) {
                 block_dev
-                    .read_block(block_num as u32)
+                    .read_block(block_num)
                     .map_err(|_| RSEXT4Error::IoError)?;
                 current_block = Some(block_num);
             }
@@ -475,36 +849,67 @@ impl Ext4FileSystem {
         );
         Ok(group_descs)
     }
-    /// 卸载文件系统 不写超级块备份
+    /// 按默认选项卸载文件系统：提交并 checkpoint 日志，备份过期时同步超级块备份
     pub fn umount<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        self.umount_with(block_dev, &UmountOptions::default())
+    }
+
+    /// 按指定选项卸载文件系统
+    pub fn umount_with<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        opts: &UmountOptions,
+    ) -> BlockDevResult<()> {
         if !self.mounted {
             return Ok(());
         }
+        if self.read_only {
+            // 只读挂载没有任何待写回的数据
+            self.mounted = false;
+            info!("Read-only filesystem unmounted");
+            return Ok(());
+        }
 
         debug!("Unmounting Ext4 filesystem...");
 
+        // 仍被打开的已删除 inode 随卸载一并回收
+        let orphans = core::mem::take(&mut self.orphan_inodes);
+        for ino in orphans {
+            reclaim_inode(block_dev, self, ino)?;
+        }
+        self.open_inodes.clear();
+
         // 1. Flush dirty caches
-        info!("Flushing bitmap cache...");
-        self.bitmap_cache.flush_all(block_dev)?;
-        debug!("Bitmap cache flushed");
-        self.inodetable_cahce.flush_all(block_dev)?;
-        debug!("Inode table cache flushed");
-        self.datablock_cache.flush_all(block_dev)?;
-        debug!("Data block cache flushed");
+        info!("Flushing block caches...");
+        self.flush_all_caches(block_dev)?;
+        debug!("Block caches flushed");
+        // 挂载后才打开 metadata_csum（如 migrate 的目标镜像）时位图从未带过校验和
+        if self.superblock.has_metadata_csum() && self.bitmap_cache.csum.is_none() {
+            self.rebuild_bitmap_csums(block_dev)?;
+        }
 
 
-        // 4. Update superblock
+        // 4. Update superblock and group descriptors, including backups
         info!("Writing back superblock...");
+        self.superblock.s_state |= Ext4Superblock::EXT4_VALID_FS;
+        self.superblock.s_feature_incompat &= !Ext4Superblock::EXT4_FEATURE_INCOMPAT_RECOVER;
         self.sync_superblock(block_dev)?;
-        debug!("Superblock updated");
-
-        // Write back group descriptors
-        debug!("Writing back group descriptors...");
         self.sync_group_descriptors(block_dev)?;
+        if opts.sync_backups && self.gdt_backups_stale {
+            self.sync_backup_groups(block_dev)?;
+        }
+        debug!("Superblock and group descriptors updated");
 
         //确保缓存已经提交完毕
         block_dev.umount_commit();
-       
+        self.issue_discards(block_dev)?;
+        if opts.checkpoint {
+            block_dev.checkpoint()?;
+        } else if block_dev.journal_usage().used_blocks > 0 {
+            // 日志里留有未 checkpoint 的事务：标记 needs_recovery，其它实现挂载前会先回放
+            self.superblock.s_feature_incompat |= Ext4Superblock::EXT4_FEATURE_INCOMPAT_RECOVER;
+            self.sync_superblock(block_dev)?;
+        }
 
         self.mounted = false;
         info!("Filesystem unmounted cleanly");
@@ -516,11 +921,19 @@ impl Ext4FileSystem {
     /// 按 ext4 标准布局，将所有块组描述符写回：
     /// GDT 字节流紧跟在超级块之后
     pub fn sync_group_descriptors<B: BlockDevice>(
-        &self,
+        &mut self,
         block_dev: &mut Jbd2Dev<B>,
     ) -> BlockDevResult<()> {
+        self.apply_bitmap_csums();
         let total_desc_count = self.group_descs.len();
         let desc_size = self.superblock.get_desc_size() as usize;
+        // metadata_csum 下每个描述符带自己的校验和，写回前按当前内容重算
+        if self.superblock.has_metadata_csum() {
+            let seed = self.superblock.metadata_csum_seed();
+            for (gid, desc) in self.group_descs.iter_mut().enumerate() {
+                desc.bg_checksum = desc.metadata_csum(seed, gid as u32, desc_size);
+            }
+        }
 
         // GDT 基地址统一为块号 1 的起始字节偏移
         let gdt_base: u64 = BLOCK_SIZE as u64;
@@ -533,6 +946,8 @@ impl Ext4FileSystem {
         // 为了避免频繁读写，按块聚合写回
         let mut current_block: Option<u64> = None;
         let mut buffer_snapshot_block: Option<u64> = None;
+        // 读入时的块内容，用于判断主 GDT 是否真的变化（变化后备份即过期）
+        let mut original: Vec<u8> = Vec::new();
 
         for (idx, desc) in self.group_descs.iter().enumerate() {
             let byte_offset = gdt_base + idx as u64 * desc_size as u64;
@@ -544,12 +959,15 @@ impl Ext4FileSystem {
             if current_block != Some(block_num) {
                 if let Some(prev_block) = current_block
                     && Some(prev_block) == buffer_snapshot_block {
+                        self.gdt_backups_stale |= block_dev.buffer() != &original[..];
                         //由于目前日志回放在fs构建之后（块组描述符读取之后），目前为了快速修复防止读取到旧的超级块。直接落盘写回
-                        block_dev.write_block(prev_block as u32, false)?;
+                        block_dev.write_block(prev_block, false)?;
                     }
 
                 // 读取新块
-                block_dev.read_block(block_num as u32)?;
+                block_dev.read_block(block_num)?;
+                original.clear();
+                original.extend_from_slice(block_dev.buffer());
                 current_block = Some(block_num);
                 buffer_snapshot_block = Some(block_num);
             }
@@ -572,7 +990,8 @@ impl Ext4FileSystem {
         // 写回最后一个块
         if let Some(last_block) = current_block
             && Some(last_block) == buffer_snapshot_block {
-                block_dev.write_block(last_block as u32, true)?;
+                self.gdt_backups_stale |= block_dev.buffer() != &original[..];
+                block_dev.write_block(last_block, true)?;
             }
 
         debug!("Group descriptors written back");
@@ -583,17 +1002,244 @@ impl Ext4FileSystem {
     /// 同步超级块到磁盘
     pub fn sync_superblock<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
         //同步group_desc 和 super_block计数
-        let mut real_free_blocks: u64 = 0;
+        //块组描述符按簇计数，超级块按块计数
+        let mut real_free_clusters: u64 = 0;
         let mut real_free_inodes: u64 = 0;
         for desc in &self.group_descs {
-            real_free_blocks += desc.free_blocks_count() as u64;
+            real_free_clusters += desc.free_blocks_count() as u64;
             real_free_inodes += desc.free_inodes_count() as u64;
         }
+        let real_free_blocks = self.superblock.clusters_to_blocks(real_free_clusters);
         self.superblock.s_free_blocks_count_lo = (real_free_blocks & 0xFFFFFFFF) as u32;
         self.superblock.s_free_blocks_count_hi = (real_free_blocks >> 32) as u32;
         self.superblock.s_free_inodes_count = real_free_inodes as u32;
 
-        write_superblock(block_dev, &self.superblock)
+        write_superblock(block_dev, &mut self.superblock)
+    }
+
+    /// 启用 metadata_csum 时让位图缓存加载时校验、写回时重算位图校验和
+    /// 带 BLOCK_UNINIT / INODE_UNINIT 标志的位图不在磁盘上，不做校验
+    fn init_bitmap_csums(&mut self) {
+        let sb = &self.superblock;
+        if !sb.has_metadata_csum() {
+            return;
+        }
+        let mut csum = BitmapCsum::new(
+            sb.metadata_csum_seed(),
+            (sb.s_blocks_per_group / sb.cluster_ratio()) as usize / 8,
+            sb.s_inodes_per_group as usize / 8,
+            sb.get_desc_size() as usize >= Ext4GroupDesc::EXT4_DESC_SIZE_64BIT,
+            self.checksum_policy,
+        );
+        for (gid, desc) in self.group_descs.iter().enumerate() {
+            if !desc.is_block_bitmap_uninit() {
+                csum.set_expected(CacheKey::new_block(gid as u32), desc.block_bitmap_csum());
+            }
+            if !desc.is_inode_bitmap_uninit() {
+                csum.set_expected(CacheKey::new_inode(gid as u32), desc.inode_bitmap_csum());
+            }
+        }
+        self.bitmap_cache.csum = Some(csum);
+    }
+
+    /// 按磁盘上的位图重算所有已初始化位图的校验和，并启用位图校验
+    /// 调用前位图缓存须已写回
+    pub fn rebuild_bitmap_csums<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<()> {
+        self.bitmap_cache.csum = None;
+        self.init_bitmap_csums();
+        let Some(mut csum) = self.bitmap_cache.csum.take() else {
+            return Ok(());
+        };
+        for gid in 0..self.group_descs.len() {
+            let desc = self.group_descs[gid];
+            let gid = gid as u32;
+            if !desc.is_block_bitmap_uninit() {
+                let key = CacheKey::new_block(gid);
+                block_dev.read_block(desc.block_bitmap())?;
+                let sum = csum.compute(&key, block_dev.buffer());
+                csum.set_expected(key, sum);
+                self.group_descs[gid as usize].set_block_bitmap_csum(sum);
+            }
+            if !desc.is_inode_bitmap_uninit() {
+                let key = CacheKey::new_inode(gid);
+                block_dev.read_block(desc.inode_bitmap())?;
+                let sum = csum.compute(&key, block_dev.buffer());
+                csum.set_expected(key, sum);
+                self.group_descs[gid as usize].set_inode_bitmap_csum(sum);
+            }
+        }
+        self.bitmap_cache.csum = Some(csum);
+        Ok(())
+    }
+
+    /// 给已有文件系统开启 metadata_csum：补上目录块尾，并重算所有元数据的校验和
+    /// （超级块、块组描述符、位图、inode、目录块、extent 树块、扩展属性块）
+    /// 目录叶子块放不下块尾时返回 NoSpace；已开启时直接返回
+    pub fn enable_metadata_csum<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<()> {
+        if self.superblock.has_metadata_csum() {
+            return Ok(());
+        }
+        self.ensure_writable()?;
+        self.flush_all_caches(block_dev)?;
+
+        // 开启标志后读取会校验这些块，所以先在未开启时收集
+        let mut inodes = Vec::new();
+        let mut dir_blocks = Vec::new();
+        let mut tree_blocks = Vec::new();
+        let mut xattr_blocks = BTreeSet::new();
+        let ipg = self.superblock.s_inodes_per_group;
+        for gid in 0..self.group_descs.len() as u32 {
+            if self.group_descs[gid as usize].is_inode_bitmap_uninit() {
+                continue;
+            }
+            for ino in gid * ipg + 1..=(gid + 1) * ipg {
+                if !self.inode_num_already_allocted(block_dev, ino as u64) {
+                    continue;
+                }
+                inodes.push(ino);
+                let mut inode = self.get_inode_by_num(block_dev, ino)?;
+                if inode.i_mode == 0 {
+                    continue;
+                }
+                if inode.is_dir() && !inode.has_inline_data() {
+                    let seed_inode = inode;
+                    let mut index = BTreeMap::new();
+                    if inode.i_flags & Ext4Inode::EXT4_INDEX_FL != 0 {
+                        index.extend(dx_index_blocks(self, block_dev, ino, &seed_inode)?);
+                    }
+                    for (lbn, blk) in resolve_inode_block_allextend(self, block_dev, ino, &mut inode)? {
+                        dir_blocks.push((ino, seed_inode, blk, index.get(&lbn).copied()));
+                    }
+                }
+                for blk in collect_extent_tree_blocks(block_dev, self, ino, &mut inode)? {
+                    tree_blocks.push((ino, inode.i_generation, blk));
+                }
+                if inode.file_acl() != 0 {
+                    xattr_blocks.insert(inode.file_acl());
+                }
+            }
+        }
+
+        self.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        self.superblock.s_checksum_type = 1;
+        let fs_seed = self.superblock.metadata_csum_seed();
+        self.inodetable_cahce.csum_seed = Some(fs_seed);
+
+        for (ino, dir_inode, blk, countlimit_off) in dir_blocks {
+            let seed = dir_csum_seed(self, ino, &dir_inode).ok_or(BlockDevError::Corrupted)?;
+            let mut fits = true;
+            self.datablock_cache.modify(block_dev, blk, |data| {
+                fits = dir_csum::add_tail(data, countlimit_off);
+                dir_csum::refresh(seed, data);
+            })?;
+            if !fits {
+                error!("dir {ino} block {blk} has no room for a checksum tail");
+                return Err(BlockDevError::NoSpace);
+            }
+            self.datablock_cache.flush_as_metadata(block_dev, blk)?;
+        }
+        for (ino, generation, blk) in tree_blocks {
+            let seed = ExtentTree::inode_csum_seed(fs_seed, ino, generation);
+            block_dev.read_block(blk)?;
+            ExtentTree::refresh_block_checksum(seed, block_dev.buffer_mut());
+            block_dev.write_block(blk, true)?;
+        }
+        for blk in xattr_blocks {
+            refresh_xattr_block_csum(self, block_dev, blk)?;
+        }
+        for ino in inodes {
+            self.modify_inode(block_dev, ino, |_| {})?;
+        }
+
+        self.flush_all_caches(block_dev)?;
+        self.rebuild_bitmap_csums(block_dev)?;
+        self.gdt_backups_stale = true;
+        self.sync_group_descriptors(block_dev)?;
+        self.sync_superblock(block_dev)
+    }
+
+    /// 把位图写回时重算的校验和填入块组描述符
+    fn apply_bitmap_csums(&mut self) {
+        for (key, csum) in self.bitmap_cache.take_csum_updates() {
+            let Some(desc) = self.group_descs.get_mut(key.group_id as usize) else {
+                continue;
+            };
+            match key.bitmap_type {
+                BitmapType::Block => desc.set_block_bitmap_csum(csum),
+                BitmapType::Inode => desc.set_inode_bitmap_csum(csum),
+            }
+        }
+    }
+
+    /// 写回块组描述符：主 GDT 立即写入（内容变化时记下备份已过期），备份按 gdt_backup_policy 处理，
+    /// Lazy 下留到卸载时由 sync_backups 统一同步
+    pub fn write_group_descriptors<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<()> {
+        self.sync_group_descriptors(block_dev)?;
+        match self.gdt_backup_policy {
+            GdtBackupPolicy::Eager if self.gdt_backups_stale => self.sync_backup_groups(block_dev),
+            _ => Ok(()),
+        }
+    }
+
+    /// 同步超级块和块组描述符，并写到每个存放备份的块组（由 ext4_sb_is_super_in_bg 决定）
+    /// 备份超级块的 s_block_group_nr 为所在块组号，校验和按各自内容重新计算
+    pub fn sync_superblocks<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        self.sync_superblock(block_dev)?;
+        self.sync_group_descriptors(block_dev)?;
+        self.sync_backup_groups(block_dev)
+    }
+
+    /// 把磁盘上的主超级块/GDT 复制到各备份块组，完成后备份不再过期
+    fn sync_backup_groups<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        let sb = self.superblock;
+        let group_count = self.group_count;
+        let gdt_blocks = gdt_block_count(&sb) as usize;
+        block_dev.with_raw_device(|dev| {
+            // 备份 GDT 与主 GDT 逐字节相同
+            let mut gdt = alloc::vec![0u8; gdt_blocks * BLOCK_SIZE];
+            dev.read(&mut gdt, PRIMARY_GDT_BLOCK, gdt_blocks as u32)?;
+            for gid in 1..group_count {
+                if !ext4_sb_is_super_in_bg(&sb, gid) {
+                    continue;
+                }
+                let start = backup_group_start(&sb, gid);
+                let mut backup = sb;
+                backup.s_block_group_nr = gid as u16;
+                ext4_sb_set_csum(&mut backup);
+                let mut buf = [0u8; BLOCK_SIZE];
+                backup.to_disk_bytes(&mut buf[..SUPERBLOCK_SIZE]);
+                dev.write64(&buf, start, 1)?;
+                dev.write64(&gdt, start + 1, gdt_blocks as u32)?;
+            }
+            dev.flush()
+        })?;
+        self.gdt_backups_stale = false;
+        Ok(())
+    }
+
+    /// 把一次多步操作涉及的元数据作为一个 JBD2 事务提交：
+    /// 指定的目录块、inode 表、位图和块组描述符依次进入事务后立即 commit
+    pub fn commit_metadata<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        dir_blocks: &[u64],
+    ) -> BlockDevResult<()> {
+        for &blk in dir_blocks {
+            self.datablock_cache.flush_as_metadata(block_dev, blk)?;
+        }
+        self.inodetable_cahce.flush_all(block_dev)?;
+        self.bitmap_cache.flush_all(block_dev)?;
+        self.write_group_descriptors(block_dev)?;
+        block_dev.commit_journal()
     }
 
     /// 获取块组描述符
@@ -617,59 +1263,139 @@ impl Ext4FileSystem {
         B: BlockDevice,
         F: FnOnce(&mut Ext4Inode),
     {
-        // 通过全局 inode 号计算所属块组
-        let (group_idx, _idx_in_group) = self.inode_allocator.global_to_group(inode_num);
-
-        let inode_table_start = self
-            .group_descs
-            .get(group_idx as usize)
-            .ok_or(BlockDevError::Corrupted)?
-            .inode_table();
-
-        let (block_num, offset, _g) = self.inodetable_cahce.calc_inode_location(
-            inode_num,
-            self.superblock.s_inodes_per_group,
-            inode_table_start,
-            BLOCK_SIZE,
-        );
+        self.ensure_writable()?;
+        let (block_num, offset) = self.locate_inode(block_dev, inode_num)?;
 
         self.inodetable_cahce
             .modify(block_dev, inode_num as u64, block_num, offset, f)
     }
 
-    /// 按 inode 号加载 inode（只读），内部自动计算在磁盘上的位置
-    pub fn get_inode_by_num<B: BlockDevice>(
+    /// 同 modify_inode，闭包同时修改 inode 之后的扩展属性区
+    pub fn modify_inode_with_xattr_area<B, F>(
         &mut self,
         block_dev: &mut Jbd2Dev<B>,
         inode_num: u32,
-    ) -> BlockDevResult<Ext4Inode> {
-        let (group_idx, _idx_in_group) = self.inode_allocator.global_to_group(inode_num);
+        f: F,
+    ) -> BlockDevResult<()>
+    where
+        B: BlockDevice,
+        F: FnOnce(&mut Ext4Inode, &mut [u8]),
+    {
+        self.ensure_writable()?;
+        let (block_num, offset) = self.locate_inode(block_dev, inode_num)?;
+        self.inodetable_cahce
+            .modify_with_xattr_area(block_dev, inode_num as u64, block_num, offset, f)
+    }
 
-        let inode_table_start = self
+    /// 计算 inode 所在的 (块号, 块内偏移)
+    /// 先校验所属块组描述符给出的 inode 表：整张表必须落在设备和文件系统范围内，
+    /// 未启用 flex_bg 时还必须位于本块组之内，否则返回 Corrupted，避免按坏描述符读到设备之外
+    pub fn locate_inode<B: BlockDevice>(
+        &self,
+        block_dev: &Jbd2Dev<B>,
+        inode_num: u32,
+    ) -> BlockDevResult<(u64, usize)> {
+        if inode_num == 0 || self.superblock.s_inodes_per_group == 0 {
+            return Err(BlockDevError::InvalidInput);
+        }
+        let group_idx = (inode_num - 1) / self.superblock.s_inodes_per_group;
+        let desc = self
             .group_descs
             .get(group_idx as usize)
-            .ok_or(BlockDevError::Corrupted)?
-            .inode_table();
-
-        let (block_num, offset, _g) = self.inodetable_cahce.calc_inode_location(
-            inode_num,
-            self.superblock.s_inodes_per_group,
-            inode_table_start,
-            BLOCK_SIZE,
-        );
-
-        let cached =
-            self.inodetable_cahce
-                .get_or_load(block_dev, inode_num as u64, block_num, offset)?;
-        Ok(cached.inode)
-    }
+            .ok_or(BlockDevError::Corrupted)?;
+
+        let table_start = desc.inode_table();
+        let table_end = table_start + self.superblock.inode_table_blocks() as u64;
+        let (mut region_start, mut region_end) = (1, self.superblock.blocks_count());
+        if !self
+            .superblock
+            .has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_FLEX_BG)
+        {
+            let bpg = self.superblock.s_blocks_per_group as u64;
+            region_start = self.superblock.s_first_data_block as u64 + group_idx as u64 * bpg;
+            region_end = region_end.min(region_start + bpg);
+        }
+        region_end = region_end.min(block_dev.total_blocks());
+        if table_start < region_start.max(1) || table_end > region_end {
+            error!(
+                "group {group_idx} inode table [{table_start}, {table_end}) outside [{region_start}, {region_end})"
+            );
+            return Err(BlockDevError::Corrupted);
+        }
+
+        let (_g, block_num, offset) = inode_location(&self.superblock, desc, inode_num)?;
+        Ok((block_num, offset))
+    }
+
+    /// 按 inode 号加载 inode（只读），内部自动计算在磁盘上的位置
+    pub fn get_inode_by_num<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        inode_num: u32,
+    ) -> BlockDevResult<Ext4Inode> {
+        let (block_num, offset) = self.locate_inode(block_dev, inode_num)?;
+
+        let cached =
+            self.inodetable_cahce
+                .get_or_load(block_dev, inode_num as u64, block_num, offset)?;
+        Ok(cached.inode)
+    }
+
+    /// 按 inode 号加载 inode 及其后的扩展属性区（只读）
+    pub fn get_inode_with_xattr_area<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        inode_num: u32,
+    ) -> BlockDevResult<(Ext4Inode, Vec<u8>)> {
+        let (block_num, offset) = self.locate_inode(block_dev, inode_num)?;
+        let cached =
+            self.inodetable_cahce
+                .get_or_load(block_dev, inode_num as u64, block_num, offset)?;
+        Ok((cached.inode, cached.xattr_area.clone()))
+    }
+
+    /// 登记一个打开 ino 的句柄
+    pub fn inode_opened(&mut self, ino: u32) {
+        *self.open_inodes.entry(ino).or_insert(0) += 1;
+    }
+
+    /// 注销一个句柄，返回 ino 是否已没有打开的句柄
+    pub fn inode_closed(&mut self, ino: u32) -> bool {
+        match self.open_inodes.get_mut(&ino) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            _ => {
+                self.open_inodes.remove(&ino);
+                true
+            }
+        }
+    }
+
+    /// ino 是否仍有打开的句柄
+    pub fn inode_is_open(&self, ino: u32) -> bool {
+        self.open_inodes.contains_key(&ino)
+    }
 
     /// 在整个文件系统中分配指定数量的连续数据块
+    /// bigalloc 下按整簇占用位图，块组描述符按簇计数
     pub fn alloc_blocks<B: BlockDevice>(
         &mut self,
         block_dev: &mut Jbd2Dev<B>,
         count: u32,
     ) -> BlockDevResult<Vec<u64>> {
+        self.alloc_blocks_from_group(block_dev, 0, count)
+    }
+
+    /// 同 alloc_blocks，但从 start_group 开始依次环绕扫描块组
+    fn alloc_blocks_from_group<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        start_group: u32,
+        count: u32,
+    ) -> BlockDevResult<Vec<u64>> {
+        self.ensure_writable()?;
         if count == 0 {
             return Ok(Vec::new());
         }
@@ -678,79 +1404,271 @@ impl Ext4FileSystem {
             "alloc_blocks: request count={count} (will scan groups for free space)"
         );
 
-        // 选择一个有足够空闲块的块组，并在该组内做连续分配
-        for (idx, desc) in self.group_descs.iter().enumerate() {
-            let group_idx = idx as u32;
+        let clusters = self.block_allocator.clusters_for(count);
+
+        // 选择一个有足够空闲簇的块组，并在该组内做连续分配
+        // 每组只扫描一遍位图：优先绕开避开区间和预留窗口，
+        // 记下第一个忽略这些限制也能放下的位置，所有组都没有其它空间时再用它
+        let groups = self.group_descs.len() as u32;
+        let mut fallback: Option<(u32, u32)> = None;
+        for step in 0..groups {
+            let group_idx = (start_group + step) % groups;
+            let desc = &self.group_descs[group_idx as usize];
             let free = desc.free_blocks_count();
 
             trace!(
-                "alloc_blocks: inspect group={group_idx} free_blocks={free} need={count}"
+                "alloc_blocks: inspect group={group_idx} free_clusters={free} need={clusters}"
             );
 
-            if free < count {
+            if free < clusters {
                 continue;
             }
 
             let bitmap_block = desc.block_bitmap();
-            let cache_key = CacheKey::new_block(group_idx);
-            let mut alloc_res: Result<BlockAlloc, BlockDevError> = Err(BlockDevError::NoSpace);
+            let bitmap =
+                self.bitmap_cache
+                    .get_or_load(block_dev, CacheKey::new_block(group_idx), bitmap_block)?;
+            let found = self.block_allocator.find_contiguous(&bitmap.data, group_idx, count);
+            if let Some((cluster, _)) = found.honored {
+                return self.claim_contiguous(block_dev, group_idx, cluster, count);
+            }
+            if fallback.is_none() {
+                fallback = found.fallback.map(|(cluster, _)| (group_idx, cluster));
+            }
+        }
+        if let Some((group_idx, cluster)) = fallback {
+            return self.claim_contiguous(block_dev, group_idx, cluster, count);
+        }
 
-            debug!(
-                "alloc_blocks: candidate group={group_idx} bitmap_block={bitmap_block} starting contiguous allocation of {count} blocks"
-            );
+        debug!(
+            "alloc_blocks: no group has enough free blocks for request count={count}"
+        );
 
-            self.bitmap_cache
-                .modify(block_dev, cache_key, bitmap_block, |data| {
-                    // 这里只修改位图，不直接接触 group_desc / superblock 计数
-                    let r = self
-                        .block_allocator
-                        .alloc_contiguous_blocks(data, group_idx, count);
-                    alloc_res = r.map_err(|_| BlockDevError::NoSpace);
-                })?;
-
-            let alloc = alloc_res?;
-
-            // 更新块组描述符
-            if let Some(desc_mut) = self.get_group_desc_mut(group_idx) {
-                let before = desc_mut.free_blocks_count();
-                let new_count = before.saturating_sub(count);
-                desc_mut.bg_free_blocks_count_lo = (new_count & 0xFFFF) as u16;
-                desc_mut.bg_free_blocks_count_hi = (new_count >> 16) as u16;
-
-                debug!(
-                    "alloc_blocks: group={} free_blocks_count change {} -> {} (allocated {} blocks starting at global={})",
-                    group_idx, before, new_count, count, alloc.global_block
-                );
+        Err(BlockDevError::NoSpace)
+    }
+
+    /// 占用组内从 cluster 开始、容纳 count 个块的连续簇，返回各块号
+    fn claim_contiguous<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        group_idx: u32,
+        cluster: u32,
+        count: u32,
+    ) -> BlockDevResult<Vec<u64>> {
+        let clusters = self.block_allocator.clusters_for(count);
+        let alloc = self.claim_clusters(block_dev, group_idx, cluster, clusters)?;
+        debug!(
+            "Allocated blocks: group={}, first_block_in_group={}, first_global_block={}, count={} [bitmap updated, writeback deferred]",
+            alloc.group_idx, alloc.block_in_group, alloc.global_block, count
+        );
+        Ok((0..count as u64).map(|off| alloc.global_block + off).collect())
+    }
+
+    /// 在位图中占用组内从 first 开始的 clusters 个簇，并从块组描述符和超级块中扣除
+    fn claim_clusters<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        group_idx: u32,
+        first: u32,
+        clusters: u32,
+    ) -> BlockDevResult<BlockAlloc> {
+        let bitmap_block = self.group_descs[group_idx as usize].block_bitmap();
+        let mut alloc_res: Result<BlockAlloc, AllocError> = Err(AllocError::NoSpace);
+        self.bitmap_cache
+            .modify(block_dev, CacheKey::new_block(group_idx), bitmap_block, |data| {
+                alloc_res = self.block_allocator.allocate_clusters(data, group_idx, first, clusters);
+            })?;
+        let alloc = alloc_res.map_err(|_| BlockDevError::Corrupted)?;
+        // 块组描述符和超级块一起扣除（超级块按块计数，整簇扣除）
+        self.adjust_free_clusters(group_idx, -(clusters as i64));
+        Ok(alloc)
+    }
+
+    /// 在 goal 附近分配最多 count 个连续数据块，返回 (起始块号, 实际块数)
+    /// 从 goal 所在块组开始依次尝试各组，组内从 goal 向后找第一段空闲区；
+    /// 找不到 count 个连续块时返回较短的一段，调用方继续分配剩余部分
+    /// goal 通常取文件最后一个 extent 的下一块；为 None 表示没有偏好，
+    /// 此时先整段寻找 count 个连续块，避免新文件落进零碎的空闲块；
+    /// goal 为块组起点（data_goal_for_inode 给出的块组提示）时同样整段寻找，只是从该组开始
+    pub fn alloc_blocks_near<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        goal: Option<u64>,
+        count: u32,
+    ) -> BlockDevResult<(u64, u32)> {
+        self.ensure_writable()?;
+        if count == 0 {
+            return Err(BlockDevError::InvalidInput);
+        }
+        let goal = goal.unwrap_or(0);
+        let groups = self.group_descs.len() as u32;
+        let (goal_group, goal_in_group) = self.block_allocator.global_to_group(goal);
+        let goal_group = if goal_group < groups { goal_group } else { 0 };
+        if goal == self.block_allocator.group_first_block(goal_group)
+            && let Ok(blocks) = self.alloc_blocks_from_group(block_dev, goal_group, count)
+        {
+            return Ok((blocks[0], count));
+        }
+        // 与 alloc_blocks 相同：每组扫描一遍，绕不开建议限制时才用记下的兜底位置
+        let mut fallback: Option<(u32, u32, u32)> = None;
+        for step in 0..groups {
+            let group_idx = (goal_group + step) % groups;
+            let desc = &self.group_descs[group_idx as usize];
+            if desc.free_blocks_count() == 0 {
+                continue;
             }
+            let bitmap_block = desc.block_bitmap();
+            let start = if step == 0 { goal_in_group } else { 0 };
+            let bitmap =
+                self.bitmap_cache
+                    .get_or_load(block_dev, CacheKey::new_block(group_idx), bitmap_block)?;
+            let found = self.block_allocator.find_run_near(&bitmap.data, group_idx, start, count);
+            if let Some((first, run)) = found.honored {
+                return self.claim_run(block_dev, goal, group_idx, first, run, count);
+            }
+            if fallback.is_none() {
+                fallback = found.fallback.map(|(first, run)| (group_idx, first, run));
+            }
+        }
+        if let Some((group_idx, first, run)) = fallback {
+            return self.claim_run(block_dev, goal, group_idx, first, run, count);
+        }
 
-            // 更新超级块
-            let sb_before = self.superblock.free_blocks_count();
-            self.superblock.s_free_blocks_count_lo =
-                self.superblock.s_free_blocks_count_lo.saturating_sub(count);
-            let sb_after = self.superblock.free_blocks_count();
+        Err(BlockDevError::NoSpace)
+    }
 
-            debug!(
-                "alloc_blocks: superblock free_blocks_count change {sb_before} -> {sb_after} (delta=-{count})"
-            );
+    /// 占用 find_run_near 找到的 run 个簇，返回 (起始块号, 块数)，块数不超过 count
+    fn claim_run<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        goal: u64,
+        group_idx: u32,
+        first: u32,
+        run: u32,
+        count: u32,
+    ) -> BlockDevResult<(u64, u32)> {
+        let alloc = self.claim_clusters(block_dev, group_idx, first, run)?;
+        let blocks = (run * self.block_allocator.cluster_ratio()).min(count);
+        debug!(
+            "alloc_blocks_near: goal={goal} group={group_idx} got {blocks}/{count} blocks at {}",
+            alloc.global_block
+        );
+        Ok((alloc.global_block, blocks))
+    }
 
-            let mut blocks = Vec::with_capacity(count as usize);
-            for off in 0..count {
-                blocks.push(alloc.global_block + off as u64);
+    /// 为文件 ino 分配最多 count 个连续块，返回 (起始块号, 实际块数)
+    /// goal 恰好是该 inode 预留窗口的起点时直接从窗口取块；
+    /// 否则丢弃旧窗口，在 goal 附近分配，并在新分配区之后预留下一个窗口，
+    /// 使交替增长的多个文件各自保持连续
+    pub fn alloc_blocks_for<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        ino: u32,
+        goal: Option<u64>,
+        count: u32,
+    ) -> BlockDevResult<(u64, u32)> {
+        self.ensure_writable()?;
+        if count == 0 {
+            return Err(BlockDevError::InvalidInput);
+        }
+
+        if let Some((start, end)) = self.block_allocator.reservation(ino) {
+            self.block_allocator.release_reservation(ino);
+            if Some(start) == goal {
+                let want = count.min((end - start) as u32);
+                let got = self.claim_blocks_at(block_dev, start, want)?;
+                if got > 0 {
+                    let rest = end - start - got as u64;
+                    self.block_allocator.set_reservation(ino, start + got as u64, rest);
+                    return Ok((start, got));
+                }
             }
+        }
 
-            debug!(
-                "Allocated blocks: group={}, first_block_in_group={}, first_global_block={}, count={} [bitmap updated, writeback deferred]",
-                alloc.group_idx, alloc.block_in_group, alloc.global_block, count
-            );
+        let (start, blocks) = self.alloc_blocks_near(block_dev, goal, count)?;
+        self.reserve_window_at(block_dev, ino, start + blocks as u64)?;
+        Ok((start, blocks))
+    }
 
-            return Ok(blocks);
+    /// bigalloc 下 prev 是文件逻辑上前一块的物理块：它所在的簇已归该文件所有，
+    /// 簇内 prev 之后的块可直接用于下一个逻辑块，不占用新簇也不修改位图
+    /// 所有分配都从簇首开始，簇内已用块总是紧跟 prev 的后继，因此这些块必然空闲
+    /// 返回 (起始块号, 块数)；未开启 bigalloc 或 prev 是簇内最后一块时返回 None
+    pub fn implied_cluster_run(&self, prev: u64, count: u32) -> Option<(u64, u32)> {
+        let ratio = self.block_allocator.cluster_ratio();
+        if ratio == 1 || count == 0 {
+            return None;
+        }
+        let (group_idx, block_in_group) = self.block_allocator.global_to_group(prev + 1);
+        if block_in_group % ratio == 0 || self.block_allocator.global_to_group(prev).0 != group_idx {
+            return None;
         }
+        let rest = ratio - block_in_group % ratio;
+        Some((prev + 1, rest.min(count)))
+    }
 
-        debug!(
-            "alloc_blocks: no group has enough free blocks for request count={count}"
-        );
+    /// 释放 ino 的预留窗口
+    pub fn release_reservation(&mut self, ino: u32) {
+        self.block_allocator.release_reservation(ino);
+    }
 
-        Err(BlockDevError::NoSpace)
+    /// 原地占用从 start 开始最多 count 个连续空闲块，返回实际占用的块数
+    fn claim_blocks_at<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        start: u64,
+        count: u32,
+    ) -> BlockDevResult<u32> {
+        let (group_idx, block_in_group) = self.block_allocator.global_to_group(start);
+        let Some(desc) = self.group_descs.get(group_idx as usize) else {
+            return Ok(0);
+        };
+        let bitmap_block = desc.block_bitmap();
+        let mut got = 0u32;
+        self.bitmap_cache.modify(
+            block_dev,
+            CacheKey::new_block(group_idx),
+            bitmap_block,
+            |data| {
+                got = self
+                    .block_allocator
+                    .alloc_exact(data, group_idx, block_in_group, count)
+                    .unwrap_or(0);
+            },
+        )?;
+        if got > 0 {
+            self.adjust_free_clusters(group_idx, -(got as i64));
+        }
+        Ok(got)
+    }
+
+    /// 在 next 处为 ino 预留最多 RESERVATION_WINDOW_BLOCKS 个空闲块，不修改位图
+    /// bigalloc 下不预留
+    fn reserve_window_at<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        ino: u32,
+        next: u64,
+    ) -> BlockDevResult<()> {
+        if RESERVATION_WINDOW_BLOCKS == 0 || self.block_allocator.cluster_ratio() != 1 {
+            return Ok(());
+        }
+        let (group_idx, block_in_group) = self.block_allocator.global_to_group(next);
+        let Some(desc) = self.group_descs.get(group_idx as usize) else {
+            return Ok(());
+        };
+        let bitmap_block = desc.block_bitmap();
+        let bitmap =
+            self.bitmap_cache
+                .get_or_load(block_dev, CacheKey::new_block(group_idx), bitmap_block)?;
+        let run = self.block_allocator.free_run_at(
+            &bitmap.data,
+            group_idx,
+            block_in_group,
+            RESERVATION_WINDOW_BLOCKS,
+        );
+        self.block_allocator.set_reservation(ino, next, run as u64);
+        Ok(())
     }
 
     /// 在整个文件系统中分配一个数据块（兼容旧接口）
@@ -773,60 +1691,226 @@ impl Ext4FileSystem {
         }
 
         // 目前按“同一块组内尽量连续”策略，从第一个有足够空闲 inode 的组开始分配
-        for (idx, desc) in self.group_descs.iter().enumerate() {
-            let group_idx = idx as u32;
-            let free = desc.free_inodes_count();
-            if free < count {
-                continue;
-            }
+        let group_idx = self
+            .group_descs
+            .iter()
+            .position(|desc| desc.free_inodes_count() >= count)
+            .ok_or(BlockDevError::NoSpace)? as u32;
+        self.alloc_inodes_in_group(block_dev, group_idx, count)
+    }
 
-            let bitmap_block = desc.inode_bitmap();
-            let cache_key = CacheKey::new_inode(group_idx);
+    /// 在指定块组内分配 count 个 inode（取组内最低的空闲 inode）
+    fn alloc_inodes_in_group<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        group_idx: u32,
+        count: u32,
+    ) -> BlockDevResult<Vec<u32>> {
+        self.ensure_writable()?;
+        // 预留额度之外的分配不能把空闲 inode 用到预留数以下
+        if !self.drawing_reserved_inodes
+            && self.superblock.s_free_inodes_count.saturating_sub(self.reserved_inodes) < count
+        {
+            return Err(BlockDevError::NoSpace);
+        }
+        let desc = self
+            .group_descs
+            .get(group_idx as usize)
+            .ok_or(BlockDevError::Corrupted)?;
+        let bitmap_block = desc.inode_bitmap();
+        let cache_key = CacheKey::new_inode(group_idx);
 
-            let mut inodes: Vec<u32> = Vec::with_capacity(count as usize);
+        let mut inodes: Vec<u32> = Vec::with_capacity(count as usize);
 
-            self.bitmap_cache
-                .modify(block_dev, cache_key, bitmap_block, |data| {
-                    // 简化实现：在同一块组中循环调用 alloc_inode_in_group，得到 count 个 inode
-                    for _ in 0..count {
-                        let r = self
-                            .inode_allocator
-                            .alloc_inode_in_group(data, group_idx, desc);
-                        match r {
-                            Ok(InodeAlloc { global_inode, .. }) => {
-                                inodes.push(global_inode);
-                            }
-                            Err(_) => {
-                                break;
-                            }
+        self.bitmap_cache
+            .modify(block_dev, cache_key, bitmap_block, |data| {
+                // 简化实现：在同一块组中循环调用 alloc_inode_in_group，得到 count 个 inode
+                for _ in 0..count {
+                    let r = self
+                        .inode_allocator
+                        .alloc_inode_in_group(data, group_idx, desc);
+                    match r {
+                        Ok(InodeAlloc { global_inode, .. }) => {
+                            inodes.push(global_inode);
+                        }
+                        Err(_) => {
+                            break;
                         }
                     }
-                })?;
+                }
+            })?;
 
-            if inodes.len() as u32 != count {
-                return Err(BlockDevError::NoSpace);
-            }
+        if inodes.len() as u32 != count {
+            return Err(BlockDevError::NoSpace);
+        }
 
-            // 更新块组描述符
-            if let Some(desc_mut) = self.get_group_desc_mut(group_idx) {
-                let new_count = desc_mut.free_inodes_count().saturating_sub(count);
-                desc_mut.bg_free_inodes_count_lo = (new_count & 0xFFFF) as u16;
-                desc_mut.bg_free_inodes_count_hi = (new_count >> 16) as u16;
-            }
+        // 块组描述符和超级块一起扣除
+        self.adjust_free_inodes(group_idx, -(count as i64));
+        if self.drawing_reserved_inodes {
+            self.reserved_inodes = self.reserved_inodes.saturating_sub(count);
+        }
 
-            // 更新超级块
-            self.superblock.s_free_inodes_count =
-                self.superblock.s_free_inodes_count.saturating_sub(count);
+        debug!(
+            "Allocated inodes: group={}, first_global_inode={}, count={} [delayed write]",
+            group_idx, inodes[0], count
+        );
 
-            debug!(
-                "Allocated inodes: group={}, first_global_inode={}, count={} [delayed write]",
-                group_idx, inodes[0], count
-            );
+        Ok(inodes)
+    }
 
-            return Ok(inodes);
+    /// 按 inode_alloc_policy 为 parent_ino 目录下的新条目分配 inode
+    /// 普通文件：父目录所在块组中最低的空闲 inode，不足时向后环绕查找
+    /// 子目录：Orlov 策略，根目录下的子目录分散到空闲较多的块组
+    pub fn alloc_inode_near<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        parent_ino: u32,
+        is_dir: bool,
+    ) -> BlockDevResult<u32> {
+        if self.inode_alloc_policy == InodeAllocPolicy::FirstFit || parent_ino == 0 {
+            return self.alloc_inode(block_dev);
         }
 
-        Err(BlockDevError::NoSpace)
+        let (parent_group, _) = self.inode_allocator.global_to_group(parent_ino);
+        let target = if is_dir {
+            self.find_group_orlov(parent_ino, parent_group)
+        } else {
+            self.find_group_near(parent_group)
+        };
+        let group_idx = target.ok_or(BlockDevError::NoSpace)?;
+        let mut v = self.alloc_inodes_in_group(block_dev, group_idx, 1)?;
+        v.pop().ok_or(BlockDevError::NoSpace)
+    }
+
+    /// 为批量导入预留 count 个 inode 的额度
+    /// 空闲 inode 扣除已有预留后不足 count 时返回 false，且不修改任何状态；
+    /// 预留不占用位图，只能由 with_reserved_inodes 内的分配消耗，其他分配遇到预留额度返回 NoSpace
+    pub fn reserve_inodes(&mut self, count: u32) -> bool {
+        if self.read_only {
+            return false;
+        }
+        let available = self
+            .superblock
+            .s_free_inodes_count
+            .saturating_sub(self.reserved_inodes);
+        if count > available {
+            warn!("reserve_inodes: want {count}, only {available} available");
+            return false;
+        }
+        self.reserved_inodes += count;
+        true
+    }
+
+    /// 以预留持有者的身份执行 f：其间分配的 inode 可以动用并逐个消耗预留额度
+    pub fn with_reserved_inodes<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let outer = core::mem::replace(&mut self.drawing_reserved_inodes, true);
+        let r = f(self);
+        self.drawing_reserved_inodes = outer;
+        r
+    }
+
+    /// 归还未用完的预留额度（导入中止或结束时调用）
+    pub fn release_reserved_inodes(&mut self) {
+        self.reserved_inodes = 0;
+    }
+
+    /// 目录 inode 释放时维护所属块组的 used_dirs 计数（创建时由 mkdir 递增）
+    pub fn adjust_used_dirs(&mut self, ino: u32, created: bool) {
+        let (group_idx, _) = self.inode_allocator.global_to_group(ino);
+        if let Some(desc) = self.get_group_desc_mut(group_idx) {
+            let count = if created {
+                desc.used_dirs_count().saturating_add(1)
+            } else {
+                desc.used_dirs_count().saturating_sub(1)
+            };
+            desc.bg_used_dirs_count_lo = (count & 0xFFFF) as u16;
+            desc.bg_used_dirs_count_hi = (count >> 16) as u16;
+        }
+    }
+
+    /// inode 所在的块组
+    pub fn inode_group(&self, ino: u32) -> u32 {
+        self.inode_allocator.global_to_group(ino).0
+    }
+
+    /// 块号是否位于数据区：块 0、超级块、GDT（含预留 GDT 和各备份）、
+    /// 块/inode 位图和 inode 表都属于元数据区，文件数据不能落在那里
+    /// 元数据区间在挂载时按块组算好，这里只做一次查找
+    pub fn is_data_block(&self, block: u64) -> bool {
+        block < self.superblock.blocks_count() && !self.block_allocator.is_metadata_block(block)
+    }
+
+    /// [start, start + count) 中有块不在数据区时返回 Corrupted
+    /// 分配器和文件写路径在写数据前调用，位图损坏时拒绝覆盖元数据
+    pub fn check_data_blocks(&self, start: u64, count: u64) -> BlockDevResult<()> {
+        if let Some(bad) = (start..start.saturating_add(count)).find(|&b| !self.is_data_block(b)) {
+            error!("block {bad} lies in a metadata region, refusing to use it for data");
+            return Err(BlockDevError::Corrupted);
+        }
+        Ok(())
+    }
+
+    /// 新 inode 首次分配数据块时的目标位置：inode 所在块组的开头，
+    /// 使目录及其中文件的数据和 inode 落在同一块组；inode 号无效时没有目标
+    pub fn data_goal_for_inode(&self, ino: u32) -> Option<u64> {
+        if ino == 0 || ino > self.superblock.s_inodes_count {
+            return None;
+        }
+        let group = self.inode_group(ino);
+        ((group as usize) < self.group_descs.len())
+            .then(|| self.block_allocator.group_first_block(group))
+    }
+
+    /// 从 start 开始环绕查找第一个有空闲 inode 的块组
+    fn find_group_near(&self, start: u32) -> Option<u32> {
+        let n = self.group_descs.len() as u32;
+        (0..n)
+            .map(|i| (start + i) % n)
+            .find(|&g| self.group_descs[g as usize].free_inodes_count() > 0)
+    }
+
+    /// 简化版 Orlov 选组
+    fn find_group_orlov(&self, parent_ino: u32, parent_group: u32) -> Option<u32> {
+        let n = self.group_descs.len() as u32;
+        if n == 0 {
+            return None;
+        }
+        let avg_inodes = self
+            .group_descs
+            .iter()
+            .map(|d| d.free_inodes_count() as u64)
+            .sum::<u64>()
+            / n as u64;
+        let avg_blocks = self
+            .group_descs
+            .iter()
+            .map(|d| d.free_blocks_count() as u64)
+            .sum::<u64>()
+            / n as u64;
+
+        let found = if parent_ino == self.root_inode {
+            // 顶层目录：在空闲 inode 不低于平均值的组中挑目录最少、空闲块最多的组，
+            // 让顶层目录轮流分散到各个块组
+            (0..n)
+                .filter(|&g| {
+                    let d = &self.group_descs[g as usize];
+                    d.free_inodes_count() > 0 && d.free_inodes_count() as u64 >= avg_inodes
+                })
+                .max_by_key(|&g| {
+                    let d = &self.group_descs[g as usize];
+                    (core::cmp::Reverse(d.used_dirs_count()), d.free_blocks_count())
+                })
+        } else {
+            // 非顶层目录：从父目录所在组开始，取第一个 inode/块都不低于平均值的组
+            (0..n).map(|i| (parent_group + i) % n).find(|&g| {
+                let d = &self.group_descs[g as usize];
+                d.free_inodes_count() > 0
+                    && d.free_inodes_count() as u64 >= avg_inodes
+                    && d.free_blocks_count() as u64 >= avg_blocks
+            })
+        };
+
+        found.or_else(|| self.find_group_near(parent_group))
     }
 
     /// 在整个文件系统中分配一个 inode（兼容旧接口）
@@ -840,13 +1924,18 @@ impl Ext4FileSystem {
 
     /// 根据全局物理块号释放一个数据块
     /// 内部自动计算所属块组和位图位置，并更新块组/超级块计数
+    /// bigalloc 下只有释放簇首块时才归还整簇
     pub fn free_block<B: BlockDevice>(
         &mut self,
         block_dev: &mut Jbd2Dev<B>,
         global_block: u64,
     ) -> BlockDevResult<()> {
+        self.ensure_writable()?;
         // 通过 BlockAllocator 反推 (group_idx, block_in_group)
         let (group_idx, block_in_group) = self.block_allocator.global_to_group(global_block);
+        if !self.block_allocator.is_cluster_head(block_in_group) {
+            return Ok(());
+        }
         let bitmap_block;
         let cache_key;
         // 获取对应块组描述符
@@ -880,18 +1969,63 @@ impl Ext4FileSystem {
         if !did_free {
             return Ok(());
         }
-        let desc = self
-            .get_group_desc_mut(group_idx)
-            .ok_or(BlockDevError::Corrupted)?;
-        // 更新块组 free_blocks_count
-        let before = desc.free_blocks_count();
-        let new_count = before.saturating_add(1);
-        desc.bg_free_blocks_count_lo = (new_count & 0xFFFF) as u16;
-        desc.bg_free_blocks_count_hi = (new_count >> 16) as u16;
-
-        // 更新超级块 free_blocks_count
-        self.superblock.s_free_blocks_count_lo =
-            self.superblock.s_free_blocks_count_lo.saturating_add(1);
+        // 块组描述符和超级块一起归还一个簇
+        self.adjust_free_clusters(group_idx, 1);
+        let ratio = self.block_allocator.cluster_ratio();
+
+        // 释放的块可能马上被当作数据块复用，撤销它在日志里的旧元数据副本；
+        // 缓存里的旧内容也一并丢弃，不再写回已释放的块
+        for off in 0..ratio as u64 {
+            block_dev.revoke_block(global_block + off);
+            self.datablock_cache.invalidate(global_block + off);
+            if self.online_discard {
+                self.pending_discards.push(global_block + off);
+            }
+        }
+        Ok(())
+    }
+
+    /// 有待下发的 discard 时，先写回全部缓存并提交日志，再下发 discard：
+    /// 释放这些块的位图和 inode 修改已经持久化，崩溃恢复后不会有仍被引用却已被 discard 的块
+    pub fn commit_discards<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        if self.pending_discards.is_empty() {
+            return Ok(());
+        }
+        self.flush_all_caches(block_dev)?;
+        self.write_group_descriptors(block_dev)?;
+        block_dev.commit_journal()?;
+        self.issue_discards(block_dev)
+    }
+
+    /// 把积攒的已释放块合并成连续区间，逐段对设备下发 discard
+    /// 只能在释放记录已提交之后调用；设备不支持 discard 时直接丢弃记录
+    fn issue_discards<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+    ) -> BlockDevResult<()> {
+        let mut blocks = core::mem::take(&mut self.pending_discards);
+        if blocks.is_empty() || !block_dev.capabilities().discard {
+            return Ok(());
+        }
+        blocks.sort_unstable();
+        blocks.dedup();
+        let mut runs: Vec<(u64, u32)> = Vec::new();
+        for blk in blocks {
+            match runs.last_mut() {
+                Some((start, len)) if *start + *len as u64 == blk => *len += 1,
+                _ => runs.push((blk, 1)),
+            }
+        }
+        for (start, len) in runs {
+            match block_dev.discard(start, len) {
+                Ok(()) => {}
+                Err(BlockDevError::Unsupported) => {
+                    debug!("discard unsupported by device, dropping pending ranges");
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+        }
         Ok(())
     }
 
@@ -902,6 +2036,7 @@ impl Ext4FileSystem {
         block_dev: &mut Jbd2Dev<B>,
         inode_num: u32,
     ) -> BlockDevResult<()> {
+        self.ensure_writable()?;
         // 通过 InodeAllocator 反推 (group_idx, inode_in_group)
         let (group_idx, inode_in_group) = self.inode_allocator.global_to_group(inode_num);
         let bitmap_block;
@@ -936,22 +2071,131 @@ impl Ext4FileSystem {
             return Ok(());
         }
 
-        let desc = self
-            .get_group_desc_mut(group_idx)
-            .ok_or(BlockDevError::Corrupted)?;
-        // 更新块组 free_inodes_count
-        let before = desc.free_inodes_count();
-        let new_count = before.saturating_add(1);
-        desc.bg_free_inodes_count_lo = (new_count & 0xFFFF) as u16;
-        desc.bg_free_inodes_count_hi = (new_count >> 16) as u16;
-
-        // 更新超级块 free_inodes_count
-        self.superblock.s_free_inodes_count = self.superblock.s_free_inodes_count.saturating_add(1);
+        // 块组描述符和超级块一起归还
+        self.adjust_free_inodes(group_idx, 1);
+        let xattr_block = self.get_inode_by_num(block_dev, inode_num)?.file_acl();
+        if xattr_block != 0 {
+            release_xattr_block(self, block_dev, xattr_block)?;
+        }
         // 真正清空inodetable 大坑....，free_inode必须清空inodetable。不然e2fsck会捣蛋
-        self.modify_inode(block_dev, inode_num, |td| *td = Ext4Inode::default())?;
+        // 扩展属性区一并清零，复用该 inode 时不会带上旧属性
+        self.modify_inode_with_xattr_area(block_dev, inode_num, |td, xattr_area| {
+            *td = Ext4Inode::default();
+            xattr_area.fill(0);
+        })?;
+        Ok(())
+    }
+
+    /// 分别设置数据块、inode、位图三类缓存的容量
+    /// 缩小时各类缓存按 LRU 淘汰，脏块先写回
+    pub fn set_cache_capacities<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        capacities: CacheCapacities,
+    ) -> BlockDevResult<()> {
+        self.datablock_cache.set_capacity(block_dev, capacities.data)?;
+        self.inodetable_cahce.set_capacity(block_dev, capacities.inode_table)?;
+        self.bitmap_cache.set_capacity(block_dev, capacities.bitmap)?;
+        self.cache_capacities = capacities;
         Ok(())
     }
 
+    /// 当前三类缓存的容量
+    pub fn cache_capacities(&self) -> CacheCapacities {
+        self.cache_capacities
+    }
+
+    /// 三类缓存各自的占用、容量以及命中、未命中、写回和淘汰计数
+    pub fn cache_stats(&self) -> Ext4CacheStats {
+        Ext4CacheStats {
+            data: self.datablock_cache.category_stats(),
+            inode: self.inodetable_cahce.category_stats(),
+            bitmap: self.bitmap_cache.category_stats(),
+        }
+    }
+
+    /// 三类缓存的访问计数清零
+    pub fn reset_cache_stats(&mut self) {
+        self.datablock_cache.reset_counters();
+        self.inodetable_cahce.reset_counters();
+        self.bitmap_cache.reset_counters();
+    }
+
+    /// 写回三类块缓存中的全部脏块：位图、inode 表、数据块
+    pub fn flush_all_caches<B: BlockDevice>(&mut self, block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
+        self.bitmap_cache.flush_all(block_dev)?;
+        self.inodetable_cahce.flush_all(block_dev)?;
+        self.datablock_cache.flush_all(block_dev)
+    }
+
+    /// 设置文件数据块的写回策略（见 CachePolicy），只影响之后的写入
+    pub fn set_cache_policy(&mut self, policy: CachePolicy) {
+        self.datablock_cache.set_policy(policy);
+    }
+
+    /// 同时调整块组描述符和超级块的空闲簇计数（delta 以簇为单位，超级块按块换算）
+    /// 两级计数总在同一处修改，随同一个事务提交
+    fn adjust_free_clusters(&mut self, group_idx: u32, delta: i64) {
+        let Some(desc) = self.group_descs.get_mut(group_idx as usize) else {
+            return;
+        };
+        let count = (desc.free_blocks_count() as i64 + delta).max(0);
+        desc.set_free_blocks_count(count as u32);
+        let blocks = delta * self.block_allocator.cluster_ratio() as i64;
+        let total = (self.superblock.free_blocks_count() as i64 + blocks).max(0);
+        self.superblock.set_free_blocks_count(total as u64);
+    }
+
+    /// 同时调整块组描述符和超级块的空闲 inode 计数
+    fn adjust_free_inodes(&mut self, group_idx: u32, delta: i64) {
+        let Some(desc) = self.group_descs.get_mut(group_idx as usize) else {
+            return;
+        };
+        let count = (desc.free_inodes_count() as i64 + delta).max(0);
+        desc.set_free_inodes_count(count as u32);
+        let total = (self.superblock.s_free_inodes_count as i64 + delta).max(0);
+        self.superblock.s_free_inodes_count = total as u32;
+    }
+
+    /// 按位图统计块组的空闲簇数和空闲 inode 数
+    /// 位图标记为未初始化的块组没有可信的位图，返回 None
+    pub fn bitmap_free_counts<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        group_idx: u32,
+    ) -> BlockDevResult<Option<(u32, u32)>> {
+        let desc = *self
+            .group_descs
+            .get(group_idx as usize)
+            .ok_or(BlockDevError::InvalidInput)?;
+        if desc.is_block_bitmap_uninit() || desc.is_inode_bitmap_uninit() {
+            return Ok(None);
+        }
+
+        // 最后一个块组可能不完整，只统计实际存在的簇
+        let clusters = self.block_allocator.clusters_in_group(group_idx);
+        let inodes = self.superblock.s_inodes_per_group;
+
+        let free_bits = |data: &[u8], bits: u32| -> u32 {
+            (0..bits)
+                .filter(|&i| data[(i / 8) as usize] & (1 << (i % 8)) == 0)
+                .count() as u32
+        };
+        let bitmap = self.bitmap_cache.get_or_load(
+            block_dev,
+            CacheKey::new_block(group_idx),
+            desc.block_bitmap(),
+        )?;
+        let free_clusters = free_bits(&bitmap.data, clusters);
+        let bitmap = self.bitmap_cache.get_or_load(
+            block_dev,
+            CacheKey::new_inode(group_idx),
+            desc.inode_bitmap(),
+        )?;
+        let free_inodes = free_bits(&bitmap.data, inodes);
+        Ok(Some((free_clusters, free_inodes)))
+    }
+
     /// 查找有空闲块的块组
     pub fn find_group_with_free_blocks(&self) -> Option<u32> {
         for (idx, desc) in self.group_descs.iter().enumerate() {
@@ -984,6 +2228,22 @@ impl Ext4FileSystem {
         }
     }
 
+    /// 已占用的字节数：(总块数 - 空闲块数) × 块大小，取自超级块计数
+    pub fn used_size(&self) -> u64 {
+        let sb = &self.superblock;
+        sb.blocks_count()
+            .saturating_sub(sb.free_blocks_count())
+            .saturating_mul(sb.block_size())
+    }
+
+    /// 复制当前的超级块和块组描述符，得到一份不随后续修改变化的元数据快照
+    pub fn snapshot_metadata(&self) -> MetadataSnapshot {
+        MetadataSnapshot {
+            superblock: self.superblock,
+            group_descs: self.group_descs.clone(),
+        }
+    }
+
     ///创建最基本的file
     pub fn make_base_dir(&self) {
         //root journal lost+found
@@ -1006,6 +2266,107 @@ pub struct FileSystemStats {
     /// 块组数
     pub block_groups: u32,
 }
+///已占用的字节数
+pub fn used_size(fs: &Ext4FileSystem) -> u64 {
+    fs.used_size()
+}
+
+/// 由块大小和特性推导出的理论上限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsLimits {
+    /// 单个文件的最大字节数
+    pub max_file_size: u64,
+    /// 文件系统的最大字节数
+    pub max_fs_size: u64,
+    /// 最大 inode 数
+    pub max_inode_count: u64,
+}
+
+/// 计算文件系统的理论上限（与 Linux ext4_max_size 的算法一致）：
+/// - 文件大小：extent 文件受 32 位逻辑块号限制，间接块文件受三级间接映射限制，
+///   两者都受 i_blocks 宽度限制（未启用 huge_file 时为 32 位扇区数）
+/// - 文件系统大小：64bit 时物理块号为 48 位，否则为 32 位
+/// - inode 数：s_inodes_count 为 32 位
+pub fn limits(fs: &Ext4FileSystem) -> FsLimits {
+    let sb = &fs.superblock;
+    let block_size = sb.block_size();
+    let blkbits = block_size.trailing_zeros();
+
+    // 逻辑块号 0xFFFFFFFF 保留不用
+    let mut max_blocks = (1u64 << 32) - 1;
+    // i_blocks 以 512 字节扇区计数
+    let mut iblocks_limit = if sb.has_feature_ro_compat(Ext4Superblock::EXT4_FEATURE_RO_COMPAT_HUGE_FILE) {
+        (1u64 << 48) - 1
+    } else {
+        ((1u64 << 32) - 1) >> (blkbits - 9)
+    };
+    if !sb.has_extents() {
+        // 12 个直接块 + 一/二/三级间接块，间接块本身也计入 i_blocks
+        let per_block = block_size / 4;
+        let mapped = 12 + per_block + per_block.pow(2) + per_block.pow(3);
+        let meta_blocks = 1 + (1 + per_block) + (1 + per_block + per_block.pow(2));
+        max_blocks = max_blocks.min(mapped);
+        iblocks_limit = iblocks_limit.saturating_sub(meta_blocks);
+    }
+    let max_file_size = max_blocks.min(iblocks_limit) << blkbits;
+
+    let max_fs_blocks = if sb.has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT) {
+        1u64 << 48
+    } else {
+        1u64 << 32
+    };
+
+    FsLimits {
+        max_file_size,
+        max_fs_size: max_fs_blocks.saturating_mul(block_size),
+        max_inode_count: u32::MAX as u64,
+    }
+}
+
+/// 某一时刻的超级块与块组描述符副本
+/// 只复制元数据而不复制缓存，比克隆整个文件系统便宜；
+/// 基于快照计算的统计与布局报表彼此一致，不受活动文件系统后续修改影响
+#[derive(Debug, Clone)]
+pub struct MetadataSnapshot {
+    pub superblock: Ext4Superblock,
+    pub group_descs: Vec<Ext4GroupDesc>,
+}
+
+impl MetadataSnapshot {
+    /// 快照时刻的文件系统统计信息
+    pub fn statfs(&self) -> FileSystemStats {
+        FileSystemStats {
+            total_blocks: self.superblock.blocks_count(),
+            free_blocks: self.superblock.free_blocks_count(),
+            total_inodes: self.superblock.s_inodes_count,
+            free_inodes: self.superblock.s_free_inodes_count,
+            block_size: self.superblock.block_size(),
+            block_groups: self.group_descs.len() as u32,
+        }
+    }
+
+    /// 快照时刻已占用的字节数
+    pub fn used_size(&self) -> u64 {
+        let sb = &self.superblock;
+        sb.blocks_count()
+            .saturating_sub(sb.free_blocks_count())
+            .saturating_mul(sb.block_size())
+    }
+
+    /// 各块组的 (空闲簇数, 空闲 inode 数)
+    pub fn group_free_counts(&self) -> Vec<(u32, u32)> {
+        self.group_descs
+            .iter()
+            .map(|desc| (desc.free_blocks_count(), desc.free_inodes_count()))
+            .collect()
+    }
+}
+
+/// 获取元数据快照
+pub fn snapshot_metadata(fs: &Ext4FileSystem) -> MetadataSnapshot {
+    fs.snapshot_metadata()
+}
+
 ///entries是否存在
 pub fn file_entry_exisr<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
@@ -1015,16 +2376,30 @@ pub fn file_entry_exisr<B: BlockDevice>(
     fs.file_entries_exist(device, path)
 }
 /// 文件寻找函数-线性扫描
+/// 失败时 NotFound / NotADirectory 携带出错的路径分量
 pub fn find_file<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     device: &mut Jbd2Dev<B>,
     path: &str,
-) -> Option<Ext4Inode> {
-    fs.find_file(device, path)
+) -> Ext4Result<Ext4Inode> {
+    if path.is_empty() {
+        warn!("find_file: empty path");
+        return Err(Ext4Error::InvalidInput);
+    }
+    lookup_path(fs, device, path).map(|(_, inode)| inode)
+}
+
+/// 查找文件并跟随路径中的符号链接，跟随后不存在时返回带分量的 NotFound
+pub fn find_file_follow<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    device: &mut Jbd2Dev<B>,
+    path: &str,
+) -> Ext4Result<Ext4Inode> {
+    fs.find_file_with(device, path, true)
 }
 
 /// 简化的挂载函数（用于兼容旧代码）
-pub fn mount<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<Ext4FileSystem> {
+pub fn mount<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> Ext4Result<Ext4FileSystem> {
     match Ext4FileSystem::mount(block_dev) {
         Ok(_fs) => {
             info!("Ext4 filesystem mounted");
@@ -1032,21 +2407,74 @@ pub fn mount<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<Ext4F
         }
         Err(e) => {
             error!("Mount failed: {e}");
-            Err(BlockDevError::Corrupted)
+            Err(e.into())
         }
     }
 }
 
+/// 挂载并指定元数据校验和策略
+pub fn mount_with_checksum_policy<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    policy: ChecksumPolicy,
+) -> Ext4Result<Ext4FileSystem> {
+    Ext4FileSystem::mount_with_checksum_policy(block_dev, policy).map_err(|e| {
+        error!("Mount failed: {e}");
+        e.into()
+    })
+}
+
+/// 挂载并指定时间来源（Unix 秒）
+pub fn mount_with_clock<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    now: fn() -> u64,
+) -> Ext4Result<Ext4FileSystem> {
+    Ext4FileSystem::mount_with_clock(block_dev, now).map_err(|e| {
+        error!("Mount failed: {e}");
+        e.into()
+    })
+}
+
+/// 为批量导入预先预留 inode，空间不足时尽早失败
+pub fn reserve_inodes(fs: &mut Ext4FileSystem, count: u32) -> bool {
+    fs.reserve_inodes(count)
+}
+
+/// 只读挂载
+pub fn mount_ro<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> Ext4Result<Ext4FileSystem> {
+    Ext4FileSystem::mount_ro(block_dev).map_err(|e| {
+        error!("Read-only mount failed: {e}");
+        e.into()
+    })
+}
+
+/// 将只读挂载升级为读写，无需完整的卸载/重新挂载
+pub fn remount_rw<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+) -> Ext4Result<()> {
+    fs.remount_rw(block_dev)
+}
+
 ///取消挂载函数
 pub fn umount<B: BlockDevice>(
     fs: Ext4FileSystem,
     block_dev: &mut Jbd2Dev<B>,
-) -> BlockDevResult<()> {
+) -> Ext4Result<()> {
     let mut f = fs;
     f.umount(block_dev)?;
     Ok(())
 }
 
+/// 按指定选项卸载文件系统
+pub fn umount_with<B: BlockDevice>(
+    fs: Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    opts: &UmountOptions,
+) -> Ext4Result<()> {
+    let mut f = fs;
+    Ok(f.umount_with(block_dev, opts)?)
+}
+
 /// 文件系统布局信息（仅用于 mkfs 阶段的计算）
 pub struct FsLayoutInfo {
     /// 逻辑块大小（字节）
@@ -1083,6 +2511,14 @@ pub struct FsLayoutInfo {
     reserved_blocks: u64,
 }
 
+/// 将块位图中超出块组实际大小的位标记为已用
+fn pad_block_bitmap(buffer: &mut [u8], blocks_in_group: u32) {
+    let bits_per_block = BLOCK_SIZE_U32 * 8;
+    for i in blocks_in_group..bits_per_block {
+        buffer[(i / 8) as usize] |= 1 << (i % 8);
+    }
+}
+
 /// block_group 布局信息，仅在 mkfs 阶段使用
 pub struct BlcokGroupLayout {
     /// 块组起始块号（全局块号）
@@ -1177,11 +2613,49 @@ pub fn compute_fs_layout(inode_size:u16,total_blocks: u64) -> FsLayoutInfo {
     }
 }
 
-pub fn mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
-    debug!("Start initializing Ext4 filesystem...");
-    // mkfs 阶段先强制关闭日志，避免还未初始化 journal superblock 时触发 JBD2 逻辑
-    block_dev.set_journal_use(false);
+pub fn mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> Ext4Result<()> {
+    mkfs_with_compat(block_dev, DEFAULT_FEATURE_COMPAT)
+}
+
+/// 按指定的兼容特性格式化（决定备份超级块的分布等）
+/// 格式化期间 journal 尚不存在，所有元数据以直写模式落盘
+fn mkfs_with_compat<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    feature_compat: u32,
+) -> Ext4Result<()> {
+    check_image_size(block_dev.total_blocks(), feature_compat)?;
+    // mkfs 阶段先强制关闭日志，避免内部挂载时初始化 JBD2；结束后恢复原开关
     let old_jouranl_use = block_dev.is_use_journal();
+    block_dev.set_journal_use(false);
+    let result = block_dev.with_direct_write(|dev| format_device(dev, feature_compat));
+    block_dev.set_journal_use(old_jouranl_use);
+    Ok(result?)
+}
+
+/// 最小可用尺寸：块组 0 元数据 + 基本目录，带 journal 时还要能放下最小 journal
+/// （journal 最多占用空闲块的一半，见 journal_blocks_for）
+fn check_image_size(total_blocks: u64, feature_compat: u32) -> Ext4Result<()> {
+    let layout = compute_fs_layout(DEFAULT_INODE_SIZE, total_blocks);
+    let mut required_blocks = layout.group0_metadata_blocks as u64 + MKFS_MIN_DATA_BLOCKS;
+    if feature_compat & Ext4Superblock::EXT4_FEATURE_COMPAT_HAS_JOURNAL != 0 {
+        required_blocks += 2 * JOURNAL_MIN_BLOCKS as u64;
+    }
+    if total_blocks < required_blocks {
+        error!("mkfs: image has {total_blocks} blocks, at least {required_blocks} required");
+        return Err(Ext4Error::ImageTooSmall {
+            required_blocks,
+            total_blocks,
+        });
+    }
+    Ok(())
+}
+
+/// mkfs 主体：写超级块、GDT、位图并创建根目录
+fn format_device<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    feature_compat: u32,
+) -> BlockDevResult<()> {
+    debug!("Start initializing Ext4 filesystem...");
 
     // 1. 计算布局参数
     let total_blocks = block_dev.total_blocks();
@@ -1195,8 +2669,8 @@ pub fn mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
     debug!("  Inodes per group: {}", layout.inodes_per_group);
 
     //构建并根据fearure写入到所有group超级块
-    let superblock = build_superblock(total_blocks, &layout);
-    write_superblock(block_dev, &superblock)?;
+    let mut superblock = build_superblock(total_blocks, &layout, feature_compat);
+    write_superblock(block_dev, &mut superblock)?;
     debug!("Superblock written");
 
     //写冗余备份 自动判断是否写
@@ -1215,7 +2689,7 @@ pub fn mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
     debug!("{total_groups} block group descriptors written");
 
     //实际初始化块组0（用于根目录）
-    initialize_group_0(block_dev, &layout)?;
+    initialize_group_0(block_dev, &layout, &superblock)?;
     debug!("Block group 0 initialized (for root directory)");
 
     // 初始化其它块组的位图（全部视为空闲）
@@ -1231,9 +2705,6 @@ pub fn mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
     //  验证：读回超级块检查魔数
     let verify_sb = read_superblock(block_dev)?;
 
-    // mkfs 结束前恢复日志开关（为后续真实挂载做准备）
-    block_dev.set_journal_use(old_jouranl_use);
-
     if verify_sb.s_magic == EXT4_SUPER_MAGIC {
         debug!(
             "Format completed, superblock magic verified: {:#x}",
@@ -1247,7 +2718,7 @@ pub fn mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<()> {
 }
 
 /// 构建超级块 不管字节序
-fn build_superblock(total_blocks: u64, layout: &FsLayoutInfo) -> Ext4Superblock {
+fn build_superblock(total_blocks: u64, layout: &FsLayoutInfo, feature_compat: u32) -> Ext4Superblock {
     let mut sb = Ext4Superblock::default();
 
     // 魔数
@@ -1316,7 +2787,7 @@ fn build_superblock(total_blocks: u64, layout: &FsLayoutInfo) -> Ext4Superblock
     sb.s_rev_level = Ext4Superblock::EXT4_DYNAMIC_REV;
 
     // 特性标志
-    sb.s_feature_compat = DEFAULT_FEATURE_COMPAT;
+    sb.s_feature_compat = feature_compat;
     sb.s_feature_incompat = DEFAULT_FEATURE_INCOMPAT;
     sb.s_feature_ro_compat = DEFAULT_FEATURE_RO_COMPAT;
 
@@ -1325,6 +2796,15 @@ fn build_superblock(total_blocks: u64, layout: &FsLayoutInfo) -> Ext4Superblock
     // 预留的 GDT 块数（仅 mkfs 默认值，挂载时应相信磁盘中的值）
     sb.s_reserved_gdt_blocks = layout.reserved_gdt_blocks as u16;
 
+    // sparse_super2：与 mke2fs 一致，备份放在组 1 和最后一组
+    if sb.has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_SPARSE_SUPER2) {
+        let last = layout.groups.saturating_sub(1);
+        sb.s_backup_bgs = [
+            if layout.groups > 1 { 1 } else { 0 },
+            if last > 1 { last } else { 0 },
+        ];
+    }
+
     sb
 }
 
@@ -1353,9 +2833,9 @@ fn build_uninit_group_desc(
     desc.bg_inode_bitmap_lo = gl.group_inode_bitmap_startblocks as u32;
     desc.bg_inode_table_lo = gl.group_inode_table_startblocks as u32;
 
-    // 理论空闲块数：整组减去元数据块
+    // 理论空闲块数：该组实际块数减去元数据块
     let used_meta = gl.metadata_blocks_in_group as u32;
-    let free_blocks = layout.blocks_per_group.saturating_sub(used_meta);
+    let free_blocks = sb.blocks_in_group_cnt(group_id).saturating_sub(used_meta);
 
     if group_id == 0 {
         // 组0 还需要扣掉保留 inode
@@ -1377,81 +2857,292 @@ fn build_uninit_group_desc(
     desc
 }
 
-///写备份超级块到所有组，从块组1开始
-fn write_superblock_redundant_backup<B: BlockDevice>(
+///写备份超级块到所有组，从块组1开始
+fn write_superblock_redundant_backup<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    sb: &Ext4Superblock,
+    groups_count: u32,
+    fs_layout: &FsLayoutInfo,
+) -> BlockDevResult<()> {
+    //从1开始，备份所在块组由 sparse_super / sparse_super2 特性决定
+    for gid in 1..groups_count {
+        //需要超级块备份
+        if group_has_super_backup(sb, gid) {
+            let group_layout = cloc_group_layout(
+                gid,
+                sb,
+                fs_layout.blocks_per_group,
+                fs_layout.inode_table_blocks,
+                fs_layout.group0_block_bitmap,
+                fs_layout.group0_inode_bitmap,
+                fs_layout.group0_inode_table,
+                fs_layout.gdt_blocks,
+            );
+            let super_blocks = group_layout.group_start_block;
+            let mut backup = *sb;
+            backup.s_block_group_nr = gid as u16;
+            block_dev.read_block(super_blocks).expect("Superblock read failed!");
+            let buffer = block_dev.buffer_mut();
+            backup.to_disk_bytes(&mut buffer[0..SUPERBLOCK_SIZE]);
+            block_dev.write_block(super_blocks, true)?;
+        }
+    }
+    Ok(())
+}
+
+/// 写入超级块到磁盘 管字节序 不写备份
+/// 直接写底层设备（不进日志），同时更新 sb 中的校验和
+fn write_superblock<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    sb: &mut Ext4Superblock,
+) -> BlockDevResult<()> {
+    block_dev.with_raw_device(|dev| ext4_sb_write(dev, sb))
+}
+
+/// 主 GDT 所在块号（紧跟超级块所在块）
+const PRIMARY_GDT_BLOCK: u32 = (SUPERBLOCK_OFFSET as usize / BLOCK_SIZE) as u32 + 1;
+
+/// 块组描述符表占用的块数（不含预留 GDT 块）
+fn gdt_block_count(sb: &Ext4Superblock) -> u32 {
+    (sb.block_groups_count() as usize * sb.get_desc_size() as usize).div_ceil(BLOCK_SIZE) as u32
+}
+
+/// 各块组内的元数据区间（组内块号 [start, end)），供分配器和 is_data_block 使用
+/// 超级块备份与 GDT（含预留 GDT）按块组计算；位图和 inode 表按其实际所在的块组归类，
+/// flex_bg 下它们可能集中在别的块组里
+fn metadata_ranges(
+    sb: &Ext4Superblock,
+    descs: &[Ext4GroupDesc],
+    allocator: &BlockAllocator,
+) -> Vec<Vec<(u32, u32)>> {
+    let mut ranges = alloc::vec![Vec::new(); descs.len()];
+    let mut add = |start: u64, len: u64| {
+        let mut block = start;
+        let end = start + len;
+        while block < end {
+            let (group, in_group) = allocator.global_to_group(block);
+            let Some(list) = ranges.get_mut(group as usize) else {
+                break;
+            };
+            let run = (end - block).min((sb.s_blocks_per_group - in_group) as u64);
+            list.push((in_group, in_group + run as u32));
+            block += run;
+        }
+    };
+    let table_blocks = sb.inode_table_blocks() as u64;
+    for (gid, desc) in descs.iter().enumerate() {
+        let gid = gid as u32;
+        if group_has_super_backup(sb, gid) {
+            let start = allocator.group_first_block(gid);
+            let mut end = start + 1 + gdt_block_count(sb) as u64 + sb.s_reserved_gdt_blocks as u64;
+            // 本组位图紧跟备份 GDT 时，备份区到位图为止
+            if desc.block_bitmap() > start {
+                end = end.min(desc.block_bitmap());
+            }
+            add(start, end - start);
+        }
+        add(desc.block_bitmap(), 1);
+        add(desc.inode_bitmap(), 1);
+        add(desc.inode_table(), table_blocks);
+    }
+    ranges
+}
+
+/// 备份超级块所在块：块组起始块
+fn backup_group_start(sb: &Ext4Superblock, gid: u32) -> u64 {
+    sb.s_first_data_block as u64 + gid as u64 * sb.s_blocks_per_group as u64
+}
+
+/// 返回位图统计的空闲数与块组描述符记录不一致的块组号（供 fsck 使用）
+/// 位图未初始化的块组不参与比较
+pub fn verify_free_counts<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+) -> Ext4Result<Vec<u32>> {
+    let mut groups = Vec::new();
+    for group_idx in 0..fs.group_descs.len() as u32 {
+        let Some(counted) = fs.bitmap_free_counts(block_dev, group_idx)? else {
+            continue;
+        };
+        let desc = &fs.group_descs[group_idx as usize];
+        if counted != (desc.free_blocks_count(), desc.free_inodes_count()) {
+            groups.push(group_idx);
+        }
+    }
+    Ok(groups)
+}
+
+/// 按位图重新统计每个块组的空闲簇/inode 数，修正漂移的描述符，
+/// 再由描述符汇总出超级块的空闲块/inode 总数，并写回磁盘
+/// 返回被修正的块组号
+pub fn recompute_free_counts<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
-    sb: &Ext4Superblock,
-    groups_count: u32,
-    fs_layout: &FsLayoutInfo,
-) -> BlockDevResult<()> {
-    //从1开始
-    // sparse_superbllock特性判断
-    let sprse_feature =
-        sb.has_feature_ro_compat(Ext4Superblock::EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER);
-    if sprse_feature {
-        for gid in 1..groups_count {
-            let group_layout = cloc_group_layout(
-                gid,
-                sb,
-                fs_layout.blocks_per_group,
-                fs_layout.inode_table_blocks,
-                fs_layout.group0_block_bitmap,
-                fs_layout.group0_inode_bitmap,
-                fs_layout.group0_inode_table,
-                fs_layout.gdt_blocks,
+    fs: &mut Ext4FileSystem,
+) -> Ext4Result<Vec<u32>> {
+    fs.ensure_writable()?;
+    let groups = verify_free_counts(block_dev, fs)?;
+    for &group_idx in &groups {
+        if let Some((free_clusters, free_inodes)) = fs.bitmap_free_counts(block_dev, group_idx)? {
+            let desc = &mut fs.group_descs[group_idx as usize];
+            warn!(
+                "recompute_free_counts: group {} free blocks {} -> {}, free inodes {} -> {}",
+                group_idx,
+                desc.free_blocks_count(),
+                free_clusters,
+                desc.free_inodes_count(),
+                free_inodes
             );
-            //需要超级块备份
-            if need_redundant_backup(gid) {
-                let super_blocks = group_layout.group_start_block;
-                block_dev.read_block(super_blocks as u32).expect("Superblock read failed!");
-                let buffer = block_dev.buffer_mut();
-                sb.to_disk_bytes(&mut buffer[0..SUPERBLOCK_SIZE]);
-                block_dev.write_block(super_blocks as u32, true)?;
+            desc.set_free_blocks_count(free_clusters);
+            desc.set_free_inodes_count(free_inodes);
+        }
+    }
+    // 块组描述符进入事务提交，超级块总数由 sync_superblock 从描述符汇总
+    fs.write_group_descriptors(block_dev)?;
+    block_dev.commit_journal()?;
+    fs.sync_superblock(block_dev)?;
+    Ok(groups)
+}
+
+/// 离线 trim：扫描所有块组的块位图，对长度不小于 min_blocks 的空闲区间下发 discard，
+/// 返回 trim 的总字节数。与在线 discard 不同，适合定期执行
+/// BLOCK_UNINIT 块组的位图不在磁盘上，除元数据外整组都是空闲的；
+/// 只读挂载返回 ReadOnly，设备不支持 discard 时返回 Unsupported
+pub fn fstrim<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    min_blocks: u32,
+) -> Ext4Result<u64> {
+    fs.ensure_writable()?;
+    if !block_dev.capabilities().discard {
+        return Err(BlockDevError::Unsupported.into());
+    }
+    let ratio = fs.block_allocator.cluster_ratio();
+    let mut runs: Vec<(u64, u32)> = Vec::new();
+    for group_idx in 0..fs.group_descs.len() as u32 {
+        let desc = fs.group_descs[group_idx as usize];
+        if desc.free_blocks_count() == 0 {
+            continue;
+        }
+        let clusters = fs.block_allocator.clusters_in_group(group_idx);
+        let first_block = fs.block_allocator.group_first_block(group_idx);
+        let bitmap = if desc.is_block_bitmap_uninit() {
+            None
+        } else {
+            Some(
+                &fs.bitmap_cache
+                    .get_or_load(block_dev, CacheKey::new_block(group_idx), desc.block_bitmap())?
+                    .data,
+            )
+        };
+        let allocator = &fs.block_allocator;
+        let is_free = |c: u32| {
+            !allocator.cluster_is_metadata(group_idx, c)
+                && bitmap
+                    .as_ref()
+                    .is_none_or(|data| data[(c / 8) as usize] & (1 << (c % 8)) == 0)
+        };
+        let mut c = 0u32;
+        while c < clusters {
+            if !is_free(c) {
+                c += 1;
+                continue;
+            }
+            let start = c;
+            while c < clusters && is_free(c) {
+                c += 1;
+            }
+            let blocks = (c - start) * ratio;
+            if blocks >= min_blocks.max(1) {
+                runs.push((first_block + (start * ratio) as u64, blocks));
             }
         }
     }
-    Ok(())
+
+    let mut trimmed = 0u64;
+    for (start, len) in runs {
+        block_dev.discard(start, len)?;
+        trimmed += len as u64 * BLOCK_SIZE as u64;
+    }
+    debug!("fstrim: trimmed {trimmed} bytes (min run {min_blocks} blocks)");
+    Ok(trimmed)
 }
 
-/// 写入超级块到磁盘 管字节序 不写备份
-fn write_superblock<B: BlockDevice>(
+/// 主超级块无法通过 ext4_sb_check（或校验和不对）时，从块组 group 的备份恢复
+/// 备份按默认几何（每组 8 * BLOCK_SIZE 块）定位，必须通过检查且 s_block_group_nr 与 group 一致；
+/// 恢复时把备份超级块和备份 GDT 写回主位置。主超级块完好时直接返回它，不做任何修改
+pub fn recover_from_backup<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
-    sb: &Ext4Superblock,
-) -> BlockDevResult<()> {
-    // 超级块总是从分区偏移 1024 字节开始，占用 1024 字节
-    if BLOCK_SIZE == 1024 {
-        block_dev.read_block(1)?;
-        let buffer = block_dev.buffer_mut();
-        sb.to_disk_bytes(&mut buffer[0..SUPERBLOCK_SIZE]);
-        block_dev.write_block(1, true)?;
-    } else {
-        block_dev.read_block(0)?;
-        let buffer = block_dev.buffer_mut();
-        let offset = Ext4Superblock::SUPERBLOCK_OFFSET as usize; // 1024
-        let end = offset + Ext4Superblock::SUPERBLOCK_SIZE;
-        sb.to_disk_bytes(&mut buffer[offset..end]);
-        block_dev.write_block(0, false)?; //由于目前日志回放在超级块读取后，目前为了快速修复防止读取到旧的超级块。直接让超级块落盘写回
+    group: u32,
+) -> Ext4Result<Ext4Superblock> {
+    let mut primary = Ext4Superblock::default();
+    if block_dev
+        .with_raw_device(|dev| ext4_sb_read(dev, &mut primary))
+        .is_ok()
+    {
+        return Ok(primary);
+    }
+    if group == 0 {
+        return Err(Ext4Error::InvalidInput);
     }
 
-    Ok(())
+    let first_data_block = (SUPERBLOCK_OFFSET as usize / BLOCK_SIZE) as u64;
+    let start = first_data_block + group as u64 * 8 * BLOCK_SIZE_U32 as u64;
+    let restored = block_dev.with_raw_device(|dev| {
+        let mut buf = [0u8; BLOCK_SIZE];
+        dev.read64(&mut buf, start, 1)?;
+        let raw = &buf[..SUPERBLOCK_SIZE];
+        let mut backup = Ext4Superblock::from_disk_bytes(raw);
+        if !ext4_sb_check(&backup)
+            || !Ext4Superblock::verify_checksum(raw)
+            || backup.s_block_group_nr as u32 != group
+            || backup_group_start(&backup, group) != start
+            || group >= backup.block_groups_count()
+        {
+            warn!("backup superblock in group {group} is not usable");
+            return Err(BlockDevError::Corrupted);
+        }
+        info!("Restoring primary superblock from group {group} backup");
+
+        let gdt_blocks = gdt_block_count(&backup);
+        let mut gdt = alloc::vec![0u8; gdt_blocks as usize * BLOCK_SIZE];
+        dev.read64(&mut gdt, start + 1, gdt_blocks)?;
+        dev.write(&gdt, PRIMARY_GDT_BLOCK, gdt_blocks)?;
+        backup.s_block_group_nr = 0;
+        ext4_sb_write(dev, &mut backup)?;
+        Ok(backup)
+    })?;
+    Ok(restored)
+}
+
+/// 读取块组 gid 中的备份超级块，该组没有备份时返回 None
+/// 备份分布按 sparse_super / sparse_super2 特性判断
+pub fn read_backup_superblock<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    sb: &Ext4Superblock,
+    gid: u32,
+) -> BlockDevResult<Option<Ext4Superblock>> {
+    if gid == 0 || gid >= sb.block_groups_count() || !group_has_super_backup(sb, gid) {
+        return Ok(None);
+    }
+    let group_start = gid as u64 * sb.s_blocks_per_group as u64;
+    block_dev.read_block(group_start)?;
+    let backup = Ext4Superblock::from_disk_bytes(&block_dev.buffer()[0..SUPERBLOCK_SIZE]);
+    Ok(Some(backup))
 }
 
 /// 读取超级块 管字节序
+/// 校验和按原始 1024 字节计算，未识别的尾部字段原样参与校验
 fn read_superblock<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<Ext4Superblock> {
-    // 超级块总是从分区偏移 1024 字节开始，占用 1024 字节
-    // 这里通过按 BLOCK_SIZE 读块，再在块内做 1024 字节切片来解析
-    if BLOCK_SIZE == 1024 {
-        block_dev.read_block(1)?;
-        let buffer = block_dev.buffer();
-        let sb = Ext4Superblock::from_disk_bytes(&buffer[0..SUPERBLOCK_SIZE]);
-        Ok(sb)
-    } else {
-        block_dev.read_block(0)?;
-        let buffer = block_dev.buffer();
-        let offset = Ext4Superblock::SUPERBLOCK_OFFSET as usize; // 1024
-        let end = offset + Ext4Superblock::SUPERBLOCK_SIZE;
-        let sb = Ext4Superblock::from_disk_bytes(&buffer[offset..end]);
-        Ok(sb)
+    let mut sb = Ext4Superblock::default();
+    block_dev.with_raw_device(|dev| ext4_sb_read(dev, &mut sb))?;
+    Ok(sb)
+}
+
+/// 超级块读取错误转换为挂载错误：校验失败视为超级块无效
+fn superblock_read_error(e: BlockDevError) -> RSEXT4Error {
+    match e {
+        BlockDevError::ChecksumError | BlockDevError::Corrupted => RSEXT4Error::InvalidSuperblock,
+        _ => RSEXT4Error::IoError,
     }
 }
 
@@ -1474,42 +3165,38 @@ fn write_gdt_redundant_backup<B: BlockDevice>(
         });
     }
 
-    let sprse_feature =
-        sb.has_feature_ro_compat(Ext4Superblock::EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER);
-    if sprse_feature {
-        //为每个块组执行
-        for gid in 1..groups_count {
-            if need_redundant_backup(gid) {
-                let group_layout = cloc_group_layout(
-                    gid,
-                    sb,
-                    fs_layout.blocks_per_group,
-                    fs_layout.inode_table_blocks,
-                    fs_layout.group0_block_bitmap,
-                    fs_layout.group0_inode_bitmap,
-                    fs_layout.group0_inode_table,
-                    fs_layout.gdt_blocks,
-                );
-                let gdt_start = group_layout.group_start_block + 1; //跳过超级块
-
-                let mut desc_iter = descs.iter();
-                //循环写入desc
-                for gdt_block_id in gdt_start..group_layout.group_blcok_bitmap_startblocks {
-                    block_dev.read_block(gdt_block_id as u32)?;
-                    let buffer = block_dev.buffer_mut();
-                    let mut current_offset = 0_usize; //descoffset循环记录
-                    for _ in 0..fs_layout.descs_per_block {
-                        if let Some(desc) = desc_iter.next() {
-                            desc.to_disk_bytes(
-                                &mut buffer
-                                    [current_offset..current_offset + desc_size as usize],
-                            );
-                            current_offset += desc_size as usize;
-                        }
+    //为每个块组执行
+    for gid in 1..groups_count {
+        if group_has_super_backup(sb, gid) {
+            let group_layout = cloc_group_layout(
+                gid,
+                sb,
+                fs_layout.blocks_per_group,
+                fs_layout.inode_table_blocks,
+                fs_layout.group0_block_bitmap,
+                fs_layout.group0_inode_bitmap,
+                fs_layout.group0_inode_table,
+                fs_layout.gdt_blocks,
+            );
+            let gdt_start = group_layout.group_start_block + 1; //跳过超级块
+
+            let mut desc_iter = descs.iter();
+            //循环写入desc
+            for gdt_block_id in gdt_start..group_layout.group_blcok_bitmap_startblocks {
+                block_dev.read_block(gdt_block_id)?;
+                let buffer = block_dev.buffer_mut();
+                let mut current_offset = 0_usize; //descoffset循环记录
+                for _ in 0..fs_layout.descs_per_block {
+                    if let Some(desc) = desc_iter.next() {
+                        desc.to_disk_bytes(
+                            &mut buffer
+                                [current_offset..current_offset + desc_size as usize],
+                        );
+                        current_offset += desc_size as usize;
                     }
-                    //写回磁盘
-                    block_dev.write_block(gdt_block_id as u32, true)?;
                 }
+                //写回磁盘
+                block_dev.write_block(gdt_block_id, true)?;
             }
         }
     }
@@ -1536,13 +3223,13 @@ fn write_group_desc<B: BlockDevice>(
     let end = in_block + desc_size;
 
     // 读取目标块，修改对应 slice，再写回
-    block_dev.read_block(block_num as u32)?;
+    block_dev.read_block(block_num)?;
     let buffer = block_dev.buffer_mut();
     if end > buffer.len() {
         return Err(BlockDevError::Corrupted);
     }
     desc.to_disk_bytes(&mut buffer[in_block..end]);
-    block_dev.write_block(block_num as u32, true)?;
+    block_dev.write_block(block_num, true)?;
 
     Ok(())
 }
@@ -1551,6 +3238,7 @@ fn write_group_desc<B: BlockDevice>(
 fn initialize_group_0<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
     layout: &FsLayoutInfo,
+    sb: &Ext4Superblock,
 ) -> BlockDevResult<()> {
     // 计算块组0的布局
     let block_bitmap_blk = layout.group0_block_bitmap;
@@ -1567,8 +3255,9 @@ fn initialize_group_0<B: BlockDevice>(
             let bit_idx = i % 8;
             buffer[byte_idx] |= 1 << bit_idx;
         }
+        pad_block_bitmap(buffer, sb.blocks_in_group_cnt(0));
     }
-    block_dev.write_block(block_bitmap_blk, true)?;
+    block_dev.write_block(block_bitmap_blk as u64, true)?;
 
     {
         let buffer = block_dev.buffer_mut();
@@ -1588,7 +3277,7 @@ fn initialize_group_0<B: BlockDevice>(
             buffer[byte_idx] |= 1 << bit_idx;
         }
     }
-    block_dev.write_block(inode_bitmap_blk, true)?;
+    block_dev.write_block(inode_bitmap_blk as u64, true)?;
 
     //  清零inode表
     {
@@ -1596,14 +3285,14 @@ fn initialize_group_0<B: BlockDevice>(
         buffer.fill(0);
     }
     for i in 0..layout.inode_table_blocks {
-        block_dev.write_block(inode_table_blk + i, true)?;
+        block_dev.write_block((inode_table_blk + i) as u64, true)?;
     }
 
     //  更新块组0的描述符（清除UNINIT标志）
     let mut desc = Ext4GroupDesc::default();
     desc.bg_flags = Ext4GroupDesc::EXT4_BG_INODE_ZEROED;
-    desc.bg_free_blocks_count_lo = layout
-        .blocks_per_group
+    desc.bg_free_blocks_count_lo = sb
+        .blocks_in_group_cnt(0)
         .saturating_sub(layout.group0_metadata_blocks) as u16;
     desc.bg_free_inodes_count_lo = layout.inodes_per_group.saturating_sub(RESERVED_INODES) as u16;
     desc.bg_block_bitmap_lo = block_bitmap_blk;
@@ -1650,8 +3339,10 @@ fn initialize_other_groups_bitmaps<B: BlockDevice>(
                 let bit_idx = i % 8;
                 buffer[byte_idx] |= 1 << bit_idx;
             }
+            // 末尾不完整块组：超出设备的位视为已用
+            pad_block_bitmap(buffer, sb.blocks_in_group_cnt(group_id));
         }
-        block_dev.write_block(block_bitmap_blk, true)?;
+        block_dev.write_block(block_bitmap_blk as u64, true)?;
 
         {
             //  初始化inode位图：全0 → 所有inode空闲
@@ -1666,8 +3357,1396 @@ fn initialize_other_groups_bitmaps<B: BlockDevice>(
                 buffer[byte_idx] |= 1 << bit_idx;
             }
         }
-        block_dev.write_block(inode_bitmap_blk, true)?;
+        block_dev.write_block(inode_bitmap_blk as u64, true)?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::entries::DirEntryIterator;
+    use crate::ext4_backend::file::{mkfile, read_file, try_mkfile_with_ino, unlink, write_file};
+    use crate::ext4_backend::loopfile::{
+        get_file_inode, resolve_inode_block, resolve_inode_block_allextend,
+    };
+    use crate::ext4_backend::test_support::*;
+    use alloc::format;
+    use alloc::vec;
+
+    #[test]
+    fn find_file_root_path_variants() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let root = fs.get_root(&mut dev).unwrap();
+
+        for path in ["/", "//", "/./"] {
+            let inode = find_file(&mut fs, &mut dev, path).unwrap();
+            assert!(inode.is_dir(), "{path} should be a directory");
+            assert_eq!(inode.i_block, root.i_block, "{path} should resolve to root");
+            let (ino, _) = get_file_inode(&mut fs, &mut dev, path).unwrap().unwrap();
+            assert_eq!(ino, fs.root_inode);
+        }
+    }
+
+    #[test]
+    fn find_file_empty_path_is_invalid() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        assert!(matches!(
+            find_file(&mut fs, &mut dev, ""),
+            Err(Ext4Error::InvalidInput)
+        ));
+    }
+
+    #[test]
+    fn find_file_reports_missing_middle_component() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/a").unwrap();
+        mkdir(&mut dev, &mut fs, "/a/x").unwrap();
+
+        assert_eq!(
+            find_file(&mut fs, &mut dev, "/a/b/c").err(),
+            Some(Ext4Error::NotFound { index: 1, component: "b".into() })
+        );
+        // 下标按原始分量计，"." 和 ".." 仍正常规范化
+        assert_eq!(
+            find_file(&mut fs, &mut dev, "//./a/x/../b/c").err(),
+            Some(Ext4Error::NotFound { index: 4, component: "b".into() })
+        );
+        assert!(find_file(&mut fs, &mut dev, "/.////.././/a/./x").unwrap().is_dir());
+        assert_eq!(
+            read_file(&mut dev, &mut fs, "/a/b/c"),
+            Err(Ext4Error::NotFound { index: 1, component: "b".into() })
+        );
+    }
+
+    #[test]
+    fn find_file_reports_non_directory_component() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+        mkfile(&mut dev, &mut fs, "/d/f", Some(b"x"), None).unwrap();
+
+        let not_dir = Ext4Error::NotADirectory { index: 1, component: "f".into() };
+        assert_eq!(find_file(&mut fs, &mut dev, "/d/f/g").err(), Some(not_dir.clone()));
+        assert_eq!(
+            find_file(&mut fs, &mut dev, "/d/./f/..").err(),
+            Some(Ext4Error::NotADirectory { index: 2, component: "f".into() })
+        );
+        assert_eq!(read_file(&mut dev, &mut fs, "/d/f/g"), Err(not_dir));
+    }
+
+    fn bit_set(bitmap: &[u8], idx: u32) -> bool {
+        bitmap[(idx / 8) as usize] & (1 << (idx % 8)) != 0
+    }
+
+    /// mkfs 产出的镜像需满足 ext4 磁盘布局不变式（e2fsck -n 可读）
+    #[test]
+    fn mkfs_layout_invariants() {
+        // 略多于一个块组，覆盖末尾不完整块组
+        let total_blocks: u64 = 32768 + 8192;
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(total_blocks), false, false);
+        mkfs(&mut dev).unwrap();
+
+        // 1. 超级块位于字节偏移 1024
+        dev.read_block(0).unwrap();
+        let sb = Ext4Superblock::from_disk_bytes(
+            &dev.buffer()[Ext4Superblock::SUPERBLOCK_OFFSET as usize
+                ..Ext4Superblock::SUPERBLOCK_OFFSET as usize + Ext4Superblock::SUPERBLOCK_SIZE],
+        );
+        assert_eq!(sb.s_magic, EXT4_SUPER_MAGIC);
+        assert_eq!(sb.block_size(), BLOCK_SIZE as u64);
+        assert_eq!(sb.blocks_count(), total_blocks);
+        assert_eq!(sb.s_first_data_block, 0);
+
+        let bpg = sb.s_blocks_per_group;
+        let ipg = sb.s_inodes_per_group;
+        let groups = total_blocks.div_ceil(bpg as u64) as u32;
+        assert_eq!(sb.s_inodes_count, groups * ipg);
+        let itable_blocks = (ipg as u64 * sb.s_inode_size as u64).div_ceil(BLOCK_SIZE as u64);
+
+        // 2. 组描述符紧随超级块所在块之后（块 1 起）
+        let desc_size = sb.get_desc_size() as usize;
+        let mut descs = Vec::new();
+        for g in 0..groups {
+            let off = BLOCK_SIZE + g as usize * desc_size;
+            dev.read_block((off / BLOCK_SIZE) as u64).unwrap();
+            let in_blk = off % BLOCK_SIZE;
+            descs.push(Ext4GroupDesc::from_disk_bytes(
+                &dev.buffer()[in_blk..in_blk + desc_size],
+            ));
+        }
+
+        // 3. 位图与 inode 表落在描述符指定的位置，且在位图中被标记为已用
+        let mut free_blocks_sum = 0u64;
+        let mut free_inodes_sum = 0u64;
+        for (g, desc) in descs.iter().enumerate() {
+            let g_start = g as u64 * bpg as u64;
+            let g_blocks = core::cmp::min(bpg as u64, total_blocks - g_start);
+            let in_group = |b: u64| b >= g_start && b < g_start + g_blocks;
+            assert!(in_group(desc.block_bitmap()), "group {g} block bitmap");
+            assert!(in_group(desc.inode_bitmap()), "group {g} inode bitmap");
+            assert!(in_group(desc.inode_table()), "group {g} inode table");
+            assert!(in_group(desc.inode_table() + itable_blocks - 1), "group {g} inode table end");
+
+            dev.read_block(desc.block_bitmap()).unwrap();
+            let bbitmap = dev.buffer().to_vec();
+            let mut meta = vec![desc.block_bitmap(), desc.inode_bitmap()];
+            meta.extend(desc.inode_table()..desc.inode_table() + itable_blocks);
+            for b in meta {
+                assert!(bit_set(&bbitmap, (b - g_start) as u32), "group {g} block {b} not marked");
+            }
+            let free_b = (0..g_blocks as u32).filter(|&i| !bit_set(&bbitmap, i)).count() as u32;
+            assert_eq!(free_b, desc.free_blocks_count(), "group {g} free blocks");
+
+            dev.read_block(desc.inode_bitmap()).unwrap();
+            let ibitmap = dev.buffer().to_vec();
+            let free_i = (0..ipg).filter(|&i| !bit_set(&ibitmap, i)).count() as u32;
+            assert_eq!(free_i, desc.free_inodes_count(), "group {g} free inodes");
+            if g == 0 {
+                for ino in 1..=RESERVED_INODES {
+                    assert!(bit_set(&ibitmap, ino - 1), "reserved inode {ino} not marked");
+                }
+            }
+
+            free_blocks_sum += desc.free_blocks_count() as u64;
+            free_inodes_sum += desc.free_inodes_count() as u64;
+        }
+        assert_eq!(sb.free_blocks_count(), free_blocks_sum);
+        assert_eq!(sb.s_free_inodes_count as u64, free_inodes_sum);
+
+        // 4. 根 inode 是合法目录，首块含 "." 与 ".." 均指向自身
+        let mut fs = mount(&mut dev).unwrap();
+        let mut root = fs.get_root(&mut dev).unwrap();
+        assert!(root.is_dir());
+        assert!(root.i_links_count >= 3);
+        let first = resolve_inode_block(&mut dev, &fs, fs.root_inode, &mut root, 0).unwrap().unwrap();
+        dev.read_block(first as u64).unwrap();
+        let entries: Vec<(u32, Vec<u8>)> = DirEntryIterator::new(dev.buffer())
+            .map(|(e, _)| (e.inode, e.name.to_vec()))
+            .collect();
+        assert_eq!(entries[0], (fs.root_inode, b".".to_vec()));
+        assert_eq!(entries[1], (fs.root_inode, b"..".to_vec()));
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn inode_alloc_clusters_files_in_parent_group() {
+        // 两个块组：组0 已有保留 inode，Orlov 会把顶层目录放到组1
+        let (mut dev, mut fs) = setup_fs(32768 + 8192);
+        let ipg = fs.superblock.s_inodes_per_group;
+
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+        let (dir_ino, _) = get_file_inode(&mut fs, &mut dev, "/d").unwrap().unwrap();
+        let group = (dir_ino - 1) / ipg;
+        assert_eq!(group, 1);
+
+        let mut inos = Vec::new();
+        for i in 0..8 {
+            let path = format!("/d/f{i}");
+            mkfile(&mut dev, &mut fs, &path, None, None).unwrap();
+            let (ino, _) = get_file_inode(&mut fs, &mut dev, &path).unwrap().unwrap();
+            inos.push(ino);
+        }
+        let range = group * ipg + 1..=(group + 1) * ipg;
+
+        // 重新挂载后（缓存已清空）仍能按正确块组找到 inode
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let (ino, inode) = get_file_inode(&mut fs, &mut dev, "/d/f7").unwrap().unwrap();
+        assert_eq!(ino, inos[7]);
+        assert!(inode.is_file());
+        for w in inos.windows(2) {
+            assert_eq!(w[1], w[0] + 1);
+        }
+        assert!(inos.iter().all(|ino| range.contains(ino)), "{inos:?}");
+
+        // 覆盖为 FirstFit 后回到组0
+        fs.inode_alloc_policy = InodeAllocPolicy::FirstFit;
+        mkfile(&mut dev, &mut fs, "/d/first", None, None).unwrap();
+        let (ino, _) = get_file_inode(&mut fs, &mut dev, "/d/first").unwrap().unwrap();
+        assert_eq!((ino - 1) / ipg, 0);
+    }
+
+    #[test]
+    fn top_level_dirs_spread_and_keep_children_local() {
+        let (mut dev, mut fs) = setup_fs(4 * 32768);
+        let groups: Vec<u32> = (0..20)
+            .map(|i| {
+                let (ino, _) = mkdir_with_ino(&mut dev, &mut fs, &format!("/top{i}")).unwrap();
+                fs.inode_group(ino)
+            })
+            .collect();
+        let used: BTreeSet<u32> = groups.iter().copied().collect();
+        assert!(used.len() > 1, "{groups:?}");
+        assert!(groups.iter().filter(|&&g| g == 0).count() < 20);
+
+        // 子目录、文件及其数据块都留在父目录所在的块组
+        let (dir_ino, mut dir_inode) = get_file_inode(&mut fs, &mut dev, "/top1").unwrap().unwrap();
+        let group = fs.inode_group(dir_ino);
+        let dir_block = resolve_inode_block(&mut dev, &fs, dir_ino, &mut dir_inode, 0).unwrap().unwrap();
+        assert_eq!(fs.block_allocator.global_to_group(dir_block as u64).0, group);
+        let (sub_ino, _) = mkdir_with_ino(&mut dev, &mut fs, "/top1/sub").unwrap();
+        assert_eq!(fs.inode_group(sub_ino), group);
+        mkfile(&mut dev, &mut fs, "/top1/f", Some(&[3u8; BLOCK_SIZE * 2]), None).unwrap();
+        let (f_ino, mut f_inode) = get_file_inode(&mut fs, &mut dev, "/top1/f").unwrap().unwrap();
+        assert_eq!(fs.inode_group(f_ino), group);
+        let f_block = resolve_inode_block(&mut dev, &fs, f_ino, &mut f_inode, 0).unwrap().unwrap();
+        assert_eq!(fs.block_allocator.global_to_group(f_block as u64).0, group);
+
+        // 块组 0 的目标是块 0 本身，不能与"没有目标"混淆；无效 inode 号才没有目标
+        assert_eq!(fs.data_goal_for_inode(fs.root_inode), Some(0));
+        assert_eq!(fs.data_goal_for_inode(0), None);
+        assert_eq!(fs.data_goal_for_inode(fs.superblock.s_inodes_count + 1), None);
+    }
+
+    #[test]
+    fn short_last_group_ignores_bitmap_padding() {
+        let (mut dev, mut fs) = setup_fs(32768 + 8192);
+        assert_eq!(fs.block_allocator.clusters_in_group(0), 32768);
+        assert_eq!(fs.block_allocator.clusters_in_group(1), 8192);
+
+        // 清掉最后一组位图中超出实际块数的填充位，统计和分配都不应受影响
+        let bitmap_block = fs.group_descs[1].block_bitmap();
+        fs.bitmap_cache
+            .modify(&mut dev, CacheKey::new_block(1), bitmap_block, |data| {
+                data[8192 / 8..].fill(0);
+            })
+            .unwrap();
+        assert!(verify_free_counts(&mut dev, &mut fs).unwrap().is_empty());
+
+        // 实际存在的块全部占满后，不会把填充位当作空闲块分配出去
+        let mut data = fs
+            .bitmap_cache
+            .get_or_load(&mut dev, CacheKey::new_block(1), bitmap_block)
+            .unwrap()
+            .data
+            .clone();
+        data[..8192 / 8].fill(0xFF);
+        let alloc = &fs.block_allocator;
+        assert!(alloc.alloc_contiguous_blocks(&mut data, 1, 1).is_err());
+        assert!(alloc.alloc_run_near(&mut data, 1, 0, 4, true).is_err());
+        let mut desc = fs.group_descs[1];
+        desc.set_free_blocks_count(1);
+        assert!(alloc.alloc_block_in_group(&mut data, 1, &desc).is_err());
+
+        // 每组块数超过一个位图块能表示的位数时拒绝挂载
+        let mut sb = fs.superblock;
+        assert!(ext4_sb_check(&sb));
+        sb.s_blocks_per_group = 8 * BLOCK_SIZE as u32 + 8;
+        assert!(!ext4_sb_check(&sb));
+    }
+
+    #[test]
+    fn remount_rw_upgrades_read_only_mount() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/probe", Some(b"hello"), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount_ro(&mut dev).unwrap();
+        assert!(fs.read_only);
+        let data = read_file(&mut dev, &mut fs, "/probe").unwrap();
+        assert_eq!(&data[..5], b"hello");
+        assert!(matches!(
+            write_file(&mut dev, &mut fs, "/probe", 0, b"HELLO"),
+            Err(Ext4Error::ReadOnlyFs)
+        ));
+        assert_eq!(mkfile(&mut dev, &mut fs, "/new", Some(b"x"), None).err(), Some(Ext4Error::ReadOnlyFs));
+
+        remount_rw(&mut dev, &mut fs).unwrap();
+        assert!(!fs.read_only);
+        assert_eq!(fs.superblock.s_state & Ext4Superblock::EXT4_VALID_FS, 0);
+        write_file(&mut dev, &mut fs, "/probe", 0, b"HELLO").unwrap();
+        mkfile(&mut dev, &mut fs, "/new", Some(b"x"), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert_ne!(fs.superblock.s_state & Ext4Superblock::EXT4_VALID_FS, 0);
+        let data = read_file(&mut dev, &mut fs, "/probe").unwrap();
+        assert_eq!(&data[..5], b"HELLO");
+        assert!(read_file(&mut dev, &mut fs, "/new").is_ok());
+    }
+
+    #[test]
+    fn from_superblock_after_probe() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/probed", Some(b"probe data"), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        let sb = Ext4FileSystem::probe(&mut dev).unwrap();
+        assert_eq!(sb.s_magic, EXT4_SUPER_MAGIC);
+        let mut fs = Ext4FileSystem::from_superblock(sb, &mut dev).unwrap();
+        assert_eq!(fs.group_descs.len() as u32, sb.block_groups_count());
+        let data = read_file(&mut dev, &mut fs, "/probed").unwrap();
+        assert_eq!(&data[..10], b"probe data");
+
+        let mut bad = sb;
+        bad.s_magic = 0;
+        assert!(matches!(
+            Ext4FileSystem::from_superblock(bad, &mut dev),
+            Err(RSEXT4Error::InvalidMagic)
+        ));
+    }
+
+    /// 修改磁盘上的超级块（测试用）
+    fn patch_superblock(dev: &mut Jbd2Dev<MemBlockDev>, f: impl FnOnce(&mut Ext4Superblock)) {
+        let mut sb = read_superblock(dev).unwrap();
+        f(&mut sb);
+        write_superblock(dev, &mut sb).unwrap();
+    }
+
+    #[test]
+    fn mount_validates_cluster_size() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+
+        patch_superblock(&mut dev, |sb| sb.s_log_cluster_size = sb.s_log_block_size - 1);
+        assert!(matches!(
+            Ext4FileSystem::mount(&mut dev),
+            Err(RSEXT4Error::InvalidSuperblock)
+        ));
+        assert!(Ext4FileSystem::mount_ro(&mut dev).is_err());
+    }
+
+    /// 把 mkfs 镜像转换成簇为 4 块的 bigalloc 镜像：
+    /// 块位图按簇重建（簇内任一块已用即视为已用），计数同步改为按簇
+    fn convert_to_bigalloc(dev: &mut Jbd2Dev<MemBlockDev>) {
+        let fs = mount_ro(dev).unwrap();
+        let cpg = fs.superblock.s_blocks_per_group / 4;
+        let mut descs = fs.group_descs.clone();
+        umount(fs, dev).unwrap();
+
+        let mut free_clusters = 0u64;
+        for (g, desc) in descs.iter_mut().enumerate() {
+            dev.read_block(desc.block_bitmap()).unwrap();
+            let old = dev.buffer().to_vec();
+            let buf = dev.buffer_mut();
+            buf.fill(0);
+            let mut free = 0u32;
+            for c in 0..cpg {
+                if (0..4).any(|i| bit_set(&old, c * 4 + i)) {
+                    buf[(c / 8) as usize] |= 1 << (c % 8);
+                } else {
+                    free += 1;
+                }
+            }
+            dev.write_block(desc.block_bitmap(), true).unwrap();
+            desc.bg_free_blocks_count_lo = (free & 0xFFFF) as u16;
+            desc.bg_free_blocks_count_hi = (free >> 16) as u16;
+            write_group_desc(dev, g as u32, desc).unwrap();
+            free_clusters += free as u64;
+        }
+
+        // 簇 16KB，块 4KB
+        patch_superblock(dev, |sb| {
+            sb.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_BIGALLOC;
+            sb.s_log_cluster_size = sb.s_log_block_size + 2;
+            sb.s_clusters_per_group = cpg;
+            let free_blocks = free_clusters * 4;
+            sb.s_free_blocks_count_lo = (free_blocks & 0xFFFFFFFF) as u32;
+            sb.s_free_blocks_count_hi = (free_blocks >> 32) as u32;
+        });
+    }
+
+    #[test]
+    fn bigalloc_accounts_free_space_in_clusters() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+        convert_to_bigalloc(&mut dev);
+
+        let mut fs = Ext4FileSystem::mount_ro(&mut dev).unwrap();
+        assert_eq!(fs.superblock.cluster_ratio(), 4);
+        let free_clusters: u64 = fs
+            .group_descs
+            .iter()
+            .map(|d| d.free_blocks_count() as u64)
+            .sum();
+        fs.sync_superblock(&mut dev).unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_clusters * 4);
+    }
+
+    #[test]
+    fn bigalloc_small_file_takes_whole_cluster() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+        convert_to_bigalloc(&mut dev);
+
+        let mut fs = mount(&mut dev).unwrap();
+        let desc_before = fs.group_descs[0].free_blocks_count();
+        let sb_before = fs.superblock.free_blocks_count();
+
+        mkfile(&mut dev, &mut fs, "/small", Some(b"tiny"), None).unwrap();
+
+        assert_eq!(fs.group_descs[0].free_blocks_count(), desc_before - 1);
+        assert_eq!(fs.superblock.free_blocks_count(), sb_before - 4);
+
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/small").unwrap().unwrap();
+        let phys = resolve_inode_block(&mut dev, &fs, ino, &mut inode, 0).unwrap().unwrap();
+        assert_eq!(phys % 4, 0, "data block must start a cluster");
+        let bitmap_block = fs.group_descs[0].block_bitmap();
+        let bm = fs
+            .bitmap_cache
+            .get_or_load(&mut dev, CacheKey::new_block(0), bitmap_block)
+            .unwrap();
+        assert!(bit_set(&bm.data, (phys / 4) as u32));
+
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let data = read_file(&mut dev, &mut fs, "/small").unwrap();
+        assert_eq!(&data[..4], b"tiny");
+    }
+
+    #[test]
+    fn bigalloc_append_fills_partial_cluster_first() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+        convert_to_bigalloc(&mut dev);
+
+        let mut fs = mount(&mut dev).unwrap();
+        let desc_before = fs.group_descs[0].free_blocks_count();
+        let sb_before = fs.superblock.free_blocks_count();
+
+        // 逐块追加：前 4 块共用一个簇，第 5 块才占用新簇
+        mkfile(&mut dev, &mut fs, "/grow", Some(&[1u8; BLOCK_SIZE]), None).unwrap();
+        for i in 1..4u8 {
+            let off = i as u64 * BLOCK_SIZE as u64;
+            write_file(&mut dev, &mut fs, "/grow", off, &[i + 1; BLOCK_SIZE]).unwrap();
+            assert_eq!(fs.group_descs[0].free_blocks_count(), desc_before - 1);
+        }
+        write_file(&mut dev, &mut fs, "/grow", 4 * BLOCK_SIZE as u64, &[5u8; BLOCK_SIZE]).unwrap();
+        assert_eq!(fs.group_descs[0].free_blocks_count(), desc_before - 2);
+        assert_eq!(fs.superblock.free_blocks_count(), sb_before - 8);
+
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/grow").unwrap().unwrap();
+        let first = resolve_inode_block(&mut dev, &fs, ino, &mut inode, 0).unwrap().unwrap();
+        for lbn in 1..4u32 {
+            let phys = resolve_inode_block(&mut dev, &fs, ino, &mut inode, lbn).unwrap().unwrap();
+            assert_eq!(phys, first + lbn as u64);
+        }
+        let data = read_file(&mut dev, &mut fs, "/grow").unwrap();
+        for i in 0..5 {
+            assert!(data[i * BLOCK_SIZE..(i + 1) * BLOCK_SIZE].iter().all(|&b| b == i as u8 + 1));
+        }
+
+        unlink(&mut dev, &mut fs, "/grow").unwrap();
+        assert_eq!(fs.group_descs[0].free_blocks_count(), desc_before);
+        assert_eq!(fs.superblock.free_blocks_count(), sb_before);
+    }
+
+    #[test]
+    fn remount_rw_rejects_unsupported_ro_compat() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount_ro(&mut dev).unwrap();
+        fs.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_READONLY;
+        assert!(matches!(
+            remount_rw(&mut dev, &mut fs),
+            Err(Ext4Error::Device(BlockDevError::Unsupported))
+        ));
+        assert!(fs.read_only);
+    }
+
+    #[test]
+    fn journaled_rw_mount_refuses_blocks_beyond_32bit_journal_tags() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+        dev.set_journal_use(true);
+        let fs = mount(&mut dev).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        // 64 位文件系统声称有 2^32 + 16K 个块：高位块的元数据无法写进 32 位 tag
+        dev.with_raw_device(|d| {
+            let mut sb = Ext4Superblock::default();
+            ext4_sb_read(d, &mut sb)?;
+            assert!(sb.has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT));
+            assert!(sb.has_journal());
+            sb.s_blocks_count_hi = 1;
+            ext4_sb_write(d, &mut sb)
+        })
+        .unwrap();
+        let mut before = dev.with_raw_device(|d| Ok(d.clone())).unwrap();
+        assert_eq!(
+            mount(&mut dev).err(),
+            Some(Ext4Error::Mount(RSEXT4Error::UnsupportedFeature))
+        );
+        // 拒绝发生在日志回放和任何写入之前
+        let mut after = dev.with_raw_device(|d| Ok(d.clone())).unwrap();
+        let (mut a, mut b) = ([0u8; BLOCK_SIZE], [0u8; BLOCK_SIZE]);
+        for blk in 0..16 * 1024 {
+            before.read(&mut a, blk, 1).unwrap();
+            after.read(&mut b, blk, 1).unwrap();
+            assert!(a == b, "block {blk} changed");
+        }
+    }
+
+    #[test]
+    fn mount_refuses_unknown_incompat_and_rw_mount_with_unknown_ro_compat() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+        let set_features = |dev: &mut Jbd2Dev<MemBlockDev>, incompat: u32, ro_compat: u32| {
+            let mut sb = Ext4Superblock::default();
+            dev.with_raw_device(|d| {
+                ext4_sb_read(d, &mut sb)?;
+                sb.s_feature_incompat |= incompat;
+                sb.s_feature_ro_compat |= ro_compat;
+                ext4_sb_write(d, &mut sb)
+            })
+            .unwrap();
+        };
+
+        let bogus = 0x0100_0000;
+        set_features(&mut dev, bogus | Ext4Superblock::EXT4_FEATURE_INCOMPAT_ENCRYPT, 0);
+        let err = Ext4FileSystem::mount(&mut dev).err().unwrap();
+        assert_eq!(mount(&mut dev).err(), Some(Ext4Error::Mount(err)));
+        assert_eq!(
+            err,
+            RSEXT4Error::UnsupportedIncompat(bogus | Ext4Superblock::EXT4_FEATURE_INCOMPAT_ENCRYPT)
+        );
+        assert_eq!(
+            alloc::format!("{err}"),
+            "不支持的 incompat 特性 0x1010000: encrypt 0x1000000"
+        );
+        assert!(mount_ro(&mut dev).is_err());
+
+        // 清掉 incompat 位，换成未知的 ro_compat 位：拒绝读写挂载，只读挂载可以
+        dev.with_raw_device(|d| {
+            let mut sb = Ext4Superblock::default();
+            ext4_sb_read(d, &mut sb)?;
+            sb.s_feature_incompat &= EXT4_SUPPORTED_INCOMPAT;
+            ext4_sb_write(d, &mut sb)
+        })
+        .unwrap();
+        set_features(&mut dev, 0, 0x0100_0000);
+        assert_eq!(
+            mount(&mut dev).err(),
+            Some(Ext4Error::Mount(RSEXT4Error::UnsupportedFeature))
+        );
+        let mut fs = mount_ro(&mut dev).unwrap();
+        assert!(fs.read_only);
+        assert_eq!(mkfile(&mut dev, &mut fs, "/x", Some(b"x"), None).err(), Some(Ext4Error::ReadOnlyFs));
+    }
+
+    #[test]
+    fn sparse_super2_backups_only_in_recorded_groups() {
+        // 5 个块组：经典 sparse_super 会在组 1、3 放备份
+        let bpg = 8 * BLOCK_SIZE as u64;
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(4 * bpg + 8192), false, false);
+        mkfs_with_compat(
+            &mut dev,
+            DEFAULT_FEATURE_COMPAT | Ext4Superblock::EXT4_FEATURE_COMPAT_SPARSE_SUPER2,
+        )
+        .unwrap();
+
+        let fs = mount(&mut dev).unwrap();
+        let sb = fs.superblock;
+        assert_eq!(sb.block_groups_count(), 5);
+        assert_eq!(sb.s_backup_bgs, [1, 4]);
+
+        for gid in 1..5u32 {
+            let group_start = gid as u64 * bpg;
+            let desc = fs.group_descs[gid as usize];
+            let backup = read_backup_superblock(&mut dev, &sb, gid).unwrap();
+            if sb.s_backup_bgs.contains(&gid) {
+                let backup = backup.expect("missing backup superblock");
+                assert_eq!(backup.s_magic, EXT4_SUPER_MAGIC);
+                assert_eq!(backup.s_backup_bgs, [1, 4]);
+                assert!(desc.block_bitmap() > group_start, "group {gid} reserves backup");
+            } else {
+                assert!(backup.is_none());
+                // 没有备份的组从组起始块开始放位图
+                assert_eq!(desc.block_bitmap(), group_start, "group {gid}");
+                dev.read_block(group_start).unwrap();
+                let raw = Ext4Superblock::from_disk_bytes(&dev.buffer()[0..SUPERBLOCK_SIZE]);
+                assert_ne!(raw.s_magic, EXT4_SUPER_MAGIC, "group {gid}");
+            }
+        }
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn used_size_tracks_file_blocks() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let before = used_size(&fs);
+        let stats = fs.statfs();
+        assert_eq!(before, (stats.total_blocks - stats.free_blocks) * stats.block_size);
+
+        let data = [0x42u8; BLOCK_SIZE * 4];
+        mkfile(&mut dev, &mut fs, "/used", Some(&data), None).unwrap();
+        assert_eq!(used_size(&fs), before + 4 * BLOCK_SIZE as u64);
+
+        unlink(&mut dev, &mut fs, "/used").unwrap();
+        assert_eq!(used_size(&fs), before);
+    }
+
+    #[test]
+    fn superblock_with_unknown_trailing_bytes_mounts() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        fs.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        umount(fs, &mut dev).unwrap();
+
+        // 在本版本未解释的保留区写入非零数据，并按完整 1024 字节重算校验和
+        let off = Ext4Superblock::SUPERBLOCK_OFFSET as usize;
+        let pattern = |i: usize| (i as u8) | 1;
+        dev.read_block(0).unwrap();
+        let raw = &mut dev.buffer_mut()[off..off + SUPERBLOCK_SIZE];
+        for (i, b) in raw[0x300..0x3F0].iter_mut().enumerate() {
+            *b = pattern(i);
+        }
+        let csum = Ext4Superblock::compute_checksum(raw);
+        raw[Ext4Superblock::CHECKSUM_OFFSET..].copy_from_slice(&csum.to_le_bytes());
+        dev.write_block(0, false).unwrap();
+
+        let fs = mount(&mut dev).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        // 写回后未识别的字节保持原样，校验和仍然有效
+        dev.read_block(0).unwrap();
+        let raw = &dev.buffer()[off..off + SUPERBLOCK_SIZE];
+        assert!(raw[0x300..0x3F0].iter().enumerate().all(|(i, &b)| b == pattern(i)));
+        assert!(Ext4Superblock::verify_checksum(raw));
+
+        // 任意字节被破坏都会导致校验失败
+        dev.buffer_mut()[off + 0x300] ^= 0xFF;
+        dev.write_block(0, false).unwrap();
+        assert!(matches!(
+            Ext4FileSystem::mount(&mut dev),
+            Err(RSEXT4Error::InvalidSuperblock)
+        ));
+    }
+
+    #[test]
+    fn avoid_range_steers_allocations_while_space_remains() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        // 把接下来最先会被分配的一段区间设为避开区间
+        let probe = fs.alloc_block(&mut dev).unwrap();
+        fs.free_block(&mut dev, probe).unwrap();
+        let avoid_len = 2048;
+        fs.block_allocator.set_avoid_range(probe, avoid_len);
+
+        for i in 0..8u8 {
+            let path = format!("/f{i}");
+            mkfile(&mut dev, &mut fs, &path, Some(&vec![i; BLOCK_SIZE * 16]), None).unwrap();
+            let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, &path).unwrap().unwrap();
+            for &blk in resolve_inode_block_allextend(&mut fs, &mut dev, ino, &mut inode)
+                .unwrap()
+                .values()
+            {
+                assert!(blk < probe || blk >= probe + avoid_len, "block {blk} in avoid range");
+            }
+        }
+
+        // 除避开区间外已无空间时仍然退回到区间内分配
+        let total = fs.superblock.blocks_count();
+        fs.block_allocator.set_avoid_range(0, total);
+        assert!(fs.alloc_block(&mut dev).is_ok());
+    }
+
+    #[test]
+    fn reserve_inodes_fails_fast_and_holds_back_other_allocations() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let free = fs.superblock.s_free_inodes_count;
+
+        assert!(!reserve_inodes(&mut fs, free + 1));
+        assert_eq!(fs.reserved_inodes, 0);
+        assert_eq!(fs.superblock.s_free_inodes_count, free);
+
+        assert!(reserve_inodes(&mut fs, 3));
+        assert_eq!(fs.reserved_inodes, 3);
+        // 其余额度已被预留，再多要一个就失败
+        assert!(!reserve_inodes(&mut fs, free - 2));
+
+        // 空闲 inode 只剩预留额度时，非持有者的分配失败
+        assert!(reserve_inodes(&mut fs, free - 3));
+        assert!(matches!(fs.alloc_inode(&mut dev), Err(BlockDevError::NoSpace)));
+        assert!(matches!(fs.alloc_inode_near(&mut dev, 2, false), Err(BlockDevError::NoSpace)));
+        assert!(mkfile(&mut dev, &mut fs, "/outside", None, None).is_err());
+        assert_eq!(fs.superblock.s_free_inodes_count, free);
+
+        // 持有者的分配逐个消耗额度
+        fs.with_reserved_inodes(|fs| mkfile(&mut dev, fs, "/a", None, None))
+            .unwrap();
+        assert_eq!(fs.reserved_inodes, free - 1);
+        assert_eq!(fs.superblock.s_free_inodes_count, free - 1);
+
+        fs.release_reserved_inodes();
+        mkfile(&mut dev, &mut fs, "/b", None, None).unwrap();
+        assert!(reserve_inodes(&mut fs, free - 2));
+    }
+
+    #[test]
+    fn checksum_policy_decides_bad_superblock_outcome() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        fs.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        mkfile(&mut dev, &mut fs, "/keep", Some(b"recoverable"), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        // 破坏一个不影响布局的保留字节，校验和随之失效
+        let off = Ext4Superblock::SUPERBLOCK_OFFSET as usize;
+        dev.read_block(0).unwrap();
+        dev.buffer_mut()[off + 0x300] ^= 0xFF;
+        dev.write_block(0, false).unwrap();
+
+        assert!(matches!(
+            Ext4FileSystem::mount_with_checksum_policy(&mut dev, ChecksumPolicy::Strict),
+            Err(RSEXT4Error::InvalidSuperblock)
+        ));
+
+        let mut fs =
+            mount_with_checksum_policy(&mut dev, ChecksumPolicy::WarnAndContinue).unwrap();
+        assert_eq!(fs.checksum_policy, ChecksumPolicy::WarnAndContinue);
+        assert_eq!(
+            read_file(&mut dev, &mut fs, "/keep").unwrap(),
+            b"recoverable"
+        );
+
+        assert!(ChecksumPolicy::Strict.check(true, "test").is_ok());
+        assert!(matches!(
+            ChecksumPolicy::Strict.check(false, "test"),
+            Err(BlockDevError::ChecksumError)
+        ));
+        assert!(ChecksumPolicy::WarnAndContinue.check(false, "test").is_ok());
+    }
+
+    #[test]
+    fn inode_table_past_device_end_is_corrupted() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let good = fs.group_descs[0].bg_inode_table_lo;
+        fs.inodetable_cahce.clear();
+
+        // 描述符把 inode 表指到设备末尾，整张表放不下
+        fs.group_descs[0].bg_inode_table_lo = (dev.total_blocks() - 2) as u32;
+        assert!(matches!(
+            fs.get_inode_by_num(&mut dev, 20),
+            Err(BlockDevError::Corrupted)
+        ));
+        assert!(matches!(fs.get_root(&mut dev), Err(BlockDevError::Corrupted)));
+        assert!(matches!(
+            fs.modify_inode(&mut dev, 20, |inode| inode.i_mode = 0),
+            Err(BlockDevError::Corrupted)
+        ));
+
+        fs.group_descs[0].bg_inode_table_lo = good;
+        assert!(fs.get_root(&mut dev).unwrap().is_dir());
+    }
+
+    #[test]
+    fn umount_writes_backups_and_recover_restores_primary() {
+        let bpg = 8 * BLOCK_SIZE as u64;
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(4 * bpg + 8192), false, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        fs.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        mkfile(&mut dev, &mut fs, "/keep", Some(b"backup"), None).unwrap();
+        let free_blocks = fs.superblock.free_blocks_count();
+        let sb = fs.superblock;
+        umount(fs, &mut dev).unwrap();
+
+        // 每个备份组里的超级块记录自己的组号，并有独立的校验和
+        let backups: Vec<u32> = (1..5).filter(|&g| ext4_sb_is_super_in_bg(&sb, g)).collect();
+        assert_eq!(backups, [1, 3]);
+        for &gid in &backups {
+            dev.read_block((gid * sb.s_blocks_per_group) as u64).unwrap();
+            let raw = &dev.buffer()[..SUPERBLOCK_SIZE];
+            assert!(Ext4Superblock::verify_checksum(raw));
+            let backup = Ext4Superblock::from_disk_bytes(raw);
+            assert_eq!(backup.s_block_group_nr as u32, gid);
+            assert_eq!(backup.free_blocks_count(), free_blocks);
+        }
+
+        // 主超级块被破坏后挂载失败，从组 3 的备份恢复
+        dev.with_raw_device(|d| d.write(&[0u8; BLOCK_SIZE], 0, 1)).unwrap();
+        assert!(mount(&mut dev).is_err());
+        assert!(matches!(
+            recover_from_backup(&mut dev, 2),
+            Err(Ext4Error::Corrupt)
+        ));
+        let restored = recover_from_backup(&mut dev, 3).unwrap();
+        assert_eq!(restored.s_block_group_nr, 0);
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/keep").unwrap(), b"backup");
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn free_counts_follow_bitmaps_and_drift_is_repaired() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let data = vec![7u8; 5 * BLOCK_SIZE];
+        mkfile(&mut dev, &mut fs, "/a", Some(&data), None).unwrap();
+        mkfile(&mut dev, &mut fs, "/b", Some(b"b"), None).unwrap();
+        unlink(&mut dev, &mut fs, "/b").unwrap();
+        assert!(verify_free_counts(&mut dev, &mut fs).unwrap().is_empty());
+        let sum_blocks: u64 = fs.group_descs.iter().map(|d| d.free_blocks_count() as u64).sum();
+        let sum_inodes: u32 = fs.group_descs.iter().map(|d| d.free_inodes_count()).sum();
+        assert_eq!(fs.superblock.free_blocks_count(), sum_blocks);
+        assert_eq!(fs.superblock.s_free_inodes_count, sum_inodes);
+
+        // 人为制造描述符计数漂移
+        let desc = &mut fs.group_descs[0];
+        desc.set_free_blocks_count(desc.free_blocks_count() + 5);
+        desc.set_free_inodes_count(desc.free_inodes_count() - 1);
+        fs.superblock.set_free_blocks_count(1);
+        assert_eq!(verify_free_counts(&mut dev, &mut fs).unwrap(), [0]);
+
+        assert_eq!(recompute_free_counts(&mut dev, &mut fs).unwrap(), [0]);
+        assert!(verify_free_counts(&mut dev, &mut fs).unwrap().is_empty());
+        assert_eq!(fs.superblock.free_blocks_count(), sum_blocks);
+        assert_eq!(fs.superblock.s_free_inodes_count, sum_inodes);
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(verify_free_counts(&mut dev, &mut fs).unwrap().is_empty());
+        assert_eq!(fs.superblock.free_blocks_count(), sum_blocks);
+        umount(fs, &mut dev).unwrap();
+    }
+
+    #[test]
+    fn metadata_snapshot_is_unaffected_by_later_changes() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let snap = snapshot_metadata(&fs);
+        let before = snap.statfs();
+        let groups_before = snap.group_free_counts();
+        assert_eq!(before.free_blocks, fs.statfs().free_blocks);
+        assert_eq!(snap.used_size(), fs.used_size());
+
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+        mkfile(&mut dev, &mut fs, "/d/f", Some(&[7u8; BLOCK_SIZE * 3]), None).unwrap();
+        assert!(fs.statfs().free_blocks < before.free_blocks);
+        assert!(fs.statfs().free_inodes < before.free_inodes);
+
+        let after = snap.statfs();
+        assert_eq!(after.free_blocks, before.free_blocks);
+        assert_eq!(after.free_inodes, before.free_inodes);
+        assert_eq!(snap.group_free_counts(), groups_before);
+        assert_ne!(snapshot_metadata(&fs).group_free_counts(), groups_before);
+    }
+
+    #[test]
+    fn allocations_never_land_in_metadata() {
+        let total = 40 * 1024;
+        let (mut dev, mut fs) = setup_fs(total);
+        let layout = compute_fs_layout(DEFAULT_INODE_SIZE, total);
+        let regions: Vec<(u64, u64)> = (0..fs.group_descs.len() as u32)
+            .map(|gid| {
+                let gl = cloc_group_layout(
+                    gid,
+                    &fs.superblock,
+                    layout.blocks_per_group,
+                    layout.inode_table_blocks,
+                    layout.group0_block_bitmap,
+                    layout.group0_inode_bitmap,
+                    layout.group0_inode_table,
+                    layout.gdt_blocks,
+                );
+                let start = gl.group_start_block;
+                (start, start + gl.metadata_blocks_in_group as u64)
+            })
+            .collect();
+        assert_eq!(regions.len(), 2);
+        let in_meta = |b: u64| regions.iter().any(|&(s, e)| (s..e).contains(&b));
+        for &(s, e) in &regions {
+            assert!((s..e).all(|b| !fs.is_data_block(b)));
+            assert!(fs.is_data_block(e));
+        }
+
+        let mut got = Vec::new();
+        // 以元数据块为目标的分配也只能落在数据区
+        for &(s, e) in &regions {
+            for goal in [s, s + 1, e - 1] {
+                let (start, n) = fs.alloc_blocks_near(&mut dev, Some(goal), 8).unwrap();
+                got.extend(start..start + n as u64);
+            }
+        }
+        // 再把剩余空间全部分完
+        let mut want = 1024;
+        while want > 0 {
+            match fs.alloc_blocks_near(&mut dev, None, want) {
+                Ok((start, n)) => got.extend(start..start + n as u64),
+                Err(BlockDevError::NoSpace) => want /= 2,
+                Err(e) => panic!("unexpected {e:?}"),
+            }
+        }
+        assert!(got.len() > 30 * 1024);
+        assert!(got.iter().all(|&b| !in_meta(b) && b < total));
+
+        // 位图把块 0 错标为空闲也不会分出去
+        let bitmap = fs.group_descs[0].block_bitmap();
+        fs.bitmap_cache
+            .modify(&mut dev, CacheKey::new_block(0), bitmap, |data| data[0] &= !1)
+            .unwrap();
+        fs.adjust_free_clusters(0, 1);
+        assert!(matches!(
+            fs.alloc_blocks_near(&mut dev, None, 1),
+            Err(BlockDevError::NoSpace)
+        ));
+        // inode 表块被错标为空闲时同样不会分出去，位图也不被改动
+        let table = fs.group_descs[0].inode_table();
+        fs.bitmap_cache
+            .modify(&mut dev, CacheKey::new_block(0), bitmap, |data| {
+                data[(table / 8) as usize] &= !(1 << (table % 8))
+            })
+            .unwrap();
+        fs.adjust_free_clusters(0, 1);
+        assert!(matches!(
+            fs.alloc_blocks_near(&mut dev, Some(table), 1),
+            Err(BlockDevError::NoSpace)
+        ));
+        let data = &fs.bitmap_cache.get_or_load(&mut dev, CacheKey::new_block(0), bitmap).unwrap().data;
+        assert_eq!(data[(table / 8) as usize] & (1 << (table % 8)), 0);
+        assert!(matches!(fs.check_data_blocks(table, 1), Err(BlockDevError::Corrupted)));
+    }
+
+    #[test]
+    fn fstrim_discards_free_runs_matching_bitmap() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let (free, _) = fs.bitmap_free_counts(&mut dev, 0).unwrap().unwrap();
+        let half: Vec<u8> = (0..(free as usize / 2) * BLOCK_SIZE).map(|i| (i % 253) as u8).collect();
+        mkfile(&mut dev, &mut fs, "/half", Some(&half), None).unwrap();
+        let (free, _) = fs.bitmap_free_counts(&mut dev, 0).unwrap().unwrap();
+        assert!(free > 0);
+
+        let trimmed = fstrim(&mut dev, &mut fs, DEFAULT_FSTRIM_MIN_BLOCKS).unwrap();
+        assert_eq!(trimmed, free as u64 * BLOCK_SIZE as u64);
+        // 过长的最小长度下短区间不再 trim
+        assert_eq!(fstrim(&mut dev, &mut fs, free + 1).unwrap(), 0);
+        assert_eq!(read_file(&mut dev, &mut fs, "/half").unwrap(), half);
+    }
+
+    #[test]
+    fn fstrim_covers_block_uninit_groups_and_respects_caps_and_read_only() {
+        let (mut dev, mut fs) = setup_fs(40 * 1024);
+        assert_eq!(fs.group_descs.len(), 2);
+        let with_bitmap = fstrim(&mut dev, &mut fs, 1).unwrap();
+
+        // 组 1 标成 BLOCK_UNINIT 后不读位图，按元数据区间推出的空闲块与位图一致
+        fs.group_descs[1].bg_flags |= Ext4GroupDesc::EXT4_BG_BLOCK_UNINIT;
+        fs.bitmap_cache.flush_all(&mut dev).unwrap();
+        fs.bitmap_cache.clear();
+        assert_eq!(fstrim(&mut dev, &mut fs, 1).unwrap(), with_bitmap);
+        assert!(fs.bitmap_cache.get(&CacheKey::new_block(1)).is_none());
+        fs.group_descs[1].bg_flags &= !Ext4GroupDesc::EXT4_BG_BLOCK_UNINIT;
+
+        // 设备不支持 discard：不下发任何 discard
+        let mut counting = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(16 * 1024), false, false);
+        mkfs(&mut counting).unwrap();
+        let mut cfs = mount(&mut counting).unwrap();
+        counting.with_raw_device(|d| {
+            d.caps.discard = false;
+            Ok(())
+        })
+        .unwrap();
+        assert!(matches!(
+            fstrim(&mut counting, &mut cfs, 1),
+            Err(Ext4Error::Device(BlockDevError::Unsupported))
+        ));
+        assert!(counting.with_raw_device(|d| Ok(d.discards.is_empty())).unwrap());
+
+        umount(fs, &mut dev).unwrap();
+        let mut ro = Ext4FileSystem::mount_ro(&mut dev).unwrap();
+        assert!(matches!(fstrim(&mut dev, &mut ro, 1), Err(Ext4Error::ReadOnlyFs)));
+    }
+
+    #[test]
+    fn umount_without_checkpoint_leaves_committed_journal() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+        dev.set_journal_use(true);
+        // 第一次日志挂载创建 journal
+        let fs = mount(&mut dev).unwrap();
+        umount(fs, &mut dev).unwrap();
+        assert_eq!(dev.journal_usage().used_blocks, 0);
+
+        let mut fs = mount(&mut dev).unwrap();
+        mkfile(&mut dev, &mut fs, "/fast", Some(b"replay me"), None).unwrap();
+        let opts = UmountOptions {
+            checkpoint: false,
+            ..UmountOptions::default()
+        };
+        umount_with(fs, &mut dev, &opts).unwrap();
+        assert!(dev.journal_usage().used_blocks > 0);
+        let recover = Ext4Superblock::EXT4_FEATURE_INCOMPAT_RECOVER;
+        let sb = Ext4FileSystem::probe(&mut dev).unwrap();
+        assert_ne!(sb.s_feature_incompat & recover, 0);
+
+        let mut rec = Jbd2Dev::initial_jbd2dev(0, dev.with_raw_device(|d| Ok(d.clone())).unwrap(), true, false);
+        assert!(jbd2_recover(&mut rec).unwrap() >= 1);
+        assert_eq!(Ext4FileSystem::probe(&mut rec).unwrap().s_feature_incompat & recover, 0);
+        let mut fs = mount(&mut rec).unwrap();
+        assert_eq!(read_file(&mut rec, &mut fs, "/fast").unwrap(), b"replay me");
+        mkfile(&mut rec, &mut fs, "/slow", Some(b"clean"), None).unwrap();
+        umount(fs, &mut rec).unwrap();
+        assert_eq!(rec.journal_usage().used_blocks, 0);
+
+        let mut again = Jbd2Dev::initial_jbd2dev(0, rec.with_raw_device(|d| Ok(d.clone())).unwrap(), true, false);
+        assert_eq!(jbd2_recover(&mut again).unwrap(), 0);
+        let mut fs = mount(&mut again).unwrap();
+        assert_eq!(read_file(&mut again, &mut fs, "/slow").unwrap(), b"clean");
+    }
+
+    #[test]
+    fn mkfs_small_images_shrink_journal_or_fail_cleanly() {
+        // 2MB：连块组 0 的元数据加最小 journal 都放不下，干净地报错而不是产出坏镜像
+        let mut tiny = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(512), true, false);
+        match mkfs(&mut tiny) {
+            Err(Ext4Error::ImageTooSmall {
+                required_blocks,
+                total_blocks,
+            }) => {
+                assert_eq!(total_blocks, 512);
+                assert!(required_blocks > total_blocks);
+            }
+            other => panic!("expected ImageTooSmall, got {other:?}"),
+        }
+
+        // 略大于最小尺寸：journal 自动缩小，镜像能以日志模式挂载并正常读写
+        let layout = compute_fs_layout(DEFAULT_INODE_SIZE, 4096);
+        let total = layout.group0_metadata_blocks as u64 + MKFS_MIN_DATA_BLOCKS + 3 * JOURNAL_MIN_BLOCKS as u64;
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(total), true, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let journal = fs.get_inode_by_num(&mut dev, JOURNAL_FILE_INODE as u32).unwrap();
+        let journal_blocks = journal.size() / BLOCK_SIZE as u64;
+        assert!(journal_blocks >= JOURNAL_MIN_BLOCKS as u64);
+        assert!(journal_blocks < JOURNAL_DEFAULT_BLOCKS as u64);
+        mkfile(&mut dev, &mut fs, "/f", Some(b"small"), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/f").unwrap(), b"small");
+    }
+
+    #[test]
+    fn limits_match_known_ext4_maxima() {
+        let (_dev, mut fs) = setup_fs(16 * 1024);
+        const TIB: u64 = 1 << 40;
+        // 默认 4K 块 extents+64bit，未启用 huge_file：i_blocks 把文件限制在 2TiB - 4KiB
+        let l = limits(&fs);
+        assert_eq!(l.max_file_size, 2 * TIB - 4096);
+        assert_eq!(l.max_fs_size, 1 << 60);
+        assert_eq!(l.max_inode_count, u32::MAX as u64);
+
+        // huge_file：32 位逻辑块号的上限 16TiB - 4KiB
+        fs.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_HUGE_FILE;
+        assert_eq!(limits(&fs).max_file_size, 16 * TIB - 4096);
+
+        // 非 64bit：文件系统最大 16TiB
+        fs.superblock.s_feature_incompat &= !Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT;
+        assert_eq!(limits(&fs).max_fs_size, 16 * TIB);
+
+        // 间接块映射：12 + 1024 + 1024^2 + 1024^3 个块，约 4TiB
+        fs.superblock.s_feature_incompat &= !Ext4Superblock::EXT4_FEATURE_INCOMPAT_EXTENTS;
+        assert_eq!(
+            limits(&fs).max_file_size,
+            (12 + 1024 + (1u64 << 20) + (1u64 << 30)) * 4096
+        );
+    }
+
+    #[test]
+    fn mkfs_marks_reserved_inodes_and_allocates_from_first_ino() {
+        let (mut dev, mut fs) = setup_fs(32768 + 8192);
+        let first_ino = fs.superblock.s_first_ino;
+        assert_eq!(first_ino, RESERVED_INODES + 1);
+
+        let bitmap_block = fs.group_descs[0].inode_bitmap();
+        dev.read_block(bitmap_block).unwrap();
+        let bitmap = dev.buffer().to_vec();
+        let used = |ino: u32| bitmap[((ino - 1) / 8) as usize] & (1 << ((ino - 1) % 8)) != 0;
+        for ino in 1..first_ino {
+            assert!(used(ino), "reserved inode {ino} not marked used");
+        }
+        assert!(used(fs.root_inode));
+
+        // mkfs 时创建的 lost+found 是第一个非保留 inode，之后的用户文件紧随其后
+        assert_eq!(fs.superblock.s_lpf_ino, first_ino);
+        let (ino, _) = try_mkfile_with_ino(&mut dev, &mut fs, "/first", None, None).unwrap();
+        assert_eq!(ino, first_ino + 1);
+
+        // 其它块组没有保留 inode，从组内第一个 inode 开始分配
+        let ipg = fs.superblock.s_inodes_per_group;
+        let got = fs.alloc_inodes_in_group(&mut dev, 1, 1).unwrap();
+        assert_eq!(got, vec![ipg + 1]);
+    }
+
+    #[test]
+    fn lazy_gdt_backups_are_written_once_at_umount() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(40960), false, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(fs.gdt_backup_policy, GdtBackupPolicy::Lazy);
+        assert!(ext4_sb_is_super_in_bg(&fs.superblock, 1));
+        let backup_gdt = backup_group_start(&fs.superblock, 1) as u32 + 1;
+        let desc_size = fs.superblock.get_desc_size() as usize;
+        let backup_writes = |dev: &mut Jbd2Dev<CountingDev>| {
+            dev.with_raw_device(|d| {
+                Ok(d.write_log
+                    .iter()
+                    .filter(|&&(b, n)| b <= backup_gdt && backup_gdt < b + n)
+                    .count())
+            })
+            .unwrap()
+        };
+        let primary_free = |dev: &mut Jbd2Dev<CountingDev>| {
+            dev.with_raw_device(|d| {
+                let mut buf = [0u8; BLOCK_SIZE];
+                d.read(&mut buf, PRIMARY_GDT_BLOCK, 1)?;
+                Ok(Ext4GroupDesc::from_disk_bytes(&buf[..desc_size]).free_blocks_count())
+            })
+            .unwrap()
+        };
+        dev.with_raw_device(|d| {
+            d.write_log.clear();
+            Ok(())
+        })
+        .unwrap();
+
+        for i in 0..20 {
+            mkfile(&mut dev, &mut fs, &format!("/f{i}"), Some(&[7u8; 5000]), None).unwrap();
+            fs.commit_metadata(&mut dev, &[]).unwrap();
+            assert_eq!(primary_free(&mut dev), fs.group_descs[0].free_blocks_count());
+        }
+        assert!(fs.gdt_backups_stale);
+        assert_eq!(backup_writes(&mut dev), 0);
+        umount(fs, &mut dev).unwrap();
+        assert_eq!(backup_writes(&mut dev), 1);
+
+        // Eager：每次写回描述符都同步备份
+        let mut fs = mount(&mut dev).unwrap();
+        fs.gdt_backup_policy = GdtBackupPolicy::Eager;
+        dev.with_raw_device(|d| {
+            d.write_log.clear();
+            Ok(())
+        })
+        .unwrap();
+        for i in 0..3 {
+            mkfile(&mut dev, &mut fs, &format!("/g{i}"), Some(b"x"), None).unwrap();
+            fs.commit_metadata(&mut dev, &[]).unwrap();
+        }
+        assert!(!fs.gdt_backups_stale);
+        assert_eq!(backup_writes(&mut dev), 3);
+        // 备份已是最新，卸载不再重写
+        umount(fs, &mut dev).unwrap();
+        assert_eq!(backup_writes(&mut dev), 3);
+
+        // 没有任何修改的挂载/卸载也不碰备份
+        let fs = mount(&mut dev).unwrap();
+        umount(fs, &mut dev).unwrap();
+        assert_eq!(backup_writes(&mut dev), 3);
+    }
+
+    #[test]
+    fn bitmap_checksums_round_trip_and_catch_corruption() {
+        use crate::ext4_backend::crc32c::crc32c;
+
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        fs.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        mkfile(&mut dev, &mut fs, "/a", Some(b"first"), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        // 描述符里的校验和与磁盘位图独立计算的结果一致
+        let check = |dev: &mut Jbd2Dev<MemBlockDev>, fs: &Ext4FileSystem| {
+            let sb = &fs.superblock;
+            let seed = sb.metadata_csum_seed();
+            let desc = fs.group_descs[0];
+            dev.read_block(desc.block_bitmap()).unwrap();
+            let sum = crc32c(seed, &dev.buffer()[..sb.s_blocks_per_group as usize / 8]);
+            assert_eq!(desc.block_bitmap_csum(), sum);
+            dev.read_block(desc.inode_bitmap()).unwrap();
+            let sum = crc32c(seed, &dev.buffer()[..sb.s_inodes_per_group as usize / 8]);
+            assert_eq!(desc.inode_bitmap_csum(), sum);
+        };
+
+        let mut fs = mount(&mut dev).unwrap();
+        check(&mut dev, &fs);
+        let data = vec![7u8; 3 * BLOCK_SIZE];
+        mkfile(&mut dev, &mut fs, "/b", Some(&data), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+
+        // 写回过的位图重新加载时校验通过
+        let mut fs = mount(&mut dev).unwrap();
+        check(&mut dev, &fs);
+        assert_eq!(read_file(&mut dev, &mut fs, "/b").unwrap(), data);
+        mkfile(&mut dev, &mut fs, "/c", Some(b"third"), None).unwrap();
+        fs.bitmap_cache.flush_all(&mut dev).unwrap();
+        fs.bitmap_cache.clear();
+        fs.sync_group_descriptors(&mut dev).unwrap();
+
+        // 磁盘上的位图被改动后，加载时报告校验和错误
+        let bitmap_block = fs.group_descs[0].block_bitmap();
+        dev.read_block(bitmap_block).unwrap();
+        dev.buffer_mut()[100] ^= 0x01;
+        dev.write_block(bitmap_block, false).unwrap();
+        assert!(matches!(
+            fs.bitmap_cache.get_or_load(&mut dev, CacheKey::new_block(0), bitmap_block),
+            Err(BlockDevError::ChecksumError)
+        ));
+        drop(fs);
+        assert!(mount(&mut dev).is_err());
+    }
+
+    #[test]
+    fn filesystem_inside_partition_mounts_through_offset() {
+        const OFFSET: u64 = 2048;
+        const SIZE: u64 = 16 * 1024;
+        let disk = MemBlockDev::new(OFFSET + SIZE + 64);
+        let part = PartitionDev::new(disk, OFFSET, SIZE).unwrap();
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, part, false, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        mkfile(&mut dev, &mut fs, "/hello", Some(b"partition"), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/hello").unwrap(), b"partition");
+        assert_eq!(fs.superblock.blocks_count(), SIZE);
+
+        dev.with_raw_device(|part| {
+            let mut buf = [0u8; BLOCK_SIZE];
+            // 分区外的块不受影响，超级块位于分区起始处
+            let disk = part.inner_mut();
+            disk.read64(&mut buf, 0, 1)?;
+            assert!(buf.iter().all(|&b| b == 0));
+            disk.read64(&mut buf, OFFSET, 1)?;
+            assert_eq!(u16::from_le_bytes([buf[1024 + 56], buf[1024 + 57]]), EXT4_SUPER_MAGIC);
+
+            assert_eq!(
+                part.read64(&mut buf, SIZE, 1),
+                Err(BlockDevError::BlockOutOfRange { block_id: SIZE, max_blocks: SIZE })
+            );
+            assert!(part.write64(&[0u8; BLOCK_SIZE * 2], SIZE - 1, 2).is_err());
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(PartitionDev::new(MemBlockDev::new(100), 64, 64).is_err());
+    }
+
+    #[test]
+    fn cache_capacities_bound_each_category() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        // 挂载时各类缓存的容量已按默认 CacheCapacities 设置
+        let capacity = |s: Ext4CacheStats| s.data.capacity + s.inode.capacity + s.bitmap.capacity;
+        assert_eq!(capacity(fs.cache_stats()), CacheCapacities::default().total_blocks());
+        assert_eq!(CacheCapacities::new(4, 4, 4), Err(Ext4Error::InvalidInput));
+        assert_eq!(CacheCapacities::new(24, 2, 4), Err(Ext4Error::InvalidInput));
+        let caps = CacheCapacities::new(24, 4, 4).unwrap();
+        fs.set_cache_capacities(&mut dev, caps).unwrap();
+
+        let data: Vec<u8> = (0..200 * BLOCK_SIZE).map(|i| (i % 251) as u8).collect();
+        mkfile(&mut dev, &mut fs, "/big", Some(&data), None).unwrap();
+        mkfile(&mut dev, &mut fs, "/small", Some(b"small"), None).unwrap();
+        // 已解析的 inode 也计入 inode 容量：4 块中 2 块给表块，另 2 块最多折算 32 个 inode
+        for i in 0..64 {
+            mkfile(&mut dev, &mut fs, &alloc::format!("/f{i}"), None, None).unwrap();
+        }
+        assert_eq!(fs.inodetable_cahce.stats().total_entries, 32);
+        assert_eq!(read_file(&mut dev, &mut fs, "/big").unwrap(), data);
+
+        // 顺序读大文件只淘汰数据块缓存中的条目，inode 表块和位图仍留在缓存里
+        let stats = fs.cache_stats();
+        assert!(stats.cached_blocks() <= caps.total_blocks(), "{stats:?}");
+        assert!(stats.data.cached_blocks <= 24);
+        assert_eq!(stats.data.capacity, 24);
+        assert!(stats.inode.cached_blocks > 0);
+        assert!(stats.bitmap.cached_blocks > 0);
+        assert!(stats.inode.counters.hits > 0 && stats.bitmap.counters.hits > 0);
+        assert!(stats.data.counters.evictions > 0);
+        assert_eq!(capacity(stats), caps.total_blocks());
+
+        fs.flush_all_caches(&mut dev).unwrap();
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/small").unwrap(), b"small");
+        assert_eq!(fs.cache_capacities(), CacheCapacities::default());
+        umount(fs, &mut dev).unwrap();
+
+        // remount_rw 沿用只读挂载时设置的容量
+        let mut fs = mount_ro(&mut dev).unwrap();
+        fs.set_cache_capacities(&mut dev, caps).unwrap();
+        remount_rw(&mut dev, &mut fs).unwrap();
+        assert_eq!(fs.cache_capacities(), caps);
+        assert_eq!(capacity(fs.cache_stats()), caps.total_blocks());
+    }
+
+    #[test]
+    fn cache_stats_count_exact_hits_and_misses() {
+        use crate::ext4_backend::bitmap_cache::CacheKey;
+
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        fs.flush_all_caches(&mut dev).unwrap();
+        fs.inodetable_cahce.clear();
+        fs.datablock_cache.clear();
+        fs.bitmap_cache.clear();
+        fs.reset_cache_stats();
+        assert_eq!(fs.cache_stats().total(), CacheCounters::default());
+        assert_eq!(fs.cache_stats().cached_blocks(), 0);
+
+        // inode 3 读表块（未命中），再读 3 和同一表块上的 5 都命中
+        for ino in [3, 3, 5] {
+            fs.get_inode_by_num(&mut dev, ino).unwrap();
+        }
+        let blk = 2000u64;
+        fs.datablock_cache.get_or_load(&mut dev, blk).unwrap();
+        fs.datablock_cache.modify(&mut dev, blk, |d| d[0] = 1).unwrap();
+        fs.datablock_cache.get_or_load(&mut dev, blk + 1).unwrap();
+        fs.datablock_cache.flush_all(&mut dev).unwrap();
+        let bitmap_blk = fs.group_descs[0].block_bitmap();
+        for _ in 0..3 {
+            fs.bitmap_cache
+                .get_or_load(&mut dev, CacheKey::new_block(0), bitmap_blk)
+                .unwrap();
+        }
+
+        let stats = fs.cache_stats();
+        assert_eq!(stats.inode.counters, CacheCounters { hits: 2, misses: 1, writebacks: 0, evictions: 0 });
+        assert_eq!(stats.data.counters, CacheCounters { hits: 1, misses: 2, writebacks: 1, evictions: 0 });
+        assert_eq!(stats.bitmap.counters, CacheCounters { hits: 2, misses: 1, writebacks: 0, evictions: 0 });
+        assert_eq!(stats.total().hits, 5);
+        assert_eq!(stats.total().misses, 4);
+        assert_eq!(stats.data.counters.hit_rate_percent(), 33);
+
+        fs.reset_cache_stats();
+        assert_eq!(fs.cache_stats().total(), CacheCounters::default());
+    }
+
+    #[test]
+    fn enable_metadata_csum_checksums_all_existing_metadata() {
+        use crate::ext4_backend::hashtree::Ext4InodeHashTreeExt;
+        use crate::ext4_backend::xattr::{get_xattr, set_xattr};
+
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/big").unwrap();
+        for i in 0..600 {
+            mkfile(&mut dev, &mut fs, &format!("/big/f{i:04}"), None, None).unwrap();
+        }
+        mkfile(&mut dev, &mut fs, "/sparse", None, None).unwrap();
+        for i in 0..12u64 {
+            write_file(&mut dev, &mut fs, "/sparse", i * 2 * BLOCK_SIZE as u64, b"x").unwrap();
+        }
+        for i in 0..40 {
+            set_xattr(&mut dev, &mut fs, "/sparse", &format!("user.a{i:02}"), &[i as u8; 40]).unwrap();
+        }
+        let (_, big) = get_file_inode(&mut fs, &mut dev, "/big").unwrap().unwrap();
+        assert!(big.is_htree_indexed());
+        let (ino, mut sparse) = get_file_inode(&mut fs, &mut dev, "/sparse").unwrap().unwrap();
+        assert_ne!(sparse.file_acl(), 0);
+        assert!(!collect_extent_tree_blocks(&mut dev, &fs, ino, &mut sparse).unwrap().is_empty());
+
+        fs.enable_metadata_csum(&mut dev).unwrap();
+        assert!(fs.superblock.has_metadata_csum());
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(fsck_metadata_csums(&mut dev, &mut fs).unwrap(), vec![]);
+        assert!(get_file_inode(&mut fs, &mut dev, "/big/f0421").unwrap().is_some());
+        let got = read_file(&mut dev, &mut fs, "/sparse").unwrap();
+        assert_eq!(got[22 * BLOCK_SIZE], b'x');
+        assert_eq!(
+            get_xattr(&mut dev, &mut fs, "/sparse", "user.a07").unwrap(),
+            Some(vec![7u8; 40])
+        );
+
+        // 开启后的新写入同样带校验和
+        mkfile(&mut dev, &mut fs, "/big/new", Some(b"new"), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(fsck_metadata_csums(&mut dev, &mut fs).unwrap(), vec![]);
+    }
+}
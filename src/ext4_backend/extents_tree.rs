@@ -2,10 +2,12 @@ use log::{debug, error};
 
 use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::config::*;
+use crate::ext4_backend::crc32c::*;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::endian::*;
 use crate::ext4_backend::ext4::*;
 use crate::ext4_backend::error::*;
+use crate::ext4_backend::superblock::Ext4Superblock;
 use alloc::vec;
 use alloc::vec::*;
 
@@ -42,11 +44,174 @@ impl ExtentNode {
     pub fn is_leaf(&self) -> bool {
         matches!(self, ExtentNode::Leaf { .. })
     }
+
+    /// 校验节点内条目按逻辑块号严格递增；叶子节点的 extent 之间还不能重叠
+    /// 违反时返回 Corrupted，避免损坏的树把逻辑块映射到错误的数据
+    pub fn verify_order(&self) -> BlockDevResult<()> {
+        match self {
+            ExtentNode::Leaf { entries, .. } => verify_extent_order(entries)?,
+            ExtentNode::Index { entries, .. } => {
+                for pair in entries.windows(2) {
+                    if pair[1].ei_block <= pair[0].ei_block {
+                        error!(
+                            "Extent index out of order: {} then {}",
+                            pair[0].ei_block, pair[1].ei_block
+                        );
+                        return Err(BlockDevError::Corrupted);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 校验一组 extent 按逻辑块号严格递增且互不重叠
+pub fn verify_extent_order(entries: &[Ext4Extent]) -> BlockDevResult<()> {
+    for pair in entries.windows(2) {
+        let end = pair[0].ee_block as u64 + extent_block_count(&pair[0]) as u64;
+        if pair[1].ee_block <= pair[0].ee_block || end > pair[1].ee_block as u64 {
+            error!(
+                "Extent leaf out of order: [{}, {}) then {}",
+                pair[0].ee_block, end, pair[1].ee_block
+            );
+            return Err(BlockDevError::Corrupted);
+        }
+    }
+    Ok(())
+}
+
+/// extent 实际覆盖的块数：超过 EXT_INIT_MAX_LEN 的部分表示未初始化 extent
+pub fn extent_block_count(et: &Ext4Extent) -> u32 {
+    if et.is_initialized() {
+        et.ee_len as u32
+    } else {
+        (et.ee_len - Ext4Extent::EXT_INIT_MAX_LEN) as u32
+    }
+}
+
+/// extent 树允许的最大深度
+const EXT4_MAX_EXTENT_DEPTH: u16 = 5;
+
+/// 按逻辑块号顺序遍历整棵 extent 树（含中间索引节点），
+/// 逐个产出 (逻辑块号, 物理块号, 块数, 是否未初始化)，块数按 extent_block_count 计算
+/// 每个节点都校验 magic、条目数、顺序和深度（子节点深度必须恰好比父节点小 1），
+/// 遇到损坏的节点时产出一次 CorruptExtentTree 后结束，不会 panic
+pub struct ExtentIter<'d, B: BlockDevice> {
+    dev: &'d mut Jbd2Dev<B>,
+    /// 文件系统总块数，索引指向范围之外的块视为损坏
+    blocks_count: u64,
+    /// 从根到当前节点的路径：(节点, 下一个要访问的条目下标)
+    stack: Vec<(ExtentNode, usize)>,
+    /// 根节点解析失败时先产出的错误
+    pending_err: Option<BlockDevError>,
+}
+
+/// 遍历 inode 的全部 extent；inode 不使用 extent 时产出空序列
+pub fn iter_extents<'d, B: BlockDevice>(
+    block_dev: &'d mut Jbd2Dev<B>,
+    fs: &Ext4FileSystem,
+    inode: &Ext4Inode,
+) -> ExtentIter<'d, B> {
+    let mut iter = ExtentIter {
+        dev: block_dev,
+        blocks_count: fs.superblock.blocks_count(),
+        stack: Vec::new(),
+        pending_err: None,
+    };
+    let mut copy = *inode;
+    if !copy.have_extend_header_and_use_extend() {
+        return iter;
+    }
+    match ExtentTree::new(&mut copy).load_root_checked() {
+        Ok(Some(root)) if root.header().eh_depth <= EXT4_MAX_EXTENT_DEPTH => {
+            iter.stack.push((root, 0))
+        }
+        Ok(_) => iter.pending_err = Some(BlockDevError::Corrupted),
+        Err(e) => iter.pending_err = Some(e),
+    }
+    iter
+}
+
+impl<B: BlockDevice> ExtentIter<'_, B> {
+    /// 读入索引条目指向的子节点，并检查其深度
+    fn load_child(&mut self, idx: &Ext4ExtentIdx, parent_depth: u16) -> BlockDevResult<ExtentNode> {
+        let child = ((idx.ei_leaf_hi as u64) << 32) | idx.ei_leaf_lo as u64;
+        if child == 0 || child >= self.blocks_count {
+            error!("extent index points outside the filesystem: {child}");
+            return Err(BlockDevError::Corrupted);
+        }
+        self.dev.read_block(child)?;
+        let node = ExtentTree::parse_node_checked(self.dev.buffer())?;
+        if node.header().eh_depth + 1 != parent_depth {
+            error!(
+                "extent node {child} depth {} under parent depth {parent_depth}",
+                node.header().eh_depth
+            );
+            return Err(BlockDevError::Corrupted);
+        }
+        Ok(node)
+    }
+}
+
+impl<B: BlockDevice> Iterator for ExtentIter<'_, B> {
+    type Item = Ext4Result<(u32, u64, u32, bool)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.pending_err.take() {
+            return Some(Err(extent_error(e)));
+        }
+        loop {
+            let (node, pos) = self.stack.last_mut()?;
+            let idx = match node {
+                ExtentNode::Leaf { entries, .. } => {
+                    let Some(e) = entries.get(*pos) else {
+                        self.stack.pop();
+                        continue;
+                    };
+                    *pos += 1;
+                    return Some(Ok((
+                        e.ee_block,
+                        e.start_block(),
+                        extent_block_count(e),
+                        !e.is_initialized(),
+                    )));
+                }
+                ExtentNode::Index { header, entries } => {
+                    let Some(idx) = entries.get(*pos).copied() else {
+                        self.stack.pop();
+                        continue;
+                    };
+                    *pos += 1;
+                    (idx, header.eh_depth)
+                }
+            };
+            match self.load_child(&idx.0, idx.1) {
+                Ok(child) => self.stack.push((child, 0)),
+                Err(e) => {
+                    self.stack.clear();
+                    return Some(Err(extent_error(e)));
+                }
+            }
+        }
+    }
+}
+
+/// 节点结构不合法归为 CorruptExtentTree，校验和与设备错误按块层统一映射
+fn extent_error(e: BlockDevError) -> Ext4Error {
+    match e {
+        BlockDevError::Corrupted => Ext4Error::CorruptExtentTree,
+        other => other.into(),
+    }
 }
 
 /// 绑定到单个 inode 的 extent 树视图（不持有 BlockDev，按需传入）
 pub struct ExtentTree<'a> {
     pub inode: &'a mut Ext4Inode,
+    /// 该 inode 的元数据校验和种子，启用 metadata_csum 时写 extent 块会带上块尾校验和
+    csum_seed: Option<u32>,
+    /// 读入的 extent 块校验和不一致时的处理策略（取自文件系统）
+    csum_policy: ChecksumPolicy,
 }
 
 /// 用于在递归插入时向上冒泡分裂信息
@@ -60,7 +225,77 @@ struct SplitInfo {
 impl<'a> ExtentTree<'a> {
     /// 构造：从给定 inode 开始操作其 extent 树
     pub fn new(inode: &'a mut Ext4Inode) -> Self {
-        Self { inode }
+        Self {
+            inode,
+            csum_seed: None,
+            csum_policy: ChecksumPolicy::default(),
+        }
+    }
+
+    /// 文件系统启用 metadata_csum 时，为 inode ino 新写的 extent 块计算块尾校验和
+    pub fn with_checksum(self, fs: &Ext4FileSystem, ino: u32) -> Self {
+        let mut tree = self.with_superblock_checksum(&fs.superblock, ino);
+        tree.csum_policy = fs.checksum_policy;
+        tree
+    }
+
+    /// 尚未挂载（没有 Ext4FileSystem）时直接按超级块开启校验，策略为默认的 Strict
+    pub fn with_superblock_checksum(mut self, sb: &Ext4Superblock, ino: u32) -> Self {
+        if sb.has_metadata_csum() {
+            self.csum_seed = Some(Self::inode_csum_seed(
+                sb.metadata_csum_seed(),
+                ino,
+                self.inode.i_generation,
+            ));
+        }
+        self
+    }
+
+    /// inode 的校验和种子：依次混入 inode 号和 i_generation
+    pub fn inode_csum_seed(fs_seed: u32, ino: u32, generation: u32) -> u32 {
+        let seed = crc32c(fs_seed, &ino.to_le_bytes());
+        crc32c(seed, &generation.to_le_bytes())
+    }
+
+    /// extent 块尾（ext4_extent_tail）的偏移：紧跟 eh_max 个条目之后
+    fn tail_offset(block: &[u8]) -> Option<usize> {
+        let header = Ext4ExtentHeader::from_disk_bytes(block.get(..Ext4ExtentHeader::disk_size())?);
+        let off = Ext4ExtentHeader::disk_size() + header.eh_max as usize * Ext4Extent::disk_size();
+        (off + 4 <= block.len()).then_some(off)
+    }
+
+    /// 计算 extent 块的校验和（覆盖块尾之前的全部字节）
+    pub fn block_checksum(seed: u32, block: &[u8]) -> Option<u32> {
+        Self::tail_offset(block).map(|off| crc32c(seed, &block[..off]))
+    }
+
+    /// 重算 extent 块尾的校验和，块内放不下块尾时不变
+    pub fn refresh_block_checksum(seed: u32, block: &mut [u8]) {
+        if let Some(off) = Self::tail_offset(block) {
+            let sum = crc32c(seed, &block[..off]);
+            write_u32_le(sum, &mut block[off..off + 4]);
+        }
+    }
+
+    /// 校验 extent 块尾的校验和
+    pub fn verify_block_checksum(seed: u32, block: &[u8]) -> bool {
+        match (Self::tail_offset(block), Self::block_checksum(seed, block)) {
+            (Some(off), Some(sum)) => read_u32_le(&block[off..off + 4]) == sum,
+            _ => false,
+        }
+    }
+
+    /// seed 不为 None 时核对读入的 extent 块尾校验和，不一致时按 policy 处理
+    fn check_block_csum(seed: Option<u32>, policy: ChecksumPolicy, block: &[u8]) -> BlockDevResult<()> {
+        match seed {
+            Some(seed) => policy.check(Self::verify_block_checksum(seed, block), "Extent block"),
+            None => Ok(()),
+        }
+    }
+
+    /// 核对从磁盘读入的非根 extent 节点块的校验和（供树外的遍历使用）
+    pub(crate) fn check_node_block(&self, block: &[u8]) -> BlockDevResult<()> {
+        Self::check_block_csum(self.csum_seed, self.csum_policy, block)
     }
 
     fn add_inode_sectors_for_block(&mut self) {
@@ -83,8 +318,25 @@ impl<'a> ExtentTree<'a> {
         Self::parse_node_from_bytes(bytes)
     }
 
-    /// 从原始字节缓冲区解析一个 extent 节点（根或子节点）
+    /// 解析节点并校验条目顺序，用于读路径；解析失败或顺序错误都返回 Corrupted
+    pub fn parse_node_checked(bytes: &[u8]) -> BlockDevResult<ExtentNode> {
+        let node = Self::parse_node_unsorted(bytes).ok_or(BlockDevError::Corrupted)?;
+        node.verify_order()?;
+        Ok(node)
+    }
+
+    /// 从原始字节缓冲区解析一个 extent 节点（根或子节点），条目按逻辑块号排序
     fn parse_node_from_bytes(bytes: &[u8]) -> Option<ExtentNode> {
+        let mut node = Self::parse_node_unsorted(bytes)?;
+        match &mut node {
+            ExtentNode::Leaf { entries, .. } => entries.sort_unstable_by_key(|e| e.ee_block),
+            ExtentNode::Index { entries, .. } => entries.sort_unstable_by_key(|e| e.ei_block),
+        }
+        Some(node)
+    }
+
+    /// 按磁盘上的原始顺序解析一个 extent 节点
+    fn parse_node_unsorted(bytes: &[u8]) -> Option<ExtentNode> {
         let hdr_size = Ext4ExtentHeader::disk_size();
         if bytes.len() < hdr_size {
             error!(
@@ -133,7 +385,6 @@ impl<'a> ExtentTree<'a> {
                 vec.push(et);
                 offset += et_size;
             }
-            vec.sort_unstable_by_key(|entries| entries.ee_block);
             Some(ExtentNode::Leaf {
                 header,
                 entries: vec,
@@ -155,7 +406,6 @@ impl<'a> ExtentTree<'a> {
                 vec.push(idx);
                 offset += idx_size;
             }
-            vec.sort_unstable_by_key(|entries| entries.ei_block);
             Some(ExtentNode::Index {
                 header,
                 entries: vec,
@@ -165,18 +415,30 @@ impl<'a> ExtentTree<'a> {
 
     /// 从 inode.i_block 解析根节点
     pub fn load_root_from_inode(&self) -> Option<ExtentNode> {
+        Self::parse_node_from_bytes(&self.root_bytes())
+    }
+
+    /// 从 inode.i_block 解析根节点并校验条目顺序；没有合法 extent 头时返回 None
+    pub fn load_root_checked(&self) -> BlockDevResult<Option<ExtentNode>> {
+        match Self::parse_node_unsorted(&self.root_bytes()) {
+            Some(node) => {
+                node.verify_order()?;
+                Ok(Some(node))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// inode.i_block 的 60 字节原始内容
+    fn root_bytes(&self) -> [u8; 60] {
         // inode.i_block 是 15 * u32 = 60 字节，正好容纳一个 extent 节点
         let iblocks = &self.inode.i_block; //不同端序解析为错误端序
         let mut bytes: [u8; 60] = [0; 60];
         for idx in 0..15 {
             //正确处理字节序
-            let trans_b1 = iblocks[idx].to_le_bytes();
-            bytes[idx * 4] = trans_b1[0];
-            bytes[idx * 4 + 1] = trans_b1[1];
-            bytes[idx * 4 + 2] = trans_b1[2];
-            bytes[idx * 4 + 3] = trans_b1[3];
+            write_u32_le(iblocks[idx], &mut bytes[idx * 4..idx * 4 + 4]);
         }
-        Self::parse_node_from_bytes(&bytes)
+        bytes
     }
 
     /// 将根节点写回 inode.i_block
@@ -204,8 +466,7 @@ impl<'a> ExtentTree<'a> {
                 // 将 60 字节解释为 15 个 u32 写回 i_block
                 for i in 0..15 {
                     let off = i * 4;
-                    let v =
-                        u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]]);
+                    let v = read_u32_le(&buf[off..off + 4]);
                     self.inode.i_block[i] = v;
                 }
             }
@@ -226,8 +487,7 @@ impl<'a> ExtentTree<'a> {
 
                 for i in 0..15 {
                     let off = i * 4;
-                    let v =
-                        u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]]);
+                    let v = read_u32_le(&buf[off..off + 4]);
                     self.inode.i_block[i] = v;
                 }
             }
@@ -240,7 +500,7 @@ impl<'a> ExtentTree<'a> {
         dev: &mut Jbd2Dev<B>,
         lblock: u32,
     ) -> BlockDevResult<Option<Ext4Extent>> {
-        let root = match self.load_root_from_inode() {
+        let root = match self.load_root_checked()? {
             Some(node) => node,
             None => return Ok(None),
         };
@@ -258,7 +518,7 @@ impl<'a> ExtentTree<'a> {
             ExtentNode::Leaf { entries, .. } => {
                 for et in entries {
                     let start = et.ee_block; // 逻辑起始块
-                    let len = et.ee_len as u32; // 覆盖长度
+                    let len = extent_block_count(et); // 覆盖长度（去掉未初始化标志）
                     let end = start.saturating_add(len); // 半开区间 [start, end)
                     if lblock >= start && lblock < end {
                         return Ok(Some(*et));
@@ -288,18 +548,95 @@ impl<'a> ExtentTree<'a> {
                 );
 
                 // 读取子节点所在的物理块，并从块开头解析 extent 节点
-                dev.read_block(child_block as u32)?;
-                let buf = dev.buffer();
-                let child = match Self::parse_node_from_bytes(buf) {
-                    Some(n) => n,
-                    None => return Ok(None),
-                };
+                dev.read_block(child_block)?;
+                Self::check_block_csum(self.csum_seed, self.csum_policy, dev.buffer())?;
+                let child = Self::parse_node_checked(dev.buffer())?;
 
                 self.find_in_node(dev, &child, lblock)
             }
         }
     }
 
+    /// 把覆盖 [lbn, lbn+count) 的未初始化 extent 中这一段转为已初始化
+    /// 范围必须落在同一个 extent 内；前后未写到的部分拆成独立的未初始化 extent。
+    /// 第一段原地替换原条目（起始逻辑块不变，索引键无需更新），其余段走 insert_extent，
+    /// 叶子放不下时由插入逻辑负责分裂。lbn 不在未初始化 extent 中时什么都不做
+    pub fn mark_initialized<B: BlockDevice>(
+        &mut self,
+        fs: &mut Ext4FileSystem,
+        block_dev: &mut Jbd2Dev<B>,
+        lbn: u32,
+        count: u32,
+    ) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        let Some(root) = self.load_root_checked()? else {
+            return Ok(());
+        };
+        let rest = self.split_uninit_in_node(block_dev, root, None, lbn, count)?;
+        for ext in rest {
+            self.insert_extent(fs, ext, block_dev)?;
+        }
+        Ok(())
+    }
+
+    /// mark_initialized 的递归部分：找到包含 lbn 的叶子条目，原地写回第一段，返回剩余各段
+    fn split_uninit_in_node<B: BlockDevice>(
+        &mut self,
+        dev: &mut Jbd2Dev<B>,
+        mut node: ExtentNode,
+        block: Option<u64>,
+        lbn: u32,
+        count: u32,
+    ) -> BlockDevResult<Vec<Ext4Extent>> {
+        let eh_max = node.header().eh_max;
+        let rest = match &mut node {
+            ExtentNode::Leaf { entries, .. } => {
+                let Some(i) = entries.iter().position(|e| {
+                    !e.is_initialized()
+                        && e.ee_block <= lbn
+                        && lbn < e.ee_block.saturating_add(extent_block_count(e))
+                }) else {
+                    return Ok(Vec::new());
+                };
+                let e = entries[i];
+                let e_start = e.ee_block;
+                let e_end = e_start + extent_block_count(&e);
+                let mid_end = lbn.saturating_add(count).min(e_end);
+                let phys_at = |l: u32| e.start_block() + (l - e_start) as u64;
+
+                let mut pieces = Vec::new();
+                if e_start < lbn {
+                    pieces.push(Ext4Extent::new_uninit(e_start, phys_at(e_start), (lbn - e_start) as u16));
+                }
+                pieces.push(Ext4Extent::new(lbn, phys_at(lbn), (mid_end - lbn) as u16));
+                if mid_end < e_end {
+                    pieces.push(Ext4Extent::new_uninit(mid_end, phys_at(mid_end), (e_end - mid_end) as u16));
+                }
+                entries[i] = pieces.remove(0);
+                pieces
+            }
+            ExtentNode::Index { entries, .. } => {
+                // 与 find_in_node 相同：最后一个 ei_block <= lbn 的条目
+                let Some(idx) = entries.iter().rev().find(|idx| idx.ei_block <= lbn).or(entries.first())
+                else {
+                    return Ok(Vec::new());
+                };
+                let child_block = (idx.ei_leaf_hi as u64) << 32 | idx.ei_leaf_lo as u64;
+                dev.read_block(child_block)?;
+                Self::check_block_csum(self.csum_seed, self.csum_policy, dev.buffer())?;
+                let child = Self::parse_node_checked(dev.buffer())?;
+                return self.split_uninit_in_node(dev, child, Some(child_block), lbn, count);
+            }
+        };
+        match block {
+            Some(block_id) => Self::write_node_to_block(dev, block_id, &node, eh_max, self.csum_seed)?,
+            None => self.store_root_to_inode(&node),
+        }
+        Ok(rest)
+    }
+
     pub fn remove_extend<B: BlockDevice>(
         &mut self,
         fs: &mut Ext4FileSystem,
@@ -398,6 +735,8 @@ impl<'a> ExtentTree<'a> {
 
             fn pre_step<B: BlockDevice>(
                 dev: &mut Jbd2Dev<B>,
+                seed: Option<u32>,
+                policy: ChecksumPolicy,
                 node: &ExtentNode,
                 cur_lbn: u32,
             ) -> BlockDevResult<PreRes> {
@@ -421,11 +760,12 @@ impl<'a> ExtentTree<'a> {
                         while idx_pos < entries.len() {
                             let child_phy = ((entries[idx_pos].ei_leaf_hi as u64) << 32)
                                 | (entries[idx_pos].ei_leaf_lo as u64);
-                            dev.read_block(child_phy as u32)?;
+                            dev.read_block(child_phy)?;
+                            ExtentTree::check_block_csum(seed, policy, dev.buffer())?;
                             let child = ExtentTree::parse_node_from_bytes(dev.buffer())
                                 .ok_or(BlockDevError::Corrupted)?;
 
-                            let r = pre_step(dev, &child, search_lbn)?;
+                            let r = pre_step(dev, seed, policy, &child, search_lbn)?;
                             match r.kind {
                                 PreKind::Have | PreKind::HoleSkip => return Ok(r),
                                 PreKind::NoMore => {
@@ -456,7 +796,7 @@ impl<'a> ExtentTree<'a> {
             let mut need = del_len;
             let mut cur = del_start;
             while need > 0 {
-                let r = pre_step(block_dev, &pre_root, cur)?;
+                let r = pre_step(block_dev, self.csum_seed, self.csum_policy, &pre_root, cur)?;
                 match r.kind {
                     PreKind::Have => {
                         let take = core::cmp::min(need, r.can_take);
@@ -535,7 +875,7 @@ impl<'a> ExtentTree<'a> {
             entries: &mut Vec<Ext4Extent>,
             cur_lbn: u32,
             remaining: u32,
-            phy_block: Option<u32>,
+            phy_block: Option<u64>,
         ) -> BlockDevResult<StepRes> {
             if entries.is_empty() {
                 return Ok(StepRes {
@@ -666,7 +1006,7 @@ impl<'a> ExtentTree<'a> {
                     header: *header,
                     entries: entries.clone(),
                 };
-                ExtentTree::write_node_to_block(dev, block_id, &disk_node, header.eh_max)?;
+                ExtentTree::write_node_to_block(dev, block_id, &disk_node, header.eh_max, tree.csum_seed)?;
             }
 
             Ok(StepRes {
@@ -685,7 +1025,7 @@ impl<'a> ExtentTree<'a> {
             node: &mut ExtentNode,
             cur_lbn: u32,
             remaining: u32,
-            phy_block: Option<u32>,
+            phy_block: Option<u64>,
         ) -> BlockDevResult<StepRes> {
             match node {
                 ExtentNode::Leaf { header, entries } =>
@@ -710,7 +1050,8 @@ impl<'a> ExtentTree<'a> {
                     while idx_pos < entries.len() {
                         let child_phy = ((entries[idx_pos].ei_leaf_hi as u64) << 32)
                             | (entries[idx_pos].ei_leaf_lo as u64);
-                        dev.read_block(child_phy as u32)?;
+                        dev.read_block(child_phy)?;
+                        ExtentTree::check_block_csum(tree.csum_seed, tree.csum_policy, dev.buffer())?;
                         let child_bytes = dev.buffer();
                         let mut child_node =
                             ExtentTree::parse_node_from_bytes(child_bytes).ok_or(BlockDevError::Corrupted)?;
@@ -722,7 +1063,7 @@ impl<'a> ExtentTree<'a> {
                             &mut child_node,
                             search_lbn,
                             remaining,
-                            Some(child_phy as u32),
+                            Some(child_phy),
                         )?;
 
                         match child_res.kind {
@@ -744,7 +1085,7 @@ impl<'a> ExtentTree<'a> {
                                         header: *header,
                                         entries: entries.clone(),
                                     };
-                                    ExtentTree::write_node_to_block(dev, block_id, &disk_node, header.eh_max)?;
+                                    ExtentTree::write_node_to_block(dev, block_id, &disk_node, header.eh_max, tree.csum_seed)?;
                                 }
 
                                 return Ok(StepRes {
@@ -843,7 +1184,8 @@ impl<'a> ExtentTree<'a> {
 
                 if entries.len() == 1 {
                     let child_phy = ((entries[0].ei_leaf_hi as u64) << 32) | (entries[0].ei_leaf_lo as u64);
-                    block_dev.read_block(child_phy as u32)?;
+                    block_dev.read_block(child_phy)?;
+                    Self::check_block_csum(self.csum_seed, self.csum_policy, block_dev.buffer())?;
                     let child_bytes = block_dev.buffer();
                     let mut child_node =
                         ExtentTree::parse_node_from_bytes(child_bytes).ok_or(BlockDevError::Corrupted)?;
@@ -958,7 +1300,7 @@ impl<'a> ExtentTree<'a> {
 
                 // 将当前的 root (左半部分) 写入新分配的物理块
                 // 注意：写入磁盘时要更新 eh_max，因为从 inode (max~4) 移到了 block (max~340)
-                Self::write_node_to_block(block_dev, new_left_block as u32, &root, block_eh_max)?;
+                Self::write_node_to_block(block_dev, new_left_block, &root, block_eh_max, self.csum_seed)?;
 
                 // 在 Inode 中构建新的 Root Index
                 let inline_bytes = self.inode.i_block.len() * 4;
@@ -1011,7 +1353,7 @@ impl<'a> ExtentTree<'a> {
         block_dev: &mut Jbd2Dev<B>,
         node: &mut ExtentNode,
         new_ext: Ext4Extent,
-        phy_block: Option<u32>,
+        phy_block: Option<u64>,
     ) -> BlockDevResult<Option<SplitInfo>> {
         match node {
             ExtentNode::Leaf { header, entries } => {
@@ -1029,7 +1371,7 @@ impl<'a> ExtentTree<'a> {
                     .binary_search_by_key(&new_ext.ee_block, |e| e.ee_block)
                     .unwrap_or_else(|i| i);
 
-                const MAX_LEN: u32 = 32768;
+                const MAX_LEN: u32 = Ext4Extent::EXT_MAX_MERGE_LEN as u32;
 
                 if pos > 0 {
                     let prev = &mut entries[pos - 1];
@@ -1039,7 +1381,9 @@ impl<'a> ExtentTree<'a> {
                     let new_logical = new_ext.ee_block;
                     let new_len = new_ext.ee_len as u32 & 0x7FFF;
 
-                    if prev_len != 0 && new_len != 0 {
+                    // 已初始化和未初始化的 extent 不能合并
+                    let same_kind = (prev.ee_len ^ new_ext.ee_len) & 0x8000 == 0;
+                    if prev_len != 0 && new_len != 0 && same_kind {
                         let prev_end = prev_logical.saturating_add(prev_len);
 
                         if new_logical == prev_end {
@@ -1070,6 +1414,7 @@ impl<'a> ExtentTree<'a> {
                                                 block_id,
                                                 &disk_node,
                                                 header.eh_max,
+                                                self.csum_seed,
                                             )?;
                                         }
                                         return Ok(None);
@@ -1112,6 +1457,7 @@ impl<'a> ExtentTree<'a> {
                                                     block_id,
                                                     &disk_node,
                                                     header.eh_max,
+                                                    self.csum_seed,
                                                 )?;
                                             }
                                             return Ok(None);
@@ -1144,7 +1490,7 @@ impl<'a> ExtentTree<'a> {
                             header: *header,
                             entries: entries.clone(),
                         };
-                        Self::write_node_to_block(block_dev, block_id, &disk_node, header.eh_max)?;
+                        Self::write_node_to_block(block_dev, block_id, &disk_node, header.eh_max, self.csum_seed)?;
                     }
                     // Root 节点由调用方负责写回 Inode，这里返回 None
                     return Ok(None);
@@ -1186,9 +1532,10 @@ impl<'a> ExtentTree<'a> {
                 // 写右节点（新块）
                 Self::write_node_to_block(
                     block_dev,
-                    new_phy_block as u32,
+                    new_phy_block,
                     &right_node,
                     right_header.eh_max,
+                    self.csum_seed,
                 )?;
                 // 写左节点（当前节点）
                 // 如果当前节点是普通块，写回磁盘；如果是 Root，调用方会处理，但这里我们要在内存中保持正确状态
@@ -1197,7 +1544,7 @@ impl<'a> ExtentTree<'a> {
                         header: *header,
                         entries: entries.clone(),
                     };
-                    Self::write_node_to_block(block_dev, block_id, &disk_node, header.eh_max)?;
+                    Self::write_node_to_block(block_dev, block_id, &disk_node, header.eh_max, self.csum_seed)?;
                 }
 
                 //返回分裂信息
@@ -1239,7 +1586,8 @@ impl<'a> ExtentTree<'a> {
                 let child_phy_block = ((entries[idx_pos].ei_leaf_hi as u64) << 32)
                     | (entries[idx_pos].ei_leaf_lo as u64);
                 // 读取子节点
-                block_dev.read_block(child_phy_block as u32)?;
+                block_dev.read_block(child_phy_block)?;
+                Self::check_block_csum(self.csum_seed, self.csum_policy, block_dev.buffer())?;
                 let child_bytes = block_dev.buffer();
                 let mut child_node =
                     Self::parse_node_from_bytes(child_bytes).expect("Can't parse node from bytes!");
@@ -1250,7 +1598,7 @@ impl<'a> ExtentTree<'a> {
                     block_dev,
                     &mut child_node,
                     new_ext,
-                    Some(child_phy_block as u32),
+                    Some(child_phy_block),
                 )?;
 
                 //  处理子节点返回的结果
@@ -1284,6 +1632,7 @@ impl<'a> ExtentTree<'a> {
                                 block_id,
                                 &disk_node,
                                 header.eh_max,
+                                self.csum_seed,
                             )?;
                         }
                         return Ok(None);
@@ -1325,16 +1674,17 @@ impl<'a> ExtentTree<'a> {
                     // 写回
                     Self::write_node_to_block(
                         block_dev,
-                        new_phy_block as u32,
+                        new_phy_block,
                         &right_node,
                         right_header.eh_max,
+                        self.csum_seed,
                     )?;
                     if let Some(block_id) = phy_block {
                         let disk_node = ExtentNode::Index {
                             header: *header,
                             entries: entries.clone(),
                         };
-                        Self::write_node_to_block(block_dev, block_id, &disk_node, header.eh_max)?;
+                        Self::write_node_to_block(block_dev, block_id, &disk_node, header.eh_max, self.csum_seed)?;
                     }
 
                     // 返回分裂信息
@@ -1357,11 +1707,13 @@ impl<'a> ExtentTree<'a> {
     }
 
     /// 通用的写节点到物理块函数
+    /// csum_seed 不为 None 时同时写入块尾校验和
     fn write_node_to_block<B: BlockDevice>(
         dev: &mut Jbd2Dev<B>,
-        block_id: u32,
+        block_id: u64,
         node: &ExtentNode,
         eh_max: u16,
+        csum_seed: Option<u32>,
     ) -> BlockDevResult<()> {
         let hdr_size = Ext4ExtentHeader::disk_size();
         // 读取块
@@ -1402,6 +1754,9 @@ impl<'a> ExtentTree<'a> {
                 }
             }
         }
+        if let Some(seed) = csum_seed {
+            Self::refresh_block_checksum(seed, buf);
+        }
         // 标记脏并写回
         dev.write_block(block_id, true)?;
         Ok(())
@@ -1476,7 +1831,7 @@ mod tests {
             let end = start + required;
             if end > self.data.len() {
                 return Err(BlockDevError::BlockOutOfRange {
-                    block_id,
+                    block_id: block_id as u64,
                     max_blocks: self.total_blocks,
                 });
             }
@@ -1497,7 +1852,7 @@ mod tests {
             let end = start + required;
             if end > self.data.len() {
                 return Err(BlockDevError::BlockOutOfRange {
-                    block_id,
+                    block_id: block_id as u64,
                     max_blocks: self.total_blocks,
                 });
             }
@@ -1524,7 +1879,7 @@ mod tests {
 
     fn setup_fs(total_blocks: u64) -> (Jbd2Dev<MemBlockDev>, Ext4FileSystem) {
         let dev = MemBlockDev::new(total_blocks);
-        let mut jbd = Jbd2Dev::initial_jbd2dev(0, dev, false);
+        let mut jbd = Jbd2Dev::initial_jbd2dev(0, dev, false, false);
         mkfs(&mut jbd).unwrap();
         let fs = mount(&mut jbd).unwrap();
         (jbd, fs)
@@ -1612,7 +1967,7 @@ mod tests {
                 ExtentNode::Index { entries, .. } => {
                     for idx in entries {
                         let child_phy = ((idx.ei_leaf_hi as u64) << 32) | (idx.ei_leaf_lo as u64);
-                        dev.read_block(child_phy as u32).unwrap();
+                        dev.read_block(child_phy).unwrap();
                         let child =
                             ExtentTree::parse_node_from_bytes(dev.buffer()).expect("parse child");
                         walk(dev, &child, out);
@@ -1888,4 +2243,246 @@ mod tests {
             assert_eq!(a.ee_start_lo, b.ee_start_lo);
         }
     }
+
+    #[test]
+    fn overlapping_extents_are_reported_as_corrupted() {
+        let (mut dev, mut fs) = setup_fs(64 * 1024);
+        let mut inode = new_extent_inode();
+        let base = alloc_contiguous(&mut fs, &mut dev, 8);
+        {
+            let mut tree = ExtentTree::new(&mut inode);
+            tree.insert_extent(&mut fs, Ext4Extent::new(0, base, 4), &mut dev)
+                .unwrap();
+            tree.insert_extent(&mut fs, Ext4Extent::new(8, base + 4, 4), &mut dev)
+                .unwrap();
+            assert!(tree.find_extent(&mut dev, 9).unwrap().is_some());
+        }
+
+        // 把第二个 extent 的起点改到 2，与 [0, 4) 重叠
+        let mut root = ExtentTree::new(&mut inode).load_root_from_inode().unwrap();
+        if let ExtentNode::Leaf { entries, .. } = &mut root {
+            entries[1].ee_block = 2;
+        }
+        ExtentTree::new(&mut inode).store_root_to_inode(&root);
+
+        let mut tree = ExtentTree::new(&mut inode);
+        assert!(matches!(root.verify_order(), Err(BlockDevError::Corrupted)));
+        assert!(matches!(
+            tree.find_extent(&mut dev, 1),
+            Err(BlockDevError::Corrupted)
+        ));
+        assert!(matches!(
+            crate::ext4_backend::loopfile::collect_inode_extents(&mut dev, &fs, 12, &mut inode),
+            Err(BlockDevError::Corrupted)
+        ));
+    }
+
+    #[test]
+    fn loopfile_walkers_verify_extent_block_checksum_per_policy() {
+        use crate::ext4_backend::loopfile::{collect_inode_extents, resolve_inode_block};
+
+        let (mut dev, mut fs) = setup_fs(64 * 1024);
+        fs.superblock.s_feature_ro_compat |=
+            crate::ext4_backend::superblock::Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        let ino = 12;
+        let mut inode = new_extent_inode();
+        let base = fs.alloc_blocks(&mut dev, 24).unwrap()[0];
+        {
+            let mut tree = ExtentTree::new(&mut inode).with_checksum(&fs, ino);
+            for i in 0..12u32 {
+                tree.insert_extent(&mut fs, Ext4Extent::new(i, base + 2 * i as u64, 1), &mut dev)
+                    .unwrap();
+            }
+        }
+        let root = ExtentTree::new(&mut inode).load_root_checked().unwrap().unwrap();
+        let leaf = match root {
+            ExtentNode::Index { entries, .. } => {
+                ((entries[0].ei_leaf_hi as u64) << 32) | entries[0].ei_leaf_lo as u64
+            }
+            ExtentNode::Leaf { .. } => panic!("expected an index root"),
+        };
+        assert_eq!(collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap().len(), 12);
+
+        // 破坏叶子块尾部的校验和
+        dev.read_block(leaf).unwrap();
+        dev.buffer_mut()[BLOCK_SIZE - 1] ^= 0xff;
+        dev.write_block(leaf, true).unwrap();
+
+        assert!(matches!(
+            collect_inode_extents(&mut dev, &fs, ino, &mut inode),
+            Err(BlockDevError::ChecksumError)
+        ));
+        assert!(matches!(
+            resolve_inode_block(&mut dev, &fs, ino, &mut inode, 0),
+            Err(BlockDevError::ChecksumError)
+        ));
+
+        fs.checksum_policy = ChecksumPolicy::WarnAndContinue;
+        assert_eq!(collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap().len(), 12);
+        assert_eq!(resolve_inode_block(&mut dev, &fs, ino, &mut inode, 0).unwrap(), Some(base));
+    }
+
+    #[test]
+    fn fragmented_file_grows_checksummed_depth_two_tree() {
+        let (mut dev, mut fs) = setup_fs(64 * 1024);
+        fs.superblock.s_feature_ro_compat |=
+            crate::ext4_backend::superblock::Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        let ino = 12;
+        let mut inode = new_extent_inode();
+        inode.i_generation = 0x1234;
+        let seed = ExtentTree::inode_csum_seed(fs.superblock.metadata_csum_seed(), ino, 0x1234);
+
+        // 逻辑连续、物理上隔块分布，相邻 extent 无法合并
+        let extents = 1000u32;
+        let base = fs.alloc_blocks(&mut dev, extents * 2).unwrap()[0];
+        {
+            let mut tree = ExtentTree::new(&mut inode).with_checksum(&fs, ino);
+            for i in 0..extents {
+                tree.insert_extent(&mut fs, Ext4Extent::new(i, base + 2 * i as u64, 1), &mut dev)
+                    .unwrap();
+            }
+        }
+        let root = ExtentTree::new(&mut inode).load_root_checked().unwrap().unwrap();
+        assert!(root.header().eh_depth >= 2);
+
+        // 每个 extent 块都带正确的块尾校验和
+        let mut pending = vec![root];
+        let mut blocks = 0;
+        while let Some(node) = pending.pop() {
+            if let ExtentNode::Index { entries, .. } = node {
+                for idx in entries {
+                    let child = ((idx.ei_leaf_hi as u64) << 32) | idx.ei_leaf_lo as u64;
+                    dev.read_block(child).unwrap();
+                    assert!(ExtentTree::verify_block_checksum(seed, dev.buffer()));
+                    pending.push(ExtentTree::parse_node_checked(dev.buffer()).unwrap());
+                    blocks += 1;
+                }
+            }
+        }
+        assert!(blocks > 5);
+
+        let all = crate::ext4_backend::loopfile::collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap();
+        assert_eq!(all.len(), extents as usize);
+        let mut tree = ExtentTree::new(&mut inode);
+        for i in (0..extents).step_by(37) {
+            let ext = tree.find_extent(&mut dev, i).unwrap().unwrap();
+            assert_eq!(ext.ee_block, i);
+            assert_eq!(ext.start_block(), base + 2 * i as u64);
+        }
+    }
+
+    #[test]
+    fn extent_tail_checksum_detects_flipped_byte() {
+        let (mut dev, mut fs) = setup_fs(64 * 1024);
+        fs.superblock.s_feature_ro_compat |=
+            crate::ext4_backend::superblock::Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        let ino = 13;
+        let mut inode = new_extent_inode();
+        inode.i_generation = 7;
+        let extents = 1000u32;
+        let base = fs.alloc_blocks(&mut dev, extents * 2).unwrap()[0];
+        {
+            let mut tree = ExtentTree::new(&mut inode).with_checksum(&fs, ino);
+            for i in 0..extents {
+                tree.insert_extent(&mut fs, Ext4Extent::new(i, base + 2 * i as u64, 1), &mut dev)
+                    .unwrap();
+            }
+        }
+
+        // 沿最右路径找到最后一个叶子块
+        let mut node = ExtentTree::new(&mut inode).load_root_checked().unwrap().unwrap();
+        assert!(node.header().eh_depth >= 2);
+        let mut leaf_block = 0;
+        while let ExtentNode::Index { entries, .. } = node {
+            let last = entries.last().unwrap();
+            leaf_block = ((last.ei_leaf_hi as u64) << 32) | last.ei_leaf_lo as u64;
+            dev.read_block(leaf_block).unwrap();
+            node = ExtentTree::parse_node_checked(dev.buffer()).unwrap();
+        }
+        let last = extents - 1;
+        let mut tree = ExtentTree::new(&mut inode).with_checksum(&fs, ino);
+        assert_eq!(tree.find_extent(&mut dev, last).unwrap().unwrap().ee_block, last);
+
+        // 改动叶子中未使用的条目槽：结构仍然合法，但块尾校验和不再匹配
+        let used = Ext4ExtentHeader::disk_size()
+            + node.header().eh_entries as usize * Ext4Extent::disk_size();
+        dev.read_block(leaf_block).unwrap();
+        assert!(used + 4 <= ExtentTree::tail_offset(dev.buffer()).unwrap());
+        dev.buffer_mut()[used] ^= 0x40;
+        dev.write_block(leaf_block, false).unwrap();
+
+        assert!(matches!(
+            tree.find_extent(&mut dev, last),
+            Err(BlockDevError::ChecksumError)
+        ));
+        assert!(matches!(
+            tree.insert_extent(&mut fs, Ext4Extent::new(extents, base + 2 * extents as u64, 1), &mut dev),
+            Err(BlockDevError::ChecksumError)
+        ));
+        // 不带校验和的视图仍能读出（供恢复工具使用）
+        let mut plain = ExtentTree::new(&mut inode);
+        assert_eq!(plain.find_extent(&mut dev, last).unwrap().unwrap().ee_block, last);
+    }
+
+    #[test]
+    fn iter_extents_walks_multi_level_tree_and_reports_corruption() {
+        let (mut dev, mut fs) = setup_fs(64 * 1024);
+        let mut inode = new_extent_inode();
+        let extents = 900u32;
+        let base = fs.alloc_blocks(&mut dev, extents * 2).unwrap()[0];
+        {
+            let mut tree = ExtentTree::new(&mut inode);
+            for i in 0..extents {
+                tree.insert_extent(&mut fs, Ext4Extent::new(i, base + 2 * i as u64, 1), &mut dev)
+                    .unwrap();
+            }
+        }
+        let root = ExtentTree::new(&mut inode).load_root_checked().unwrap().unwrap();
+        assert!(root.header().eh_depth >= 2);
+
+        let walked: Vec<(u32, u64, u32, bool)> =
+            iter_extents(&mut dev, &fs, &inode).collect::<Ext4Result<_>>().unwrap();
+        assert_eq!(walked.len(), extents as usize);
+        for (i, &entry) in walked.iter().enumerate() {
+            assert_eq!(entry, (i as u32, base + 2 * i as u64, 1, false));
+        }
+
+        // 破坏一个子节点的 magic：遍历报告 CorruptExtentTree 后停止
+        let ExtentNode::Index { entries, .. } = &root else {
+            panic!("root should be an index node");
+        };
+        let child = entries[1].ei_leaf_lo;
+        dev.read_block(child as u64).unwrap();
+        dev.buffer_mut()[0] = 0;
+        dev.write_block(child as u64, true).unwrap();
+        let results: Vec<_> = iter_extents(&mut dev, &fs, &inode).collect();
+        assert!(matches!(results.last(), Some(Err(Ext4Error::CorruptExtentTree))));
+        assert!(results[..results.len() - 1].iter().all(|r| r.is_ok()));
+
+        // 子节点深度与父节点不符同样视为损坏
+        dev.buffer_mut()[0..2].copy_from_slice(&Ext4ExtentHeader::EXT4_EXT_MAGIC.to_le_bytes());
+        dev.buffer_mut()[6] = 7;
+        dev.write_block(child as u64, true).unwrap();
+        assert!(iter_extents(&mut dev, &fs, &inode).any(|r| matches!(r, Err(Ext4Error::CorruptExtentTree))));
+
+        // 不使用 extent 的 inode 产出空序列
+        assert_eq!(iter_extents(&mut dev, &fs, &Ext4Inode::default()).count(), 0);
+    }
+
+    #[test]
+    fn iter_extents_reports_full_length_and_uninit_flag() {
+        let (mut dev, mut fs) = setup_fs(64 * 1024);
+        let mut inode = new_extent_inode();
+        {
+            let mut tree = ExtentTree::new(&mut inode);
+            // ee_len == 32768 是满长度的已初始化 extent，不能按低 15 位读成 0
+            tree.insert_extent(&mut fs, Ext4Extent::new(0, 1000, Ext4Extent::EXT_INIT_MAX_LEN), &mut dev)
+                .unwrap();
+            tree.insert_extent(&mut fs, Ext4Extent::new_uninit(40000, 50000, 5), &mut dev)
+                .unwrap();
+        }
+        let walked: Vec<_> =
+            iter_extents(&mut dev, &fs, &inode).collect::<Ext4Result<_>>().unwrap();
+        assert_eq!(walked, [(0, 1000, 32768, false), (40000, 50000, 5, true)]);
+    }
 }
@@ -9,75 +9,245 @@ use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::dir::*;
 use crate::ext4_backend::disknode::*;
+use crate::ext4_backend::endian::*;
 use crate::ext4_backend::entries::*;
 use crate::ext4_backend::ext4::*;
 use crate::ext4_backend::extents_tree::*;
 use crate::ext4_backend::loopfile::*;
 use crate::ext4_backend::error::*;
+use crate::ext4_backend::inline_data::*;
 use alloc::string::String;
 
 
+/// 规范化路径的父目录路径
+pub fn parent_of(norm_path: &str) -> String {
+    match norm_path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(pos) => norm_path[..pos].to_string(),
+    }
+}
 
-
+///重命名/移动文件或目录（POSIX 语义）
+/// new_path 已存在时：文件被替换；空目录只能被目录替换；非空目录返回 DirectoryNotEmpty；
+/// 用文件替换目录返回 IsADirectory，用目录替换文件返回 NotADirectory。
+/// old_path 不存在返回 NotFound，目录不能移动到自身子树下。先插入新目录项（替换时原地改写目标目录项）再删除旧目录项，
+/// 涉及的元数据作为一个 JBD2 事务提交，中途中断不会丢失 inode，也不会丢失目标名字
 pub fn rename<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     old_path: &str,
     new_path: &str,
-) -> BlockDevResult<()> {
+) -> Ext4Result<()> {
+    fs.ensure_writable()?;
     let old_norm = split_paren_child_and_tranlatevalid(old_path);
     let new_norm = split_paren_child_and_tranlatevalid(new_path);
 
-    // 新文件是否存在：存在则先删除
-    if let Some((_ino, inod)) = get_inode_with_num(fs, device, &new_norm).ok().flatten() {
-        if inod.is_dir() {
-            delete_dir(fs, device, new_path);
-        } else {
-            delete_file(fs, device, new_path);
-        }
+    let (src_ino, src_inode) = lookup_path(fs, device, &old_norm)?;
+    if src_ino == fs.root_inode || new_norm == "/" {
+        return Err(Ext4Error::InvalidInput);
+    }
+    if src_inode.is_dir() && new_norm.starts_with(&(old_norm.clone() + "/")) {
+        error!("rename refuses to move dir into itself: {old_norm} -> {new_norm}");
+        return Err(Ext4Error::InvalidInput);
     }
-    //删除了还存在？错误!
-    if get_inode_with_num(fs, device, &new_norm).ok().flatten().is_some() {
-        return Err(BlockDevError::WriteError);
+
+    // 先提交之前积累的事务，让本次 rename 独占一个事务
+    device.commit_journal()?;
+
+    // 目标已存在：同一 inode 直接返回，否则按类型替换
+    if let Some((dst_ino, mut dst_inode)) = get_file_inode(fs, device, &new_norm)? {
+        if dst_ino == src_ino {
+            return Ok(());
+        }
+        match (src_inode.is_dir(), dst_inode.is_dir()) {
+            (true, true) => ensure_dir_empty(fs, device, dst_ino, &mut dst_inode)?,
+            (false, false) => {}
+            (true, false) => return Err(not_a_directory(&new_norm)),
+            (false, true) => return Err(Ext4Error::IsADirectory),
+        }
+        replace_entry(device, fs, &old_norm, &new_norm, src_ino, dst_ino, &dst_inode)?;
+    } else {
+        mv(fs, device, &old_norm, &new_norm)?;
     }
 
-    mv(fs, device, &old_norm, &new_norm)?;
+    // 新旧父目录和被移动目录的目录块一并进入事务
+    let mut dir_blocks: Vec<u64> = Vec::new();
+    for path in [parent_of(&old_norm), parent_of(&new_norm), new_norm.clone()] {
+        if let Some((dir_ino, mut inode)) = get_file_inode(fs, device, &path)?
+            && inode.is_dir()
+        {
+            dir_blocks.extend(resolve_inode_block_allextend(fs, device, dir_ino, &mut inode)?.into_values());
+        }
+    }
+    fs.commit_metadata(device, &dir_blocks)?;
 
     // 校验
     if get_inode_with_num(fs, device, &old_norm).ok().flatten().is_some() {
-        return Err(BlockDevError::WriteError);
+        return Err(BlockDevError::WriteError.into());
     }
     if get_inode_with_num(fs, device, &new_norm).ok().flatten().is_none() {
-        return Err(BlockDevError::WriteError);
+        return Err(BlockDevError::WriteError.into());
     }
 
     Ok(())
 }
+/// rename 覆盖已存在的目标：目标目录项原地改指向源 inode，再删除源目录项，
+/// 最后丢弃目标原来的 inode。目标名字始终指向某个 inode，不存在先删后移的窗口
+fn replace_entry<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    old_norm: &str,
+    new_norm: &str,
+    src_ino: u32,
+    dst_ino: u32,
+    dst_inode: &Ext4Inode,
+) -> Ext4Result<()> {
+    let (old_parent, old_name) = (parent_of(old_norm), &old_norm[old_norm.rfind('/').map_or(0, |p| p + 1)..]);
+    let (new_parent, new_name) = (parent_of(new_norm), &new_norm[new_norm.rfind('/').map_or(0, |p| p + 1)..]);
+    let Some((old_pino, _)) = get_inode_with_num(fs, device, &old_parent)? else {
+        return Err(Ext4Error::Corrupt);
+    };
+    let (new_pino, new_parent_inode) = lookup_dir(fs, device, &new_parent)?;
+
+    // 内联目录没有可原地改写的目录块
+    let (Some((src_blk, src_off)), Some((dst_blk, dst_off))) = (
+        find_entry_location(device, fs, &old_parent, old_name)?,
+        find_entry_location(device, fs, &new_parent, new_name)?,
+    ) else {
+        return Err(BlockDevError::Unsupported.into());
+    };
+    let src_ft = fs.datablock_cache.get_or_load(device, src_blk)?.data[src_off + 7];
+
+    let seed = dir_csum_seed(fs, new_pino, &new_parent_inode);
+    fs.datablock_cache.modify(device, dst_blk, |data| {
+        write_u32_le(src_ino, &mut data[dst_off..dst_off + 4]);
+        data[dst_off + 7] = src_ft;
+        if let Some(seed) = seed {
+            dir_csum::refresh(seed, data);
+        }
+    })?;
+    fs.dentry_cache.invalidate(new_pino, new_name.as_bytes());
+
+    if !remove_inodeentry_from_parentdir(fs, device, &old_parent, old_name) {
+        return Err(Ext4Error::Corrupt);
+    }
+    if fs.get_inode_by_num(device, src_ino)?.is_dir() {
+        reparent_moved_dir(fs, device, src_ino, old_pino, new_pino)?;
+    }
+
+    // 丢弃目标原来的 inode：目录（已确认为空）直接回收，并撤销它的 ".." 对父目录的链接
+    if dst_inode.is_dir() {
+        fs.modify_inode(device, new_pino, |td| {
+            td.i_links_count = td.i_links_count.saturating_sub(1);
+        })?;
+        fs.dentry_cache.invalidate_dir(dst_ino);
+        reclaim_inode(device, fs, dst_ino)?;
+        fs.adjust_used_dirs(dst_ino, false);
+        Ok(())
+    } else {
+        Ok(drop_link(device, fs, dst_ino)?)
+    }
+}
+
+/// 按路径截断，路径不存在时返回 NotFound
 pub fn truncate<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     path: &str,
     truncate_size: u64,
-) -> BlockDevResult<()> {
+) -> Ext4Result<()> {
     let norm_path = split_paren_child_and_tranlatevalid(path);
 
     // 首先找到目标文件。
-    let (inode_num, _inode) = match get_inode_with_num(fs, device, &norm_path).ok().flatten() {
-        Some(v) => v,
-        None => return Err(BlockDevError::InvalidInput),
-    };
+    let (inode_num, _inode) = lookup_path(fs, device, &norm_path)?;
+
+    Ok(truncate_with_ino(device, fs, inode_num, truncate_size)?)
+}
+
+/// 把文件截断或扩展到 new_size
+/// 缩小时释放 new_size 之后的所有块（位图与空闲计数经 bitmap_cache 更新），
+/// 并把保留的最后一块中 new_size 之后的字节清零；
+/// 扩大时只修改 inode 大小，新增区间作为空洞读出为 0。
+/// 元数据经 Jbd2Dev 写回，开启日志时随事务提交；
+/// 路径不存在返回 NotFound，目标是目录返回 IsADirectory
+pub fn truncate_file<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    new_size: u64,
+) -> Ext4Result<()> {
+    fs.ensure_writable()?;
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    let (inode_num, inode) = lookup_path(fs, block_dev, &norm_path)?;
+    if inode.is_dir() {
+        return Err(Ext4Error::IsADirectory);
+    }
+    if !inode.is_file() {
+        return Err(Ext4Error::InvalidInput);
+    }
+
+    let old_size = inode.size();
+    if new_size == old_size {
+        return Ok(());
+    }
+    if !inode.have_extend_header_and_use_extend() {
+        // 传统块映射不支持空洞，沿用分配式截断；内联文件也在这里处理
+        truncate_with_ino(block_dev, fs, inode_num, new_size)?;
+        return Ok(fs.touch_mtime_ctime(block_dev, inode_num)?);
+    }
 
-    truncate_with_ino(device, fs, inode_num, truncate_size)
+    if new_size > old_size {
+        fs.modify_inode(block_dev, inode_num, |td| {
+            td.i_size_lo = (new_size & 0xffff_ffff) as u32;
+            td.i_size_high = (new_size >> 32) as u32;
+        })?;
+        return Ok(fs.touch_mtime_ctime(block_dev, inode_num)?);
+    }
+
+    // 由 extent 树释放尾部块，同时更新 size 和 i_blocks
+    truncate_with_ino(block_dev, fs, inode_num, new_size)?;
+
+    let tail = (new_size % BLOCK_SIZE as u64) as usize;
+    if tail != 0 {
+        let mut inode = fs.get_inode_by_num(block_dev, inode_num)?;
+        let last_lbn = (new_size / BLOCK_SIZE as u64) as u32;
+        let blocks = resolve_inode_block_allextend(fs, block_dev, inode_num, &mut inode)?;
+        if let Some(&phys) = blocks.get(&last_lbn) {
+            fs.datablock_cache.modify(block_dev, phys, |data| {
+                data[tail..].fill(0);
+            })?;
+            fs.datablock_cache.write_through(block_dev, phys)?;
+        }
+    }
+    Ok(fs.touch_mtime_ctime(block_dev, inode_num)?)
 }
 
 ///TODO:shrink暂时不要用不成熟   记得更新inodesize extendtree不负责更新inodesize
+/// 截断结束后对释放的块下发 discard（online_discard 开启时）
 pub fn truncate_with_ino<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     inode_num: u32,
     truncate_size: u64,
+) -> BlockDevResult<()> {
+    truncate_blocks(device, fs, inode_num, truncate_size)?;
+    fs.commit_discards(device)
+}
+
+fn truncate_blocks<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    inode_num: u32,
+    truncate_size: u64,
 ) -> BlockDevResult<()> {
     let mut inode = fs.get_inode_by_num(device, inode_num)?;
+    if inode.has_inline_data() {
+        if truncate_size <= INLINE_DATA_MAX as u64 {
+            return truncate_inline(device, fs, inode_num, truncate_size);
+        }
+        convert_to_extents(device, fs, inode_num)?;
+        inode = fs.get_inode_by_num(device, inode_num)?;
+    }
     
     if !inode.is_file() {
         warn!("trubcate abnormal file")
@@ -87,6 +257,8 @@ pub fn truncate_with_ino<B: BlockDevice>(
     }
 
     let old_size = inode.size();
+    // 截断后文件的末尾变了，旧的预留窗口不再紧跟文件数据
+    fs.release_reservation(inode_num);
     if truncate_size == old_size {
         return Ok(());
     }
@@ -111,7 +283,7 @@ pub fn truncate_with_ino<B: BlockDevice>(
             let del_start_lbn = new_blocks as u32;
 
             loop {
-                let blocks_map = resolve_inode_block_allextend(fs, device, &mut inode)?;
+                let blocks_map = resolve_inode_block_allextend(fs, device, inode_num, &mut inode)?;
                 let del_len = if truncate_size == 0 {
                     blocks_map.len() as u32
                 } else {
@@ -134,7 +306,7 @@ pub fn truncate_with_ino<B: BlockDevice>(
 
                 let chunk = core::cmp::min(del_len, 0x7FFF);
                 {
-                    let mut tree = ExtentTree::new(&mut inode);
+                    let mut tree = ExtentTree::new(&mut inode).with_checksum(fs, inode_num);
                     tree.remove_extend(fs, Ext4Extent::new(start_lbn, 0, chunk as u16), device)?;
                 }
             }
@@ -151,10 +323,11 @@ pub fn truncate_with_ino<B: BlockDevice>(
                         *b = 0;
                     }
                 });
+                fs.datablock_cache.write_through(device, phys)?;
                 new_blocks_map.push((lbn, phys));
             }
 
-            let mut tree = ExtentTree::new(&mut inode);
+            let mut tree = ExtentTree::new(&mut inode).with_checksum(fs, inode_num);
             if !new_blocks_map.is_empty() {
                 let mut idx = 0usize;
                 while idx < new_blocks_map.len() {
@@ -183,7 +356,9 @@ pub fn truncate_with_ino<B: BlockDevice>(
         inode.i_size_lo = (truncate_size & 0xffff_ffff) as u32;
         inode.i_size_high = (truncate_size >> 32) as u32;
         // i_blocks reflects number of allocated blocks, not logical length. Recompute after edits.
-        let alloc_blocks = resolve_inode_block_allextend(fs, device, &mut inode)?.len() as u64;
+        // 扩展属性块也计入 i_blocks
+        let alloc_blocks = resolve_inode_block_allextend(fs, device, inode_num, &mut inode)?.len() as u64
+            + (inode.file_acl() != 0) as u64;
         let iblocks_used = alloc_blocks.saturating_mul(BLOCK_SIZE as u64 / 512);
         inode.i_blocks_lo = (iblocks_used & 0xffff_ffff) as u32;
         inode.l_i_blocks_high = ((iblocks_used >> 32) & 0xffff) as u16;
@@ -209,6 +384,7 @@ pub fn truncate_with_ino<B: BlockDevice>(
                     *b = 0;
                 }
             });
+            fs.datablock_cache.write_through(device, phys)?;
             inode.i_block[lbn as usize] = phys as u32;
         }
     }
@@ -226,7 +402,8 @@ pub fn truncate_with_ino<B: BlockDevice>(
 
     inode.i_size_lo = (truncate_size & 0xffff_ffff) as u32;
     inode.i_size_high = (truncate_size >> 32) as u32;
-    let iblocks_used = (new_blocks.saturating_mul(BLOCK_SIZE as u64 / 512)) as u64;
+    let iblocks_used = ((new_blocks + (inode.file_acl() != 0) as u64)
+        .saturating_mul(BLOCK_SIZE as u64 / 512)) as u64;
     inode.i_blocks_lo = (iblocks_used & 0xffff_ffff) as u32;
     inode.l_i_blocks_high = ((iblocks_used >> 32) & 0xffff) as u16;
 
@@ -236,49 +413,46 @@ pub fn truncate_with_ino<B: BlockDevice>(
 
     Ok(())
 }
+///创建指向已存在文件的符号链接（目标不存在时报错）
 pub fn create_symbol_link<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     src_path: &str,
     dst_path: &str,
-) -> BlockDevResult<()> {
-    // 首先判断两个目标文件是否存在，被链接不存在报错，链接文件存在报错。
+) -> Ext4Result<()> {
+    // 首先判断被链接文件是否存在，不存在报错
     let src_norm = split_paren_child_and_tranlatevalid(src_path);
-    let dst_norm = split_paren_child_and_tranlatevalid(dst_path);
+    lookup_path(fs, device, &src_norm)?;
+    symlink(device, fs, src_path, dst_path)
+}
 
-    if get_file_inode(fs, device, &src_norm)?.is_none() {
-        return Err(BlockDevError::InvalidInput);
-    }
+///创建符号链接 link_path -> target（POSIX 语义，target 可以不存在）
+/// target 不超过 60 字节时直接存放在 i_block 中（fast symlink），否则写入数据块；
+/// link_path 已存在返回 InvalidInput，父路径问题同 validate_parent
+pub fn symlink<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    target: &str,
+    link_path: &str,
+) -> Ext4Result<()> {
+    fs.ensure_writable()?;
+    let dst_norm = split_paren_child_and_tranlatevalid(link_path);
+    // 链接文件已存在报错
     if get_file_inode(fs, device, &dst_norm)?.is_some() {
-        return Err(BlockDevError::InvalidInput);
+        return Err(Ext4Error::InvalidInput);
     }
 
-    // 拆 parent / child（父目录必须存在）
-    let (parent, child) = if let Some(pos) = dst_norm.rfind('/') {
-        let p = if pos == 0 {
-            "/".to_string()
-        } else {
-            dst_norm[..pos].to_string()
-        };
-        let c = dst_norm[pos + 1..].to_string();
-        (p, c)
-    } else {
-        ("/".to_string(), dst_norm)
-    };
-
-    let (parent_ino_num, parent_inode) = match get_inode_with_num(fs, device, &parent).ok().flatten()
-    {
-        Some(v) => v,
-        None => return Err(BlockDevError::InvalidInput),
+    // 拆 parent / child（父目录必须存在且是目录）
+    let child = match dst_norm.rfind('/') {
+        Some(pos) => dst_norm[pos + 1..].to_string(),
+        None => dst_norm.clone(),
     };
-    if !parent_inode.is_dir() {
-        return Err(BlockDevError::InvalidInput);
-    }
+    let (parent_ino_num, parent_inode) = validate_parent(device, fs, &dst_norm)?;
 
     // 为新链接分配 inode
-    let new_ino = fs.alloc_inode(device)?;
+    let new_ino = fs.alloc_inode_near(device, parent_ino_num, false)?;
 
-    let target_bytes = src_path.as_bytes();
+    let target_bytes = target.as_bytes();
     let target_len = target_bytes.len();
     let size_lo = (target_len as u64 & 0xffffffff) as u32;
     let size_hi = ((target_len as u64) >> 32) as u32;
@@ -316,7 +490,7 @@ pub fn create_symbol_link<B: BlockDevice>(
 
         while remaining > 0 {
             if !fs.superblock.has_extents() && data_blocks.len() >= 12 {
-                return Err(BlockDevError::Unsupported);
+                return Err(BlockDevError::Unsupported.into());
             }
 
             let blk = fs.alloc_block(device)?;
@@ -339,7 +513,7 @@ pub fn create_symbol_link<B: BlockDevice>(
         new_inode.i_blocks_lo = iblocks_used as u32;
         new_inode.l_i_blocks_high = (iblocks_used as u64 >> 32) as u16;
 
-        build_file_block_mapping(fs, &mut new_inode, &data_blocks, device);
+        build_file_block_mapping(fs, &mut new_inode, new_ino, &data_blocks, device);
     }
 
     fs.modify_inode(device, new_ino, |on_disk| {
@@ -364,9 +538,75 @@ pub fn create_symbol_link<B: BlockDevice>(
 
 
 
+///读取符号链接中保存的目标路径（不跟随链接）
+/// 中间分量是符号链接时照常跟随，只有最后一个分量不跟随；
+/// 目标短于 60 字节时存放在 i_block 中，否则在数据块中；
+/// 路径不存在返回 NotFound，目标不是符号链接返回 InvalidInput
+pub fn read_link<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<String> {
+    let Some((ino, mut inode)) = resolve_path_follow(device, fs, path, false)? else {
+        return Err(path_lookup_error(fs, device, path));
+    };
+    if !inode.is_symlink() {
+        return Err(Ext4Error::InvalidInput);
+    }
+    let target = read_symlink_target(device, fs, ino, &mut inode)?;
+    String::from_utf8(target).map_err(|_| Ext4Error::Corrupt)
+}
+
+///解析路径并跟随其中的符号链接
+/// 中间分量是符号链接时总是跟随；`follow_last` 决定是否跟随最后一个分量。
+/// 跟随次数超过 SYMLINK_MAX_HOPS 返回 TooManyLinks
+pub fn resolve_path_follow<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    follow_last: bool,
+) -> Ext4Result<Option<(u32, Ext4Inode)>> {
+    let mut current = split_paren_child_and_tranlatevalid(path);
+    let mut hops = 0usize;
+    'restart: loop {
+        let components: Vec<String> = current
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
+        let mut prefix = String::new();
+        for (idx, name) in components.iter().enumerate() {
+            prefix.push('/');
+            prefix.push_str(name);
+            let Some((ino, mut inode)) = get_file_inode(fs, device, &prefix)? else {
+                return Ok(None);
+            };
+            let is_last = idx + 1 == components.len();
+            if !inode.is_symlink() || (is_last && !follow_last) {
+                continue;
+            }
+            hops += 1;
+            if hops > SYMLINK_MAX_HOPS {
+                return Err(Ext4Error::TooManyLinks);
+            }
+            let target_bytes = read_symlink_target(device, fs, ino, &mut inode)?;
+            let target = core::str::from_utf8(&target_bytes).map_err(|_| Ext4Error::Corrupt)?;
+            let mut next = resolve_symlink_path(&prefix, target);
+            for rest in &components[idx + 1..] {
+                next.push('/');
+                next.push_str(rest);
+            }
+            current = split_paren_child_and_tranlatevalid(&next);
+            continue 'restart;
+        }
+        return Ok(get_file_inode(fs, device, &current)?);
+    }
+}
+
 fn read_symlink_target<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
+    ino: u32,
     inode: &mut Ext4Inode,
 ) -> BlockDevResult<Vec<u8>> {
 
@@ -389,7 +629,7 @@ fn read_symlink_target<B: BlockDevice>(
     let mut buf = Vec::with_capacity(size);
 
     if inode.have_extend_header_and_use_extend() {
-        let blocks = resolve_inode_block_allextend(fs, device, inode)?;
+        let blocks = resolve_inode_block_allextend(fs, device, ino, inode)?;
         for &phys in blocks.values() {
             let cached = fs.datablock_cache.get_or_load(device, phys)?;
             let data = &cached.data[..block_bytes];
@@ -400,7 +640,7 @@ fn read_symlink_target<B: BlockDevice>(
         }
     } else {
         for lbn in 0..total_blocks {
-            let phys = match resolve_inode_block( device, inode, lbn as u32)? {
+            let phys = match resolve_inode_block(device, fs, ino, inode, lbn as u32)? {
                 Some(b) => b,
                 None => break,
             };
@@ -437,41 +677,86 @@ fn resolve_symlink_path(current_path: &str, target: &str) -> String {
     split_paren_child_and_tranlatevalid(&combined)
 }
 
+/// 从 lbn 开始预读一个窗口（fs.readahead_blocks 个块）到 datablock_cache，返回新载入的块数
+/// 窗口可跨越逻辑上相邻的已初始化 extent，但在第一个空洞或未初始化 extent 处停止，且不超过 file_blocks；
+/// 每段物理连续的块只发一次设备读
+pub fn prefetch_extent_window<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    extents: &[Ext4Extent],
+    lbn: u64,
+    file_blocks: u64,
+) -> BlockDevResult<u32> {
+    if fs.readahead_blocks == 0 {
+        return Ok(0);
+    }
+    let end = file_blocks.min(lbn + fs.readahead_blocks as u64);
+    let mut cur = lbn;
+    let mut loaded = 0;
+    while cur < end {
+        let Some(ext) = extents.iter().find(|e| {
+            let start = e.ee_block as u64;
+            start <= cur && cur < start + extent_block_count(e) as u64
+        }) else {
+            break;
+        };
+        if !ext.is_initialized() {
+            break;
+        }
+        let run_end = (ext.ee_block as u64 + extent_block_count(ext) as u64).min(end);
+        let phys = ext.start_block() + (cur - ext.ee_block as u64);
+        loaded += fs.datablock_cache.prefetch(device, phys, (run_end - cur) as u32)?;
+        cur = run_end;
+    }
+    Ok(loaded)
+}
+
+/// 跟随符号链接读取整个文件，同时返回读取所用的 inode 号与 inode
 fn read_file_follow<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     path: &str,
     depth: usize,
-) -> BlockDevResult<Option<Vec<u8>>> {
+) -> Ext4Result<Option<(u32, Ext4Inode, Vec<u8>)>> {
   
-    if depth > 8 {
-        return Err(BlockDevError::InvalidInput);
+    if depth > SYMLINK_MAX_HOPS {
+        return Err(Ext4Error::TooManyLinks);
     }
 
-    let mut inode = match get_file_inode(fs, device, path) {
-        Ok(Some((_ino_num, ino))) => ino,
+    let (ino_num, mut inode) = match get_file_inode(fs, device, path) {
+        Ok(Some(v)) => v,
         Ok(None) => return Ok(None),
-        Err(e) => return Err(e),
+        Err(e) => return Err(e.into()),
     };
 
     if inode.is_symlink() {
-        let target_bytes = read_symlink_target(device, fs, &mut inode)?;
+        let target_bytes = read_symlink_target(device, fs, ino_num, &mut inode)?;
         let target = match core::str::from_utf8(&target_bytes) {
             Ok(s) => s,
-            Err(_) => return Err(BlockDevError::Corrupted),
+            Err(_) => return Err(Ext4Error::Corrupt),
         };
         let resolved = resolve_symlink_path(path, target);
         return read_file_follow(device, fs, &resolved, depth + 1);
     }
 
+    if inode.is_dir() {
+        error!("Entry:{path} is a dir");
+        return Err(Ext4Error::IsADirectory);
+    }
     if !inode.is_file() {
         error!("Entry:{path} not aa file");
-        return BlockDevResult::Err(BlockDevError::ReadError);
+        return Err(BlockDevError::ReadError.into());
     }
+    fs.touch_atime(device, ino_num)?;
 
     let size = inode.size() as usize;
     if size == 0 {
-        return Ok(Some(Vec::new()));
+        return Ok(Some((ino_num, inode, Vec::new())));
+    }
+    if inode.has_inline_data() {
+        let (_, xattr_area) = fs.get_inode_with_xattr_area(device, ino_num)?;
+        let data = read_inline(&inode, &xattr_area)?;
+        return Ok(Some((ino_num, inode, data)));
     }
 
     let block_bytes = BLOCK_SIZE;
@@ -480,18 +765,25 @@ fn read_file_follow<B: BlockDevice>(
     let mut buf = Vec::with_capacity(size);
 
     if inode.have_extend_header_and_use_extend() {
-        let blocks = resolve_inode_block_allextend(fs, device, &mut inode)?;
-        for &phys in blocks.values() {
-            let cached = fs.datablock_cache.get_or_load(device, phys)?;
-            let data = &cached.data[..block_bytes];
-            buf.extend_from_slice(data);
-            if buf.len() >= size {
-                break;
+        let extents = collect_inode_extents(device, fs, ino_num, &mut inode)?;
+        let blocks = map_extent_range(&extents, 0, total_blocks as u64);
+        for lbn in 0..total_blocks {
+            match blocks.get(&(lbn as u32)) {
+                Some(&phys) => {
+                    // 整文件读取天然是顺序的：未命中时把后续一窗口的块一次读入
+                    if fs.datablock_cache.get(phys).is_none() {
+                        prefetch_extent_window(device, fs, &extents, lbn as u64, total_blocks as u64)?;
+                    }
+                    let cached = fs.datablock_cache.get_or_load(device, phys)?;
+                    buf.extend_from_slice(&cached.data[..block_bytes]);
+                }
+                // 空洞读出为 0
+                None => buf.resize(buf.len() + block_bytes, 0),
             }
         }
     } else {
         for lbn in 0..total_blocks {
-            let phys = match resolve_inode_block( device, &mut inode, lbn as u32)? {
+            let phys = match resolve_inode_block(device, fs, ino_num, &mut inode, lbn as u32)? {
                 Some(b) => b,
                 None => break,
             };
@@ -506,7 +798,7 @@ fn read_file_follow<B: BlockDevice>(
 
    
 
-    Ok(Some(buf))
+    Ok(Some((ino_num, inode, buf)))
 }
 
 //mv
@@ -515,7 +807,7 @@ pub fn mv<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
     old_path: &str,
     new_path: &str,
-) -> BlockDevResult<()> {
+) -> Ext4Result<()> {
     //找到对应entry，找不到就返回。
     //判断new_path的父目录是否已经存在不存在就返回，存在继续判断new_path是否有对应的entry，存在就返回
     //判断被移动的entry类型，如果是目录
@@ -540,7 +832,7 @@ pub fn mv<B: BlockDevice>(
         }
         None => {
             error!("mv invalid old_path(no '/'): old_path={}", old_path);
-            return Err(BlockDevError::InvalidInput);
+            return Err(Ext4Error::InvalidInput);
         }
     };
     let (new_parent, new_name) = match new_norm.rfind('/') {
@@ -555,33 +847,24 @@ pub fn mv<B: BlockDevice>(
         }
         None => {
             error!("mv invalid new_path(no '/'): new_path={}", new_path);
-            return Err(BlockDevError::InvalidInput);
+            return Err(Ext4Error::InvalidInput);
         }
     };
 
     // 找到 old entry（inode + file_type），找不到就返回
-    let (_old_pino, mut old_parent_inode) = match get_inode_with_num(fs, block_dev, &old_parent)
-        .ok()
-        .flatten()
-    {
-        Some(v) => v,
-        None => {
-            error!("mv old parent not found: old_path={} old_parent={}", old_path, old_parent);
-            return Err(BlockDevError::InvalidInput);
-        }
-    };
+    let (old_parent_ino, mut old_parent_inode) = lookup_dir(fs, block_dev, &old_parent)?;
 
     let mut src_ino: Option<u32> = None;
     let mut src_ft: Option<u8> = None;
-    if let Ok(blocks) = resolve_inode_block_allextend(fs, block_dev, &mut old_parent_inode) {
+    if let Ok(blocks) = resolve_inode_block_allextend(fs, block_dev, old_parent_ino, &mut old_parent_inode) {
         for phys in blocks {
             let cached = match fs.datablock_cache.get_or_load(block_dev, phys.1) {
                 Ok(v) => v,
                 Err(_) => continue,
             };
             let data = &cached.data[..BLOCK_SIZE];
-            let iter = DirEntryIterator::new(data);
-            for (entry, _) in iter {
+            let mut iter = DirEntryIterator::new(data);
+            for (entry, _) in iter.by_ref() {
                 if entry.inode == 0 {
                     continue;
                 }
@@ -591,6 +874,9 @@ pub fn mv<B: BlockDevice>(
                     break;
                 }
             }
+            if let Some(e) = iter.error() {
+                return Err(e.into());
+            }
             if src_ino.is_some() {
                 break;
             }
@@ -605,7 +891,7 @@ pub fn mv<B: BlockDevice>(
             total_size.div_ceil(BLOCK_SIZE)
         };
         for lbn in 0..total_blocks {
-            let phys = match resolve_inode_block( block_dev, &mut old_parent_inode, lbn as u32) {
+            let phys = match resolve_inode_block(block_dev, fs, old_parent_ino, &mut old_parent_inode, lbn as u32) {
                 Ok(Some(b)) => b,
                 _ => continue,
             };
@@ -614,8 +900,8 @@ pub fn mv<B: BlockDevice>(
                 Err(_) => continue,
             };
             let data = &cached.data[..BLOCK_SIZE];
-            let iter = DirEntryIterator::new(data);
-            for (entry, _) in iter {
+            let mut iter = DirEntryIterator::new(data);
+            for (entry, _) in iter.by_ref() {
                 if entry.inode == 0 {
                     continue;
                 }
@@ -625,6 +911,9 @@ pub fn mv<B: BlockDevice>(
                     break;
                 }
             }
+            if let Some(e) = iter.error() {
+                return Err(e.into());
+            }
             if src_ino.is_some() {
                 break;
             }
@@ -637,37 +926,24 @@ pub fn mv<B: BlockDevice>(
                 "mv source entry not found in old parent: old_path={} old_parent={} old_name={}",
                 old_path, old_parent, old_name
             );
-            return Err(BlockDevError::InvalidInput);
+            return Err(path_lookup_error(fs, block_dev, &old_norm));
         }
     };
     let src_ft = src_ft.unwrap_or(Ext4DirEntry2::EXT4_FT_UNKNOWN);
 
     // new_parent 必须存在且是目录
-    let (new_pino, new_parent_inode) = match get_inode_with_num(fs, block_dev, &new_parent)
-        .ok()
-        .flatten()
-    {
-        Some(v) => v,
-        None => {
-            error!("mv new parent not found: new_path={} new_parent={}", new_path, new_parent);
-            return Err(BlockDevError::InvalidInput);
-        }
-    };
-    if !new_parent_inode.is_dir() {
-        error!("mv new parent is not dir: new_path={} new_parent={}", new_path, new_parent);
-        return Err(BlockDevError::InvalidInput);
-    }
+    let (new_pino, new_parent_inode) = lookup_dir(fs, block_dev, &new_parent)?;
 
     // new_path 已存在则返回
     if get_inode_with_num(fs, block_dev, &new_norm).ok().flatten().is_some() {
         error!("mv destination already exists: new_path={} new_norm={}", new_path, new_norm);
-        return Err(BlockDevError::InvalidInput);
+        return Err(Ext4Error::InvalidInput);
     }
 
     // old_path 不允许为根目录
     if old_norm == "/" {
         error!("mv refuses to move root: old_path={}", old_path);
-        return Err(BlockDevError::InvalidInput);
+        return Err(Ext4Error::InvalidInput);
     }
 
     // 插入新 entry 到 new_parent
@@ -691,7 +967,7 @@ pub fn mv<B: BlockDevice>(
             new_name,
             src_ino
         );
-        return Err(BlockDevError::WriteError);
+        return Err(BlockDevError::WriteError.into());
     }
 
     // 删除旧 entry
@@ -704,19 +980,11 @@ pub fn mv<B: BlockDevice>(
             new_parent,
             new_name
         );
-        return Err(BlockDevError::WriteError);
+        return Err(BlockDevError::WriteError.into());
     }
 
     // 目录跨父目录移动：更新 link 以及 '..'
-    let mut moved_inode = match fs.get_inode_by_num(block_dev, src_ino) {
-        Ok(v) => v,
-        Err(e) => {
-            error!("mv get_inode_by_num failed ino={} err={:?} ({})", src_ino, e, e);
-            return Err(e);
-        }
-    };
-    if moved_inode.is_dir() {
-        // 父目录不同才需要改
+    if fs.get_inode_by_num(block_dev, src_ino)?.is_dir() {
         let old_pino = match get_inode_with_num(fs, block_dev, &old_parent)
             .ok()
             .flatten()
@@ -724,285 +992,216 @@ pub fn mv<B: BlockDevice>(
             Some((n, _)) => n,
             None => {
                 error!("mv old parent vanished while moving dir: old_parent={}", old_parent);
-                return Err(BlockDevError::InvalidInput);
+                return Err(Ext4Error::InvalidInput);
             }
         };
-        if old_pino != new_pino {
-            let _ = fs.modify_inode(block_dev, old_pino, |td| {
-                td.i_links_count = td.i_links_count.saturating_sub(1);
-            });
-            let _ = fs.modify_inode(block_dev, new_pino, |td| {
-                td.i_links_count = td.i_links_count.saturating_add(1);
-            });
-
-            // 更新被移动目录的 ".." 指向新父目录 inode
-            let first_blk = match resolve_inode_block( block_dev, &mut moved_inode, 0) {
-                Ok(Some(b)) => b,
-                _ => {
-                    error!("mv resolve_inode_block failed for moved dir ino={}", src_ino);
-                    return Err(BlockDevError::Corrupted);
-                }
-            };
-            let _ = fs
-                .datablock_cache
-                .modify(block_dev, first_blk as u64, |data| {
-                    let block_bytes = BLOCK_SIZE;
-                    if block_bytes < 24 {
-                        return;
-                    }
-                    // '.' entry at offset 0
-                    let rec_len0 = u16::from_le_bytes([data[4], data[5]]) as usize;
-                    if rec_len0 == 0 || rec_len0 + 8 > block_bytes {
-                        return;
-                    }
-                    let off1 = rec_len0;
-                    if off1 + 4 > block_bytes {
-                        return;
-                    }
-                    let bytes = new_pino.to_le_bytes();
-                    data[off1] = bytes[0];
-                    data[off1 + 1] = bytes[1];
-                    data[off1 + 2] = bytes[2];
-                    data[off1 + 3] = bytes[3];
-                });
-        }
+        reparent_moved_dir(fs, block_dev, src_ino, old_pino, new_pino)?;
     }
 
     Ok(())
 }
 
-///UnLink
-pub fn unlink<B: BlockDevice>(
+/// 目录从 old_pino 移到 new_pino 下：调整两个父目录的链接数，并把 ".." 改指向新父目录
+fn reparent_moved_dir<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     block_dev: &mut Jbd2Dev<B>,
-    link_path: &str,
-) {
-    //首先逐级扫描entry找到对应linkentry。
-    let norm_path = split_paren_child_and_tranlatevalid(link_path);
-    let (parent_path, child_name) = if let Some(pos) = norm_path.rfind('/') {
-        let parent = if pos == 0 {
-            "/".to_string()
-        } else {
-            norm_path[..pos].to_string()
-        };
-        let child = norm_path[pos + 1..].to_string();
-        (parent, child)
-    } else {
-        ("/".to_string(), norm_path)
-    };
-
-    let (_pino, mut parent_inode) = match get_inode_with_num(fs, block_dev, &parent_path)
-        .ok()
-        .flatten()
-    {
-        Some(v) => v,
-        None => {
-            warn!("Parent directory not found, unlink failed: {parent_path}");
-            return;
-        }
-    };
+    src_ino: u32,
+    old_pino: u32,
+    new_pino: u32,
+) -> BlockDevResult<()> {
+    if old_pino == new_pino {
+        return Ok(());
+    }
+    let mut moved_inode = fs.get_inode_by_num(block_dev, src_ino)?;
+    let _ = fs.modify_inode(block_dev, old_pino, |td| {
+        td.i_links_count = td.i_links_count.saturating_sub(1);
+    });
+    let _ = fs.modify_inode(block_dev, new_pino, |td| {
+        td.i_links_count = td.i_links_count.saturating_add(1);
+    });
 
-    let mut target_ino: Option<u32> = None;
-    let blocks = match resolve_inode_block_allextend(fs, block_dev, &mut parent_inode) {
-        Ok(v) => v,
-        Err(e) => {
-            warn!(
-                "Parse parent dir blocks failed, unlink failed: {e:?} parent={parent_path}"
-            );
-            return;
+    // 更新被移动目录的 ".." 指向新父目录 inode
+    let first_blk = match resolve_inode_block(block_dev, fs, src_ino, &mut moved_inode, 0) {
+        Ok(Some(b)) => b,
+        _ => {
+            error!("mv resolve_inode_block failed for moved dir ino={}", src_ino);
+            return Err(BlockDevError::Corrupted);
         }
     };
-
-    for &phys in blocks.values() {
-        let cached = match fs.datablock_cache.get_or_load(block_dev, phys) {
-            Ok(v) => v,
-            Err(_) => continue,
-        };
-        let data = &cached.data[..BLOCK_SIZE];
-        let iter = DirEntryIterator::new(data);
-        for (entry, _) in iter {
-            if entry.inode == 0 {
-                continue;
+    let seed = dir_csum_seed(fs, src_ino, &moved_inode);
+    let _ = fs
+        .datablock_cache
+        .modify(block_dev, first_blk as u64, |data| {
+            let block_bytes = BLOCK_SIZE;
+            if block_bytes < 24 {
+                return;
             }
-            if entry.name == child_name.as_bytes() {
-                target_ino = Some(entry.inode);
-                break;
+            // '.' entry at offset 0
+            let rec_len0 = read_u16_le(&data[4..6]) as usize;
+            if rec_len0 == 0 || rec_len0 + 8 > block_bytes {
+                return;
             }
-        }
-        if target_ino.is_some() {
-            break;
-        }
-    }
-
-    let target_ino = match target_ino {
-        Some(v) => v,
-        None => {
-            warn!("Link entry not found, unlink failed: {link_path}");
-            return;
-        }
-    };
-
-    let mut target_inode = match fs.get_inode_by_num(block_dev, target_ino) {
-        Ok(v) => v,
-        Err(e) => {
-            warn!("get inode {target_ino} failed, unlink failed: {e:?}");
-            return;
-        }
-    };
-
-    //首先对指向inode 的link -1。
-    let new_links = target_inode.i_links_count.saturating_sub(1);
-    target_inode.i_links_count = new_links;
-    if fs
-        .modify_inode(block_dev, target_ino, |td| {
-            td.i_links_count = new_links;
-        })
-        .is_err()
-    {
-        warn!("modify inode {target_ino} links_count failed in unlink");
-        return;
-    }
-
-    //如果此时link数为0就调用deletefile删除对应文件.   这里不复用deletefile，因为需要额外的定位
-    if new_links == 0 {
-        let mut used_blocks: Vec<u64> =
-            match resolve_inode_block_allextend(fs, block_dev, &mut target_inode) {
-                Ok(v) => v.into_values().collect(),
-                Err(e) => {
-                    warn!("Parse inode blocks failed (unlink free): {e:?}");
-                    return;
-                }
-            };
-        used_blocks.sort();
-        for blk in used_blocks {
-            if let Err(e) = fs.free_block(block_dev, blk) {
-                warn!("free_block failed for blk {blk}: {e:?}");
+            let off1 = rec_len0;
+            if off1 + 4 > block_bytes {
                 return;
             }
-        }
-        if let Err(e) = fs.free_inode(block_dev, target_ino) {
-            warn!("free_inode failed for inode {target_ino}: {e:?}");
-            return;
-        }
-        let _ = fs.modify_inode(block_dev, target_ino, |td| {
-            td.i_dtime = u32::MAX;
+            write_u32_le(new_pino, &mut data[off1..off1 + 4]);
+            if let Some(seed) = seed {
+                dir_csum::refresh(seed, data);
+            }
         });
-    }
-
-    //最后调用removeentryfromparent移除entry
-    let removed = remove_inodeentry_from_parentdir(fs, block_dev, &parent_path, &child_name);
-    if !removed {
-        warn!(
-            "Dir entry '{child_name}' not found under parent {parent_path} in unlink"
-        );
-    }
+    Ok(())
 }
 
-///Link
-pub fn link<B: BlockDevice>(
-    fs: &mut Ext4FileSystem,
+/// 释放链接数已为 0 的 inode：归还全部数据块与 inode，并写入 dtime
+pub fn reclaim_inode<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
-    link_path: &str,
-    linked_path: &str,
-) {
-    let link_norm = split_paren_child_and_tranlatevalid(link_path);
-    let linked_norm = split_paren_child_and_tranlatevalid(linked_path);
-
-    // 1.检查 被链接文件本身是否存在，不存在返回。
-    let (target_ino, target_inode) = match get_file_inode(fs, block_dev, &linked_norm) {
-        Ok(Some(v)) => v,
-        _ => return,
-    };
+    fs: &mut Ext4FileSystem,
+    ino: u32,
+) -> BlockDevResult<()> {
+    fs.release_reservation(ino);
+    let mut inode = fs.get_inode_by_num(block_dev, ino)?;
+    let mut used_blocks: Vec<u64> = resolve_inode_block_allextend(fs, block_dev, ino, &mut inode)?
+        .into_values()
+        .collect();
+    // 深度大于 0 的 extent 树还占用索引块和叶子块
+    used_blocks.extend(collect_extent_tree_blocks(block_dev, fs, ino, &mut inode)?);
+    used_blocks.sort();
+    for blk in used_blocks {
+        fs.free_block(block_dev, blk)?;
+    }
+    fs.modify_inode(block_dev, ino, |td| {
+        td.i_links_count = 0;
+        td.i_dtime = u32::MAX;
+    })?;
+    fs.free_inode(block_dev, ino)?;
+    fs.commit_discards(block_dev)
+}
 
-    // 1.5 不允许链接目录
-    if target_inode.is_dir() {
-        return;
+/// 关闭 ino 的一个句柄；若它已被 unlink 且这是最后一个句柄，则回收
+pub fn release_open_inode<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    ino: u32,
+) -> BlockDevResult<()> {
+    if !fs.inode_closed(ino) {
+        return Ok(());
     }
-
-    // 2.检查链接文件本身是否已经存在同名entry，存在返回
-    if get_file_inode(fs, block_dev, &link_norm)
-        .ok()
-        .flatten()
-        .is_some()
-    {
-        return;
+    // 最后一个句柄关闭，文件不再增长，归还预留窗口
+    fs.release_reservation(ino);
+    if fs.orphan_inodes.remove(&ino) {
+        reclaim_inode(block_dev, fs, ino)?;
     }
+    Ok(())
+}
 
-    // link_path 的父目录必须存在且是目录
-    let (parent_path, child_name) = if let Some(pos) = link_norm.rfind('/') {
+///UnLink
+/// 从父目录删除 path 对应的目录项（空间并入前一个条目），链接数减一；
+/// 链接数降为 0 时回收 inode 与数据块，若仍被打开则推迟到最后一个句柄关闭
+/// 路径不存在时返回 NotFound，目标是目录时返回 IsADirectory
+pub fn unlink<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    link_path: &str,
+) -> Ext4Result<()> {
+    fs.ensure_writable()?;
+    //首先逐级扫描entry找到对应linkentry。
+    let norm_path = split_paren_child_and_tranlatevalid(link_path);
+    let (parent_path, child_name) = if let Some(pos) = norm_path.rfind('/') {
         let parent = if pos == 0 {
             "/".to_string()
         } else {
-            link_norm[..pos].to_string()
+            norm_path[..pos].to_string()
         };
-        let child = link_norm[pos + 1..].to_string();
+        let child = norm_path[pos + 1..].to_string();
         (parent, child)
     } else {
-        ("/".to_string(), link_norm)
-    };
-    let (parent_ino, mut parent_inode) = match get_inode_with_num(fs, block_dev, &parent_path)
-        .ok()
-        .flatten()
-    {
-        Some(v) => v,
-        None => return,
+        ("/".to_string(), norm_path.clone())
     };
-    if !parent_inode.is_dir() {
-        return;
+
+    let (target_ino, target_inode) = lookup_path(fs, block_dev, link_path).inspect_err(|_| {
+        warn!("Link entry not found, unlink failed: {link_path}");
+    })?;
+    if target_inode.is_dir() {
+        error!("unlink target is a dir: {link_path}");
+        return Err(Ext4Error::IsADirectory);
     }
 
-    // 3.复制目标entry（主要复制 file_type），插入到当前父目录（新名字）
-    let (linked_parent_path, linked_child_name) = if let Some(pos) = linked_norm.rfind('/') {
-        let parent = if pos == 0 {
-            "/".to_string()
-        } else {
-            linked_norm[..pos].to_string()
-        };
-        let child = linked_norm[pos + 1..].to_string();
-        (parent, child)
-    } else {
-        ("/".to_string(), linked_norm.clone())
-    };
+    //先移除entry，再对指向inode 的link -1。
+    if !remove_inodeentry_from_parentdir(fs, block_dev, &parent_path, &child_name) {
+        warn!("Dir entry '{child_name}' not found under parent {parent_path} in unlink");
+        return Err(Ext4Error::Corrupt);
+    }
 
-    let mut copied_ft: Option<u8> = None;
-    if let Some((_lpino, mut lp_inode)) = get_inode_with_num(fs, block_dev, &linked_parent_path)
-        .ok()
-        .flatten()
-        && let Ok(blocks) = resolve_inode_block_allextend(fs, block_dev, &mut lp_inode) {
-            for &phys in blocks.values() {
-                let cached = match fs.datablock_cache.get_or_load(block_dev, phys) {
-                    Ok(v) => v,
-                    Err(_) => continue,
-                };
-                let data = &cached.data[..BLOCK_SIZE];
-                let iter = DirEntryIterator::new(data);
-                for (entry, _) in iter {
-                    if entry.inode == 0 {
-                        continue;
-                    }
-                    if entry.name == linked_child_name.as_bytes() {
-                        copied_ft = Some(entry.file_type);
-                        break;
-                    }
-                }
-                if copied_ft.is_some() {
-                    break;
-                }
-            }
-        }
+    Ok(drop_link(block_dev, fs, target_ino)?)
+}
 
-    let file_type = copied_ft.unwrap_or_else(|| {
-        if target_inode.is_file() {
-            Ext4DirEntry2::EXT4_FT_REG_FILE
-        } else if target_inode.is_symlink() {
-            Ext4DirEntry2::EXT4_FT_SYMLINK
+/// 非目录 inode 的链接数减一；降为 0 时回收，仍被打开时由最后一次 close 回收
+fn drop_link<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    ino: u32,
+) -> BlockDevResult<()> {
+    let mut new_links = 0;
+    fs.modify_inode(block_dev, ino, |td| {
+        td.i_links_count = td.i_links_count.saturating_sub(1);
+        new_links = td.i_links_count;
+    })?;
+
+    if new_links == 0 {
+        // 已删除的文件不应再占着预留窗口挡住其它文件，即使它还被打开
+        fs.release_reservation(ino);
+        if fs.inode_is_open(ino) {
+            debug!("inode {ino} still open, defer reclaim");
+            fs.orphan_inodes.insert(ino);
         } else {
-            Ext4DirEntry2::EXT4_FT_UNKNOWN
+            reclaim_inode(block_dev, fs, ino)?;
         }
-    });
+    }
+    Ok(())
+}
 
-    // insert_dir_entry 会根据 child_name 重新计算 name_len/rec_len（满足“更新名字和长度信息”）
-    if insert_dir_entry(
+///硬链接：在 new_path 创建指向 existing_path 所在 inode 的目录项，并使 links_count +1
+/// 不允许链接目录；existing_path 或 new_path 的父路径不存在返回 NotFound，
+/// new_path 已存在时返回 InvalidInput；inode 不属于本文件系统时返回 CrossDevice
+pub fn link<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    existing_path: &str,
+    new_path: &str,
+) -> Ext4Result<()> {
+    fs.ensure_writable()?;
+    let existing_norm = split_paren_child_and_tranlatevalid(existing_path);
+    let new_norm = split_paren_child_and_tranlatevalid(new_path);
+
+    // 1.被链接文件必须存在，且不能是目录
+    let (target_ino, target_inode) = lookup_path(fs, block_dev, &existing_norm)?;
+    if target_inode.is_dir() {
+        error!("hard link to dir is not allowed: {existing_path}");
+        return Err(Ext4Error::PermissionDenied);
+    }
+    // 同一个 Ext4FileSystem 内 inode 号必在本文件系统范围内，否则视为跨设备链接
+    if target_ino == 0 || target_ino > fs.superblock.s_inodes_count {
+        return Err(Ext4Error::CrossDevice);
+    }
+
+    // 2.新路径不能已存在，其父目录必须存在且是目录
+    if get_file_inode(fs, block_dev, &new_norm)?.is_some() {
+        return Err(Ext4Error::InvalidInput);
+    }
+    let parent_path = parent_of(&new_norm);
+    let child_name = new_norm[new_norm.rfind('/').map_or(0, |pos| pos + 1)..].to_string();
+    let (parent_ino, mut parent_inode) = validate_parent(block_dev, fs, &new_norm)?;
+
+    // 3.按目标 inode 类型插入新目录项
+    let file_type = if target_inode.is_file() {
+        Ext4DirEntry2::EXT4_FT_REG_FILE
+    } else if target_inode.is_symlink() {
+        Ext4DirEntry2::EXT4_FT_SYMLINK
+    } else {
+        Ext4DirEntry2::EXT4_FT_UNKNOWN
+    };
+    insert_dir_entry(
         fs,
         block_dev,
         parent_ino,
@@ -1010,21 +1209,16 @@ pub fn link<B: BlockDevice>(
         target_ino,
         &child_name,
         file_type,
-    )
-    .is_err()
-    {
-        return;
-    }
+    )?;
 
     // 4.更新目标inode的link+1，失败则回滚刚插入的目录项
-    if fs
-        .modify_inode(block_dev, target_ino, |td| {
-            td.i_links_count = td.i_links_count.saturating_add(1);
-        })
-        .is_err()
-    {
+    if let Err(e) = fs.modify_inode(block_dev, target_ino, |td| {
+        td.i_links_count = td.i_links_count.saturating_add(1);
+    }) {
         let _ = remove_inodeentry_from_parentdir(fs, block_dev, &parent_path, &child_name);
+        return Err(e.into());
     }
+    Ok(())
 }
 
 pub fn remove_inodeentry_from_parentdir<B: BlockDevice>(
@@ -1045,7 +1239,8 @@ pub fn remove_inodeentry_from_parentdir<B: BlockDevice>(
             return false;
         }
     };
-    let (_parent_ino_num, mut parent_inode) = parent_info;
+    let (parent_ino_num, mut parent_inode) = parent_info;
+    fs.dentry_cache.invalidate(parent_ino_num, child_name.as_bytes());
 
     let total_size = parent_inode.size() as usize;
     let block_bytes = BLOCK_SIZE;
@@ -1057,74 +1252,20 @@ pub fn remove_inodeentry_from_parentdir<B: BlockDevice>(
 
     let mut removed = false;
     let name_bytes = child_name.as_bytes();
+    let seed = dir_csum_seed(fs, parent_ino_num, &parent_inode);
 
     for lbn in 0..total_blocks {
         if removed {
             break;
         }
-        let phys = match resolve_inode_block( block_dev, &mut parent_inode, lbn as u32) {
+        let phys = match resolve_inode_block(block_dev, fs, parent_ino_num, &mut parent_inode, lbn as u32) {
             Ok(Some(b)) => b,
             _ => continue,
         };
         let _ = fs.datablock_cache.modify(block_dev, phys as u64, |data| {
-            if removed {
-                return;
-            }
-            let mut offset: usize = 0;
-            let mut prev_off: Option<usize> = None;
-            let mut prev_rec_len: u16 = 0;
-            while offset + 8 <= block_bytes {
-                let inode = u32::from_le_bytes([
-                    data[offset],
-                    data[offset + 1],
-                    data[offset + 2],
-                    data[offset + 3],
-                ]);
-                let rec_len = u16::from_le_bytes([data[offset + 4], data[offset + 5]]);
-                if rec_len < 8 {
-                    break;
-                }
-                let name_len = data[offset + 6] as usize;
-                let entry_end = offset + rec_len as usize;
-                if entry_end > block_bytes {
-                    break;
-                }
-
-                // Only compare name bytes within the current entry's rec_len.
-                if name_len > 0 && offset + 8 + name_len <= entry_end {
-                    let name = &data[offset + 8..offset + 8 + name_len];
-                    if inode != 0 && name == name_bytes {
-                        if let Some(poff) = prev_off {
-                            // Merge current entry's space into previous entry.
-                            let new_len = prev_rec_len.saturating_add(rec_len);
-                            let bytes = new_len.to_le_bytes();
-                            data[poff + 4] = bytes[0];
-                            data[poff + 5] = bytes[1];
-
-                            // Clear current entry inode so it will be treated as free.
-                            let zero = 0u32.to_le_bytes();
-                            data[offset] = zero[0];
-                            data[offset + 1] = zero[1];
-                            data[offset + 2] = zero[2];
-                            data[offset + 3] = zero[3];
-                        } else {
-                            // No previous entry in this block: mark this entry free.
-                            let zero = 0u32.to_le_bytes();
-                            data[offset] = zero[0];
-                            data[offset + 1] = zero[1];
-                            data[offset + 2] = zero[2];
-                            data[offset + 3] = zero[3];
-                        }
-                        removed = true;
-                        break;
-                    }
-                }
-                if entry_end >= block_bytes {
-                    break;
-                }
-                prev_off = Some(offset);
-                prev_rec_len = rec_len;
-                offset = entry_end;
+            removed = classic_dir::remove_entry(&mut data[..block_bytes], name_bytes).is_some();
+            if let (true, Some(seed)) = (removed, seed) {
+                dir_csum::refresh(seed, data);
             }
         });
     }
@@ -1132,6 +1273,41 @@ pub fn remove_inodeentry_from_parentdir<B: BlockDevice>(
     removed
 }
 
+/// 目录只含 "." 和 ".."（inode 为 0 的空闲槽位忽略）时返回 Ok，否则返回 DirectoryNotEmpty
+pub fn ensure_dir_empty<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    ino: u32,
+    inode: &mut Ext4Inode,
+) -> Ext4Result<()> {
+    if inode.has_inline_data() {
+        let raw = inline_dir::raw_bytes(&inode.i_block);
+        let mut iter = DirEntryIterator::new(inline_dir::entries(&raw));
+        let not_empty = iter.next().is_some();
+        if let Some(e) = iter.error() {
+            return Err(e.into());
+        }
+        if not_empty {
+            return Err(Ext4Error::DirectoryNotEmpty);
+        }
+    }
+    let blocks = resolve_inode_block_allextend(fs, block_dev, ino, inode)?;
+    for &phys in blocks.values() {
+        let cached = fs.datablock_cache.get_or_load(block_dev, phys)?;
+        let mut iter = DirEntryIterator::new(&cached.data[..BLOCK_SIZE]);
+        let not_empty =
+            iter.any(|(entry, _)| entry.inode != 0 && !entry.is_dot() && !entry.is_dotdot());
+        // 损坏的目录块不能当作空目录删除
+        if let Some(e) = iter.error() {
+            return Err(e.into());
+        }
+        if not_empty {
+            return Err(Ext4Error::DirectoryNotEmpty);
+        }
+    }
+    Ok(())
+}
+
 ///删除目录
 pub fn delete_dir<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &mut Jbd2Dev<B>, path: &str) {
     #[derive(Clone)]
@@ -1192,7 +1368,7 @@ pub fn delete_dir<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &mut Jbd2D
             let block_bytes = BLOCK_SIZE;
 
             let dir_blocks =
-                match resolve_inode_block_allextend(fs, block_dev, &mut frame.inode) {
+                match resolve_inode_block_allextend(fs, block_dev, frame.ino_num, &mut frame.inode) {
                     Ok(v) => v,
                     Err(e) => {
                         warn!("Parse dir blocks failed: {:?} path={}", e, frame.path);
@@ -1222,8 +1398,8 @@ pub fn delete_dir<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &mut Jbd2D
                         }
                     };
                     let data = &cached.data[..block_bytes];
-                    let iter = DirEntryIterator::new(data);
-                    for (entry, _) in iter {
+                    let mut iter = DirEntryIterator::new(data);
+                    for (entry, _) in iter.by_ref() {
                         if entry.is_dot() || entry.is_dotdot() {
                             continue;
                         }
@@ -1237,6 +1413,11 @@ pub fn delete_dir<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &mut Jbd2D
                         };
                         child_entries.push((entry.inode, child_name_str.to_string()));
                     }
+                    // 目录块损坏时停止删除，不能把读不到的子项当成不存在
+                    if let Some(e) = iter.error() {
+                        warn!("corrupted dir block {} path={}: {:?}", phys, frame.path, e);
+                        return;
+                    }
                 }
 
                 for (child_ino, child_name) in child_entries {
@@ -1332,6 +1513,8 @@ pub fn delete_dir<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &mut Jbd2D
                 );
                 return;
             }
+            // 目录即将释放，其下的名字映射随之失效
+            fs.dentry_cache.invalidate_dir(frame.ino_num);
 
             if let Some((pino, _)) = get_inode_with_num(fs, block_dev, pp).ok().flatten() {
                 let _ = fs.modify_inode(block_dev, pino, |td| {
@@ -1342,7 +1525,7 @@ pub fn delete_dir<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &mut Jbd2D
 
         // 然后仿照deletefile的逻辑释放entry对应的inode的blocks和inode。
         let used_blocks: Vec<u64> =
-            match resolve_inode_block_allextend(fs, block_dev, &mut cur_inode) {
+            match resolve_inode_block_allextend(fs, block_dev, frame.ino_num, &mut cur_inode) {
                 Ok(v) => v.into_values().collect(),
                 Err(e) => {
                     warn!(
@@ -1371,14 +1554,96 @@ pub fn delete_dir<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &mut Jbd2D
         }
 
         // 最后更新块组的dir计数-1。
-        let (group_idx, _idx_in_group) = fs.inode_allocator.global_to_group(frame.ino_num);
-        if let Some(desc) = fs.get_group_desc_mut(group_idx) {
-            let before = desc.used_dirs_count();
-            let new_count = before.saturating_sub(1);
-            desc.bg_used_dirs_count_lo = (new_count & 0xFFFF) as u16;
-            desc.bg_used_dirs_count_hi = (new_count >> 16) as u16;
+        fs.adjust_used_dirs(frame.ino_num, false);
+    }
+}
+
+///删除空目录
+/// 目录中只能剩下 "." 和 ".."，否则返回 DirectoryNotEmpty；不允许删除根目录。
+/// 路径不存在返回 NotFound，不是目录返回 NotADirectory。
+/// 删除后父目录链接数减一（少了子目录的 ".." 反向链接），并回收数据块与 inode
+pub fn rmdir<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<()> {
+    fs.ensure_writable()?;
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    let (ino, mut inode) = lookup_dir(fs, block_dev, &norm_path)?;
+    if ino == fs.root_inode {
+        error!("Can't remove root directory");
+        return Err(Ext4Error::PermissionDenied);
+    }
+
+    ensure_dir_empty(fs, block_dev, ino, &mut inode)?;
+
+    let (parent_path, child_name) = match norm_path.rfind('/') {
+        Some(0) => ("/".to_string(), norm_path[1..].to_string()),
+        Some(pos) => (norm_path[..pos].to_string(), norm_path[pos + 1..].to_string()),
+        None => ("/".to_string(), norm_path.clone()),
+    };
+    let Some((parent_ino, _)) = get_inode_with_num(fs, block_dev, &parent_path)? else {
+        return Err(Ext4Error::Corrupt);
+    };
+    if !remove_inodeentry_from_parentdir(fs, block_dev, &parent_path, &child_name) {
+        return Err(Ext4Error::Corrupt);
+    }
+    fs.modify_inode(block_dev, parent_ino, |td| {
+        td.i_links_count = td.i_links_count.saturating_sub(1);
+    })?;
+
+    fs.dentry_cache.invalidate_dir(ino);
+    reclaim_inode(block_dev, fs, ino)?;
+    fs.adjust_used_dirs(ino, false);
+    Ok(())
+}
+
+/// 递归删除整棵目录树（rm -rf），path 不是目录时等同于 unlink
+/// 用显式栈代替递归：目录先压回栈中标记为已展开，再压入子目录，
+/// 子项全部删除后才 rmdir 该目录；符号链接只删除链接本身，不跟随
+pub fn remove_all<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<()> {
+    fs.ensure_writable()?;
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    let (ino, inode) = lookup_path(fs, block_dev, &norm_path)?;
+    if !inode.is_dir() {
+        return unlink(block_dev, fs, &norm_path);
+    }
+    if ino == fs.root_inode {
+        error!("Can't remove root directory");
+        return Err(Ext4Error::PermissionDenied);
+    }
+
+    // (目录路径, 子项是否已处理)
+    let mut stack: Vec<(String, bool)> = Vec::new();
+    stack.push((norm_path, false));
+    while let Some((dir, expanded)) = stack.pop() {
+        if expanded {
+            rmdir(block_dev, fs, &dir)?;
+            continue;
+        }
+        // 先收集子项再删除，避免边遍历边修改目录块
+        let mut children: Vec<(String, u32)> = Vec::new();
+        for entry in read_dir(block_dev, fs, &dir)? {
+            let entry = entry?;
+            if entry.name != "." && entry.name != ".." {
+                children.push((entry.name, entry.inode));
+            }
+        }
+        stack.push((dir.clone(), true));
+        for (name, child_ino) in children {
+            let child_path = alloc::format!("{dir}/{name}");
+            if fs.get_inode_by_num(block_dev, child_ino)?.is_dir() {
+                stack.push((child_path, false));
+            } else {
+                unlink(block_dev, fs, &child_path)?;
+            }
         }
     }
+    Ok(())
 }
 
 ///删除文件/删除链接文件
@@ -1409,7 +1674,7 @@ pub fn delete_file<B: BlockDevice>(
 
     //统计block（i_blocks 以 512 字节为单位，换算成数据块个数）
     let mut inode_used_blocks: Vec<u64> =
-        resolve_inode_block_allextend(fs, block_dev, &mut target_inode)
+        resolve_inode_block_allextend(fs, block_dev, ino_num, &mut target_inode)
             .expect("Parse inode extend failed")
             .into_values()
             .collect();
@@ -1475,6 +1740,7 @@ pub fn delete_file<B: BlockDevice>(
 pub fn build_file_block_mapping<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     inode: &mut Ext4Inode,
+    ino: u32,
     data_blocks: &[u64],
     block_dev: &mut Jbd2Dev<B>,
 ) {
@@ -1507,7 +1773,8 @@ pub fn build_file_block_mapping<B: BlockDevice>(
             let prev_lbn = lbn - 1;
             let prev_pblk = data_blocks[prev_lbn as usize];
 
-            let is_contiguous = pblk == prev_pblk.saturating_add(1);
+            let is_contiguous = pblk == prev_pblk.saturating_add(1)
+                && run_len < Ext4Extent::EXT_MAX_MERGE_LEN as u32;
 
             if is_contiguous {
                 run_len = run_len.saturating_add(1);
@@ -1526,7 +1793,7 @@ pub fn build_file_block_mapping<B: BlockDevice>(
         exts_vec.push(ext);
 
         // 构造一个叶子根节点，并通过 ExtentTree 将其写入 inode.i_block
-        let mut tree = ExtentTree::new(inode);
+        let mut tree = ExtentTree::new(inode).with_checksum(fs, ino);
         for extend in exts_vec {
             tree.insert_extent(fs, extend, block_dev).expect("Extend insert Failed!");
         }
@@ -1538,14 +1805,15 @@ pub fn build_file_block_mapping<B: BlockDevice>(
 ///创建文件类型entry通用接口
 /// 传入文件名称,可选初始数据
 /// file_type 可选文件entry类型，None表示默认普通文件,传entry类型,别传inode类型
+/// 父目录不存在或不是目录时返回错误，不会自动创建父目录
 pub fn mkfile<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     path: &str,
     initial_data: Option<&[u8]>,
     file_type: Option<u8>,
-) -> Option<Ext4Inode> {
-    mkfile_with_ino(device, fs, path, initial_data, file_type).map(|(_, inode)| inode)
+) -> Ext4Result<Ext4Inode> {
+    Ok(try_mkfile_with_ino(device, fs, path, initial_data, file_type)?.1)
 }
 
 pub fn mkfile_with_ino<B: BlockDevice>(
@@ -1555,66 +1823,77 @@ pub fn mkfile_with_ino<B: BlockDevice>(
     initial_data: Option<&[u8]>,
     file_type: Option<u8>,
 ) -> Option<(u32, Ext4Inode)> {
+    try_mkfile_with_ino(device, fs, path, initial_data, file_type).ok()
+}
+
+/// 创建文件并返回 (inode 号, inode)，目标已存在时直接返回已有的
+/// 父目录在分配任何 inode/数据块之前校验：父路径上的分量不存在返回 NotFound，不是目录返回 NotADirectory
+pub fn try_mkfile_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    initial_data: Option<&[u8]>,
+    file_type: Option<u8>,
+) -> Ext4Result<(u32, Ext4Inode)> {
     // 规范化路径
     let norm_path = split_paren_child_and_tranlatevalid(path);
 
     // 如果目标已存在，直接返回
-    if let Ok(Some((_ino_num, inode))) = get_file_inode(fs, device, &norm_path) {
-        let ino = match get_inode_with_num(fs, device, &norm_path).ok().flatten() {
-            Some((ino, _)) => ino,
-            None => {
-                error!("mkfile_with_ino existing file but failed to get ino path={}", path);
-                return None;
-            }
-        };
-        return Some((ino, inode));
+    if let Some(found) = get_file_inode(fs, device, &norm_path)? {
+        return Ok(found);
     }
 
-    // 拆 parent / child
-    let mut valid_path = norm_path;
-    let split_point = match valid_path.rfind('/') {
-        Some(v) => v,
-        None => {
-            error!("mkfile invalid path(no '/'): path={}", path);
-            return None;
-        }
-    };
-    let child = valid_path.split_off(split_point)[1..].to_string();
-    let parent = valid_path;
+    // 父目录必须已存在且是目录，校验通过前不做任何分配
+    let parent = validate_parent(device, fs, &norm_path).inspect_err(|e| {
+        error!("mkfile parent check failed path={path} err={e:?}");
+    })?;
+    Ok(create_file_with_ino(device, fs, &norm_path, parent, initial_data, file_type)?)
+}
 
-    // 确保父目录存在
-    if mkdir(device, fs, &parent).is_none() {
-        error!("mkfile mkdir parent failed path={} parent={}", path, parent);
-        return None;
-    }
+/// 在已校验的父目录 parent 下创建文件（路径检查由 try_mkfile_with_ino 完成）
+fn create_file_with_ino<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    parent: (u32, Ext4Inode),
+    initial_data: Option<&[u8]>,
+    file_type: Option<u8>,
+) -> BlockDevResult<(u32, Ext4Inode)> {
+    // 整个创建过程的元数据写归入同一个日志事务，返回时提交
+    let mut handle = device.transaction();
+    let device = &mut *handle;
 
-    // 重新获取父目录 inode 及其 inode 号
-    let (parent_ino_num, parent_inode) =
-        match get_inode_with_num(fs, device, &parent).ok().flatten() {
-            Some((n, ino)) => (n, ino),
-            None => {
-                error!("mkfile get parent inode failed path={} parent={}", path, parent);
-                return None;
-            }
-        };
+    // 拆 parent / child
+    let Some(split_point) = path.rfind('/') else {
+        error!("mkfile invalid path(no '/'): path={path}");
+        return Err(BlockDevError::InvalidInput);
+    };
+    let child = path[split_point + 1..].to_string();
+    let (parent_ino_num, parent_inode) = parent;
 
     //为新文件分配 inode（内部自动选择块组）
-    let new_file_ino = match fs.alloc_inode(device) {
+    let new_file_ino = match fs.alloc_inode_near(device, parent_ino_num, false) {
         Ok(ino) => ino,
         Err(e) => {
             error!("mkfile alloc_inode failed path={} err={:?} ({})", path, e, e);
-            return None;
+            return Err(e);
         }
     };
 
 
 
+    // 普通文件的初始数据放得进 i_block 且开启了 inline_data 时直接内联，不分配数据块
+    let is_regular = matches!(file_type, None | Some(Ext4DirEntry2::EXT4_FT_REG_FILE));
+    let inline = initial_data.filter(|buf| is_regular && fits_inline(fs, buf));
+
     // 如有初始数据，为文件分配一个或多个数据块并写入
     let mut data_blocks: Vec<u64> = Vec::new();
     let mut total_written: usize = 0;
-    if let Some(buf) = initial_data {
+    if let Some(buf) = initial_data.filter(|_| inline.is_none()) {
         let mut remaining = buf.len();
         let mut src_off = 0usize;
+        // 当前连续分配段中尚未使用的部分 (下一块, 剩余块数)
+        let mut run: (u64, u32) = (0, 0);
 
         while remaining > 0 {
             // 如果未启用 extents，则最多只使用 12 个直接块
@@ -1622,17 +1901,35 @@ pub fn mkfile_with_ino<B: BlockDevice>(
                 break;
             }
 
-            let blk = match fs.alloc_block(device) {
-                Ok(b) => b,
-                Err(e) => {
-                    error!("mkfile alloc_block failed path={} err={:?} ({})", path, e, e);
-                    break;
+            if run.1 == 0 {
+                // 剩余数据按一段连续块申请，紧接在上一段之后；
+                // 经预留窗口分配，之后追加写入能接着这段数据连续增长
+                let mut want = remaining.div_ceil(BLOCK_SIZE).min(u16::MAX as usize / 2);
+                if !fs.superblock.has_extents() {
+                    want = want.min(12 - data_blocks.len());
                 }
-            };
+                let want = want as u32;
+                let goal = data_blocks
+                    .last()
+                    .map(|b| b + 1)
+                    .or_else(|| fs.data_goal_for_inode(new_file_ino));
+                let implied = data_blocks.last().and_then(|&b| fs.implied_cluster_run(b, want));
+                let alloc = || fs.alloc_blocks_for(device, new_file_ino, goal, want);
+                run = match implied.map_or_else(alloc, Ok) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        error!("mkfile alloc_blocks_for failed path={} err={:?} ({})", path, e, e);
+                        break;
+                    }
+                };
+            }
+            let blk = run.0;
+            run = (run.0 + 1, run.1 - 1);
 
             let write_len = core::cmp::min(remaining, BLOCK_SIZE);
 
             // 将数据写入新分配的数据块，其余部分填零
+            fs.check_data_blocks(blk, 1)?;
             fs.datablock_cache.modify_new(blk, |data| {
                 for b in data.iter_mut() {
                     *b = 0;
@@ -1640,12 +1937,13 @@ pub fn mkfile_with_ino<B: BlockDevice>(
                 let end = src_off + write_len;
                 data[..write_len].copy_from_slice(&buf[src_off..end]);
             });
-
             data_blocks.push(blk);
             total_written += write_len;
             remaining -= write_len;
             src_off += write_len;
         }
+        fs.datablock_cache.write_through_blocks(device, &data_blocks)?;
+        fs.datablock_cache.shrink_to_capacity(device)?;
     }
 
     // 构造新文件 inode 的内存版本，然后通过 modify_inode 一次性写回
@@ -1668,6 +1966,14 @@ pub fn mkfile_with_ino<B: BlockDevice>(
     
     new_inode.i_mode = imode;
 
+    // 大 inode 保留 extra 区，四个时间戳都取当前时间
+    new_inode.i_extra_isize = fs.new_inode_extra_isize();
+    let now = fs.current_time();
+    new_inode.set_atime_ts(now);
+    new_inode.set_mtime_ts(now);
+    new_inode.set_ctime_ts(now);
+    new_inode.set_crtime_ts(now);
+
     //extend是否开启
     if fs.superblock.has_extents() {
         new_inode.write_extend_header();
@@ -1689,7 +1995,7 @@ pub fn mkfile_with_ino<B: BlockDevice>(
         new_inode.i_blocks_lo = used_blocks_lo;
         new_inode.l_i_blocks_high = (iblocks_used as u64 >> 32) as u16;
 
-        build_file_block_mapping(fs, &mut new_inode, &data_blocks, device);
+        build_file_block_mapping(fs, &mut new_inode, new_file_ino, &data_blocks, device);
     } else {
         //无初始数据：空文件
         new_inode.i_size_lo = 0;
@@ -1704,15 +2010,18 @@ pub fn mkfile_with_ino<B: BlockDevice>(
         }
     }
 
-    if fs
-        .modify_inode(device, new_file_ino, |on_disk| {
-            *on_disk = new_inode;
-        })
-        .is_err()
-    {
+    let mut inline_result = Ok(());
+    if let Err(e) = fs.modify_inode_with_xattr_area(device, new_file_ino, |on_disk, xattr_area| {
+        *on_disk = new_inode;
+        xattr_area.fill(0);
+        if let Some(buf) = inline {
+            inline_result = init_inline_file(on_disk, xattr_area, buf);
+        }
+    }) {
         error!("mkfile modify_inode failed path={} ino={}", path, new_file_ino);
-        return None;
+        return Err(e);
     }
+    inline_result?;
 
     //在父目录中插入一个普通文件类型的目录项（必要时自动扩展目录块）
 
@@ -1722,7 +2031,7 @@ pub fn mkfile_with_ino<B: BlockDevice>(
     };
 
     let mut parent_inode_copy = parent_inode;
-    if insert_dir_entry(
+    if let Err(e) = insert_dir_entry(
         fs,
         device,
         parent_ino_num,
@@ -1730,9 +2039,7 @@ pub fn mkfile_with_ino<B: BlockDevice>(
         new_file_ino,
         &child,
         file_type,
-    )
-    .is_err()
-    {
+    ) {
         error!(
             "mkfile insert_dir_entry failed path={} parent_ino={} child={} ino={}",
             path,
@@ -1740,12 +2047,12 @@ pub fn mkfile_with_ino<B: BlockDevice>(
             child,
             new_file_ino
         );
-        return None;
+        return Err(e);
     }
 
     // 返回新文件 inode
     match fs.get_inode_by_num(device, new_file_ino) {
-        Ok(inode) => Some((new_file_ino, inode)),
+        Ok(inode) => Ok((new_file_ino, inode)),
         Err(e) => {
             error!(
                 "mkfile get_inode_by_num failed path={} ino={} err={:?} ({})",
@@ -1754,39 +2061,91 @@ pub fn mkfile_with_ino<B: BlockDevice>(
                 e,
                 e
             );
-            None
+            Err(e)
         }
     }
 }
 
-///读取指定路径的整个文件内容
+///读取指定路径的整个文件内容，路径不存在时返回带出错分量的 NotFound / NotADirectory
 pub fn read_file<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     path: &str,
-) -> BlockDevResult<Option<Vec<u8>>> {
-    read_file_follow(device, fs, path, 0)
+) -> Ext4Result<Vec<u8>> {
+    read_file_with_inode(device, fs, path).map(|(_, _, data)| data)
+}
+
+///读取整个文件内容并返回同一次加载的 inode（跟随符号链接）
+/// 读取长度与返回的元数据来自同一个 inode，不会再次读取 inode 表；错误语义同 read_file
+pub fn read_file_with_inode<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<(u32, Ext4Inode, Vec<u8>)> {
+    match read_file_follow(device, fs, path, 0)? {
+        Some(found) => Ok(found),
+        None => Err(path_lookup_error(fs, device, path)),
+    }
+}
+
+/// 文件碎片化信息
+#[derive(Debug, Clone, Copy)]
+pub struct FragInfo {
+    /// 实际 extent 数量
+    pub extent_count: u32,
+    /// 理想情况下（完全连续）所需的 extent 数量
+    pub ideal_extent_count: u32,
+    /// extent_count / ideal_extent_count，1.0 表示无碎片
+    pub score: f32,
+}
+
+/// 统计指定路径文件的碎片化程度，路径不存在返回 NotFound
+pub fn fragmentation<B: BlockDevice>(
+    device: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<FragInfo> {
+    let (ino, mut inode) = lookup_path(fs, device, path)?;
+
+    let extents = collect_inode_extents(device, fs, ino, &mut inode)?;
+    let mut allocated: u64 = 0;
+    for ext in &extents {
+        // 最高位表示 uninitialized 标志，长度使用低 15 位
+        allocated += (ext.ee_len & 0x7FFF) as u64;
+    }
+
+    // 单个 extent 最多覆盖 32768 个块
+    let ideal = allocated.div_ceil(32768) as u32;
+    let extent_count = extents.len() as u32;
+    let score = if ideal == 0 {
+        1.0
+    } else {
+        extent_count as f32 / ideal as f32
+    };
+
+    Ok(FragInfo {
+        extent_count,
+        ideal_extent_count: ideal,
+        score,
+    })
 }
 
+/// 在 offset 处写入 data，路径不存在时返回 NotFound
 pub fn write_file<B: BlockDevice>(
     device: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
     path: &str,
     offset: u64,
     data: &[u8],
-) -> BlockDevResult<()> {
+) -> Ext4Result<()> {
     if data.is_empty() {
         return Ok(());
     }
 
     // 获取 inode 及其 inode 号
-    let info = match get_inode_with_num(fs, device, path).ok().flatten() {
-        Some(v) => v,
-        None => return Err(BlockDevError::WriteError),
-    };
-    let (inode_num, _inode) = info;
+    let (inode_num, _inode) = lookup_path(fs, device, path)?;
 
-    write_file_with_ino(device, fs, inode_num, offset, data)
+    Ok(write_file_with_ino(device, fs, inode_num, offset, data)?)
 }
 
 pub fn write_file_with_ino<B: BlockDevice>(
@@ -1799,9 +2158,18 @@ pub fn write_file_with_ino<B: BlockDevice>(
     if data.is_empty() {
         return Ok(());
     }
+    fs.ensure_writable()?;
 
     let mut inode = fs.get_inode_by_num(device, inode_num)?;
 
+    // 内联文件：仍放得进 i_block 时原地写，否则先转换为 extent 文件
+    if inode.has_inline_data() {
+        if offset.saturating_add(data.len() as u64) <= INLINE_DATA_MAX as u64 {
+            return write_inline_at(device, fs, inode_num, offset, data);
+        }
+        convert_to_extents(device, fs, inode_num)?;
+        inode = fs.get_inode_by_num(device, inode_num)?;
+    }
 
     let old_size = inode.size() as u64;
     let block_bytes = BLOCK_SIZE as u64;
@@ -1833,47 +2201,88 @@ pub fn write_file_with_ino<B: BlockDevice>(
         }
     }
 
+    // 写入覆盖的未初始化 extent 先转为已初始化：这些块从未写过，先整块清零再写入，
+    // 没写到的部分仍保持未初始化
+    if inode.have_extend_header_and_use_extend() {
+        let extents = collect_inode_extents(device, fs, inode_num, &mut inode)?;
+        for e in extents.iter().filter(|e| !e.is_initialized()) {
+            let e_start = e.ee_block as u64;
+            let from = e_start.max(start_lbn);
+            let to = (e_start + extent_block_count(e) as u64).min(end_lbn + 1);
+            if from >= to {
+                continue;
+            }
+            for lbn in from..to {
+                fs.datablock_cache.modify_new(e.start_block() + (lbn - e_start), |blk| blk.fill(0));
+            }
+            ExtentTree::new(&mut inode)
+                .with_checksum(fs, inode_num)
+                .mark_initialized(fs, device, from as u32, (to - from) as u32)?;
+        }
+    }
+
     let mut blocks_map = if inode.have_extend_header_and_use_extend() {
-        Some(resolve_inode_block_allextend(fs, device, &mut inode)?)
+        Some(resolve_inode_block_allextend(fs, device, inode_num, &mut inode)?)
     } else {
         None
     };
 
+    let mut written = Vec::new();
     for lbn in start_lbn..=end_lbn {
         let phys = if inode.have_extend_header_and_use_extend() {
             let map = blocks_map.as_mut().ok_or(BlockDevError::Corrupted)?;
             if let Some(&b) = map.get(&(lbn as u32)) {
                 b
             } else {
-                // Hole: allocate a new block and insert an extent for this single LBN.
-                let new_phys = fs.alloc_block(device)?;
-                fs.datablock_cache.modify_new(new_phys, |blk| {
-                    for b in blk.iter_mut() {
-                        *b = 0;
-                    }
-                });
+                // 空洞：把从 lbn 开始的连续空洞一次性分配成尽量长的 extent，
+                // 目标位置取前一个已映射块之后，让文件数据在磁盘上保持连续
+                let mut holes = 1u64;
+                while lbn + holes <= end_lbn
+                    && holes < Ext4Extent::EXT_MAX_MERGE_LEN as u64
+                    && !map.contains_key(&((lbn + holes) as u32))
+                {
+                    holes += 1;
+                }
+                let prev = map.range(..lbn as u32).next_back().map(|(&l, &p)| (l, p));
+                let goal = prev
+                    .map(|(l, p)| p + (lbn - l as u64))
+                    .or_else(|| fs.data_goal_for_inode(inode_num));
+                let implied = prev
+                    .filter(|&(l, _)| l as u64 + 1 == lbn)
+                    .and_then(|(_, p)| fs.implied_cluster_run(p, holes as u32));
+                let (run_start, run_len) = match implied {
+                    Some(run) => run,
+                    None => fs.alloc_blocks_for(device, inode_num, goal, holes as u32)?,
+                };
+                for i in 0..run_len as u64 {
+                    fs.datablock_cache.modify_new(run_start + i, |blk| {
+                        for b in blk.iter_mut() {
+                            *b = 0;
+                        }
+                    });
+                    map.insert((lbn + i) as u32, run_start + i);
+                }
                 {
-                    let mut tree = ExtentTree::new(&mut inode);
-                    let ext = Ext4Extent::new(lbn as u32, new_phys, 1);
+                    let mut tree = ExtentTree::new(&mut inode).with_checksum(fs, inode_num);
+                    let ext = Ext4Extent::new(lbn as u32, run_start, run_len as u16);
                     tree.insert_extent(fs, ext, device)?;
                 }
-                map.insert(lbn as u32, new_phys);
-
-                let add_iblocks = (BLOCK_SIZE / 512) as u32;
-                inode.i_blocks_lo = inode.i_blocks_lo.saturating_add(add_iblocks);
-                inode.l_i_blocks_high =
-                    inode.l_i_blocks_high.saturating_add(((add_iblocks as u64) >> 32) as u16);
 
+                let add_iblocks = run_len as u64 * (BLOCK_SIZE / 512) as u64;
+                let iblocks = inode.blocks_count().saturating_add(add_iblocks);
+                inode.i_blocks_lo = iblocks as u32;
+                inode.l_i_blocks_high = (iblocks >> 32) as u16;
 
-                new_phys
+                run_start
             }
         } else {
-            match resolve_inode_block(device, &mut inode, lbn as u32)? {
-                Some(b) => b as u64,
+            match resolve_inode_block(device, fs, inode_num, &mut inode, lbn as u32)? {
+                Some(b) => b,
                 None => return Err(BlockDevError::Unsupported),
             }
         };
 
+        fs.check_data_blocks(phys, 1)?;
         fs.datablock_cache.modify(device, phys as u64, |blk| {
             let block_start = lbn * block_bytes;
             let block_end = block_start + block_bytes;
@@ -1890,12 +2299,18 @@ pub fn write_file_with_ino<B: BlockDevice>(
 
             blk[dst_off..dst_off + len as usize].copy_from_slice(&data[src_off as usize..(src_off + len) as usize]);
         })?;
+        written.push(phys);
     }
+    fs.datablock_cache.write_through_blocks(device, &written)?;
+    fs.datablock_cache.shrink_to_capacity(device)?;
 
     if end > old_size {
         inode.i_size_lo = (end as u64 & 0xffff_ffff) as u32;
         inode.i_size_high = ((end as u64) >> 32) as u32;
     }
+    let now = fs.current_time();
+    inode.set_mtime_ts(now);
+    inode.set_ctime_ts(now);
 
     fs.modify_inode(device, inode_num, |td| {
         *td = inode;
@@ -1903,3 +2318,1072 @@ pub fn write_file_with_ino<B: BlockDevice>(
 
     Ok(())
 }
+
+/// 为 [offset, offset+len) 预分配块（fallocate mode 0）：
+/// 空洞部分分配物理块并记为未初始化 extent，真正写入前读出为 0，写入时再逐段转为已初始化；
+/// 已映射的块保持不变。范围超出当前大小时扩展文件大小；
+/// 路径不存在返回 NotFound，目标是目录返回 IsADirectory
+pub fn fallocate<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    offset: u64,
+    len: u64,
+) -> Ext4Result<()> {
+    fs.ensure_writable()?;
+    let end = offset.checked_add(len).ok_or(Ext4Error::InvalidInput)?;
+    if len == 0 {
+        return Err(Ext4Error::InvalidInput);
+    }
+    let norm_path = split_paren_child_and_tranlatevalid(path);
+    let (inode_num, mut inode) = lookup_path(fs, block_dev, &norm_path)?;
+    if inode.is_dir() {
+        return Err(Ext4Error::IsADirectory);
+    }
+    if !inode.is_file() {
+        return Err(Ext4Error::InvalidInput);
+    }
+    if !fs.superblock.has_extents() {
+        return Err(BlockDevError::Unsupported.into());
+    }
+    if !inode.have_extend_header_and_use_extend() {
+        inode.i_flags |= Ext4Inode::EXT4_EXTENTS_FL;
+        inode.write_extend_header();
+    }
+
+    let block_bytes = BLOCK_SIZE as u64;
+    let end_lbn = end.div_ceil(block_bytes);
+    let extents = collect_inode_extents(block_dev, fs, inode_num, &mut inode)?;
+    let mut lbn = offset / block_bytes;
+    while lbn < end_lbn {
+        // 已映射（无论是否初始化）的部分跳过
+        if let Some(e) = extents.iter().find(|e| {
+            let start = e.ee_block as u64;
+            start <= lbn && lbn < start + extent_block_count(e) as u64
+        }) {
+            lbn = e.ee_block as u64 + extent_block_count(e) as u64;
+            continue;
+        }
+        let hole_end = extents
+            .iter()
+            .map(|e| e.ee_block as u64)
+            .filter(|&s| s > lbn)
+            .min()
+            .unwrap_or(end_lbn)
+            .min(end_lbn);
+        let want = (hole_end - lbn).min(Ext4Extent::EXT_MAX_MERGE_LEN as u64) as u32;
+        let prev = extents
+            .iter()
+            .filter(|e| (e.ee_block as u64) < lbn)
+            .max_by_key(|e| e.ee_block);
+        let goal = prev
+            .map(|e| e.start_block() + (lbn - e.ee_block as u64))
+            .or_else(|| fs.data_goal_for_inode(inode_num));
+        let implied = prev
+            .filter(|e| e.ee_block as u64 + extent_block_count(e) as u64 == lbn)
+            .and_then(|e| {
+                fs.implied_cluster_run(e.start_block() + extent_block_count(e) as u64 - 1, want)
+            });
+        let (run_start, run_len) = match implied {
+            Some(run) => run,
+            None => fs.alloc_blocks_for(block_dev, inode_num, goal, want)?,
+        };
+        {
+            let mut tree = ExtentTree::new(&mut inode).with_checksum(fs, inode_num);
+            let ext = Ext4Extent::new_uninit(lbn as u32, run_start, run_len as u16);
+            tree.insert_extent(fs, ext, block_dev)?;
+        }
+        let iblocks = inode
+            .blocks_count()
+            .saturating_add(run_len as u64 * (BLOCK_SIZE / 512) as u64);
+        inode.i_blocks_lo = iblocks as u32;
+        inode.l_i_blocks_high = (iblocks >> 32) as u16;
+        lbn += run_len as u64;
+    }
+
+    let now = fs.current_time();
+    if end > inode.size() {
+        inode.i_size_lo = end as u32;
+        inode.i_size_high = (end >> 32) as u32;
+        inode.set_mtime_ts(now);
+    }
+    inode.set_ctime_ts(now);
+    Ok(fs.modify_inode(block_dev, inode_num, |td| {
+        *td = inode;
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::api::{OpenFlags, close, open, write_at};
+    use crate::ext4_backend::datablock_cache::CachePolicy;
+    use crate::ext4_backend::test_support::*;
+    use alloc::format;
+    use alloc::vec;
+
+    #[test]
+    fn fragmentation_counts_discontiguous_runs() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/frag", None, None).unwrap();
+        mkfile(&mut dev, &mut fs, "/other", None, None).unwrap();
+
+        // 交替写入两个文件，写满预留窗口之后 /frag 的数据块不再连续
+        let block = [0xA5u8; BLOCK_SIZE];
+        for i in 0..2 * (RESERVATION_WINDOW_BLOCKS as u64 + 1) {
+            write_file(&mut dev, &mut fs, "/frag", i * BLOCK_SIZE as u64, &block).unwrap();
+            write_file(&mut dev, &mut fs, "/other", i * BLOCK_SIZE as u64, &block).unwrap();
+        }
+
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/frag").unwrap().unwrap();
+        let map = resolve_inode_block_allextend(&mut fs, &mut dev, ino, &mut inode).unwrap();
+        let mut runs = 0u32;
+        let mut prev: Option<(u32, u64)> = None;
+        for (&lbn, &phys) in map.iter() {
+            match prev {
+                Some((pl, pp)) if pl + 1 == lbn && pp + 1 == phys => {}
+                _ => runs += 1,
+            }
+            prev = Some((lbn, phys));
+        }
+        assert!(runs > 1);
+
+        let info = fragmentation(&mut dev, &mut fs, "/frag").unwrap();
+        assert_eq!(info.extent_count, runs);
+        assert_eq!(info.ideal_extent_count, 1);
+        assert!(info.score > 1.0);
+    }
+
+    #[test]
+    fn fragmentation_contiguous_file_scores_one() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let data = [0x5Au8; BLOCK_SIZE * 4];
+        mkfile(&mut dev, &mut fs, "/contig", Some(&data), None).unwrap();
+
+        let info = fragmentation(&mut dev, &mut fs, "/contig").unwrap();
+        assert_eq!(info.extent_count, 1);
+        assert_eq!(info.ideal_extent_count, 1);
+        assert_eq!(info.score, 1.0);
+    }
+
+    #[test]
+    fn repeated_lookup_hits_name_cache() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/etc").unwrap();
+        mkfile(&mut dev, &mut fs, "/etc/app.conf", Some(b"k=v"), None).unwrap();
+
+        let (ino, _) = get_file_inode(&mut fs, &mut dev, "/etc/app.conf").unwrap().unwrap();
+        fs.datablock_cache.flush_all(&mut dev).unwrap();
+        fs.datablock_cache.clear();
+        let hits_before = fs.dentry_cache.stats().hits;
+
+        for _ in 0..50 {
+            let (again, _) = get_file_inode(&mut fs, &mut dev, "/etc/app.conf").unwrap().unwrap();
+            assert_eq!(again, ino);
+        }
+
+        // 每次两级路径都命中缓存，没有读入任何目录块
+        assert_eq!(fs.dentry_cache.stats().hits - hits_before, 100);
+        assert_eq!(fs.datablock_cache.stats().total_entries, 0);
+    }
+
+    #[test]
+    fn name_cache_invalidated_on_unlink_rename_rmdir() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/etc").unwrap();
+        mkfile(&mut dev, &mut fs, "/etc/a.conf", Some(b"a"), None).unwrap();
+        assert!(get_file_inode(&mut fs, &mut dev, "/etc/a.conf").unwrap().is_some());
+
+        delete_file(&mut fs, &mut dev, "/etc/a.conf");
+        assert!(get_file_inode(&mut fs, &mut dev, "/etc/a.conf").unwrap().is_none());
+
+        mkfile(&mut dev, &mut fs, "/etc/a.conf", Some(b"b"), None).unwrap();
+        assert!(get_file_inode(&mut fs, &mut dev, "/etc/a.conf").unwrap().is_some());
+        rename(&mut dev, &mut fs, "/etc/a.conf", "/etc/b.conf").unwrap();
+        assert!(get_file_inode(&mut fs, &mut dev, "/etc/a.conf").unwrap().is_none());
+        assert!(get_file_inode(&mut fs, &mut dev, "/etc/b.conf").unwrap().is_some());
+
+        delete_dir(&mut fs, &mut dev, "/etc");
+        assert!(get_file_inode(&mut fs, &mut dev, "/etc").unwrap().is_none());
+        assert!(get_file_inode(&mut fs, &mut dev, "/etc/b.conf").unwrap().is_none());
+    }
+
+    #[test]
+    fn truncate_file_shrinks_and_grows_sparse() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let data = [0xABu8; BLOCK_SIZE * 3];
+        mkfile(&mut dev, &mut fs, "/t", Some(&data), None).unwrap();
+
+        // 缩小到非块对齐大小：释放最后两块中的一块，保留块的尾部清零
+        let free_before = fs.superblock.free_blocks_count();
+        let new_size = BLOCK_SIZE as u64 + 100;
+        truncate_file(&mut dev, &mut fs, "/t", new_size).unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_before + 1);
+        let got = read_file(&mut dev, &mut fs, "/t").unwrap();
+        assert_eq!(got.len() as u64, new_size);
+        assert!(got.iter().all(|&b| b == 0xAB));
+
+        // 扩大只改大小，不分配新块
+        let free_before = fs.superblock.free_blocks_count();
+        truncate_file(&mut dev, &mut fs, "/t", BLOCK_SIZE as u64 * 3).unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_before);
+        let got = read_file(&mut dev, &mut fs, "/t").unwrap();
+        assert_eq!(got.len(), BLOCK_SIZE * 3);
+        assert!(got[..new_size as usize].iter().all(|&b| b == 0xAB));
+        assert!(got[new_size as usize..].iter().all(|&b| b == 0));
+
+        truncate_file(&mut dev, &mut fs, "/t", 0).unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_before + 2);
+        assert!(read_file(&mut dev, &mut fs, "/t").unwrap().is_empty());
+        assert!(matches!(
+            truncate_file(&mut dev, &mut fs, "/missing", 0),
+            Err(Ext4Error::NotFound { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn unlink_reclaims_inode_and_blocks() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let data = [0x11u8; BLOCK_SIZE * 3];
+        let (ino, _) = mkfile_with_ino(&mut dev, &mut fs, "/u", Some(&data), None).unwrap();
+        let group = ((ino - 1) / fs.superblock.s_inodes_per_group) as usize;
+        let free_blocks = fs.superblock.free_blocks_count();
+        let free_inodes = fs.superblock.s_free_inodes_count;
+        let desc_inodes = fs.group_descs[group].free_inodes_count();
+
+        unlink(&mut dev, &mut fs, "/u").unwrap();
+        assert!(get_file_inode(&mut fs, &mut dev, "/u").unwrap().is_none());
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks + 3);
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes + 1);
+        assert_eq!(fs.group_descs[group].free_inodes_count(), desc_inodes + 1);
+        assert_eq!(
+            unlink(&mut dev, &mut fs, "/u"),
+            Err(Ext4Error::NotFound { index: 0, component: "u".into() })
+        );
+    }
+
+    #[test]
+    fn unlink_frees_extent_tree_blocks_of_deep_file() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let free_blocks = fs.superblock.free_blocks_count();
+        mkfile(&mut dev, &mut fs, "/sparse", None, None).unwrap();
+        // 隔块写入，每块一个 extent，超过 inode 内 4 个条目后树长到深度 1
+        for i in 0..12u64 {
+            write_file(&mut dev, &mut fs, "/sparse", i * 2 * BLOCK_SIZE as u64, b"x").unwrap();
+        }
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/sparse").unwrap().unwrap();
+        let root = ExtentTree::new(&mut inode).load_root_from_inode().unwrap();
+        assert!(root.header().eh_depth >= 1);
+        let tree_blocks = collect_extent_tree_blocks(&mut dev, &fs, ino, &mut inode).unwrap();
+        assert!(!tree_blocks.is_empty());
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks - 12 - tree_blocks.len() as u64);
+
+        unlink(&mut dev, &mut fs, "/sparse").unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+    }
+
+    #[test]
+    fn unlink_keeps_inode_with_remaining_links() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/a", Some(b"shared"), None).unwrap();
+        link(&mut dev, &mut fs, "/a", "/b").unwrap();
+        let free_blocks = fs.superblock.free_blocks_count();
+
+        unlink(&mut dev, &mut fs, "/a").unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+        let (_, inode) = get_file_inode(&mut fs, &mut dev, "/b").unwrap().unwrap();
+        assert_eq!(inode.i_links_count, 1);
+        let got = read_file(&mut dev, &mut fs, "/b").unwrap();
+        assert_eq!(&got[..6], b"shared");
+    }
+
+    #[test]
+    fn rmdir_removes_empty_dir_and_rejects_non_empty() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/a").unwrap();
+        let (a_ino, a_inode) = get_file_inode(&mut fs, &mut dev, "/a").unwrap().unwrap();
+        let group = ((a_ino - 1) / fs.superblock.s_inodes_per_group) as usize;
+        let free_blocks = fs.superblock.free_blocks_count();
+        let free_inodes = fs.superblock.s_free_inodes_count;
+        let used_dirs = fs.group_descs[group].used_dirs_count();
+
+        mkdir(&mut dev, &mut fs, "/a/b").unwrap();
+        assert_eq!(fs.group_descs[group].used_dirs_count(), used_dirs + 1);
+        mkfile(&mut dev, &mut fs, "/a/b/f", Some(b"x"), None).unwrap();
+        assert!(matches!(
+            rmdir(&mut dev, &mut fs, "/a/b"),
+            Err(Ext4Error::DirectoryNotEmpty)
+        ));
+
+        unlink(&mut dev, &mut fs, "/a/b/f").unwrap();
+        rmdir(&mut dev, &mut fs, "/a/b").unwrap();
+        assert!(get_file_inode(&mut fs, &mut dev, "/a/b").unwrap().is_none());
+        let (_, a_after) = get_file_inode(&mut fs, &mut dev, "/a").unwrap().unwrap();
+        assert_eq!(a_after.i_links_count, a_inode.i_links_count);
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes);
+        assert_eq!(fs.group_descs[group].used_dirs_count(), used_dirs);
+
+        assert!(rmdir(&mut dev, &mut fs, "/").is_err());
+        assert!(matches!(
+            rmdir(&mut dev, &mut fs, "/a/b"),
+            Err(Ext4Error::NotFound { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn rename_moves_dir_and_updates_parent_links() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/a").unwrap();
+        mkdir(&mut dev, &mut fs, "/b").unwrap();
+        mkdir(&mut dev, &mut fs, "/a/d").unwrap();
+        mkfile(&mut dev, &mut fs, "/a/d/f", Some(b"payload"), None).unwrap();
+        let (d_ino, _) = get_file_inode(&mut fs, &mut dev, "/a/d").unwrap().unwrap();
+        let (_, a_before) = get_file_inode(&mut fs, &mut dev, "/a").unwrap().unwrap();
+        let (b_ino, b_before) = get_file_inode(&mut fs, &mut dev, "/b").unwrap().unwrap();
+
+        rename(&mut dev, &mut fs, "/a/d", "/b/d2").unwrap();
+        assert!(get_file_inode(&mut fs, &mut dev, "/a/d").unwrap().is_none());
+        let (moved, _) = get_file_inode(&mut fs, &mut dev, "/b/d2").unwrap().unwrap();
+        assert_eq!(moved, d_ino);
+        let got = read_file(&mut dev, &mut fs, "/b/d2/f").unwrap();
+        assert_eq!(&got[..7], b"payload");
+
+        let (_, a_after) = get_file_inode(&mut fs, &mut dev, "/a").unwrap().unwrap();
+        let (_, b_after) = get_file_inode(&mut fs, &mut dev, "/b").unwrap().unwrap();
+        assert_eq!(a_after.i_links_count, a_before.i_links_count - 1);
+        assert_eq!(b_after.i_links_count, b_before.i_links_count + 1);
+        let dotdot = read_dir(&mut dev, &mut fs, "/b/d2")
+            .unwrap()
+            .map(|e| e.unwrap())
+            .find(|e| e.name == "..")
+            .unwrap();
+        assert_eq!(dotdot.inode, b_ino);
+
+        // 不能移动到自身子树下
+        assert!(matches!(
+            rename(&mut dev, &mut fs, "/b", "/b/d2/inner"),
+            Err(Ext4Error::InvalidInput)
+        ));
+    }
+
+    #[test]
+    fn rename_replaces_file_but_not_non_empty_dir() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/x", Some(b"xxxx"), None).unwrap();
+        mkfile(&mut dev, &mut fs, "/y", Some(b"yyyy"), None).unwrap();
+        let free_inodes = fs.superblock.s_free_inodes_count;
+        rename(&mut dev, &mut fs, "/x", "/y").unwrap();
+        assert!(get_file_inode(&mut fs, &mut dev, "/x").unwrap().is_none());
+        assert_eq!(&read_file(&mut dev, &mut fs, "/y").unwrap()[..4], b"xxxx");
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes + 1);
+
+        mkdir(&mut dev, &mut fs, "/p").unwrap();
+        mkdir(&mut dev, &mut fs, "/q").unwrap();
+        mkfile(&mut dev, &mut fs, "/q/z", None, None).unwrap();
+        assert!(matches!(
+            rename(&mut dev, &mut fs, "/p", "/q"),
+            Err(Ext4Error::DirectoryNotEmpty)
+        ));
+        assert!(get_file_inode(&mut fs, &mut dev, "/p").unwrap().is_some());
+        // 文件与目录不能互相替换
+        assert!(matches!(
+            rename(&mut dev, &mut fs, "/y", "/p"),
+            Err(Ext4Error::IsADirectory)
+        ));
+        assert!(matches!(
+            rename(&mut dev, &mut fs, "/p", "/y"),
+            Err(Ext4Error::NotADirectory { index: 0, .. })
+        ));
+        assert!(matches!(
+            rename(&mut dev, &mut fs, "/nope", "/y"),
+            Err(Ext4Error::NotFound { index: 0, .. })
+        ));
+        assert!(matches!(
+            mv(&mut fs, &mut dev, "/nope", "/w"),
+            Err(Ext4Error::NotFound { index: 0, .. })
+        ));
+        assert_eq!(
+            mv(&mut fs, &mut dev, "/p", "/y/w"),
+            Err(Ext4Error::NotADirectory { index: 0, component: "y".into() })
+        );
+
+        unlink(&mut dev, &mut fs, "/q/z").unwrap();
+        rename(&mut dev, &mut fs, "/p", "/q").unwrap();
+        assert!(get_file_inode(&mut fs, &mut dev, "/p").unwrap().is_none());
+        assert!(get_file_inode(&mut fs, &mut dev, "/q").unwrap().unwrap().1.is_dir());
+    }
+
+    #[test]
+    fn rename_over_existing_rewrites_destination_entry_in_place() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/y", Some(b"yyyy"), None).unwrap();
+        symlink(&mut dev, &mut fs, "/y", "/s").unwrap();
+        let (s_ino, _) = get_file_inode(&mut fs, &mut dev, "/s").unwrap().unwrap();
+        let slot = find_entry_location(&mut dev, &mut fs, "/", "y").unwrap().unwrap();
+        let free_blocks = fs.superblock.free_blocks_count();
+
+        // 目标目录项原位改指向源 inode，类型随之变为符号链接，旧文件被回收
+        rename(&mut dev, &mut fs, "/s", "/y").unwrap();
+        assert_eq!(find_entry_location(&mut dev, &mut fs, "/", "y").unwrap(), Some(slot));
+        let entry = read_dir(&mut dev, &mut fs, "/")
+            .unwrap()
+            .map(|e| e.unwrap())
+            .find(|e| e.name == "y")
+            .unwrap();
+        assert_eq!(entry.inode, s_ino);
+        assert_eq!(entry.file_type, Ext4DirEntry2::EXT4_FT_SYMLINK);
+        assert!(get_file_inode(&mut fs, &mut dev, "/s").unwrap().is_none());
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks + 1);
+
+        // 跨父目录用空目录替换空目录：链接数和 ".." 与 mv 一致，被替换的目录被回收
+        mkdir(&mut dev, &mut fs, "/a").unwrap();
+        mkdir(&mut dev, &mut fs, "/a/d").unwrap();
+        mkdir(&mut dev, &mut fs, "/b").unwrap();
+        mkdir(&mut dev, &mut fs, "/b/e").unwrap();
+        let (d_ino, _) = get_file_inode(&mut fs, &mut dev, "/a/d").unwrap().unwrap();
+        let (_, a_before) = get_file_inode(&mut fs, &mut dev, "/a").unwrap().unwrap();
+        let (b_ino, b_before) = get_file_inode(&mut fs, &mut dev, "/b").unwrap().unwrap();
+        let free_inodes = fs.superblock.s_free_inodes_count;
+
+        rename(&mut dev, &mut fs, "/a/d", "/b/e").unwrap();
+        assert_eq!(get_file_inode(&mut fs, &mut dev, "/b/e").unwrap().unwrap().0, d_ino);
+        let (_, a_after) = get_file_inode(&mut fs, &mut dev, "/a").unwrap().unwrap();
+        let (_, b_after) = get_file_inode(&mut fs, &mut dev, "/b").unwrap().unwrap();
+        assert_eq!(a_after.i_links_count, a_before.i_links_count - 1);
+        assert_eq!(b_after.i_links_count, b_before.i_links_count);
+        let dotdot = read_dir(&mut dev, &mut fs, "/b/e")
+            .unwrap()
+            .map(|e| e.unwrap())
+            .find(|e| e.name == "..")
+            .unwrap();
+        assert_eq!(dotdot.inode, b_ino);
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes + 1);
+    }
+
+    #[test]
+    fn rename_commits_one_journal_transaction() {
+        let (mut dev, fs) = setup_fs(16 * 1024);
+        umount(fs, &mut dev).unwrap();
+        dev.set_journal_use(true);
+        let mut fs = mount(&mut dev).unwrap();
+        mkfile(&mut dev, &mut fs, "/old", Some(b"data"), None).unwrap();
+
+        rename(&mut dev, &mut fs, "/old", "/new").unwrap();
+        // rename 结束时事务已经提交，不会残留在缓存里等待后续操作
+        assert_eq!(dev.pending_journal_blocks(), 0);
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(get_file_inode(&mut fs, &mut dev, "/old").unwrap().is_none());
+        assert_eq!(&read_file(&mut dev, &mut fs, "/new").unwrap()[..4], b"data");
+    }
+
+    #[test]
+    fn symlink_inline_and_block_targets_resolve() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkdir(&mut dev, &mut fs, "/dir").unwrap();
+        mkfile(&mut dev, &mut fs, "/dir/f", Some(b"hello"), None).unwrap();
+        let (f_ino, _) = get_file_inode(&mut fs, &mut dev, "/dir/f").unwrap().unwrap();
+
+        // 短目标存放在 i_block 中，不占数据块
+        symlink(&mut dev, &mut fs, "dir/f", "/short").unwrap();
+        let (_, short) = get_file_inode(&mut fs, &mut dev, "/short").unwrap().unwrap();
+        assert!(short.is_symlink());
+        assert_eq!(short.i_blocks_lo, 0);
+        assert_eq!(read_link(&mut dev, &mut fs, "/short").unwrap(), "dir/f");
+
+        // 超过 60 字节的目标写入数据块
+        let long_target = format!("/dir/{}/../f", "x".repeat(80));
+        symlink(&mut dev, &mut fs, &long_target, "/long").unwrap();
+        let (_, long) = get_file_inode(&mut fs, &mut dev, "/long").unwrap().unwrap();
+        assert!(long.i_blocks_lo > 0);
+        assert_eq!(read_link(&mut dev, &mut fs, "/long").unwrap(), long_target);
+
+        // 跟随最后一个分量和中间分量
+        let via_short = fs.find_file_with(&mut dev, "/short", true).unwrap();
+        assert!(via_short.is_file());
+        assert_eq!(read_file(&mut dev, &mut fs, "/short").unwrap(), b"hello");
+        symlink(&mut dev, &mut fs, "/dir", "/ld").unwrap();
+        let (ino, _) = resolve_path_follow(&mut dev, &mut fs, "/ld/f", false).unwrap().unwrap();
+        assert_eq!(ino, f_ino);
+        symlink(&mut dev, &mut fs, "f", "/dir/rel").unwrap();
+        assert_eq!(read_link(&mut dev, &mut fs, "/ld/rel").unwrap(), "f");
+        // 不跟随时返回链接本身
+        assert!(fs.find_file(&mut dev, "/short").unwrap().is_symlink());
+
+        // 悬空链接允许创建，解析结果为不存在；read_link 拒绝普通文件
+        symlink(&mut dev, &mut fs, "/missing", "/dangling").unwrap();
+        assert!(matches!(
+            fs.find_file_with(&mut dev, "/dangling", true),
+            Err(Ext4Error::NotFound { .. })
+        ));
+        assert!(matches!(
+            read_link(&mut dev, &mut fs, "/dir/f"),
+            Err(Ext4Error::InvalidInput)
+        ));
+        assert!(matches!(
+            read_link(&mut dev, &mut fs, "/dir/none"),
+            Err(Ext4Error::NotFound { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn symlink_cycle_reports_too_many_links() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        symlink(&mut dev, &mut fs, "/b", "/a").unwrap();
+        symlink(&mut dev, &mut fs, "/a", "/b").unwrap();
+        assert!(matches!(
+            fs.find_file_with(&mut dev, "/a", true),
+            Err(Ext4Error::TooManyLinks)
+        ));
+        assert!(matches!(read_file(&mut dev, &mut fs, "/a"), Err(Ext4Error::TooManyLinks)));
+    }
+
+    #[test]
+    fn hard_link_shares_data_and_rejects_dirs() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/orig", Some(b"old"), None).unwrap();
+        link(&mut dev, &mut fs, "/orig", "/alias").unwrap();
+        let (ino, inode) = get_file_inode(&mut fs, &mut dev, "/alias").unwrap().unwrap();
+        assert_eq!(inode.i_links_count, 2);
+
+        write_file(&mut dev, &mut fs, "/alias", 0, b"new data").unwrap();
+        let got = read_file(&mut dev, &mut fs, "/orig").unwrap();
+        assert_eq!(&got[..8], b"new data");
+
+        mkdir(&mut dev, &mut fs, "/d").unwrap();
+        assert!(matches!(
+            link(&mut dev, &mut fs, "/d", "/d2"),
+            Err(Ext4Error::PermissionDenied)
+        ));
+        assert!(matches!(
+            link(&mut dev, &mut fs, "/orig", "/alias"),
+            Err(Ext4Error::InvalidInput)
+        ));
+        assert!(matches!(
+            link(&mut dev, &mut fs, "/orig", "/nodir/alias"),
+            Err(Ext4Error::NotFound { index: 0, .. })
+        ));
+
+        unlink(&mut dev, &mut fs, "/orig").unwrap();
+        let (alias_ino, inode) = get_file_inode(&mut fs, &mut dev, "/alias").unwrap().unwrap();
+        assert_eq!((alias_ino, inode.i_links_count), (ino, 1));
+    }
+
+    static TEST_NOW: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+
+    fn test_now() -> u64 {
+        TEST_NOW.load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    fn set_test_now(secs: u64) {
+        TEST_NOW.store(secs, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    #[test]
+    fn reads_and_writes_update_timestamps() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        assert_eq!(fs.current_time(), Ext4Timestamp::default());
+        fs.now = test_now;
+        set_test_now(1_000);
+        mkfile(&mut dev, &mut fs, "/t", Some(b"abc"), None).unwrap();
+        let (ino, inode) = get_file_inode(&mut fs, &mut dev, "/t").unwrap().unwrap();
+        assert_eq!(inode.i_extra_isize, fs.new_inode_extra_isize());
+        assert!(inode.i_extra_isize >= 32);
+        for ts in [inode.atime(), inode.mtime(), inode.ctime(), inode.crtime()] {
+            assert_eq!(ts.sec, 1_000);
+        }
+
+        // 超过 32 位的秒数落到 extra 的 epoch 位
+        let late = (1u64 << 32) + 5;
+        set_test_now(late);
+        write_file(&mut dev, &mut fs, "/t", 0, b"xyz").unwrap();
+        let inode = fs.get_inode_by_num(&mut dev, ino).unwrap();
+        assert_eq!((inode.mtime().sec, inode.ctime().sec), (late as i64, late as i64));
+        assert_eq!((inode.i_mtime, inode.i_mtime_extra), (5, 1));
+        assert_eq!(inode.atime().sec, 1_000);
+
+        set_test_now(3_000);
+        read_file(&mut dev, &mut fs, "/t").unwrap();
+        assert_eq!(fs.get_inode_by_num(&mut dev, ino).unwrap().atime().sec, 3_000);
+
+        // noatime 下读文件不再改动 inode
+        fs.noatime = true;
+        set_test_now(4_000);
+        read_file(&mut dev, &mut fs, "/t").unwrap();
+        assert_eq!(fs.get_inode_by_num(&mut dev, ino).unwrap().atime().sec, 3_000);
+
+        truncate_file(&mut dev, &mut fs, "/t", 1).unwrap();
+        let inode = fs.get_inode_by_num(&mut dev, ino).unwrap();
+        assert_eq!((inode.mtime().sec, inode.ctime().sec), (4_000, 4_000));
+        assert_eq!(inode.crtime().sec, 1_000);
+    }
+
+    #[test]
+    fn mkfile_under_missing_parent_fails_without_allocating() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/plain", Some(b"x"), None).unwrap();
+        let free_inodes = fs.superblock.s_free_inodes_count;
+        let free_blocks = fs.superblock.free_blocks_count();
+
+        assert!(matches!(
+            try_mkfile_with_ino(&mut dev, &mut fs, "/nonexistent_dir/file", Some(b"data"), None),
+            Err(Ext4Error::NotFound { index: 0, .. })
+        ));
+        assert!(matches!(
+            try_mkfile_with_ino(&mut dev, &mut fs, "/plain/file", Some(b"data"), None),
+            Err(Ext4Error::NotADirectory { index: 0, .. })
+        ));
+        assert!(mkfile(&mut dev, &mut fs, "/nonexistent_dir/file", None, None).is_err());
+
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes);
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+        assert!(get_file_inode(&mut fs, &mut dev, "/nonexistent_dir").unwrap().is_none());
+    }
+
+    #[test]
+    fn write_through_leaves_no_dirty_data_blocks() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let data = vec![3u8; 2 * BLOCK_SIZE + 10];
+
+        // 写回模式：数据块留在缓存里等待写回
+        mkfile(&mut dev, &mut fs, "/wb", Some(&data), None).unwrap();
+        write_file(&mut dev, &mut fs, "/wb", 5, b"back").unwrap();
+        assert!(fs.datablock_cache.stats().dirty_entries > 0);
+        fs.datablock_cache.flush_all(&mut dev).unwrap();
+
+        fs.set_cache_policy(CachePolicy::WriteThrough);
+        mkfile(&mut dev, &mut fs, "/wt", Some(&data), None).unwrap();
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/wt").unwrap().unwrap();
+        for phys in resolve_inode_block_allextend(&mut fs, &mut dev, ino, &mut inode).unwrap().values() {
+            assert!(!fs.datablock_cache.get(*phys).unwrap().dirty);
+        }
+        // 目录块属于元数据，不受写回策略影响，先写回再观察纯数据写
+        fs.datablock_cache.flush_all(&mut dev).unwrap();
+        write_file(&mut dev, &mut fs, "/wt", 3 * BLOCK_SIZE as u64, b"through").unwrap();
+        truncate(&mut dev, &mut fs, "/wt", 5 * BLOCK_SIZE as u64 + 1).unwrap();
+        assert_eq!(fs.datablock_cache.stats().dirty_entries, 0);
+
+        // 清空缓存后从设备读回，内容完整
+        fs.datablock_cache.clear();
+        let got = read_file(&mut dev, &mut fs, "/wt").unwrap();
+        assert_eq!(got.len(), 5 * BLOCK_SIZE + 1);
+        assert_eq!(&got[..data.len()], &data[..]);
+        assert_eq!(&got[3 * BLOCK_SIZE..3 * BLOCK_SIZE + 7], b"through");
+    }
+
+    #[test]
+    fn appends_allocate_next_to_last_extent() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/gap", Some(&[1u8; BLOCK_SIZE]), None).unwrap();
+        mkfile(&mut dev, &mut fs, "/grow", Some(&[2u8; BLOCK_SIZE * 4]), None).unwrap();
+        // 释放 /grow 之前的一个空闲块，首次适配会先把它分给追加的数据
+        unlink(&mut dev, &mut fs, "/gap").unwrap();
+
+        let tail: Vec<u8> = (0..BLOCK_SIZE * 12).map(|i| (i / BLOCK_SIZE) as u8).collect();
+        write_file(&mut dev, &mut fs, "/grow", 4 * BLOCK_SIZE as u64, &tail).unwrap();
+        let info = fragmentation(&mut dev, &mut fs, "/grow").unwrap();
+        assert_eq!(info.extent_count, 1);
+
+        // 一次写入跨越的空洞被分配成一个 extent
+        mkfile(&mut dev, &mut fs, "/sparse", None, None).unwrap();
+        write_file(&mut dev, &mut fs, "/sparse", 0, &tail).unwrap();
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/sparse").unwrap().unwrap();
+        assert_eq!(collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap().len(), 1);
+        assert_eq!(inode.blocks_count(), (12 * BLOCK_SIZE / 512) as u64);
+        assert_eq!(read_file(&mut dev, &mut fs, "/sparse").unwrap(), tail);
+    }
+
+    #[test]
+    fn interleaved_appends_stay_contiguous_with_reservations() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let mut a = open(&mut dev, &mut fs, "/a", OpenFlags::RDWR.with_create()).unwrap();
+        let mut b = open(&mut dev, &mut fs, "/b", OpenFlags::RDWR.with_create()).unwrap();
+        let window = RESERVATION_WINDOW_BLOCKS as usize;
+        // 第三段只用掉窗口的一块，关闭前两个文件都还持有窗口
+        let rounds = 2 * (window + 1) + 1;
+        for i in 0..rounds {
+            write_at(&mut dev, &mut fs, &mut a, &[i as u8; BLOCK_SIZE]).unwrap();
+            write_at(&mut dev, &mut fs, &mut b, &[!(i as u8); BLOCK_SIZE]).unwrap();
+        }
+        // 每个文件只在窗口用完时断开，而不是每块都与另一个文件交错
+        for path in ["/a", "/b"] {
+            let info = fragmentation(&mut dev, &mut fs, path).unwrap();
+            assert!(info.extent_count <= 3, "{path}: {}", info.extent_count);
+        }
+        let data = read_file(&mut dev, &mut fs, "/b").unwrap();
+        assert_eq!(data.len(), rounds * BLOCK_SIZE);
+        assert_eq!(data[(rounds - 1) * BLOCK_SIZE], !((rounds - 1) as u8));
+
+        // 关闭句柄后窗口被释放
+        assert_eq!(fs.block_allocator.reservation_count(), 2);
+        close(&mut dev, &mut fs, a).unwrap();
+        close(&mut dev, &mut fs, b).unwrap();
+        assert_eq!(fs.block_allocator.reservation_count(), 0);
+        assert!(verify_free_counts(&mut dev, &mut fs).unwrap().is_empty());
+    }
+
+    #[test]
+    fn mkfile_reserves_a_window_and_unlink_releases_it() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let data = [3u8; 2 * BLOCK_SIZE];
+        let (a, _) = mkfile_with_ino(&mut dev, &mut fs, "/a", Some(&data), None).unwrap();
+        let (_, mut a_inode) = get_file_inode(&mut fs, &mut dev, "/a").unwrap().unwrap();
+        let a_first = resolve_inode_block(&mut dev, &fs, a, &mut a_inode, 0).unwrap().unwrap();
+        let (start, end) = fs.block_allocator.reservation(a).unwrap();
+        assert_eq!((start, end - start), (a_first + 2, RESERVATION_WINDOW_BLOCKS as u64));
+
+        // 另一个新文件不会落进 /a 的窗口，/a 之后的追加写入仍然紧跟原有数据
+        mkfile(&mut dev, &mut fs, "/b", Some(&data), None).unwrap();
+        let (b, mut b_inode) = get_file_inode(&mut fs, &mut dev, "/b").unwrap().unwrap();
+        let b_first = resolve_inode_block(&mut dev, &fs, b, &mut b_inode, 0).unwrap().unwrap();
+        assert!(b_first >= end || b_first + 2 <= start);
+        write_file(&mut dev, &mut fs, "/a", data.len() as u64, &data).unwrap();
+        assert_eq!(fragmentation(&mut dev, &mut fs, "/a").unwrap().extent_count, 1);
+
+        // 仍被打开的文件删除后立即归还窗口，inode 留到 close 时回收
+        let file = open(&mut dev, &mut fs, "/a", OpenFlags::RDWR).unwrap();
+        unlink(&mut dev, &mut fs, "/a").unwrap();
+        assert!(fs.block_allocator.reservation(a).is_none());
+        close(&mut dev, &mut fs, file).unwrap();
+        assert!(verify_free_counts(&mut dev, &mut fs).unwrap().is_empty());
+    }
+
+    #[test]
+    fn freed_runs_are_discarded_when_enabled() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(16 * 1024), false, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let data = [9u8; BLOCK_SIZE * 6];
+        mkfile(&mut dev, &mut fs, "/off", Some(&data), None).unwrap();
+        unlink(&mut dev, &mut fs, "/off").unwrap();
+        assert_eq!(dev.with_raw_device(|d| Ok(d.discards.len())).unwrap(), 0);
+
+        fs.online_discard = true;
+        mkfile(&mut dev, &mut fs, "/f", Some(&data), None).unwrap();
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/f").unwrap().unwrap();
+        let first = resolve_inode_block(&mut dev, &fs, ino, &mut inode, 0).unwrap().unwrap();
+
+        // 截断释放尾部 4 块，合并成一次 discard
+        truncate_file(&mut dev, &mut fs, "/f", 2 * BLOCK_SIZE as u64).unwrap();
+        let discards = dev.with_raw_device(|d| Ok(core::mem::take(&mut d.discards))).unwrap();
+        assert_eq!(discards, [(first + 2, 4)]);
+
+        unlink(&mut dev, &mut fs, "/f").unwrap();
+        let discards = dev.with_raw_device(|d| Ok(core::mem::take(&mut d.discards))).unwrap();
+        assert_eq!(discards, [(first, 2)]);
+        assert!(fs.pending_discards.is_empty());
+    }
+
+    #[test]
+    fn discards_follow_device_capabilities() {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(16 * 1024), false, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        fs.online_discard = true;
+        let data = [3u8; BLOCK_SIZE * 4];
+
+        // 设备不报告 discard 能力时释放路径不下发 discard
+        dev.with_raw_device(|d| {
+            d.caps.discard = false;
+            Ok(())
+        })
+        .unwrap();
+        assert!(!dev.capabilities().discard);
+        mkfile(&mut dev, &mut fs, "/nodiscard", Some(&data), None).unwrap();
+        unlink(&mut dev, &mut fs, "/nodiscard").unwrap();
+        assert!(dev.with_raw_device(|d| Ok(d.discards.is_empty())).unwrap());
+        assert!(fs.pending_discards.is_empty());
+
+        dev.with_raw_device(|d| {
+            d.caps.discard = true;
+            Ok(())
+        })
+        .unwrap();
+        mkfile(&mut dev, &mut fs, "/discard", Some(&data), None).unwrap();
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/discard").unwrap().unwrap();
+        let first = resolve_inode_block(&mut dev, &fs, ino, &mut inode, 0).unwrap().unwrap();
+        unlink(&mut dev, &mut fs, "/discard").unwrap();
+        let discards = dev.with_raw_device(|d| Ok(core::mem::take(&mut d.discards))).unwrap();
+        assert_eq!(discards, [(first, 4)]);
+    }
+
+    #[test]
+    fn discards_wait_for_the_journal_commit_that_frees_the_blocks() {
+        use crate::ext4_backend::bitmap_cache::CacheKey;
+        use crate::ext4_backend::jbd2::jbd2::jbd2_recover;
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(16 * 1024), false, false);
+        mkfs(&mut dev).unwrap();
+        umount(mount(&mut dev).unwrap(), &mut dev).unwrap();
+        dev.set_journal_use(true);
+        umount(mount(&mut dev).unwrap(), &mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        fs.online_discard = true;
+
+        let data = [5u8; BLOCK_SIZE * 3];
+        mkfile(&mut dev, &mut fs, "/gone", Some(&data), None).unwrap();
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/gone").unwrap().unwrap();
+        let first = resolve_inode_block(&mut dev, &fs, ino, &mut inode, 0).unwrap().unwrap();
+        assert!(fs.datablock_cache.get(first).is_some());
+
+        unlink(&mut dev, &mut fs, "/gone").unwrap();
+        // 已释放的块不再留在数据块缓存里
+        assert!((first..first + 3).all(|b| fs.datablock_cache.get(b).is_none()));
+        let discards = dev.with_raw_device(|d| Ok(d.discards.clone())).unwrap();
+        assert_eq!(discards, [(first, 3)]);
+        assert_eq!(dev.pending_journal_blocks(), 0);
+
+        // discard 之后立刻崩溃：回放日志后文件已删除，这些块在位图里是空闲的
+        let image = dev.with_raw_device(|d| Ok(d.inner.clone())).unwrap();
+        let mut crashed = Jbd2Dev::initial_jbd2dev(0, image, true, false);
+        jbd2_recover(&mut crashed).unwrap();
+        let mut after = mount(&mut crashed).unwrap();
+        assert!(get_file_inode(&mut after, &mut crashed, "/gone").unwrap().is_none());
+        let (group, in_group) = after.block_allocator.global_to_group(first);
+        let bitmap_block = after.group_descs[group as usize].block_bitmap();
+        let bitmap = after
+            .bitmap_cache
+            .get_or_load(&mut crashed, CacheKey::new_block(group), bitmap_block)
+            .unwrap();
+        assert!((in_group..in_group + 3).all(|b| bitmap.data[(b / 8) as usize] & (1 << (b % 8)) == 0));
+        assert!(verify_free_counts(&mut crashed, &mut after).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sequential_writes_merge_into_few_extents() {
+        let (mut dev, mut fs) = setup_fs(80 * 1024);
+        let chunk = alloc::vec![0x5Au8; 1024 * 1024];
+        let mut file = open(&mut dev, &mut fs, "/big", OpenFlags::RDWR.with_create()).unwrap();
+        for _ in 0..200 {
+            write_at(&mut dev, &mut fs, &mut file, &chunk).unwrap();
+        }
+        close(&mut dev, &mut fs, file).unwrap();
+
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/big").unwrap().unwrap();
+        assert_eq!(inode.size(), 200 * 1024 * 1024);
+        let count = extent_count(&mut dev, &fs, ino, &mut inode).unwrap();
+        assert!(count < 10, "{count} extents");
+        let exts = collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap();
+        assert!(exts.iter().all(|e| e.ee_len & 0x8000 == 0 && e.ee_len > 0));
+        let total: u64 = exts.iter().map(|e| e.ee_len as u64).sum();
+        assert_eq!(total, 200 * 1024 * 1024 / BLOCK_SIZE as u64);
+    }
+
+    /// 直接往物理块写入垃圾，模拟未初始化块里的旧数据
+    fn scribble<B: BlockDevice>(dev: &mut Jbd2Dev<B>, phys: u64) {
+        dev.read_block(phys).unwrap();
+        dev.buffer_mut().fill(0xEE);
+        dev.write_block(phys, true).unwrap();
+    }
+
+    #[test]
+    fn fallocate_then_full_extent_write_initializes_it() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/db", None, None).unwrap();
+        fallocate(&mut dev, &mut fs, "/db", 0, 4 * BLOCK_SIZE as u64).unwrap();
+
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/db").unwrap().unwrap();
+        assert_eq!(inode.size(), 4 * BLOCK_SIZE as u64);
+        assert_eq!(inode.blocks_count(), 4 * (BLOCK_SIZE / 512) as u64);
+        let exts = collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap();
+        assert_eq!(exts.len(), 1);
+        assert!(!exts[0].is_initialized());
+        assert_eq!(extent_block_count(&exts[0]), 4);
+        for i in 0..4 {
+            scribble(&mut dev, exts[0].start_block() + i);
+        }
+        // 预分配区间读出为 0，而不是块里的旧数据
+        let data = read_file(&mut dev, &mut fs, "/db").unwrap();
+        assert!(data.iter().all(|&b| b == 0));
+
+        let payload: Vec<u8> = (0..4 * BLOCK_SIZE).map(|i| (i % 251) as u8).collect();
+        write_file(&mut dev, &mut fs, "/db", 0, &payload).unwrap();
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/db").unwrap().unwrap();
+        let after = collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap();
+        assert_eq!(after.len(), 1);
+        assert!(after[0].is_initialized());
+        assert_eq!(after[0].start_block(), exts[0].start_block());
+        assert_eq!(inode.blocks_count(), 4 * (BLOCK_SIZE / 512) as u64);
+        assert_eq!(read_file(&mut dev, &mut fs, "/db").unwrap(), payload);
+    }
+
+    #[test]
+    fn partial_write_splits_uninitialized_extent() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/db", None, None).unwrap();
+        fallocate(&mut dev, &mut fs, "/db", 0, 8 * BLOCK_SIZE as u64).unwrap();
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/db").unwrap().unwrap();
+        let base = collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap()[0].start_block();
+        for i in 0..8 {
+            scribble(&mut dev, base + i);
+        }
+
+        let off = 3 * BLOCK_SIZE as u64 + 10;
+        write_file(&mut dev, &mut fs, "/db", off, b"hello").unwrap();
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/db").unwrap().unwrap();
+        let exts = collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap();
+        let shape: Vec<(u32, u32, bool, u64)> = exts
+            .iter()
+            .map(|e| (e.ee_block, extent_block_count(e), e.is_initialized(), e.start_block()))
+            .collect();
+        assert_eq!(
+            shape,
+            vec![(0, 3, false, base), (3, 1, true, base + 3), (4, 4, false, base + 4)]
+        );
+        assert_eq!(inode.size(), 8 * BLOCK_SIZE as u64);
+        assert_eq!(inode.blocks_count(), 8 * (BLOCK_SIZE / 512) as u64);
+
+        // 写入块中未写到的字节和其余未初始化块都读出为 0
+        let data = read_file(&mut dev, &mut fs, "/db").unwrap();
+        assert_eq!(&data[off as usize..off as usize + 5], b"hello");
+        assert!(data[..off as usize].iter().all(|&b| b == 0));
+        assert!(data[off as usize + 5..].iter().all(|&b| b == 0));
+        assert_eq!(
+            resolve_inode_block(&mut dev, &fs, ino, &mut inode, 3).unwrap(),
+            Some(base + 3)
+        );
+
+        // 再写最后一块的末尾：右侧未初始化 extent 再拆一次
+        write_file(&mut dev, &mut fs, "/db", 8 * BLOCK_SIZE as u64 - 2, b"zz").unwrap();
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/db").unwrap().unwrap();
+        let exts = collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap();
+        assert_eq!(exts.len(), 4);
+        assert_eq!((exts[3].ee_block, extent_block_count(&exts[3])), (7, 1));
+        assert!(exts[3].is_initialized());
+        let data = read_file(&mut dev, &mut fs, "/db").unwrap();
+        assert_eq!(&data[data.len() - 2..], b"zz");
+        assert!(data[4 * BLOCK_SIZE..data.len() - 2].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn large_write_coalesces_device_writes() {
+        const SIZE: usize = 200 * 1024 * 1024;
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(64 * 1024), false, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let data: Vec<u8> = (0..SIZE).map(|i| (i / BLOCK_SIZE) as u8).collect();
+
+        let before = dev.with_raw_device(|d| Ok(d.writes)).unwrap();
+        mkfile(&mut dev, &mut fs, "/big", Some(&data), None).unwrap();
+        umount(fs, &mut dev).unwrap();
+        let writes = dev.with_raw_device(|d| Ok(d.writes)).unwrap() - before;
+
+        // 51200 个数据块按连续段写回，设备写调用次数远少于块数
+        let blocks = (SIZE / BLOCK_SIZE) as u32;
+        assert!(writes < blocks / 64, "{writes} device writes for {blocks} blocks");
+
+        let mut fs = mount(&mut dev).unwrap();
+        let back = read_file(&mut dev, &mut fs, "/big").unwrap();
+        assert!(back == data);
+    }
+
+    #[test]
+    fn public_api_reports_ext4_errors() {
+        use crate::ext4_backend::api::{open, OpenFlags};
+        use crate::ext4_backend::dir::mkdir;
+
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/f", Some(b"data"), None).unwrap();
+
+        let missing = Ext4Error::NotFound { index: 0, component: "missing".into() };
+        assert_eq!(read_file(&mut dev, &mut fs, "/missing"), Err(missing.clone()));
+        assert_eq!(find_file(&mut fs, &mut dev, "/missing").err(), Some(missing.clone()));
+        assert_eq!(
+            open(&mut dev, &mut fs, "/missing", OpenFlags::RDONLY).err(),
+            Some(missing)
+        );
+        assert_eq!(
+            mkdir(&mut dev, &mut fs, "/f/sub").err(),
+            Some(Ext4Error::NotADirectory { index: 0, component: "f".into() })
+        );
+        assert_eq!(read_file(&mut dev, &mut fs, "/f").unwrap(), b"data");
+
+        // 由块层错误转换：文件系统层面的错误有独立分类，其余原样包装
+        assert_eq!(Ext4Error::from(BlockDevError::NoSpace), Ext4Error::NoSpace);
+        assert_eq!(Ext4Error::from(BlockDevError::Corrupted), Ext4Error::Corrupt);
+        assert_eq!(Ext4Error::from(BlockDevError::ChecksumError), Ext4Error::ChecksumError);
+        assert_eq!(
+            Ext4Error::from(BlockDevError::IoError),
+            Ext4Error::Device(BlockDevError::IoError)
+        );
+        assert_eq!(
+            alloc::format!("{}", Ext4Error::NotFound { index: 1, component: "b".into() }),
+            "no such file or directory: component 1 \"b\""
+        );
+    }
+
+    #[test]
+    fn remove_all_deletes_deep_tree_and_reclaims_space() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/keep", Some(b"outside"), None).unwrap();
+        let free_blocks = fs.superblock.free_blocks_count();
+        let free_inodes = fs.superblock.s_free_inodes_count;
+        let root_links = fs.get_root(&mut dev).unwrap().i_links_count;
+
+        let mut dir = String::from("/t");
+        for depth in 0..5 {
+            mkdir(&mut dev, &mut fs, &dir).unwrap();
+            let big = vec![depth as u8; 3 * BLOCK_SIZE + 7];
+            mkfile(&mut dev, &mut fs, &format!("{dir}/big"), Some(&big), None).unwrap();
+            mkfile(&mut dev, &mut fs, &format!("{dir}/small"), Some(b"s"), None).unwrap();
+            mkdir(&mut dev, &mut fs, &format!("{dir}/empty")).unwrap();
+            dir = format!("{dir}/d{depth}");
+        }
+        // 指向树外的符号链接只删除链接本身；树内文件的硬链接在树外保留
+        symlink(&mut dev, &mut fs, "/keep", "/t/d0/d1/to_keep").unwrap();
+        link(&mut dev, &mut fs, "/t/d0/big", "/kept_link").unwrap();
+        assert!(fs.superblock.free_blocks_count() < free_blocks);
+
+        remove_all(&mut dev, &mut fs, "/t").unwrap();
+        assert!(matches!(fs.find_file(&mut dev, "/t"), Err(Ext4Error::NotFound { .. })));
+        assert_eq!(read_file(&mut dev, &mut fs, "/keep").unwrap(), b"outside");
+        let kept = fs.find_file(&mut dev, "/kept_link").unwrap();
+        assert_eq!(kept.i_links_count, 1);
+        assert_eq!(fs.get_root(&mut dev).unwrap().i_links_count, root_links);
+
+        unlink(&mut dev, &mut fs, "/kept_link").unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes);
+
+        // 普通文件直接 unlink；根目录不能删除
+        remove_all(&mut dev, &mut fs, "/keep").unwrap();
+        assert!(matches!(fs.find_file(&mut dev, "/keep"), Err(Ext4Error::NotFound { .. })));
+        assert_eq!(remove_all(&mut dev, &mut fs, "/").err(), Some(Ext4Error::PermissionDenied));
+        assert!(matches!(
+            remove_all(&mut dev, &mut fs, "/missing").err(),
+            Some(Ext4Error::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn remove_all_frees_every_block_of_a_fragmented_deep_file() {
+        let (mut dev, mut fs) = setup_fs(64 * 1024);
+        let free_blocks = fs.superblock.free_blocks_count();
+        let free_inodes = fs.superblock.s_free_inodes_count;
+
+        mkdir(&mut dev, &mut fs, "/t").unwrap();
+        mkdir(&mut dev, &mut fs, "/t/sub").unwrap();
+        mkfile(&mut dev, &mut fs, "/t/sub/frag", None, None).unwrap();
+        // 隔块写入，每块一个 extent；叶子块装满后分裂，树长到深度 2，每层都远超 4 个条目
+        let extents = 1000u64;
+        for i in 0..extents {
+            write_file(&mut dev, &mut fs, "/t/sub/frag", i * 2 * BLOCK_SIZE as u64, b"f").unwrap();
+        }
+        mkfile(&mut dev, &mut fs, "/t/small", Some(b"s"), None).unwrap();
+
+        let (ino, mut inode) = get_file_inode(&mut fs, &mut dev, "/t/sub/frag").unwrap().unwrap();
+        let root = ExtentTree::new(&mut inode).load_root_from_inode().unwrap();
+        assert!(root.header().eh_depth >= 2);
+        assert_eq!(collect_inode_extents(&mut dev, &fs, ino, &mut inode).unwrap().len(), extents as usize);
+        let tree_blocks = collect_extent_tree_blocks(&mut dev, &fs, ino, &mut inode).unwrap();
+        assert!(tree_blocks.len() > 4);
+
+        remove_all(&mut dev, &mut fs, "/t").unwrap();
+        assert!(matches!(fs.find_file(&mut dev, "/t"), Err(Ext4Error::NotFound { .. })));
+        assert_eq!(fs.superblock.free_blocks_count(), free_blocks);
+        assert_eq!(fs.superblock.s_free_inodes_count, free_inodes);
+        assert!(verify_free_counts(&mut dev, &mut fs).unwrap().is_empty());
+    }
+}
@@ -5,9 +5,11 @@
 
 use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::config::*;
+use crate::ext4_backend::dir::*;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::endian::*;
 use crate::ext4_backend::entries::*;
+use crate::ext4_backend::error::*;
 use crate::ext4_backend::ext4::*;
 use crate::ext4_backend::loopfile::*;
 
@@ -30,6 +32,8 @@ pub enum HashTreeError {
     BufferTooSmall,
     /// Entry not found
     EntryNotFound,
+    /// Directory block checksum mismatch
+    ChecksumError,
 }
 
 impl core::fmt::Display for HashTreeError {
@@ -41,6 +45,7 @@ impl core::fmt::Display for HashTreeError {
             HashTreeError::BlockOutOfRange => write!(f, "Block number out of range"),
             HashTreeError::BufferTooSmall => write!(f, "Buffer too small"),
             HashTreeError::EntryNotFound => write!(f, "Entry not found"),
+            HashTreeError::ChecksumError => write!(f, "Directory block checksum mismatch"),
         }
     }
 }
@@ -51,7 +56,7 @@ pub struct HashTreeSearchResult {
     /// Found directory entry
     pub entry: Ext4DirEntryInfo<'static>,
     /// Block number where entry is located
-    pub block_num: u32,
+    pub block_num: u64,
     /// Offset within the block
     pub offset: usize,
 }
@@ -59,11 +64,11 @@ pub struct HashTreeSearchResult {
 /// Hash tree manager
 pub struct HashTreeManager {
     /// Hash seed (from superblock)
-    hash_seed: [u32; 4],
+    pub hash_seed: [u32; 4],
     /// Hash version
-    hash_version: u8,
+    pub hash_version: u8,
     /// Number of indirect levels
-    indirect_levels: u8,
+    pub indirect_levels: u8,
 }
 
 impl HashTreeManager {
@@ -81,6 +86,7 @@ impl HashTreeManager {
         &self,
         fs: &mut Ext4FileSystem,
         block_dev: &mut Jbd2Dev<B>,
+        dir_ino: u32,
         dir_inode: &Ext4Inode,
         target_name: &[u8],
     ) -> Result<HashTreeSearchResult, HashTreeError> {
@@ -92,232 +98,30 @@ impl HashTreeManager {
         // 1. Check if directory has hash tree index enabled
         if !dir_inode.is_htree_indexed() {
            //warn!("Directory does not have hash tree index enabled, falling back to linear search");
-            return self.fallback_to_linear_search(fs, block_dev, dir_inode, target_name);
+            return self.fallback_to_linear_search(fs, block_dev, dir_ino, dir_inode, target_name);
         }
 
-        // 2. Calculate hash value of target filename
-        let target_hash =
-            htree_dir::calculate_hash(target_name, self.hash_version, &self.hash_seed);
-        debug!("Target hash value: 0x{target_hash:08x}");
-
-        // 3. Read root node
-        let root_block = self.get_root_block( block_dev, dir_inode)?;
-        let root_data = self.read_block_data(fs, block_dev, root_block)?;
-
-        // 4. Parse root node
-        let root_info = self.parse_root_node(&root_data)?;
-
-        // 5. Search in hash tree
-        match self.search_in_hash_tree(fs, block_dev, &root_info, target_hash, target_name) {
-            Ok(result) => Ok(result),
+        // 2. Walk dx_root/dx_node down to the single leaf that can hold the name
+        match dx_find_entry(fs, block_dev, dir_ino, dir_inode, target_name) {
+            Ok(Some((block_num, offset, inode, file_type))) => Ok(HashTreeSearchResult {
+                // The caller already knows the name; only inode and type are returned
+                entry: Ext4DirEntryInfo {
+                    inode,
+                    file_type,
+                    name: &[],
+                },
+                block_num,
+                offset,
+            }),
+            Ok(None) => Err(HashTreeError::EntryNotFound),
+            Err(BlockDevError::ChecksumError) => Err(HashTreeError::ChecksumError),
             Err(e) => {
                 warn!(
                     "Hash tree lookup failed: {e}, falling back to linear search"
                 );
-                self.fallback_to_linear_search(fs, block_dev, dir_inode, target_name)
-            }
-        }
-    }
-
-    /// Get hash tree root block number
-    fn get_root_block<B: BlockDevice>(
-        &self,
-        block_dev: &mut Jbd2Dev<B>,
-        dir_inode: &Ext4Inode,
-    ) -> Result<u32, HashTreeError> {
-        // Root block is usually the first data block of the directory
-        match resolve_inode_block(block_dev, &mut dir_inode.clone(), 0) {
-            Ok(Some(block)) => Ok(block),
-            Ok(None) => Err(HashTreeError::InvalidHashTree),
-            Err(_) => Err(HashTreeError::BlockOutOfRange),
-        }
-    }
-
-    /// Read block data
-    fn read_block_data<B: BlockDevice>(
-        &self,
-        fs: &mut Ext4FileSystem,
-        block_dev: &mut Jbd2Dev<B>,
-        block_num: u32,
-    ) -> Result<Vec<u8>, HashTreeError> {
-        match fs.datablock_cache.get_or_load(block_dev, block_num as u64) {
-            Ok(cached_block) => Ok(cached_block.data.clone()),
-            Err(_) => Err(HashTreeError::BlockOutOfRange),
-        }
-    }
-
-    /// Parse root node
-    fn parse_root_node(&self, data: &[u8]) -> Result<HashTreeNode, HashTreeError> {
-        if data.len() < core::mem::size_of::<Ext4DxRoot>() {
-            return Err(HashTreeError::BufferTooSmall);
-        }
-
-        // Parse root node info
-        let dot = Ext4DirEntryInfo::parse_from_bytes(&data[0..8])
-            .ok_or(HashTreeError::CorruptedHashTree)?;
-
-        let dotdot = Ext4DirEntryInfo::parse_from_bytes(&data[dot.inode as usize..])
-            .ok_or(HashTreeError::CorruptedHashTree)?;
-
-        // Extract root info
-        let info_offset = dot.inode as usize + dotdot.inode as usize;
-        if info_offset + core::mem::size_of::<Ext4DxRootInfo>() > data.len() {
-            return Err(HashTreeError::CorruptedHashTree);
-        }
-
-        let info_bytes = &data[info_offset..info_offset + core::mem::size_of::<Ext4DxRootInfo>()];
-        let hash_version = info_bytes[5]; // hash_version field is at offset 5
-        let indirect_levels = info_bytes[6]; // indirect_levels field is at offset 6
-
-        // Parse hash entries
-        let entries_offset = info_offset + core::mem::size_of::<Ext4DxRootInfo>();
-        let entries = self.parse_dx_entries(&data[entries_offset..])?;
-
-        Ok(HashTreeNode::Root {
-            hash_version,
-            indirect_levels,
-            entries,
-        })
-    }
-
-    /// Parse DX entry array
-    fn parse_dx_entries(&self, data: &[u8]) -> Result<Vec<Ext4DxEntry>, HashTreeError> {
-        let mut entries = Vec::new();
-        let mut offset = 0;
-
-        while offset + core::mem::size_of::<Ext4DxEntry>() <= data.len() {
-            let hash = read_u32_le(&data[offset..offset + 4]);
-            let block = read_u32_le(&data[offset + 4..offset + 8]);
-
-            if block == 0 {
-                break;
+                self.fallback_to_linear_search(fs, block_dev, dir_ino, dir_inode, target_name)
             }
-
-            entries.push(Ext4DxEntry { hash, block });
-            offset += core::mem::size_of::<Ext4DxEntry>();
         }
-
-        Ok(entries)
-    }
-
-    /// Search in hash tree
-    fn search_in_hash_tree<B: BlockDevice>(
-        &self,
-        fs: &mut Ext4FileSystem,
-        block_dev: &mut Jbd2Dev<B>,
-        node: &HashTreeNode,
-        target_hash: u32,
-        target_name: &[u8],
-    ) -> Result<HashTreeSearchResult, HashTreeError> {
-        match node {
-            HashTreeNode::Root { entries, .. } => {
-                self.search_in_entries(fs, block_dev, entries, target_hash, target_name, 0)
-            }
-            HashTreeNode::Internal { entries, .. } => {
-                self.search_in_entries(fs, block_dev, entries, target_hash, target_name, 0)
-            }
-            HashTreeNode::Leaf { block_num, .. } => {
-                self.search_in_leaf_block(fs, block_dev, *block_num, target_name)
-            }
-        }
-    }
-
-    /// Search in entry list
-    fn search_in_entries<B: BlockDevice>(
-        &self,
-        fs: &mut Ext4FileSystem,
-        block_dev: &mut Jbd2Dev<B>,
-        entries: &[Ext4DxEntry],
-        target_hash: u32,
-        target_name: &[u8],
-        level: u32,
-    ) -> Result<HashTreeSearchResult, HashTreeError> {
-        // Find appropriate entry (largest entry with hash <= target hash)
-        let mut selected_entry = None;
-        for entry in entries {
-            if entry.hash <= target_hash {
-                selected_entry = Some(entry);
-            } else {
-                break;
-            }
-        }
-
-        let entry = selected_entry.ok_or(HashTreeError::EntryNotFound)?;
-
-        // Read target block
-        let block_data = self.read_block_data(fs, block_dev, entry.block)?;
-
-        // Check if this is a leaf node
-        if level >= self.indirect_levels as u32 {
-            // Leaf node, search for specific directory entries within it
-            self.search_in_leaf_data(&block_data, target_name, entry.block)
-        } else {
-            // Internal node, recursive search
-            let internal_node = self.parse_internal_node(&block_data)?;
-            self.search_in_hash_tree(fs, block_dev, &internal_node, target_hash, target_name)
-        }
-    }
-
-    /// Search in leaf block
-    fn search_in_leaf_block<B: BlockDevice>(
-        &self,
-        fs: &mut Ext4FileSystem,
-        block_dev: &mut Jbd2Dev<B>,
-        block_num: u32,
-        target_name: &[u8],
-    ) -> Result<HashTreeSearchResult, HashTreeError> {
-        let block_data = self.read_block_data(fs, block_dev, block_num)?;
-        self.search_in_leaf_data(&block_data, target_name, block_num)
-    }
-
-    /// Search in leaf data
-    fn search_in_leaf_data(
-        &self,
-        data: &[u8],
-        target_name: &[u8],
-        block_num: u32,
-    ) -> Result<HashTreeSearchResult, HashTreeError> {
-        let iter = DirEntryIterator::new(data);
-
-        for (entry, offset) in iter {
-            if entry.name == target_name {
-                return Ok(HashTreeSearchResult {
-                    entry: unsafe { core::mem::transmute(entry) },
-                    block_num,
-                    offset: offset as usize,
-                });
-            }
-        }
-
-        Err(HashTreeError::EntryNotFound)
-    }
-
-    /// Parse internal node
-    fn parse_internal_node(&self, data: &[u8]) -> Result<HashTreeNode, HashTreeError> {
-        if data.len() < core::mem::size_of::<Ext4DxNode>() {
-            return Err(HashTreeError::BufferTooSmall);
-        }
-
-        // Skip fake directory entries
-        let fake_entry_size = core::mem::size_of::<Ext4DirEntry2>();
-        let countlimit_offset = fake_entry_size;
-
-        if countlimit_offset + core::mem::size_of::<Ext4DxCountlimit>() > data.len() {
-            return Err(HashTreeError::CorruptedHashTree);
-        }
-
-        let countlimit_bytes =
-            &data[countlimit_offset..countlimit_offset + core::mem::size_of::<Ext4DxCountlimit>()];
-        let _count = read_u16_le(&countlimit_bytes[2..4]) as usize; // count field is at offset 2
-
-        // Parse entries
-        let entries_offset = countlimit_offset + core::mem::size_of::<Ext4DxCountlimit>();
-        let entries = self.parse_dx_entries(&data[entries_offset..])?;
-
-        Ok(HashTreeNode::Internal {
-            entries,
-            level: 0, // Should actually read from node
-        })
     }
 
     /// Fall back to linear search
@@ -325,6 +129,7 @@ impl HashTreeManager {
         &self,
         fs: &mut Ext4FileSystem,
         block_dev: &mut Jbd2Dev<B>,
+        dir_ino: u32,
         dir_inode: &Ext4Inode,
         target_name: &[u8],
     ) -> Result<HashTreeSearchResult, HashTreeError> {
@@ -341,10 +146,13 @@ impl HashTreeManager {
             total_size.div_ceil(block_bytes)
         };
 
+        let seed = dir_csum_seed(fs, dir_ino, dir_inode);
+        let policy = fs.checksum_policy;
+
         // Fast path for extent-based directories: resolve all blocks once, then scan.
         if dir_inode.have_extend_header_and_use_extend() {
             let mut inode_clone = dir_inode.clone();
-            let blocks_map = match resolve_inode_block_allextend(fs, block_dev, &mut inode_clone) {
+            let blocks_map = match resolve_inode_block_allextend(fs, block_dev, dir_ino, &mut inode_clone) {
                 Ok(v) => v,
                 Err(_) => return Err(HashTreeError::BlockOutOfRange),
             };
@@ -361,11 +169,22 @@ impl HashTreeManager {
                 };
 
                 let block_data = &cached_block.data[..block_bytes];
-                if let Some(entry) = classic_dir::find_entry(block_data, target_name) {
+                let csum_ok = seed.is_none_or(|seed| dir_csum::verify(seed, block_data));
+                if policy.check(csum_ok, "directory block").is_err() {
+                    return Err(HashTreeError::ChecksumError);
+                }
+                let found = classic_dir::find_entry_with_offset(block_data, target_name)
+                    .map_err(|_| HashTreeError::CorruptedHashTree)?;
+                if let Some((entry, offset)) = found {
                     return Ok(HashTreeSearchResult {
-                        entry: unsafe { core::mem::transmute(entry) },
-                        block_num: phys as u32,
-                        offset: 0,
+                        // name borrows the cached block; as on the dx path only inode and type are returned
+                        entry: Ext4DirEntryInfo {
+                            inode: entry.inode,
+                            file_type: entry.file_type,
+                            name: &[],
+                        },
+                        block_num: phys,
+                        offset,
                     });
                 }
             }
@@ -394,7 +213,7 @@ pub enum HashTreeNode {
     },
     /// Leaf node
     Leaf {
-        block_num: u32,
+        block_num: u64,
         entries: Vec<Ext4DirEntryInfo<'static>>,
     },
 }
@@ -436,11 +255,528 @@ pub fn create_hash_tree_manager(fs: &Ext4FileSystem) -> HashTreeManager {
 pub fn lookup_directory_entry<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
     dir_inode: &Ext4Inode,
     target_name: &[u8],
 ) -> Result<HashTreeSearchResult, HashTreeError> {
     let manager = create_hash_tree_manager(fs);
-    manager.lookup(fs, block_dev, dir_inode, target_name)
+    manager.lookup(fs, block_dev, dir_ino, dir_inode, target_name)
+}
+
+/// Offset of dx_root_info inside the root block ("." is 12 bytes, ".." header 12 bytes)
+pub(crate) const DX_ROOT_INFO_OFF: usize = 24;
+/// Offset of countlimit / dx entries inside the root block
+pub(crate) const DX_ROOT_ENTRIES_OFF: usize = 32;
+/// Offset of countlimit / dx entries inside a dx_node (after the 8-byte fake dirent)
+const DX_NODE_ENTRIES_OFF: usize = 8;
+/// Maximum number of dx entries in the root block
+pub const DX_ROOT_LIMIT: u16 = ((BLOCK_SIZE - DX_ROOT_ENTRIES_OFF) / 8) as u16;
+/// Maximum number of dx entries in an internal node
+pub const DX_NODE_LIMIT: u16 = ((BLOCK_SIZE - DX_NODE_ENTRIES_OFF) / 8) as u16;
+/// Supported internal node levels (same as the kernel without largedir)
+const DX_MAX_INDIRECT_LEVELS: u8 = 1;
+/// Low bit of an index hash: the leaf continues a hash collision from the previous leaf
+const DX_HASH_CONTINUED: u32 = 1;
+/// Superblock s_flags bit: names are hashed as unsigned chars
+const EXT2_FLAGS_UNSIGNED_HASH: u32 = 0x0002;
+
+/// One level of the index path from dx_root to the leaf
+#[derive(Debug, Clone)]
+pub struct DxFrame {
+    /// Logical block of this index block (0 for the root)
+    pub lbn: u32,
+    /// countlimit.limit
+    pub limit: u16,
+    /// All dx entries; entry 0 has an implicit hash of 0
+    pub entries: Vec<Ext4DxEntry>,
+    /// Index of the entry covering the probed hash
+    pub at: usize,
+}
+
+/// Result of probing the index for one name
+#[derive(Debug, Clone)]
+pub struct DxPath {
+    /// Effective hash version (unsigned variant already applied)
+    pub hash_version: u8,
+    /// Hash of the probed name
+    pub hash: u32,
+    /// Frames from the root down to the lowest index block
+    pub frames: Vec<DxFrame>,
+}
+
+/// Read countlimit and the dx entries that follow it.
+/// The hash slot of entry 0 is occupied by countlimit and is treated as 0.
+pub fn read_dx_entries(data: &[u8], off: usize) -> BlockDevResult<(u16, Vec<Ext4DxEntry>)> {
+    if off + 4 > data.len() {
+        return Err(BlockDevError::Corrupted);
+    }
+    let limit = read_u16_le(&data[off..off + 2]);
+    let count = read_u16_le(&data[off + 2..off + 4]);
+    if count == 0 || count > limit || off + limit as usize * 8 > data.len() {
+        return Err(BlockDevError::Corrupted);
+    }
+    let entries = (0..count as usize)
+        .map(|i| {
+            let p = off + i * 8;
+            Ext4DxEntry {
+                hash: if i == 0 { 0 } else { read_u32_le(&data[p..p + 4]) },
+                block: read_u32_le(&data[p + 4..p + 8]),
+            }
+        })
+        .collect();
+    Ok((limit, entries))
+}
+
+/// Write countlimit and dx entries (only the block number of entry 0 is stored)
+pub fn write_dx_entries(data: &mut [u8], off: usize, limit: u16, entries: &[Ext4DxEntry]) {
+    write_u16_le(limit, &mut data[off..off + 2]);
+    write_u16_le(entries.len() as u16, &mut data[off + 2..off + 4]);
+    for (i, e) in entries.iter().enumerate() {
+        let p = off + i * 8;
+        if i > 0 {
+            write_u32_le(e.hash, &mut data[p..p + 4]);
+        }
+        write_u32_le(e.block, &mut data[p + 4..p + 8]);
+    }
+}
+
+/// Hash version actually used for a directory: switch to the unsigned
+/// variant when the superblock says names are hashed as unsigned chars
+fn dx_hash_version(fs: &Ext4FileSystem, version: u8) -> u8 {
+    if version <= Ext4DxRootInfo::DX_HASH_TEA
+        && fs.superblock.s_flags & EXT2_FLAGS_UNSIGNED_HASH != 0
+    {
+        version + 3
+    } else {
+        version
+    }
+}
+
+/// Physical block of directory logical block lbn
+fn dir_block_phys<B: BlockDevice>(
+    fs: &Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &Ext4Inode,
+    lbn: u32,
+) -> BlockDevResult<u64> {
+    resolve_inode_block(block_dev, fs, dir_ino, &mut dir_inode.clone(), lbn)?
+        .ok_or(BlockDevError::Corrupted)
+}
+
+/// Read directory logical block lbn through the data block cache,
+/// verifying its checksum when seed is given
+fn read_dir_block<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &Ext4Inode,
+    seed: Option<u32>,
+    lbn: u32,
+) -> BlockDevResult<(u64, Vec<u8>)> {
+    let phys = dir_block_phys(fs, block_dev, dir_ino, dir_inode, lbn)?;
+    let data = fs.datablock_cache.get_or_load(block_dev, phys)?.data.clone();
+    check_dir_block(fs, seed, &data)?;
+    Ok((phys, data))
+}
+
+/// Walk from dx_root down the index, binary searching each level for the name's hash
+pub fn dx_probe<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &Ext4Inode,
+    name: &[u8],
+) -> BlockDevResult<DxPath> {
+    let seed = dir_csum_seed(fs, dir_ino, dir_inode);
+    let (_, root) = read_dir_block(fs, block_dev, dir_ino, dir_inode, seed, 0)?;
+    let info = &root[DX_ROOT_INFO_OFF..DX_ROOT_ENTRIES_OFF];
+    let (version, info_length, levels) = (info[4], info[5], info[6]);
+    if info_length != 8 || levels > DX_MAX_INDIRECT_LEVELS {
+        return Err(BlockDevError::Corrupted);
+    }
+    let hash_version = dx_hash_version(fs, version);
+    let hash = htree_dir::calculate_hash(name, hash_version, &fs.superblock.s_hash_seed);
+
+    let mut frames = Vec::new();
+    let mut lbn = 0;
+    let (mut limit, mut entries) = read_dx_entries(&root, DX_ROOT_ENTRIES_OFF)?;
+    loop {
+        // entries[0] has hash 0, so at least one entry is <= hash
+        let at = entries.partition_point(|e| e.hash <= hash) - 1;
+        let child = entries[at].block;
+        frames.push(DxFrame {
+            lbn,
+            limit,
+            entries,
+            at,
+        });
+        if frames.len() > levels as usize {
+            break;
+        }
+        let (_, node) = read_dir_block(fs, block_dev, dir_ino, dir_inode, seed, child)?;
+        (limit, entries) = read_dx_entries(&node, DX_NODE_ENTRIES_OFF)?;
+        lbn = child;
+    }
+    Ok(DxPath {
+        hash_version,
+        hash,
+        frames,
+    })
+}
+
+/// Logical blocks of all index blocks of an indexed directory with their countlimit offsets,
+/// root first. Checksums are not verified, so this also works while enabling metadata_csum.
+pub fn dx_index_blocks<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &Ext4Inode,
+) -> BlockDevResult<Vec<(u32, usize)>> {
+    let (_, root) = read_dir_block(fs, block_dev, dir_ino, dir_inode, None, 0)?;
+    let levels = root[DX_ROOT_INFO_OFF + 6];
+    if levels > DX_MAX_INDIRECT_LEVELS {
+        return Err(BlockDevError::Corrupted);
+    }
+    let mut out = Vec::new();
+    out.push((0, DX_ROOT_ENTRIES_OFF));
+    if levels > 0 {
+        let (_, entries) = read_dx_entries(&root, DX_ROOT_ENTRIES_OFF)?;
+        out.extend(entries.iter().map(|e| (e.block, DX_NODE_ENTRIES_OFF)));
+    }
+    Ok(out)
+}
+
+/// Look up a name through the index; returns (physical block, offset, inode, file type).
+/// If the name is not in the leaf, the next leaf of the same index block is searched
+/// as long as its start hash carries the collision bit for the same hash.
+pub fn dx_find_entry<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &Ext4Inode,
+    name: &[u8],
+) -> BlockDevResult<Option<(u64, usize, u32, u8)>> {
+    let seed = dir_csum_seed(fs, dir_ino, dir_inode);
+    let path = dx_probe(fs, block_dev, dir_ino, dir_inode, name)?;
+    let frame = &path.frames[path.frames.len() - 1];
+    let mut at = frame.at;
+    loop {
+        let (phys, data) = read_dir_block(fs, block_dev, dir_ino, dir_inode, seed, frame.entries[at].block)?;
+        if let Some((entry, offset)) = classic_dir::find_entry_with_offset(&data, name)? {
+            return Ok(Some((phys, offset, entry.inode, entry.file_type)));
+        }
+        at += 1;
+        match frame.entries.get(at) {
+            Some(next)
+                if next.hash & DX_HASH_CONTINUED != 0
+                    && next.hash & !DX_HASH_CONTINUED == path.hash => {}
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Live entries of a leaf block together with their hashes ("." and ".." excluded).
+/// A bad rec_len is reported as Corrupted so a split never drops the entries behind it
+fn hashed_leaf_entries(
+    data: &[u8],
+    hash_version: u8,
+    seed: &[u32; 4],
+) -> BlockDevResult<Vec<(u32, Ext4DirEntry2)>> {
+    let mut iter = DirEntryIterator::new(data);
+    let entries = iter
+        .by_ref()
+        .filter(|(e, _)| !e.is_dot() && !e.is_dotdot())
+        .map(|(e, _)| {
+            let entry = Ext4DirEntry2::new(
+                e.inode,
+                Ext4DirEntry2::entry_len(e.name.len() as u8),
+                e.file_type,
+                e.name,
+            );
+            (htree_dir::calculate_hash(e.name, hash_version, seed), entry)
+        })
+        .collect();
+    match iter.error() {
+        Some(e) => Err(e),
+        None => Ok(entries),
+    }
+}
+
+/// Hash a new entry the same way as the entries already in the tree
+fn hashed_entry(entry: &Ext4DirEntry2, hash_version: u8, seed: &[u32; 4]) -> (u32, Ext4DirEntry2) {
+    let name = &entry.name[..entry.name_len as usize];
+    (htree_dir::calculate_hash(name, hash_version, seed), *entry)
+}
+
+/// Split hash-sorted entries at half of their total size.
+/// Returns the split index and the start hash of the right half,
+/// with the collision bit set when the hash straddles both halves.
+fn dx_split_point(entries: &[(u32, Ext4DirEntry2)]) -> (usize, u32) {
+    let size = |e: &Ext4DirEntry2| Ext4DirEntry2::entry_len(e.name_len) as usize;
+    let total: usize = entries.iter().map(|(_, e)| size(e)).sum();
+    let mut acc = 0;
+    let mut split = entries.len() - 1;
+    for (i, (_, e)) in entries.iter().enumerate() {
+        acc += size(e);
+        if acc >= total / 2 {
+            split = i + 1;
+            break;
+        }
+    }
+    let split = split.clamp(1, entries.len() - 1);
+    let hash = entries[split].0;
+    if entries[split - 1].0 == hash {
+        (split, hash | DX_HASH_CONTINUED)
+    } else {
+        (split, hash)
+    }
+}
+
+/// Rewrite a leaf block with the given entries packed from the start;
+/// the last entry's rec_len extends to the end of the block, or to the
+/// checksum tail when seed is given
+fn write_leaf(data: &mut [u8], entries: &[(u32, Ext4DirEntry2)], seed: Option<u32>) {
+    data.fill(0);
+    let end = if seed.is_some() {
+        BLOCK_SIZE - dir_csum::DIR_TAIL_SIZE
+    } else {
+        BLOCK_SIZE
+    };
+    if entries.is_empty() {
+        write_u16_le(end as u16, &mut data[4..6]);
+    }
+    let mut off = 0;
+    for (i, (_, e)) in entries.iter().enumerate() {
+        let len = Ext4DirEntry2::entry_len(e.name_len) as usize;
+        let mut entry = *e;
+        entry.rec_len = if i + 1 == entries.len() { end - off } else { len } as u16;
+        entry.to_disk_bytes(&mut data[off..off + 8]);
+        let nlen = e.name_len as usize;
+        data[off + 8..off + 8 + nlen].copy_from_slice(&e.name[..nlen]);
+        off += len;
+    }
+    if let Some(seed) = seed {
+        dir_csum::init_tail(data);
+        dir_csum::refresh(seed, data);
+    }
+}
+
+/// Entry limit of a new index block: one slot less when it carries a dx_tail
+fn dx_new_limit(countlimit_off: usize, seed: Option<u32>) -> u16 {
+    match seed {
+        Some(_) => dir_csum::dx_limit(countlimit_off, BLOCK_SIZE),
+        None => ((BLOCK_SIZE - countlimit_off) / 8) as u16,
+    }
+}
+
+/// Recompute a directory block checksum when seed is given
+fn refresh_csum(data: &mut [u8], seed: Option<u32>) {
+    if let Some(seed) = seed {
+        dir_csum::refresh(seed, data);
+    }
+}
+
+/// Write an internal index node: a fake empty dirent spanning the block, then dx entries
+fn write_dx_node(data: &mut [u8], entries: &[Ext4DxEntry], seed: Option<u32>) {
+    data.fill(0);
+    write_u16_le(BLOCK_SIZE as u16, &mut data[4..6]);
+    let limit = dx_new_limit(DX_NODE_ENTRIES_OFF, seed);
+    write_dx_entries(data, DX_NODE_ENTRIES_OFF, limit, entries);
+    refresh_csum(data, seed);
+}
+
+/// Write dx_root: "." and "..", dx_root_info with zero indirect levels, then dx entries
+fn write_dx_root(
+    data: &mut [u8],
+    self_ino: u32,
+    parent_ino: u32,
+    hash_version: u8,
+    entries: &[Ext4DxEntry],
+    seed: Option<u32>,
+) {
+    data.fill(0);
+    let dot = Ext4DirEntry2::new(self_ino, 12, Ext4DirEntry2::EXT4_FT_DIR, b".");
+    dot.to_disk_bytes(&mut data[0..8]);
+    data[8] = b'.';
+    let dotdot = Ext4DirEntry2::new(parent_ino, (BLOCK_SIZE - 12) as u16, Ext4DirEntry2::EXT4_FT_DIR, b"..");
+    dotdot.to_disk_bytes(&mut data[12..20]);
+    data[20..22].copy_from_slice(b"..");
+    data[DX_ROOT_INFO_OFF + 4] = hash_version;
+    data[DX_ROOT_INFO_OFF + 5] = 8;
+    let limit = dx_new_limit(DX_ROOT_ENTRIES_OFF, seed);
+    write_dx_entries(data, DX_ROOT_ENTRIES_OFF, limit, entries);
+    refresh_csum(data, seed);
+}
+
+/// Write back the dx entries of index block lbn (the root when lbn is 0)
+fn write_dx_index<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &Ext4Inode,
+    lbn: u32,
+    limit: u16,
+    entries: &[Ext4DxEntry],
+) -> BlockDevResult<()> {
+    let off = if lbn == 0 { DX_ROOT_ENTRIES_OFF } else { DX_NODE_ENTRIES_OFF };
+    let seed = dir_csum_seed(fs, dir_ino, dir_inode);
+    let phys = dir_block_phys(fs, block_dev, dir_ino, dir_inode, lbn)?;
+    fs.datablock_cache.modify(block_dev, phys, |data| {
+        write_dx_entries(data, off, limit, entries);
+        refresh_csum(data, seed);
+    })
+}
+
+/// Convert a linear directory whose single block is full into an htree directory
+/// and insert new_entry: block 0 becomes dx_root, and the existing entries plus the
+/// new one are sorted by hash and split across two new leaf blocks
+pub fn make_indexed_dir<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    new_entry: &Ext4DirEntry2,
+) -> BlockDevResult<()> {
+    let version = fs.superblock.s_def_hash_version;
+    let hash_version = dx_hash_version(fs, version);
+    let seed = fs.superblock.s_hash_seed;
+    let csum_seed = dir_csum_seed(fs, dir_ino, dir_inode);
+    let (root_phys, block0) = read_dir_block(fs, block_dev, dir_ino, dir_inode, csum_seed, 0)?;
+    let parent_ino = DirEntryIterator::new(&block0)
+        .find(|(e, _)| e.is_dotdot())
+        .map_or(dir_ino, |(e, _)| e.inode);
+
+    let mut entries = hashed_leaf_entries(&block0, hash_version, &seed)?;
+    entries.push(hashed_entry(new_entry, hash_version, &seed));
+    entries.sort_by_key(|(hash, _)| *hash);
+    let (split, split_hash) = dx_split_point(&entries);
+
+    dir_inode.i_flags |= Ext4Inode::EXT4_INDEX_FL;
+    let (left_lbn, left_phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+    let (right_lbn, right_phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+    fs.datablock_cache
+        .modify(block_dev, left_phys, |data| write_leaf(data, &entries[..split], csum_seed))?;
+    fs.datablock_cache
+        .modify(block_dev, right_phys, |data| write_leaf(data, &entries[split..], csum_seed))?;
+    let index = [
+        Ext4DxEntry {
+            hash: 0,
+            block: left_lbn,
+        },
+        Ext4DxEntry {
+            hash: split_hash,
+            block: right_lbn,
+        },
+    ];
+    fs.datablock_cache.modify(block_dev, root_phys, |data| {
+        write_dx_root(data, dir_ino, parent_ino, version, &index, csum_seed)
+    })
+}
+
+/// Make room in the lowest index block before a leaf split.
+/// A full root without internal nodes moves its entries down into a new node;
+/// a full internal node is split in half and the new node is added to the root.
+fn dx_grow_index<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    frames: &[DxFrame],
+) -> BlockDevResult<()> {
+    let seed = dir_csum_seed(fs, dir_ino, dir_inode);
+    let root = &frames[0];
+    if frames.len() == 1 {
+        let (node_lbn, node_phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+        fs.datablock_cache
+            .modify(block_dev, node_phys, |data| write_dx_node(data, &root.entries, seed))?;
+        let root_phys = dir_block_phys(fs, block_dev, dir_ino, dir_inode, 0)?;
+        return fs.datablock_cache.modify(block_dev, root_phys, |data| {
+            data[DX_ROOT_INFO_OFF + 6] = 1;
+            let entry = Ext4DxEntry {
+                hash: 0,
+                block: node_lbn,
+            };
+            write_dx_entries(data, DX_ROOT_ENTRIES_OFF, root.limit, &[entry]);
+            refresh_csum(data, seed);
+        });
+    }
+
+    if root.entries.len() >= root.limit as usize {
+        error!("htree index of directory {dir_ino} is full");
+        return Err(BlockDevError::NoSpace);
+    }
+    let node = &frames[1];
+    let half = node.entries.len() / 2;
+    let right = &node.entries[half..];
+    let (new_lbn, new_phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+    fs.datablock_cache
+        .modify(block_dev, new_phys, |data| write_dx_node(data, right, seed))?;
+    write_dx_index(fs, block_dev, dir_ino, dir_inode, node.lbn, node.limit, &node.entries[..half])?;
+    let mut root_entries = root.entries.clone();
+    root_entries.insert(
+        root.at + 1,
+        Ext4DxEntry {
+            hash: right[0].hash,
+            block: new_lbn,
+        },
+    );
+    write_dx_index(fs, block_dev, dir_ino, dir_inode, 0, root.limit, &root_entries)
+}
+
+/// Insert a directory entry into an htree directory.
+/// A full leaf is split in half by hash; a full index block is grown first.
+pub fn dx_insert_entry<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    dir_ino: u32,
+    dir_inode: &mut Ext4Inode,
+    new_entry: &Ext4DirEntry2,
+) -> BlockDevResult<()> {
+    let name = &new_entry.name[..new_entry.name_len as usize];
+    let seed = fs.superblock.s_hash_seed;
+    let csum_seed = dir_csum_seed(fs, dir_ino, dir_inode);
+    loop {
+        let path = dx_probe(fs, block_dev, dir_ino, dir_inode, name)?;
+        let frame = &path.frames[path.frames.len() - 1];
+        let leaf_phys = dir_block_phys(fs, block_dev, dir_ino, dir_inode, frame.entries[frame.at].block)?;
+        let mut inserted = false;
+        fs.datablock_cache.modify(block_dev, leaf_phys, |data| {
+            inserted = classic_dir::insert_entry(data, new_entry);
+            if inserted {
+                refresh_csum(data, csum_seed);
+            }
+        })?;
+        if inserted {
+            return Ok(());
+        }
+
+        if frame.entries.len() >= frame.limit as usize {
+            dx_grow_index(fs, block_dev, dir_ino, dir_inode, &path.frames)?;
+            continue;
+        }
+
+        let leaf = fs.datablock_cache.get_or_load(block_dev, leaf_phys)?.data.clone();
+        let mut entries = hashed_leaf_entries(&leaf, path.hash_version, &seed)?;
+        entries.push(hashed_entry(new_entry, path.hash_version, &seed));
+        entries.sort_by_key(|(hash, _)| *hash);
+        let (split, split_hash) = dx_split_point(&entries);
+
+        let (new_lbn, new_phys) = append_dir_block(fs, block_dev, dir_ino, dir_inode)?;
+        fs.datablock_cache
+            .modify(block_dev, leaf_phys, |data| write_leaf(data, &entries[..split], csum_seed))?;
+        fs.datablock_cache
+            .modify(block_dev, new_phys, |data| write_leaf(data, &entries[split..], csum_seed))?;
+        let mut index = frame.entries.clone();
+        index.insert(
+            frame.at + 1,
+            Ext4DxEntry {
+                hash: split_hash,
+                block: new_lbn,
+            },
+        );
+        return write_dx_index(fs, block_dev, dir_ino, dir_inode, frame.lbn, frame.limit, &index);
+    }
 }
 
 #[cfg(test)]
@@ -478,7 +814,7 @@ use crate::ext4_backend::error::BlockDevError;
 
             if end > self.data.len() {
                 return Err(BlockDevError::BlockOutOfRange {
-                    block_id,
+                    block_id: block_id as u64,
                     max_blocks: (self.data.len() / 512) as u64,
                 });
             }
@@ -497,7 +833,7 @@ use crate::ext4_backend::error::BlockDevError;
 
             if end > self.data.len() {
                 return Err(BlockDevError::BlockOutOfRange {
-                    block_id,
+                    block_id: block_id as u64,
                     max_blocks: (self.data.len() / 512) as u64,
                 });
             }
@@ -526,6 +862,7 @@ use crate::ext4_backend::error::BlockDevError;
         use crate::ext4_backend::superblock::Ext4Superblock;
         use crate::ext4_backend::inodetable_cache::InodeCache;
         use crate::ext4_backend::datablock_cache::DataBlockCache;
+        use crate::ext4_backend::dentry_cache::DentryCache;
         use crate::ext4_backend::bitmap_cache::BitmapCache;
         use crate::ext4_backend::bmalloc::*;
         let mut superblock = Ext4Superblock::default();
@@ -545,10 +882,26 @@ use crate::ext4_backend::error::BlockDevError;
             bitmap_cache: BitmapCache::new(100),
             inodetable_cahce: InodeCache::new(100, inode_size),
             datablock_cache: DataBlockCache::new(100, 4096),
+            dentry_cache: DentryCache::new(100),
             root_inode: 2,
             group_count: 1,
             mounted: true,
             journal_sb_block_start: None,
+            inode_alloc_policy: InodeAllocPolicy::default(),
+            read_only: false,
+            open_inodes: alloc::collections::BTreeMap::new(),
+            orphan_inodes: alloc::collections::BTreeSet::new(),
+            now: frozen_clock,
+            noatime: false,
+            online_discard: false,
+            pending_discards: Vec::new(),
+            readahead_blocks: 0,
+            reserved_inodes: 0,
+            drawing_reserved_inodes: false,
+            checksum_policy: ChecksumPolicy::default(),
+            gdt_backup_policy: GdtBackupPolicy::default(),
+            gdt_backups_stale: false,
+            cache_capacities: crate::ext4_backend::block_cache::CacheCapacities::default(),
         }
     }
 
@@ -648,26 +1001,32 @@ use crate::ext4_backend::error::BlockDevError;
 
     #[test]
     fn test_dx_entry_parsing() {
-        let fs = create_test_fs();
-        let manager = create_hash_tree_manager(&fs);
-
-        // Create test data: two DX entries
-        let mut test_data = Vec::new();
-        test_data.resize(16, 0);
-        // First entry: hash=0x12345678, block=1
-        test_data[0..4].copy_from_slice(&0x12345678u32.to_le_bytes());
-        test_data[4..8].copy_from_slice(&1u32.to_le_bytes());
-        // Second entry: hash=0x87654321, block=2
-        test_data[8..12].copy_from_slice(&0x87654321u32.to_le_bytes());
-        test_data[12..16].copy_from_slice(&2u32.to_le_bytes());
-
-        let entries = manager.parse_dx_entries(&test_data).unwrap();
-
-        assert_eq!(entries.len(), 2);
-        assert_eq!(entries[0].hash, 0x12345678);
-        assert_eq!(entries[0].block, 1);
-        assert_eq!(entries[1].hash, 0x87654321);
-        assert_eq!(entries[1].block, 2);
+        let mut test_data = [0u8; 32];
+        let entries = [
+            Ext4DxEntry { hash: 0, block: 1 },
+            Ext4DxEntry {
+                hash: 0x87654320,
+                block: 2,
+            },
+        ];
+        write_dx_entries(&mut test_data, 8, 3, &entries);
+
+        // countlimit overlays the hash of entry 0
+        assert_eq!(read_u16_le(&test_data[8..10]), 3);
+        assert_eq!(read_u16_le(&test_data[10..12]), 2);
+        let (limit, parsed) = read_dx_entries(&test_data, 8).unwrap();
+        assert_eq!(limit, 3);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].hash, 0);
+        assert_eq!(parsed[0].block, 1);
+        assert_eq!(parsed[1].hash, 0x87654320);
+        assert_eq!(parsed[1].block, 2);
+
+        // count beyond limit or beyond the buffer is corruption
+        write_u16_le(4, &mut test_data[10..12]);
+        assert!(matches!(read_dx_entries(&test_data, 8), Err(BlockDevError::Corrupted)));
+        write_u16_le(5, &mut test_data[8..10]);
+        assert!(matches!(read_dx_entries(&test_data, 8), Err(BlockDevError::Corrupted)));
     }
 
     #[test]
@@ -713,16 +1072,62 @@ use crate::ext4_backend::error::BlockDevError;
         // Create a mock block device
         let mut mock_device = MockBlockDevice::new(1024 * 1024);
         mock_device.open().unwrap();
-        let mut mock_dev = Jbd2Dev::initial_jbd2dev(0, mock_device, false);
+        let mut mock_dev = Jbd2Dev::initial_jbd2dev(0, mock_device, false, false);
         dir_inode.write_extend_header();
         dir_inode.i_flags |=Ext4Inode::EXT4_EXTENTS_FL;
         let result = manager.fallback_to_linear_search(
             &mut fs,
             &mut mock_dev,
+            2,
             &dir_inode,
             b"nonexistent.txt",
         );
 
         assert!(matches!(result, Err(HashTreeError::EntryNotFound)));
     }
+
+    #[test]
+    fn large_directory_is_indexed_and_lookup_reads_few_blocks() {
+        use crate::ext4_backend::dir::mkdir;
+        use crate::ext4_backend::file::mkfile;
+        use crate::ext4_backend::loopfile::get_file_inode;
+        use crate::ext4_backend::test_support::CountingDev;
+        use alloc::format;
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(32 * 1024), false, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        mkdir(&mut dev, &mut fs, "/big").unwrap();
+        for i in 0..5000 {
+            mkfile(&mut dev, &mut fs, &format!("/big/file_{i:05}"), None, None).unwrap();
+        }
+        let (_, dir) = get_file_inode(&mut fs, &mut dev, "/big").unwrap().unwrap();
+        assert!(dir.is_htree_indexed());
+        assert!(dir.size() > 8 * BLOCK_SIZE as u64);
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        for i in (0..5000).step_by(37) {
+            let path = format!("/big/file_{i:05}");
+            assert!(get_file_inode(&mut fs, &mut dev, &path).unwrap().is_some(), "{path}");
+        }
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        let (dir_ino, dir) = get_file_inode(&mut fs, &mut dev, "/big").unwrap().unwrap();
+        dev.with_raw_device(|d| {
+            d.reads = 0;
+            Ok(())
+        })
+        .unwrap();
+        let found = lookup_directory_entry(&mut fs, &mut dev, dir_ino, &dir, b"file_04321").unwrap();
+        assert_ne!(found.entry.inode, 0);
+        let reads = dev.with_raw_device(|d| Ok(d.reads)).unwrap();
+        // dx_root and the leaf only, instead of every directory block
+        assert!(reads <= 2, "lookup read {reads} blocks");
+        assert!(matches!(
+            lookup_directory_entry(&mut fs, &mut dev, dir_ino, &dir, b"missing"),
+            Err(HashTreeError::EntryNotFound)
+        ));
+    }
 }
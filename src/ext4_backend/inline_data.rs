@@ -0,0 +1,261 @@
+//! 内联数据（inline_data）文件
+//!
+//! 小文件的内容直接存放在 inode 的 i_block（60 字节）中，不占数据块，inode 带 EXT4_INLINE_DATA_FL。
+//! ext4 要求内联 inode 在 ibody 中带一个 system.data 扩展属性，超出 i_block 的内容存放在其值中。
+//! 本实现只创建完全放进 i_block 的内联文件（system.data 为空），
+//! 写入或扩展超出 i_block 时整体转换为 extent 文件
+
+use alloc::vec::Vec;
+
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::disknode::*;
+use crate::ext4_backend::endian::read_u32_le;
+use crate::ext4_backend::entries::inline_dir;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::file::write_file_with_ino;
+use crate::ext4_backend::superblock::*;
+use crate::ext4_backend::xattr::*;
+
+/// i_block 能容纳的内联数据字节数
+pub const INLINE_DATA_MAX: usize = inline_dir::INLINE_DIR_BYTES;
+/// system.data 属性：索引 7（system.），名字 "data"
+const SYSTEM_DATA_INDEX: u8 = 7;
+const SYSTEM_DATA_NAME: &[u8] = b"data";
+
+/// 文件系统开启 inline_data 且新 inode 有 ibody 可放 system.data 时，新建小文件才用内联数据
+pub fn inline_data_enabled(fs: &Ext4FileSystem) -> bool {
+    fs.superblock
+        .has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_INLINE_DATA)
+        && fs.new_inode_extra_isize() > 0
+}
+
+/// 内容是否可以作为内联数据存放
+pub fn fits_inline(fs: &Ext4FileSystem, data: &[u8]) -> bool {
+    !data.is_empty() && data.len() <= INLINE_DATA_MAX && inline_data_enabled(fs)
+}
+
+fn store_i_block(inode: &mut Ext4Inode, raw: &[u8; INLINE_DATA_MAX]) {
+    for (i, word) in raw.chunks_exact(4).enumerate() {
+        inode.i_block[i] = read_u32_le(word);
+    }
+}
+
+/// 把新建 inode 设为内联文件：内容写入 i_block，并在 ibody 中放一个空的 system.data
+pub fn init_inline_file(
+    inode: &mut Ext4Inode,
+    xattr_area: &mut [u8],
+    data: &[u8],
+) -> BlockDevResult<()> {
+    if data.len() > INLINE_DATA_MAX {
+        return Err(BlockDevError::InvalidInput);
+    }
+    modify_ibody_xattrs(inode, xattr_area, |entries| {
+        entries.retain(|e| !(e.index == SYSTEM_DATA_INDEX && e.name == SYSTEM_DATA_NAME));
+        entries.insert(
+            0,
+            XattrEntry {
+                index: SYSTEM_DATA_INDEX,
+                name: SYSTEM_DATA_NAME.to_vec(),
+                value: Vec::new(),
+            },
+        );
+    })?;
+    let mut raw = [0u8; INLINE_DATA_MAX];
+    raw[..data.len()].copy_from_slice(data);
+    store_i_block(inode, &raw);
+    inode.i_flags = (inode.i_flags | Ext4Inode::EXT4_INLINE_DATA_FL) & !Ext4Inode::EXT4_EXTENTS_FL;
+    inode.i_size_lo = data.len() as u32;
+    inode.i_size_high = 0;
+    inode.i_blocks_lo = 0;
+    inode.l_i_blocks_high = 0;
+    Ok(())
+}
+
+/// 读出内联文件的全部内容：i_block 之后接 system.data 的值
+pub fn read_inline(inode: &Ext4Inode, xattr_area: &[u8]) -> BlockDevResult<Vec<u8>> {
+    let size = inode.size() as usize;
+    let raw = inline_dir::raw_bytes(&inode.i_block);
+    let mut data = raw[..size.min(INLINE_DATA_MAX)].to_vec();
+    if size > INLINE_DATA_MAX {
+        let extra = ibody_xattr(inode, xattr_area, SYSTEM_DATA_INDEX, SYSTEM_DATA_NAME)?
+            .ok_or(BlockDevError::Corrupted)?;
+        data.extend_from_slice(&extra);
+        if data.len() < size {
+            return Err(BlockDevError::Corrupted);
+        }
+        data.truncate(size);
+    }
+    Ok(data)
+}
+
+/// 在 i_block 内原地写入，要求 offset + data.len() 不超过 INLINE_DATA_MAX
+pub fn write_inline_at<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    ino: u32,
+    offset: u64,
+    data: &[u8],
+) -> BlockDevResult<()> {
+    let end = offset as usize + data.len();
+    if end > INLINE_DATA_MAX {
+        return Err(BlockDevError::InvalidInput);
+    }
+    let now = fs.current_time();
+    fs.modify_inode(dev, ino, |inode| {
+        let mut raw = inline_dir::raw_bytes(&inode.i_block);
+        raw[offset as usize..end].copy_from_slice(data);
+        store_i_block(inode, &raw);
+        if end as u64 > inode.size() {
+            inode.i_size_lo = end as u32;
+        }
+        inode.set_mtime_ts(now);
+        inode.set_ctime_ts(now);
+    })
+}
+
+/// 在 i_block 内截断或扩展（新增部分为 0），要求 new_size 不超过 INLINE_DATA_MAX
+pub fn truncate_inline<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    ino: u32,
+    new_size: u64,
+) -> BlockDevResult<()> {
+    if new_size as usize > INLINE_DATA_MAX {
+        return Err(BlockDevError::InvalidInput);
+    }
+    fs.modify_inode(dev, ino, |inode| {
+        let mut raw = inline_dir::raw_bytes(&inode.i_block);
+        raw[new_size as usize..].fill(0);
+        store_i_block(inode, &raw);
+        inode.i_size_lo = new_size as u32;
+    })
+}
+
+/// 把内联文件转换为 extent 文件：清除内联标志和 system.data，再经普通写路径写回原内容
+pub fn convert_to_extents<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    ino: u32,
+) -> BlockDevResult<()> {
+    let (inode, xattr_area) = fs.get_inode_with_xattr_area(dev, ino)?;
+    let data = read_inline(&inode, &xattr_area)?;
+    let mut result = Ok(());
+    fs.modify_inode_with_xattr_area(dev, ino, |inode, xattr_area| {
+        result = modify_ibody_xattrs(inode, xattr_area, |entries| {
+            entries.retain(|e| !(e.index == SYSTEM_DATA_INDEX && e.name == SYSTEM_DATA_NAME));
+        });
+        // 删不掉 system.data 时保持内联状态不变，避免丢失 i_block 里的数据
+        if result.is_err() {
+            return;
+        }
+        inode.i_flags = (inode.i_flags & !Ext4Inode::EXT4_INLINE_DATA_FL) | Ext4Inode::EXT4_EXTENTS_FL;
+        inode.i_block = [0; 15];
+        inode.write_extend_header();
+        inode.i_size_lo = 0;
+        inode.i_size_high = 0;
+    })?;
+    result?;
+    write_file_with_ino(dev, fs, ino, 0, &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::api::{OpenFlags, open, read_at};
+    use crate::ext4_backend::loopfile::lookup_path;
+    use crate::ext4_backend::file::{mkfile, read_file, truncate, unlink, write_file};
+    use crate::ext4_backend::test_support::*;
+    use crate::BLOCK_SIZE;
+    use alloc::vec;
+
+    fn enable_inline_data(fs: &mut Ext4FileSystem) {
+        fs.superblock.s_feature_incompat |= Ext4Superblock::EXT4_FEATURE_INCOMPAT_INLINE_DATA;
+    }
+
+    #[test]
+    fn failed_conversion_leaves_inline_file_intact() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        enable_inline_data(&mut fs);
+        mkfile(&mut dev, &mut fs, "/tiny", Some(b"keep me"), None).unwrap();
+        let (ino, _) = lookup_path(&mut fs, &mut dev, "/tiny").unwrap();
+        // 把 ibody 里 system.data 条目的 e_value_inum 改成非 0（ea_inode，不支持）
+        fs.modify_inode_with_xattr_area(&mut dev, ino, |inode, xattr_area| {
+            let begin = Ext4Inode::GOOD_OLD_INODE_SIZE as usize + inode.i_extra_isize as usize
+                - Ext4Inode::CORE_FIELDS_SIZE;
+            xattr_area[begin + 8..begin + 12].copy_from_slice(&5u32.to_le_bytes());
+        })
+        .unwrap();
+
+        assert_eq!(convert_to_extents(&mut dev, &mut fs, ino), Err(BlockDevError::Unsupported));
+        let inode = fs.get_inode_by_num(&mut dev, ino).unwrap();
+        assert!(inode.has_inline_data());
+        assert!(!inode.have_extend_header_and_use_extend());
+        assert_eq!(read_file(&mut dev, &mut fs, "/tiny").unwrap(), b"keep me");
+    }
+
+    #[test]
+    fn small_file_is_stored_inline_and_survives_remount() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        enable_inline_data(&mut fs);
+        let free_before = fs.superblock.free_blocks_count();
+        mkfile(&mut dev, &mut fs, "/tiny", Some(b"hello inline"), None).unwrap();
+
+        let inode = fs.find_file(&mut dev, "/tiny").unwrap();
+        assert!(inode.has_inline_data());
+        assert!(!inode.have_extend_header_and_use_extend());
+        assert_eq!(inode.blocks_count(), 0);
+        assert_eq!(fs.superblock.free_blocks_count(), free_before);
+        assert_eq!(read_file(&mut dev, &mut fs, "/tiny").unwrap(), b"hello inline");
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(read_file(&mut dev, &mut fs, "/tiny").unwrap(), b"hello inline");
+        let mut file = open(&mut dev, &mut fs, "/tiny", OpenFlags::RDONLY).unwrap();
+        file.offset = 6;
+        assert_eq!(read_at(&mut dev, &mut fs, &mut file, 100).unwrap(), b"inline");
+
+        // 原地写仍在 i_block 内；截断也不转换
+        write_file(&mut dev, &mut fs, "/tiny", 0, b"HELLO").unwrap();
+        truncate(&mut dev, &mut fs, "/tiny", 8).unwrap();
+        let inode = fs.find_file(&mut dev, "/tiny").unwrap();
+        assert!(inode.has_inline_data());
+        assert_eq!(read_file(&mut dev, &mut fs, "/tiny").unwrap(), b"HELLO in");
+
+        unlink(&mut dev, &mut fs, "/tiny").unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_before);
+    }
+
+    #[test]
+    fn growing_past_i_block_converts_to_extents() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        enable_inline_data(&mut fs);
+        mkfile(&mut dev, &mut fs, "/grow", Some(b"0123456789"), None).unwrap();
+
+        let tail = vec![b'x'; BLOCK_SIZE];
+        write_file(&mut dev, &mut fs, "/grow", 10, &tail).unwrap();
+        let (ino, inode) = lookup_path(&mut fs, &mut dev, "/grow").unwrap();
+        assert!(!inode.has_inline_data());
+        assert!(inode.have_extend_header_and_use_extend());
+        assert_eq!(inode.size(), (BLOCK_SIZE + 10) as u64);
+        assert!(inode.blocks_count() > 0);
+        let (_, area) = fs.get_inode_with_xattr_area(&mut dev, ino).unwrap();
+        assert_eq!(ibody_xattr(&inode, &area, SYSTEM_DATA_INDEX, SYSTEM_DATA_NAME).unwrap(), None);
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        let data = read_file(&mut dev, &mut fs, "/grow").unwrap();
+        assert_eq!(&data[..10], b"0123456789");
+        assert!(data[10..].iter().all(|&b| b == b'x'));
+
+        // 截断到超出 i_block 的大小同样转换
+        mkfile(&mut dev, &mut fs, "/trunc", Some(b"abc"), None).unwrap();
+        truncate(&mut dev, &mut fs, "/trunc", 100).unwrap();
+        let inode = fs.find_file(&mut dev, "/trunc").unwrap();
+        assert!(!inode.has_inline_data());
+        let data = read_file(&mut dev, &mut fs, "/trunc").unwrap();
+        assert_eq!(data.len(), 100);
+        assert_eq!(&data[..3], b"abc");
+        assert!(data[3..].iter().all(|&b| b == 0));
+    }
+}
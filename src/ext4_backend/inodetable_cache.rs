@@ -1,22 +1,72 @@
 //! Inode表缓存模块
 //!
-//! 提供inode结构的缓存管理，支持延迟写回和LRU淘汰
+//! 提供inode结构的缓存管理，支持延迟写回和LRU淘汰。
+//! 未命中时按整块读取 inode 表并缓存原始块，同一块里的其它 inode 直接从内存解析
 
+use crate::ext4_backend::block_cache::{CacheCategoryStats, CacheCounters};
 use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::config::*;
+use crate::ext4_backend::crc32c::crc32c;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::endian::*;
+use crate::ext4_backend::blockgroup_description::*;
+use crate::ext4_backend::superblock::*;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use crate::ext4_backend::error::*;
 /// Inode缓存键（全局inode号）
 pub type InodeCacheKey = u64;
 
+/// 计算 inode 在磁盘上的位置
+/// * `desc` - inode 所在块组的描述符（提供 inode 表起始块）
+/// * `inode_no` - inode号（从1开始，inode N 位于下标 N-1）
+///
+/// # 返回
+/// (块组索引, 块号, 块内偏移)；inode 0 或 s_inodes_per_group 为 0 时返回 InvalidInput
+pub fn inode_location(
+    sb: &Ext4Superblock,
+    desc: &Ext4GroupDesc,
+    inode_no: u32,
+) -> BlockDevResult<(u32, u64, usize)> {
+    if inode_no == 0 || sb.s_inodes_per_group == 0 {
+        return Err(BlockDevError::InvalidInput);
+    }
+    let inode_idx = inode_no - 1;
+    let group_idx = inode_idx / sb.s_inodes_per_group;
+    let inode_size = match sb.inode_size() {
+        0 => DEFAULT_INODE_SIZE as usize,
+        n => n as usize,
+    };
+    let byte_offset = (inode_idx % sb.s_inodes_per_group) as usize * inode_size;
+    let block_num = desc.inode_table() + (byte_offset / BLOCK_SIZE) as u64;
+    Ok((group_idx, block_num, byte_offset % BLOCK_SIZE))
+}
+
+/// 在编码好的 inode 表项上填写校验和：种子依次混入 inode 号和 i_generation，
+/// 对校验和字段置零后的整个表项计算；低 16 位放 l_i_checksum_lo（偏移 124），
+/// 扩展区容纳 i_checksum_hi（偏移 130）时放高 16 位
+pub fn set_inode_csum(raw: &mut [u8], fs_seed: u32, ino: u32, inode: &Ext4Inode) {
+    let has_hi = raw.len() > Ext4Inode::GOOD_OLD_INODE_SIZE as usize && inode.i_extra_isize >= 4;
+    raw[124..126].fill(0);
+    if has_hi {
+        raw[130..132].fill(0);
+    }
+    let seed = crc32c(fs_seed, &ino.to_le_bytes());
+    let seed = crc32c(seed, &inode.i_generation.to_le_bytes());
+    let sum = crc32c(seed, raw);
+    write_u16_le((sum & 0xFFFF) as u16, &mut raw[124..126]);
+    if has_hi {
+        write_u16_le((sum >> 16) as u16, &mut raw[130..132]);
+    }
+}
+
 /// 缓存的inode数据
 #[derive(Debug, Clone)]
 pub struct CachedInode {
     /// Inode结构体
     pub inode: Ext4Inode,
+    /// 结构体字段之后到 inode 末尾的原始字节（in-inode 扩展属性区），写回时原样保留
+    pub xattr_area: Vec<u8>,
     /// 是否被修改（脏）
     pub dirty: bool,
     /// Inode在磁盘上的位置（块号）
@@ -33,6 +83,7 @@ impl CachedInode {
     pub fn new(inode: Ext4Inode, inode_num: u64, block_num: u64, offset: usize) -> Self {
         Self {
             inode,
+            xattr_area: Vec::new(),
             dirty: false,
             block_num,
             offset_in_block: offset,
@@ -70,6 +121,15 @@ pub struct InodeCache {
     access_counter: u64,
     /// 每个inode的大小=
     inode_size: usize,
+    /// inode 表块的原始内容：块号 -> (块数据, 最后访问时间)
+    /// 写回 inode 时同步更新，始终与磁盘内容一致
+    table_blocks: BTreeMap<u64, (Vec<u8>, u64)>,
+    /// 最多缓存的 inode 表块数
+    max_table_blocks: usize,
+    /// 命中、未命中、写回和淘汰计数（未命中即需要读 inode 表块）
+    counters: CacheCounters,
+    /// 启用 metadata_csum 时的文件系统校验和种子，写回 inode 时据此重算 inode 校验和
+    pub csum_seed: Option<u32>,
 }
 
 impl InodeCache {
@@ -82,6 +142,10 @@ impl InodeCache {
             max_entries,
             access_counter: 0,
             inode_size,
+            table_blocks: BTreeMap::new(),
+            max_table_blocks: INODE_TABLE_BLOCK_CACHE_MAX,
+            counters: CacheCounters::default(),
+            csum_seed: None,
         }
     }
 
@@ -90,53 +154,72 @@ impl InodeCache {
         Self::new(INODE_CACHE_MAX, inode_size as usize)
     }
 
-    /// 计算inode在磁盘上的位置
-    /// * `inode_num` - inode号（从1开始）
-    /// * `inodes_per_group` - 每个块组的inode数
-    /// * `inode_table_start` - inode表起始块号（从块组描述符获取）
-    /// * `block_size` - 块大小
-    ///
-    /// # 返回
-    /// (块号, 块内偏移, 块组索引)
-    pub fn calc_inode_location(
-        &self,
-        inode_num: u32,
-        inodes_per_group: u32,
-        inode_table_start: u64,
-        block_size: usize,
-    ) -> (u64, usize, u32) {
-        let inode_idx = inode_num - 1;
-
-        let idx_in_group = inode_idx % inodes_per_group;
-        let group_idx = inode_idx / inodes_per_group;
-
-        let byte_offset = idx_in_group as usize * self.inode_size;
-
-        let block_offset = byte_offset / block_size;
-        let offset_in_block = byte_offset % block_size;
-
-        let block_num = inode_table_start + block_offset as u64;
-
-        (block_num, offset_in_block, group_idx)
-    }
-
-    /// 从磁盘加载inode
+    /// 加载inode：所在的 inode 表块已缓存时直接解析，否则整块读入后缓存
+    /// 同时返回结构体字段之后的扩展属性区字节
     fn load_inode<B: BlockDevice>(
-        &self,
+        &mut self,
         block_dev: &mut Jbd2Dev<B>,
         block_num: u64,
         offset: usize,
-    ) -> BlockDevResult<Ext4Inode> {
-        block_dev.read_block(block_num as u32)?;
-        let buffer = block_dev.buffer();
-
-        if offset + self.inode_size > buffer.len() {
+    ) -> BlockDevResult<(Ext4Inode, Vec<u8>)> {
+        if offset + self.inode_size > BLOCK_SIZE {
             return Err(BlockDevError::Corrupted);
         }
+        self.access_counter += 1;
+        let stamp = self.access_counter;
+        if self.table_blocks.contains_key(&block_num) {
+            self.counters.hits += 1;
+        } else {
+            self.counters.misses += 1;
+            block_dev.read_block(block_num)?;
+            let data = block_dev.buffer().to_vec();
+            if self.table_blocks.len() >= self.max_table_blocks {
+                let oldest = self
+                    .table_blocks
+                    .iter()
+                    .min_by_key(|(_, (_, last))| *last)
+                    .map(|(key, _)| *key);
+                if let Some(key) = oldest {
+                    self.table_blocks.remove(&key);
+                }
+            }
+            self.table_blocks.insert(block_num, (data, stamp));
+        }
+        let (data, last) = self
+            .table_blocks
+            .get_mut(&block_num)
+            .ok_or(BlockDevError::Corrupted)?;
+        *last = stamp;
+        let raw = &data[offset..offset + self.inode_size];
+        let xattr_area = raw.get(Ext4Inode::CORE_FIELDS_SIZE..).unwrap_or(&[]).to_vec();
+        Ok((Ext4Inode::from_disk_bytes(raw), xattr_area))
+    }
+
+    /// 编码整个 inode 表项：结构体字段加上原样保留的扩展属性区
+    fn encode(&self, cached: &CachedInode) -> Vec<u8> {
+        let mut buffer = alloc::vec![0u8; self.inode_size];
+        cached.inode.to_disk_bytes(&mut buffer);
+        if let Some(tail) = buffer.get_mut(Ext4Inode::CORE_FIELDS_SIZE..)
+            && tail.len() == cached.xattr_area.len()
+        {
+            tail.copy_from_slice(&cached.xattr_area);
+        }
+        if let Some(seed) = self.csum_seed {
+            set_inode_csum(&mut buffer, seed, cached.inode_num as u32, &cached.inode);
+        }
+        buffer
+    }
 
-        let inode = Ext4Inode::from_disk_bytes(&buffer[offset..offset + self.inode_size]);
+    /// inode 写回磁盘后同步更新已缓存的 inode 表块
+    fn update_table_block(&mut self, block_num: u64, offset: usize, data: &[u8]) {
+        if let Some((block, _)) = self.table_blocks.get_mut(&block_num) {
+            block[offset..offset + data.len()].copy_from_slice(data);
+        }
+    }
 
-        Ok(inode)
+    /// 已缓存的 inode 表块数
+    pub fn cached_table_blocks(&self) -> usize {
+        self.table_blocks.len()
     }
 
     /// 获取inode（如果不存在则从磁盘加载，只读）
@@ -152,15 +235,18 @@ impl InodeCache {
         offset: usize,
     ) -> BlockDevResult<&CachedInode> {
         // 如果缓存中不存在，则加载
-        if !self.cache.contains_key(&inode_num) {
+        if self.cache.contains_key(&inode_num) {
+            self.counters.hits += 1;
+        } else {
             // 检查是否需要淘汰
             if self.cache.len() >= self.max_entries {
                 self.evict_lru(block_dev)?;
             }
 
             // 从磁盘加载
-            let inode = self.load_inode(block_dev,  block_num, offset)?;
-            let cached = CachedInode::new(inode, inode_num, block_num, offset);
+            let (inode, xattr_area) = self.load_inode(block_dev, block_num, offset)?;
+            let mut cached = CachedInode::new(inode, inode_num, block_num, offset);
+            cached.xattr_area = xattr_area;
             self.cache.insert(inode_num, cached);
         }
 
@@ -182,13 +268,16 @@ impl InodeCache {
         offset: usize,
     ) -> BlockDevResult<&mut CachedInode> {
         // 如果缓存中不存在，则加载
-        if !self.cache.contains_key(&inode_num) {
+        if self.cache.contains_key(&inode_num) {
+            self.counters.hits += 1;
+        } else {
             if self.cache.len() >= self.max_entries {
                 self.evict_lru(block_dev)?;
             }
 
-            let inode = self.load_inode(block_dev,  block_num, offset)?;
-            let cached = CachedInode::new(inode, inode_num, block_num, offset);
+            let (inode, xattr_area) = self.load_inode(block_dev, block_num, offset)?;
+            let mut cached = CachedInode::new(inode, inode_num, block_num, offset);
+            cached.xattr_area = xattr_area;
             self.cache.insert(inode_num, cached);
         }
 
@@ -218,6 +307,11 @@ impl InodeCache {
         }
     }
 
+    /// 所有脏 inode 的编号（升序）
+    pub fn dirty_inodes(&self) -> Vec<u64> {
+        self.cache.values().filter(|c| c.dirty).map(|c| c.inode_num).collect()
+    }
+
     /// 标记inode为脏
     pub fn mark_dirty(&mut self, inode_num: u64) {
         if let Some(cached) = self.cache.get_mut(&inode_num) {
@@ -244,6 +338,25 @@ impl InodeCache {
         Ok(())
     }
 
+    /// 同 modify，闭包同时拿到 inode 和其后的扩展属性区
+    pub fn modify_with_xattr_area<B, F>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        inode_num: u64,
+        block_num: u64,
+        offset: usize,
+        f: F,
+    ) -> BlockDevResult<()>
+    where
+        B: BlockDevice,
+        F: FnOnce(&mut Ext4Inode, &mut [u8]),
+    {
+        let cached = self.get_or_load_mut(block_dev, inode_num, block_num, offset)?;
+        f(&mut cached.inode, &mut cached.xattr_area);
+        cached.mark_dirty();
+        Ok(())
+    }
+
     /// 使用句柄修改inode的便捷方法
     pub fn modify_by_handle<B, F>(
         &mut self,
@@ -270,6 +383,7 @@ impl InodeCache {
 
         if let Some(key) = lru_key {
             self.evict(block_dev, key)?;
+            self.counters.evictions += 1;
         }
 
         Ok(())
@@ -283,13 +397,15 @@ impl InodeCache {
     ) -> BlockDevResult<()> {
         if let Some(cached) = self.cache.remove(&inode_num)
             && cached.dirty {
-                Self::write_inode_static(
+                let buffer = self.encode(&cached);
+                self.counters.writebacks += 1;
+                Self::write_inode_bytes_static(
                     block_dev,
-                    &cached.inode,
                     cached.block_num,
                     cached.offset_in_block,
-                    self.inode_size,
+                    &buffer,
                 )?;
+                self.update_table_block(cached.block_num, cached.offset_in_block, &buffer);
             }
         Ok(())
     }
@@ -301,11 +417,7 @@ impl InodeCache {
             .cache
             .values()
             .filter(|cached| cached.dirty)
-            .map(|cached| {
-                let mut buffer = alloc::vec![0u8; self.inode_size];
-                cached.inode.to_disk_bytes(&mut buffer);
-                (cached.block_num, cached.offset_in_block, buffer)
-            })
+            .map(|cached| (cached.block_num, cached.offset_in_block, self.encode(cached)))
             .collect();
 
         if dirty_inodes.is_empty() {
@@ -320,7 +432,7 @@ impl InodeCache {
             let (block_num, _, _) = dirty_inodes[idx];
 
             // 读出当前 inode 表块到 Jbd2Dev 的 buffer
-            block_dev.read_block(block_num as u32)?;
+            block_dev.read_block(block_num)?;
             {
                 let buffer = block_dev.buffer_mut();
 
@@ -335,9 +447,13 @@ impl InodeCache {
                     idx += 1;
                 }
             }
+            if let Some((block, _)) = self.table_blocks.get_mut(&block_num) {
+                block.copy_from_slice(&block_dev.buffer()[..BLOCK_SIZE]);
+            }
 
             // 该 inode 表块只调用一次 write_block，作为 metadata 走 JBD2
-            block_dev.write_block(block_num as u32, true)?;
+            block_dev.write_block(block_num, true)?;
+            self.counters.writebacks += 1;
         }
 
         // 清除所有脏标记
@@ -358,10 +474,11 @@ impl InodeCache {
             && cached.dirty {
                 let block_num = cached.block_num;
                 let offset = cached.offset_in_block;
-                let mut buffer = alloc::vec![0u8; self.inode_size];
-                cached.inode.to_disk_bytes(&mut buffer);
+                let buffer = self.encode(cached);
 
                 Self::write_inode_bytes_static(block_dev, block_num, offset, &buffer)?;
+                self.counters.writebacks += 1;
+                self.update_table_block(block_num, offset, &buffer);
 
                 if let Some(cached) = self.cache.get_mut(&inode_num) {
                     cached.dirty = false;
@@ -370,19 +487,6 @@ impl InodeCache {
         Ok(())
     }
 
-    /// 写inode到磁盘
-    fn write_inode_static<B: BlockDevice>(
-        block_dev: &mut Jbd2Dev<B>,
-        inode: &Ext4Inode,
-        block_num: u64,
-        offset: usize,
-        inode_size: usize,
-    ) -> BlockDevResult<()> {
-        let mut buffer = alloc::vec![0u8; inode_size];
-        inode.to_disk_bytes(&mut buffer);
-        Self::write_inode_bytes_static(block_dev, block_num, offset, &buffer)
-    }
-
     /// 写inode字节到磁盘
     fn write_inode_bytes_static<B: BlockDevice>(
         block_dev: &mut Jbd2Dev<B>,
@@ -390,18 +494,64 @@ impl InodeCache {
         offset: usize,
         data: &[u8],
     ) -> BlockDevResult<()> {
-        block_dev.read_block(block_num as u32)?;
+        block_dev.read_block(block_num)?;
         let buffer = block_dev.buffer_mut();
 
         buffer[offset..offset + data.len()].copy_from_slice(data);
 
-        block_dev.write_block(block_num as u32, true)?; //只供崩溃恢复用
+        block_dev.write_block(block_num, true)?; //只供崩溃恢复用
         Ok(())
     }
 
     /// 清空缓存（不写回）
     pub fn clear(&mut self) {
         self.cache.clear();
+        self.table_blocks.clear();
+    }
+
+    /// 每块能容纳的已解析 inode 数，用于把已解析的 inode 折算成块
+    fn inodes_per_block(&self) -> usize {
+        (BLOCK_SIZE / self.inode_size).max(1)
+    }
+
+    /// 按块数调整容量：一半给 inode 表块，另一半按块折算成已解析 inode 的条目上限
+    /// 超出部分按 LRU 淘汰（脏 inode 先写回）
+    pub fn set_capacity<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut Jbd2Dev<B>,
+        blocks: usize,
+    ) -> BlockDevResult<()> {
+        self.max_table_blocks = (blocks / 2).max(1);
+        self.max_entries = blocks.saturating_sub(self.max_table_blocks).max(1) * self.inodes_per_block();
+        while self.cache.len() > self.max_entries {
+            self.evict_lru(block_dev)?;
+        }
+        while self.table_blocks.len() > self.max_table_blocks {
+            let oldest = self
+                .table_blocks
+                .iter()
+                .min_by_key(|(_, (_, last))| *last)
+                .map(|(key, _)| *key);
+            if let Some(key) = oldest {
+                self.table_blocks.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    /// 块缓存统计中的 inode 部分：inode 表块和已解析的 inode（按所占块数折算）都计入
+    pub fn category_stats(&self) -> CacheCategoryStats {
+        let per_block = self.inodes_per_block();
+        CacheCategoryStats {
+            cached_blocks: self.table_blocks.len() + self.cache.len().div_ceil(per_block),
+            capacity: self.max_table_blocks + self.max_entries.div_ceil(per_block),
+            counters: self.counters,
+        }
+    }
+
+    /// 访问计数清零
+    pub fn reset_counters(&mut self) {
+        self.counters = CacheCounters::default();
     }
 
     /// 获取缓存统计
@@ -429,37 +579,71 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_inode_location_calc() {
-        let cache = InodeCache::default(DEFAULT_INODE_SIZE);
+    fn test_inode_cache_basic() {
+        let cache = InodeCache::new(4, 256);
+        let stats = cache.stats();
 
-        let inodes_per_group = 128;
-        let inode_table_start = 100;
-        let block_size = BLOCK_SIZE;
+        assert_eq!(stats.total_entries, 0);
+        assert_eq!(stats.max_entries, 4);
+    }
 
-        let (block, offset, group) =
-            cache.calc_inode_location(1, inodes_per_group, inode_table_start, block_size);
-        assert_eq!(block, 100);
-        assert_eq!(offset, 0);
-        assert_eq!(group, 0);
+    #[test]
+    fn inode_location_matches_root_inode_on_disk() {
+        use crate::ext4_backend::test_support::*;
 
-        let inodes_per_block = (block_size / DEFAULT_INODE_SIZE as usize) as u32;
-        let (block, offset, group) = cache.calc_inode_location(
-            (inodes_per_block + 1) as u32,
-            inodes_per_group,
-            inode_table_start,
-            block_size,
-        );
-        assert_eq!(block, inode_table_start + 1);
-        assert_eq!(offset, 0);
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        let root = fs.get_root(&mut dev).unwrap();
+        let (group, block, offset) = inode_location(&fs.superblock, &fs.group_descs[0], 2).unwrap();
         assert_eq!(group, 0);
+        // 与缓存里记录的根目录位置一致
+        let cached = fs.inodetable_cahce.get(2).unwrap();
+        assert_eq!((cached.block_num, cached.offset_in_block), (block, offset));
+
+        dev.read_block(block).unwrap();
+        let size = fs.superblock.inode_size() as usize;
+        let on_disk = Ext4Inode::from_disk_bytes(&dev.buffer()[offset..offset + size]);
+        assert!(on_disk.is_dir());
+        assert_eq!(on_disk.i_mode, root.i_mode);
+        assert_eq!(on_disk.i_links_count, root.i_links_count);
+
+        // inode 1 位于 inode 表开头
+        let (_, block1, offset1) = inode_location(&fs.superblock, &fs.group_descs[0], 1).unwrap();
+        assert_eq!((block1, offset1), (fs.group_descs[0].inode_table(), 0));
+        // inode 0 不存在，返回错误而不是下溢
+        assert_eq!(
+            inode_location(&fs.superblock, &fs.group_descs[0], 0),
+            Err(BlockDevError::InvalidInput)
+        );
     }
 
     #[test]
-    fn test_inode_cache_basic() {
-        let cache = InodeCache::new(4, 256);
-        let stats = cache.stats();
-
-        assert_eq!(stats.total_entries, 0);
-        assert_eq!(stats.max_entries, 4);
+    fn inodes_sharing_a_table_block_cost_one_read() {
+        use crate::ext4_backend::ext4::*;
+        use crate::ext4_backend::test_support::*;
+
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, CountingDev::new(16 * 1024), false, false);
+        mkfs(&mut dev).unwrap();
+        let mut fs = mount(&mut dev).unwrap();
+        let per_block = BLOCK_SIZE / fs.superblock.inode_size() as usize;
+        assert!(per_block >= 8);
+        fs.inodetable_cahce.flush_all(&mut dev).unwrap();
+        fs.inodetable_cahce.clear();
+
+        let reads = |dev: &mut Jbd2Dev<CountingDev>| dev.with_raw_device(|d| Ok(d.reads)).unwrap();
+        let before = reads(&mut dev);
+        fs.get_inode_by_num(&mut dev, 3).unwrap();
+        // 让 Jbd2Dev 的单块缓冲换成别的块，之后的 inode 只能来自 inode 表块缓存
+        dev.read_block(0).unwrap();
+        for ino in [5, 7, 8] {
+            fs.get_inode_by_num(&mut dev, ino).unwrap();
+        }
+        assert_eq!(reads(&mut dev), before + 2);
+        assert_eq!(fs.inodetable_cahce.cached_table_blocks(), 1);
+
+        // 写回的 inode 同步到缓存的表块，重新加载读到新内容
+        fs.modify_inode(&mut dev, 6, |inode| inode.i_links_count = 7).unwrap();
+        fs.inodetable_cahce.flush_all(&mut dev).unwrap();
+        fs.inodetable_cahce.evict(&mut dev, 6).unwrap();
+        assert_eq!(fs.get_inode_by_num(&mut dev, 6).unwrap().i_links_count, 7);
     }
 }
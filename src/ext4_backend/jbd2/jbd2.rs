@@ -1,54 +1,246 @@
 use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::blockgroup_description::*;
 use crate::ext4_backend::config::*;
+use crate::ext4_backend::crc32c::crc32c;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::endian::*;
 use crate::ext4_backend::ext4::*;
 use crate::ext4_backend::file::*;
+use crate::ext4_backend::extents_tree::*;
 use crate::ext4_backend::jbd2::jbdstruct::*;
 use crate::ext4_backend::loopfile::*;
+use crate::ext4_backend::superblock::*;
 use crate::ext4_backend::error::*;
-use alloc::vec;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use log::debug;
+use log::error;
 use log::info;
 use log::warn;
 
 use alloc::vec::Vec;
 
 
+/// 扫描到的一个完整事务
+struct JournalTxn {
+    /// 事务号
+    tid: u32,
+    /// descriptor 中的 tags，与 blocks 一一对应
+    tags: Vec<JournalBlockTagS>,
+    /// 日志中保存的元数据块内容
+    blocks: Vec<[u8; BLOCK_SIZE]>,
+    /// revoke 块中撤销的主盘块号
+    revoked: Vec<u32>,
+}
+
+/// 扫描到完整事务时的回调：(设备, 事务)
+type TxnVisitor<'a, B> = dyn FnMut(&mut B, &mut JournalTxn) + 'a;
+
+/// 一次日志扫描的结果
+struct ReplayScan {
+    /// 完整事务数
+    applied: u32,
+    /// 扫描结束后期望的下一个事务号
+    next_seq: u32,
+    /// 下一个事务起点（相对块号）
+    next_rel: u32,
+    /// 是否因读盘失败提前结束
+    io_error: bool,
+}
+
 impl JBD2DEVSYSTEM {
     ///计算下一个日志块的位置(处理回绕),返回当前的（可以直接用，直接写，已经处理过偏移）!
-    pub fn set_next_log_block<B:BlockDevice>(&mut self,block_dev: &mut B) -> u32 {
+    pub fn set_next_log_block<B:BlockDevice>(&mut self,block_dev: &mut B) -> u64 {
        //处理第一次使用journal提交
        if self.jbd2_super_block.s_start==0 {
-           //更新内存的s_start 
+           //更新内存的s_start 并写入超级块
            self.jbd2_super_block.s_start = self.jbd2_super_block.s_first;
-           //写入超级块
-           let mut sb_data = [0u8; BLOCK_SIZE];
-           block_dev.read(&mut sb_data, self.start_block, 1).expect("Read superblock failed");
-           self.jbd2_super_block.to_disk_bytes(&mut sb_data);
-           block_dev.write(&sb_data, self.start_block, 1).expect("Write superblock failed");
-           self.head+=1;
-           let mut target_use = self.start_block + self.jbd2_super_block.s_start+self.head-1;
-           //处理环绕
-           if target_use - self.start_block > self.max_len {
-               self.head = 0;
-               target_use = self.start_block + self.jbd2_super_block.s_start;
-           }
-           return target_use;
-       }else {
-        //不是第一次提交
-           self.head+=1;
-           //处理环绕
-           let mut target_use = self.start_block + self.jbd2_super_block.s_start+self.head-1;
-           if target_use - self.start_block > self.max_len {
-               self.head = 0;
-               target_use = self.start_block + self.jbd2_super_block.s_start;
-           }
-           return target_use;
+           self.head = 0;
+           self.write_superblock(block_dev).expect("Write superblock failed");
        }
-       
+       let target_rel = self.rel_after(self.jbd2_super_block.s_start, self.head);
+       self.head += 1;
+       self.start_block + target_rel as u64
+    }
+
+    /// 日志区可用于事务的块数，相对块号范围 [s_first, s_first + max_len - 1]
+    fn log_capacity(&self) -> u32 {
+        self.max_len.max(1)
+    }
+
+    /// 相对块号 rel 之后第 n 个日志块（处理回绕）
+    fn rel_after(&self, rel: u32, n: u32) -> u32 {
+        let first = self.jbd2_super_block.s_first;
+        first + (rel.saturating_sub(first) + n) % self.log_capacity()
+    }
+
+    /// 当前日志空间占用
+    pub fn usage(&self) -> JournalUsage {
+        let used_blocks = if self.jbd2_super_block.s_start == 0 {
+            0
+        } else {
+            self.head
+        };
+        JournalUsage {
+            used_blocks,
+            total_blocks: self.log_capacity(),
+        }
+    }
+
+    /// 把 journal 超级块写回日志区第一个块（read-modify-write，避免破坏其它字节）
+    fn write_superblock<B: BlockDevice>(&mut self, block_dev: &mut B) -> BlockDevResult<()> {
+        let mut sb_data = [0u8; BLOCK_SIZE];
+        block_dev.read64(&mut sb_data, self.start_block, 1)?;
+        self.encode_superblock(&mut sb_data);
+        block_dev.write64(&sb_data, self.start_block, 1)
+    }
+
+    /// checkpoint：把已提交的元数据写回主盘位置，然后推进 s_sequence / s_start 释放日志空间
+    /// 事务缓存中的块会带着更新的内容随下一次提交重新记入日志，这里跳过
+    pub fn checkpoint<B: BlockDevice>(&mut self, block_dev: &mut B) -> BlockDevResult<()> {
+        let list = core::mem::take(&mut self.checkpoint_list);
+        debug!("[JBD2 checkpoint] blocks={} used={}", list.len(), self.head);
+        for (blk, data) in list {
+            if self.commit_queue.iter().any(|u| u.0 == blk as u64) {
+                continue;
+            }
+            block_dev.write(&data[..], blk, 1)?;
+        }
+        block_dev.flush()?;
+
+        // 日志中已没有需要重放的块，旧的 revoke 记录也随之作废
+        self.logged.clear();
+        self.jbd2_super_block.s_sequence = self.sequence;
+        if self.jbd2_super_block.s_start != 0 {
+            self.jbd2_super_block.s_start = self.rel_after(self.jbd2_super_block.s_start, self.head);
+            self.head = 0;
+        }
+        self.write_superblock(block_dev)?;
+        block_dev.flush()
+    }
+
+    /// 单个 revoke 块可容纳的记录数，CSUM_V3 时最后 4 字节留给块尾校验和
+    fn revoke_records_per_block(&self) -> usize {
+        if self.csum_v3() {
+            JBD2_REVOKE_RECORDS_MAX - 1
+        } else {
+            JBD2_REVOKE_RECORDS_MAX
+        }
+    }
+
+    /// 日志是否启用 CSUM_V3 校验（以 journal 超级块中的特性位为准）
+    pub fn csum_v3(&self) -> bool {
+        self.jbd2_super_block.s_feature_incompat & JBD2_FEATURE_INCOMPAT_CSUM_V3 != 0
+    }
+
+    /// 打开 CSUM_V3：之后提交的 descriptor/revoke/commit 块和日志块都带 CRC32C 校验和
+    /// 只应在日志干净（s_start == 0）时调用，否则旧事务会因缺少校验和被当作撕裂丢弃
+    pub fn enable_csum_v3(&mut self) {
+        self.jbd2_super_block.s_feature_incompat |= JBD2_FEATURE_INCOMPAT_CSUM_V3;
+        self.jbd2_super_block.s_checksum_type = JBD2_CRC32C_CHKSUM;
+    }
+
+    /// 校验和种子 crc32c(~0, uuid)
+    fn csum_seed(&self) -> u32 {
+        crc32c(!0, &self.jbd2_super_block.s_uuid)
+    }
+
+    /// descriptor 中每个 tag 的字节数
+    fn tag_bytes(&self) -> usize {
+        if self.csum_v3() { 16 } else { 8 }
+    }
+
+    /// 单个 descriptor 块可容纳的 tag 数，CSUM_V3 时块尾 4 字节留给校验和
+    pub fn tags_per_descriptor(&self) -> usize {
+        let tail = if self.csum_v3() { 4 } else { 0 };
+        (BLOCK_SIZE - 12 - tail) / self.tag_bytes()
+    }
+
+    /// 日志块校验和：crc32c(seed, be32 事务号) 之后接着算块内容（日志中保存的、已做逃逸处理的内容）
+    fn block_csum(&self, tid: u32, data: &[u8]) -> u32 {
+        crc32c(crc32c(self.csum_seed(), &tid.to_be_bytes()), data)
+    }
+
+    /// descriptor / revoke 块尾校验和：尾部 4 字节按 0 参与计算
+    fn tail_csum(&self, block: &[u8]) -> u32 {
+        let sum = crc32c(self.csum_seed(), &block[..BLOCK_SIZE - 4]);
+        crc32c(sum, &[0u8; 4])
+    }
+
+    /// 序列化 journal 超级块，启用 CSUM_V3 时顺带更新 s_checksum
+    fn encode_superblock(&mut self, out: &mut [u8]) {
+        if self.csum_v3() {
+            self.jbd2_super_block.s_checksum = 0;
+            self.jbd2_super_block.to_disk_bytes(out);
+            self.jbd2_super_block.s_checksum = crc32c(!0, &out[..1024]);
+        }
+        self.jbd2_super_block.to_disk_bytes(out);
+    }
+
+    /// 把一个元数据块放进事务缓存：同一事务内重复写同一块只保留最新内容，
+    /// 重新记入日志的块取消之前的 revoke
+    pub fn queue_update(&mut self, update: Jbd2Update) {
+        self.revoke_table.remove(&(update.0 as u32));
+        match self.commit_queue.iter_mut().find(|u| u.0 == update.0) {
+            Some(slot) => slot.1 = update.1,
+            None => self.commit_queue.push(update),
+        }
+    }
+
+    /// 撤销某个主盘块在日志中的旧副本，块被释放（之后可能被当作数据块复用）时调用
+    /// 尚未提交的副本直接从事务缓存丢弃；已提交过的记入 revoke 表，随下一次提交写出 revoke 记录
+    pub fn revoke(&mut self, block_nr: u32) {
+        self.commit_queue.retain(|u| u.0 != block_nr as u64);
+        self.checkpoint_list.remove(&block_nr);
+        if self.logged.remove(&block_nr) {
+            self.revoke_table.insert(block_nr, self.sequence);
+        }
+    }
+
+    /// 把 revoke 表中属于事务 tid（及更早）的记录写成 revoke 块，写完即从表中清除
+    /// 一块写不下时拆成多块，每块格式见 Jbd2JournalRevokeHeadS；启用 CSUM_V3 时带块尾校验和
+    fn write_revoke_blocks<B: BlockDevice>(&mut self, block_dev: &mut B, tid: u32) -> BlockDevResult<()> {
+        let blocks: Vec<u32> = self
+            .revoke_table
+            .iter()
+            .filter(|&(_, &seq)| seq <= tid)
+            .map(|(&blk, _)| blk)
+            .collect();
+        let csum = self.csum_v3();
+        for chunk in blocks.chunks(self.revoke_records_per_block()) {
+            let mut buf = [0u8; BLOCK_SIZE];
+            let head = Jbd2JournalRevokeHeadS {
+                r_header: JournalHeaderS {
+                    h_magic: JBD2_MAGIC,
+                    h_blocktype: JBD2_REVOKE_BLOCK,
+                    h_sequence: tid,
+                },
+                r_count: (16 + chunk.len() * 4) as u32,
+            };
+            head.to_disk_bytes(&mut buf[0..16]);
+            for (i, blk) in chunk.iter().enumerate() {
+                buf[16 + i * 4..20 + i * 4].copy_from_slice(&blk.to_be_bytes());
+            }
+            if csum {
+                let tail = self.tail_csum(&buf);
+                buf[BLOCK_SIZE - 4..].copy_from_slice(&tail.to_be_bytes());
+            }
+            let block_id = self.set_next_log_block(block_dev);
+            debug!(
+                "[JBD2 commit] tid={tid} revoke_block_id={block_id} (absolute) records={}",
+                chunk.len()
+            );
+            block_dev.write64(&buf, block_id, 1)?;
+        }
+        // ordered 模式下提交时主盘已同步写好，事务的 revoke 记录随即作废
+        for blk in blocks {
+            self.revoke_table.remove(&blk);
+        }
+        Ok(())
     }
+
     ///提交事务
+    /// 日志块写入失败时返回 Err，本事务不会被视为已提交
     /// 允许使用原始块设备!
     /// update:Vec<JBD2_UPDATE>
     pub fn commit_transaction<B: BlockDevice>(&mut self, block_dev: &mut B) -> Result<bool, ()> {
@@ -64,82 +256,116 @@ impl JBD2DEVSYSTEM {
             self.jbd2_super_block.s_start,
         );
 
-        if self.commit_queue.len() <= 0 {
+        if self.commit_queue.is_empty() && self.revoke_table.is_empty() {
             warn!("No thing need to commit");
             return Ok(false);
         }
 
-        let mut desc_buffer = vec![0; BLOCK_SIZE];
-
-        //写header->内存缓存
-        let mut new_jbd_header = JournalHeaderS::default();
-        new_jbd_header.h_blocktype = 1; //Descriptor
-        new_jbd_header.h_sequence = tid; //设置事务id
-        new_jbd_header.to_disk_bytes(&mut desc_buffer[0..JournalHeaderS::disk_size()]);
-
-        let mut current_offset = 12; //跳过头
-        //写many tag，目前开发测试简化为一个descriptor块能塞下:)
-        for (idx, update) in self.commit_queue.iter().enumerate() {
-            //检查逃逸escape 如果数据块开头也是jbd2_magic 要标志逃逸
-            let mut tag = JournalBlockTagS {
-                t_blocknr: update.0 as u32,
-                t_checksum: 0,
-                t_flags: 0, //后面记得处理逃逸
-            };
-            let magic: u32 = u32::from_le_bytes(update.1[0..4].try_into().unwrap());
-            if magic == JBD2_MAGIC {
-                tag.t_flags |= JOURANL_ESCAPE;
-                debug!("JOURNAL ERROR ,Updates data escape!!!");
-            }
+        // 日志剩余空间放不下本事务时先 checkpoint，避免回绕覆盖尚未 checkpoint 的事务
+        let needed = self.commit_queue.len()
+            + self.commit_queue.len().div_ceil(self.tags_per_descriptor())
+            + self.revoke_table.len().div_ceil(self.revoke_records_per_block())
+            + 1;
+        if (self.usage().free_blocks() as usize) < needed {
+            self.checkpoint(block_dev).map_err(|_| ())?;
+        }
+
+        // 只有 revoke 记录的事务不需要 descriptor；
+        // 一个 descriptor 装不下时拆成多组 descriptor + 日志块
+        let csum = self.csum_v3();
+        let tag_bytes = self.tag_bytes();
+        let queue = core::mem::take(&mut self.commit_queue);
+        for chunk in queue.chunks(self.tags_per_descriptor()) {
+            let mut desc_buffer = [0u8; BLOCK_SIZE];
+
+            //写header->内存缓存
+            let mut new_jbd_header = JournalHeaderS::default();
+            new_jbd_header.h_blocktype = JBD2_DESCRIPTOR_BLOCK;
+            new_jbd_header.h_sequence = tid; //设置事务id
+            new_jbd_header.to_disk_bytes(&mut desc_buffer[0..JournalHeaderS::disk_size()]);
+
+            let mut current_offset = 12; //跳过头
+            let mut journal_data: Vec<[u8; BLOCK_SIZE]> = Vec::with_capacity(chunk.len());
+            for (idx, update) in chunk.iter().enumerate() {
+                //检查逃逸escape 如果数据块开头也是jbd2_magic 要标志逃逸，日志里开头 4 字节填 0
+                let mut data = update.1;
+                let mut t_flags = 0u16;
+                let magic: u32 = u32::from_le_bytes(data[0..4].try_into().unwrap());
+                if magic == JBD2_MAGIC {
+                    t_flags |= JOURANL_ESCAPE;
+                    data[0..4].fill(0);
+                    debug!("JOURNAL ERROR ,Updates data escape!!!");
+                }
 
-            //最后一个
-            if idx == self.commit_queue.len() - 1 {
-                tag.t_flags |= JBD2_FLAG_LAST_TAG;
+                //最后一个
+                if idx == chunk.len() - 1 {
+                    t_flags |= JBD2_FLAG_LAST_TAG;
+                }
+                debug!(
+                    "[JBD2 commit] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
+                    tid, idx, update.0, t_flags,
+                );
+                let tag_buf = &mut desc_buffer[current_offset..current_offset + tag_bytes];
+                if csum {
+                    JouranlBlockTag3S {
+                        t_blocknr: update.0 as u32,
+                        t_flags: t_flags as u32,
+                        t_blocknr_high: 0,
+                        t_checksum: self.block_csum(tid, &data),
+                    }
+                    .to_disk_bytes(tag_buf);
+                } else {
+                    JournalBlockTagS {
+                        t_blocknr: update.0 as u32,
+                        t_checksum: 0,
+                        t_flags,
+                    }
+                    .to_disk_bytes(tag_buf);
+                }
+                current_offset += tag_bytes;
+                journal_data.push(data);
+            }
+            if csum {
+                let tail = self.tail_csum(&desc_buffer);
+                desc_buffer[BLOCK_SIZE - 4..].copy_from_slice(&tail.to_be_bytes());
             }
+
+            //实际写入盘 这里可以直接写
+            let block_id = self.set_next_log_block(block_dev);
             debug!(
-                "[JBD2 commit] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
-                tid, idx, tag.t_blocknr, tag.t_flags,
+                "[JBD2 commit] tid={tid} descriptor_block_id={block_id} (absolute)"
             );
-            tag.to_disk_bytes(&mut desc_buffer[current_offset..current_offset + 8]);
-            current_offset += 8;
-        }
+            let mut log_blocks: Vec<(u64, &[u8])> = Vec::with_capacity(chunk.len() + 1);
+            log_blocks.push((block_id, &desc_buffer));
 
-        //实际写入盘 这里可以直接写
-        let block_id = self.set_next_log_block(block_dev);
-        debug!(
-            "[JBD2 commit] tid={tid} descriptor_block_id={block_id} (absolute)"
-        );
-        block_dev.write(&desc_buffer, block_id, 1).expect("Jouranl block write failed!");
-
-        let mut no_escape: Vec<(u64, [u8; BLOCK_SIZE])> = Vec::new();
-        //逃逸处理
-        for update in self.commit_queue.iter() {
-            //逃逸处理
-            let mut check_data: [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
-            check_data.copy_from_slice(&update.1);
-            let magic = u32::from_le_bytes(check_data[0..4].try_into().unwrap());
-            if magic == JBD2_MAGIC {
-                debug!("Find excape data,will fill 0");
-                check_data[0..4].fill(0);
+            //写实际的metadata CORE!!!!!
+            for (idx, (data, update)) in journal_data.iter().zip(chunk).enumerate() {
+                let metadata_journal_block_id = self.set_next_log_block(block_dev);
+                debug!(
+                    "[JBD2 commit] tid={} meta_idx={} journal_block_id={} (absolute) target_phys_block={}",
+                    tid, idx, metadata_journal_block_id, update.0
+                );
+                log_blocks.push((metadata_journal_block_id, data));
             }
-            no_escape.push((update.0, check_data));
+            write_log_runs(block_dev, &log_blocks).map_err(log_write_failed)?;
         }
 
-        //写实际的metadata CORE!!!!!
-        for (idx, up) in no_escape.iter().enumerate() {
-            let metadata_journal_block_id = self.set_next_log_block(block_dev);
-            debug!(
-                "[JBD2 commit] tid={} meta_idx={} journal_block_id={} (absolute) target_phys_block={}",
-                tid, idx, metadata_journal_block_id, up.0
-            );
-            block_dev.write(&up.1, metadata_journal_block_id, 1).expect("Jouranl block write failed!");
+        if !queue.is_empty() {
+            // commit 块之前的屏障：写入即持久的设备不需要
+            if block_dev.capabilities().flush {
+                block_dev.flush().map_err(log_write_failed)?;
+            }
+            //清空update缓存，这些块之后重放时可能被写回
+            self.logged.extend(queue.iter().map(|u| u.0 as u32));
+            for update in queue {
+                self.checkpoint_list.insert(update.0 as u32, Box::new(update.1));
+            }
+            debug!("[JBD2 BUFFER] BUFFER ALREADY CLEA");
         }
 
-        block_dev.flush().expect("Jouranl block write failed!");
+        //写 revoke 块
+        self.write_revoke_blocks(block_dev, tid).map_err(log_write_failed)?;
 
-        //清空update缓存
-        self.commit_queue.clear();
-        debug!("[JBD2 BUFFER] BUFFER ALREADY CLEA");
 
         //写入Commit Block
 
@@ -161,13 +387,18 @@ impl JBD2DEVSYSTEM {
         };
 
         commit_block.to_disk_bytes(&mut commit_buffer);
+        if self.csum_v3() {
+            // h_chksum[0] 置 0 时对整个 commit 块求校验和
+            let sum = crc32c(self.csum_seed(), &commit_buffer);
+            commit_buffer[16..20].copy_from_slice(&sum.to_be_bytes());
+        }
         let commit_block_id = self.set_next_log_block(block_dev);
         debug!(
             "[JBD2 commit] tid={tid} commit_block_id={commit_block_id} (absolute)"
         );
-        block_dev.write(&commit_buffer, commit_block_id, 1).expect("Jouranl block write failed!");
+        block_dev.write64(&commit_buffer, commit_block_id, 1).map_err(log_write_failed)?;
         //至此，commit已经完成，metadata数据已经安全:）
-        block_dev.flush().expect("Jouranl block write failed!");
+        block_dev.flush().map_err(log_write_failed)?;
         self.sequence += 1;
         debug!(
             "[JBD2 commit] end: tid={} new_sequence={}",
@@ -178,26 +409,25 @@ impl JBD2DEVSYSTEM {
         Ok(true)
     }
 
-    ///事务重放：从当前 superblock 状态开始，尽可能重放连续的完整事务 replay前确保全部commit
-    pub fn replay<B: BlockDevice>(&mut self, block_dev: &mut B) {
+    ///从 s_start 开始扫描连续的完整事务（若干 descriptor/revoke 块 + commit），不修改任何状态
+    /// 每找到一个完整事务调用一次 on_txn(设备, 事务)
+    fn scan_transactions<B: BlockDevice>(
+        &self,
+        block_dev: &mut B,
+        on_txn: &mut TxnVisitor<'_, B>,
+    ) -> ReplayScan {
         // 注意：journal_superblock_s 里的 s_first / s_start 是“日志区内部的相对块号”，
         // 真实物理块号 = self.start_block + rel。
-
-        // 扫描起点（相对块号）：只使用 s_start。s_start==0 表示没有需要重放的事务。
         let mut journal_rel = self.jbd2_super_block.s_start;
-        if journal_rel == 0 {
-            return;
-        }
-
         let first_rel = self.jbd2_super_block.s_first; // 第一个日志块（相对 superblock）
         let maxlen = self.jbd2_super_block.s_maxlen; // 可用日志块数量（不含 superblock）
         let last_rel = first_rel.saturating_add(maxlen.saturating_sub(1));
-        let mut expect_seq = self.jbd2_super_block.s_sequence;
-
-        // 简单防护：maxlen 为 0 直接返回
-        if maxlen == 0 {
-            return;
-        }
+        let mut scan = ReplayScan {
+            applied: 0,
+            next_seq: self.jbd2_super_block.s_sequence,
+            next_rel: journal_rel,
+            io_error: false,
+        };
 
         debug!(
             "[JBD2 replay] begin: journal_sb_phys={} first_rel={} last_rel={} s_start(rel)={} maxlen={} expect_seq={}",
@@ -206,7 +436,7 @@ impl JBD2DEVSYSTEM {
             last_rel,
             journal_rel,
             maxlen,
-            expect_seq,
+            scan.next_seq,
         );
 
         // 相对块号前进（含回绕）
@@ -218,116 +448,216 @@ impl JBD2DEVSYSTEM {
             }
         };
 
-        loop {
-            // 1) 读取 descriptor 块并做基本校验
-            let mut desc_buf = [0u8; BLOCK_SIZE];
-            let desc_phys = self.start_block + journal_rel; // descriptor 物理块号
-            if let Err(e) = block_dev.read(&mut desc_buf, desc_phys, 1) {
-                debug!(
-                    "[JBD2 replay] read descriptor failed at rel_block={journal_rel} phys_block={desc_phys} err={e:?}"
-                );
-                break;
-            }
-
-            let hdr = JournalHeaderS::from_disk_bytes(&desc_buf[0..12]);
-            debug!(
-                "[JBD2 replay] descriptor: phys_block={} h_magic=0x{:x} h_blocktype={} h_sequence={} expect_seq={}",
-                desc_phys, hdr.h_magic, hdr.h_blocktype, hdr.h_sequence, expect_seq
-            );
-            if hdr.h_magic != JBD2_MAGIC || hdr.h_blocktype != 1 {
-                // 不是合法的 descriptor，认为后面没有可重放事务
-                break;
-            }
-            if hdr.h_sequence != expect_seq {
-                // 序列号不匹配，认为没有更多可重放事务
-                break;
-            }
-
-            // 2) 解析 descriptor 里的 tags
-            let mut tags: Vec<JournalBlockTagS> = Vec::new();
-            let mut off = 12usize; // 跳过 header
-            let mut tag_idx = 0usize;
-            while off + 8 <= BLOCK_SIZE {
-                let tag = JournalBlockTagS::from_disk_bytes(&desc_buf[off..off + 8]);
-
-                // 注意：t_blocknr==0 在 ext4 上是合法的（例如 superblock/group desc 等元数据），
-                // 不能直接用 "t_blocknr==0" 当作 tag 结束条件。
-                // 我们只在“当前 8 字节全 0 且后续全部为 0 padding”时，才认为 descriptor 结束。
-                if tag.t_blocknr == 0 && tag.t_checksum == 0 && tag.t_flags == 0 {
-                    if desc_buf[off + 8..].iter().all(|b| *b == 0) {
-                        break;
-                    }
+        let csum = self.csum_v3();
+        let tag_bytes = self.tag_bytes();
+        // CSUM_V3 时 descriptor / revoke 块尾 4 字节是校验和
+        let tag_limit = if csum { BLOCK_SIZE - 4 } else { BLOCK_SIZE };
+
+        'txn: loop {
+            let expect_seq = scan.next_seq;
+            let mut txn = JournalTxn {
+                tid: expect_seq,
+                tags: Vec::new(),
+                blocks: Vec::new(),
+                revoked: Vec::new(),
+            };
+            // 一个事务由若干 descriptor / revoke 块组成，以同序列号的 commit 块结束
+            let mut walked = 0u32;
+            let commit_rel = loop {
+                let phys = self.start_block + journal_rel as u64;
+                let mut buf = [0u8; BLOCK_SIZE];
+                if let Err(e) = block_dev.read64(&mut buf, phys, 1) {
+                    debug!(
+                        "[JBD2 replay] read failed at rel_block={journal_rel} phys_block={phys} err={e:?}"
+                    );
+                    // 事务起点读失败视为日志结束，事务中途读失败则是 I/O 错误
+                    scan.io_error = walked != 0;
+                    return scan;
+                }
+                walked += 1;
+                if walked > maxlen {
+                    // 绕了整整一圈也没找到 commit，日志已损坏
+                    break 'txn;
                 }
 
+                let hdr = JournalHeaderS::from_disk_bytes(&buf[0..12]);
                 debug!(
-                    "[JBD2 replay] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
-                    expect_seq, tag_idx, tag.t_blocknr, tag.t_flags
+                    "[JBD2 replay] block: phys_block={} h_magic=0x{:x} h_blocktype={} h_sequence={} expect_seq={}",
+                    phys, hdr.h_magic, hdr.h_blocktype, hdr.h_sequence, expect_seq
                 );
+                if hdr.h_magic != JBD2_MAGIC || hdr.h_sequence != expect_seq {
+                    // 不是本事务的日志块，认为后面没有可重放事务
+                    break 'txn;
+                }
 
-                let last = (tag.t_flags & JBD2_FLAG_LAST_TAG) != 0;
-                tags.push(tag);
-                off += 8;
-                tag_idx += 1;
-
-                if last {
-                    break;
+                // 校验和不对的事务视为撕裂，连同之后的事务一起丢弃
+                let tail_ok = |buf: &[u8; BLOCK_SIZE]| {
+                    u32::from_be_bytes(buf[BLOCK_SIZE - 4..].try_into().unwrap())
+                        == self.tail_csum(buf)
+                };
+                match hdr.h_blocktype {
+                    JBD2_DESCRIPTOR_BLOCK | JBD2_REVOKE_BLOCK if csum && !tail_ok(&buf) => {
+                        warn!("[JBD2 replay] tid={expect_seq} bad block tail checksum, treat as torn");
+                        break 'txn;
+                    }
+                    JBD2_DESCRIPTOR_BLOCK => {
+                        // 解析 descriptor 里的 tags
+                        let mut tags: Vec<JournalBlockTagS> = Vec::new();
+                        let mut csums: Vec<u32> = Vec::new();
+                        let mut off = 12usize; // 跳过 header
+                        while off + tag_bytes <= tag_limit {
+                            let tag = if csum {
+                                let tag3 = JouranlBlockTag3S::from_disk_bytes(&buf[off..off + 16]);
+                                csums.push(tag3.t_checksum);
+                                JournalBlockTagS {
+                                    t_blocknr: tag3.t_blocknr,
+                                    t_checksum: 0,
+                                    t_flags: tag3.t_flags as u16,
+                                }
+                            } else {
+                                JournalBlockTagS::from_disk_bytes(&buf[off..off + 8])
+                            };
+
+                            // 注意：t_blocknr==0 在 ext4 上是合法的（例如 superblock/group desc 等元数据），
+                            // 不能直接用 "t_blocknr==0" 当作 tag 结束条件。
+                            // 我们只在“当前 8 字节全 0 且后续全部为 0 padding”时，才认为 descriptor 结束。
+                            if buf[off..tag_limit].iter().all(|b| *b == 0) {
+                                break;
+                            }
+
+                            debug!(
+                                "[JBD2 replay] tid={} tag_idx={} t_blocknr={} t_flags=0x{:x}",
+                                expect_seq, tags.len(), tag.t_blocknr, tag.t_flags
+                            );
+
+                            let last = (tag.t_flags & JBD2_FLAG_LAST_TAG) != 0;
+                            tags.push(tag);
+                            off += tag_bytes;
+
+                            if last {
+                                break;
+                            }
+                        }
+                        if tags.is_empty() {
+                            // 没有任何 tag，无事务可重放
+                            break 'txn;
+                        }
+
+                        // 读取对应数量的 metadata 日志块
+                        for idx in 0..tags.len() {
+                            // 下一个 journal 块（相对块号），注意处理回绕
+                            advance_rel(&mut journal_rel);
+                            let meta_phys = self.start_block + journal_rel as u64;
+                            let mut mbuf = [0u8; BLOCK_SIZE];
+                            if let Err(e) = block_dev.read64(&mut mbuf, meta_phys, 1) {
+                                debug!(
+                                    "[JBD2 replay] read meta block failed: idx={idx} rel_block={journal_rel} phys_block={meta_phys} err={e:?}"
+                                );
+                                scan.io_error = true;
+                                return scan;
+                            }
+                            debug!(
+                                "[JBD2 replay] tid={expect_seq} loaded meta_idx={idx} from rel_block={journal_rel} phys_block={meta_phys}"
+                            );
+                            if csum && csums.get(idx) != Some(&self.block_csum(expect_seq, &mbuf)) {
+                                warn!("[JBD2 replay] tid={expect_seq} meta_idx={idx} bad checksum, treat as torn");
+                                break 'txn;
+                            }
+                            txn.blocks.push(mbuf);
+                        }
+                        walked += tags.len() as u32;
+                        txn.tags.extend(tags);
+                    }
+                    JBD2_REVOKE_BLOCK => {
+                        // r_count 为块内已用字节数（含 16 字节头），其后是 be32 块号
+                        let head = Jbd2JournalRevokeHeadS::from_disk_bytes(&buf[0..16]);
+                        let used = (head.r_count as usize).min(BLOCK_SIZE);
+                        let mut off = 16usize;
+                        while off + 4 <= used {
+                            let blk = u32::from_be_bytes(buf[off..off + 4].try_into().unwrap());
+                            debug!("[JBD2 replay] tid={expect_seq} revoke phys_block={blk}");
+                            txn.revoked.push(blk);
+                            off += 4;
+                        }
+                    }
+                    JBD2_COMMIT_BLOCK => {
+                        if csum {
+                            let stored = u32::from_be_bytes(buf[16..20].try_into().unwrap());
+                            buf[16..20].fill(0);
+                            if crc32c(self.csum_seed(), &buf) != stored {
+                                warn!("[JBD2 replay] tid={expect_seq} bad commit checksum, treat as torn");
+                                break 'txn;
+                            }
+                        }
+                        break journal_rel;
+                    }
+                    _ => break 'txn,
                 }
-            }
+                advance_rel(&mut journal_rel);
+            };
 
-            if tags.is_empty() {
-                // 没有任何 tag，无事务可重放
-                break;
-            }
+            on_txn(block_dev, &mut txn);
 
-            // 3) 读取对应数量的 metadata 日志块
-            let mut meta_blocks: Vec<[u8; BLOCK_SIZE]> = Vec::new();
-            for (idx, _) in tags.iter().enumerate() {
-                // 下一个 journal 块（相对块号），注意处理回绕
-                advance_rel(&mut journal_rel);
-                let meta_phys = self.start_block + journal_rel;
-                let mut mbuf = [0u8; BLOCK_SIZE];
-                if let Err(e) = block_dev.read(&mut mbuf, meta_phys, 1) {
-                    debug!(
-                        "[JBD2 replay] read meta block failed: idx={idx} rel_block={journal_rel} phys_block={meta_phys} err={e:?}"
-                    );
-                    return;
-                }
-                debug!(
-                    "[JBD2 replay] tid={expect_seq} loaded meta_idx={idx} from rel_block={journal_rel} phys_block={meta_phys}"
-                );
-                meta_blocks.push(mbuf);
-            }
+            // s_start 指向下一个事务起点（commit 后一块），保持为相对块号
+            let mut next_desc_rel = commit_rel;
+            advance_rel(&mut next_desc_rel);
+            scan.applied += 1;
+            scan.next_seq = expect_seq.wrapping_add(1);
+            scan.next_rel = next_desc_rel;
 
-            // 4) 读取 commit 块并验证
-            advance_rel(&mut journal_rel);
-            let commit_rel = journal_rel;
-            let commit_phys = self.start_block + commit_rel;
-            let mut cbuf = [0u8; BLOCK_SIZE];
-            if let Err(e) = block_dev.read(&mut cbuf, commit_phys, 1) {
-                debug!(
-                    "[JBD2 replay] read commit failed at rel_block={commit_rel} phys_block={commit_phys} err={e:?}"
-                );
-                return;
-            }
-            let chdr = JournalHeaderS::from_disk_bytes(&cbuf[0..12]);
             debug!(
-                "[JBD2 replay] commit: rel_block={} phys_block={} h_magic=0x{:x} h_blocktype={} h_sequence={} expect_seq={}",
-                commit_rel, commit_phys, chdr.h_magic, chdr.h_blocktype, chdr.h_sequence, expect_seq
+                "[JBD2 replay] transaction applied: new_sequence={} new_s_start(rel)={}",
+                scan.next_seq, scan.next_rel
             );
-            if chdr.h_magic != JBD2_MAGIC || chdr.h_blocktype != 2 || chdr.h_sequence != expect_seq
-            {
-                // 没有匹配的 commit，事务不完整，不再继续
-                break;
-            }
 
-            // 5) 真正重放：把每个 metadata 块写回主盘对应的 t_blocknr
-            for (i, tag) in tags.iter().enumerate() {
+            // 下一轮从新的 descriptor 起点开始
+            journal_rel = next_desc_rel;
+        }
+        scan
+    }
+
+    ///事务重放：从当前 superblock 状态开始，尽可能重放连续的完整事务 replay前确保全部commit
+    /// 返回重放的事务数
+    pub fn replay<B: BlockDevice>(&mut self, block_dev: &mut B) -> u32 {
+        self.replay_with_progress(block_dev, &mut |_, _| {})
+    }
+
+    ///带进度回调的事务重放
+    /// 先扫描一遍统计完整事务总数并收集 revoke 记录，重放时每应用一个事务调用一次 progress(已处理, 总数)
+    /// 某块在事务 T 中被 revoke 后，事务号 <= T 的日志副本都不再写回
+    pub fn replay_with_progress<B: BlockDevice>(
+        &mut self,
+        block_dev: &mut B,
+        progress: &mut dyn FnMut(u32, u32),
+    ) -> u32 {
+        if self.jbd2_super_block.s_start == 0 || self.jbd2_super_block.s_maxlen == 0 {
+            return 0;
+        }
+
+        // 主盘块号 -> 撤销它的最大事务号
+        let mut revoked: BTreeMap<u32, u32> = BTreeMap::new();
+        let total = self
+            .scan_transactions(block_dev, &mut |_, txn| {
+                for &blk in &txn.revoked {
+                    let tid = revoked.entry(blk).or_insert(txn.tid);
+                    *tid = (*tid).max(txn.tid);
+                }
+            })
+            .applied;
+        let mut done = 0;
+        let scan = self.scan_transactions(block_dev, &mut |dev, txn| {
+            let tid = txn.tid;
+            // 真正重放：把每个 metadata 块写回主盘对应的 t_blocknr
+            for (i, tag) in txn.tags.iter().enumerate() {
                 let phys = tag.t_blocknr;
-                let data = &mut meta_blocks[i];
+                if revoked.get(&phys).is_some_and(|&r| r >= tid) {
+                    debug!("[JBD2 replay] tid={tid} skip revoked phys_block={phys}");
+                    continue;
+                }
+                let data = &mut txn.blocks[i];
 
                 //检查是否逃逸
-                if (tag.t_flags & 1) != 0 {
-                    // JBD2_FLAG_ESCAPE = 1
+                if (tag.t_flags & JOURANL_ESCAPE) != 0 {
                     let magic_bytes = JBD2_MAGIC.to_be_bytes();
                     data[0] = magic_bytes[0];
                     data[1] = magic_bytes[1];
@@ -336,32 +666,22 @@ impl JBD2DEVSYSTEM {
                     debug!("Restored JBD2 Magic for block {phys}");
                 }
                 debug!(
-                    "[JBD2 replay] tid={expect_seq} apply meta_idx={i} to phys_block={phys} (journal data from idx={i})"
+                    "[JBD2 replay] tid={tid} apply meta_idx={i} to phys_block={phys} (journal data from idx={i})"
                 );
 
-                let _ = block_dev.write(data, phys, 1);
+                let _ = dev.write(data, phys, 1);
             }
-            let _ = block_dev.flush();
-
-            // 6) 更新内存中的 journal superblock 状态
-            expect_seq = expect_seq.wrapping_add(1);
-            self.jbd2_super_block.s_sequence = expect_seq;
-            //更新内存
-            self.sequence = expect_seq;
-
-            // s_start 指向下一个事务起点（commit 后一块），保持为相对块号
-            let mut next_desc_rel = commit_rel;
-            advance_rel(&mut next_desc_rel);
-            self.jbd2_super_block.s_start = next_desc_rel;
-
-            debug!(
-                "[JBD2 replay] transaction applied: new_sequence={} new_s_start(rel)={}",
-                self.jbd2_super_block.s_sequence,
-                self.jbd2_super_block.s_start
-            );
-
-            // 下一轮从新的 descriptor 起点开始
-            journal_rel = next_desc_rel;
+            let _ = dev.flush();
+            done += 1;
+            progress(done, total);
+        });
+
+        // 更新内存中的 journal superblock 状态
+        self.jbd2_super_block.s_sequence = scan.next_seq;
+        self.sequence = scan.next_seq;
+        self.jbd2_super_block.s_start = scan.next_rel;
+        if scan.io_error {
+            return scan.applied;
         }
 
         // 已经没有更多可重放事务：将 s_start 置 0 表示 journal clean
@@ -373,14 +693,14 @@ impl JBD2DEVSYSTEM {
         let sb_block = self.start_block;
         if sb_block != 0 {
             let mut blk = [0u8; BLOCK_SIZE];
-            if block_dev.read(&mut blk, sb_block, 1).is_ok() {
-                self.jbd2_super_block.to_disk_bytes(&mut blk[0..1024]);
+            if block_dev.read64(&mut blk, sb_block, 1).is_ok() {
+                self.encode_superblock(&mut blk[0..1024]);
                 debug!(
                     "[JBD2 replay] write journal superblock to block={} (sequence={} s_start={})",
                     sb_block, self.jbd2_super_block.s_sequence, self.jbd2_super_block.s_start
                 );
                 //直接写，避免鬼打墙
-                let _ = block_dev.write(&blk, sb_block, 1);
+                let _ = block_dev.write64(&blk, sb_block, 1);
                 let _ = block_dev.flush();
             }
         }
@@ -388,14 +708,83 @@ impl JBD2DEVSYSTEM {
         "[JBD2 replay] end: final_sequence={} final_s_start={} ",
         self.jbd2_super_block.s_sequence, self.jbd2_super_block.s_start
     );
+        scan.applied
+    }
+
+}
+
+/// 挂载前的日志崩溃恢复
+/// 不依赖已挂载的文件系统：直接从超级块、0 号块组描述符定位 journal inode，读出 journal 超级块；
+/// s_start != 0 说明有未 checkpoint 的事务，按顺序把完整事务写回主盘（遵守 revoke 记录）。
+/// 返回重放的事务数；未开启日志、没有 journal 或日志干净时返回 0
+pub fn jbd2_recover<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) -> BlockDevResult<u32> {
+    if !block_dev.is_use_journal() {
+        return Ok(0);
+    }
+    let mut buf = [0u8; BLOCK_SIZE];
+
+    // 1) 超级块（固定在分区偏移 1024 字节）；校验和是否可接受交给之后的 probe 按策略判断
+    let mut sb = Ext4Superblock::default();
+    let sb_ok = match block_dev.with_raw_device(|dev| ext4_sb_read(dev, &mut sb)) {
+        Ok(()) => true,
+        Err(BlockDevError::ChecksumError) => false,
+        Err(e) => return Err(e),
+    };
+    if !sb.has_journal() {
+        return Ok(0);
     }
-    
+
+    // 2) journal inode 位于 0 号块组的 inode 表
+    let desc_size = sb.get_desc_size() as usize;
+    block_dev.read_blocks(&mut buf, 1, 1)?;
+    let gd0 = Ext4GroupDesc::from_disk_bytes(&buf[..desc_size]);
+    let inode_size = sb.inode_size() as usize;
+    let byte = (JOURNAL_FILE_INODE as usize - 1) * inode_size;
+    block_dev.read_blocks(&mut buf, gd0.inode_table() + (byte / BLOCK_SIZE) as u64, 1)?;
+    let off = byte % BLOCK_SIZE;
+    let mut j_inode = Ext4Inode::from_disk_bytes(&buf[off..off + inode_size]);
+    if j_inode.i_mode == 0 {
+        // journal 还没创建，首次挂载时再建
+        return Ok(0);
+    }
+
+    // 3) journal 超级块
+    if !j_inode.have_extend_header_and_use_extend() {
+        return Err(BlockDevError::Unsupported);
+    }
+    let journal_first_block = ExtentTree::new(&mut j_inode)
+        .with_superblock_checksum(&sb, JOURNAL_FILE_INODE as u32)
+        .find_extent(block_dev, 0)?
+        .filter(|e| e.ee_block == 0 && extent_block_count(e) > 0)
+        .map(|e| ((e.ee_start_hi as u64) << 32) | e.ee_start_lo as u64)
+        .ok_or(BlockDevError::Corrupted)?;
+    block_dev.read_blocks(&mut buf, journal_first_block, 1)?;
+    let j_sb = JournalSuperBllockS::from_disk_bytes(&buf);
+    if j_sb.s_header.h_magic != JBD2_MAGIC {
+        return Err(BlockDevError::Corrupted);
+    }
+    let applied = if j_sb.s_start == 0 {
+        0
+    } else {
+        info!("Journal needs recovery: s_start={} sequence={}", j_sb.s_start, j_sb.s_sequence);
+        block_dev.set_journal_superblock(j_sb, journal_first_block);
+        let applied = block_dev.journal_replay();
+        info!("Journal recovery done: {applied} transactions replayed");
+        applied
+    };
+
+    // 日志已清空，撤掉 needs_recovery；超级块校验和本身不对时不改写，留给挂载时按策略处理
+    if sb_ok && sb.s_feature_incompat & Ext4Superblock::EXT4_FEATURE_INCOMPAT_RECOVER != 0 {
+        sb.s_feature_incompat &= !Ext4Superblock::EXT4_FEATURE_INCOMPAT_RECOVER;
+        block_dev.with_raw_device(|dev| ext4_sb_write(dev, &mut sb))?;
+    }
+    Ok(applied)
 }
 
 ///dump jouranl inode
 pub fn dump_journal_inode<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &mut Jbd2Dev<B>) {
     let mut indo = fs.get_inode_by_num(block_dev, 8).expect("journal");
-    let datablock = resolve_inode_block( block_dev, &mut indo, 0)
+    let datablock = resolve_inode_block(block_dev, fs, JOURNAL_FILE_INODE as u32, &mut indo, 0)
         .unwrap()
         .unwrap();
     let journal_data = fs
@@ -409,29 +798,70 @@ pub fn dump_journal_inode<B: BlockDevice>(fs: &mut Ext4FileSystem, block_dev: &m
     debug!("Jouranl Inode:{indo:?}");
 }
 
+/// 按日志位置顺序写入一组日志块，位置连续（未回绕）的块合并成一次设备写
+/// 日志块写入失败：记录原因，commit_transaction 以 Err(()) 返回
+fn log_write_failed(e: BlockDevError) {
+    error!("journal block write failed: {e:?}");
+}
+
+fn write_log_runs<B: BlockDevice>(block_dev: &mut B, blocks: &[(u64, &[u8])]) -> BlockDevResult<()> {
+    let mut i = 0;
+    while i < blocks.len() {
+        let start = blocks[i].0;
+        let mut n = 1;
+        while i + n < blocks.len() && blocks[i + n].0 == start + n as u64 {
+            n += 1;
+        }
+        if n == 1 {
+            block_dev.write64(blocks[i].1, start, 1)?;
+        } else {
+            let mut buf = Vec::with_capacity(n * BLOCK_SIZE);
+            for (_, data) in &blocks[i..i + n] {
+                buf.extend_from_slice(&data[..BLOCK_SIZE]);
+            }
+            block_dev.write64(&buf, start, n as u32)?;
+        }
+        i += n;
+    }
+    Ok(())
+}
+
 ///jouranl目录创建 journal超级块写入
+/// 根据空闲块数确定新 journal 的大小：
+/// 默认 JOURNAL_DEFAULT_BLOCKS，超过空闲块一半时自动缩小，
+/// 缩到 JOURNAL_MIN_BLOCKS 以下仍放不下时返回 None
+pub fn journal_blocks_for(free_blocks: u64) -> Option<u32> {
+    let blocks = (free_blocks / 2).min(JOURNAL_DEFAULT_BLOCKS as u64) as u32;
+    (blocks >= JOURNAL_MIN_BLOCKS).then_some(blocks)
+}
+
 pub fn create_journal_entry<B: BlockDevice>(
     fs: &mut Ext4FileSystem,
     block_dev: &mut Jbd2Dev<B>,
 ) -> BlockDevResult<()> {
     //分配新数据块放superblock
     let journal_inode_num = JOURNAL_FILE_INODE;
-    let free_block = fs
-        .alloc_blocks(block_dev, 4096)
-        .expect("No enough block can alloc out!");
+    let Some(journal_blocks) = journal_blocks_for(fs.superblock.free_blocks_count()) else {
+        warn!("Not enough free blocks for a journal");
+        return Err(BlockDevError::NoSpace);
+    };
+    if journal_blocks < JOURNAL_DEFAULT_BLOCKS {
+        info!("Small filesystem: journal shrunk to {journal_blocks} blocks");
+    }
+    let free_block = fs.alloc_blocks(block_dev, journal_blocks)?;
 
     // Ensure journal area starts clean: otherwise old image contents could look like valid
     // descriptor/commit blocks and replay would corrupt filesystem metadata.
     let zero = [0u8; BLOCK_SIZE];
     for &b in free_block.iter() {
-        block_dev.write_blocks(&zero, b as u32, 1, true)?;
+        block_dev.write_blocks(&zero, b, 1, true)?;
     }
     //journal inode 额外参数
     let mut jour_inode = fs
         .get_inode_by_num(block_dev, journal_inode_num as u32)
         .unwrap();
     jour_inode.write_extend_header();
-    build_file_block_mapping(fs, &mut jour_inode, &free_block, block_dev);
+    build_file_block_mapping(fs, &mut jour_inode, journal_inode_num as u32, &free_block, block_dev);
     debug!("When create jouranl inode: iblock:{:?}", jour_inode.i_block);
     let inode_size: usize = BLOCK_SIZE * free_block.len();
     //初始化 然后写入 journal inode
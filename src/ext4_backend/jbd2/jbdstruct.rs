@@ -1,24 +1,57 @@
 use crate::ext4_backend::config::*;
 use crate::ext4_backend::endian::*;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::vec::Vec;
 use core::convert::TryInto;
 pub const JOURNAL_FILE_INODE: u64 = 8;
 /// 根据 ext4 标准，journal 的 inode 为 8
 pub const JBD2_MAGIC: u32 = 0xC03B_3998u32; // jbd2 magic number (on-disk big-endian)
 pub const JOURNAL_BLOCK_COUNT: u32 = 32 * 1024 * 1024 / BLOCK_SIZE_U32;
+/// 新建 journal 的默认块数（含 journal 超级块）
+pub const JOURNAL_DEFAULT_BLOCKS: u32 = 4096;
+/// journal 的最小块数，小镜像上的 journal 不会缩到这个值以下
+pub const JOURNAL_MIN_BLOCKS: u32 = 1024;
 pub const JOURANL_ESCAPE: u16 = 0x1;
 pub const JBD2_FLAG_LAST_TAG: u16 = 0x8;
+/// 日志块类型（journal_header_s.h_blocktype）
+pub const JBD2_DESCRIPTOR_BLOCK: u32 = 1;
+pub const JBD2_COMMIT_BLOCK: u32 = 2;
+pub const JBD2_REVOKE_BLOCK: u32 = 5;
+/// 日志校验和 v3 特性位（s_feature_incompat）
+pub const JBD2_FEATURE_INCOMPAT_CSUM_V3: u32 = 0x10;
+/// s_checksum_type：crc32c
+pub const JBD2_CRC32C_CHKSUM: u8 = 4;
 #[repr(C)]
 ///（主物理块号，元数据内容）
 pub struct Jbd2Update(pub u64, pub [u8; BLOCK_SIZE]);
 #[repr(C)]
 pub struct JBD2DEVSYSTEM {
     pub jbd2_super_block: JournalSuperBllockS,
-    pub start_block: u32, // Journal 超级块 开始块号
+    pub start_block: u64, // Journal 超级块 开始块号
     pub max_len: u32,     // 日志总块数
-    pub head: u32,        //commit游标(相对块号)
+    pub head: u32,        //commit游标：从 s_start 起已写出的日志块数
     pub sequence: u32,    //当前期待事务ID(验证和写commit用)
     pub commit_queue: Vec<Jbd2Update>, //事务缓存
+    pub revoke_table: BTreeMap<u32, u32>, //待写出的 revoke 记录（主盘块号 -> 撤销它的事务号）
+    pub logged: BTreeSet<u32>, //本次挂载后已提交进日志、重放时会被写回的主盘块号
+    pub checkpoint_list: BTreeMap<u32, Box<[u8; BLOCK_SIZE]>>, //已提交、尚未 checkpoint 的元数据（主盘块号 -> 最新内容）
+}
+
+/// 日志空间占用情况
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JournalUsage {
+    /// 尚未 checkpoint 的事务占用的日志块数
+    pub used_blocks: u32,
+    /// 日志区可用于事务的总块数（不含 journal 超级块）
+    pub total_blocks: u32,
+}
+
+impl JournalUsage {
+    /// 剩余可写的日志块数
+    pub fn free_blocks(&self) -> u32 {
+        self.total_blocks.saturating_sub(self.used_blocks)
+    }
 }
 
 #[repr(C)]
@@ -305,7 +338,14 @@ impl DiskFormat for Jbd2JournalBlockTail {
     }
 }
 
-// Revocation block header
+/// Revocation block header
+///
+/// revoke 块磁盘格式（全部大端）：
+/// - 0x00 journal_header_s：h_magic / h_blocktype=5 / h_sequence=所属事务号
+/// - 0x0C r_count：本块已用字节数，含 16 字节头
+/// - 0x10 起：(r_count - 16) / 4 个 be32 主盘块号
+///
+/// 重放时，事务号 <= h_sequence 的事务里这些块的日志副本都不再写回
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Jbd2JournalRevokeHeadS {
@@ -1,11 +1,13 @@
 //文件遍历
 
 use alloc::collections::BTreeMap;
+use alloc::string::ToString;
 use alloc::vec::Vec;
 use log::{error, info};
 
 use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::config::*;
+use crate::ext4_backend::dir::dir_csum_seed;
 use crate::ext4_backend::disknode::*;
 use crate::ext4_backend::entries::*;
 use crate::ext4_backend::ext4::*;
@@ -16,20 +18,19 @@ use log::debug;
 
 ///支持extend数和多级索引(多级索引将来弃用)
 /// 根据 inode 的逻辑块号解析到物理块号，支持 12 个直接块和 1/2/3 级间接块
+/// `ino` 为 inode 号，启用 metadata_csum 时用于核对 extent 块校验和
 pub fn resolve_inode_block<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
+    fs: &Ext4FileSystem,
+    ino: u32,
     inode: &mut Ext4Inode,
     logical_block: u32,
-) -> BlockDevResult<Option<u32>> {
+) -> BlockDevResult<Option<u64>> {
     // 优先走 extent 树（支持多层索引）；失败时再回退到传统多级指针逻辑
     if inode.have_extend_header_and_use_extend() {
-        let mut tree = ExtentTree::new(inode);
+        let mut tree = ExtentTree::new(inode).with_checksum(fs, ino);
         if let Some(ext) = tree.find_extent(block_dev, logical_block)? {
-            let mut len = ext.ee_len as u32;
-            // 最高位表示 uninitialized 标志，长度使用低 15 位
-            if (len & 0x8000) != 0 {
-                len &= 0x7FFF;
-            }
+            let len = extent_block_count(&ext);
             if len == 0 {
                 return Ok(None);
             }
@@ -40,11 +41,7 @@ pub fn resolve_inode_block<B: BlockDevice>(
             }
 
             let base = ((ext.ee_start_hi as u64) << 32) | ext.ee_start_lo as u64;
-            let phys = base + (logical_block - start_lbn) as u64;
-            if phys > u32::MAX as u64 {
-                return Err(BlockDevError::Corrupted);
-            }
-            return Ok(Some(phys as u32));
+            return Ok(Some(base + (logical_block - start_lbn) as u64));
         }
         error!("Can't find proper extend for this logical block");
         return Err(BlockDevError::ReadError);
@@ -56,70 +53,134 @@ pub fn resolve_inode_block<B: BlockDevice>(
     
 }
 
-pub fn resolve_inode_block_allextend<B: BlockDevice>(
-    _fs: &mut Ext4FileSystem,
+/// 遍历整棵 extent 树，按逻辑块号顺序收集所有叶子 extent
+/// 启用 metadata_csum 时按 inode ino 的种子核对每个非根节点块的校验和
+pub fn collect_inode_extents<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
+    fs: &Ext4FileSystem,
+    ino: u32,
     inode: &mut Ext4Inode,
-) -> BlockDevResult<BTreeMap<u32, u64>> {
+) -> BlockDevResult<Vec<Ext4Extent>> {
     if !inode.have_extend_header_and_use_extend() {
-        return Ok(BTreeMap::new());
-    }
-
-    fn push_extent_blocks(out: &mut Vec<(u32, u64)>, ext: &Ext4Extent) {
-        let mut len = ext.ee_len as u32;
-        // 最高位表示 uninitialized 标志，长度使用低 15 位
-        if (len & 0x8000) != 0 {
-            len &= 0x7FFF;
-        }
-        if len == 0 {
-            return;
-        }
-        let base = ((ext.ee_start_hi as u64) << 32) | ext.ee_start_lo as u64;
-        for i in 0..len {
-            let lbn = ext.ee_block.saturating_add(i);
-            out.push((lbn, base + i as u64));
-        }
+        return Ok(Vec::new());
     }
 
     fn walk_node<B: BlockDevice>(
         dev: &mut Jbd2Dev<B>,
+        tree: &ExtentTree,
         node: &ExtentNode,
-        out: &mut Vec<(u32, u64)>,
+        out: &mut Vec<Ext4Extent>,
     ) -> BlockDevResult<()> {
         match node {
             ExtentNode::Leaf { entries, .. } => {
-                for ext in entries {
-                    push_extent_blocks(out, ext);
-                }
+                out.extend_from_slice(entries);
                 Ok(())
             }
             ExtentNode::Index { entries, .. } => {
                 for idx in entries {
                     let child_block = ((idx.ei_leaf_hi as u64) << 32) | (idx.ei_leaf_lo as u64);
-                    dev.read_block(child_block as u32)?;
-                    let buf = dev.buffer();
-                    let child = ExtentTree::parse_node(buf).ok_or(BlockDevError::Corrupted)?;
-                    walk_node(dev, &child, out)?;
+                    dev.read_block(child_block)?;
+                    tree.check_node_block(dev.buffer())?;
+                    let child = ExtentTree::parse_node_checked(dev.buffer())?;
+                    walk_node(dev, tree, &child, out)?;
                 }
                 Ok(())
             }
         }
     }
 
-    let tree = ExtentTree::new(inode);
-    let root = match tree.load_root_from_inode() {
+    let tree = ExtentTree::new(inode).with_checksum(fs, ino);
+    let root = match tree.load_root_checked()? {
         Some(n) => n,
-        None => return Ok(BTreeMap::new()),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut extents: Vec<Ext4Extent> = Vec::new();
+    walk_node(block_dev, &tree, &root, &mut extents)?;
+    // 各节点内部已校验有序，不同叶子之间仍可能重叠，整体再校验一次
+    verify_extent_order(&extents)?;
+    Ok(extents)
+}
+
+/// 收集 extent 树中除根（在 inode 内）之外的全部索引块和叶子块
+/// 回收 inode 时这些块和数据块一样需要释放
+pub fn collect_extent_tree_blocks<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &Ext4FileSystem,
+    ino: u32,
+    inode: &mut Ext4Inode,
+) -> BlockDevResult<Vec<u64>> {
+    if !inode.have_extend_header_and_use_extend() {
+        return Ok(Vec::new());
+    }
+    let tree = ExtentTree::new(inode).with_checksum(fs, ino);
+    let Some(root) = tree.load_root_checked()? else {
+        return Ok(Vec::new());
     };
 
-    let mut blocks: Vec<(u32, u64)> = Vec::new();
-    walk_node(block_dev, &root, &mut blocks)?;
-    blocks.sort_unstable_by_key(|(lbn, _)| *lbn);
-    blocks.dedup_by_key(|(lbn, _)| *lbn);
+    let mut blocks: Vec<u64> = Vec::new();
+    let mut pending: Vec<ExtentNode> = Vec::new();
+    pending.push(root);
+    while let Some(node) = pending.pop() {
+        let ExtentNode::Index { entries, .. } = node else {
+            continue;
+        };
+        for idx in entries {
+            let child_block = ((idx.ei_leaf_hi as u64) << 32) | (idx.ei_leaf_lo as u64);
+            blocks.push(child_block);
+            block_dev.read_block(child_block)?;
+            tree.check_node_block(block_dev.buffer())?;
+            pending.push(ExtentTree::parse_node_checked(block_dev.buffer())?);
+        }
+    }
+    Ok(blocks)
+}
+
+/// inode 的 extent 记录数（所有叶子中的条目总数）
+pub fn extent_count<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &Ext4FileSystem,
+    ino: u32,
+    inode: &mut Ext4Inode,
+) -> BlockDevResult<usize> {
+    Ok(collect_inode_extents(block_dev, fs, ino, inode)?.len())
+}
 
+/// 把 extent 列表中落在 [start_lbn, end_lbn) 内的块展开为 逻辑块号 -> 物理块号
+/// 不在映射中的块（空洞）以及未初始化 extent 中的块都不返回，读取时应视为全 0，
+/// 只展开请求的范围，稀疏大文件的读取开销与文件大小无关
+pub fn map_extent_range(extents: &[Ext4Extent], start_lbn: u64, end_lbn: u64) -> BTreeMap<u32, u64> {
     let mut out = BTreeMap::new();
-    for (lbn, phys) in blocks {
-        out.insert(lbn, phys);
+    for ext in extents.iter().filter(|e| e.is_initialized()) {
+        let ext_start = ext.ee_block as u64;
+        let ext_end = ext_start + extent_block_count(ext) as u64;
+        let from = ext_start.max(start_lbn);
+        let to = ext_end.min(end_lbn);
+        for lbn in from..to {
+            out.entry(lbn as u32).or_insert(ext.start_block() + (lbn - ext_start));
+        }
+    }
+    out
+}
+
+pub fn resolve_inode_block_allextend<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    ino: u32,
+    inode: &mut Ext4Inode,
+) -> BlockDevResult<BTreeMap<u32, u64>> {
+    let mut out = BTreeMap::new();
+    for ext in collect_inode_extents(block_dev, fs, ino, inode)? {
+        let mut len = ext.ee_len as u32;
+        // 最高位表示 uninitialized 标志，长度使用低 15 位
+        if (len & 0x8000) != 0 {
+            len &= 0x7FFF;
+        }
+        let base = ((ext.ee_start_hi as u64) << 32) | ext.ee_start_lo as u64;
+        for i in 0..len {
+            let lbn = ext.ee_block.saturating_add(i);
+            out.entry(lbn).or_insert(base + i as u64);
+        }
     }
     Ok(out)
 }
@@ -130,32 +191,84 @@ pub fn get_file_inode<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
     path: &str,
 ) -> BlockDevResult<Option<(u32, Ext4Inode)>> {
+    Ok(walk_path(fs, block_dev, path)?.ok())
+}
+
+/// 同 get_file_inode，但解析失败时返回出错的分量：
+/// 分量不存在返回 NotFound，把非目录当目录使用返回 NotADirectory（携带该非目录分量）
+pub fn lookup_path<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    path: &str,
+) -> Ext4Result<(u32, Ext4Inode)> {
+    walk_path(fs, block_dev, path)?
+}
 
+/// 同 lookup_path，但要求解析结果是目录，否则返回携带最后一个分量的 NotADirectory
+pub fn lookup_dir<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    path: &str,
+) -> Ext4Result<(u32, Ext4Inode)> {
+    let (ino, inode) = lookup_path(fs, block_dev, path)?;
+    if !inode.is_dir() {
+        return Err(not_a_directory(path));
+    }
+    Ok((ino, inode))
+}
+
+/// 路径本身存在但不是目录时的错误，归咎于最后一个分量
+pub fn not_a_directory(path: &str) -> Ext4Error {
+    let last = path.split('/').filter(|s| !s.is_empty()).enumerate().last();
+    let (index, component) = last.unwrap_or((0, ""));
+    Ext4Error::NotADirectory { index, component: component.to_string() }
+}
 
+/// 为已知解析失败的路径（例如跟随符号链接后不存在）构造带分量的错误
+/// 不跟随链接时路径能解析成功，则归咎于最后一个分量
+pub fn path_lookup_error<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    path: &str,
+) -> Ext4Error {
+    match lookup_path(fs, block_dev, path) {
+        Err(e) => e,
+        Ok(_) => {
+            let last = path.split('/').filter(|s| !s.is_empty()).enumerate().last();
+            let (index, component) = last.unwrap_or((0, ""));
+            Ext4Error::NotFound { index, component: component.to_string() }
+        }
+    }
+}
+
+/// 逐级解析路径；外层是设备错误，内层是路径本身的解析结果
+fn walk_path<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    block_dev: &mut Jbd2Dev<B>,
+    path: &str,
+) -> BlockDevResult<Ext4Result<(u32, Ext4Inode)>> {
     // 规范化路径：空串或"/" 视为根目录
     if path.is_empty() || path == "/" {
         let inode = fs.get_root(block_dev)?;
-        return Ok(Some((fs.root_inode, inode)));
+        return Ok(Ok((fs.root_inode, inode)));
     }
 
-    // 按 '/' 分割，过滤掉空段
-    let components = path.split('/').filter(|s| !s.is_empty());
+    // 按 '/' 分割，过滤掉空段；分量下标按过滤后的顺序计（含 "." 和 ".."）
+    let components = path.split('/').filter(|s| !s.is_empty()).enumerate();
+    // 最近一次成功查找的分量，用于报告非目录分量
+    let mut last_found: (usize, &str) = (0, "");
 
     // 从根目录开始逐级解析，并维护一个路径栈以支持 ".." 回溯
     let mut current_inode = fs.get_root(block_dev)?;
     let mut current_ino_num: u32 = fs.root_inode;
-    let mut path_vec: Vec<Ext4Inode> = Vec::new();
-    path_vec.push(current_inode);
+    let mut path_vec: Vec<(u32, Ext4Inode)> = Vec::new();
+    path_vec.push((current_ino_num, current_inode));
 
-    // 根目录所在的 inode 表起始块目前按 group0 处理
-    let inode_table_start = match fs.group_descs.first() {
-        Some(desc) => desc.inode_table(),
-        None => return Err(BlockDevError::Corrupted),
-    };
-    for name in components {
+    for (index, name) in components {
         if !current_inode.is_dir() {
             // 中间层不是目录，路径非法
-            return Ok(None);
+            let (index, component) = last_found;
+            return Ok(Err(Ext4Error::NotADirectory { index, component: component.to_string() }));
         }
 
         // 特殊处理当前目录和父目录
@@ -166,43 +279,67 @@ pub fn get_file_inode<B: BlockDevice>(
             // 回溯到父目录：栈中至少保留根目录一层
             if path_vec.len() > 1 {
                 path_vec.pop();
-                if let Some(parent_inode) = path_vec.last() {
-                    current_inode = *parent_inode;
+                if let Some(&(parent_ino, parent_inode)) = path_vec.last() {
+                    current_ino_num = parent_ino;
+                    current_inode = parent_inode;
                 }
             }
             continue;
         }
 
         let target = name.as_bytes();
+
+        // 名字缓存命中时无需扫描目录块
+        if let Some(ino) = fs.dentry_cache.lookup(current_ino_num, target) {
+            current_inode = fs.get_inode_by_num(block_dev, ino)?;
+            current_ino_num = ino;
+            path_vec.push((current_ino_num, current_inode));
+            last_found = (index, name);
+            continue;
+        }
+
         let mut found_inode_num: Option<u64> = None;
 
-        // 尝试使用哈希树查找
-        match lookup_directory_entry(fs, block_dev, &current_inode, target) {
-            Ok(result) => {
-                found_inode_num = Some(result.entry.inode as u64);
-            }
-            Err(_) => {
-                // 哈希树查找失败，回退到线性查找
-                debug!("Hash tree lookup failed, falling back to linear search");
-
-                // 使用 resolve_inode_block_allextend 获取所有物理块，然后逐块线性查找
-                let total_size = current_inode.size() as usize;
-                let block_bytes = BLOCK_SIZE;
-                let blocks = resolve_inode_block_allextend(fs, block_dev, &mut current_inode)?;
-                info!(
-                    "Directory inode size: {} bytes, blocks used: {}",
-                    &total_size,
-                    &blocks.len()
-                );
-
-                for (idx, phys) in blocks.iter().enumerate() {
-                    info!("Scan dir block idx {} phys {}", &idx, phys.1);
-                    let cached_block = fs.datablock_cache.get_or_load(block_dev, *phys.1)?;
-                    let block_data = &cached_block.data[..block_bytes];
-
-                    if let Some(entry) = classic_dir::find_entry(block_data, target) {
-                        found_inode_num = Some(entry.inode as u64);
-                        break;
+        // 内联目录：目录项直接存放在 inode 的 i_block 中
+        if current_inode.has_inline_data() {
+            let raw = inline_dir::raw_bytes(&current_inode.i_block);
+            found_inode_num =
+                classic_dir::find_entry(inline_dir::entries(&raw), target)?.map(|e| e.inode as u64);
+        } else {
+            // 尝试使用哈希树查找
+            match lookup_directory_entry(fs, block_dev, current_ino_num, &current_inode, target) {
+                Ok(result) => {
+                    found_inode_num = Some(result.entry.inode as u64);
+                }
+                // 目录块校验和不符时不能信任其中的目录项
+                Err(HashTreeError::ChecksumError) => return Err(BlockDevError::ChecksumError),
+                Err(_) => {
+                    // 哈希树查找失败，回退到线性查找
+                    debug!("Hash tree lookup failed, falling back to linear search");
+
+                    // 使用 resolve_inode_block_allextend 获取所有物理块，然后逐块线性查找
+                    let total_size = current_inode.size() as usize;
+                    let block_bytes = BLOCK_SIZE;
+                    let blocks = resolve_inode_block_allextend(fs, block_dev, current_ino_num, &mut current_inode)?;
+                    info!(
+                        "Directory inode size: {} bytes, blocks used: {}",
+                        &total_size,
+                        &blocks.len()
+                    );
+
+                    let seed = dir_csum_seed(fs, current_ino_num, &current_inode);
+                    let policy = fs.checksum_policy;
+                    for (idx, phys) in blocks.iter().enumerate() {
+                        info!("Scan dir block idx {} phys {}", &idx, phys.1);
+                        let cached_block = fs.datablock_cache.get_or_load(block_dev, *phys.1)?;
+                        let block_data = &cached_block.data[..block_bytes];
+                        let csum_ok = seed.is_none_or(|seed| dir_csum::verify(seed, block_data));
+                        policy.check(csum_ok, "directory block")?;
+
+                        if let Some(entry) = classic_dir::find_entry(block_data, target)? {
+                            found_inode_num = Some(entry.inode as u64);
+                            break;
+                        }
                     }
                 }
             }
@@ -212,27 +349,22 @@ pub fn get_file_inode<B: BlockDevice>(
 
         let inode_num = match found_inode_num {
             Some(n) => n,
-            None => return Ok(None),
+            None => {
+                return Ok(Err(Ext4Error::NotFound { index, component: name.to_string() }));
+            }
         };
 
         let inode_num_u32 = inode_num as u32;
+        fs.dentry_cache.insert(current_ino_num, target, inode_num_u32);
 
-        let (block_num, offset, _group_idx) = fs.inodetable_cahce.calc_inode_location(
-            inode_num_u32,
-            fs.superblock.s_inodes_per_group,
-            inode_table_start,
-            BLOCK_SIZE,
-        );
-
-        let cached_inode = fs
-            .inodetable_cahce
-            .get_or_load(block_dev, inode_num, block_num, offset)?;
-        current_inode = cached_inode.inode;
+        // 按 inode 所属块组定位 inode 表
+        current_inode = fs.get_inode_by_num(block_dev, inode_num_u32)?;
         current_ino_num = inode_num_u32;
-        path_vec.push(current_inode);
+        path_vec.push((current_ino_num, current_inode));
+        last_found = (index, name);
     }
 
  
 
-    Ok(Some((current_ino_num, current_inode)))
+    Ok(Ok((current_ino_num, current_inode)))
 }
@@ -0,0 +1,801 @@
+//! 镜像迁移模块
+//!
+//! 把源镜像的整棵目录树（目录、普通文件、符号链接、硬链接）
+//! 逐个复制到新格式化的目标镜像，相当于一次备份加还原，
+//! 用于给旧镜像换上新的特性组合。目标块大小由编译期 BLOCK_SIZE 决定；
+//! 块大小与之相同的源镜像只读挂载后复制，更小块（1K/2K）的源镜像由本模块直接按原始布局读取
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use log::warn;
+
+use crate::ext4_backend::api::*;
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::blockgroup_description::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::dir::*;
+use crate::ext4_backend::disknode::*;
+use crate::ext4_backend::endian::*;
+use crate::ext4_backend::entries::DirEntryIterator;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::extents_tree::*;
+use crate::ext4_backend::file::*;
+use crate::ext4_backend::superblock::*;
+
+/// 单次复制的文件数据大小
+const MIGRATE_CHUNK: usize = 1024 * 1024;
+
+/// 目标镜像的格式参数
+#[derive(Debug, Clone, Copy)]
+pub struct MigrateOptions {
+    /// 目标块大小（字节），必须等于 BLOCK_SIZE
+    pub block_size: u32,
+    /// 是否启用 metadata_csum
+    pub metadata_csum: bool,
+}
+
+impl Default for MigrateOptions {
+    fn default() -> Self {
+        Self {
+            block_size: BLOCK_SIZE_U32,
+            metadata_csum: false,
+        }
+    }
+}
+
+/// 迁移统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MigrateReport {
+    pub dirs: u32,
+    pub files: u32,
+    pub symlinks: u32,
+    /// 作为硬链接复制的目录项数
+    pub hard_links: u32,
+    /// 不支持复制而跳过的特殊文件数（设备、FIFO、socket）
+    pub skipped: u32,
+    /// 复制的文件数据字节数
+    pub bytes: u64,
+}
+
+/// 拼接目录路径和子项名
+fn child_path(dir: &str, name: &str) -> String {
+    if dir == "/" {
+        format!("/{name}")
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+/// 迁移的数据来源，路径都来自此前 list_dir 的结果
+trait MigrateSource {
+    /// 目录下的全部子项名（含 "." 和 ".."）
+    fn list_dir(&mut self, dir: &str) -> Ext4Result<Vec<String>>;
+    fn stat(&mut self, path: &str) -> Ext4Result<FileStat>;
+    fn read_link(&mut self, path: &str) -> Ext4Result<String>;
+    /// 按顺序把文件内容分块交给 sink，返回总字节数
+    fn read_file(
+        &mut self,
+        path: &str,
+        sink: &mut dyn FnMut(&[u8]) -> Ext4Result<()>,
+    ) -> Ext4Result<u64>;
+    /// 复制结束后释放源镜像
+    fn finish(self: Box<Self>) -> Ext4Result<()>;
+}
+
+/// 只读挂载的源镜像（块大小等于 BLOCK_SIZE）
+struct MountedSource<'a, B: BlockDevice> {
+    dev: &'a mut Jbd2Dev<B>,
+    fs: Ext4FileSystem,
+}
+
+impl<B: BlockDevice> MigrateSource for MountedSource<'_, B> {
+    fn list_dir(&mut self, dir: &str) -> Ext4Result<Vec<String>> {
+        let names = read_dir(self.dev, &mut self.fs, dir)?
+            .map(|e| e.map(|e| e.name))
+            .collect::<Ext4Result<_>>()?;
+        Ok(names)
+    }
+
+    fn stat(&mut self, path: &str) -> Ext4Result<FileStat> {
+        stat(self.dev, &mut self.fs, path)
+    }
+
+    fn read_link(&mut self, path: &str) -> Ext4Result<String> {
+        read_link(self.dev, &mut self.fs, path)
+    }
+
+    fn read_file(
+        &mut self,
+        path: &str,
+        sink: &mut dyn FnMut(&[u8]) -> Ext4Result<()>,
+    ) -> Ext4Result<u64> {
+        let mut input = open(self.dev, &mut self.fs, path, OpenFlags::RDONLY)?;
+        let mut total = 0u64;
+        loop {
+            let chunk = read_at(self.dev, &mut self.fs, &mut input, MIGRATE_CHUNK)?;
+            if chunk.is_empty() {
+                break;
+            }
+            sink(&chunk)?;
+            total += chunk.len() as u64;
+        }
+        close(self.dev, &mut self.fs, input)?;
+        Ok(total)
+    }
+
+    fn finish(self: Box<Self>) -> Ext4Result<()> {
+        let Self { dev, fs } = *self;
+        umount(fs, dev)
+    }
+}
+
+/// 块大小小于 BLOCK_SIZE 的源镜像：挂载路径只支持 BLOCK_SIZE，
+/// 这里按原始布局直接读取超级块、块组描述符、inode 和数据块，不校验源镜像的校验和。
+/// 支持 extent 和间接块两种映射，不支持 inline_data、meta_bg 和待恢复的日志
+struct SmallBlockSource<'a, B: BlockDevice> {
+    dev: &'a mut Jbd2Dev<B>,
+    sb: Ext4Superblock,
+    block_size: u64,
+    descs: Vec<Ext4GroupDesc>,
+    /// list_dir 见过的路径 -> inode 号
+    inodes: BTreeMap<String, u32>,
+}
+
+/// 一段连续映射：(起始逻辑块, 起始物理块, 块数)
+type BlockRun = (u64, u64, u64);
+
+/// 追加一个映射块，与上一段连续时合并
+fn push_run(runs: &mut Vec<BlockRun>, lbn: u64, pblk: u64) {
+    if let Some(last) = runs.last_mut()
+        && last.0 + last.2 == lbn
+        && last.1 + last.2 == pblk
+    {
+        last.2 += 1;
+        return;
+    }
+    runs.push((lbn, pblk, 1));
+}
+
+impl<'a, B: BlockDevice> SmallBlockSource<'a, B> {
+    fn new(dev: &'a mut Jbd2Dev<B>, sb: Ext4Superblock) -> Ext4Result<Self> {
+        let unsupported = Ext4Superblock::EXT4_FEATURE_INCOMPAT_META_BG
+            | Ext4Superblock::EXT4_FEATURE_INCOMPAT_RECOVER;
+        if sb.s_feature_incompat & unsupported != 0
+            || sb.s_blocks_per_group == 0
+            || sb.s_inodes_per_group == 0
+        {
+            return Err(BlockDevError::Unsupported.into());
+        }
+        let mut src = Self {
+            dev,
+            sb,
+            block_size: 1024u64 << sb.s_log_block_size,
+            descs: Vec::new(),
+            inodes: BTreeMap::new(),
+        };
+        let desc_size = if sb.has_feature_incompat(Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT) {
+            (sb.s_desc_size as usize).max(GROUP_DESC_SIZE_OLD as usize)
+        } else {
+            GROUP_DESC_SIZE_OLD as usize
+        };
+        let groups = (sb.blocks_count() - sb.s_first_data_block as u64).div_ceil(sb.s_blocks_per_group as u64);
+        let gdt = (sb.s_first_data_block as u64 + 1) * src.block_size;
+        let raw = src.read_bytes(gdt, groups as usize * desc_size)?;
+        src.descs = raw.chunks_exact(desc_size).map(Ext4GroupDesc::from_disk_bytes).collect();
+        src.inodes.insert(String::from("/"), 2);
+        Ok(src)
+    }
+
+    /// 按字节偏移读取，跨越设备块时逐块拼接
+    fn read_bytes(&mut self, offset: u64, len: usize) -> Ext4Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(len);
+        let mut pos = offset;
+        while out.len() < len {
+            self.dev.read_block(pos / BLOCK_SIZE as u64)?;
+            let from = (pos % BLOCK_SIZE as u64) as usize;
+            let take = (BLOCK_SIZE - from).min(len - out.len());
+            out.extend_from_slice(&self.dev.buffer()[from..from + take]);
+            pos += take as u64;
+        }
+        Ok(out)
+    }
+
+    fn read_fs_block(&mut self, block: u64) -> Ext4Result<Vec<u8>> {
+        self.read_bytes(block * self.block_size, self.block_size as usize)
+    }
+
+    fn read_inode(&mut self, ino: u32) -> Ext4Result<Ext4Inode> {
+        let ipg = self.sb.s_inodes_per_group;
+        let desc = self
+            .descs
+            .get(((ino - 1) / ipg) as usize)
+            .ok_or(BlockDevError::Corrupted)?;
+        let inode_size = self.sb.s_inode_size as usize;
+        let offset = desc.inode_table() * self.block_size + ((ino - 1) % ipg) as u64 * inode_size as u64;
+        let raw = self.read_bytes(offset, inode_size)?;
+        let inode = Ext4Inode::from_disk_bytes(&raw);
+        if inode.has_inline_data() {
+            warn!("migrate: inline data in inode {ino} is not supported");
+            return Err(BlockDevError::Unsupported.into());
+        }
+        Ok(inode)
+    }
+
+    fn lookup(&mut self, path: &str) -> Ext4Result<(u32, Ext4Inode)> {
+        let ino = *self.inodes.get(path).ok_or(BlockDevError::InvalidInput)?;
+        Ok((ino, self.read_inode(ino)?))
+    }
+
+    /// inode 覆盖的全部映射，按逻辑块号排序；空洞和未初始化 extent 不在其中
+    fn block_runs(&mut self, inode: &Ext4Inode) -> Ext4Result<Vec<BlockRun>> {
+        let mut runs = Vec::new();
+        if inode.i_flags & Ext4Inode::EXT4_EXTENTS_FL != 0 {
+            let mut root = [0u8; 60];
+            for (i, word) in inode.i_block.iter().enumerate() {
+                root[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+            }
+            self.extent_runs(&root, 0, &mut runs)?;
+            runs.sort_unstable();
+        } else {
+            let last = inode.size().div_ceil(self.block_size);
+            for (lbn, &blk) in inode.i_block[..12].iter().enumerate() {
+                if blk != 0 && (lbn as u64) < last {
+                    push_run(&mut runs, lbn as u64, blk as u64);
+                }
+            }
+            let per = self.block_size / 4;
+            let mut start = 12;
+            for (depth, &blk) in inode.i_block[12..].iter().enumerate() {
+                self.indirect_runs(blk as u64, depth as u32 + 1, start, last, &mut runs)?;
+                start += per.pow(depth as u32 + 1);
+            }
+        }
+        Ok(runs)
+    }
+
+    fn extent_runs(&mut self, node: &[u8], level: u32, runs: &mut Vec<BlockRun>) -> Ext4Result<()> {
+        if level > 5 {
+            return Err(BlockDevError::Corrupted.into());
+        }
+        match ExtentTree::parse_node(node).ok_or(BlockDevError::Corrupted)? {
+            ExtentNode::Leaf { entries, .. } => {
+                for ext in entries.iter().filter(|e| e.is_initialized()) {
+                    runs.push((ext.ee_block as u64, ext.start_block(), extent_block_count(ext) as u64));
+                }
+            }
+            ExtentNode::Index { entries, .. } => {
+                for idx in entries {
+                    let child = ((idx.ei_leaf_hi as u64) << 32) | idx.ei_leaf_lo as u64;
+                    let data = self.read_fs_block(child)?;
+                    self.extent_runs(&data, level + 1, runs)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 展开一个 depth 级间接块，start 为它覆盖的第一个逻辑块，last 之后的不再读取
+    fn indirect_runs(
+        &mut self,
+        block: u64,
+        depth: u32,
+        start: u64,
+        last: u64,
+        runs: &mut Vec<BlockRun>,
+    ) -> Ext4Result<()> {
+        if block == 0 || start >= last {
+            return Ok(());
+        }
+        let data = self.read_fs_block(block)?;
+        let span = (self.block_size / 4).pow(depth - 1);
+        for (i, ptr) in data.chunks_exact(4).map(read_u32_le).enumerate() {
+            let lbn = start + i as u64 * span;
+            if ptr == 0 {
+                continue;
+            }
+            if depth == 1 {
+                if lbn < last {
+                    push_run(runs, lbn, ptr as u64);
+                }
+            } else {
+                self.indirect_runs(ptr as u64, depth - 1, lbn, last, runs)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 按文件大小读出内容，空洞补 0
+    fn read_inode_data(
+        &mut self,
+        inode: &Ext4Inode,
+        sink: &mut dyn FnMut(&[u8]) -> Ext4Result<()>,
+    ) -> Ext4Result<u64> {
+        let size = inode.size();
+        let zeros = vec![0u8; MIGRATE_CHUNK];
+        let mut pos = 0u64;
+        let emit_zeros = |sink: &mut dyn FnMut(&[u8]) -> Ext4Result<()>, from: u64, to: u64| {
+            let mut at = from;
+            while at < to {
+                let n = (to - at).min(MIGRATE_CHUNK as u64);
+                sink(&zeros[..n as usize])?;
+                at += n;
+            }
+            Ok::<(), Ext4Error>(())
+        };
+        for (lbn, pblk, count) in self.block_runs(inode)? {
+            let start = (lbn * self.block_size).max(pos);
+            if start >= size {
+                break;
+            }
+            let end = ((lbn + count) * self.block_size).min(size);
+            emit_zeros(sink, pos, start)?;
+            let mut at = start;
+            while at < end {
+                let n = (end - at).min(MIGRATE_CHUNK as u64);
+                let data = self.read_bytes(pblk * self.block_size + (at - lbn * self.block_size), n as usize)?;
+                sink(&data)?;
+                at += n;
+            }
+            pos = end.max(pos);
+        }
+        emit_zeros(sink, pos, size)?;
+        Ok(size)
+    }
+}
+
+impl<B: BlockDevice> MigrateSource for SmallBlockSource<'_, B> {
+    fn list_dir(&mut self, dir: &str) -> Ext4Result<Vec<String>> {
+        let (_, inode) = self.lookup(dir)?;
+        if !inode.is_dir() {
+            return Err(BlockDevError::Corrupted.into());
+        }
+        let mut names = Vec::new();
+        for (_, pblk, count) in self.block_runs(&inode)? {
+            for blk in pblk..pblk + count {
+                let data = self.read_fs_block(blk)?;
+                let mut iter = DirEntryIterator::new(&data);
+                for (entry, _) in iter.by_ref() {
+                    let name = String::from_utf8(entry.name.to_vec()).map_err(|_| BlockDevError::Corrupted)?;
+                    self.inodes.insert(child_path(dir, &name), entry.inode);
+                    names.push(name);
+                }
+                if let Some(e) = iter.error() {
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    fn stat(&mut self, path: &str) -> Ext4Result<FileStat> {
+        let (ino, inode) = self.lookup(path)?;
+        Ok(FileStat::from_inode(ino, &inode))
+    }
+
+    fn read_link(&mut self, path: &str) -> Ext4Result<String> {
+        let (_, inode) = self.lookup(path)?;
+        let size = inode.size() as usize;
+        let acl_sectors = if inode.file_acl() != 0 { self.block_size / 512 } else { 0 };
+        // 快速符号链接的目标直接存放在 i_block 中，不占数据块
+        let target = if size < 60 && inode.blocks_count() == acl_sectors {
+            let mut raw = [0u8; 60];
+            for (i, word) in inode.i_block.iter().enumerate() {
+                raw[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+            }
+            raw[..size].to_vec()
+        } else {
+            let mut buf = Vec::with_capacity(size);
+            self.read_inode_data(&inode, &mut |chunk| {
+                buf.extend_from_slice(chunk);
+                Ok(())
+            })?;
+            buf
+        };
+        Ok(String::from_utf8(target).map_err(|_| BlockDevError::Corrupted)?)
+    }
+
+    fn read_file(
+        &mut self,
+        path: &str,
+        sink: &mut dyn FnMut(&[u8]) -> Ext4Result<()>,
+    ) -> Ext4Result<u64> {
+        let (_, inode) = self.lookup(path)?;
+        self.read_inode_data(&inode, sink)
+    }
+
+    fn finish(self: Box<Self>) -> Ext4Result<()> {
+        Ok(())
+    }
+}
+
+/// 读出设备上的原始超级块，不检查块大小
+fn read_raw_superblock<B: BlockDevice>(dev: &mut Jbd2Dev<B>) -> Ext4Result<Ext4Superblock> {
+    dev.read_block(0)?;
+    let off = Ext4Superblock::SUPERBLOCK_OFFSET as usize;
+    Ok(Ext4Superblock::from_disk_bytes(&dev.buffer()[off..off + SUPERBLOCK_SIZE]))
+}
+
+/// 把源镜像的全部内容迁移到目标设备：目标会被重新格式化，原有内容全部丢失
+/// 源镜像不会被修改；目标块大小与 BLOCK_SIZE 不一致、或源镜像块大小大于 BLOCK_SIZE 时返回 Unsupported
+pub fn migrate_image<S: BlockDevice, D: BlockDevice>(
+    src_dev: &mut Jbd2Dev<S>,
+    dst_dev: &mut Jbd2Dev<D>,
+    opts: &MigrateOptions,
+) -> Ext4Result<MigrateReport> {
+    if opts.block_size != BLOCK_SIZE_U32 {
+        return Err(BlockDevError::Unsupported.into());
+    }
+    let sb = read_raw_superblock(src_dev)?;
+    if sb.is_valid() && sb.s_log_block_size > LOG_BLOCK_SIZE {
+        return Err(BlockDevError::Unsupported.into());
+    }
+    let mut src: Box<dyn MigrateSource + '_> = if sb.is_valid() && sb.s_log_block_size < LOG_BLOCK_SIZE {
+        Box::new(SmallBlockSource::new(src_dev, sb)?)
+    } else {
+        let fs = mount_ro(src_dev)?;
+        Box::new(MountedSource { dev: src_dev, fs })
+    };
+
+    mkfs(dst_dev)?;
+    let mut dst = mount(dst_dev)?;
+    if opts.metadata_csum {
+        dst.enable_metadata_csum(dst_dev)?;
+    }
+    let report = copy_tree(src.as_mut(), dst_dev, &mut dst)?;
+    umount(dst, dst_dev)?;
+    src.finish()?;
+    Ok(report)
+}
+
+/// 从 src 的根目录开始逐个复制到 dst
+fn copy_tree<D: BlockDevice>(
+    src: &mut dyn MigrateSource,
+    dst_dev: &mut Jbd2Dev<D>,
+    dst: &mut Ext4FileSystem,
+) -> Ext4Result<MigrateReport> {
+    let mut report = MigrateReport::default();
+    // 源 inode 号 -> 目标中第一次出现的路径，用于还原硬链接
+    let mut copied: BTreeMap<u32, String> = BTreeMap::new();
+    let mut pending: Vec<String> = vec![String::from("/")];
+    while let Some(dir) = pending.pop() {
+        for name in src.list_dir(&dir)? {
+            if name == "." || name == ".." {
+                continue;
+            }
+            let path = child_path(&dir, &name);
+            let st = src.stat(&path)?;
+            match st.mode & Ext4Inode::S_IFMT {
+                Ext4Inode::S_IFDIR => {
+                    // mkfs 已经建好的目录（如 lost+found）直接复用
+                    if stat(dst_dev, dst, &path).is_err() && mkdir(dst_dev, dst, &path).is_err() {
+                        return Err(BlockDevError::WriteError.into());
+                    }
+                    report.dirs += 1;
+                    pending.push(path.clone());
+                }
+                Ext4Inode::S_IFREG if st.links_count > 1 && copied.contains_key(&st.ino) => {
+                    link(dst_dev, dst, &copied[&st.ino], &path)?;
+                    report.hard_links += 1;
+                    continue;
+                }
+                Ext4Inode::S_IFREG => {
+                    report.bytes += copy_file(src, dst_dev, dst, &path)?;
+                    report.files += 1;
+                    copied.insert(st.ino, path.clone());
+                }
+                Ext4Inode::S_IFLNK => {
+                    let target = src.read_link(&path)?;
+                    symlink(dst_dev, dst, &target, &path)?;
+                    report.symlinks += 1;
+                    continue;
+                }
+                _ => {
+                    warn!("migrate: skip special file {path} mode={:#o}", st.mode);
+                    report.skipped += 1;
+                    continue;
+                }
+            }
+            chmod(dst_dev, dst, &path, st.mode)?;
+            chown(dst_dev, dst, &path, st.uid, st.gid)?;
+        }
+    }
+    Ok(report)
+}
+
+/// 分块复制一个普通文件，返回复制的字节数
+fn copy_file<D: BlockDevice>(
+    src: &mut dyn MigrateSource,
+    dst_dev: &mut Jbd2Dev<D>,
+    dst: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<u64> {
+    let mut output = open(dst_dev, dst, path, OpenFlags::RDWR.with_create().with_truncate())?;
+    let total = src.read_file(path, &mut |chunk| write_at(dst_dev, dst, &mut output, chunk))?;
+    close(dst_dev, dst, output)?;
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::entries::Ext4DirEntry2;
+    use crate::ext4_backend::test_support::*;
+
+    #[test]
+    fn migrate_copies_tree_into_metadata_csum_image() {
+        let (mut src_dev, mut src) = setup_fs(16 * 1024);
+        mkdir(&mut src_dev, &mut src, "/a").unwrap();
+        mkdir(&mut src_dev, &mut src, "/a/b").unwrap();
+        let big: Vec<u8> = (0..3 * BLOCK_SIZE + 123).map(|i| (i % 251) as u8).collect();
+        mkfile(&mut src_dev, &mut src, "/a/b/big", Some(&big), None).unwrap();
+        mkfile(&mut src_dev, &mut src, "/top", Some(b"hello"), None).unwrap();
+        mkfile(&mut src_dev, &mut src, "/empty", None, None).unwrap();
+        link(&mut src_dev, &mut src, "/top", "/a/top_link").unwrap();
+        symlink(&mut src_dev, &mut src, "b/big", "/a/sym").unwrap();
+        chmod(&mut src_dev, &mut src, "/top", 0o640).unwrap();
+        chown(&mut src_dev, &mut src, "/a/b", 1000, 100).unwrap();
+        umount(src, &mut src_dev).unwrap();
+
+        let mut dst_dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(16 * 1024), false, false);
+        let opts = MigrateOptions {
+            metadata_csum: true,
+            ..MigrateOptions::default()
+        };
+        let report = migrate_image(&mut src_dev, &mut dst_dev, &opts).unwrap();
+        assert_eq!(report.files, 3);
+        assert_eq!(report.hard_links, 1);
+        assert_eq!(report.symlinks, 1);
+        assert_eq!(report.bytes, big.len() as u64 + 5);
+
+        let mut dst = mount(&mut dst_dev).unwrap();
+        assert!(dst.superblock.has_metadata_csum());
+        assert_eq!(read_file(&mut dst_dev, &mut dst, "/a/b/big").unwrap(), big);
+        assert_eq!(read_file(&mut dst_dev, &mut dst, "/a/sym").unwrap(), big);
+        assert_eq!(read_file(&mut dst_dev, &mut dst, "/top").unwrap(), b"hello");
+        assert!(read_file(&mut dst_dev, &mut dst, "/empty").unwrap().is_empty());
+        let top = stat(&mut dst_dev, &mut dst, "/top").unwrap();
+        assert_eq!(top.mode & 0o7777, 0o640);
+        assert_eq!(top.links_count, 2);
+        assert_eq!(stat(&mut dst_dev, &mut dst, "/a/top_link").unwrap().ino, top.ino);
+        let b = stat(&mut dst_dev, &mut dst, "/a/b").unwrap();
+        assert_eq!((b.uid, b.gid), (1000, 100));
+        // mkfs 建好的根目录和 lost+found 也补上了目录块尾和校验和
+        assert_eq!(fsck_metadata_csums(&mut dst_dev, &mut dst).unwrap(), vec![]);
+        umount(dst, &mut dst_dev).unwrap();
+
+        let bad = MigrateOptions {
+            block_size: 1024,
+            ..MigrateOptions::default()
+        };
+        assert!(matches!(
+            migrate_image(&mut src_dev, &mut dst_dev, &bad),
+            Err(Ext4Error::Device(BlockDevError::Unsupported))
+        ));
+    }
+
+    /// 1K 块源镜像中的逻辑块号为 lbn 的块的内容
+    fn big_block(lbn: u64) -> Vec<u8> {
+        (0..1024).map(|i| ((lbn * 7 + i) % 251) as u8).collect()
+    }
+
+    const BIG_BLOCKS: u64 = 300;
+    const BIG_HOLE: u64 = 5;
+
+    /// 在 4K 内存设备上手工构造一个 1K 块的 ext4 镜像：
+    /// 单块组，inode 表在 5..9 块，根目录和 /docs 用间接块映射，
+    /// /big 跨越直接块、一级和二级间接块且 lbn 5 是空洞，/sparse 用 extent 映射且带空洞，
+    /// 另有硬链接、快速和慢速符号链接
+    fn build_1k_image() -> Jbd2Dev<MemBlockDev> {
+        let mut dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(512), false, false);
+        let mut put = |offset: u64, bytes: &[u8]| {
+            let mut pos = 0;
+            while pos < bytes.len() {
+                let at = offset + pos as u64;
+                let blk = at / BLOCK_SIZE as u64;
+                let from = (at % BLOCK_SIZE as u64) as usize;
+                let n = (BLOCK_SIZE - from).min(bytes.len() - pos);
+                dev.read_block(blk).unwrap();
+                dev.buffer_mut()[from..from + n].copy_from_slice(&bytes[pos..pos + n]);
+                dev.write_block(blk, false).unwrap();
+                pos += n;
+            }
+        };
+        let block = |n: u64| n * 1024;
+
+        let mut sb = Ext4Superblock::default();
+        sb.s_inodes_count = 32;
+        sb.s_blocks_count_lo = 2048;
+        sb.s_first_data_block = 1;
+        sb.s_log_block_size = 0;
+        sb.s_log_cluster_size = 0;
+        sb.s_blocks_per_group = 8192;
+        sb.s_clusters_per_group = 8192;
+        sb.s_inodes_per_group = 32;
+        sb.s_magic = Ext4Superblock::EXT4_SUPER_MAGIC;
+        sb.s_rev_level = 1;
+        sb.s_first_ino = 11;
+        sb.s_inode_size = 128;
+        sb.s_feature_compat = 0;
+        sb.s_feature_incompat = Ext4Superblock::EXT4_FEATURE_INCOMPAT_FILETYPE
+            | Ext4Superblock::EXT4_FEATURE_INCOMPAT_EXTENTS;
+        sb.s_feature_ro_compat = 0;
+        let mut raw = vec![0u8; SUPERBLOCK_SIZE];
+        sb.to_disk_bytes(&mut raw);
+        put(1024, &raw);
+
+        let mut desc = [0u8; 32];
+        write_u32_le(3, &mut desc[0..4]);
+        write_u32_le(4, &mut desc[4..8]);
+        write_u32_le(5, &mut desc[8..12]);
+        put(block(2), &desc);
+
+        let put_inode = |put: &mut dyn FnMut(u64, &[u8]), ino: u32, inode: &Ext4Inode| {
+            let mut raw = [0u8; 128];
+            inode.to_disk_bytes(&mut raw);
+            put(block(5) + (ino as u64 - 1) * 128, &raw);
+        };
+        let new_inode = |mode: u16, size: u64, links: u16| {
+            let mut inode = Ext4Inode::default();
+            inode.i_mode = mode;
+            inode.i_size_lo = size as u32;
+            inode.i_links_count = links;
+            inode
+        };
+        let dir_block = |entries: &[(u32, u8, &str)]| {
+            let mut data = vec![0u8; 1024];
+            let mut off = 0;
+            for (i, &(ino, ft, name)) in entries.iter().enumerate() {
+                let rec_len = if i + 1 == entries.len() {
+                    1024 - off
+                } else {
+                    (8 + name.len() + 3) & !3
+                };
+                Ext4DirEntry2::new(ino, rec_len as u16, ft, name.as_bytes())
+                    .to_disk_bytes(&mut data[off..off + 8]);
+                data[off + 8..off + 8 + name.len()].copy_from_slice(name.as_bytes());
+                off += rec_len;
+            }
+            data
+        };
+        let (dir_ft, reg_ft, lnk_ft) = (
+            Ext4DirEntry2::EXT4_FT_DIR,
+            Ext4DirEntry2::EXT4_FT_REG_FILE,
+            Ext4DirEntry2::EXT4_FT_SYMLINK,
+        );
+
+        // 根目录
+        put(block(9), &dir_block(&[
+            (2, dir_ft, "."),
+            (2, dir_ft, ".."),
+            (12, dir_ft, "docs"),
+            (13, reg_ft, "hello"),
+            (14, reg_ft, "big"),
+            (15, lnk_ft, "fast"),
+            (16, lnk_ft, "slow"),
+            (17, reg_ft, "sparse"),
+        ]));
+        let mut root = new_inode(Ext4Inode::S_IFDIR | 0o755, 1024, 3);
+        root.i_block[0] = 9;
+        put_inode(&mut put, 2, &root);
+
+        put(block(10), &dir_block(&[(12, dir_ft, "."), (2, dir_ft, ".."), (13, reg_ft, "hello_link")]));
+        let mut docs = new_inode(Ext4Inode::S_IFDIR | 0o750, 1024, 2);
+        docs.i_block[0] = 10;
+        put_inode(&mut put, 12, &docs);
+
+        put(block(11), b"hello world\n");
+        let mut hello = new_inode(Ext4Inode::S_IFREG | 0o640, 12, 2);
+        hello.i_uid = 1000;
+        hello.i_gid = 100;
+        hello.i_block[0] = 11;
+        put_inode(&mut put, 13, &hello);
+
+        // /big：lbn 直接对应物理块 100 + lbn，一级间接块 20，二级间接块 21 -> 22
+        let mut big = new_inode(Ext4Inode::S_IFREG | 0o644, BIG_BLOCKS * 1024 - 100, 1);
+        let mut single = vec![0u8; 1024];
+        let mut level2 = vec![0u8; 1024];
+        for lbn in (0..BIG_BLOCKS).filter(|&l| l != BIG_HOLE) {
+            put(block(100 + lbn), &big_block(lbn));
+            let pblk = (100 + lbn) as u32;
+            match lbn {
+                0..12 => big.i_block[lbn as usize] = pblk,
+                12..268 => write_u32_le(pblk, &mut single[(lbn as usize - 12) * 4..]),
+                _ => write_u32_le(pblk, &mut level2[(lbn as usize - 268) * 4..]),
+            }
+        }
+        let mut double = vec![0u8; 1024];
+        write_u32_le(22, &mut double[0..4]);
+        put(block(20), &single);
+        put(block(21), &double);
+        put(block(22), &level2);
+        big.i_block[12] = 20;
+        big.i_block[13] = 21;
+        put_inode(&mut put, 14, &big);
+
+        let mut fast = new_inode(Ext4Inode::S_IFLNK | 0o777, 9, 1);
+        let mut target = [0u8; 60];
+        target[..9].copy_from_slice(b"docs/none");
+        for (i, word) in target.chunks_exact(4).enumerate() {
+            fast.i_block[i] = read_u32_le(word);
+        }
+        put_inode(&mut put, 15, &fast);
+
+        let long = "docs/".repeat(16) + "hello_link";
+        put(block(12), long.as_bytes());
+        let mut slow = new_inode(Ext4Inode::S_IFLNK | 0o777, long.len() as u64, 1);
+        slow.i_block[0] = 12;
+        slow.i_blocks_lo = 2;
+        put_inode(&mut put, 16, &slow);
+
+        // /sparse：lbn 0 -> 13，lbn 3..5 -> 14..16，lbn 1、2 是空洞
+        put(block(13), &[0xAA; 1024]);
+        put(block(14), &[0xBB; 2048]);
+        let mut sparse = new_inode(Ext4Inode::S_IFREG | 0o600, 5 * 1024, 1);
+        sparse.i_flags = Ext4Inode::EXT4_EXTENTS_FL;
+        let mut root_node = [0u8; 60];
+        write_u16_le(Ext4ExtentHeader::EXT4_EXT_MAGIC, &mut root_node[0..2]);
+        write_u16_le(2, &mut root_node[2..4]);
+        write_u16_le(4, &mut root_node[4..6]);
+        for (i, (lbn, len, start)) in [(0u32, 1u16, 13u32), (3, 2, 14)].into_iter().enumerate() {
+            let off = 12 + i * 12;
+            write_u32_le(lbn, &mut root_node[off..off + 4]);
+            write_u16_le(len, &mut root_node[off + 4..off + 6]);
+            write_u32_le(start, &mut root_node[off + 8..off + 12]);
+        }
+        for (i, word) in root_node.chunks_exact(4).enumerate() {
+            sparse.i_block[i] = read_u32_le(word);
+        }
+        put_inode(&mut put, 17, &sparse);
+        dev
+    }
+
+    #[test]
+    fn migrate_converts_1k_block_image_into_4k_metadata_csum_image() {
+        let mut src_dev = build_1k_image();
+        let mut dst_dev = Jbd2Dev::initial_jbd2dev(0, MemBlockDev::new(16 * 1024), false, false);
+        let opts = MigrateOptions {
+            metadata_csum: true,
+            ..MigrateOptions::default()
+        };
+        let report = migrate_image(&mut src_dev, &mut dst_dev, &opts).unwrap();
+        assert_eq!(report.dirs, 1);
+        assert_eq!(report.files, 3);
+        assert_eq!(report.hard_links, 1);
+        assert_eq!(report.symlinks, 2);
+
+        let mut dst = mount(&mut dst_dev).unwrap();
+        assert_eq!(dst.superblock.block_size(), BLOCK_SIZE as u64);
+        assert_eq!(read_file(&mut dst_dev, &mut dst, "/hello").unwrap(), b"hello world\n");
+        let hello = stat(&mut dst_dev, &mut dst, "/hello").unwrap();
+        assert_eq!(hello.mode & 0o7777, 0o640);
+        assert_eq!((hello.uid, hello.gid, hello.links_count), (1000, 100, 2));
+        assert_eq!(stat(&mut dst_dev, &mut dst, "/docs/hello_link").unwrap().ino, hello.ino);
+        assert_eq!(stat(&mut dst_dev, &mut dst, "/docs").unwrap().mode & 0o7777, 0o750);
+
+        let big = read_file(&mut dst_dev, &mut dst, "/big").unwrap();
+        assert_eq!(big.len() as u64, BIG_BLOCKS * 1024 - 100);
+        for (lbn, chunk) in big.chunks(1024).enumerate() {
+            let lbn = lbn as u64;
+            let expect = if lbn == BIG_HOLE { vec![0u8; 1024] } else { big_block(lbn) };
+            assert_eq!(chunk, &expect[..chunk.len()], "lbn {lbn}");
+        }
+        let sparse = read_file(&mut dst_dev, &mut dst, "/sparse").unwrap();
+        let mut expect = vec![0xAA; 1024];
+        expect.extend_from_slice(&[0u8; 2048]);
+        expect.extend_from_slice(&[0xBB; 2048]);
+        assert_eq!(sparse, expect);
+
+        assert_eq!(read_link(&mut dst_dev, &mut dst, "/fast").unwrap(), "docs/none");
+        assert_eq!(
+            read_link(&mut dst_dev, &mut dst, "/slow").unwrap(),
+            "docs/".repeat(16) + "hello_link"
+        );
+        assert_eq!(fsck_metadata_csums(&mut dst_dev, &mut dst).unwrap(), vec![]);
+        umount(dst, &mut dst_dev).unwrap();
+    }
+}
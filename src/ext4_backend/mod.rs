@@ -1,11 +1,18 @@
 pub mod api;
+#[cfg(any(feature = "block-on", test))]
+pub mod blockon;
+#[cfg(any(feature = "std", test))]
+pub mod bench;
 pub mod bitmap;
 pub mod bitmap_cache;
+pub mod block_cache;
 pub mod blockdev;
 pub mod blockgroup_description;
 pub mod bmalloc;
 pub mod config;
+pub mod crc32c;
 pub mod datablock_cache;
+pub mod dentry_cache;
 pub mod dir;
 pub mod disknode;
 pub mod endian;
@@ -15,8 +22,13 @@ pub mod extents_tree;
 pub mod file;
 pub mod hashtree;
 pub mod error;
+pub mod inline_data;
 pub mod inodetable_cache;
 pub mod jbd2;
 pub mod loopfile;
+pub mod migrate;
 pub mod superblock;
+#[cfg(test)]
+pub(crate) mod test_support;
 pub mod tool;
+pub mod xattr;
@@ -1,5 +1,8 @@
+use crate::ext4_backend::blockdev::*;
 use crate::ext4_backend::config::*;
+use crate::ext4_backend::crc32c::*;
 use crate::ext4_backend::endian::*;
+use crate::ext4_backend::error::*;
 use crate::ext4_backend::jbd2::jbdstruct::*;
 ///UUID
 pub struct UUID(pub [u32; 4]);
@@ -259,6 +262,10 @@ impl Ext4Superblock {
 
     /// 超级块大小（字节）
     pub const SUPERBLOCK_SIZE: usize = 1024;
+    /// s_feature_ro_compat 在超级块中的偏移
+    pub const RO_COMPAT_OFFSET: usize = 0x64;
+    /// s_checksum 在超级块中的偏移（最后 4 字节）
+    pub const CHECKSUM_OFFSET: usize = 0x3FC;
 
     /// 检查超级块魔数是否有效
     pub fn is_valid(&self) -> bool {
@@ -270,6 +277,33 @@ impl Ext4Superblock {
         1024 << self.s_log_block_size
     }
 
+    /// 簇大小是否合法：簇不能小于块；未启用 bigalloc 时两者必须相等
+    pub fn cluster_size_valid(&self) -> bool {
+        if self.s_log_cluster_size < self.s_log_block_size {
+            return false;
+        }
+        self.has_feature_ro_compat(Self::EXT4_FEATURE_RO_COMPAT_BIGALLOC)
+            || self.s_log_cluster_size == self.s_log_block_size
+    }
+
+    /// 每簇包含的块数（未启用 bigalloc 时为 1）
+    pub fn cluster_ratio(&self) -> u32 {
+        if !self.has_feature_ro_compat(Self::EXT4_FEATURE_RO_COMPAT_BIGALLOC) {
+            return 1;
+        }
+        1u32 << self.s_log_cluster_size.saturating_sub(self.s_log_block_size)
+    }
+
+    /// 块数转簇数（向上取整）
+    pub fn blocks_to_clusters(&self, blocks: u64) -> u64 {
+        blocks.div_ceil(self.cluster_ratio() as u64)
+    }
+
+    /// 簇数转块数
+    pub fn clusters_to_blocks(&self, clusters: u64) -> u64 {
+        clusters * self.cluster_ratio() as u64
+    }
+
     /// 获取块总数（64位）
     pub fn blocks_count(&self) -> u64 {
         (self.s_blocks_count_hi as u64) << 32 | self.s_blocks_count_lo as u64
@@ -280,18 +314,50 @@ impl Ext4Superblock {
         (self.s_free_blocks_count_hi as u64) << 32 | self.s_free_blocks_count_lo as u64
     }
 
+    /// 设置空闲块数（64位）
+    pub fn set_free_blocks_count(&mut self, count: u64) {
+        self.s_free_blocks_count_lo = count as u32;
+        self.s_free_blocks_count_hi = (count >> 32) as u32;
+    }
+
     /// 获取保留块数（64位）
     pub fn reserved_blocks_count(&self) -> u64 {
         (self.s_r_blocks_count_hi as u64) << 32 | self.s_r_blocks_count_lo as u64
     }
 
-    /// 获取块组数量
+    /// 获取块组数量（未初始化的超级块 s_blocks_per_group 为 0，返回 0）
     pub fn block_groups_count(&self) -> u32 {
         let blocks = self.blocks_count();
         let blocks_per_group = self.s_blocks_per_group as u64;
+        if blocks_per_group == 0 {
+            return 0;
+        }
         blocks.div_ceil(blocks_per_group) as u32
     }
 
+    /// 指定块组实际包含的块数（最后一个块组可能不完整）
+    pub fn blocks_in_group_cnt(&self, bgid: u32) -> u32 {
+        let block_group_count = self.block_groups_count();
+        let blocks_per_group = self.s_blocks_per_group;
+        if block_group_count == 0 || bgid < block_group_count - 1 {
+            return blocks_per_group;
+        }
+        // 先转 u64 再相乘，避免大文件系统上 u32 乘积溢出
+        let full = (block_group_count as u64 - 1) * blocks_per_group as u64;
+        (self.blocks_count() - full) as u32
+    }
+
+    /// 指定块组实际包含的 inode 数（最后一个块组可能不完整）
+    pub fn inodes_in_group_cnt(&self, bgid: u32) -> u32 {
+        let block_group_count = self.block_groups_count();
+        let inodes_per_group = self.s_inodes_per_group;
+        if block_group_count == 0 || bgid < block_group_count - 1 {
+            return inodes_per_group;
+        }
+        let full = (block_group_count as u64 - 1) * inodes_per_group as u64;
+        (self.s_inodes_count as u64).saturating_sub(full) as u32
+    }
+
     /// 每组块数
     pub fn blocks_per_group(&self) -> u32 {
         self.s_blocks_per_group
@@ -366,6 +432,36 @@ impl Ext4Superblock {
     pub fn has_journal(&self) -> bool {
         self.has_feature_compat(Self::EXT4_FEATURE_COMPAT_HAS_JOURNAL)
     }
+
+    /// 是否启用了 metadata_csum 特性
+    pub fn has_metadata_csum(&self) -> bool {
+        self.has_feature_ro_compat(Self::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM)
+    }
+
+    /// 元数据校验和的种子：启用 csum_seed 特性时取 s_checksum_seed，否则由 UUID 计算
+    pub fn metadata_csum_seed(&self) -> u32 {
+        if self.has_feature_incompat(Self::EXT4_FEATURE_INCOMPAT_CSUM_SEED) {
+            self.s_checksum_seed
+        } else {
+            crc32c(!0, &self.s_uuid)
+        }
+    }
+
+    /// 计算超级块校验和
+    /// 直接对磁盘上的原始字节计算（s_checksum 之前的全部内容），
+    /// 不依赖本版本是否认识其中的字段
+    pub fn compute_checksum(raw: &[u8]) -> u32 {
+        crc32c(!0, &raw[..Self::CHECKSUM_OFFSET])
+    }
+
+    /// 校验原始超级块字节；未启用 metadata_csum 时总是通过
+    pub fn verify_checksum(raw: &[u8]) -> bool {
+        let ro_compat = read_u32_le(&raw[Self::RO_COMPAT_OFFSET..]);
+        if ro_compat & Self::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM == 0 {
+            return true;
+        }
+        read_u32_le(&raw[Self::CHECKSUM_OFFSET..]) == Self::compute_checksum(raw)
+    }
 }
 
 // 文件系统状态常量
@@ -431,6 +527,28 @@ impl Ext4Superblock {
     pub const EXT4_FEATURE_INCOMPAT_LARGEDIR: u32 = 0x4000;
     pub const EXT4_FEATURE_INCOMPAT_INLINE_DATA: u32 = 0x8000;
     pub const EXT4_FEATURE_INCOMPAT_ENCRYPT: u32 = 0x10000;
+
+    /// incompat 特性位的名字（与 e2fsprogs 一致），未知位返回 None
+    pub fn incompat_feature_name(bit: u32) -> Option<&'static str> {
+        Some(match bit {
+            Self::EXT4_FEATURE_INCOMPAT_COMPRESSION => "compression",
+            Self::EXT4_FEATURE_INCOMPAT_FILETYPE => "filetype",
+            Self::EXT4_FEATURE_INCOMPAT_RECOVER => "needs_recovery",
+            Self::EXT4_FEATURE_INCOMPAT_JOURNAL_DEV => "journal_dev",
+            Self::EXT4_FEATURE_INCOMPAT_META_BG => "meta_bg",
+            Self::EXT4_FEATURE_INCOMPAT_EXTENTS => "extent",
+            Self::EXT4_FEATURE_INCOMPAT_64BIT => "64bit",
+            Self::EXT4_FEATURE_INCOMPAT_MMP => "mmp",
+            Self::EXT4_FEATURE_INCOMPAT_FLEX_BG => "flex_bg",
+            Self::EXT4_FEATURE_INCOMPAT_EA_INODE => "ea_inode",
+            Self::EXT4_FEATURE_INCOMPAT_DIRDATA => "dirdata",
+            Self::EXT4_FEATURE_INCOMPAT_CSUM_SEED => "metadata_csum_seed",
+            Self::EXT4_FEATURE_INCOMPAT_LARGEDIR => "large_dir",
+            Self::EXT4_FEATURE_INCOMPAT_INLINE_DATA => "inline_data",
+            Self::EXT4_FEATURE_INCOMPAT_ENCRYPT => "encrypt",
+            _ => return None,
+        })
+    }
 }
 
 // 只读兼容特性标志
@@ -839,6 +957,12 @@ impl DiskFormat for Ext4Superblock {
         write_u32!(self.s_checksum);
 
         let _ = offset;
+
+        // 启用 metadata_csum 时按序列化后的字节重新计算校验和
+        if self.has_metadata_csum() {
+            let csum = Self::compute_checksum(bytes);
+            write_u32_le(csum, &mut bytes[Self::CHECKSUM_OFFSET..]);
+        }
     }
 
     fn disk_size() -> usize {
@@ -846,6 +970,80 @@ impl DiskFormat for Ext4Superblock {
     }
 }
 
+/// 超级块所在的 (块号, 块内偏移)：总是位于设备字节偏移 1024 处
+fn sb_location() -> (u32, usize) {
+    let off = Ext4Superblock::SUPERBLOCK_OFFSET as usize;
+    ((off / BLOCK_SIZE) as u32, off % BLOCK_SIZE)
+}
+
+/// 按当前字段重新计算 s_checksum，未启用 metadata_csum 时不变
+pub fn ext4_sb_set_csum(sb: &mut Ext4Superblock) {
+    if !sb.has_metadata_csum() {
+        return;
+    }
+    let mut raw = [0u8; SUPERBLOCK_SIZE];
+    sb.to_disk_bytes(&mut raw);
+    sb.s_checksum = Ext4Superblock::compute_checksum(&raw);
+}
+
+/// 块组 group 是否存放超级块（0 号组为主超级块，其余为备份）
+/// 按 sparse_super / sparse_super2 特性判断
+pub fn ext4_sb_is_super_in_bg(sb: &Ext4Superblock, group: u32) -> bool {
+    crate::ext4_backend::tool::group_has_super_backup(sb, group)
+}
+
+/// 超级块基本字段是否合理：魔数正确、块大小与本实现一致、每组块数/inode 数非 0，
+/// 且每组的簇数和 inode 数都不超过一个位图块的位数（位图只占一个块）
+pub fn ext4_sb_check(sb: &Ext4Superblock) -> bool {
+    let bits_per_block = 8 * BLOCK_SIZE as u32;
+    if !(sb.is_valid()
+        && sb.block_size() == BLOCK_SIZE as u64
+        && sb.s_blocks_per_group != 0
+        && sb.s_inodes_per_group != 0)
+    {
+        return false;
+    }
+    let ratio = sb.cluster_ratio();
+    let clusters_per_group = if ratio > 1 && sb.s_clusters_per_group != 0 {
+        sb.s_clusters_per_group
+    } else {
+        sb.s_blocks_per_group / ratio
+    };
+    clusters_per_group <= bits_per_block && sb.s_inodes_per_group <= bits_per_block
+}
+
+/// 写超级块到设备偏移 1024 处，先更新校验和
+/// 块大小大于 1024 时对所在块做 read-modify-write，块内其它字节保持不变
+pub fn ext4_sb_write<B: BlockDevice>(block_dev: &mut B, sb: &mut Ext4Superblock) -> BlockDevResult<()> {
+    ext4_sb_set_csum(sb);
+    let (block, offset) = sb_location();
+    let mut buf = [0u8; BLOCK_SIZE];
+    if offset != 0 {
+        block_dev.read(&mut buf, block, 1)?;
+    }
+    sb.to_disk_bytes(&mut buf[offset..offset + SUPERBLOCK_SIZE]);
+    block_dev.write(&buf, block, 1)?;
+    block_dev.flush()
+}
+
+/// 从设备偏移 1024 处读出超级块到 sb 并检查
+/// 基本字段不合理返回 Corrupted；校验和按原始 1024 字节计算，不匹配返回 ChecksumError，
+/// 两种情况下 sb 都已经填好，调用方可以自行决定是否继续使用
+pub fn ext4_sb_read<B: BlockDevice>(block_dev: &mut B, sb: &mut Ext4Superblock) -> BlockDevResult<()> {
+    let (block, offset) = sb_location();
+    let mut buf = [0u8; BLOCK_SIZE];
+    block_dev.read(&mut buf, block, 1)?;
+    let raw = &buf[offset..offset + SUPERBLOCK_SIZE];
+    *sb = Ext4Superblock::from_disk_bytes(raw);
+    if !ext4_sb_check(sb) {
+        return Err(BlockDevError::Corrupted);
+    }
+    if !Ext4Superblock::verify_checksum(raw) {
+        return Err(BlockDevError::ChecksumError);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -919,4 +1117,124 @@ mod tests {
         assert_eq!(sb2.s_blocks_count_lo, 0xFFFFFFFF);
         assert_eq!(sb2.s_blocks_count_hi, 0x00000001);
     }
+
+    #[test]
+    fn test_last_group_counts_no_u32_overflow() {
+        let mut sb = Ext4Superblock::default();
+        sb.s_blocks_per_group = 32768;
+        // 2^32 + 1000 块：(groups - 1) * blocks_per_group == 2^32，u32 下会溢出为 0
+        let total: u64 = (1u64 << 32) + 1000;
+        sb.s_blocks_count_lo = total as u32;
+        sb.s_blocks_count_hi = (total >> 32) as u32;
+        let groups = sb.block_groups_count();
+        assert_eq!(groups, 131073);
+
+        assert_eq!(sb.blocks_in_group_cnt(0), 32768);
+        assert_eq!(sb.blocks_in_group_cnt(groups - 2), 32768);
+        assert_eq!(sb.blocks_in_group_cnt(groups - 1), 1000);
+
+        sb.s_inodes_per_group = 8192;
+        sb.s_inodes_count = (groups - 1) * 8192 + 100;
+        assert_eq!(sb.inodes_in_group_cnt(0), 8192);
+        assert_eq!(sb.inodes_in_group_cnt(groups - 1), 100);
+    }
+
+    #[test]
+    fn test_cluster_conversion() {
+        let mut sb = Ext4Superblock::default();
+        sb.s_log_block_size = 2; // 4KB
+        sb.s_log_cluster_size = 2;
+        assert!(sb.cluster_size_valid());
+        assert_eq!(sb.cluster_ratio(), 1);
+        assert_eq!(sb.blocks_to_clusters(5), 5);
+
+        // bigalloc：簇 16KB，块 4KB
+        sb.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_BIGALLOC;
+        sb.s_log_cluster_size = 4;
+        assert!(sb.cluster_size_valid());
+        assert_eq!(sb.cluster_ratio(), 4);
+        assert_eq!(sb.blocks_to_clusters(1), 1);
+        assert_eq!(sb.blocks_to_clusters(5), 2);
+        assert_eq!(sb.clusters_to_blocks(3), 12);
+
+        // 簇小于块非法；未开 bigalloc 却簇大于块也非法
+        sb.s_log_cluster_size = 1;
+        assert!(!sb.cluster_size_valid());
+        sb.s_feature_ro_compat &= !Ext4Superblock::EXT4_FEATURE_RO_COMPAT_BIGALLOC;
+        sb.s_log_cluster_size = 4;
+        assert!(!sb.cluster_size_valid());
+    }
+
+    #[test]
+    fn sb_write_read_roundtrip_preserves_rest_of_block() {
+        use crate::ext4_backend::test_support::MemBlockDev;
+
+        let mut dev = MemBlockDev::new(16);
+        let (block, offset) = sb_location();
+        dev.write(&[0xABu8; BLOCK_SIZE], block, 1).unwrap();
+
+        let mut sb = Ext4Superblock {
+            s_magic: Ext4Superblock::EXT4_SUPER_MAGIC,
+            s_log_block_size: LOG_BLOCK_SIZE,
+            s_log_cluster_size: LOG_BLOCK_SIZE,
+            s_blocks_per_group: 32768,
+            s_inodes_per_group: 8192,
+            s_inode_size: 256,
+            ..Ext4Superblock::default()
+        };
+        sb.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        ext4_sb_write(&mut dev, &mut sb).unwrap();
+        assert_ne!(sb.s_checksum, 0);
+
+        // 同一块中超级块之外的字节保持原样
+        let mut buf = [0u8; BLOCK_SIZE];
+        dev.read(&mut buf, block, 1).unwrap();
+        assert!(buf[..offset].iter().all(|&b| b == 0xAB));
+        assert!(buf[offset + SUPERBLOCK_SIZE..].iter().all(|&b| b == 0xAB));
+
+        let mut back = Ext4Superblock::default();
+        ext4_sb_read(&mut dev, &mut back).unwrap();
+        assert_eq!(back.s_inodes_per_group, 8192);
+        assert_eq!(back.s_checksum, sb.s_checksum);
+
+        // 校验和被破坏：字段照样读出，但返回 ChecksumError
+        buf[offset + 0x300] ^= 0xFF;
+        dev.write(&buf, block, 1).unwrap();
+        let mut bad = Ext4Superblock::default();
+        assert!(matches!(ext4_sb_read(&mut dev, &mut bad), Err(BlockDevError::ChecksumError)));
+        assert_eq!(bad.s_inodes_per_group, 8192);
+
+        // 魔数不对视为损坏
+        sb.s_magic = 0;
+        ext4_sb_write(&mut dev, &mut sb).unwrap();
+        assert!(matches!(ext4_sb_read(&mut dev, &mut bad), Err(BlockDevError::Corrupted)));
+    }
+
+    #[test]
+    fn superblock_bytes_match_simulated_big_endian_host() {
+        let mut sb = Ext4Superblock {
+            s_magic: Ext4Superblock::EXT4_SUPER_MAGIC,
+            s_inodes_count: 0x0102_0304,
+            s_blocks_count_lo: 0x1122_3344,
+            s_blocks_count_hi: 0x5566_7788,
+            s_feature_incompat: Ext4Superblock::EXT4_FEATURE_INCOMPAT_64BIT,
+            ..Default::default()
+        };
+        sb.s_uuid[0] = 0xAA;
+        let mut raw = [0u8; SUPERBLOCK_SIZE];
+        sb.to_disk_bytes(&mut raw);
+
+        // 大端主机上字段经 to_le 转换后的内存字节，必须与磁盘字节相同
+        let be16 = |v: u16| le16_on(true, v).to_be_bytes();
+        let be32 = |v: u32| le32_on(true, v).to_be_bytes();
+        assert_eq!(&raw[0x00..0x04], &be32(0x0102_0304));
+        assert_eq!(&raw[0x04..0x08], &be32(0x1122_3344));
+        assert_eq!(&raw[0x150..0x154], &be32(0x5566_7788));
+        assert_eq!(&raw[0x38..0x3A], &be16(Ext4Superblock::EXT4_SUPER_MAGIC));
+        assert_eq!(raw[0x68], 0xAA);
+
+        let back = Ext4Superblock::from_disk_bytes(&raw);
+        assert_eq!(back.blocks_count(), 0x5566_7788_1122_3344);
+        assert_eq!(back.s_inodes_count, from_le32(to_le32(0x0102_0304)));
+    }
 }
@@ -0,0 +1,383 @@
+//! 单元测试共用的内存块设备与挂载辅助
+//!
+//! 仅在 `cfg(test)` 下编译
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+
+use crate::ext4_backend::bitmap_cache::CacheKey;
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::disknode::Ext4Inode;
+use crate::ext4_backend::entries::{dir_csum, DirEntryIterator};
+use crate::ext4_backend::dir::dir_csum_seed;
+use crate::ext4_backend::endian::{read_u32_le, DiskFormat};
+use crate::ext4_backend::extents_tree::ExtentTree;
+use crate::ext4_backend::inodetable_cache::set_inode_csum;
+use crate::ext4_backend::superblock::Ext4Superblock;
+use crate::ext4_backend::blockgroup_description::Ext4GroupDesc;
+use crate::ext4_backend::xattr::block_csum;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::jbd2::jbdstruct::JOURNAL_FILE_INODE;
+use crate::ext4_backend::loopfile::*;
+
+/// 基于内存的块设备，块大小固定为 BLOCK_SIZE
+/// 只保存写过的块，未写过的块读出为 0，便于构造多块组的大镜像
+#[derive(Clone)]
+pub struct MemBlockDev {
+    blocks: BTreeMap<u64, Box<[u8; BLOCK_SIZE]>>,
+    pub total_blocks: u64,
+}
+
+impl MemBlockDev {
+    pub fn new(total_blocks: u64) -> Self {
+        Self {
+            blocks: BTreeMap::new(),
+            total_blocks,
+        }
+    }
+
+    fn check(&self, block_id: u64, count: u32, provided: usize) -> BlockDevResult<usize> {
+        if block_id + count as u64 > self.total_blocks {
+            return Err(BlockDevError::BlockOutOfRange {
+                block_id,
+                max_blocks: self.total_blocks,
+            });
+        }
+        let required = count as usize * BLOCK_SIZE;
+        if provided < required {
+            return Err(BlockDevError::BufferTooSmall { provided, required });
+        }
+        Ok(required)
+    }
+}
+
+impl BlockDevice for MemBlockDev {
+    fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.write64(buffer, block_id as u64, count)
+    }
+
+    fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.read64(buffer, block_id as u64, count)
+    }
+
+    /// 块号按 u64 保存，可以模拟超过 2^32 个块的稀疏大镜像
+    fn write64(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        self.check(block_id, count, buffer.len())?;
+        for (i, chunk) in buffer.chunks_exact(BLOCK_SIZE).take(count as usize).enumerate() {
+            let block = self
+                .blocks
+                .entry(block_id + i as u64)
+                .or_insert_with(|| Box::new([0u8; BLOCK_SIZE]));
+            block.copy_from_slice(chunk);
+        }
+        Ok(())
+    }
+
+    fn read64(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        let required = self.check(block_id, count, buffer.len())?;
+        for (i, chunk) in buffer[..required].chunks_exact_mut(BLOCK_SIZE).enumerate() {
+            match self.blocks.get(&(block_id + i as u64)) {
+                Some(block) => chunk.copy_from_slice(&block[..]),
+                None => chunk.fill(0),
+            }
+        }
+        Ok(())
+    }
+
+    /// 丢弃的块直接从内存中移除，之后读出为 0
+    fn discard(&mut self, block_id: u64, count: u32) -> BlockDevResult<()> {
+        self.check(block_id, count, count as usize * BLOCK_SIZE)?;
+        for id in block_id..block_id + count as u64 {
+            self.blocks.remove(&id);
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> DevCaps {
+        DevCaps {
+            discard: true,
+            flush: false,
+            rotational: false,
+            sector_size: BLOCK_SIZE_U32,
+            readonly: false,
+        }
+    }
+
+    fn open(&mut self) -> BlockDevResult<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> BlockDevResult<()> {
+        Ok(())
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.total_blocks
+    }
+
+    fn block_size(&self) -> u32 {
+        BLOCK_SIZE_U32
+    }
+}
+
+/// 记录底层读写调用次数、写入区间和 discard 区间的内存设备
+pub struct CountingDev {
+    pub inner: MemBlockDev,
+    pub reads: u32,
+    pub writes: u32,
+    /// 每次写入的 (起始块号, 块数)
+    pub write_log: Vec<(u32, u32)>,
+    /// 每次读取的 (起始块号, 块数)
+    pub read_log: Vec<(u32, u32)>,
+    pub discards: Vec<(u64, u32)>,
+    /// 对外报告的设备能力
+    pub caps: DevCaps,
+}
+
+impl CountingDev {
+    pub fn new(total_blocks: u64) -> Self {
+        Self {
+            inner: MemBlockDev::new(total_blocks),
+            reads: 0,
+            writes: 0,
+            write_log: Vec::new(),
+            read_log: Vec::new(),
+            discards: Vec::new(),
+            caps: DevCaps {
+                discard: true,
+                flush: true,
+                rotational: false,
+                sector_size: BLOCK_SIZE_U32,
+                readonly: false,
+            },
+        }
+    }
+}
+
+impl BlockDevice for CountingDev {
+    fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.writes += 1;
+        self.write_log.push((block_id, count));
+        self.inner.write(buffer, block_id, count)
+    }
+
+    fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.reads += 1;
+        self.read_log.push((block_id, count));
+        self.inner.read(buffer, block_id, count)
+    }
+
+    fn discard(&mut self, block_id: u64, count: u32) -> BlockDevResult<()> {
+        self.discards.push((block_id, count));
+        self.inner.discard(block_id, count)
+    }
+
+    fn capabilities(&self) -> DevCaps {
+        self.caps
+    }
+
+    fn open(&mut self) -> BlockDevResult<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> BlockDevResult<()> {
+        Ok(())
+    }
+
+    fn total_blocks(&self) -> u64 {
+        self.inner.total_blocks
+    }
+
+    fn block_size(&self) -> u32 {
+        BLOCK_SIZE_U32
+    }
+}
+
+/// mkfs 并挂载一个全新的内存文件系统（不开启日志）
+pub fn setup_fs(total_blocks: u64) -> (Jbd2Dev<MemBlockDev>, Ext4FileSystem) {
+    let dev = MemBlockDev::new(total_blocks);
+    let mut jbd = Jbd2Dev::initial_jbd2dev(0, dev, false, false);
+    mkfs(&mut jbd).unwrap();
+    let fs = mount(&mut jbd).unwrap();
+    (jbd, fs)
+}
+
+/// 简化版 e2fsck 第 1/5 遍：从根目录和日志 inode 出发找出所有可达 inode 及其占用的块
+/// （数据块、extent 树块、扩展属性块），与位图逐位比对
+/// 返回（位图已分配却无属主的块，位图已分配却不可达的 inode），一致时两者都为空
+/// 元数据块和保留 inode 不参与比较；BLOCK_UNINIT 块组没有位图，跳过
+pub fn fsck_bitmap_owners<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+) -> BlockDevResult<(Vec<u64>, Vec<u32>)> {
+    let mut reachable: BTreeSet<u32> = BTreeSet::new();
+    let mut owned: BTreeSet<u64> = BTreeSet::new();
+    let mut pending: Vec<u32> = alloc::vec![2];
+    if fs.superblock.has_journal() {
+        pending.push(JOURNAL_FILE_INODE as u32);
+    }
+    while let Some(ino) = pending.pop() {
+        if !reachable.insert(ino) {
+            continue;
+        }
+        let mut inode = fs.get_inode_by_num(dev, ino)?;
+        let blocks = resolve_inode_block_allextend(fs, dev, ino, &mut inode)?;
+        owned.extend(collect_extent_tree_blocks(dev, fs, ino, &mut inode)?);
+        if inode.file_acl() != 0 {
+            owned.insert(inode.file_acl());
+        }
+        if inode.is_dir() {
+            for &blk in blocks.values() {
+                let data = fs.datablock_cache.get_or_load(dev, blk)?.data.clone();
+                let mut iter = DirEntryIterator::new(&data);
+                for (entry, _) in iter.by_ref() {
+                    if entry.inode != 0 && entry.name != b"." && entry.name != b".." {
+                        pending.push(entry.inode);
+                    }
+                }
+                if let Some(e) = iter.error() {
+                    return Err(e);
+                }
+            }
+        }
+        owned.extend(blocks.into_values());
+    }
+
+    let ratio = fs.block_allocator.cluster_ratio() as u64;
+    let first_ino = fs.superblock.s_first_ino;
+    let inodes_per_group = fs.superblock.s_inodes_per_group;
+    let mut orphan_blocks = Vec::new();
+    let mut orphan_inodes = Vec::new();
+    for group_idx in 0..fs.group_descs.len() as u32 {
+        let desc = fs.group_descs[group_idx as usize];
+        if !desc.is_block_bitmap_uninit() {
+            let first_block = fs.block_allocator.group_first_block(group_idx);
+            let clusters = fs.block_allocator.clusters_in_group(group_idx);
+            let bitmap = fs
+                .bitmap_cache
+                .get_or_load(dev, CacheKey::new_block(group_idx), desc.block_bitmap())?
+                .data
+                .clone();
+            for c in 0..clusters {
+                if bitmap[(c / 8) as usize] & (1 << (c % 8)) == 0
+                    || fs.block_allocator.cluster_is_metadata(group_idx, c)
+                {
+                    continue;
+                }
+                let start = first_block + c as u64 * ratio;
+                if owned.range(start..start + ratio).next().is_none() {
+                    orphan_blocks.push(start);
+                }
+            }
+        }
+        if !desc.is_inode_bitmap_uninit() {
+            let bitmap = fs
+                .bitmap_cache
+                .get_or_load(dev, CacheKey::new_inode(group_idx), desc.inode_bitmap())?
+                .data
+                .clone();
+            for i in 0..inodes_per_group {
+                let ino = group_idx * inodes_per_group + i + 1;
+                if ino >= first_ino
+                    && bitmap[(i / 8) as usize] & (1 << (i % 8)) != 0
+                    && !reachable.contains(&ino)
+                {
+                    orphan_inodes.push(ino);
+                }
+            }
+        }
+    }
+    Ok((orphan_blocks, orphan_inodes))
+}
+
+/// 逐项核对 metadata_csum 校验和：超级块、块组描述符，以及从根目录和日志 inode 可达的
+/// inode、目录块（叶子块须带块尾）、extent 树块、扩展属性块。直接读盘，调用前须已写回缓存
+/// 返回校验和不对的（元数据种类, 块号或 inode 号 / 组号），全部正确时为空
+pub fn fsck_metadata_csums<B: BlockDevice>(
+    dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+) -> BlockDevResult<Vec<(&'static str, u64)>> {
+    let mut bad = Vec::new();
+    let fs_seed = fs.superblock.metadata_csum_seed();
+    let off = Ext4Superblock::SUPERBLOCK_OFFSET as usize;
+    dev.read_block(0)?;
+    if !Ext4Superblock::verify_checksum(&dev.buffer()[off..off + SUPERBLOCK_SIZE]) {
+        bad.push(("superblock", 0));
+    }
+
+    let desc_size = fs.superblock.get_desc_size() as usize;
+    for gid in 0..fs.group_descs.len() {
+        let pos = gid * desc_size;
+        dev.read_block(1 + (pos / BLOCK_SIZE) as u64)?;
+        let raw = &dev.buffer()[pos % BLOCK_SIZE..pos % BLOCK_SIZE + desc_size];
+        let desc = Ext4GroupDesc::from_disk_bytes(raw);
+        if desc.bg_checksum != desc.metadata_csum(fs_seed, gid as u32, desc_size) {
+            bad.push(("group descriptor", gid as u64));
+        }
+    }
+
+    let inode_size = fs.superblock.s_inode_size as usize;
+    let mut reachable: BTreeSet<u32> = BTreeSet::new();
+    let mut pending: Vec<u32> = alloc::vec![2];
+    if fs.superblock.has_journal() {
+        pending.push(JOURNAL_FILE_INODE as u32);
+    }
+    while let Some(ino) = pending.pop() {
+        if !reachable.insert(ino) {
+            continue;
+        }
+        let (blk, pos) = fs.locate_inode(dev, ino)?;
+        dev.read_block(blk)?;
+        let raw = dev.buffer()[pos..pos + inode_size].to_vec();
+        let mut inode = Ext4Inode::from_disk_bytes(&raw);
+        let mut expect = raw.clone();
+        set_inode_csum(&mut expect, fs_seed, ino, &inode);
+        if expect != raw {
+            bad.push(("inode", ino as u64));
+        }
+
+        let tree_seed = ExtentTree::inode_csum_seed(fs_seed, ino, inode.i_generation);
+        for blk in collect_extent_tree_blocks(dev, fs, ino, &mut inode)? {
+            dev.read_block(blk)?;
+            if !ExtentTree::verify_block_checksum(tree_seed, dev.buffer()) {
+                bad.push(("extent block", blk));
+            }
+        }
+        if inode.file_acl() != 0 {
+            dev.read_block(inode.file_acl())?;
+            let data = dev.buffer();
+            if read_u32_le(&data[16..20]) != block_csum(fs, inode.file_acl(), data) {
+                bad.push(("xattr block", inode.file_acl()));
+            }
+        }
+        if !inode.is_dir() || inode.has_inline_data() {
+            continue;
+        }
+        let dir_seed = dir_csum_seed(fs, ino, &inode).ok_or(BlockDevError::Corrupted)?;
+        let indexed = inode.i_flags & Ext4Inode::EXT4_INDEX_FL != 0;
+        for (lbn, blk) in resolve_inode_block_allextend(fs, dev, ino, &mut inode)? {
+            dev.read_block(blk)?;
+            let data = dev.buffer().to_vec();
+            // 索引块没有目录块尾，由 verify 按 dx_tail 校验
+            let leaf = !indexed || (lbn != 0 && DirEntryIterator::new(&data).next().is_some());
+            if (leaf && !dir_csum::has_tail(&data)) || !dir_csum::verify(dir_seed, &data) {
+                bad.push(("dir block", blk));
+            }
+            for (entry, _) in DirEntryIterator::new(&data) {
+                if entry.name != b"." && entry.name != b".." {
+                    pending.push(entry.inode);
+                }
+            }
+        }
+    }
+    Ok(bad)
+}
@@ -63,6 +63,23 @@ pub fn need_redundant_backup(gid: u32) -> bool {
     }
     false
 }
+/// 该块组是否存放超级块/GDT 备份（按特性判断）
+/// - sparse_super2：只有 `s_backup_bgs` 记录的两个块组
+/// - sparse_super：组 1 以及 3、5、7 的幂
+/// - 都未开启：每个块组都有备份
+pub fn group_has_super_backup(sb: &Ext4Superblock, gid: u32) -> bool {
+    if gid == 0 {
+        return true;
+    }
+    if sb.has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_SPARSE_SUPER2) {
+        return sb.s_backup_bgs.contains(&gid);
+    }
+    if !sb.has_feature_ro_compat(Ext4Superblock::EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER) {
+        return true;
+    }
+    need_redundant_backup(gid)
+}
+
 ///number是不是base的幂
 pub fn is_numbers_power(number: usize, base: usize) -> bool {
     let mut tmp_number = number;
@@ -77,7 +94,7 @@ pub fn is_numbers_power(number: usize, base: usize) -> bool {
 
 ///根据块组号 计算块组布局（仅在 mkfs 阶段使用）
 /// - `gid` 当前块组号
-/// - `sb`  超级块（用于检查是否启用 sparse_super）
+/// - `sb`  超级块（用于检查 sparse_super / sparse_super2）
 /// - `blocks_per_group` 每组块数
 /// - `inode_table_blocks` 每组 inode 表占用的块数
 /// - `group0_block_bitmap`/`group0_inode_bitmap`/`group0_inode_table` 组0的固定布局
@@ -102,18 +119,14 @@ pub fn cloc_group_layout(
         };
     }
 
-    // 普通块组从其起始块开始布置
-    let group_start = gid * blocks_per_group;
-
-    // 是否启用 sparse super
-    let sparse_feature =
-        sb.has_feature_ro_compat(Ext4Superblock::EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER);
+    // 普通块组从其起始块开始布置（u64 计算，避免大文件系统上溢出）
+    let group_start = gid as u64 * blocks_per_group as u64;
 
     // 是否在该组放置超级块 / GDT 备份
-    let has_backup = sparse_feature && need_redundant_backup(gid);
+    let has_backup = group_has_super_backup(sb, gid);
 
     let (block_bitmap, inode_bitmap, inode_table, meta_blocks) = if has_backup {
-        let bb = group_start + 1 + gdt_blocks;
+        let bb = group_start + 1 + gdt_blocks as u64;
         let ib = bb + 1;
         let it = ib + 1;
         let meta = 1 + gdt_blocks + 1 + 1 + inode_table_blocks;
@@ -127,10 +140,10 @@ pub fn cloc_group_layout(
     };
 
     BlcokGroupLayout {
-        group_start_block: group_start as u64,
-        group_blcok_bitmap_startblocks: block_bitmap as u64,
-        group_inode_bitmap_startblocks: inode_bitmap as u64,
-        group_inode_table_startblocks: inode_table as u64,
+        group_start_block: group_start,
+        group_blcok_bitmap_startblocks: block_bitmap,
+        group_inode_bitmap_startblocks: inode_bitmap,
+        group_inode_table_startblocks: inode_table,
         metadata_blocks_in_group: meta_blocks,
     }
 }
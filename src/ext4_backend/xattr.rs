@@ -0,0 +1,602 @@
+//! 扩展属性（xattr）
+//!
+//! 属性优先放在大 inode 结构体字段之后的空闲区（ibody，从 128 + i_extra_isize 起），
+//! 放不下的溢出到 i_file_acl 指向的独立属性块。两处条目格式相同：
+//! 16 字节条目头 + 名字（按 4 字节对齐），以 4 字节 0 结尾；值从区域末尾向前存放。
+//! ibody 的值偏移相对第一个条目，属性块的值偏移相对块首。
+//! 名字前缀（user./trusted./security./system.）折算成属性索引存储，只保存前缀之后的部分
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::ext4_backend::blockdev::*;
+use crate::ext4_backend::config::*;
+use crate::ext4_backend::crc32c::crc32c;
+use crate::ext4_backend::disknode::*;
+use crate::ext4_backend::endian::*;
+use crate::ext4_backend::error::*;
+use crate::ext4_backend::ext4::*;
+use crate::ext4_backend::loopfile::*;
+use crate::ext4_backend::superblock::*;
+
+/// ibody 头和属性块头的魔数
+pub const XATTR_MAGIC: u32 = 0xEA02_0000;
+/// 属性块头长度，条目紧随其后
+const BLOCK_HEADER_SIZE: usize = 32;
+/// 属性块头中 h_checksum 的偏移
+const BLOCK_CSUM_OFFSET: usize = 16;
+/// ibody 头只有魔数
+const IBODY_HEADER_SIZE: usize = 4;
+/// 条目头长度（不含名字）
+const ENTRY_HEADER_SIZE: usize = 16;
+/// 条目列表的结束标记长度
+const END_MARKER_SIZE: usize = 4;
+
+/// 名字前缀与属性索引；posix_acl 两项是完整名字，不带后缀
+const NAME_PREFIXES: [(u8, &str); 6] = [
+    (2, "system.posix_acl_access"),
+    (3, "system.posix_acl_default"),
+    (1, "user."),
+    (4, "trusted."),
+    (6, "security."),
+    (7, "system."),
+];
+
+/// 一个扩展属性：索引 + 去掉前缀的名字 + 值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XattrEntry {
+    pub index: u8,
+    pub name: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+impl XattrEntry {
+    /// 带前缀的完整名字
+    pub fn full_name(&self) -> String {
+        let prefix = NAME_PREFIXES
+            .iter()
+            .find(|(idx, _)| *idx == self.index)
+            .map_or("", |(_, p)| p);
+        let mut name = String::from(prefix);
+        name.push_str(&String::from_utf8_lossy(&self.name));
+        name
+    }
+
+    /// 条目头加名字占用的字节数
+    fn entry_len(&self) -> usize {
+        pad4(ENTRY_HEADER_SIZE + self.name.len())
+    }
+
+    /// 条目和值合计占用的字节数
+    fn footprint(&self) -> usize {
+        self.entry_len() + pad4(self.value.len())
+    }
+
+    /// 条目哈希：先混入名字，再按 32 位小端字混入值
+    fn hash(&self) -> u32 {
+        let mut hash = 0u32;
+        for &c in &self.name {
+            hash = (hash << 5) ^ (hash >> 27) ^ (c as i8 as i32 as u32);
+        }
+        let mut padded = self.value.clone();
+        padded.resize(pad4(padded.len()), 0);
+        for word in padded.chunks_exact(4) {
+            let w = read_u32_le(word);
+            hash = (hash << 16) ^ (hash >> 16) ^ w;
+        }
+        hash
+    }
+}
+
+fn pad4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+/// 把完整名字拆成 (索引, 后缀)；未知命名空间返回 Unsupported
+pub fn split_xattr_name(name: &str) -> BlockDevResult<(u8, &[u8])> {
+    for (index, prefix) in NAME_PREFIXES {
+        if let Some(suffix) = name.strip_prefix(prefix) {
+            // posix_acl 必须完全匹配；其它前缀后面必须还有名字
+            let exact = index == 2 || index == 3;
+            if exact != suffix.is_empty() {
+                continue;
+            }
+            if suffix.len() > 255 {
+                return Err(BlockDevError::InvalidInput);
+            }
+            return Ok((index, suffix.as_bytes()));
+        }
+    }
+    if name.is_empty() {
+        return Err(BlockDevError::InvalidInput);
+    }
+    Err(BlockDevError::Unsupported)
+}
+
+/// 从 region[start..] 解析条目列表，值偏移相对 region[value_base]
+fn parse_entries(region: &[u8], start: usize, value_base: usize) -> BlockDevResult<Vec<XattrEntry>> {
+    let mut out = Vec::new();
+    let mut pos = start;
+    loop {
+        let Some(head) = region.get(pos..pos + END_MARKER_SIZE) else {
+            return Err(BlockDevError::Corrupted);
+        };
+        if head == [0; END_MARKER_SIZE] {
+            return Ok(out);
+        }
+        let hdr = region
+            .get(pos..pos + ENTRY_HEADER_SIZE)
+            .ok_or(BlockDevError::Corrupted)?;
+        let name_len = hdr[0] as usize;
+        let index = hdr[1];
+        let value_offs = read_u16_le(&hdr[2..4]) as usize;
+        let value_inum = read_u32_le(&hdr[4..8]);
+        let value_size = read_u32_le(&hdr[8..12]) as usize;
+        if value_inum != 0 {
+            // 值存放在独立 inode 中（ea_inode）
+            return Err(BlockDevError::Unsupported);
+        }
+        let name = region
+            .get(pos + ENTRY_HEADER_SIZE..pos + ENTRY_HEADER_SIZE + name_len)
+            .ok_or(BlockDevError::Corrupted)?
+            .to_vec();
+        let value = if value_size == 0 {
+            Vec::new()
+        } else {
+            let begin = value_base + value_offs;
+            region
+                .get(begin..begin + value_size)
+                .ok_or(BlockDevError::Corrupted)?
+                .to_vec()
+        };
+        out.push(XattrEntry { index, name, value });
+        pos += pad4(ENTRY_HEADER_SIZE + name_len);
+    }
+}
+
+/// 把条目写进 region[start..]（调用方已确认放得下），值从 region 末尾向前存放
+fn encode_entries(entries: &[XattrEntry], region: &mut [u8], start: usize, value_base: usize) {
+    region[start..].fill(0);
+    let mut pos = start;
+    let mut value_end = region.len();
+    for e in entries {
+        let value_offs = if e.value.is_empty() {
+            0
+        } else {
+            value_end -= pad4(e.value.len());
+            region[value_end..value_end + e.value.len()].copy_from_slice(&e.value);
+            value_end - value_base
+        };
+        let hdr = &mut region[pos..pos + ENTRY_HEADER_SIZE];
+        hdr[0] = e.name.len() as u8;
+        hdr[1] = e.index;
+        write_u16_le(value_offs as u16, &mut hdr[2..4]);
+        write_u32_le(e.value.len() as u32, &mut hdr[8..12]);
+        write_u32_le(e.hash(), &mut hdr[12..16]);
+        region[pos + ENTRY_HEADER_SIZE..pos + ENTRY_HEADER_SIZE + e.name.len()]
+            .copy_from_slice(&e.name);
+        pos += e.entry_len();
+    }
+}
+
+/// inode 扩展属性区中 ibody 的起点；小 inode 或 i_extra_isize 异常时没有 ibody
+fn ibody_start(inode: &Ext4Inode, xattr_area: &[u8]) -> Option<usize> {
+    let begin = (Ext4Inode::GOOD_OLD_INODE_SIZE as usize + inode.i_extra_isize as usize)
+        .checked_sub(Ext4Inode::CORE_FIELDS_SIZE)?;
+    (begin + IBODY_HEADER_SIZE + END_MARKER_SIZE <= xattr_area.len()).then_some(begin)
+}
+
+/// ibody 可容纳的条目与值总字节数
+fn ibody_capacity(inode: &Ext4Inode, xattr_area: &[u8]) -> usize {
+    ibody_start(inode, xattr_area)
+        .map_or(0, |b| xattr_area.len() - b - IBODY_HEADER_SIZE - END_MARKER_SIZE)
+}
+
+fn read_ibody(inode: &Ext4Inode, xattr_area: &[u8]) -> BlockDevResult<Vec<XattrEntry>> {
+    let Some(begin) = ibody_start(inode, xattr_area) else {
+        return Ok(Vec::new());
+    };
+    let region = &xattr_area[begin..];
+    if read_u32_le(region) != XATTR_MAGIC {
+        return Ok(Vec::new());
+    }
+    parse_entries(region, IBODY_HEADER_SIZE, IBODY_HEADER_SIZE)
+}
+
+/// 用给定条目重写 ibody（调用方已确认放得下），没有条目时整个区域清零
+fn write_ibody(inode: &Ext4Inode, xattr_area: &mut [u8], entries: &[XattrEntry]) {
+    let Some(begin) = ibody_start(inode, xattr_area) else {
+        return;
+    };
+    let region = &mut xattr_area[begin..];
+    if entries.is_empty() {
+        region.fill(0);
+    } else {
+        write_u32_le(XATTR_MAGIC, &mut region[..IBODY_HEADER_SIZE]);
+        encode_entries(entries, region, IBODY_HEADER_SIZE, IBODY_HEADER_SIZE);
+    }
+}
+
+/// 只在 ibody 内读出属性值（不读属性块），供 inline_data 等内部属性使用
+pub fn ibody_xattr(
+    inode: &Ext4Inode,
+    xattr_area: &[u8],
+    index: u8,
+    name: &[u8],
+) -> BlockDevResult<Option<Vec<u8>>> {
+    Ok(read_ibody(inode, xattr_area)?
+        .into_iter()
+        .find(|e| e.index == index && e.name == name)
+        .map(|e| e.value))
+}
+
+/// 只在 ibody 内改写条目（不涉及属性块），改写后放不下时返回 NoSpace 且不做修改
+pub fn modify_ibody_xattrs<F>(inode: &Ext4Inode, xattr_area: &mut [u8], f: F) -> BlockDevResult<()>
+where
+    F: FnOnce(&mut Vec<XattrEntry>),
+{
+    let mut entries = read_ibody(inode, xattr_area)?;
+    f(&mut entries);
+    let used: usize = entries.iter().map(XattrEntry::footprint).sum();
+    if used > ibody_capacity(inode, xattr_area) {
+        return Err(BlockDevError::NoSpace);
+    }
+    write_ibody(inode, xattr_area, &entries);
+    Ok(())
+}
+
+/// 属性块校验和：依次混入 64 位块号和 h_checksum 置零后的整块
+pub(crate) fn block_csum(fs: &Ext4FileSystem, block: u64, data: &[u8]) -> u32 {
+    let seed = crc32c(fs.superblock.metadata_csum_seed(), &block.to_le_bytes());
+    let seed = crc32c(seed, &data[..BLOCK_CSUM_OFFSET]);
+    let seed = crc32c(seed, &[0u8; 4]);
+    crc32c(seed, &data[BLOCK_CSUM_OFFSET + 4..BLOCK_SIZE])
+}
+
+/// 按当前内容重算属性块的校验和并写回（打开 metadata_csum 时为已有属性块补算）
+pub(crate) fn refresh_block_csum<B: BlockDevice>(
+    fs: &Ext4FileSystem,
+    dev: &mut Jbd2Dev<B>,
+    block: u64,
+) -> BlockDevResult<()> {
+    dev.read_block(block)?;
+    let sum = block_csum(fs, block, dev.buffer());
+    write_u32_le(sum, &mut dev.buffer_mut()[BLOCK_CSUM_OFFSET..BLOCK_CSUM_OFFSET + 4]);
+    dev.write_block(block, true)
+}
+
+/// 读取属性块，返回 (引用计数, 条目)
+fn read_block<B: BlockDevice>(
+    fs: &Ext4FileSystem,
+    dev: &mut Jbd2Dev<B>,
+    block: u64,
+) -> BlockDevResult<(u32, Vec<XattrEntry>)> {
+    dev.read_block(block)?;
+    let data = dev.buffer();
+    let word = |off: usize| read_u32_le(&data[off..off + 4]);
+    if word(0) != XATTR_MAGIC || word(8) != 1 {
+        return Err(BlockDevError::Corrupted);
+    }
+    if fs.superblock.has_metadata_csum() {
+        let ok = word(BLOCK_CSUM_OFFSET) == block_csum(fs, block, data);
+        fs.checksum_policy.check(ok, "xattr block")?;
+    }
+    let refcount = word(4);
+    Ok((refcount, parse_entries(&data[..BLOCK_SIZE], BLOCK_HEADER_SIZE, 0)?))
+}
+
+/// 写属性块：块内条目按 (索引, 名字长度, 名字) 排序，重算块哈希和校验和
+fn write_block<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    dev: &mut Jbd2Dev<B>,
+    block: u64,
+    refcount: u32,
+    entries: &[XattrEntry],
+) -> BlockDevResult<()> {
+    let mut sorted = entries.to_vec();
+    sorted.sort_by(|a, b| (a.index, a.name.len(), &a.name).cmp(&(b.index, b.name.len(), &b.name)));
+    let mut block_hash = 0u32;
+    for e in &sorted {
+        block_hash = (block_hash << 16) ^ (block_hash >> 16) ^ e.hash();
+    }
+
+    let mut data = alloc::vec![0u8; BLOCK_SIZE];
+    write_u32_le(XATTR_MAGIC, &mut data[0..4]);
+    write_u32_le(refcount, &mut data[4..8]);
+    write_u32_le(1, &mut data[8..12]);
+    write_u32_le(block_hash, &mut data[12..16]);
+    encode_entries(&sorted, &mut data, BLOCK_HEADER_SIZE, 0);
+    if fs.superblock.has_metadata_csum() {
+        let sum = block_csum(fs, block, &data);
+        write_u32_le(sum, &mut data[BLOCK_CSUM_OFFSET..BLOCK_CSUM_OFFSET + 4]);
+    }
+
+    // 块可能刚从数据块回收，丢掉数据块缓存里的旧内容
+    fs.datablock_cache.invalidate(block);
+    dev.read_block(block)?;
+    dev.buffer_mut()[..BLOCK_SIZE].copy_from_slice(&data);
+    dev.write_block(block, true)
+}
+
+/// 释放 inode 对属性块的引用：共享块只减引用计数，最后一个引用释放整块
+pub fn release_xattr_block<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    dev: &mut Jbd2Dev<B>,
+    block: u64,
+) -> BlockDevResult<()> {
+    let (refcount, entries) = read_block(fs, dev, block)?;
+    if refcount > 1 {
+        return write_block(fs, dev, block, refcount - 1, &entries);
+    }
+    fs.datablock_cache.invalidate(block);
+    fs.free_block(dev, block)
+}
+
+/// 读出 inode 的全部属性：先 ibody，后属性块
+fn load_all<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    dev: &mut Jbd2Dev<B>,
+    ino: u32,
+) -> BlockDevResult<Vec<XattrEntry>> {
+    let (inode, xattr_area) = fs.get_inode_with_xattr_area(dev, ino)?;
+    let mut entries = read_ibody(&inode, &xattr_area)?;
+    if inode.file_acl() != 0 {
+        entries.extend(read_block(fs, dev, inode.file_acl())?.1);
+    }
+    Ok(entries)
+}
+
+/// 把整组属性重新分配到 ibody 和属性块并写回
+/// 按顺序能放进 ibody 的放 ibody，其余进属性块；属性块放不下返回 NoSpace
+fn store_all<B: BlockDevice>(
+    fs: &mut Ext4FileSystem,
+    dev: &mut Jbd2Dev<B>,
+    ino: u32,
+    entries: Vec<XattrEntry>,
+) -> BlockDevResult<()> {
+    let (inode, xattr_area) = fs.get_inode_with_xattr_area(dev, ino)?;
+    let mut ibody_left = ibody_capacity(&inode, &xattr_area);
+    let (mut in_inode, mut in_block) = (Vec::new(), Vec::new());
+    for e in entries {
+        if e.footprint() <= ibody_left {
+            ibody_left -= e.footprint();
+            in_inode.push(e);
+        } else {
+            in_block.push(e);
+        }
+    }
+    let block_cap = BLOCK_SIZE - BLOCK_HEADER_SIZE - END_MARKER_SIZE;
+    if in_block.iter().map(XattrEntry::footprint).sum::<usize>() > block_cap {
+        return Err(BlockDevError::NoSpace);
+    }
+
+    // 旧块只被本 inode 引用时原地改写，否则（共享或不再需要）先释放引用
+    let old_block = inode.file_acl();
+    let mut new_block = 0;
+    if old_block != 0 {
+        let (refcount, _) = read_block(fs, dev, old_block)?;
+        if refcount == 1 && !in_block.is_empty() {
+            new_block = old_block;
+        } else {
+            release_xattr_block(fs, dev, old_block)?;
+        }
+    }
+    if !in_block.is_empty() {
+        if new_block == 0 {
+            new_block = fs.alloc_block(dev)?;
+        }
+        write_block(fs, dev, new_block, 1, &in_block)?;
+        if !fs
+            .superblock
+            .has_feature_compat(Ext4Superblock::EXT4_FEATURE_COMPAT_EXT_ATTR)
+        {
+            fs.superblock.s_feature_compat |= Ext4Superblock::EXT4_FEATURE_COMPAT_EXT_ATTR;
+            fs.gdt_backups_stale = true;
+        }
+    }
+
+    let now = fs.current_time();
+    let sectors = (BLOCK_SIZE / 512) as u64;
+    fs.modify_inode_with_xattr_area(dev, ino, |inode, xattr_area| {
+        write_ibody(inode, xattr_area, &in_inode);
+        let mut iblocks = inode.blocks_count();
+        if old_block != 0 {
+            iblocks = iblocks.saturating_sub(sectors);
+        }
+        if new_block != 0 {
+            iblocks += sectors;
+        }
+        inode.i_blocks_lo = iblocks as u32;
+        inode.l_i_blocks_high = (iblocks >> 32) as u16;
+        inode.set_file_acl(new_block);
+        inode.set_ctime_ts(now);
+    })
+}
+
+///设置扩展属性，已存在时替换值
+/// 名字须带 user./trusted./security./system. 前缀，未知前缀返回 Unsupported
+pub fn set_xattr<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    name: &str,
+    value: &[u8],
+) -> Ext4Result<()> {
+    fs.ensure_writable()?;
+    let (index, suffix) = split_xattr_name(name)?;
+    let (ino, _) = lookup_path(fs, block_dev, path)?;
+    let mut entries = load_all(fs, block_dev, ino)?;
+    match entries.iter_mut().find(|e| e.index == index && e.name == suffix) {
+        Some(e) => e.value = value.to_vec(),
+        None => entries.push(XattrEntry {
+            index,
+            name: suffix.to_vec(),
+            value: value.to_vec(),
+        }),
+    }
+    Ok(store_all(fs, block_dev, ino, entries)?)
+}
+
+///读取扩展属性，不存在时返回 None
+pub fn get_xattr<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    name: &str,
+) -> Ext4Result<Option<Vec<u8>>> {
+    let (index, suffix) = split_xattr_name(name)?;
+    let (ino, _) = lookup_path(fs, block_dev, path)?;
+    Ok(load_all(fs, block_dev, ino)?
+        .into_iter()
+        .find(|e| e.index == index && e.name == suffix)
+        .map(|e| e.value))
+}
+
+///列出全部扩展属性的完整名字（先 inode 内，后属性块）
+pub fn list_xattr<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+) -> Ext4Result<Vec<String>> {
+    let (ino, _) = lookup_path(fs, block_dev, path)?;
+    Ok(load_all(fs, block_dev, ino)?
+        .iter()
+        .map(XattrEntry::full_name)
+        .collect())
+}
+
+///删除扩展属性，返回是否存在过；属性块变空时释放
+pub fn remove_xattr<B: BlockDevice>(
+    block_dev: &mut Jbd2Dev<B>,
+    fs: &mut Ext4FileSystem,
+    path: &str,
+    name: &str,
+) -> Ext4Result<bool> {
+    fs.ensure_writable()?;
+    let (index, suffix) = split_xattr_name(name)?;
+    let (ino, _) = lookup_path(fs, block_dev, path)?;
+    let mut entries = load_all(fs, block_dev, ino)?;
+    let before = entries.len();
+    entries.retain(|e| !(e.index == index && e.name == suffix));
+    if entries.len() == before {
+        return Ok(false);
+    }
+    store_all(fs, block_dev, ino, entries)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext4_backend::file::{mkfile, unlink};
+    use crate::ext4_backend::test_support::*;
+    use alloc::format;
+    use alloc::vec;
+
+    #[test]
+    fn xattr_in_inode_survives_remount() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/f", Some(b"data"), None).unwrap();
+        set_xattr(&mut dev, &mut fs, "/f", "user.comment", b"hello").unwrap();
+        assert_eq!(fs.find_file(&mut dev, "/f").unwrap().file_acl(), 0);
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        assert_eq!(
+            get_xattr(&mut dev, &mut fs, "/f", "user.comment").unwrap().as_deref(),
+            Some(&b"hello"[..])
+        );
+        assert_eq!(get_xattr(&mut dev, &mut fs, "/f", "user.missing").unwrap(), None);
+        assert_eq!(list_xattr(&mut dev, &mut fs, "/f").unwrap(), vec![String::from("user.comment")]);
+        assert_eq!(
+            set_xattr(&mut dev, &mut fs, "/f", "bogus.name", b"x"),
+            Err(Ext4Error::Device(BlockDevError::Unsupported))
+        );
+        // inode 本身的字段不受影响
+        assert_eq!(crate::ext4_backend::file::read_file(&mut dev, &mut fs, "/f").unwrap(), b"data");
+    }
+
+    #[test]
+    fn xattrs_spill_to_block_and_are_freed_with_the_inode() {
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        mkfile(&mut dev, &mut fs, "/f", None, None).unwrap();
+        let free_before = fs.superblock.free_blocks_count();
+
+        for i in 0..40 {
+            let value = vec![i as u8; 20 + i];
+            set_xattr(&mut dev, &mut fs, "/f", &format!("user.attr{i:02}"), &value).unwrap();
+        }
+        set_xattr(&mut dev, &mut fs, "/f", "trusted.t", b"tv").unwrap();
+        let inode = fs.find_file(&mut dev, "/f").unwrap();
+        assert_ne!(inode.file_acl(), 0);
+        assert_eq!(inode.blocks_count(), (BLOCK_SIZE / 512) as u64);
+        assert_eq!(fs.superblock.free_blocks_count(), free_before - 1);
+        umount(fs, &mut dev).unwrap();
+
+        let mut fs = mount(&mut dev).unwrap();
+        let names = list_xattr(&mut dev, &mut fs, "/f").unwrap();
+        assert_eq!(names.len(), 41);
+        for i in 0..40 {
+            let got = get_xattr(&mut dev, &mut fs, "/f", &format!("user.attr{i:02}")).unwrap();
+            assert_eq!(got, Some(vec![i as u8; 20 + i]));
+        }
+        assert_eq!(get_xattr(&mut dev, &mut fs, "/f", "trusted.t").unwrap(), Some(b"tv".to_vec()));
+
+        // 删到全部放得进 inode 时属性块被释放
+        for i in 0..40 {
+            assert!(remove_xattr(&mut dev, &mut fs, "/f", &format!("user.attr{i:02}")).unwrap());
+        }
+        let inode = fs.find_file(&mut dev, "/f").unwrap();
+        assert_eq!(inode.file_acl(), 0);
+        assert_eq!(inode.blocks_count(), 0);
+        assert_eq!(fs.superblock.free_blocks_count(), free_before);
+
+        // 再次溢出后删除文件，属性块随 inode 回收
+        for i in 0..40 {
+            set_xattr(&mut dev, &mut fs, "/f", &format!("user.attr{i:02}"), &[1; 40]).unwrap();
+        }
+        assert_eq!(fs.superblock.free_blocks_count(), free_before - 1);
+        unlink(&mut dev, &mut fs, "/f").unwrap();
+        assert_eq!(fs.superblock.free_blocks_count(), free_before);
+    }
+
+    #[test]
+    fn spilled_xattr_block_checksum_is_verified_on_metadata_csum_fs() {
+        use crate::ext4_backend::superblock::Ext4Superblock;
+
+        let (mut dev, mut fs) = setup_fs(16 * 1024);
+        fs.superblock.s_feature_ro_compat |= Ext4Superblock::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+        mkfile(&mut dev, &mut fs, "/f", None, None).unwrap();
+        for i in 0..40 {
+            set_xattr(&mut dev, &mut fs, "/f", &format!("user.attr{i:02}"), &[i as u8; 30]).unwrap();
+        }
+        let block = fs.find_file(&mut dev, "/f").unwrap().file_acl();
+        assert_ne!(block, 0);
+        umount(fs, &mut dev).unwrap();
+
+        // 校验和随属性块一起写入，重新挂载后能正常读出
+        let mut fs = mount(&mut dev).unwrap();
+        assert!(fs.superblock.has_metadata_csum());
+        assert_eq!(
+            get_xattr(&mut dev, &mut fs, "/f", "user.attr39").unwrap(),
+            Some(vec![39u8; 30])
+        );
+
+        // 翻转属性块尾部值区的一个字节，块头和条目仍然可解析
+        dev.read_block(block).unwrap();
+        dev.buffer_mut()[BLOCK_SIZE - 1] ^= 0xff;
+        let corrupted = dev.buffer()[..BLOCK_SIZE].to_vec();
+        dev.write_block(block, true).unwrap();
+
+        assert_eq!(read_block(&fs, &mut dev, block).err(), Some(BlockDevError::ChecksumError));
+        assert_eq!(get_xattr(&mut dev, &mut fs, "/f", "user.attr00"), Err(Ext4Error::ChecksumError));
+        assert_eq!(list_xattr(&mut dev, &mut fs, "/f"), Err(Ext4Error::ChecksumError));
+        // 校验失败的块不会被改写
+        assert_eq!(set_xattr(&mut dev, &mut fs, "/f", "user.new", b"v"), Err(Ext4Error::ChecksumError));
+        dev.read_block(block).unwrap();
+        assert_eq!(dev.buffer()[..BLOCK_SIZE], corrupted[..]);
+
+        fs.checksum_policy = ChecksumPolicy::WarnAndContinue;
+        assert_eq!(list_xattr(&mut dev, &mut fs, "/f").unwrap().len(), 40);
+    }
+}
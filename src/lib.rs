@@ -5,6 +5,8 @@
 
 
 extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
 pub mod ext4_backend;
 pub use ext4_backend::api::*;
 pub use ext4_backend::blockdev::*;
@@ -13,3 +15,4 @@ pub use ext4_backend::dir::*;
 pub use ext4_backend::ext4::*;
 pub use ext4_backend::file::*;
 pub use ext4_backend::error::*;
+pub use ext4_backend::xattr::*;
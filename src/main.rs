@@ -78,6 +78,18 @@ impl FileBlockDev {
 
 impl BlockDevice for FileBlockDev {
     fn write(&mut self, buffer: &[u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.write64(buffer, block_id as u64, count)
+    }
+
+    fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+        self.read64(buffer, block_id as u64, count)
+    }
+
+    /// 按字节偏移定位，块号超过 u32 的大镜像也能直接访问
+    fn write64(&mut self, buffer: &[u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
         let block_size = self.block_size() as usize;
         let required = block_size * count as usize;
         if buffer.len() < required {
@@ -87,7 +99,7 @@ impl BlockDevice for FileBlockDev {
             });
         }
 
-        let offset = block_id as u64 * block_size as u64;
+        let offset = block_id * block_size as u64;
         let bytes = &buffer[..required];
 
         self.file
@@ -100,7 +112,10 @@ impl BlockDevice for FileBlockDev {
         Ok(())
     }
 
-    fn read(&mut self, buffer: &mut [u8], block_id: u32, count: u32) -> BlockDevResult<()> {
+    fn read64(&mut self, buffer: &mut [u8], block_id: u64, count: u32) -> BlockDevResult<()> {
+        if count == 0 {
+            return Ok(());
+        }
         let block_size = self.block_size() as usize;
         let required = block_size * count as usize;
         if buffer.len() < required {
@@ -110,7 +125,7 @@ impl BlockDevice for FileBlockDev {
             });
         }
 
-        let offset = block_id as u64 * block_size as u64;
+        let offset = block_id * block_size as u64;
 
         let mut f = &self.file;
         f.seek(SeekFrom::Start(offset))
@@ -120,6 +135,18 @@ impl BlockDevice for FileBlockDev {
         Ok(())
     }
 
+    /// 每次写入都直接落到镜像文件，不需要额外的 flush 屏障
+    /// std 没有 fallocate(PUNCH_HOLE)，不报告 discard 能力，用默认的 Unsupported
+    fn capabilities(&self) -> DevCaps {
+        DevCaps {
+            discard: false,
+            flush: false,
+            rotational: false,
+            sector_size: BLOCK_SIZE as u32,
+            readonly: false,
+        }
+    }
+
     fn open(&mut self) -> BlockDevResult<()> {
         Ok(())
     }
@@ -169,7 +196,7 @@ fn main() {
     };
 
     // 包一层 Jbd2Dev，开启 journal
-    let mut jbd = Jbd2Dev::initial_jbd2dev(0, host_dev, false);
+    let mut jbd = Jbd2Dev::initial_jbd2dev(0, host_dev, false, false);
 
     info!("=== 测试 Ext4 mkfs ===");
     test_mkfs(&mut jbd);
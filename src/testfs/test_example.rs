@@ -8,7 +8,7 @@ pub fn test_mkfs<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>) {
 }
 /// 大文件写入/读取测试
 pub fn _test_base_io<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) {
-    mkdir(block_dev, fs, "/test_dir/");
+    let _ = mkdir(block_dev, fs, "/test_dir/");
     // 大文件测试：写入 + 读取 吞吐量
     let big_file_mib: usize = if cfg!(target_pointer_width = "64") { //prevent overflow
         println!("64-bits Machine Detected!");
@@ -20,10 +20,11 @@ pub fn _test_base_io<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4Fi
     let test_big_file: Vec<u8> = vec![b'g'; 1024 * 1024 * big_file_mib];
     let file_count = 1u64;
     let total_write_bytes = test_big_file.len() as u64;
+    fs.reset_cache_stats();
     let write_start = std::time::Instant::now();
     for i in 0..file_count {
         let file_name = format!("/test_dir/test_file:{i}");
-        mkfile(block_dev, fs, &file_name, Some(&test_big_file),None);
+        let _ = mkfile(block_dev, fs, &file_name, Some(&test_big_file),None);
     }
     //数据实际落盘
     fs.datablock_cache.flush_all(block_dev).expect("Bitmap Flsuh failed!");
@@ -37,16 +38,21 @@ pub fn _test_base_io<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4Fi
     } else {
         0.0
     };
+    let stats = fs.cache_stats();
     println!(
-        "大文件写入: total={write_mib:.2} MiB, time={write_secs:.3} s, speed={write_mib_s:.2} MiB/s"
+        "大文件写入: total={write_mib:.2} MiB, time={write_secs:.3} s, speed={write_mib_s:.2} MiB/s, hit rate data={}% inode={}% bitmap={}%",
+        stats.data.counters.hit_rate_percent(),
+        stats.inode.counters.hit_rate_percent(),
+        stats.bitmap.counters.hit_rate_percent()
     );
+    fs.reset_cache_stats();
 
     // 读取吞吐量测试：依次读回刚才写入的几个大文件
     let read_start = std::time::Instant::now();
     let mut read_bytes: u64 = 0;
     for i in 0..file_count {
         let file_name = format!("/test_dir/test_file:{i}");
-        if let Some(data) = read_file(block_dev, fs, &file_name).unwrap() {
+        if let Ok(data) = read_file(block_dev, fs, &file_name) {
             read_bytes += data.len() as u64;
         }
     }
@@ -58,8 +64,12 @@ pub fn _test_base_io<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4Fi
     } else {
         0.0
     };
+    let stats = fs.cache_stats();
     println!(
-        "大文件读取: total={read_mib:.2} MiB, time={read_secs:.3} s, speed={read_mib_s:.2} MiB/s"
+        "大文件读取: total={read_mib:.2} MiB, time={read_secs:.3} s, speed={read_mib_s:.2} MiB/s, hit rate data={}% inode={}% bitmap={}%",
+        stats.data.counters.hit_rate_percent(),
+        stats.inode.counters.hit_rate_percent(),
+        stats.bitmap.counters.hit_rate_percent()
     );
 
     //=== 宿主机文件系统: 相同规模的大文件写入/读取测试 ===
@@ -112,18 +122,18 @@ pub fn test_delete<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4File
     let test_big_file: Vec<u8> = vec![b'g'; 1024 * 1024 * 20]; // 20MB
     for idx in 0..10 {
         let file_name = format!("/deltest/childdir/file:{idx}");
-        mkfile(block_dev, fs, &file_name, Some(&test_big_file),None);
+        let _ = mkfile(block_dev, fs, &file_name, Some(&test_big_file),None);
     }
     delete_dir(fs, block_dev, "/deltest");
 }
 
 pub fn test_link<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) {
-    mkdir(block_dev, fs, "/linktest_link");
+    let _ = mkdir(block_dev, fs, "/linktest_link");
 
     let payload: Vec<u8> = (0..(1024 * 1024)).map(|i| (i % 251) as u8).collect();
-    mkfile(block_dev, fs, "/linktest_link/target", Some(&payload),None);
+    let _ = mkfile(block_dev, fs, "/linktest_link/target", Some(&payload),None);
 
-    link(fs, block_dev, "/linktest_link/l1", "/linktest_link/target");
+    link(block_dev, fs, "/linktest_link/target", "/linktest_link/l1").expect("link failed");
 
     let (ino_target, _) = get_file_inode(fs, block_dev, "/linktest_link/target")
         .ok()
@@ -136,28 +146,27 @@ pub fn test_link<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSy
     assert_eq!(ino_target, ino_link);
 
     let data_target = read_file(block_dev, fs, "/linktest_link/target")
-        .unwrap()
         .expect("read target failed");
     let data_link = read_file(block_dev, fs, "/linktest_link/l1")
-        .unwrap()
         .expect("read link failed");
     assert_eq!(data_target, payload);
     assert_eq!(data_link, payload);
 }
 
 pub fn test_unlink<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) {
-    mkdir(block_dev, fs, "/linktest_unlink");
+    let _ = mkdir(block_dev, fs, "/linktest_unlink");
 
     let payload: Vec<u8> = (0..(1024 * 1024)).map(|i| (i % 251) as u8).collect();
-    mkfile(block_dev, fs, "/linktest_unlink/target", Some(&payload),None);
+    let _ = mkfile(block_dev, fs, "/linktest_unlink/target", Some(&payload),None);
     link(
-        fs,
         block_dev,
-        "/linktest_unlink/l1",
+        fs,
         "/linktest_unlink/target",
-    );
+        "/linktest_unlink/l1",
+    )
+    .expect("link failed");
 
-    unlink(fs, block_dev, "/linktest_unlink/l1");
+    unlink(block_dev, fs, "/linktest_unlink/l1").expect("unlink failed");
     assert!(
         get_file_inode(fs, block_dev, "/linktest_unlink/l1")
             .ok()
@@ -172,7 +181,6 @@ pub fn test_unlink<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4File
     );
 
     let data_target2 = read_file(block_dev, fs, "/linktest_unlink/target")
-        .unwrap()
         .expect("read target after unlink failed");
     assert_eq!(data_target2, payload);
 
@@ -186,10 +194,10 @@ pub fn test_unlink<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4File
 }
 
 pub fn test_symbol_link<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) {
-    mkdir(block_dev, fs, "/symlinktest");
+    let _ = mkdir(block_dev, fs, "/symlinktest");
 
     let payload: Vec<u8> = (0..(64 * 1024)).map(|i| (i % 251) as u8).collect();
-    mkfile(block_dev, fs, "/symlinktest/target", Some(&payload),None);
+    let _ = mkfile(block_dev, fs, "/symlinktest/target", Some(&payload),None);
 
     create_symbol_link(block_dev, fs, "/symlinktest/target", "/symlinktest/l1")
         .expect("create_symbol_link failed");
@@ -201,22 +209,20 @@ pub fn test_symbol_link<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext
     assert!(inode_link.is_symlink());
 
     let data_via_link = read_file(block_dev, fs, "/symlinktest/l1")
-        .unwrap()
         .expect("read symlink-follow failed");
     assert_eq!(data_via_link, payload);
 }
 
 pub fn test_truncate<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) {
-    mkdir(block_dev, fs, "/truncatetest");
+    let _ = mkdir(block_dev, fs, "/truncatetest");
 
     let payload: Vec<u8> = (0..(64 * 1024)).map(|i| (i % 251) as u8).collect();
-    mkfile(block_dev, fs, "/truncatetest/f1", Some(&payload),None);
+    let _ = mkfile(block_dev, fs, "/truncatetest/f1", Some(&payload),None);
 
     // shrink to non-zero (cross block boundary)
     let shrink_len: u64 = (BLOCK_SIZE + 123) as u64;
     truncate(block_dev, fs, "/truncatetest/f1", shrink_len).expect("truncate shrink failed");
     let data_shrink = read_file(block_dev, fs, "/truncatetest/f1")
-        .unwrap()
         .expect("read after truncate shrink failed");
     assert_eq!(data_shrink.len() as u64, shrink_len);
     assert_eq!(&data_shrink[..], &payload[..shrink_len as usize]);
@@ -224,14 +230,12 @@ pub fn test_truncate<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4Fi
     // truncate to same size should be no-op
     truncate(block_dev, fs, "/truncatetest/f1", shrink_len).expect("truncate same size failed");
     let data_same = read_file(block_dev, fs, "/truncatetest/f1")
-        .unwrap()
         .expect("read after truncate same size failed");
     assert_eq!(data_same, data_shrink);
 
     // truncate -> 0
     truncate(block_dev, fs, "/truncatetest/f1", 0).expect("truncate to 0 failed");
     let data0 = read_file(block_dev, fs, "/truncatetest/f1")
-        .unwrap()
         .expect("read after truncate(0) failed");
     assert!(data0.is_empty());
 
@@ -239,13 +243,12 @@ pub fn test_truncate<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4Fi
     let new_len: u64 = (BLOCK_SIZE + 17) as u64;
     truncate(block_dev, fs, "/truncatetest/f1", new_len).expect("truncate grow failed");
     let data1 = read_file(block_dev, fs, "/truncatetest/f1")
-        .unwrap()
         .expect("read after truncate grow failed");
     assert_eq!(data1.len() as u64, new_len);
     assert!(data1.iter().all(|&b| b == 0));
 
     // shrink on sparse file: create a hole then truncate to 0 (should not double free)
-    mkfile(block_dev, fs, "/truncatetest/f_sparse", None,None);
+    let _ = mkfile(block_dev, fs, "/truncatetest/f_sparse", None,None);
     write_file(block_dev, fs, "/truncatetest/f_sparse", 0, b"ABC").unwrap();
     write_file(
         block_dev,
@@ -257,7 +260,6 @@ pub fn test_truncate<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4Fi
     .unwrap();
     truncate(block_dev, fs, "/truncatetest/f_sparse", 0).expect("truncate sparse->0 failed");
     let data_sparse0 = read_file(block_dev, fs, "/truncatetest/f_sparse")
-        .unwrap()
         .expect("read sparse after truncate(0) failed");
     assert!(data_sparse0.is_empty());
 }
@@ -266,9 +268,9 @@ pub fn test_api_write_at_read_at<B: BlockDevice>(
     block_dev: &mut Jbd2Dev<B>,
     fs: &mut Ext4FileSystem,
 ) {
-    mkdir(block_dev, fs, "/apiiotest");
+    let _ = mkdir(block_dev, fs, "/apiiotest");
 
-    let mut f = open(block_dev, fs, "/apiiotest/f1", true).expect("open failed");
+    let mut f = open(block_dev, fs, "/apiiotest/f1", OpenFlags::RDWR.with_create()).expect("open failed");
 
     // write_at appends at current offset
     write_at(block_dev, fs, &mut f, b"HELLO").expect("write_at failed");
@@ -307,8 +309,8 @@ pub fn _test_journal_powerfail<B: BlockDevice>(
     // This test only makes sense when journal is enabled.
     block_dev.set_journal_use(true);
 
-    mkdir(block_dev, &mut fs, "/journaltest");
-    mkfile(block_dev, &mut fs, "/journaltest/f1", None,None);
+    let _ = mkdir(block_dev, &mut fs, "/journaltest");
+    let _ = mkfile(block_dev, &mut fs, "/journaltest/f1", None,None);
 
     let payload = b"JOURNAL_PAYLOAD_123456";
     write_file(block_dev, &mut fs, "/journaltest/f1", 0, payload)
@@ -338,7 +340,6 @@ pub fn _test_journal_powerfail<B: BlockDevice>(
 
     // After replay, inode size/metadata should be visible, and file should read correctly.
     let got = read_file(block_dev, &mut fs2, "/journaltest/f1")
-        .unwrap()
         .expect("read after replay failed");
     assert_eq!(got, payload);
 
@@ -347,13 +348,13 @@ pub fn _test_journal_powerfail<B: BlockDevice>(
 }
 
 pub fn _test_rename<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) {
-    mkdir(block_dev, fs, "/renametest");
+    let _ = mkdir(block_dev, fs, "/renametest");
 
     let payload_a: Vec<u8> = (0..(32 * 1024)).map(|i| (i % 251) as u8).collect();
     let payload_b: Vec<u8> = (0..(16 * 1024)).map(|i| ((i + 7) % 251) as u8).collect();
 
-    mkfile(block_dev, fs, "/renametest/a", Some(&payload_a),None);
-    mkfile(block_dev, fs, "/renametest/b", Some(&payload_b),None);
+    let _ = mkfile(block_dev, fs, "/renametest/a", Some(&payload_a),None);
+    let _ = mkfile(block_dev, fs, "/renametest/b", Some(&payload_b),None);
 
     // rename a -> c
     rename(block_dev, fs, "/renametest/a", "/renametest/c").expect("rename a->c failed");
@@ -364,7 +365,6 @@ pub fn _test_rename<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4Fil
             .is_none()
     );
     let c = read_file(block_dev, fs, "/renametest/c")
-        .unwrap()
         .expect("read /renametest/c failed");
     assert_eq!(c, payload_a);
 
@@ -377,7 +377,6 @@ pub fn _test_rename<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4Fil
             .is_none()
     );
     let c2 = read_file(block_dev, fs, "/renametest/c")
-        .unwrap()
         .expect("read /renametest/c after overwrite failed");
     assert_eq!(c2, payload_b);
 }
@@ -385,12 +384,12 @@ pub fn _test_rename<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4Fil
 
 
 pub fn test_mv<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) {
-    mkdir(block_dev, fs, "/mvtest");
-    mkdir(block_dev, fs, "/mvtest/a");
-    mkdir(block_dev, fs, "/mvtest/b");
+    let _ = mkdir(block_dev, fs, "/mvtest");
+    let _ = mkdir(block_dev, fs, "/mvtest/a");
+    let _ = mkdir(block_dev, fs, "/mvtest/b");
 
     let payload: Vec<u8> = (0..(128 * 1024)).map(|i| (i % 251) as u8).collect();
-    mkfile(block_dev, fs, "/mvtest/a/f1", Some(&payload),None);
+    let _ = mkfile(block_dev, fs, "/mvtest/a/f1", Some(&payload),None);
 
     mv(fs, block_dev, "/mvtest/a/f1", "/mvtest/a/f1_renamed").expect("mv rename failed");
     assert!(
@@ -400,7 +399,6 @@ pub fn test_mv<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSyst
             .is_none()
     );
     let data1 = read_file(block_dev, fs, "/mvtest/a/f1_renamed")
-        .unwrap()
         .expect("read moved file failed");
     assert_eq!(data1, payload);
 
@@ -412,14 +410,13 @@ pub fn test_mv<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSyst
             .is_none()
     );
     let data2 = read_file(block_dev, fs, "/mvtest/b/f1_moved")
-        .unwrap()
         .expect("read moved-across file failed");
     assert_eq!(data2, payload);
 
     // directory move across parents
-    mkdir(block_dev, fs, "/mvtest/dir1");
-    mkfile(block_dev, fs, "/mvtest/dir1/inner", Some(&payload),None);
-    mkdir(block_dev, fs, "/mvtest/dir2");
+    let _ = mkdir(block_dev, fs, "/mvtest/dir1");
+    let _ = mkfile(block_dev, fs, "/mvtest/dir1/inner", Some(&payload),None);
+    let _ = mkdir(block_dev, fs, "/mvtest/dir2");
 
     mv(fs, block_dev, "/mvtest/dir1", "/mvtest/dir2/dir1_moved").expect("mv dir failed");
     assert!(
@@ -429,7 +426,6 @@ pub fn test_mv<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSyst
             .is_none()
     );
     let data3 = read_file(block_dev, fs, "/mvtest/dir2/dir1_moved/inner")
-        .unwrap()
         .expect("read inner file after dir mv failed");
     assert_eq!(data3, payload);
 }
@@ -437,17 +433,17 @@ pub fn test_mv<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSyst
 /// 文件写入测试
 pub fn test_normal_apiuse<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) {
     //make many file and dir
-    mkdir(block_dev, fs, "/test/hello");
+    let _ = mkdir_p(block_dev, fs, "/test/hello");
     let test_big_file: Vec<u8> = vec![b'g'; 1024 * 1024 * 20]; // 20MB
     for idx in 0..10 {
         let file_name = format!("/test/hello/test{idx}");
-        mkfile(block_dev, fs, &file_name, Some(&test_big_file),None);
+        let _ = mkfile(block_dev, fs, &file_name, Some(&test_big_file),None);
     }
 }
 
 /// 文件查找测试\
 pub fn test_find_file_line<B: BlockDevice>(block_dev: &mut Jbd2Dev<B>, fs: &mut Ext4FileSystem) {
-    find_file(fs, block_dev, "/.////../.a");
+    let _ = find_file(fs, block_dev, "/.////../.a");
 }
 
 /// 挂载测试